@@ -2,6 +2,20 @@
 
 layout(location = 0) in vec3 f_normal;
 layout(location = 1) in vec2 f_diffuse;
+layout(location = 2) in float f_depth;
+layout(location = 3) in vec3 f_model_normal;
+
+layout(push_constant) uniform PushConstants {
+  layout(offset = 128) vec4 shade_vector;
+  vec4 shade_light;
+} push_constants;
+
+// set 0: per-frame
+layout(set = 0, binding = 0) uniform FrameUniforms {
+  float light_anim_frames[64];
+  vec4 camera_pos;
+  vec4 fog;
+} frame_uniforms;
 
 // set 1: per-entity
 layout(set = 1, binding = 1) uniform sampler u_diffuse_sampler;
@@ -13,14 +27,29 @@ layout(location = 0) out vec4 diffuse_attachment;
 layout(location = 1) out vec4 normal_attachment;
 layout(location = 2) out vec4 light_attachment;
 
+// Shades a surface facing the shade vector up to twice as bright and one facing away a little
+// darker. This reproduces the original engine's precalculated shadedots table.
+float shade_dot(vec3 normal) {
+  float d = dot(normalize(normal), push_constants.shade_vector.xyz);
+  return d < 0.0 ? 1.0 + d * (13.0 / 44.0) : 1.0 + d;
+}
+
+#include "fog.glsl"
+
 void main() {
   diffuse_attachment = texture(
     sampler2D(u_diffuse_texture, u_diffuse_sampler),
     f_diffuse
   );
 
-  // TODO: get ambient light from uniform
-  light_attachment = vec4(0.25);
+  // the light attachment stores an eighth of the final light level, see brush.frag
+  vec3 light = push_constants.shade_light.rgb * shade_dot(f_model_normal);
+  light_attachment = vec4(light / 4.0, 1.0);
+
+  // fogged texels are lit at unit brightness regardless of the lightmap
+  float fog = fog_factor(frame_uniforms.fog.w, f_depth);
+  diffuse_attachment.rgb = mix(diffuse_attachment.rgb, frame_uniforms.fog.rgb, fog);
+  light_attachment = mix(light_attachment, vec4(0.125), fog);
 
   // rescale normal to [0, 1]
   normal_attachment = vec4(f_normal / 2.0 + 0.5, 1.0);
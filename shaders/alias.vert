@@ -12,6 +12,8 @@ layout(push_constant) uniform PushConstants {
 
 layout(location = 0) out vec3 f_normal;
 layout(location = 1) out vec2 f_diffuse;
+layout(location = 2) out float f_depth;
+layout(location = 3) out vec3 f_model_normal;
 
 // convert from Quake coordinates
 vec3 convert(vec3 from) {
@@ -20,6 +22,8 @@ vec3 convert(vec3 from) {
 
 void main() {
   f_normal = mat3(transpose(inverse(push_constants.model_view))) * convert(a_normal);
+  f_model_normal = a_normal;
   f_diffuse = a_diffuse;
   gl_Position = push_constants.transform * vec4(convert(a_position1), 1.0);
+  f_depth = gl_Position.w;
 }
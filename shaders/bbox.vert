@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) in vec3 a_position;
+
+layout(push_constant) uniform PushConstants {
+  mat4 transform;
+} push_constants;
+
+void main() {
+  gl_Position = push_constants.transform * vec4(a_position, 1.0);
+}
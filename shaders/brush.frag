@@ -5,34 +5,41 @@ const uint TEXTURE_KIND_REGULAR = 0;
 const uint TEXTURE_KIND_WARP = 1;
 const uint TEXTURE_KIND_SKY = 2;
 
-const float WARP_AMPLITUDE = 0.15;
-const float WARP_FREQUENCY = 0.25;
-const float WARP_SCALE = 1.0;
+// 8 texels per second on a 128-texel sky layer
+const float SKY_SCROLL_SPEED = 8.0 / 128.0;
 
 layout(location = 0) in vec3 f_normal;
 layout(location = 1) in vec2 f_diffuse; // also used for fullbright
 layout(location = 2) in vec2 f_lightmap;
 flat layout(location = 3) in uvec4 f_lightmap_anim;
+layout(location = 4) in float f_depth;
+flat layout(location = 5) in vec4 f_face_seed;
+layout(location = 6) in vec3 f_barycentric;
 
 layout(push_constant) uniform PushConstants {
   layout(offset = 128) uint texture_kind;
+  uint texture_layer;
 } push_constants;
 
 // set 0: per-frame
 layout(set = 0, binding = 0) uniform FrameUniforms {
     float light_anim_frames[64];
     vec4 camera_pos;
+    vec4 fog;
     float time;
     bool r_lightmap;
+    bool r_waterwarp;
+    bool r_drawflat;
+    bool r_showtris;
 } frame_uniforms;
 
 // set 1: per-entity
 layout(set = 1, binding = 1) uniform sampler u_diffuse_sampler; // also used for fullbright
 layout(set = 1, binding = 2) uniform sampler u_lightmap_sampler;
 
-// set 2: per-texture
-layout(set = 2, binding = 0) uniform texture2D u_diffuse_texture;
-layout(set = 2, binding = 1) uniform texture2D u_fullbright_texture;
+// set 2: per-texture array, indexed by push_constants.texture_layer
+layout(set = 2, binding = 0) uniform texture2DArray u_diffuse_texture;
+layout(set = 2, binding = 1) uniform texture2DArray u_fullbright_texture;
 layout(set = 2, binding = 2) uniform TextureUniforms {
     uint kind;
 } texture_uniforms;
@@ -45,32 +52,55 @@ layout(location = 1) out vec4 normal_attachment;
 layout(location = 2) out vec4 light_attachment;
 
 vec4 calc_light() {
-    vec4 light = vec4(0.0, 0.0, 0.0, 0.0);
+    vec3 light = vec3(0.0, 0.0, 0.0);
     for (int i = 0; i < 4 && f_lightmap_anim[i] != LIGHTMAP_ANIM_END; i++) {
-        float map = texture(
+        // white unless the map has colored lighting
+        vec3 map = texture(
             sampler2D(u_lightmap_texture[i], u_lightmap_sampler),
             f_lightmap
-        ).r;
+        ).rgb;
 
         // range [0, 4]
         float style = frame_uniforms.light_anim_frames[f_lightmap_anim[i]];
-        light[i] = map * style;
+        light += map * style;
     }
 
-    return light;
+    // the light attachment stores an eighth of the final light level
+    return vec4(light / 4.0, 1.0);
+}
+
+#include "fog.glsl"
+
+// a color derived from the face's first vertex, so that adjacent faces are easy to tell apart
+vec3 flat_color() {
+    uvec4 bits = floatBitsToUint(f_face_seed);
+    uint h = bits.x * 73856093u ^ bits.y * 19349663u ^ bits.z * 83492791u ^ bits.w;
+    h ^= h >> 16;
+    h *= 0x7feb352du;
+    h ^= h >> 15;
+    h *= 0x846ca68bu;
+    h ^= h >> 16;
+    return vec3(h & 0xFFu, (h >> 8) & 0xFFu, (h >> 16) & 0xFFu) / 255.0;
+}
+
+// 1.0 on the edges of the triangle, falling off to 0.0 over about a pixel
+float edge_factor() {
+    vec3 width = fwidth(f_barycentric);
+    vec3 edge = smoothstep(vec3(0.0), width * 1.5, f_barycentric);
+    return 1.0 - min(min(edge.x, edge.y), edge.z);
 }
 
 void main() {
     switch (push_constants.texture_kind) {
         case TEXTURE_KIND_REGULAR:
             diffuse_attachment = texture(
-                sampler2D(u_diffuse_texture, u_diffuse_sampler),
-                f_diffuse
+                sampler2DArray(u_diffuse_texture, u_diffuse_sampler),
+                vec3(f_diffuse, push_constants.texture_layer)
             );
 
             float fullbright = texture(
-                sampler2D(u_fullbright_texture, u_diffuse_sampler),
-                f_diffuse
+                sampler2DArray(u_fullbright_texture, u_diffuse_sampler),
+                vec3(f_diffuse, push_constants.texture_layer)
             ).r;
 
             if (fullbright != 0.0) {
@@ -78,36 +108,30 @@ void main() {
             } else {
                 light_attachment = calc_light();
             }
-            break;
-
-        case TEXTURE_KIND_WARP:
-            // note the texcoord transpose here
-            vec2 wave1 = 3.14159265359
-                * (WARP_SCALE * f_diffuse.ts
-                    + WARP_FREQUENCY * frame_uniforms.time);
 
-            vec2 warp_texcoord = f_diffuse.st + WARP_AMPLITUDE
-                * vec2(sin(wave1.s), sin(wave1.t));
-
-            diffuse_attachment = texture(
-                sampler2D(u_diffuse_texture, u_diffuse_sampler),
-                warp_texcoord
-            );
-            light_attachment = vec4(0.25);
+            // fogged texels are lit at unit brightness regardless of the lightmap
+            float fog = fog_factor(frame_uniforms.fog.w, f_depth);
+            diffuse_attachment.rgb = mix(diffuse_attachment.rgb, frame_uniforms.fog.rgb, fog);
+            light_attachment = mix(light_attachment, vec4(0.125), fog);
             break;
 
         case TEXTURE_KIND_SKY:
-            vec2 base = mod(f_diffuse + frame_uniforms.time, 1.0);
-            vec2 cloud_texcoord = vec2(base.s * 0.5, base.t);
-            vec2 sky_texcoord = vec2(base.s * 0.5 + 0.5, base.t);
+            // the solid back layer scrolls at 8 texels per second and the
+            // cloud layer in front of it at 16, as in Quake
+            vec2 sky_base = mod(f_diffuse + SKY_SCROLL_SPEED * frame_uniforms.time, 1.0);
+            vec2 cloud_base = mod(f_diffuse + 2.0 * SKY_SCROLL_SPEED * frame_uniforms.time, 1.0);
+
+            // the left half of the texture holds the clouds, the right half the sky
+            vec2 cloud_texcoord = vec2(cloud_base.s * 0.5, cloud_base.t);
+            vec2 sky_texcoord = vec2(sky_base.s * 0.5 + 0.5, sky_base.t);
 
             vec4 sky_color = texture(
-                sampler2D(u_diffuse_texture, u_diffuse_sampler),
-                sky_texcoord
+                sampler2DArray(u_diffuse_texture, u_diffuse_sampler),
+                vec3(sky_texcoord, push_constants.texture_layer)
             );
             vec4 cloud_color = texture(
-                sampler2D(u_diffuse_texture, u_diffuse_sampler),
-                cloud_texcoord
+                sampler2DArray(u_diffuse_texture, u_diffuse_sampler),
+                vec3(cloud_texcoord, push_constants.texture_layer)
             );
 
             // 0.0 if black, 1.0 otherwise
@@ -121,11 +145,22 @@ void main() {
             light_attachment = vec4(0.25);
             break;
 
-        // not possible
+        // liquids are drawn by the warp pipeline
         default:
             break;
     }
 
+    if (frame_uniforms.r_drawflat && push_constants.texture_kind == TEXTURE_KIND_REGULAR) {
+        diffuse_attachment = vec4(flat_color(), 1.0);
+        light_attachment = vec4(0.25);
+    }
+
+    if (frame_uniforms.r_showtris) {
+        float edge = edge_factor();
+        diffuse_attachment = mix(diffuse_attachment, vec4(1.0), edge);
+        light_attachment = mix(light_attachment, vec4(0.25), edge);
+    }
+
     // rescale normal to [0, 1]
     normal_attachment = vec4(f_normal / 2.0 + 0.5, 1.0);
 }
@@ -14,19 +14,32 @@ layout(push_constant) uniform PushConstants {
   mat4 transform;
   mat4 model_view;
   uint texture_kind;
+  uint texture_layer;
 } push_constants;
 
 layout(location = 0) out vec3 f_normal;
 layout(location = 1) out vec2 f_diffuse;
 layout(location = 2) out vec2 f_lightmap;
 layout(location = 3) out uvec4 f_lightmap_anim;
+layout(location = 4) out float f_depth;
+flat layout(location = 5) out vec4 f_face_seed;
+layout(location = 6) out vec3 f_barycentric;
 
 layout(set = 0, binding = 0) uniform FrameUniforms {
     float light_anim_frames[64];
     vec4 camera_pos;
+    vec4 fog;
     float time;
 } frame_uniforms;
 
+// faces are drawn as non-indexed triangle lists, so the vertex index gives the corner of each
+// triangle for r_showtris
+const vec3 BARYCENTRIC[3] = vec3[3](
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 1.0)
+);
+
 // convert from Quake coordinates
 vec3 convert(vec3 from) {
   return vec3(-from.y, from.z, -from.x);
@@ -40,7 +53,8 @@ void main() {
         // the coefficients here are magic taken from the Quake source
         float len = 6.0 * 63.0 / length(dir);
         dir = vec3(dir.xy * len, dir.z);
-        f_diffuse = (mod(8.0 * frame_uniforms.time, 128.0) + dir.xy) / 128.0;
+        // scrolling is applied per layer in the fragment shader
+        f_diffuse = dir.xy / 128.0;
     } else {
         f_diffuse = a_diffuse;
     }
@@ -49,5 +63,10 @@ void main() {
     f_lightmap = a_lightmap;
     f_lightmap_anim = a_lightmap_anim;
     gl_Position = push_constants.transform * vec4(convert(a_position), 1.0);
+    f_depth = gl_Position.w;
 
+    // every triangle of a face starts at the base vertex of its fan, so this is constant across
+    // the face for r_drawflat
+    f_face_seed = vec4(a_position, a_lightmap.s * 4096.0 + a_lightmap.t);
+    f_barycentric = BARYCENTRIC[gl_VertexIndex % 3];
 }
@@ -0,0 +1,25 @@
+#version 450
+
+layout(location = 0) in vec2 f_texcoord;
+
+layout(push_constant) uniform PushConstants {
+  layout(offset = 64) vec4 color;
+} push_constants;
+
+layout(location = 0) out vec4 diffuse_attachment;
+// layout(location = 1) out vec4 normal_attachment;
+layout(location = 2) out vec4 light_attachment;
+
+void main() {
+  // fade linearly from the center to the edge of the quad, like the triangle fan in
+  // R_RenderDlight
+  float glow = max(1.0 - length(f_texcoord * 2.0 - 1.0), 0.0);
+  if (glow == 0.0) {
+    discard;
+  }
+
+  diffuse_attachment = vec4(push_constants.color.rgb * glow, 0.0);
+
+  // the light attachment holds an eighth of the light level, so this is full brightness
+  light_attachment = vec4(0.125 * glow);
+}
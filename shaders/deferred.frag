@@ -5,11 +5,22 @@ const uint MAX_LIGHTS = 32;
 
 layout(location = 0) in vec2 a_texcoord;
 
+// the attachments are only multisampled if MSAA is enabled
+#ifdef MULTISAMPLED
+#define ATTACHMENT texture2DMS
+#define ATTACHMENT_SIZE(t) textureSize(sampler2DMS(t, u_sampler))
+#define ATTACHMENT_FETCH(t, coord) texelFetch(sampler2DMS(t, u_sampler), coord, gl_SampleID)
+#else
+#define ATTACHMENT texture2D
+#define ATTACHMENT_SIZE(t) textureSize(sampler2D(t, u_sampler), 0)
+#define ATTACHMENT_FETCH(t, coord) texelFetch(sampler2D(t, u_sampler), coord, 0)
+#endif
+
 layout(set = 0, binding = 0) uniform sampler u_sampler;
-layout(set = 0, binding = 1) uniform texture2DMS u_diffuse;
-layout(set = 0, binding = 2) uniform texture2DMS u_normal;
-layout(set = 0, binding = 3) uniform texture2DMS u_light;
-layout(set = 0, binding = 4) uniform texture2DMS u_depth;
+layout(set = 0, binding = 1) uniform ATTACHMENT u_diffuse;
+layout(set = 0, binding = 2) uniform ATTACHMENT u_normal;
+layout(set = 0, binding = 3) uniform ATTACHMENT u_light;
+layout(set = 0, binding = 4) uniform ATTACHMENT u_depth;
 layout(set = 0, binding = 5) uniform DeferredUniforms {
   mat4 inv_projection;
   uint light_count;
@@ -37,24 +48,23 @@ vec3 reconstruct_position(float depth) {
 }
 
 void main() {
-  ivec2 dims = textureSize(sampler2DMS(u_diffuse, u_sampler));
+  ivec2 dims = ATTACHMENT_SIZE(u_diffuse);
   ivec2 texcoord = ivec2(vec2(dims) * a_texcoord);
-  vec4 in_color = texelFetch(sampler2DMS(u_diffuse, u_sampler), texcoord, gl_SampleID);
+  vec4 in_color = ATTACHMENT_FETCH(u_diffuse, texcoord);
 
   // scale from [0, 1] to [-1, 1]
-  vec3 in_normal = 2.0
-    * texelFetch(sampler2DMS(u_normal, u_sampler), texcoord, gl_SampleID).xyz
-    - 1.0;
+  vec3 in_normal = 2.0 * ATTACHMENT_FETCH(u_normal, texcoord).xyz - 1.0;
 
-  // Double to restore overbright values.
-  vec4 in_light = 2.0 * texelFetch(sampler2DMS(u_light, u_sampler), texcoord, gl_SampleID);
+  // The light attachment stores an eighth of the light level to leave room for overbright
+  // values.
+  vec3 in_light = 8.0 * ATTACHMENT_FETCH(u_light, texcoord).rgb;
 
-  float in_depth = texelFetch(sampler2DMS(u_depth, u_sampler), texcoord, gl_SampleID).x;
+  float in_depth = ATTACHMENT_FETCH(u_depth, texcoord).x;
   vec3 position = reconstruct_position(in_depth);
 
   vec4 out_color = in_color;
 
-  float light = in_light.x + in_light.y + in_light.z + in_light.w;
+  vec3 light = in_light;
   for (uint i = 0; i < u_deferred.light_count && i < MAX_LIGHTS; i++) {
     vec4 dlight = u_deferred.lights[i];
     vec3 dir = normalize(position - dlight_origin(dlight));
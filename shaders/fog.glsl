@@ -0,0 +1,6 @@
+// distance fog, using the same exp2 falloff as FitzQuake. `fog_density` is the density set by the
+// fog command and `depth` is the fragment's distance from the camera.
+float fog_factor(float fog_density, float depth) {
+    float density = fog_density / 64.0;
+    return 1.0 - clamp(exp(-density * density * depth * depth), 0.0, 1.0);
+}
@@ -3,6 +3,7 @@
 
 layout(location = 0) in vec2 f_texcoord;
 layout(location = 1) flat in uint f_layer;
+layout(location = 2) in vec4 f_color;
 
 layout(location = 0) out vec4 output_attachment;
 
@@ -14,6 +15,6 @@ void main() {
   if (color.a == 0) {
     discard;
   } else {
-    output_attachment = color;
+    output_attachment = color * f_color;
   }
 }
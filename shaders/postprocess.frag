@@ -5,21 +5,58 @@ layout(location = 0) in vec2 a_texcoord;
 layout(location = 0) out vec4 color_attachment;
 
 layout(set = 0, binding = 0) uniform sampler u_sampler;
+#ifdef MULTISAMPLED
 layout(set = 0, binding = 1) uniform texture2DMS u_color;
+#else
+layout(set = 0, binding = 1) uniform texture2D u_color;
+#endif
 layout(set = 0, binding = 2) uniform PostProcessUniforms {
   vec4 color_shift;
+  uint quantize;
 } postprocess_uniforms;
+layout(set = 0, binding = 3) uniform texture3D u_palette_table;
+
+// 4x4 ordered dither thresholds
+const float BAYER[16] = float[](
+  0.0, 8.0, 2.0, 10.0,
+  12.0, 4.0, 14.0, 6.0,
+  3.0, 11.0, 1.0, 9.0,
+  15.0, 7.0, 13.0, 5.0
+);
+
+// roughly the distance between neighboring shades of the palette's color ramps
+const float DITHER_SPREAD = 1.0 / 16.0;
+
+// Dithers a color and replaces it with the nearest palette entry. The dither pattern follows the
+// scene's texels so it scales with them.
+vec3 quantize(vec3 color, ivec2 texcoord) {
+  float threshold = (BAYER[(texcoord.y % 4) * 4 + texcoord.x % 4] + 0.5) / 16.0 - 0.5;
+  vec3 dithered = clamp(color + threshold * DITHER_SPREAD, 0.0, 1.0);
+
+  ivec3 table_size = textureSize(sampler3D(u_palette_table, u_sampler), 0);
+  ivec3 entry = ivec3(dithered * vec3(table_size - 1) + 0.5);
+  return texelFetch(sampler3D(u_palette_table, u_sampler), entry, 0).rgb;
+}
 
 void main() {
+#ifdef MULTISAMPLED
   ivec2 dims = textureSize(sampler2DMS(u_color, u_sampler));
   ivec2 texcoord = ivec2(vec2(dims) * a_texcoord);
-
   vec4 in_color = texelFetch(sampler2DMS(u_color, u_sampler), texcoord, gl_SampleID);
+#else
+  ivec2 dims = textureSize(sampler2D(u_color, u_sampler), 0);
+  ivec2 texcoord = ivec2(vec2(dims) * a_texcoord);
+  vec4 in_color = texelFetch(sampler2D(u_color, u_sampler), texcoord, 0);
+#endif
 
   float src_factor = postprocess_uniforms.color_shift.a;
   float dst_factor = 1.0 - src_factor;
   vec4 color_shifted = src_factor * postprocess_uniforms.color_shift
     + dst_factor * in_color;
 
+  if (postprocess_uniforms.quantize != 0u) {
+    color_shifted.rgb = quantize(color_shifted.rgb, texcoord);
+  }
+
   color_attachment = color_shifted;
 }
@@ -7,11 +7,17 @@ layout(location = 0) out vec4 color_attachment;
 layout(set = 0, binding = 0) uniform sampler quad_sampler;
 layout(set = 1, binding = 0) uniform texture2D quad_texture;
 
+layout(set = 2, binding = 0) uniform QuadUniforms {
+  mat4 transform;
+  vec4 color;
+  vec2 texcoord_scale;
+} quad_uniforms;
+
 void main() {
   vec4 color = texture(sampler2D(quad_texture, quad_sampler), f_texcoord);
   if (color.a == 0) {
     discard;
   } else {
-    color_attachment = color;
+    color_attachment = color * quad_uniforms.color;
   }
 }
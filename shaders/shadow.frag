@@ -0,0 +1,13 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+  layout(offset = 80) float alpha;
+} push_constants;
+
+layout(location = 0) out vec4 diffuse_attachment;
+// layout(location = 1) out vec4 normal_attachment;
+// layout(location = 2) out vec4 light_attachment;
+
+void main() {
+  diffuse_attachment = vec4(0.0, 0.0, 0.0, push_constants.alpha);
+}
@@ -0,0 +1,24 @@
+#version 450
+
+layout(location = 0) in vec3 a_position1;
+
+layout(push_constant) uniform PushConstants {
+  mat4 transform;
+  vec4 shadow;
+} push_constants;
+
+// convert from Quake coordinates
+vec3 convert(vec3 from) {
+  return vec3(-from.y, from.z, -from.x);
+}
+
+void main() {
+  // flatten the model onto the floor, slanted along the shade vector as in GL_DrawAliasShadow.
+  // the shadow is raised slightly to keep it from fighting with the floor.
+  float height = push_constants.shadow.z;
+  vec3 pos = a_position1;
+  pos.xy -= push_constants.shadow.xy * (pos.z + height);
+  pos.z = 1.0 - height;
+
+  gl_Position = push_constants.transform * vec4(convert(pos), 1.0);
+}
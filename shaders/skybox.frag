@@ -0,0 +1,61 @@
+#version 450
+
+layout(location = 0) in vec3 f_normal;
+layout(location = 1) in vec3 f_dir;
+
+// set 1: per-entity
+// the lightmap sampler clamps to the edge, which keeps the seams between faces invisible
+layout(set = 1, binding = 2) uniform sampler u_lightmap_sampler;
+
+// set 2: skybox faces, in the order rt, bk, lf, ft, up, dn
+layout(set = 2, binding = 0) uniform texture2DArray u_skybox_texture;
+
+layout(location = 0) out vec4 diffuse_attachment;
+layout(location = 1) out vec4 normal_attachment;
+layout(location = 2) out vec4 light_attachment;
+
+// for each axis, the direction components that map to s, t and the major axis.
+// 1-based, negative values negate the component. this is vec_to_st from Quake II.
+const ivec3 VEC_TO_ST[6] = ivec3[](
+    ivec3(-2, 3, 1),
+    ivec3(2, 3, -1),
+    ivec3(1, 3, 2),
+    ivec3(-1, 3, -2),
+    ivec3(-2, -1, 3),
+    ivec3(-2, 1, -3)
+);
+
+// maps each axis to its texture layer
+const int AXIS_TO_LAYER[6] = int[](0, 2, 1, 3, 4, 5);
+
+float component(vec3 v, int i) {
+    return i > 0 ? v[i - 1] : -v[-i - 1];
+}
+
+void main() {
+    vec3 a = abs(f_dir);
+    int axis;
+    if (a.x > a.y && a.x > a.z) {
+        axis = f_dir.x < 0.0 ? 1 : 0;
+    } else if (a.y > a.z && a.y > a.x) {
+        axis = f_dir.y < 0.0 ? 3 : 2;
+    } else {
+        axis = f_dir.z < 0.0 ? 5 : 4;
+    }
+
+    ivec3 map = VEC_TO_ST[axis];
+    float dv = component(f_dir, map.z);
+    vec2 st = vec2(component(f_dir, map.x), component(f_dir, map.y)) / dv;
+    st = vec2((st.s + 1.0) / 2.0, 1.0 - (st.t + 1.0) / 2.0);
+
+    diffuse_attachment = texture(
+        sampler2DArray(u_skybox_texture, u_lightmap_sampler),
+        vec3(st, AXIS_TO_LAYER[axis])
+    );
+
+    // rescale normal to [0, 1]
+    normal_attachment = vec4(f_normal / 2.0 + 0.5, 1.0);
+
+    // fully lit, like the regular sky
+    light_attachment = vec4(0.25);
+}
@@ -2,6 +2,14 @@
 
 layout(location = 0) in vec3 f_normal;
 layout(location = 1) in vec2 f_diffuse;
+layout(location = 2) in float f_depth;
+
+// set 0: per-frame
+layout(set = 0, binding = 0) uniform FrameUniforms {
+  float light_anim_frames[64];
+  vec4 camera_pos;
+  vec4 fog;
+} frame_uniforms;
 
 // set 1: per-entity
 layout(set = 1, binding = 1) uniform sampler u_diffuse_sampler;
@@ -13,10 +21,22 @@ layout(location = 0) out vec4 diffuse_attachment;
 layout(location = 1) out vec4 normal_attachment;
 layout(location = 2) out vec4 light_attachment;
 
+#include "fog.glsl"
+
 void main() {
   diffuse_attachment = texture(sampler2D(u_diffuse_texture, u_diffuse_sampler), f_diffuse);
 
+  // transparent texels are cut out rather than drawn black, using GLQuake's alpha test threshold
+  if (diffuse_attachment.a < 0.666) {
+    discard;
+  }
+
   // rescale normal to [0, 1]
   normal_attachment = vec4(f_normal / 2.0 + 0.5, 1.0);
   light_attachment = vec4(1.0, 1.0, 1.0, 1.0);
+
+  // fogged texels are lit at unit brightness regardless of the lightmap
+  float fog = fog_factor(frame_uniforms.fog.w, f_depth);
+  diffuse_attachment.rgb = mix(diffuse_attachment.rgb, frame_uniforms.fog.rgb, fog);
+  light_attachment = mix(light_attachment, vec4(0.125), fog);
 }
@@ -6,10 +6,12 @@ layout(location = 2) in vec2 a_diffuse;
 
 layout(location = 0) out vec3 f_normal;
 layout(location = 1) out vec2 f_diffuse;
+layout(location = 2) out float f_depth;
 
 layout(set = 0, binding = 0) uniform FrameUniforms {
   float light_anim_frames[64];
   vec4 camera_pos;
+  vec4 fog;
   float time;
 } frame_uniforms;
 
@@ -28,4 +30,5 @@ void main() {
   f_diffuse = a_diffuse;
   gl_Position = entity_uniforms.u_transform
     * vec4(convert(a_position), 1.0);
+  f_depth = gl_Position.w;
 }
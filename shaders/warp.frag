@@ -0,0 +1,65 @@
+#version 450
+
+const float WARP_AMPLITUDE = 0.15;
+const float WARP_FREQUENCY = 0.25;
+const float WARP_SCALE = 1.0;
+
+layout(location = 0) in vec3 f_normal;
+layout(location = 1) in vec2 f_diffuse;
+layout(location = 2) in float f_depth;
+
+layout(push_constant) uniform PushConstants {
+  layout(offset = 128) uint texture_kind;
+  uint texture_layer;
+} push_constants;
+
+// set 0: per-frame
+layout(set = 0, binding = 0) uniform FrameUniforms {
+    float light_anim_frames[64];
+    vec4 camera_pos;
+    vec4 fog;
+    float time;
+    bool r_lightmap;
+    bool r_waterwarp;
+} frame_uniforms;
+
+// set 1: per-entity
+layout(set = 1, binding = 1) uniform sampler u_diffuse_sampler;
+
+// set 2: per-texture array, indexed by push_constants.texture_layer
+layout(set = 2, binding = 0) uniform texture2DArray u_diffuse_texture;
+
+layout(location = 0) out vec4 diffuse_attachment;
+layout(location = 1) out vec4 normal_attachment;
+layout(location = 2) out vec4 light_attachment;
+
+#include "fog.glsl"
+
+void main() {
+    vec2 texcoord = f_diffuse;
+
+    if (frame_uniforms.r_waterwarp) {
+        // note the texcoord transpose here
+        vec2 wave = 3.14159265359
+            * (WARP_SCALE * f_diffuse.ts
+                + WARP_FREQUENCY * frame_uniforms.time);
+
+        texcoord = f_diffuse.st + WARP_AMPLITUDE * vec2(sin(wave.s), sin(wave.t));
+    }
+
+    diffuse_attachment = texture(
+        sampler2DArray(u_diffuse_texture, u_diffuse_sampler),
+        vec3(texcoord, push_constants.texture_layer)
+    );
+
+    // rescale normal to [0, 1]
+    normal_attachment = vec4(f_normal / 2.0 + 0.5, 1.0);
+
+    // liquids are fullbright
+    light_attachment = vec4(0.25);
+
+    // fogged texels are lit at unit brightness regardless of the lightmap
+    float fog = fog_factor(frame_uniforms.fog.w, f_depth);
+    diffuse_attachment.rgb = mix(diffuse_attachment.rgb, frame_uniforms.fog.rgb, fog);
+    light_attachment = mix(light_attachment, vec4(0.125), fog);
+}
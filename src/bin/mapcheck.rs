@@ -0,0 +1,256 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Loads every map in the game data without a window or GPU and reports which ones fail.
+//!
+//! Each map goes through the same steps as on the client: the BSP and its `.lit` file are loaded,
+//! the entity string is parsed and any alias or sprite models named by entities are loaded. A map
+//! which panics the loader is reported as failed and the rest are still checked. The load time of
+//! each map and how much the process's resident memory grew while loading it are printed, so this
+//! doubles as a loader benchmark. With `--json`, the results are printed as a [`Summary`] instead.
+
+extern crate richter;
+
+use std::{
+    any::Any,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    process::exit,
+    time::{Duration, Instant},
+};
+
+use richter::common::{bsp, install, model::Model, parse, vfs::Vfs};
+
+use serde::Serialize;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct Opt {
+    #[structopt(long)]
+    version: bool,
+
+    #[structopt(long)]
+    base_dir: Option<PathBuf>,
+
+    /// Print the results as JSON instead of plain text.
+    #[structopt(long)]
+    json: bool,
+
+    /// Maps to check, e.g. maps/e1m1.bsp. Every map in the game data is checked if none are given.
+    #[structopt(name = "MAPS")]
+    maps: Vec<String>,
+}
+
+const VERSION: &'static str = "
+mapcheck 0.1
+Copyright © 2020 Cormac O'Brien
+Released under the terms of the MIT License
+";
+
+/// The statistics of a map which loaded successfully.
+#[derive(Serialize)]
+struct MapReport {
+    models: usize,
+    entities: usize,
+    load_time_ms: f64,
+    /// How much the process's resident memory grew while loading the map, if the platform reports
+    /// it. This can be negative if memory from an earlier map was returned to the system.
+    resident_growth_bytes: Option<i64>,
+}
+
+/// The result of checking one map. Exactly one of `report` and `error` is set.
+#[derive(Serialize)]
+struct MapResult {
+    name: String,
+    report: Option<MapReport>,
+    error: Option<String>,
+}
+
+/// The output of `--json`.
+#[derive(Serialize)]
+struct Summary {
+    maps: Vec<MapResult>,
+    failed: usize,
+    total_time_ms: f64,
+}
+
+fn duration_ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// Returns the resident set size of this process in bytes.
+///
+/// Only implemented on Linux, where it is read from `/proc/self/status`.
+fn resident_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kib = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kib * 1024)
+}
+
+/// Returns the message of a panic caught by `catch_unwind`.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(msg) => msg.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(msg) => msg.clone(),
+            None => String::from("unknown panic"),
+        },
+    }
+}
+
+fn check_map(vfs: &Vfs, name: &str) -> Result<MapReport, failure::Error> {
+    let resident_before = resident_bytes();
+    let start = Instant::now();
+
+    let bsp_data = vfs.open(name)?;
+    let lit_data = vfs
+        .open(format!("{}.lit", name.trim_end_matches(".bsp")))
+        .ok();
+    let (brush_models, ent_string) = bsp::load_with_lit(bsp_data, lit_data)?;
+    let entities = parse::map::entities(&ent_string)?;
+
+    // brush models (*1, *2, ...) come from the BSP itself
+    let mut models = Vec::new();
+    for entity in entities.iter() {
+        if let Some(model) = entity.get("model") {
+            if model.ends_with(".mdl") || model.ends_with(".spr") {
+                models.push(Model::load(vfs, model)?);
+            }
+        }
+    }
+
+    let load_time = start.elapsed();
+
+    Ok(MapReport {
+        models: brush_models.len() + models.len(),
+        entities: entities.len(),
+        load_time_ms: duration_ms(load_time),
+        resident_growth_bytes: resident_before
+            .and_then(|before| Some(resident_bytes()? as i64 - before as i64)),
+    })
+}
+
+fn print_result(result: &MapResult) {
+    match result.report {
+        Some(ref report) => {
+            let resident = match report.resident_growth_bytes {
+                Some(r) => format!("{:+.1} MiB", r as f64 / (1024.0 * 1024.0)),
+                None => String::from("-"),
+            };
+            println!(
+                "{:<24} ok     {:>4} models {:>5} entities {:>9.2} ms {:>10}",
+                result.name, report.models, report.entities, report.load_time_ms, resident,
+            );
+        }
+
+        None => println!(
+            "{:<24} FAILED {}",
+            result.name,
+            result.error.as_deref().unwrap_or_default()
+        ),
+    }
+}
+
+fn main() {
+    env_logger::init();
+    let opt = Opt::from_args();
+
+    if opt.version {
+        println!("{}", VERSION);
+        exit(0);
+    }
+
+    let base_dir = opt
+        .base_dir
+        .unwrap_or_else(|| install::detect_base_dir(&install::find_installs(), None));
+    let vfs = Vfs::with_base_dir(base_dir);
+
+    let maps = if opt.maps.is_empty() {
+        vfs.file_names()
+            .into_iter()
+            .filter(|n| n.starts_with("maps/") && n.ends_with(".bsp"))
+            .collect()
+    } else {
+        opt.maps
+    };
+
+    if maps.is_empty() {
+        if opt.json {
+            println!("{}", serde_json::json!({ "error": "No maps found" }));
+        } else {
+            println!("No maps found");
+        }
+        exit(1);
+    }
+
+    let mut summary = Summary {
+        maps: Vec::new(),
+        failed: 0,
+        total_time_ms: 0.0,
+    };
+    for name in maps {
+        // a panic in the loader fails this map rather than the whole run
+        let checked = panic::catch_unwind(AssertUnwindSafe(|| check_map(&vfs, &name)))
+            .unwrap_or_else(|payload| {
+                Err(failure::err_msg(format!(
+                    "panicked: {}",
+                    panic_message(&*payload)
+                )))
+            });
+        let result = match checked {
+            Ok(report) => {
+                summary.total_time_ms += report.load_time_ms;
+                MapResult {
+                    name,
+                    report: Some(report),
+                    error: None,
+                }
+            }
+
+            Err(e) => {
+                summary.failed += 1;
+                MapResult {
+                    name,
+                    report: None,
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+
+        if !opt.json {
+            print_result(&result);
+        }
+        summary.maps.push(result);
+    }
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&summary).unwrap());
+    } else {
+        println!(
+            "{} maps checked, {} failed, {:.2} ms total",
+            summary.maps.len(),
+            summary.failed,
+            summary.total_time_ms,
+        );
+    }
+
+    if summary.failed > 0 {
+        exit(1);
+    }
+}
@@ -9,22 +9,41 @@ use std::{
 
 use richter::client::render::Extent2d;
 
-use chrono::Utc;
+use failure::Error;
 
 const BYTES_PER_PIXEL: u32 = 4;
 
+// screenshots are numbered from richter0000.png to richter9999.png
+const MAX_SCREENSHOTS: u32 = 10000;
+
+/// Returns the first screenshot path in `dir` which isn't taken.
+fn next_screenshot_path(dir: &Path) -> Option<PathBuf> {
+    (0..MAX_SCREENSHOTS)
+        .map(|i| dir.join(format!("richter{:04}.png", i)))
+        .find(|path| !path.exists())
+}
+
 /// Implements the "screenshot" command.
 ///
 /// This function returns a boxed closure which sets the `screenshot_path`
-/// argument to `Some` when called.
+/// argument to `Some` when called. Screenshots are saved in `screenshot_dir`,
+/// which should be the directory of the current profile.
 pub fn cmd_screenshot(
     screenshot_path: Rc<RefCell<Option<PathBuf>>>,
+    screenshot_dir: PathBuf,
 ) -> Box<dyn Fn(&[&str]) -> String> {
     Box::new(move |args| {
         let path = match args.len() {
-            // TODO: make default path configurable
-            0 => PathBuf::from(format!("richter-{}.png", Utc::now().format("%FT%H-%M-%S"))),
-            1 => PathBuf::from(args[0]),
+            0 => match next_screenshot_path(&screenshot_dir) {
+                Some(p) => p,
+                None => {
+                    return format!(
+                        "Couldn't save screenshot: {} screenshots already exist",
+                        MAX_SCREENSHOTS
+                    )
+                }
+            },
+            1 => screenshot_dir.join(args[0]),
             _ => {
                 log::error!("Usage: screenshot [PATH]");
                 return "Usage: screenshot [PATH]".to_owned();
@@ -43,12 +62,19 @@ pub struct Capture {
     // width of a row in the buffer, must be a multiple of 256 for mapped reads
     row_width: u32,
 
+    // format of the captured texture, which determines the channel order
+    format: wgpu::TextureFormat,
+
     // mappable buffer
     buffer: wgpu::Buffer,
 }
 
 impl Capture {
-    pub fn new(device: &wgpu::Device, capture_size: Extent2d) -> Capture {
+    pub fn new(
+        device: &wgpu::Device,
+        capture_size: Extent2d,
+        format: wgpu::TextureFormat,
+    ) -> Capture {
         // bytes_per_row must be a multiple of 256
         // 4 bytes per pixel, so width must be multiple of 64
         let row_width = (capture_size.width + 63) / 64 * 64;
@@ -63,6 +89,7 @@ impl Capture {
         Capture {
             capture_size,
             row_width,
+            format,
             buffer,
         }
     }
@@ -86,10 +113,16 @@ impl Capture {
         );
     }
 
-    pub fn write_to_file<P>(&self, device: &wgpu::Device, path: P)
+    pub fn write_to_file<P>(&self, device: &wgpu::Device, path: P) -> Result<(), Error>
     where
         P: AsRef<Path>,
     {
+        // PNG stores RGBA, so BGRA pixels have to be swizzled
+        let bgra = matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+
         let mut data = Vec::new();
         {
             // map the buffer
@@ -97,7 +130,8 @@ impl Capture {
             let slice = self.buffer.slice(..);
             let map_future = slice.map_async(wgpu::MapMode::Read);
             device.poll(wgpu::Maintain::Wait);
-            futures::executor::block_on(map_future).unwrap();
+            futures::executor::block_on(map_future)
+                .map_err(|_| failure::err_msg("couldn't map capture buffer"))?;
 
             // copy pixel data
             let mapped = slice.get_mapped_range();
@@ -106,14 +140,17 @@ impl Capture {
                 for pixel in
                     (&row[..self.capture_size.width as usize * BYTES_PER_PIXEL as usize]).chunks(4)
                 {
-                    // swap BGRA->RGBA
-                    data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                    if bgra {
+                        data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                    } else {
+                        data.extend_from_slice(pixel);
+                    }
                 }
             }
         }
         self.buffer.unmap();
 
-        let f = File::create(path).unwrap();
+        let f = File::create(path)?;
         let mut png_encoder = png::Encoder::new(
             BufWriter::new(f),
             self.capture_size.width,
@@ -121,7 +158,9 @@ impl Capture {
         );
         png_encoder.set_color(png::ColorType::RGBA);
         png_encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = png_encoder.write_header().unwrap();
-        writer.write_image_data(&data).unwrap();
+        let mut writer = png_encoder.write_header()?;
+        writer.write_image_data(&data)?;
+
+        Ok(())
     }
 }
@@ -31,6 +31,7 @@ use richter::{
         menu::Menu,
         render::{
             Extent2d, GraphicsState, RenderTarget as _, RenderTargetResolve as _, SwapChainTarget,
+            DIFFUSE_ATTACHMENT_FORMAT,
         },
         trace::TraceFrame,
         Client, ClientError,
@@ -61,14 +62,20 @@ impl Game {
         cmds: Rc<RefCell<CmdRegistry>>,
         input: Rc<RefCell<Input>>,
         client: Client,
+        profile_dir: PathBuf,
     ) -> Result<Game, Error> {
         // set up input commands
-        input.borrow().register_cmds(&mut cmds.borrow_mut());
+        input
+            .borrow()
+            .register_cmds(&mut cmds.borrow_mut(), cvars.clone());
 
         // set up screenshots
         let screenshot_path = Rc::new(RefCell::new(None));
         cmds.borrow_mut()
-            .insert("screenshot", cmd_screenshot(screenshot_path.clone()))
+            .insert(
+                "screenshot",
+                cmd_screenshot(screenshot_path.clone(), profile_dir.clone()),
+            )
             .unwrap();
 
         // set up frame tracing
@@ -77,7 +84,10 @@ impl Game {
             .insert("trace_begin", cmd_trace_begin(trace.clone()))
             .unwrap();
         cmds.borrow_mut()
-            .insert("trace_end", cmd_trace_end(cvars.clone(), trace.clone()))
+            .insert(
+                "trace_end",
+                cmd_trace_end(cvars.clone(), trace.clone(), profile_dir),
+            )
             .unwrap();
 
         Ok(Game {
@@ -98,7 +108,9 @@ impl Game {
             Ok(()) => (),
             Err(e) => match e {
                 Cvar(_)
+                | RegisteredMap(_)
                 | UnrecognizedProtocol(_)
+                | ProtocolExtensionDisabled(_)
                 | NoSuchClient(_)
                 | NoSuchPlayer(_)
                 | NoSuchEntity(_)
@@ -112,7 +124,7 @@ impl Game {
                 | Sound(_)
                 | Vfs(_) => {
                     log::error!("{}", e);
-                    self.client.disconnect();
+                    self.client.disconnect_with_error(&e);
                 }
 
                 _ => panic!("{}", e),
@@ -163,7 +175,11 @@ impl Game {
 
         // screenshot setup
         let capture = self.screenshot_path.borrow().as_ref().map(|_| {
-            let cap = Capture::new(gfx_state.device(), Extent2d { width, height });
+            let cap = Capture::new(
+                gfx_state.device(),
+                Extent2d { width, height },
+                DIFFUSE_ATTACHMENT_FORMAT,
+            );
             cap.copy_from_texture(
                 &mut encoder,
                 wgpu::ImageCopyTexture {
@@ -190,12 +206,16 @@ impl Game {
         }
 
         // write screenshot if requested and clear screenshot path
-        self.screenshot_path.replace(None).map(|path| {
-            capture
+        if let Some(path) = self.screenshot_path.replace(None) {
+            match capture
                 .as_ref()
                 .unwrap()
-                .write_to_file(gfx_state.device(), path)
-        });
+                .write_to_file(gfx_state.device(), &path)
+            {
+                Ok(()) => console.println(format!("Wrote {}", path.display())),
+                Err(e) => console.println(format!("Couldn't write {}: {}", path.display(), e)),
+            }
+        }
     }
 }
 
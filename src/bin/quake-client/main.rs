@@ -22,18 +22,20 @@ mod capture;
 mod game;
 mod menu;
 mod trace;
+mod vid;
 
 use std::{
-    cell::{Ref, RefCell, RefMut},
-    fs::File,
+    cell::{Cell, Ref, RefCell, RefMut},
+    env,
     io::{Cursor, Read, Write},
     net::SocketAddr,
-    path::{Path, PathBuf},
-    process::exit,
+    path::PathBuf,
+    process::{exit, Command},
     rc::Rc,
 };
 
 use game::Game;
+use vid::AdapterChoice;
 
 use chrono::Duration;
 use common::net::ServerCmd;
@@ -43,15 +45,24 @@ use richter::{
         demo::DemoServer,
         input::{Input, InputFocus},
         menu::Menu,
-        render::{self, Extent2d, GraphicsState, UiRenderer, DIFFUSE_ATTACHMENT_FORMAT},
+        render::{
+            self, Extent2d, GraphicsState, RenderScale, TextureFilter, UiRenderer,
+            DIFFUSE_ATTACHMENT_FORMAT,
+        },
         Client,
     },
     common::{
         self,
+        cache::DownloadCache,
         console::{CmdRegistry, Console, CvarRegistry},
-        host::{Host, Program},
-        vfs::Vfs,
+        host::{Activity, Host, Program},
+        install,
+        mission_pack::MissionPack,
+        net::connect::ServerStatus,
+        profile::{self, Profile},
+        vfs::{Edition, Vfs},
     },
+    server::listen::{self, Listener},
 };
 use structopt::StructOpt;
 use winit::{
@@ -70,70 +81,190 @@ struct ClientProgram {
     window: Window,
     window_dimensions_changed: bool,
 
+    // set by the "vid_restart" command and at startup, checked at the start of each frame once
+    // the console has run every queued command
+    vid_restart: Rc<Cell<bool>>,
+
+    // the size of the window's client area as of the last resize, in physical pixels
+    display_size: Extent2d,
+
+    instance: wgpu::Instance,
+    backends: wgpu::BackendBit,
+    adapter_choice: AdapterChoice,
+    trace: bool,
+
     surface: wgpu::Surface,
     swap_chain: RefCell<wgpu::SwapChain>,
+
+    // set when the swap chain is lost, and cleared once a frame is presented
+    swap_chain_lost: bool,
+
     gfx_state: RefCell<GraphicsState>,
     ui_renderer: Rc<UiRenderer>,
+    render_scale: RenderScale,
 
     game: Game,
     input: Rc<RefCell<Input>>,
+
+    // set by the "quit" command, checked by the event handler
+    quit: Rc<Cell<bool>>,
+    // set by the "restart" command, which also sets `quit`
+    restart: Rc<Cell<bool>>,
+
+    // set by the "listen" command. The listener is opened or closed to match after the console
+    // has run
+    listen: Rc<Cell<bool>>,
+    listener: Option<Listener>,
 }
 
 impl ClientProgram {
-    pub async fn new(window: Window, base_dir: Option<PathBuf>, trace: bool) -> ClientProgram {
-        let vfs = Vfs::with_base_dir(base_dir.unwrap_or(common::default_base_dir()));
+    pub async fn new(
+        window: Window,
+        base_dir: Option<PathBuf>,
+        user_dir: Option<PathBuf>,
+        profile: &str,
+        mission_pack: Option<MissionPack>,
+        trace: bool,
+        backends: wgpu::BackendBit,
+        adapter_choice: AdapterChoice,
+    ) -> ClientProgram {
+        // the user data directory holds the chosen installation, so find it first
+        let user_dir = user_dir.or_else(profile::default_user_dir);
+        let installs = install::find_installs();
+        let choice = user_dir.as_ref().and_then(install::load_choice);
+
+        // ask which installation to use if there's more than one and nothing else decides. until
+        // the player picks one, only the built-in fallback assets are mounted
+        let choose_install = base_dir.is_none()
+            && choice.is_none()
+            && installs.len() > 1
+            && !install::is_base_dir(common::default_base_dir());
+        let base_dir = base_dir.unwrap_or_else(|| {
+            if choose_install {
+                common::default_base_dir()
+            } else {
+                install::detect_base_dir(&installs, choice.clone())
+            }
+        });
+        log::info!("Reading game data from {}", base_dir.display());
+        let mut vfs = Vfs::with_base_dir(base_dir.clone());
+        if let (Some(pack), false) = (mission_pack, choose_install) {
+            let game_dir = base_dir.join(pack.game_dir());
+            if let Err(e) = vfs.add_game_dir(&game_dir) {
+                log::warn!(
+                    "Couldn't load mission pack from {}: {}",
+                    game_dir.display(),
+                    e
+                );
+            }
+        }
+
+        // fall back to writing into the game directory if there's nowhere better
+        let user_dir = user_dir.unwrap_or_else(|| base_dir.clone());
+        let profile = match Profile::open(&user_dir, profile) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        };
+        log::info!("Writing user data to {}", profile.dir().display());
+
+        if profile.is_default() && !choose_install {
+            match profile.migrate_legacy(base_dir.join("id1")) {
+                Ok(migrated) if !migrated.is_empty() => log::info!(
+                    "Copied {} from {}",
+                    migrated.join(", "),
+                    base_dir.join("id1").display()
+                ),
+                Ok(_) => (),
+                Err(e) => log::warn!("Couldn't migrate user data: {}", e),
+            }
+        }
 
         let con_names = Rc::new(RefCell::new(Vec::new()));
 
         let cvars = Rc::new(RefCell::new(CvarRegistry::new(con_names.clone())));
         client::register_cvars(&cvars.borrow()).unwrap();
         render::register_cvars(&cvars.borrow());
+        vid::register_cvars(&cvars.borrow());
+        listen::register_cvars(&cvars.borrow());
+
+        if vfs.edition() == Edition::Registered {
+            cvars.borrow().set("registered", "1").unwrap();
+        }
+        if let Some(pack) = mission_pack {
+            cvars.borrow().set("missionpack", pack.game_dir()).unwrap();
+        }
 
         let cmds = Rc::new(RefCell::new(CmdRegistry::new(con_names)));
         // TODO: register commands as other subsystems come online
 
+        // no config has been executed yet, so cl_cachesize still has its default value. the cap is
+        // applied by the client before anything is downloaded
+        match DownloadCache::open(user_dir.join("cache"), u64::MAX) {
+            Ok(cache) => {
+                let cache = Rc::new(RefCell::new(cache));
+                vfs.add_download_cache(cache.clone());
+                DownloadCache::register_cmds(cache, &cmds);
+            }
+            Err(e) => log::warn!("Couldn't open download cache: {}", e),
+        }
+
+        // mount the profile last so its configs take precedence over the game data. everything
+        // written on the user's behalf goes there, so the base directory may be read-only
+        if let Err(e) = vfs.add_writable_directory(profile.dir()) {
+            log::warn!("Couldn't mount {}: {}", profile.dir().display(), e);
+        }
+        let vfs = Rc::new(vfs);
+        profile.register_cmds(user_dir.clone(), &cmds);
+        install::register_cmds(installs.clone(), base_dir.clone(), user_dir.clone(), &cmds);
+
         let console = Rc::new(RefCell::new(Console::new(cmds.clone(), cvars.clone())));
-        let menu = Rc::new(RefCell::new(menu::build_main_menu().unwrap()));
+        let menu = if choose_install {
+            menu::build_install_chooser(&installs, console.clone()).unwrap()
+        } else {
+            menu::build_main_menu(vfs.clone(), &installs, cvars.clone(), console.clone()).unwrap()
+        };
+        let menu = Rc::new(RefCell::new(menu));
+
+        // the chooser is up at startup, and the console lists the installations in full
+        if choose_install {
+            let console = console.borrow();
+            console.println(format!(
+                "Found {} Quake installations, choose one to play",
+                installs.len()
+            ));
+            console.stuff_text("basedir\n");
+        }
 
         let input = Rc::new(RefCell::new(Input::new(
-            InputFocus::Console,
+            if choose_install {
+                InputFocus::Menu
+            } else {
+                InputFocus::Console
+            },
             console.clone(),
             menu.clone(),
         )));
-        input.borrow_mut().bind_defaults();
+        let bindings_profile = cvars
+            .borrow()
+            .get("bindings_profile")
+            .ok()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or_default();
+        input.borrow_mut().bind_defaults(bindings_profile);
 
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+        let instance = wgpu::Instance::new(backends);
         let surface = unsafe { instance.create_surface(&window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::PUSH_CONSTANTS
-                        | wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY
-                        | wgpu::Features::SAMPLED_TEXTURE_ARRAY_DYNAMIC_INDEXING
-                        | wgpu::Features::SAMPLED_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
-                    limits: wgpu::Limits {
-                        max_sampled_textures_per_shader_stage: 256,
-                        max_uniform_buffer_binding_size: 65536,
-                        max_push_constant_size: 256,
-                        ..Default::default()
-                    },
-                },
-                if trace {
-                    Some(Path::new("./trace/"))
-                } else {
-                    None
-                },
-            )
-            .await
-            .unwrap();
+        let (adapter, device, queue) =
+            match vid::create_device(&instance, &surface, backends, &adapter_choice, trace).await {
+                Ok(d) => d,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            };
         let size: Extent2d = window.inner_size().into();
         let swap_chain = RefCell::new(device.create_swap_chain(
             &surface,
@@ -146,14 +277,19 @@ impl ClientProgram {
             },
         ));
 
-        let vfs = Rc::new(vfs);
+        vid::register_cmds(
+            &mut cmds.borrow_mut(),
+            &window,
+            &instance,
+            backends,
+            &adapter,
+            &device,
+        );
+        vid::report_adapter(&cvars.borrow(), &console.borrow(), &adapter.get_info());
 
-        // TODO: warn user if r_msaa_samples is invalid
-        let mut sample_count = cvars.borrow().get_value("r_msaa_samples").unwrap_or(2.0) as u32;
-        if !&[2, 4].contains(&sample_count) {
-            sample_count = 2;
-        }
+        Vfs::register_cmds(vfs.clone(), &cmds);
 
+        let sample_count = render::msaa_sample_count(&cvars.borrow());
         let gfx_state = GraphicsState::new(device, queue, size, sample_count, vfs.clone()).unwrap();
         let ui_renderer = Rc::new(UiRenderer::new(&gfx_state, &menu.borrow()));
 
@@ -186,6 +322,47 @@ impl ClientProgram {
             }),
         ).unwrap();
 
+        // implements "quit" command
+        let quit = Rc::new(Cell::new(false));
+        let quit_handle = quit.clone();
+        cmds.borrow_mut()
+            .insert_or_replace(
+                "quit",
+                Box::new(move |_| {
+                    quit_handle.set(true);
+                    String::new()
+                }),
+            )
+            .unwrap();
+
+        // implements "restart" command, which starts a new client once this one has quit
+        let restart = Rc::new(Cell::new(false));
+        let restart_handle = restart.clone();
+        let restart_quit_handle = quit.clone();
+        cmds.borrow_mut()
+            .insert_or_replace(
+                "restart",
+                Box::new(move |_| {
+                    restart_handle.set(true);
+                    restart_quit_handle.set(true);
+                    String::new()
+                }),
+            )
+            .unwrap();
+
+        // implements "vid_restart" command. The archived window mode is applied once the configs
+        // executed below have run
+        let vid_restart = Rc::new(Cell::new(true));
+        cmds.borrow_mut()
+            .insert_or_replace("vid_restart", vid::cmd_vid_restart(vid_restart.clone()))
+            .unwrap();
+
+        // implements "listen" command
+        let listen = Rc::new(Cell::new(false));
+        cmds.borrow_mut()
+            .insert_or_replace("listen", listen::cmd_listen(listen.clone()))
+            .unwrap();
+
         // this will also execute config.cfg and autoexec.cfg (assuming an unmodified quake.rc)
         console.borrow().stuff_text("exec quake.rc\n");
 
@@ -199,7 +376,14 @@ impl ClientProgram {
             &menu.borrow(),
         );
 
-        let game = Game::new(cvars.clone(), cmds.clone(), input.clone(), client).unwrap();
+        let game = Game::new(
+            cvars.clone(),
+            cmds.clone(),
+            input.clone(),
+            client,
+            profile.dir().to_owned(),
+        )
+        .unwrap();
 
         ClientProgram {
             vfs,
@@ -209,18 +393,72 @@ impl ClientProgram {
             menu,
             window,
             window_dimensions_changed: false,
+            vid_restart,
+            display_size: size,
+            instance,
+            backends,
+            adapter_choice,
+            trace,
             surface,
             swap_chain,
+            swap_chain_lost: false,
             gfx_state: RefCell::new(gfx_state),
             ui_renderer,
+            render_scale: RenderScale::new(),
             game,
             input,
+            quit,
+            restart,
+            listen,
+            listener: None,
+        }
+    }
+
+    /// Opens or closes the LAN listener as set by `listen` and answers waiting requests.
+    fn update_listener(&mut self) {
+        match (self.listen.get(), self.listener.is_some()) {
+            (true, false) => match Listener::bind(listen::DEFAULT_PORT) {
+                Ok(l) => self.listener = Some(l),
+                Err(e) => {
+                    self.listen.set(false);
+                    self.console.borrow().println(format!(
+                        "Couldn't listen on port {}: {}",
+                        listen::DEFAULT_PORT,
+                        e
+                    ));
+                }
+            },
+            (false, true) => self.listener = None,
+            _ => (),
+        }
+
+        let listener = match self.listener {
+            Some(ref l) => l,
+            None => return,
+        };
+
+        let address = match listener.local_addr() {
+            Ok(a) => a.to_string(),
+            Err(_) => String::new(),
+        };
+        let status = ServerStatus {
+            address,
+            hostname: self.cvars.borrow().get("hostname").unwrap_or_default(),
+            // there's no local game to describe until the client can host one
+            levelname: String::new(),
+            max_clients: 1,
+            players: Vec::new(),
+            rules: Vec::new(),
+        };
+
+        if let Err(e) = listener.frame(&status) {
+            log::warn!("Couldn't answer LAN request: {}", e);
         }
     }
 
     /// Builds a new swap chain with the specified present mode and the window's current dimensions.
     fn recreate_swap_chain(&self, present_mode: wgpu::PresentMode) {
-        let winit::dpi::PhysicalSize { width, height } = self.window.inner_size();
+        let Extent2d { width, height } = self.display_size;
         let swap_chain = self.gfx_state.borrow().device().create_swap_chain(
             &self.surface,
             &wgpu::SwapChainDescriptor {
@@ -234,9 +472,101 @@ impl ClientProgram {
         let _ = self.swap_chain.replace(swap_chain);
     }
 
+    /// Replaces the graphics device after it has been lost.
+    ///
+    /// Every renderer is rebuilt from resources held on the CPU, so the client keeps running
+    /// without reconnecting.
+    fn recover_device(&mut self) {
+        log::warn!("Graphics device lost, recreating it");
+
+        let (adapter, device, queue) = match futures::executor::block_on(vid::create_device(
+            &self.instance,
+            &self.surface,
+            self.backends,
+            &self.adapter_choice,
+            self.trace,
+        )) {
+            Ok(d) => d,
+            Err(e) => {
+                // try again next frame
+                log::error!("Couldn't recreate graphics device: {}", e);
+                return;
+            }
+        };
+
+        vid::register_cmds(
+            &mut self.cmds.borrow_mut(),
+            &self.window,
+            &self.instance,
+            self.backends,
+            &adapter,
+            &device,
+        );
+        vid::report_adapter(
+            &self.cvars.borrow(),
+            &self.console.borrow(),
+            &adapter.get_info(),
+        );
+
+        let sample_count = self.gfx_state.borrow().sample_count();
+        let gfx_state = match GraphicsState::new(
+            device,
+            queue,
+            self.display_size,
+            sample_count,
+            self.vfs.clone(),
+        ) {
+            Ok(g) => g,
+            Err(e) => {
+                log::error!("Couldn't recreate graphics state: {}", e);
+                return;
+            }
+        };
+
+        self.ui_renderer = Rc::new(UiRenderer::new(&gfx_state, &self.menu.borrow()));
+        self.game
+            .client
+            .rebuild_renderers(&gfx_state, &self.menu.borrow());
+        self.gfx_state.replace(gfx_state);
+        self.recreate_swap_chain(wgpu::PresentMode::Immediate);
+
+        self.console
+            .borrow()
+            .println_alert("WARNING: the graphics device was lost and has been recreated.");
+    }
+
     fn render(&mut self) {
-        let swap_chain_output = self.swap_chain.borrow_mut().get_current_frame().unwrap();
-        let winit::dpi::PhysicalSize { width, height } = self.window.inner_size();
+        let frame = self.swap_chain.borrow_mut().get_current_frame();
+        let swap_chain_output = match frame {
+            Ok(f) => {
+                self.swap_chain_lost = false;
+                f
+            }
+
+            // the window changed in a way the resize event didn't catch
+            Err(wgpu::SwapChainError::Outdated) => {
+                self.recreate_swap_chain(wgpu::PresentMode::Immediate);
+                return;
+            }
+
+            Err(wgpu::SwapChainError::Timeout) => return,
+
+            // a lost swap chain is recreated once, and if the new one is lost too the device is
+            // assumed to be gone
+            Err(wgpu::SwapChainError::Lost) if !self.swap_chain_lost => {
+                self.swap_chain_lost = true;
+                self.recreate_swap_chain(wgpu::PresentMode::Immediate);
+                return;
+            }
+
+            Err(e) => {
+                log::error!("Couldn't get swap chain frame: {}", e);
+                self.swap_chain_lost = false;
+                self.recover_device();
+                return;
+            }
+        };
+        let Extent2d { width, height } = self.display_size;
         self.game.render(
             &self.gfx_state.borrow(),
             &swap_chain_output.output.view,
@@ -253,13 +583,33 @@ impl Program for ClientProgram {
         &mut self,
         event: Event<T>,
         _target: &EventLoopWindowTarget<T>,
-        _control_flow: &mut ControlFlow,
+        control_flow: &mut ControlFlow,
     ) {
+        // commands run during the frame, so the quit command can't exit the loop itself
+        if self.quit.get() {
+            self.shutdown();
+            if self.restart.get() {
+                relaunch();
+            }
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+
         match event {
             Event::WindowEvent {
-                event: WindowEvent::Resized(_),
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                self.display_size = size.into();
+                self.window_dimensions_changed = true;
+            }
+
+            // moving to a display with a different DPI resizes the window without a resize event
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { new_inner_size, .. },
                 ..
             } => {
+                self.display_size = (*new_inner_size).into();
                 self.window_dimensions_changed = true;
             }
 
@@ -268,26 +618,47 @@ impl Program for ClientProgram {
     }
 
     fn frame(&mut self, frame_duration: Duration) {
-        // recreate swapchain if needed
-        if self.window_dimensions_changed {
-            self.window_dimensions_changed = false;
-            self.recreate_swap_chain(wgpu::PresentMode::Immediate);
+        // the new window size arrives as a resize event, so the swap chain and render targets
+        // are recreated on a later frame. At startup this waits for the configs, which take
+        // several frames to run since each exec queues the file's commands
+        if !self.console.borrow().has_pending_text() && self.vid_restart.replace(false) {
+            match vid::apply_window_mode(&self.window, &self.cvars.borrow()) {
+                Ok(mode) => log::info!("Switched to {:?} window mode", mode),
+                Err(e) => self
+                    .console
+                    .borrow()
+                    .println(format!("Couldn't change window mode: {}", e)),
+            }
         }
 
-        let size: Extent2d = self.window.inner_size().into();
+        // a minimized window has nothing to draw into, so the game runs without rendering until
+        // it's restored
+        let minimized = self.display_size.width == 0 || self.display_size.height == 0;
 
-        // TODO: warn user if r_msaa_samples is invalid
-        let mut sample_count = self
-            .cvars
-            .borrow()
-            .get_value("r_msaa_samples")
-            .unwrap_or(2.0) as u32;
-        if !&[2, 4].contains(&sample_count) {
-            sample_count = 2;
+        if !minimized {
+            // recreate swapchain if needed
+            if self.window_dimensions_changed {
+                self.window_dimensions_changed = false;
+                self.recreate_swap_chain(wgpu::PresentMode::Immediate);
+            }
+
+            let sample_count = render::msaa_sample_count(&self.cvars.borrow());
+            let texture_filter = TextureFilter::from_cvars(&self.cvars.borrow());
+            let scale = self
+                .render_scale
+                .update(&self.cvars.borrow(), frame_duration);
+
+            // recreate attachments at the new size and rebuild pipelines if necessary. Everything
+            // sized to the display, such as the view rectangle, aspect ratio and 2D layout, is
+            // recomputed from the display size each frame.
+            self.gfx_state.borrow_mut().update(
+                self.display_size,
+                scale,
+                sample_count,
+                texture_filter,
+            );
         }
 
-        // recreate attachments and rebuild pipelines if necessary
-        self.gfx_state.borrow_mut().update(size, sample_count);
         self.game.frame(&self.gfx_state.borrow(), frame_duration);
 
         match self.input.borrow().focus() {
@@ -311,8 +682,22 @@ impl Program for ClientProgram {
 
         // run console commands
         self.console.borrow().execute();
+        self.update_listener();
 
-        self.render();
+        if !minimized {
+            self.render();
+        }
+    }
+
+    fn activity(&self) -> Activity {
+        let input = self.input.borrow();
+        if !input.window_focused() {
+            Activity::Background
+        } else if !self.game.client.connected() && input.focus() != InputFocus::Game {
+            Activity::Idle
+        } else {
+            Activity::Active
+        }
     }
 
     fn shutdown(&mut self) {
@@ -328,6 +713,15 @@ impl Program for ClientProgram {
     }
 }
 
+/// Starts a new client with the same arguments, e.g. to mount a newly chosen installation.
+fn relaunch() {
+    let spawned =
+        env::current_exe().and_then(|exe| Command::new(exe).args(env::args_os().skip(1)).spawn());
+    if let Err(e) = spawned {
+        log::error!("Couldn't restart: {}", e);
+    }
+}
+
 #[derive(StructOpt, Debug)]
 struct Opt {
     #[structopt(long)]
@@ -344,6 +738,38 @@ struct Opt {
 
     #[structopt(long)]
     base_dir: Option<PathBuf>,
+
+    /// The directory to write configs, saves, screenshots and downloads to. Defaults to the
+    /// platform's user data directory. Pass the base directory to keep everything in place.
+    #[structopt(long)]
+    user_dir: Option<PathBuf>,
+
+    /// The profile whose configs, saves and screenshots are used. Profiles other than the default
+    /// are stored in profiles/NAME under the user directory.
+    #[structopt(long, default_value = "default")]
+    profile: String,
+
+    /// Play Scourge of Armagon, the first mission pack, from hipnotic/ under the base directory.
+    #[structopt(long, conflicts_with = "rogue")]
+    hipnotic: bool,
+
+    /// Play Dissolution of Eternity, the second mission pack, from rogue/ under the base directory.
+    #[structopt(long)]
+    rogue: bool,
+
+    /// The graphics backend to use: primary, vulkan, metal, dx12, dx11 or gl.
+    #[structopt(
+        long,
+        alias = "gpu-backend",
+        default_value = "primary",
+        parse(try_from_str = vid::parse_backends)
+    )]
+    vid_backend: wgpu::BackendBit,
+
+    /// The graphics adapter to use: integrated, discrete, an index listed by vid_info or part of
+    /// an adapter's name.
+    #[structopt(long, alias = "gpu-adapter", default_value = "discrete")]
+    vid_adapter: AdapterChoice,
 }
 
 fn main() {
@@ -374,8 +800,24 @@ fn main() {
         }
     };
 
-    let client_program =
-        futures::executor::block_on(ClientProgram::new(window, opt.base_dir, opt.trace));
+    let mission_pack = if opt.hipnotic {
+        Some(MissionPack::Hipnotic)
+    } else if opt.rogue {
+        Some(MissionPack::Rogue)
+    } else {
+        None
+    };
+
+    let client_program = futures::executor::block_on(ClientProgram::new(
+        window,
+        opt.base_dir,
+        opt.user_dir,
+        &opt.profile,
+        mission_pack,
+        opt.trace,
+        opt.vid_backend,
+        opt.vid_adapter,
+    ));
 
     // TODO: make dump_demo part of top-level binary and allow choosing file name
     if let Some(ref demo) = opt.dump_demo {
@@ -395,7 +837,13 @@ fn main() {
             }
         };
 
-        let mut outfile = File::create("demodump.txt").unwrap();
+        let mut outfile = match client_program.vfs.create("demodump.txt") {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("error creating demodump.txt: {}", e);
+                std::process::exit(1);
+            }
+        };
         loop {
             match demserv.next() {
                 Some(msg) => {
@@ -18,17 +18,53 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use richter::client::menu::{Menu, MenuBodyView, MenuBuilder, MenuView};
+use std::{cell::RefCell, rc::Rc};
+
+use richter::{
+    client::{
+        demo::DemoSummary,
+        input::game::BindingsProfile,
+        menu::{EnumItem, Menu, MenuBodyView, MenuBuilder, MenuView},
+        render::{self, ANISOTROPY_LEVELS, MSAA_SAMPLE_COUNTS},
+    },
+    common::{
+        console::{Console, CvarRegistry},
+        install::Install,
+        vfs::Vfs,
+    },
+};
 
 use failure::Error;
+use log::warn;
+
+/// Maximum number of characters of a demo's name shown in the demo browser.
+const DEMO_NAME_WIDTH: usize = 10;
+
+/// Port used by the join menu if the player doesn't enter one.
+const DEFAULT_PORT: &str = "26000";
+
+/// The bindings profiles offered by the options menu, in order.
+const BINDINGS_PROFILES: [BindingsProfile; 2] = [BindingsProfile::Modern, BindingsProfile::Classic];
 
-pub fn build_main_menu() -> Result<Menu, Error> {
+pub fn build_main_menu(
+    vfs: Rc<Vfs>,
+    installs: &[Install],
+    cvars: Rc<RefCell<CvarRegistry>>,
+    console: Rc<RefCell<Console>>,
+) -> Result<Menu, Error> {
+    let quit_console = console.clone();
     Ok(MenuBuilder::new()
-        .add_submenu("Single Player", build_menu_sp()?)
-        .add_submenu("Multiplayer", build_menu_mp()?)
-        .add_submenu("Options", build_menu_options()?)
+        .add_submenu("Single Player", build_menu_sp(console.clone())?)
+        .add_submenu("Multiplayer", build_menu_mp(console.clone())?)
+        .add_submenu(
+            "Options",
+            build_menu_options(vfs, installs, cvars, console)?,
+        )
         .add_action("Help/Ordering", Box::new(|| ()))
-        .add_action("Quit", Box::new(|| ()))
+        .add_action(
+            "Quit",
+            Box::new(move || quit_console.borrow().stuff_text("quit\n")),
+        )
         .build(MenuView {
             draw_plaque: true,
             title_path: "gfx/ttl_main.lmp".to_string(),
@@ -38,9 +74,17 @@ pub fn build_main_menu() -> Result<Menu, Error> {
         }))
 }
 
-fn build_menu_sp() -> Result<Menu, Error> {
+fn build_menu_sp(console: Rc<RefCell<Console>>) -> Result<Menu, Error> {
     Ok(MenuBuilder::new()
-        .add_action("New Game", Box::new(|| ()))
+        // TODO: start a local server once the client can host one
+        .add_action(
+            "New Game",
+            Box::new(move || {
+                console
+                    .borrow()
+                    .println("No local server available, use Multiplayer > Join a Game")
+            }),
+        )
         // .add_submenu("Load", unimplemented!())
         // .add_submenu("Save", unimplemented!())
         .build(MenuView {
@@ -52,9 +96,9 @@ fn build_menu_sp() -> Result<Menu, Error> {
         }))
 }
 
-fn build_menu_mp() -> Result<Menu, Error> {
+fn build_menu_mp(console: Rc<RefCell<Console>>) -> Result<Menu, Error> {
     Ok(MenuBuilder::new()
-        .add_submenu("Join a Game", build_menu_mp_join()?)
+        .add_submenu("Join a Game", build_menu_mp_join(console)?)
         // .add_submenu("New Game", unimplemented!())
         // .add_submenu("Setup", unimplemented!())
         .build(MenuView {
@@ -66,9 +110,9 @@ fn build_menu_mp() -> Result<Menu, Error> {
         }))
 }
 
-fn build_menu_mp_join() -> Result<Menu, Error> {
+fn build_menu_mp_join(console: Rc<RefCell<Console>>) -> Result<Menu, Error> {
     Ok(MenuBuilder::new()
-        .add_submenu("TCP", build_menu_mp_join_tcp()?)
+        .add_submenu("TCP", build_menu_mp_join_tcp(console)?)
         // .add_textbox // description
         .build(MenuView {
             draw_plaque: true,
@@ -79,20 +123,49 @@ fn build_menu_mp_join() -> Result<Menu, Error> {
         }))
 }
 
-fn build_menu_mp_join_tcp() -> Result<Menu, Error> {
-    // Join Game - TCP/IP          // title
-    //
-    //  Address: 127.0.0.1         // label
-    //
-    //  Port     [26000]           // text field
-    //
-    //  Search for local games...  // menu
-    //
-    //  Join game at:              // label
-    //  [                        ] // text field
+fn build_menu_mp_join_tcp(console: Rc<RefCell<Console>>) -> Result<Menu, Error> {
+    // the text fields only report edits, so keep the latest contents for the join action
+    let address = Rc::new(RefCell::new(String::new()));
+    let port = Rc::new(RefCell::new(DEFAULT_PORT.to_owned()));
+
+    let address_handle = address.clone();
+    let port_handle = port.clone();
     Ok(MenuBuilder::new()
-        // .add
-        .add_toggle("placeholder", false, Box::new(|_| ()))
+        .add_text_field(
+            "Join game at",
+            None,
+            Some(24),
+            Box::new(move |text| {
+                address_handle.replace(text.to_owned());
+            }),
+        )?
+        .add_text_field(
+            "Port",
+            Some(DEFAULT_PORT),
+            Some(5),
+            Box::new(move |text| {
+                port_handle.replace(text.to_owned());
+            }),
+        )?
+        .add_action(
+            "Join",
+            Box::new(move || {
+                let address = address.borrow();
+                if address.is_empty() {
+                    return;
+                }
+
+                let port = port.borrow();
+                let port = if port.is_empty() {
+                    DEFAULT_PORT
+                } else {
+                    port.as_str()
+                };
+                console
+                    .borrow()
+                    .stuff_text(format!("connect {}:{}\n", address, port));
+            }),
+        )
         .build(MenuView {
             draw_plaque: true,
             title_path: "gfx/p_multi.lmp".to_string(),
@@ -100,25 +173,407 @@ fn build_menu_mp_join_tcp() -> Result<Menu, Error> {
         }))
 }
 
-fn build_menu_options() -> Result<Menu, Error> {
+fn build_menu_options(
+    vfs: Rc<Vfs>,
+    installs: &[Install],
+    cvars: Rc<RefCell<CvarRegistry>>,
+    console: Rc<RefCell<Console>>,
+) -> Result<Menu, Error> {
+    let console_console = console.clone();
+    let reset_console = console.clone();
+    let inverted_cvars = cvars.clone();
+    let invert_cvars = cvars.clone();
+    let controls_cvars = cvars.clone();
+
     Ok(MenuBuilder::new()
         // .add_submenu("Customize controls", unimplemented!())
-        .add_action("Go to console", Box::new(|| ()))
-        .add_action("Reset to defaults", Box::new(|| ()))
-        .add_slider("Render scale", 0.25, 1.0, 2, 0, Box::new(|_| ()))?
-        .add_slider("Screen Size", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_slider("Brightness", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_slider("Mouse Speed", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_slider("CD music volume", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_slider("Sound volume", 0.0, 1.0, 10, 9, Box::new(|_| ()))?
-        .add_toggle("Always run", true, Box::new(|_| ()))
-        .add_toggle("Invert mouse", false, Box::new(|_| ()))
-        .add_toggle("Lookspring", false, Box::new(|_| ()))
-        .add_toggle("Lookstrafe", false, Box::new(|_| ()))
-        // .add_submenu("Video options", unimplemented!())
+        .add_submenu("Demos", build_menu_demos(vfs, console.clone())?)
+        .add_submenu(
+            "Game directory",
+            build_menu_installs(installs, console.clone())?,
+        )
+        .add_action(
+            "Go to console",
+            Box::new(move || console_console.borrow().stuff_text("toggleconsole\n")),
+        )
+        .add_action(
+            "Reset to defaults",
+            Box::new(move || reset_console.borrow().stuff_text("exec default.cfg\n")),
+        )
+        .add_bound_enum(
+            "Controls",
+            build_controls_items(cvars.clone())?,
+            Box::new(move || {
+                let name = controls_cvars
+                    .borrow()
+                    .get("bindings_profile")
+                    .unwrap_or_default();
+                let profile = name.parse().unwrap_or(BindingsProfile::Modern);
+                BINDINGS_PROFILES
+                    .iter()
+                    .position(|&p| p == profile)
+                    .unwrap_or(0)
+            }),
+        )?
+        .add_bound_slider(
+            "Screen Size",
+            30.0,
+            120.0,
+            10,
+            cvar_value(&cvars, "viewsize", 30.0),
+            set_cvar_value(&cvars, "viewsize"),
+        )?
+        .add_bound_slider(
+            "Status bar scale",
+            1.0,
+            4.0,
+            7,
+            cvar_value(&cvars, "scr_sbarscale", 1.0),
+            set_cvar_value(&cvars, "scr_sbarscale"),
+        )?
+        .add_bound_slider(
+            "Mouse Speed",
+            1.0,
+            11.0,
+            11,
+            cvar_value(&cvars, "sensitivity", 1.0),
+            set_cvar_value(&cvars, "sensitivity"),
+        )?
+        .add_bound_slider(
+            "Music volume",
+            0.0,
+            1.0,
+            11,
+            cvar_value(&cvars, "bgmvolume", 0.0),
+            set_cvar_value(&cvars, "bgmvolume"),
+        )?
+        .add_bound_slider(
+            "Sound volume",
+            0.0,
+            1.0,
+            11,
+            cvar_value(&cvars, "volume", 0.0),
+            set_cvar_value(&cvars, "volume"),
+        )?
+        .add_bound_toggle(
+            "Always run",
+            cvar_toggle(&cvars, "cl_run"),
+            set_cvar_toggle(&cvars, "cl_run"),
+        )
+        .add_bound_toggle(
+            "Invert mouse",
+            Box::new(move || inverted_cvars.borrow().get_value("m_pitch").unwrap_or(0.0) < 0.0),
+            Box::new(move |invert| {
+                let cvars = invert_cvars.borrow();
+                let m_pitch = cvars.get_value("m_pitch").unwrap_or(0.022).abs();
+                let m_pitch = if invert { -m_pitch } else { m_pitch };
+                let _ = cvars.set("m_pitch", m_pitch.to_string().as_str());
+            }),
+        )
+        .add_bound_toggle(
+            "Mouse look",
+            cvar_toggle(&cvars, "freelook"),
+            set_cvar_toggle(&cvars, "freelook"),
+        )
+        .add_submenu("Video options", build_menu_video(cvars.clone())?)
+        .build(MenuView {
+            draw_plaque: true,
+            title_path: "gfx/p_option.lmp".to_string(),
+            body: MenuBodyView::Dynamic,
+        }))
+}
+
+fn build_menu_video(cvars: Rc<RefCell<CvarRegistry>>) -> Result<Menu, Error> {
+    let msaa_cvars = cvars.clone();
+    let anisotropy_cvars = cvars.clone();
+
+    Ok(MenuBuilder::new()
+        .add_bound_slider(
+            "Field of view",
+            70.0,
+            130.0,
+            13,
+            cvar_value(&cvars, "fov", 70.0),
+            set_cvar_value(&cvars, "fov"),
+        )?
+        .add_bound_enum(
+            "Antialiasing",
+            build_msaa_items(cvars.clone())?,
+            Box::new(move || {
+                let samples = render::msaa_sample_count(&msaa_cvars.borrow());
+                MSAA_SAMPLE_COUNTS
+                    .iter()
+                    .position(|&s| s == samples)
+                    .unwrap_or(0)
+            }),
+        )?
+        .add_bound_enum(
+            "Anisotropy",
+            build_anisotropy_items(cvars.clone())?,
+            Box::new(move || {
+                let level = render::anisotropy_level(&anisotropy_cvars.borrow());
+                ANISOTROPY_LEVELS
+                    .iter()
+                    .position(|&l| l == level)
+                    .unwrap_or(0)
+            }),
+        )?
+        .add_bound_toggle(
+            "Show weapon",
+            cvar_toggle(&cvars, "r_drawviewmodel"),
+            set_cvar_toggle(&cvars, "r_drawviewmodel"),
+        )
+        .add_bound_toggle(
+            "Dynamic lights",
+            cvar_toggle(&cvars, "r_dynamic"),
+            set_cvar_toggle(&cvars, "r_dynamic"),
+        )
+        .add_bound_toggle(
+            "Particles",
+            cvar_toggle(&cvars, "r_particles"),
+            set_cvar_toggle(&cvars, "r_particles"),
+        )
+        .add_bound_toggle(
+            "Underwater warp",
+            cvar_toggle(&cvars, "r_waterwarp"),
+            set_cvar_toggle(&cvars, "r_waterwarp"),
+        )
+        .add_bound_toggle(
+            "Software look",
+            cvar_toggle(&cvars, "r_softwarelook"),
+            set_cvar_toggle(&cvars, "r_softwarelook"),
+        )
+        .build(MenuView {
+            draw_plaque: true,
+            title_path: "gfx/p_option.lmp".to_string(),
+            body: MenuBodyView::Dynamic,
+        }))
+}
+
+/// Returns a choice of each supported multisample count.
+fn build_msaa_items(cvars: Rc<RefCell<CvarRegistry>>) -> Result<Vec<EnumItem>, Error> {
+    MSAA_SAMPLE_COUNTS
+        .iter()
+        .map(|&samples| {
+            let cvars = cvars.clone();
+            let label = match samples {
+                1 => "Off".to_string(),
+                n => format!("{}x", n),
+            };
+            EnumItem::new(
+                label,
+                Box::new(move || {
+                    let _ = cvars.borrow().set("r_msaa", samples.to_string().as_str());
+                }),
+            )
+        })
+        .collect()
+}
+
+/// Returns a choice of each anisotropic filtering level.
+fn build_anisotropy_items(cvars: Rc<RefCell<CvarRegistry>>) -> Result<Vec<EnumItem>, Error> {
+    ANISOTROPY_LEVELS
+        .iter()
+        .map(|&level| {
+            let cvars = cvars.clone();
+            let label = match level {
+                1 => "Off".to_string(),
+                n => format!("{}x", n),
+            };
+            EnumItem::new(
+                label,
+                Box::new(move || {
+                    let _ = cvars
+                        .borrow()
+                        .set("r_anisotropy", level.to_string().as_str());
+                }),
+            )
+        })
+        .collect()
+}
+
+/// Returns a function reading the current value of a cvar, or `default` if it can't be read.
+///
+/// Menu items read their cvars whenever they're shown, so values set by configs after the menu
+/// was built are picked up.
+fn cvar_value(
+    cvars: &Rc<RefCell<CvarRegistry>>,
+    name: &'static str,
+    default: f32,
+) -> Box<dyn Fn() -> f32> {
+    let cvars = cvars.clone();
+    Box::new(move || cvars.borrow().get_value(name).unwrap_or(default))
+}
+
+fn cvar_toggle(cvars: &Rc<RefCell<CvarRegistry>>, name: &'static str) -> Box<dyn Fn() -> bool> {
+    let cvars = cvars.clone();
+    Box::new(move || cvars.borrow().get_value(name).unwrap_or(0.0) != 0.0)
+}
+
+fn set_cvar_value(cvars: &Rc<RefCell<CvarRegistry>>, name: &'static str) -> Box<dyn Fn(f32)> {
+    let cvars = cvars.clone();
+    Box::new(move |value| {
+        let _ = cvars.borrow().set(name, value.to_string().as_str());
+    })
+}
+
+fn set_cvar_toggle(cvars: &Rc<RefCell<CvarRegistry>>, name: &'static str) -> Box<dyn Fn(bool)> {
+    let cvars = cvars.clone();
+    Box::new(move |on| {
+        let _ = cvars.borrow().set(name, if on { "1" } else { "0" });
+    })
+}
+
+/// Returns a choice of bindings profile for each built-in profile.
+///
+/// Choosing a profile only sets `bindings_profile`. The player's bindings are kept until they
+/// choose to reset them.
+fn build_controls_items(cvars: Rc<RefCell<CvarRegistry>>) -> Result<Vec<EnumItem>, Error> {
+    BINDINGS_PROFILES
+        .iter()
+        .map(|profile| {
+            let label = match profile {
+                BindingsProfile::Modern => "Modern",
+                BindingsProfile::Classic => "Classic",
+            };
+            let name = profile.to_string();
+            let cvars = cvars.clone();
+            EnumItem::new(
+                label,
+                Box::new(move || {
+                    let _ = cvars.borrow().set("bindings_profile", name.as_str());
+                }),
+            )
+        })
+        .collect()
+}
+
+fn build_menu_installs(installs: &[Install], console: Rc<RefCell<Console>>) -> Result<Menu, Error> {
+    let mut builder = MenuBuilder::new();
+
+    if installs.is_empty() {
+        builder = builder.add_action("No installations found", Box::new(|| ()));
+    }
+
+    for (i, install) in installs.iter().enumerate() {
+        let console = console.clone();
+        builder = builder.add_action(
+            install_item_name(install),
+            Box::new(move || console.borrow().stuff_text(format!("basedir {}\n", i + 1))),
+        );
+    }
+
+    Ok(builder.build(MenuView {
+        draw_plaque: true,
+        title_path: "gfx/p_option.lmp".to_string(),
+        body: MenuBodyView::Dynamic,
+    }))
+}
+
+/// Builds the menu shown instead of the main menu when there are several installations and none
+/// has been chosen yet.
+///
+/// Choosing an installation remembers it and restarts the client to mount its game data.
+pub fn build_install_chooser(
+    installs: &[Install],
+    console: Rc<RefCell<Console>>,
+) -> Result<Menu, Error> {
+    let mut builder = MenuBuilder::new();
+
+    for (i, install) in installs.iter().enumerate() {
+        let console = console.clone();
+        builder = builder.add_action(
+            install_item_name(install),
+            Box::new(move || {
+                console
+                    .borrow()
+                    .stuff_text(format!("basedir {}\nrestart\n", i + 1))
+            }),
+        );
+    }
+
+    Ok(builder
+        .add_action(
+            "Quit",
+            Box::new(move || console.borrow().stuff_text("quit\n")),
+        )
         .build(MenuView {
             draw_plaque: true,
             title_path: "gfx/p_option.lmp".to_string(),
             body: MenuBodyView::Dynamic,
         }))
 }
+
+/// Returns the menu label for an installation.
+fn install_item_name(install: &Install) -> String {
+    // full paths don't fit in the menu, "basedir" prints them
+    let edition = if install.rerelease {
+        "rerelease"
+    } else {
+        "original"
+    };
+    format!("{} ({})", install.store, edition)
+}
+
+/// Builds the demo browser, which lists the demos visible through the VFS each time it's opened.
+fn build_menu_demos(vfs: Rc<Vfs>, console: Rc<RefCell<Console>>) -> Result<Menu, Error> {
+    let view = MenuView {
+        draw_plaque: true,
+        title_path: "gfx/p_option.lmp".to_string(),
+        body: MenuBodyView::Dynamic,
+    };
+
+    Ok(Menu::lazy(
+        view,
+        Box::new(move |mut builder| {
+            let demos = vfs
+                .file_names()
+                .into_iter()
+                .filter(|name| name.ends_with(".dem"))
+                .filter_map(
+                    |path| match vfs.open(&path).map(|mut f| DemoSummary::read(&mut f)) {
+                        Ok(Ok(summary)) => Some((path, summary)),
+                        Ok(Err(e)) => {
+                            warn!("Skipping unreadable demo {}: {}", path, e);
+                            None
+                        }
+                        Err(e) => {
+                            warn!("Skipping unreadable demo {}: {}", path, e);
+                            None
+                        }
+                    },
+                )
+                .collect::<Vec<_>>();
+
+            if demos.is_empty() {
+                builder = builder.add_action("No demos found", Box::new(|| ()));
+            }
+
+            for (path, summary) in demos {
+                // playdemo expects the name without the extension
+                let name = path.trim_end_matches(".dem").to_owned();
+                let item_name = demo_item_name(&name, &summary);
+                let console = console.clone();
+                builder = builder.add_action(
+                    item_name,
+                    Box::new(move || console.borrow().stuff_text(format!("playdemo {}\n", name))),
+                );
+            }
+
+            builder
+        }),
+    ))
+}
+
+/// Formats a demo browser entry as fixed-width columns of name, map and length.
+fn demo_item_name(name: &str, summary: &DemoSummary) -> String {
+    let secs = summary.duration.num_seconds();
+    format!(
+        "{:<width$} {:<8} {:>2}:{:02}",
+        name.chars().take(DEMO_NAME_WIDTH).collect::<String>(),
+        summary.map_name.as_deref().unwrap_or("?"),
+        secs / 60,
+        secs % 60,
+        width = DEMO_NAME_WIDTH,
+    )
+}
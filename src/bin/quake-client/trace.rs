@@ -1,4 +1,4 @@
-use std::{cell::RefCell, io::BufWriter, rc::Rc, fs::File};
+use std::{cell::RefCell, io::BufWriter, path::PathBuf, rc::Rc, fs::File};
 
 use richter::{client::trace::TraceFrame, common::console::CvarRegistry};
 
@@ -19,9 +19,12 @@ pub fn cmd_trace_begin(trace: Rc<RefCell<Option<Vec<TraceFrame>>>>) -> Box<dyn F
 }
 
 /// Implements the `trace_end` command.
+///
+/// Relative trace paths are resolved against `profile_dir`.
 pub fn cmd_trace_end(
     cvars: Rc<RefCell<CvarRegistry>>,
     trace: Rc<RefCell<Option<Vec<TraceFrame>>>>,
+    profile_dir: PathBuf,
 ) -> Box<dyn Fn(&[&str]) -> String> {
     Box::new(move |_| {
         if let Some(trace_frames) = trace.replace(None) {
@@ -29,6 +32,7 @@ pub fn cmd_trace_end(
                 .borrow()
                 .get("trace_path")
                 .unwrap_or(DEFAULT_TRACE_PATH.to_string());
+            let trace_path = profile_dir.join(trace_path).display().to_string();
             let trace_file = match File::create(&trace_path) {
                 Ok(f) => f,
                 Err(e) => {
@@ -0,0 +1,339 @@
+use std::{cell::Cell, fmt::Write as _, path::Path, rc::Rc, str::FromStr};
+
+use richter::common::console::{CmdRegistry, Console, CvarRegistry};
+use winit::{
+    dpi::PhysicalSize,
+    monitor::{MonitorHandle, VideoMode},
+    window::{Fullscreen, Window},
+};
+
+pub fn register_cvars(cvars: &CvarRegistry) {
+    // report the adapter chosen at startup, see report_adapter
+    cvars.register("vid_adapter", "").unwrap();
+    cvars.register("vid_backend", "").unwrap();
+    cvars.register_archive("vid_borderless", "0").unwrap();
+    cvars.register_archive("vid_fullscreen", "0").unwrap();
+    cvars.register_archive("vid_height", "0").unwrap();
+    cvars.register_archive("vid_monitor", "0").unwrap();
+    cvars.register_archive("vid_refreshrate", "0").unwrap();
+    cvars.register_archive("vid_width", "0").unwrap();
+}
+
+/// Parses the value of `--vid-backend` into the set of backends to choose adapters from.
+pub fn parse_backends(name: &str) -> Result<wgpu::BackendBit, String> {
+    match name.to_lowercase().as_str() {
+        "primary" => Ok(wgpu::BackendBit::PRIMARY),
+        "vulkan" => Ok(wgpu::BackendBit::VULKAN),
+        "metal" => Ok(wgpu::BackendBit::METAL),
+        "dx12" => Ok(wgpu::BackendBit::DX12),
+        "dx11" => Ok(wgpu::BackendBit::DX11),
+        "gl" => Ok(wgpu::BackendBit::GL),
+        _ => Err(format!(
+            "unknown backend \"{}\", expected one of primary, vulkan, metal, dx12, dx11 or gl",
+            name
+        )),
+    }
+}
+
+/// The adapter to render with, set by `--vid-adapter`.
+#[derive(Clone, Debug)]
+pub enum AdapterChoice {
+    /// Let wgpu choose an adapter compatible with the window, preferring the given power profile.
+    Power(wgpu::PowerPreference),
+
+    /// The adapter at the given index in the list printed by `vid_info`.
+    Index(usize),
+
+    /// The first adapter whose name contains the given text, ignoring case.
+    Name(String),
+}
+
+impl FromStr for AdapterChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "integrated" => Ok(AdapterChoice::Power(wgpu::PowerPreference::LowPower)),
+            "discrete" => Ok(AdapterChoice::Power(wgpu::PowerPreference::HighPerformance)),
+            "" => Err(String::from(
+                "expected integrated, discrete, an index or part of an adapter name",
+            )),
+            other => Ok(other
+                .parse()
+                .map(AdapterChoice::Index)
+                .unwrap_or_else(|_| AdapterChoice::Name(s.to_owned()))),
+        }
+    }
+}
+
+/// How the window covers the display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+
+    /// Exclusive fullscreen, which switches the monitor to the selected video mode.
+    Fullscreen,
+
+    /// A borderless window covering the monitor at its current video mode.
+    Borderless,
+}
+
+impl WindowMode {
+    /// Selects a window mode from `vid_fullscreen` and `vid_borderless`.
+    ///
+    /// `vid_borderless` only has an effect if `vid_fullscreen` is set.
+    pub fn from_cvars(cvars: &CvarRegistry) -> WindowMode {
+        let fullscreen = cvars.get_value("vid_fullscreen").unwrap() != 0.0;
+        let borderless = cvars.get_value("vid_borderless").unwrap() != 0.0;
+        match (fullscreen, borderless) {
+            (false, _) => WindowMode::Windowed,
+            (true, false) => WindowMode::Fullscreen,
+            (true, true) => WindowMode::Borderless,
+        }
+    }
+}
+
+/// Moves `window` into the mode selected by the `vid_*` cvars.
+///
+/// `vid_monitor` is an index into the monitors listed by `vid_info`. In exclusive fullscreen,
+/// `vid_width`, `vid_height` and `vid_refreshrate` select the video mode, and any of them left at 0
+/// picks the largest available value. In windowed mode, a nonzero `vid_width` and `vid_height` set
+/// the size of the window.
+pub fn apply_window_mode(window: &Window, cvars: &CvarRegistry) -> Result<WindowMode, String> {
+    let mode = WindowMode::from_cvars(cvars);
+    let width = cvars.get_value("vid_width").unwrap().max(0.0) as u32;
+    let height = cvars.get_value("vid_height").unwrap().max(0.0) as u32;
+    let refresh_rate = cvars.get_value("vid_refreshrate").unwrap().max(0.0) as u16;
+    let monitor_id = cvars.get_value("vid_monitor").unwrap().max(0.0) as usize;
+
+    let monitor = || -> Result<MonitorHandle, String> {
+        window
+            .available_monitors()
+            .nth(monitor_id)
+            .ok_or_else(|| format!("No monitor {}", monitor_id))
+    };
+
+    match mode {
+        WindowMode::Windowed => {
+            window.set_fullscreen(None);
+            if width > 0 && height > 0 {
+                window.set_inner_size(PhysicalSize::new(width, height));
+            }
+        }
+
+        WindowMode::Fullscreen => {
+            let monitor = monitor()?;
+            let video_mode = select_video_mode(monitor.video_modes(), width, height, refresh_rate)
+                .ok_or_else(|| {
+                    format!(
+                        "No video mode on monitor {} matches {}x{} at {}Hz",
+                        monitor_id, width, height, refresh_rate
+                    )
+                })?;
+            window.set_fullscreen(Some(Fullscreen::Exclusive(video_mode)));
+        }
+
+        WindowMode::Borderless => {
+            window.set_fullscreen(Some(Fullscreen::Borderless(monitor()?)));
+        }
+    }
+
+    Ok(mode)
+}
+
+// Returns the largest video mode matching the requested size and refresh rate, where 0 matches
+// anything.
+fn select_video_mode<I>(modes: I, width: u32, height: u32, refresh_rate: u16) -> Option<VideoMode>
+where
+    I: Iterator<Item = VideoMode>,
+{
+    modes
+        .filter(|m| {
+            let size = m.size();
+            (width == 0 || size.width == width)
+                && (height == 0 || size.height == height)
+                && (refresh_rate == 0 || m.refresh_rate() == refresh_rate)
+        })
+        .max_by_key(|m| {
+            let size = m.size();
+            (size.width * size.height, m.refresh_rate(), m.bit_depth())
+        })
+}
+
+/// Implements the "vid_restart" command.
+///
+/// The window belongs to the client program, so this only sets `restart`, and the program applies
+/// the `vid_*` cvars at the start of the next frame.
+pub fn cmd_vid_restart(restart: Rc<Cell<bool>>) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |_| {
+        restart.set(true);
+        String::new()
+    })
+}
+
+/// Requests the adapter described by `choice`.
+pub async fn request_adapter(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+    backends: wgpu::BackendBit,
+    choice: &AdapterChoice,
+) -> Option<wgpu::Adapter> {
+    match *choice {
+        AdapterChoice::Power(power_preference) => {
+            instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference,
+                    compatible_surface: Some(surface),
+                })
+                .await
+        }
+
+        AdapterChoice::Index(i) => instance.enumerate_adapters(backends).nth(i),
+
+        AdapterChoice::Name(ref name) => {
+            let name = name.to_lowercase();
+            instance
+                .enumerate_adapters(backends)
+                .find(|a| a.get_info().name.to_lowercase().contains(&name))
+        }
+    }
+}
+
+/// Requests the adapter described by `choice` and opens a device on it with the features and
+/// limits the renderer needs.
+///
+/// If `trace` is set, API calls are recorded to `./trace/`.
+pub async fn create_device(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+    backends: wgpu::BackendBit,
+    choice: &AdapterChoice,
+    trace: bool,
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), String> {
+    let adapter = request_adapter(instance, surface, backends, choice)
+        .await
+        .ok_or_else(|| format!("No graphics adapter matching {:?}", choice))?;
+
+    let adapter_info = adapter.get_info();
+    log::info!(
+        "Using adapter {} ({:?})",
+        adapter_info.name,
+        adapter_info.backend
+    );
+
+    // block-compressed replacement textures and r_gpuprofile are only available if the adapter
+    // supports them
+    let optional_features = adapter.features()
+        & (wgpu::Features::TEXTURE_COMPRESSION_BC | wgpu::Features::TIMESTAMP_QUERY);
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::PUSH_CONSTANTS
+                    | wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY
+                    | wgpu::Features::SAMPLED_TEXTURE_ARRAY_DYNAMIC_INDEXING
+                    | wgpu::Features::SAMPLED_TEXTURE_ARRAY_NON_UNIFORM_INDEXING
+                    | optional_features,
+                limits: wgpu::Limits {
+                    max_sampled_textures_per_shader_stage: 256,
+                    max_uniform_buffer_binding_size: 65536,
+                    max_push_constant_size: 256,
+                    ..Default::default()
+                },
+            },
+            if trace {
+                Some(Path::new("./trace/"))
+            } else {
+                None
+            },
+        )
+        .await
+        .map_err(|e| format!("Couldn't open device on {}: {}", adapter_info.name, e))?;
+
+    Ok((adapter, device, queue))
+}
+
+/// Registers the "vid_info" command for the given window, adapter and device.
+pub fn register_cmds(
+    cmds: &mut CmdRegistry,
+    window: &Window,
+    instance: &wgpu::Instance,
+    backends: wgpu::BackendBit,
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+) {
+    cmds.insert_or_replace(
+        "vid_info",
+        cmd_vid_info(
+            adapter.get_info(),
+            device.features(),
+            device.limits(),
+            instance
+                .enumerate_adapters(backends)
+                .map(|a| a.get_info())
+                .collect(),
+            window.available_monitors().map(describe_monitor).collect(),
+        ),
+    )
+    .unwrap();
+}
+
+/// Prints the adapter in use to the console and records it in `vid_adapter` and `vid_backend`.
+///
+/// The cvars only report the choice: the adapter is picked with `--vid-backend` and
+/// `--vid-adapter`, and setting the cvars doesn't change it.
+pub fn report_adapter(cvars: &CvarRegistry, console: &Console, info: &wgpu::AdapterInfo) {
+    console.println(format!("Graphics adapter: {}", format_adapter_info(info)));
+    cvars.set("vid_adapter", info.name.as_str()).unwrap();
+    cvars
+        .set("vid_backend", &format!("{:?}", info.backend).to_lowercase())
+        .unwrap();
+}
+
+fn format_adapter_info(info: &wgpu::AdapterInfo) -> String {
+    format!(
+        "{} ({:?}, {:?}, vendor {:#06x}, device {:#06x})",
+        info.name, info.device_type, info.backend, info.vendor, info.device
+    )
+}
+
+fn describe_monitor(monitor: MonitorHandle) -> String {
+    let PhysicalSize { width, height } = monitor.size();
+    format!(
+        "{} ({}x{})",
+        monitor.name().unwrap_or_else(|| "unknown".to_owned()),
+        width,
+        height
+    )
+}
+
+/// Implements the "vid_info" command.
+///
+/// This prints the adapter in use along with the features and limits of the device, followed by
+/// every adapter available on the selected backends and every monitor.
+pub fn cmd_vid_info(
+    adapter: wgpu::AdapterInfo,
+    features: wgpu::Features,
+    limits: wgpu::Limits,
+    available: Vec<wgpu::AdapterInfo>,
+    monitors: Vec<String>,
+) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |_| {
+        let mut out = String::new();
+        writeln!(out, "Adapter: {}", format_adapter_info(&adapter)).unwrap();
+        writeln!(out, "Features: {:?}", features).unwrap();
+        writeln!(out, "Limits: {:#?}", limits).unwrap();
+
+        writeln!(out, "Available adapters:").unwrap();
+        for (i, info) in available.iter().enumerate() {
+            writeln!(out, "  {}: {}", i, format_adapter_info(info)).unwrap();
+        }
+
+        writeln!(out, "Monitors:").unwrap();
+        for (i, monitor) in monitors.iter().enumerate() {
+            writeln!(out, "  {}: {}", i, monitor).unwrap();
+        }
+
+        out
+    })
+}
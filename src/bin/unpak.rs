@@ -27,6 +27,7 @@ use std::{
 
 use richter::common::pak::Pak;
 
+use serde::Serialize;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -37,6 +38,10 @@ struct Opt {
     #[structopt(long)]
     version: bool,
 
+    /// Print a JSON report of the extracted files instead of plain text.
+    #[structopt(long)]
+    json: bool,
+
     #[structopt(name = "INPUT_PAK", parse(from_os_str))]
     input_pak: PathBuf,
 
@@ -50,23 +55,33 @@ Copyright © 2020 Cormac O'Brien
 Released under the terms of the MIT License
 ";
 
-fn main() {
-    let opt = Opt::from_args();
+/// A file written by `unpak`, as reported by `--json`.
+#[derive(Serialize)]
+struct ExtractedFile {
+    /// The name of the file in the archive.
+    name: String,
 
-    if opt.version {
-        println!("{}", VERSION);
-        exit(0);
-    }
+    /// The path the file was written to.
+    path: String,
 
-    let pak = match Pak::new(&opt.input_pak) {
-        Ok(p) => p,
-        Err(why) => {
-            println!("Couldn't open {:#?}: {}", &opt.input_pak, why);
-            exit(1);
-        }
-    };
+    /// The size of the file in bytes.
+    size: usize,
+}
+
+/// The output of `--json` on success. On failure, the output is `{"error": "<message>"}`.
+#[derive(Serialize)]
+struct Report {
+    pak: String,
+    files: Vec<ExtractedFile>,
+}
+
+fn extract(opt: &Opt) -> Result<Report, String> {
+    let pak = Pak::new(&opt.input_pak)
+        .map_err(|why| format!("Couldn't open {:#?}: {}", &opt.input_pak, why))?;
 
+    let mut files = Vec::new();
     for (k, v) in pak.iter() {
+        let data = v.as_ref();
         let mut path = PathBuf::new();
 
         if let Some(ref d) = opt.output_dir {
@@ -77,28 +92,54 @@ fn main() {
 
         if let Some(p) = path.parent() {
             if !p.exists() {
-                if let Err(why) = fs::create_dir_all(p) {
-                    println!("Couldn't create parent directories: {}", why);
-                    exit(1);
-                }
+                fs::create_dir_all(p)
+                    .map_err(|why| format!("Couldn't create parent directories: {}", why))?;
             }
         }
 
-        let file = match File::create(&path) {
-            Ok(f) => f,
-            Err(why) => {
-                println!("Couldn't open {}: {}", path.to_str().unwrap(), why);
-                exit(1);
-            }
-        };
+        let file = File::create(&path)
+            .map_err(|why| format!("Couldn't open {}: {}", path.to_str().unwrap(), why))?;
 
         let mut writer = BufWriter::new(file);
-        match writer.write_all(v.as_ref()) {
-            Ok(_) => (),
-            Err(why) => {
-                println!("Couldn't write to {}: {}", path.to_str().unwrap(), why);
-                exit(1);
+        writer
+            .write_all(data)
+            .map_err(|why| format!("Couldn't write to {}: {}", path.to_str().unwrap(), why))?;
+
+        files.push(ExtractedFile {
+            name: k.to_owned(),
+            path: path.to_string_lossy().into_owned(),
+            size: data.len(),
+        });
+    }
+
+    Ok(Report {
+        pak: opt.input_pak.to_string_lossy().into_owned(),
+        files,
+    })
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    if opt.version {
+        println!("{}", VERSION);
+        exit(0);
+    }
+
+    match extract(&opt) {
+        Ok(report) => {
+            if opt.json {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            }
+        }
+
+        Err(why) => {
+            if opt.json {
+                println!("{}", serde_json::json!({ "error": why }));
+            } else {
+                println!("{}", why);
             }
+            exit(1);
         }
     }
 }
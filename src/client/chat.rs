@@ -0,0 +1,296 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Chat message handling.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{client::state::ClientState, common::console::CvarRegistry};
+
+/// Prefix the server attaches to chat messages so the client can play the chat sound.
+///
+/// See `Host_Say_f` in the original engine.
+pub const CHAT_MARKER: char = '\u{1}';
+
+/// Separator between names in the `cl_ignorelist` cvar.
+const IGNORE_LIST_SEPARATOR: char = ',';
+
+/// A chat message identified in a server print.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChatMessage<'a> {
+    /// The name of the player who sent the message, if it could be attributed to one.
+    pub sender: Option<&'a str>,
+    /// Whether the message was sent with `say_team`.
+    pub team: bool,
+    pub text: &'a str,
+}
+
+/// Identifies a server print as a chat message and attributes it to a player.
+///
+/// Chat prints take the form `\x01name: text` (or `\x01(name): text` for team chat). Since names
+/// may themselves contain `": "`, the longest matching player name wins.
+pub fn parse_chat<'a, I, S>(print: &'a str, player_names: I) -> Option<ChatMessage<'a>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let body = print.strip_prefix(CHAT_MARKER)?;
+
+    let (team, body) = match body.strip_prefix('(') {
+        Some(b) => (true, b),
+        None => (false, body),
+    };
+    let suffix = if team { "): " } else { ": " };
+
+    let sender = player_names
+        .into_iter()
+        .filter(|name| {
+            let name = name.as_ref();
+            !name.is_empty() && body.starts_with(name) && body[name.len()..].starts_with(suffix)
+        })
+        .map(|name| name.as_ref().len())
+        .max()
+        .map(|len| &body[..len]);
+
+    let text = match sender {
+        Some(s) => &body[s.len() + suffix.len()..],
+
+        // unattributed (e.g. from the server console), just split at the first separator
+        None => match body.find(suffix) {
+            Some(i) => &body[i + suffix.len()..],
+            None => body,
+        },
+    };
+
+    Some(ChatMessage { sender, team, text })
+}
+
+/// Expands team chat macros.
+///
+/// - `%l`: the name of the nearest location from the map's `.loc` file
+/// - `%h`: current health
+/// - `%a`: current armor
+/// - `%%`: a literal `%`
+///
+/// Unrecognized macros are left as-is.
+pub fn expand_macros(text: &str, state: &ClientState) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('l') => expanded.push_str(state.location().unwrap_or("somewhere")),
+            Some('h') => expanded.push_str(&state.stats().health.to_string()),
+            Some('a') => expanded.push_str(&state.stats().armor.to_string()),
+            Some('%') => expanded.push('%'),
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+
+    expanded
+}
+
+/// Builds the string command that sends `text` as a chat message.
+///
+/// The server's tokenizer has no escapes, so a quote in the text would end the message early and
+/// let the rest run as another command. Quotes are dropped instead.
+pub fn say_command(cmd_name: &str, text: &str) -> String {
+    format!("{} \"{}\"", cmd_name, text.replace('"', ""))
+}
+
+/// Cvars controlling which chat messages are displayed.
+#[derive(Clone, Debug)]
+pub struct ChatVars {
+    pub cl_mute_all_chat: f32,
+    pub cl_ignorelist: String,
+}
+
+impl ChatVars {
+    /// Returns whether the given chat message should be hidden.
+    pub fn is_filtered(&self, msg: &ChatMessage) -> bool {
+        if self.cl_mute_all_chat != 0.0 {
+            return true;
+        }
+
+        match msg.sender {
+            Some(sender) => ignore_list(&self.cl_ignorelist).any(|name| name == sender),
+            None => false,
+        }
+    }
+}
+
+fn ignore_list<'a>(list: &'a str) -> impl Iterator<Item = &'a str> {
+    list.split(IGNORE_LIST_SEPARATOR).filter(|n| !n.is_empty())
+}
+
+/// Implements the `ignore` command.
+///
+/// The ignore list is stored in the archived `cl_ignorelist` cvar so that it persists with the
+/// rest of the configuration.
+pub fn cmd_ignore(cvars: Rc<RefCell<CvarRegistry>>) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| {
+        let list = match cvars.borrow().get("cl_ignorelist") {
+            Ok(l) => l,
+            Err(e) => return format!("{}", e),
+        };
+
+        match args.len() {
+            0 => {
+                let mut out = String::from("Ignored players:\n");
+                for name in ignore_list(&list) {
+                    out.push_str(name);
+                    out.push('\n');
+                }
+                out
+            }
+
+            1 => {
+                let name = args[0];
+                if name.contains(IGNORE_LIST_SEPARATOR) {
+                    return format!("Can't ignore names containing '{}'", IGNORE_LIST_SEPARATOR);
+                }
+
+                if ignore_list(&list).any(|n| n == name) {
+                    return format!("{} is already ignored", name);
+                }
+
+                let mut names = ignore_list(&list).collect::<Vec<_>>();
+                names.push(name);
+                let new_list = names.join(&IGNORE_LIST_SEPARATOR.to_string());
+                match cvars.borrow().set("cl_ignorelist", new_list.as_str()) {
+                    Ok(()) => format!("Ignoring {}", name),
+                    Err(e) => format!("{}", e),
+                }
+            }
+
+            _ => "usage: ignore [name]".to_owned(),
+        }
+    })
+}
+
+/// Implements the `unignore` command.
+pub fn cmd_unignore(cvars: Rc<RefCell<CvarRegistry>>) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| {
+        if args.len() != 1 {
+            return "usage: unignore <name>".to_owned();
+        }
+
+        let list = match cvars.borrow().get("cl_ignorelist") {
+            Ok(l) => l,
+            Err(e) => return format!("{}", e),
+        };
+
+        let name = args[0];
+        if !ignore_list(&list).any(|n| n == name) {
+            return format!("{} is not ignored", name);
+        }
+
+        let new_list = ignore_list(&list)
+            .filter(|n| *n != name)
+            .collect::<Vec<_>>()
+            .join(&IGNORE_LIST_SEPARATOR.to_string());
+        match cvars.borrow().set("cl_ignorelist", new_list.as_str()) {
+            Ok(()) => format!("No longer ignoring {}", name),
+            Err(e) => format!("{}", e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chat_not_chat() {
+        assert_eq!(parse_chat("Player entered the game\n", &["Player"]), None);
+    }
+
+    #[test]
+    fn test_parse_chat_attributed() {
+        let msg = parse_chat("\u{1}Player: hello\n", &["Player", "Other"]).unwrap();
+        assert_eq!(
+            msg,
+            ChatMessage {
+                sender: Some("Player"),
+                team: false,
+                text: "hello\n",
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_chat_team() {
+        let msg = parse_chat("\u{1}(Player): rocket at quad\n", &["Player"]).unwrap();
+        assert_eq!(msg.sender, Some("Player"));
+        assert!(msg.team);
+        assert_eq!(msg.text, "rocket at quad\n");
+    }
+
+    #[test]
+    fn test_parse_chat_longest_name() {
+        let msg = parse_chat("\u{1}a: b: c\n", &["a", "a: b"]).unwrap();
+        assert_eq!(msg.sender, Some("a: b"));
+        assert_eq!(msg.text, "c\n");
+    }
+
+    #[test]
+    fn test_parse_chat_unattributed() {
+        let msg = parse_chat("\u{1}<console>: hi\n", &["Player"]).unwrap();
+        assert_eq!(msg.sender, None);
+        assert_eq!(msg.text, "hi\n");
+    }
+
+    #[test]
+    fn test_say_command_strips_quotes() {
+        assert_eq!(say_command("say", "hello"), "say \"hello\"");
+        assert_eq!(
+            say_command("say_team", "\"; quit; say \"hi"),
+            "say_team \"; quit; say hi\""
+        );
+    }
+
+    #[test]
+    fn test_chat_vars_filter() {
+        let vars = ChatVars {
+            cl_mute_all_chat: 0.0,
+            cl_ignorelist: "Spammer,Troll".to_owned(),
+        };
+
+        let spam = parse_chat("\u{1}Troll: hi\n", &["Troll", "Friend"]).unwrap();
+        let friendly = parse_chat("\u{1}Friend: hi\n", &["Troll", "Friend"]).unwrap();
+        assert!(vars.is_filtered(&spam));
+        assert!(!vars.is_filtered(&friendly));
+
+        let mute_all = ChatVars {
+            cl_mute_all_chat: 1.0,
+            ..vars
+        };
+        assert!(mute_all.is_filtered(&friendly));
+    }
+}
@@ -21,29 +21,44 @@
 use crate::common::console::{CvarRegistry, ConsoleError};
 
 pub fn register_cvars(cvars: &CvarRegistry) -> Result<(), ConsoleError> {
+    cvars.register_archive("bgmvolume", "1")?;
+    cvars.register_archive("bindings_profile", "modern")?;
     cvars.register("cl_anglespeedkey", "1.5")?;
     cvars.register_archive("cl_backspeed", "200")?;
     cvars.register("cl_bob", "0.02")?;
     cvars.register("cl_bobcycle", "0.6")?;
     cvars.register("cl_bobup", "0.5")?;
+    cvars.register_archive("cl_cachesize", "256")?;
     cvars.register_archive("_cl_color", "0")?;
     cvars.register("cl_crossx", "0")?;
     cvars.register("cl_crossy", "0")?;
     cvars.register_archive("cl_forwardspeed", "400")?;
+    cvars.register_archive("cl_gunbob", "1")?;
+    cvars.register_archive("cl_gunsway", "0")?;
+    cvars.register_archive("cl_httpsonly", "0")?;
+    cvars.register_archive("cl_ignorelist", "")?;
+    cvars.register("cl_interp", "0")?;
     cvars.register("cl_movespeedkey", "2.0")?;
+    cvars.register_archive("cl_mute_all_chat", "0")?;
     cvars.register_archive("_cl_name", "player")?;
     cvars.register("cl_nolerp", "0")?;
     cvars.register("cl_pitchspeed", "150")?;
+    cvars.register("cl_protocol_extensions", "1")?;
     cvars.register("cl_rollangle", "2.0")?;
     cvars.register("cl_rollspeed", "200")?;
+    cvars.register_archive("cl_run", "0")?;
     cvars.register("cl_shownet", "0")?;
     cvars.register("cl_sidespeed", "350")?;
+    cvars.register("cl_timegraph", "0")?;
     cvars.register("cl_upspeed", "200")?;
     cvars.register("cl_yawspeed", "140")?;
+    cvars.register("con_notifytime", "3")?;
     cvars.register("fov", "90")?;
+    cvars.register_archive("freelook", "1")?;
     cvars.register_archive("m_pitch", "0.022")?;
     cvars.register_archive("m_yaw", "0.022")?;
     cvars.register_archive("sensitivity", "3")?;
+    cvars.register_archive("snd_cachesize", "32768")?;
     cvars.register("v_idlescale", "0")?;
     cvars.register("v_ipitch_cycle", "1")?;
     cvars.register("v_ipitch_level", "0.3")?;
@@ -54,11 +69,24 @@ pub fn register_cvars(cvars: &CvarRegistry) -> Result<(), ConsoleError> {
     cvars.register("v_kickpitch", "0.6")?;
     cvars.register("v_kickroll", "0.6")?;
     cvars.register("v_kicktime", "0.5")?;
+    cvars.register_archive("volume", "0.7")?;
 
     // some server cvars are needed by the client, but if the server is running
     // in the same process they will have been set already, so we can ignore
     // the duplicate cvar error
     let _ = cvars.register("sv_gravity", "800");
 
+    // servers stuff the base URL of their HTTP download mirror into this
+    let _ = cvars.register("sv_downloadurl", "");
+
+    // use platform-independent math in QuakeC builtins so demos and co-op games simulate
+    // identically everywhere
+    let _ = cvars.register("sv_deterministic", "0");
+
+    // set to 1 at startup if the registered game data is found. mods test this to gate content.
+    let _ = cvars.register("registered", "0");
+    // the game directory of the mission pack started with --hipnotic or --rogue, if any
+    let _ = cvars.register("missionpack", "");
+
     Ok(())
 }
@@ -0,0 +1,217 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Interpolation delay buffering.
+//!
+//! Entities are interpolated between the last two server updates, so an update that arrives late
+//! leaves the client with nothing to interpolate toward and entities stall. Holding incoming
+//! messages for a short, fixed time (`cl_interp`) absorbs that jitter at the cost of rendering
+//! the world slightly in the past.
+//!
+//! Messages are scheduled by the server time they carry rather than by when they arrive, so a
+//! burst of late messages is still released at the rate the server sent them.
+
+use std::collections::VecDeque;
+
+use crate::common::{engine, net::ServerCmdCode};
+
+use chrono::Duration;
+
+// the number of recent messages used to estimate the fastest transit time
+const TRANSIT_SAMPLES: usize = 64;
+
+struct DelayedMessage {
+    arrival: Duration,
+    release: Duration,
+    data: Vec<u8>,
+}
+
+/// Holds server messages until their scheduled release time.
+pub struct DelayBuffer {
+    local_time: Duration,
+
+    // local arrival time minus server time for recent timestamped messages
+    transit: VecDeque<Duration>,
+
+    queue: VecDeque<DelayedMessage>,
+
+    // how long the most recently released message was held
+    last_hold: Duration,
+}
+
+impl DelayBuffer {
+    pub fn new() -> DelayBuffer {
+        DelayBuffer {
+            local_time: Duration::zero(),
+            transit: VecDeque::with_capacity(TRANSIT_SAMPLES),
+            queue: VecDeque::new(),
+            last_hold: Duration::zero(),
+        }
+    }
+
+    /// Advances the local clock by the given frame time.
+    pub fn advance(&mut self, frame_time: Duration) {
+        self.local_time = self.local_time + frame_time;
+    }
+
+    /// Queues a message to be released after at most `delay`.
+    ///
+    /// Messages that begin with a server timestamp are released `delay` after the time they
+    /// would have arrived with the fastest transit time seen recently. Other messages are
+    /// released `delay` after they arrive. Messages are never reordered.
+    pub fn push(&mut self, data: Vec<u8>, delay: Duration) {
+        let arrival = self.local_time;
+        let latest = arrival + delay;
+
+        let release = match server_time(&data) {
+            Some(time) => {
+                if self.transit.len() >= TRANSIT_SAMPLES {
+                    self.transit.pop_front();
+                }
+                self.transit.push_back(arrival - time);
+
+                let fastest = *self.transit.iter().min().unwrap();
+
+                // if the server clock jumps forward (e.g. on a map change) the estimate is stale,
+                // so never hold a message longer than the delay
+                (time + fastest + delay).min(latest)
+            }
+
+            None => latest,
+        };
+
+        // don't release a message before the one in front of it
+        let release = match self.queue.back() {
+            Some(prev) => release.max(prev.release),
+            None => release,
+        };
+
+        self.queue.push_back(DelayedMessage {
+            arrival,
+            release,
+            data,
+        });
+    }
+
+    /// Returns the next message if it is due for release.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        if self.queue.front()?.release > self.local_time {
+            return None;
+        }
+
+        self.force_pop()
+    }
+
+    /// Returns the next message regardless of its release time.
+    pub fn force_pop(&mut self) -> Option<Vec<u8>> {
+        let msg = self.queue.pop_front()?;
+        self.last_hold = self.local_time - msg.arrival;
+        Some(msg.data)
+    }
+
+    /// Returns how long the most recently released message was held.
+    pub fn last_hold(&self) -> Duration {
+        self.last_hold
+    }
+
+    /// Returns the number of messages waiting to be released.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+/// Converts a `cl_interp` value in seconds to a delay.
+pub fn interp_delay(cl_interp: f32) -> Duration {
+    // more than a second of delay would make the game unplayable
+    engine::duration_from_f32(cl_interp.max(0.0).min(1.0))
+}
+
+// unreliable server messages start with svc_time
+fn server_time(msg: &[u8]) -> Option<Duration> {
+    match *msg {
+        [code, a, b, c, d, ..] if code == ServerCmdCode::Time as u8 => {
+            let time = f32::from_le_bytes([a, b, c, d]);
+            Some(engine::duration_from_f32(time))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: i64) -> Duration {
+        Duration::milliseconds(n)
+    }
+
+    fn timestamped(time: f32) -> Vec<u8> {
+        let mut msg = vec![ServerCmdCode::Time as u8];
+        msg.extend_from_slice(&time.to_le_bytes());
+        msg
+    }
+
+    #[test]
+    fn test_no_delay() {
+        let mut buf = DelayBuffer::new();
+        buf.push(vec![1], Duration::zero());
+        assert_eq!(buf.pop(), Some(vec![1]));
+        assert_eq!(buf.pop(), None);
+    }
+
+    #[test]
+    fn test_untimestamped_delay() {
+        let mut buf = DelayBuffer::new();
+        buf.push(vec![1], ms(100));
+
+        buf.advance(ms(50));
+        assert_eq!(buf.pop(), None);
+
+        buf.advance(ms(50));
+        assert_eq!(buf.pop(), Some(vec![1]));
+        assert_eq!(buf.last_hold(), ms(100));
+    }
+
+    #[test]
+    fn test_jitter_smoothing() {
+        let mut buf = DelayBuffer::new();
+
+        // server time 1.0 arrives promptly
+        buf.advance(ms(1000));
+        buf.push(timestamped(1.0), ms(100));
+
+        // 1.125 arrives 75ms late, along with 1.25
+        buf.advance(ms(200));
+        buf.push(timestamped(1.125), ms(100));
+        buf.push(timestamped(1.25), ms(100));
+
+        // only 1.0 is due, the others are released in their own slots
+        assert_eq!(buf.pop(), Some(timestamped(1.0)));
+        assert_eq!(buf.pop(), None);
+        assert_eq!(buf.queued(), 2);
+
+        buf.advance(ms(25));
+        assert_eq!(buf.pop(), Some(timestamped(1.125)));
+        assert_eq!(buf.pop(), None);
+
+        buf.advance(ms(125));
+        assert_eq!(buf.pop(), Some(timestamped(1.25)));
+    }
+}
@@ -1,14 +1,22 @@
-use std::{io, ops::Range};
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+};
 
-use crate::common::{
-    net::{self, NetError},
-    util::read_f32_3,
-    vfs::VirtualFile,
+use crate::{
+    common::{
+        engine,
+        net::{self, NetError, ServerCmd},
+        util::read_f32_3,
+        vfs::VirtualFile,
+    },
+    server::mvd::{self, MvdError, MvdReader, MvdRecord, PovMessage},
 };
 
 use arrayvec::ArrayVec;
 use byteorder::{LittleEndian, ReadBytesExt};
 use cgmath::{Deg, Vector3};
+use chrono::Duration;
 use io::BufReader;
 use thiserror::Error;
 
@@ -25,11 +33,46 @@ pub enum DemoServerError {
     Io(#[from] io::Error),
     #[error("Network error: {0}")]
     Net(#[from] NetError),
+    #[error("Multi-view demo error: {0}")]
+    Mvd(#[from] MvdError),
 }
 
 struct DemoMessage {
     view_angles: Vector3<Deg<f32>>,
     msg_range: Range<usize>,
+    // server time the message was sent at. only recorded in multi-view demos
+    time: f32,
+}
+
+/// The messages received by one client.
+struct DemoTrack {
+    // the client whose point of view this is, for multi-view demos
+    client: Option<u8>,
+    messages: Vec<DemoMessage>,
+    message_data: Vec<u8>,
+}
+
+impl DemoTrack {
+    fn from_pov(client: u8, pov: Vec<PovMessage>) -> DemoTrack {
+        let mut messages = Vec::with_capacity(pov.len());
+        let mut message_data = Vec::new();
+
+        for msg in pov {
+            let msg_start = message_data.len();
+            message_data.extend(msg.data);
+            messages.push(DemoMessage {
+                view_angles: msg.view_angles,
+                msg_range: msg_start..message_data.len(),
+                time: msg.time,
+            });
+        }
+
+        DemoTrack {
+            client: Some(client),
+            messages,
+            message_data,
+        }
+    }
 }
 
 /// A view of a server message from a demo.
@@ -50,6 +93,150 @@ impl<'a> DemoMessageView<'a> {
     }
 }
 
+/// Information about a demo gathered from its messages without playing it back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DemoSummary {
+    /// The name of the map the demo was recorded on, e.g. `e1m3`.
+    pub map_name: Option<String>,
+    /// The amount of game time covered by the demo.
+    pub duration: Duration,
+}
+
+impl DemoSummary {
+    /// Reads the summary of a demo without loading all of its messages.
+    ///
+    /// Apart from the CD track line and the size of each message, only the messages up to the
+    /// server info and the first server time, and from the last server time on, are read.
+    /// Messages that fail to parse are skipped rather than treated as errors, since the summary
+    /// is only informational.
+    pub fn read<R>(reader: &mut R) -> Result<DemoSummary, DemoServerError>
+    where
+        R: Read + Seek,
+    {
+        read_track_override(reader)?;
+
+        // find each message by skipping over the ones before it
+        let mut msg_ranges = Vec::new();
+        while let Ok(msg_len) = reader.read_u32::<LittleEndian>() {
+            // skip the view angles
+            let msg_start = reader.seek(SeekFrom::Current(12))?;
+            let msg_end = reader.seek(SeekFrom::Current(msg_len as i64))?;
+            msg_ranges.push(msg_start..msg_end);
+        }
+
+        let mut map_name = None;
+        let mut first_time = None;
+        for range in msg_ranges.iter() {
+            for cmd in read_cmds(reader, range) {
+                match cmd {
+                    ServerCmd::ServerInfo { model_precache, .. } if map_name.is_none() => {
+                        // the first model is always the world model, e.g. "maps/e1m3.bsp"
+                        map_name = model_precache.first().map(|m| {
+                            m.trim_start_matches("maps/")
+                                .trim_end_matches(".bsp")
+                                .to_owned()
+                        });
+                    }
+
+                    ServerCmd::Time { time } if first_time.is_none() => first_time = Some(time),
+
+                    _ => (),
+                }
+            }
+
+            if map_name.is_some() && first_time.is_some() {
+                break;
+            }
+        }
+
+        let mut last_time = None;
+        for range in msg_ranges.iter().rev() {
+            last_time = read_cmds(reader, range)
+                .into_iter()
+                .filter_map(|cmd| match cmd {
+                    ServerCmd::Time { time } => Some(time),
+                    _ => None,
+                })
+                .last();
+
+            if last_time.is_some() {
+                break;
+            }
+        }
+
+        let duration = match (first_time, last_time) {
+            (Some(first), Some(last)) => engine::duration_from_f32(last - first),
+            _ => Duration::zero(),
+        };
+
+        Ok(DemoSummary { map_name, duration })
+    }
+}
+
+/// Reads the commands in the demo message at `range`, stopping at the first that fails to parse.
+fn read_cmds<R>(reader: &mut R, range: &Range<u64>) -> Vec<ServerCmd>
+where
+    R: Read + Seek,
+{
+    // a truncated demo may end partway through its last message
+    let mut msg = Vec::new();
+    let len = range.end - range.start;
+    if reader.seek(SeekFrom::Start(range.start)).is_err()
+        || reader.by_ref().take(len).read_to_end(&mut msg).is_err()
+    {
+        return Vec::new();
+    }
+
+    let mut cmds = Vec::new();
+    let mut msg_reader = msg.as_slice();
+    while let Ok(Some(cmd)) = ServerCmd::deserialize(&mut msg_reader) {
+        cmds.push(cmd);
+    }
+
+    cmds
+}
+
+/// Reads the CD track line at the start of a demo, returning the track that overrides the
+/// demo's `CdTrack` commands, if any.
+fn read_track_override<R>(reader: &mut R) -> Result<Option<u32>, DemoServerError>
+where
+    R: Read,
+{
+    let mut buf = ArrayVec::<u8, 3>::new();
+    // copy CD track number (terminated by newline) into buffer
+    for i in 0..buf.capacity() {
+        match reader.read_u8()? {
+            b'\n' => break,
+            // cannot panic because we won't exceed capacity with a loop this small
+            b => buf.push(b),
+        }
+
+        if i >= buf.capacity() - 1 {
+            // CD track would be more than 2 digits long, which is impossible
+            Err(DemoServerError::InvalidCdTrack)?;
+        }
+    }
+
+    let track_str = match std::str::from_utf8(&buf) {
+        Ok(s) => s,
+        Err(_) => Err(DemoServerError::InvalidCdTrack)?,
+    };
+
+    Ok(match track_str {
+        // if track is empty, default to track 0
+        "" => Some(0),
+        s => match s.parse::<i32>() {
+            Ok(track) => match track {
+                // if track is -1, allow demo to specify tracks in messages
+                -1 => None,
+                t if t < -1 => Err(DemoServerError::InvalidCdTrack)?,
+                _ => Some(track as u32),
+            },
+            Err(_) => Err(DemoServerError::InvalidCdTrack)?,
+        },
+    })
+}
+
 /// A server that yields commands from a demo file.
 pub struct DemoServer {
     track_override: Option<u32>,
@@ -57,52 +244,18 @@ pub struct DemoServer {
     // id of next message to "send"
     message_id: usize,
 
-    messages: Vec<DemoMessage>,
+    // one track for a regular demo, one per recorded client for a multi-view demo
+    tracks: Vec<DemoTrack>,
 
-    // all message data
-    message_data: Vec<u8>,
+    // index of the track currently being played
+    track_id: usize,
 }
 
 impl DemoServer {
     /// Construct a new `DemoServer` from the specified demo file.
     pub fn new(file: &mut VirtualFile) -> Result<DemoServer, DemoServerError> {
         let mut dem_reader = BufReader::new(file);
-
-        let mut buf = ArrayVec::<u8, 3>::new();
-        // copy CD track number (terminated by newline) into buffer
-        for i in 0..buf.capacity() {
-            match dem_reader.read_u8()? {
-                b'\n' => break,
-                // cannot panic because we won't exceed capacity with a loop this small
-                b => buf.push(b),
-            }
-
-            if i >= buf.capacity() - 1 {
-                // CD track would be more than 2 digits long, which is impossible
-                Err(DemoServerError::InvalidCdTrack)?;
-            }
-        }
-
-        let track_override = {
-            let track_str = match std::str::from_utf8(&buf) {
-                Ok(s) => s,
-                Err(_) => Err(DemoServerError::InvalidCdTrack)?,
-            };
-
-            match track_str {
-                // if track is empty, default to track 0
-                "" => Some(0),
-                s => match s.parse::<i32>() {
-                    Ok(track) => match track {
-                        // if track is -1, allow demo to specify tracks in messages
-                        -1 => None,
-                        t if t < -1 => Err(DemoServerError::InvalidCdTrack)?,
-                        _ => Some(track as u32),
-                    },
-                    Err(_) => Err(DemoServerError::InvalidCdTrack)?,
-                },
-            }
-        };
+        let track_override = read_track_override(&mut dem_reader)?;
 
         let mut message_data = Vec::new();
         let mut messages = Vec::new();
@@ -127,14 +280,61 @@ impl DemoServer {
             messages.push(DemoMessage {
                 view_angles,
                 msg_range: msg_start..msg_end,
+                time: 0.0,
             });
         }
 
         Ok(DemoServer {
             track_override,
             message_id: 0,
-            messages,
-            message_data,
+            tracks: vec![DemoTrack {
+                client: None,
+                messages,
+                message_data,
+            }],
+            track_id: 0,
+        })
+    }
+
+    /// Construct a new `DemoServer` from the specified multi-view demo file.
+    ///
+    /// Playback starts from the point of view of the first recorded client. Clients that never
+    /// recorded their view angles are assumed not to have been connected.
+    pub fn from_mvd<R>(reader: R) -> Result<DemoServer, DemoServerError>
+    where
+        R: Read,
+    {
+        let mut reader = MvdReader::new(BufReader::new(reader))?;
+
+        let mut records = Vec::new();
+        while let Some(record) = reader.read_record()? {
+            records.push(record);
+        }
+
+        let mut clients: Vec<u8> = records
+            .iter()
+            .filter_map(|r| match r {
+                MvdRecord::ViewAngles { client, .. } => Some(*client),
+                _ => None,
+            })
+            .collect();
+        clients.sort_unstable();
+        clients.dedup();
+        if clients.is_empty() {
+            clients.push(0);
+        }
+
+        let tracks = clients
+            .into_iter()
+            .map(|c| DemoTrack::from_pov(c, mvd::client_pov(records.iter().cloned(), c)))
+            .collect();
+
+        Ok(DemoServer {
+            // multi-view demos don't record a CD track line
+            track_override: None,
+            message_id: 0,
+            tracks,
+            track_id: 0,
         })
     }
 
@@ -142,19 +342,56 @@ impl DemoServer {
     ///
     /// If this returns `None`, the demo is complete.
     pub fn next(&mut self) -> Option<DemoMessageView> {
-        if self.message_id >= self.messages.len() {
+        let track = &self.tracks[self.track_id];
+        if self.message_id >= track.messages.len() {
             return None;
         }
 
-        let msg = &self.messages[self.message_id];
+        let msg = &track.messages[self.message_id];
         self.message_id += 1;
 
         Some(DemoMessageView {
             view_angles: msg.view_angles,
-            message: &self.message_data[msg.msg_range.clone()],
+            message: &track.message_data[msg.msg_range.clone()],
         })
     }
 
+    /// Returns the client whose point of view is being played back, if this is a multi-view demo.
+    pub fn pov(&self) -> Option<u8> {
+        self.tracks[self.track_id].client
+    }
+
+    /// Switches playback to the point of view of another client in a multi-view demo.
+    ///
+    /// If `client` is `None`, switches to the next recorded client. Playback of the new point of
+    /// view resumes after the last message sent from the old one. Returns the client now being
+    /// played back, or `None` if the demo has no such client.
+    pub fn switch_pov(&mut self, client: Option<u8>) -> Option<u8> {
+        let track_id = match client {
+            Some(c) => self.tracks.iter().position(|t| t.client == Some(c))?,
+            None => (self.track_id + 1) % self.tracks.len(),
+        };
+
+        let new_track = &self.tracks[track_id];
+        new_track.client?;
+
+        let last_time = self
+            .message_id
+            .checked_sub(1)
+            .map(|id| self.tracks[self.track_id].messages[id].time);
+        self.message_id = match last_time {
+            Some(t) => new_track
+                .messages
+                .iter()
+                .position(|m| m.time > t)
+                .unwrap_or_else(|| new_track.messages.len()),
+            None => 0,
+        };
+        self.track_id = track_id;
+
+        new_track.client
+    }
+
     /// Returns the currently playing demo's music track override, if any.
     ///
     /// If this is `Some`, any `CdTrack` commands from the demo server should
@@ -164,3 +401,91 @@ impl DemoServer {
         self.track_override
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use crate::{
+        common::net::{GameType, ProtocolFlags},
+        server::mvd::{MessageTarget, MvdWriter},
+    };
+
+    fn demo_message(cmds: &[ServerCmd]) -> Vec<u8> {
+        let mut msg = Vec::new();
+        for cmd in cmds {
+            cmd.serialize(&mut msg).unwrap();
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&(msg.len() as u32).to_le_bytes());
+        data.extend_from_slice(&[0; 12]);
+        data.extend(msg);
+        data
+    }
+
+    #[test]
+    fn test_demo_summary() {
+        let mut data = b"-1\n".to_vec();
+        data.extend(demo_message(&[ServerCmd::ServerInfo {
+            protocol_version: net::PROTOCOL_VERSION as i32,
+            protocol_flags: ProtocolFlags::empty(),
+            max_clients: 1,
+            game_type: GameType::CoOp,
+            message: "the Necropolis".to_owned(),
+            model_precache: vec!["maps/e1m3.bsp".to_owned()],
+            sound_precache: vec![],
+        }]));
+        data.extend(demo_message(&[ServerCmd::Time { time: 1.5 }]));
+        data.extend(demo_message(&[ServerCmd::Time { time: 61.5 }]));
+
+        let summary = DemoSummary::read(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(summary.map_name.as_deref(), Some("e1m3"));
+        assert_eq!(summary.duration, Duration::seconds(60));
+    }
+
+    #[test]
+    fn test_switch_pov() {
+        let angles = Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0));
+        let mut writer = MvdWriter::new(Vec::new(), 2).unwrap();
+        for (time, target, data) in [
+            (0.5, MessageTarget::Client(0), 1),
+            (0.5, MessageTarget::Client(1), 2),
+            (0.6, MessageTarget::Client(0), 3),
+            (0.6, MessageTarget::Client(1), 4),
+        ]
+        .iter()
+        {
+            writer
+                .write_record(&MvdRecord::Message {
+                    time: *time,
+                    target: *target,
+                    data: vec![*data],
+                })
+                .unwrap();
+        }
+        for client in 0..2 {
+            writer
+                .write_record(&MvdRecord::ViewAngles {
+                    time: 0.5,
+                    client,
+                    angles,
+                })
+                .unwrap();
+        }
+
+        let data = writer.into_inner();
+        let mut server = DemoServer::from_mvd(data.as_slice()).unwrap();
+        assert_eq!(server.pov(), Some(0));
+        assert_eq!(server.next().unwrap().message(), &[1]);
+
+        // resumes at the first message after the one last played
+        assert_eq!(server.switch_pov(None), Some(1));
+        assert_eq!(server.next().unwrap().message(), &[4]);
+        assert!(server.next().is_none());
+
+        assert_eq!(server.switch_pov(Some(2)), None);
+        assert_eq!(server.pov(), Some(1));
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright © 2021 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! HTTP fast downloads of missing content.
+//!
+//! Servers following the `sv_downloadurl` convention mirror their game directory on a web server.
+//! When the server info names models or sounds which aren't available locally, they are fetched
+//! from the mirror on a background thread and stored in the [`DownloadCache`], where the VFS picks
+//! them up immediately.
+//!
+//! The protocol has no way for a server to advertise checksums, so mirrors may publish them
+//! instead: if `<file>.sha1` exists next to a file, the download is rejected unless its SHA-1
+//! checksum matches. Files without one are stored unchecked.
+
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use crate::{
+    client::strings::Strings,
+    common::{
+        cache::DownloadCache,
+        host,
+        http::{self, FetchOptions},
+        vfs::Vfs,
+    },
+};
+
+/// The largest checksum file accepted from a mirror.
+const MAX_CHECKSUM_SIZE: u64 = 1024;
+
+enum DownloadEvent {
+    Progress {
+        received: u64,
+        total: Option<u64>,
+    },
+    Finished {
+        path: String,
+        data: Vec<u8>,
+        sha1: Option<String>,
+    },
+    Failed {
+        path: String,
+        error: String,
+    },
+}
+
+/// Returns the virtual paths of precached models and sounds which can't be found in the VFS.
+///
+/// Brush submodels (`*1`, `*2`, ...) are part of the world model and are skipped.
+pub fn missing_files(
+    vfs: &Vfs,
+    model_precache: &[String],
+    sound_precache: &[String],
+) -> Vec<String> {
+    let models = model_precache
+        .iter()
+        .filter(|name| !name.starts_with('*'))
+        .cloned();
+    let sounds = sound_precache.iter().map(|name| format!("sound/{}", name));
+
+    models
+        .chain(sounds)
+        .filter(|path| vfs.open(path).is_err())
+        .collect()
+}
+
+/// Parses the contents of a mirror's `.sha1` file.
+///
+/// Accepts both a bare checksum and `sha1sum` output, where the checksum is followed by the file
+/// name.
+fn parse_checksum(data: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(data).ok()?;
+    let sha1 = text.split_whitespace().next()?;
+    if sha1.len() == 40 && sha1.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(sha1.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+/// A set of files being fetched from a download mirror.
+pub struct HttpDownloads {
+    events: Receiver<DownloadEvent>,
+    files: Vec<String>,
+    finished: usize,
+    failed: Vec<String>,
+    progress: (u64, Option<u64>),
+    done: bool,
+}
+
+impl HttpDownloads {
+    /// Starts fetching `files` from the mirror at `base_url` on a background thread.
+    pub fn start(
+        base_url: String,
+        files: Vec<String>,
+        options: FetchOptions,
+    ) -> std::io::Result<HttpDownloads> {
+        let (tx, rx) = mpsc::channel();
+
+        let thread_files = files.clone();
+        host::spawn_named("download", move || {
+            for path in thread_files {
+                let progress_tx = tx.clone();
+                let result = http::file_url(&base_url, &path).and_then(|url| {
+                    let data = http::fetch(&url, &options, |received, total| {
+                        let _ = progress_tx.send(DownloadEvent::Progress { received, total });
+                    })?;
+
+                    // most mirrors don't publish checksums, so a missing one isn't an error
+                    let checksum_options = FetchOptions {
+                        max_size: MAX_CHECKSUM_SIZE,
+                        ..options
+                    };
+                    let sha1 = http::fetch(&format!("{}.sha1", url), &checksum_options, |_, _| ())
+                        .ok()
+                        .and_then(|c| parse_checksum(&c));
+
+                    Ok((data, sha1))
+                });
+
+                let event = match result {
+                    Ok((data, sha1)) => DownloadEvent::Finished { path, data, sha1 },
+                    Err(e) => DownloadEvent::Failed {
+                        path,
+                        error: e.to_string(),
+                    },
+                };
+
+                // the client has given up on these downloads
+                if tx.send(event).is_err() {
+                    return;
+                }
+            }
+        })?;
+
+        Ok(HttpDownloads {
+            events: rx,
+            files,
+            finished: 0,
+            failed: Vec::new(),
+            progress: (0, None),
+            done: false,
+        })
+    }
+
+    /// Stores any newly fetched files in the cache. Returns `true` once every file has finished
+    /// downloading or failed.
+    pub fn poll(&mut self, cache: &mut DownloadCache) -> bool {
+        loop {
+            match self.events.try_recv() {
+                Ok(DownloadEvent::Progress { received, total }) => {
+                    self.progress = (received, total)
+                }
+
+                Ok(DownloadEvent::Finished { path, data, sha1 }) => {
+                    self.finished += 1;
+                    self.progress = (0, None);
+                    match cache.store(&path, &data, sha1.as_deref()) {
+                        Ok(_) => log::info!("Downloaded {} ({} bytes)", path, data.len()),
+                        Err(e) => {
+                            log::warn!("Couldn't store {}: {}", path, e);
+                            self.failed.push(path);
+                        }
+                    }
+                }
+
+                Ok(DownloadEvent::Failed { path, error }) => {
+                    self.finished += 1;
+                    self.progress = (0, None);
+                    log::warn!("Couldn't download {}: {}", path, error);
+                    self.failed.push(path);
+                }
+
+                Err(TryRecvError::Empty) => break,
+
+                // the thread exits once all files are handled
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    break;
+                }
+            }
+        }
+
+        self.done
+    }
+
+    /// Returns the files which couldn't be downloaded.
+    pub fn failed(&self) -> &[String] {
+        &self.failed
+    }
+
+    /// Returns a line describing the current download, for display on the loading screen.
+    pub fn status(&self, strings: &Strings) -> String {
+        let current = match self.files.get(self.finished) {
+            Some(path) => path.as_str(),
+            None => return strings.format("download.status.done", &[&self.files.len()]),
+        };
+
+        let (key, amount) = match self.progress {
+            (received, Some(total)) if total > 0 => {
+                ("download.status.percent", received * 100 / total)
+            }
+            (received, _) => ("download.status.kib", received / 1024),
+        };
+
+        strings.format(
+            key,
+            &[&current, &(self.finished + 1), &self.files.len(), &amount],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_files() {
+        let mut vfs = Vfs::new();
+        vfs.add_memory("maps/test.bsp", vec![0]);
+        vfs.add_memory("sound/misc/null.wav", vec![0]);
+
+        let models = vec![
+            "maps/test.bsp".to_owned(),
+            "*1".to_owned(),
+            "progs/custom.mdl".to_owned(),
+        ];
+        let sounds = vec!["misc/null.wav".to_owned(), "custom/boom.wav".to_owned()];
+
+        assert_eq!(
+            missing_files(&vfs, &models, &sounds),
+            vec![
+                "progs/custom.mdl".to_owned(),
+                "sound/custom/boom.wav".to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let sha1 = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+        assert_eq!(parse_checksum(sha1.as_bytes()), Some(sha1.to_owned()));
+        assert_eq!(
+            parse_checksum(b"DA39A3EE5E6B4B0D3255BFEF95601890AFD80709  maps/custom.bsp\n"),
+            Some(sha1.to_owned())
+        );
+        assert_eq!(parse_checksum(b"<html>Not Found</html>"), None);
+        assert_eq!(parse_checksum(b""), None);
+    }
+}
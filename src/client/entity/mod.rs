@@ -19,38 +19,49 @@
 // SOFTWARE.
 
 pub mod particle;
-
-use crate::common::{
-    alloc::LinkedSlab,
-    engine,
-    net::{EntityEffects, EntityState, EntityUpdate},
+pub mod snapshot;
+
+use crate::{
+    client::entity::snapshot::{EntitySnapshot, SnapshotHistory},
+    common::{
+        alloc::LinkedSlab,
+        engine,
+        net::{EntityEffects, EntityState, EntityUpdate, PlayerColor},
+    },
 };
 
-use cgmath::{Deg, Vector3};
+use cgmath::{Deg, InnerSpace as _, Vector3};
 use chrono::Duration;
 
 // if this is changed, it must also be changed in deferred.frag
 pub const MAX_LIGHTS: usize = 32;
 pub const MAX_BEAMS: usize = 24;
-pub const MAX_TEMP_ENTITIES: usize = 64;
-pub const MAX_STATIC_ENTITIES: usize = 128;
+
+// entities that move farther than this between updates are assumed to have teleported
+const TELEPORT_DISTANCE: f32 = 100.0;
 
 #[derive(Debug)]
 pub struct ClientEntity {
     pub force_link: bool,
     pub baseline: EntityState,
-    pub msg_time: Duration,
-    pub msg_origins: [Vector3<f32>; 2],
+
+    // states received from the server, most recent last
+    pub history: SnapshotHistory,
+
+    // interpolated state for the current frame
     pub origin: Vector3<f32>,
-    pub msg_angles: [Vector3<Deg<f32>>; 2],
     pub angles: Vector3<Deg<f32>>,
+
     pub model_id: usize,
     model_changed: bool,
     pub frame_id: usize,
     pub skin_id: usize,
     colormap: Option<u8>,
+    colors: Option<PlayerColor>,
     pub sync_base: Duration,
     pub effects: EntityEffects,
+    /// The entity's opacity, where 1 is fully opaque.
+    pub alpha: f32,
     pub light_id: Option<usize>,
     // vis_frame: usize,
 }
@@ -60,21 +71,24 @@ impl ClientEntity {
         ClientEntity {
             force_link: false,
             baseline: baseline.clone(),
-            msg_time: Duration::zero(),
-            msg_origins: [Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)],
+            history: SnapshotHistory::new(EntitySnapshot {
+                time: Duration::zero(),
+                origin: baseline.origin,
+                angles: baseline.angles,
+                frame_id: baseline.frame_id,
+                effects: baseline.effects,
+            }),
             origin: baseline.origin,
-            msg_angles: [
-                Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)),
-                Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)),
-            ],
             angles: baseline.angles,
             model_id: baseline.model_id,
             model_changed: false,
             frame_id: baseline.frame_id,
             skin_id: baseline.skin_id,
             colormap: None,
+            colors: None,
             sync_base: Duration::zero(),
             effects: baseline.effects,
+            alpha: baseline.alpha,
             light_id: None,
         }
     }
@@ -83,21 +97,24 @@ impl ClientEntity {
         ClientEntity {
             force_link: false,
             baseline: EntityState::uninitialized(),
-            msg_time: Duration::zero(),
-            msg_origins: [Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0)],
+            history: SnapshotHistory::new(EntitySnapshot {
+                time: Duration::zero(),
+                origin: Vector3::new(0.0, 0.0, 0.0),
+                angles: Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)),
+                frame_id: 0,
+                effects: EntityEffects::empty(),
+            }),
             origin: Vector3::new(0.0, 0.0, 0.0),
-            msg_angles: [
-                Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)),
-                Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)),
-            ],
             angles: Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)),
             model_id: 0,
             model_changed: false,
             frame_id: 0,
             skin_id: 0,
             colormap: None,
+            colors: None,
             sync_base: Duration::zero(),
             effects: EntityEffects::empty(),
+            alpha: 1.0,
             light_id: None,
         }
     }
@@ -110,19 +127,27 @@ impl ClientEntity {
         // enable lerping
         self.force_link = false;
 
-        if update.no_lerp || self.msg_time != msg_times[1] {
+        // if the entity missed the last update, there's nothing to interpolate from
+        if update.no_lerp || self.msg_time() != msg_times[1] {
             self.force_link = true;
         }
 
-        self.msg_time = msg_times[0];
-
         // fill in missing values from baseline
         let new_state = update.to_entity_state(&self.baseline);
 
-        self.msg_origins[1] = self.msg_origins[0];
-        self.msg_origins[0] = new_state.origin;
-        self.msg_angles[1] = self.msg_angles[0];
-        self.msg_angles[0] = new_state.angles;
+        // don't slide teleported entities across the map
+        let distance = (new_state.origin - self.history.latest().origin).magnitude();
+        if distance > TELEPORT_DISTANCE {
+            self.force_link = true;
+        }
+
+        let snapshot = EntitySnapshot {
+            time: msg_times[0],
+            origin: new_state.origin,
+            angles: new_state.angles,
+            frame_id: new_state.frame_id,
+            effects: new_state.effects,
+        };
 
         if self.model_id != new_state.model_id {
             self.model_changed = true;
@@ -133,21 +158,39 @@ impl ClientEntity {
         self.frame_id = new_state.frame_id;
         self.skin_id = new_state.skin_id;
         self.effects = new_state.effects;
-        self.colormap = update.colormap;
+        self.alpha = new_state.alpha;
+        // colormap 0 leaves the skin as it is, others give the number of the player whose colors
+        // the skin is drawn in
+        self.colormap = Some(new_state.colormap).filter(|&c| c != 0);
 
         if self.force_link {
-            self.msg_origins[1] = self.msg_origins[0];
-            self.origin = self.msg_origins[0];
-            self.msg_angles[1] = self.msg_angles[0];
-            self.angles = self.msg_angles[0];
+            self.history.reset(snapshot);
+            self.origin = snapshot.origin;
+            self.angles = snapshot.angles;
+        } else {
+            self.history.push(snapshot);
         }
     }
 
+    /// Sets the entity's origin and angles between its last two snapshots.
+    ///
+    /// `lerp_factor` is the client's progress from the previous server message to the latest one.
+    /// Entities without a previous snapshot, such as those that just teleported, are placed at
+    /// their latest state.
+    pub fn interpolate(&mut self, lerp_factor: f32) {
+        let snapshot = self
+            .history
+            .previous()
+            .lerp(self.history.latest(), lerp_factor);
+        self.origin = snapshot.origin;
+        self.angles = snapshot.angles;
+    }
+
     /// Sets the entity's most recent message angles to the specified value.
     ///
     /// This is primarily useful for allowing interpolated view angles in demos.
     pub fn update_angles(&mut self, angles: Vector3<Deg<f32>>) {
-        self.msg_angles[0] = angles;
+        self.history.latest_mut().angles = angles;
     }
 
     /// Sets the entity's angles to the specified value, overwriting the message
@@ -156,14 +199,20 @@ impl ClientEntity {
     /// This causes the entity to "snap" to the correct angle rather than
     /// interpolating to it.
     pub fn set_angles(&mut self, angles: Vector3<Deg<f32>>) {
-        self.msg_angles[0] = angles;
-        self.msg_angles[1] = angles;
+        for snapshot in self.history.iter_mut() {
+            snapshot.angles = angles;
+        }
         self.angles = angles;
     }
 
     /// Returns the timestamp of the last message that updated this entity.
     pub fn msg_time(&self) -> Duration {
-        self.msg_time
+        self.history.latest().time
+    }
+
+    /// Returns the entity's state as of the given time, interpolated from its history.
+    pub fn state_at(&self, time: Duration) -> EntitySnapshot {
+        self.history.sample(time)
     }
 
     /// Returns true if the last update to this entity changed its model.
@@ -175,6 +224,15 @@ impl ClientEntity {
         self.colormap
     }
 
+    /// Returns the colors of the player given by the entity's colormap, if any.
+    pub fn colors(&self) -> Option<PlayerColor> {
+        self.colors
+    }
+
+    pub fn set_colors(&mut self, colors: Option<PlayerColor>) {
+        self.colors = colors;
+    }
+
     pub fn get_origin(&self) -> Vector3<f32> {
         self.origin
     }
@@ -330,3 +388,106 @@ pub struct Beam {
     pub start: Vector3<f32>,
     pub end: Vector3<f32>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity_at(origin: Vector3<f32>) -> ClientEntity {
+        ClientEntity::from_baseline(EntityState {
+            origin,
+            angles: Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)),
+            model_id: 1,
+            frame_id: 0,
+            colormap: 0,
+            skin_id: 0,
+            effects: EntityEffects::empty(),
+            alpha: 1.0,
+        })
+    }
+
+    fn move_to(x: f32, yaw: f32) -> EntityUpdate {
+        EntityUpdate {
+            ent_id: 1,
+            model_id: None,
+            frame_id: None,
+            colormap: None,
+            skin_id: None,
+            effects: None,
+            origin_x: Some(x),
+            pitch: None,
+            origin_y: None,
+            yaw: Some(Deg(yaw)),
+            origin_z: None,
+            roll: None,
+            no_lerp: false,
+            alpha: None,
+        }
+    }
+
+    fn ms(ms: i64) -> Duration {
+        Duration::milliseconds(ms)
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let mut ent = entity_at(Vector3::new(0.0, 0.0, 0.0));
+        ent.update([ms(100), ms(0)], move_to(10.0, 0.0));
+        ent.update([ms(200), ms(100)], move_to(30.0, 90.0));
+        assert!(!ent.force_link);
+
+        ent.interpolate(0.5);
+        assert_eq!(ent.origin, Vector3::new(20.0, 0.0, 0.0));
+        assert_eq!(ent.angles.y, Deg(45.0));
+
+        ent.interpolate(1.0);
+        assert_eq!(ent.origin, Vector3::new(30.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_interpolate_from_baseline() {
+        let mut ent = entity_at(Vector3::new(50.0, 0.0, 0.0));
+        ent.update([ms(100), ms(0)], move_to(60.0, 0.0));
+
+        ent.interpolate(0.5);
+        assert_eq!(ent.origin, Vector3::new(55.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_teleport_snaps() {
+        let mut ent = entity_at(Vector3::new(0.0, 0.0, 0.0));
+        ent.update([ms(100), ms(0)], move_to(10.0, 0.0));
+        ent.update([ms(200), ms(100)], move_to(1000.0, 0.0));
+        assert!(ent.force_link);
+
+        ent.interpolate(0.5);
+        assert_eq!(ent.origin, Vector3::new(1000.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_update_alpha() {
+        let mut ent = entity_at(Vector3::new(0.0, 0.0, 0.0));
+        ent.update(
+            [ms(100), ms(0)],
+            EntityUpdate {
+                alpha: Some(0.25),
+                ..move_to(0.0, 0.0)
+            },
+        );
+        assert_eq!(ent.alpha, 0.25);
+
+        // updates without an alpha fall back to the baseline
+        ent.update([ms(200), ms(100)], move_to(0.0, 0.0));
+        assert_eq!(ent.alpha, 1.0);
+
+        // Nehahra's opaque 0 is handled by the decoder, so 0 is fully transparent here
+        ent.update(
+            [ms(300), ms(200)],
+            EntityUpdate {
+                alpha: Some(0.0),
+                ..move_to(0.0, 0.0)
+            },
+        );
+        assert_eq!(ent.alpha, 0.0);
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Time-stamped entity state history.
+//!
+//! Each server update to an entity is recorded as an [`EntitySnapshot`] rather than overwriting
+//! the previous state. The two most recent snapshots drive interpolation, and older ones let the
+//! entity be reconstructed at any recent time.
+
+use std::collections::VecDeque;
+
+use crate::common::{engine, net::EntityEffects};
+
+use cgmath::{Angle as _, Deg, Vector3};
+use chrono::Duration;
+
+/// The number of snapshots kept for each entity.
+pub const SNAPSHOT_HISTORY: usize = 32;
+
+/// The state of an entity as of a single server message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EntitySnapshot {
+    /// The server time of the message.
+    pub time: Duration,
+    pub origin: Vector3<f32>,
+    pub angles: Vector3<Deg<f32>>,
+    pub frame_id: usize,
+    pub effects: EntityEffects,
+}
+
+impl EntitySnapshot {
+    /// Interpolates between this snapshot and a later one.
+    ///
+    /// Origin and angles are blended by `ratio`, with angles turning the short way around. Frame
+    /// and effects are taken from this snapshot until `ratio` reaches 1.
+    pub fn lerp(&self, next: &EntitySnapshot, ratio: f32) -> EntitySnapshot {
+        let time = self.time
+            + engine::duration_from_f32(ratio * engine::duration_to_f32(next.time - self.time));
+
+        let (frame_id, effects) = if ratio >= 1.0 {
+            (next.frame_id, next.effects)
+        } else {
+            (self.frame_id, self.effects)
+        };
+
+        EntitySnapshot {
+            time,
+            origin: self.origin + ratio * (next.origin - self.origin),
+            angles: lerp_angles(self.angles, next.angles, ratio),
+            frame_id,
+            effects,
+        }
+    }
+}
+
+/// Interpolates between two sets of Euler angles, turning the short way around.
+///
+/// This assumes that entities will not whip around 180+ degrees between updates, and avoids small
+/// turns across 0 <-> 359 spinning the entity the long way.
+pub fn lerp_angles(
+    from: Vector3<Deg<f32>>,
+    to: Vector3<Deg<f32>>,
+    ratio: f32,
+) -> Vector3<Deg<f32>> {
+    let mut out = from;
+    for i in 0..3 {
+        let mut delta = to[i] - from[i];
+        if delta > Deg(180.0) {
+            delta = delta - Deg(360.0);
+        } else if delta < Deg(-180.0) {
+            delta = delta + Deg(360.0);
+        }
+
+        out[i] = (from[i] + delta * ratio).normalize();
+    }
+
+    out
+}
+
+/// A bounded history of snapshots for a single entity, oldest first.
+///
+/// The history is never empty.
+#[derive(Clone, Debug)]
+pub struct SnapshotHistory {
+    snapshots: VecDeque<EntitySnapshot>,
+}
+
+impl SnapshotHistory {
+    pub fn new(initial: EntitySnapshot) -> SnapshotHistory {
+        let mut snapshots = VecDeque::with_capacity(SNAPSHOT_HISTORY);
+        snapshots.push_back(initial);
+        SnapshotHistory { snapshots }
+    }
+
+    /// Records a new snapshot, discarding the oldest one if the history is full.
+    pub fn push(&mut self, snapshot: EntitySnapshot) {
+        if self.snapshots.len() >= SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Discards the history and starts over from the given snapshot.
+    ///
+    /// This is used when the entity teleports or changes model, so that it isn't interpolated
+    /// from its old state.
+    pub fn reset(&mut self, snapshot: EntitySnapshot) {
+        self.snapshots.clear();
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Returns the most recent snapshot.
+    pub fn latest(&self) -> &EntitySnapshot {
+        self.snapshots.back().unwrap()
+    }
+
+    /// Returns a mutable reference to the most recent snapshot.
+    pub fn latest_mut(&mut self) -> &mut EntitySnapshot {
+        self.snapshots.back_mut().unwrap()
+    }
+
+    /// Returns the snapshot before the most recent one, or the most recent one if there is no
+    /// earlier snapshot.
+    pub fn previous(&self) -> &EntitySnapshot {
+        let len = self.snapshots.len();
+        &self.snapshots[len.saturating_sub(2)]
+    }
+
+    /// Returns an iterator over the snapshots from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &EntitySnapshot> {
+        self.snapshots.iter()
+    }
+
+    /// Returns a mutable iterator over the snapshots from oldest to newest.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut EntitySnapshot> {
+        self.snapshots.iter_mut()
+    }
+
+    /// Reconstructs the entity's state at the given time.
+    ///
+    /// Times between two snapshots are interpolated. Times outside the history are clamped to the
+    /// oldest or newest snapshot.
+    pub fn sample(&self, time: Duration) -> EntitySnapshot {
+        let newer_idx = match self.snapshots.iter().position(|s| s.time > time) {
+            Some(i) => i,
+            None => return *self.latest(),
+        };
+
+        if newer_idx == 0 {
+            return self.snapshots[0];
+        }
+
+        let older = &self.snapshots[newer_idx - 1];
+        let newer = &self.snapshots[newer_idx];
+        let span = engine::duration_to_f32(newer.time - older.time);
+        let ratio = engine::duration_to_f32(time - older.time) / span;
+
+        older.lerp(newer, ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(time_ms: i64, x: f32, yaw: f32) -> EntitySnapshot {
+        EntitySnapshot {
+            time: Duration::milliseconds(time_ms),
+            origin: Vector3::new(x, 0.0, 0.0),
+            angles: Vector3::new(Deg(0.0), Deg(yaw), Deg(0.0)),
+            frame_id: time_ms as usize,
+            effects: EntityEffects::empty(),
+        }
+    }
+
+    #[test]
+    fn test_history_capacity() {
+        let mut history = SnapshotHistory::new(snapshot(0, 0.0, 0.0));
+        for i in 1..SNAPSHOT_HISTORY as i64 + 1 {
+            history.push(snapshot(i, 0.0, 0.0));
+        }
+
+        assert_eq!(history.iter().count(), SNAPSHOT_HISTORY);
+        assert_eq!(
+            history.iter().next().unwrap().time,
+            Duration::milliseconds(1)
+        );
+        assert_eq!(
+            history.latest().time,
+            Duration::milliseconds(SNAPSHOT_HISTORY as i64)
+        );
+    }
+
+    #[test]
+    fn test_previous_after_reset() {
+        let mut history = SnapshotHistory::new(snapshot(0, 0.0, 0.0));
+        history.push(snapshot(100, 10.0, 0.0));
+        assert_eq!(history.previous().time, Duration::zero());
+
+        history.reset(snapshot(200, 500.0, 0.0));
+        assert_eq!(history.previous(), history.latest());
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut history = SnapshotHistory::new(snapshot(0, 0.0, 0.0));
+        history.push(snapshot(100, 10.0, 0.0));
+        history.push(snapshot(200, 30.0, 0.0));
+
+        let s = history.sample(Duration::milliseconds(150));
+        assert_eq!(s.origin, Vector3::new(20.0, 0.0, 0.0));
+        assert_eq!(s.frame_id, 100);
+
+        // clamped to either end
+        assert_eq!(history.sample(Duration::milliseconds(-50)).origin.x, 0.0);
+        assert_eq!(history.sample(Duration::milliseconds(500)).origin.x, 30.0);
+    }
+
+    #[test]
+    fn test_lerp_angles_short_way() {
+        let from = Vector3::new(Deg(0.0), Deg(350.0), Deg(0.0));
+        let to = Vector3::new(Deg(0.0), Deg(10.0), Deg(0.0));
+        let mid = lerp_angles(from, to, 0.5);
+        assert!(mid.y.0.abs() < 0.001 || (mid.y.0 - 360.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,362 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A scripted server for testing the client's networking.
+//!
+//! `FakeServer` accepts a single connection on the loopback interface and plays a script to the
+//! client, sending commands and waiting for the client's replies where the script expects them.
+//! The tests drive a silent `Connection` through the handling of commands which only update the
+//! client state, since building the world renderer needs a graphics device.
+
+use std::{
+    collections::VecDeque,
+    io::Cursor,
+    net::{SocketAddr, UdpSocket},
+    thread::{self, JoinHandle},
+    time::{Duration as StdDuration, Instant},
+};
+
+use crate::common::net::{
+    self,
+    connect::{ConnectListener, Request, Response, ResponseAccept},
+    BlockingMode, ClientCmd, NetError, QSocket, ServerCmd,
+};
+
+// how long either end waits on the other before giving up
+const TIMEOUT: StdDuration = StdDuration::from_secs(5);
+
+// how long to sleep between polls of a socket
+const POLL_INTERVAL: StdDuration = StdDuration::from_millis(1);
+
+/// A step in a fake server's script.
+pub enum Step {
+    /// Sends the commands as one reliable message and waits for the client to acknowledge it.
+    Reliable(Vec<ServerCmd>),
+
+    /// Sends the commands as one unreliable message.
+    Unreliable(Vec<ServerCmd>),
+
+    /// Waits for the client to send a string command starting with the given text.
+    Expect(&'static str),
+}
+
+/// A server which plays a script to the first client that connects to it.
+pub struct FakeServer {
+    addr: SocketAddr,
+    thread: JoinHandle<Result<(), NetError>>,
+}
+
+impl FakeServer {
+    /// Starts a server on the loopback interface which plays `script` in a separate thread.
+    pub fn start(script: Vec<Step>) -> FakeServer {
+        let listener = ConnectListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let thread = thread::spawn(move || {
+            let qsock = accept(&listener)?;
+            play(qsock, script)
+        });
+
+        FakeServer { addr, thread }
+    }
+
+    /// Returns the address clients should connect to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Waits for the script to finish, returning the error that stopped it, if any.
+    pub fn join(self) -> Result<(), NetError> {
+        self.thread.join().expect("Fake server panicked")
+    }
+}
+
+/// Accepts a connection request and returns a socket for the new connection.
+fn accept(listener: &ConnectListener) -> Result<QSocket, NetError> {
+    let (request, remote) = listener.recv_request()?;
+    match request {
+        Request::Connect(ref connect) if connect.game_name == net::GAME_NAME => (),
+        _ => return Err(NetError::with_msg("Expected a connection request")),
+    }
+
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    let port = socket.local_addr()?.port() as i32;
+    listener.send_response(Response::Accept(ResponseAccept { port }), remote)?;
+
+    Ok(QSocket::new(socket, remote))
+}
+
+fn play(mut qsock: QSocket, script: Vec<Step>) -> Result<(), NetError> {
+    // string commands received from the client and not yet expected by the script
+    let mut received = VecDeque::new();
+
+    for step in script {
+        match step {
+            Step::Reliable(cmds) => {
+                qsock.begin_send_msg(&serialize(&cmds)?)?;
+                poll(&mut qsock, &mut received, |qsock, _| qsock.can_send())?;
+            }
+
+            Step::Unreliable(cmds) => qsock.send_msg_unreliable(&serialize(&cmds)?)?,
+
+            Step::Expect(text) => {
+                poll(&mut qsock, &mut received, |_, received| {
+                    !received.is_empty()
+                })?;
+                let cmd = received.pop_front().unwrap();
+                if !cmd.starts_with(text) {
+                    return Err(NetError::with_msg(format!(
+                        "Expected \"{}\" from client, got \"{}\"",
+                        text, cmd
+                    )));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn serialize(cmds: &[ServerCmd]) -> Result<Vec<u8>, NetError> {
+    let mut msg = Vec::new();
+    for cmd in cmds {
+        cmd.serialize(&mut msg)?;
+    }
+
+    Ok(msg)
+}
+
+/// Receives messages from the client until `done` returns true.
+fn poll<F>(
+    qsock: &mut QSocket,
+    received: &mut VecDeque<String>,
+    mut done: F,
+) -> Result<(), NetError>
+where
+    F: FnMut(&QSocket, &VecDeque<String>) -> bool,
+{
+    let deadline = Instant::now() + TIMEOUT;
+
+    while !done(qsock, received) {
+        if Instant::now() > deadline {
+            return Err(NetError::with_msg("Timed out waiting for client"));
+        }
+
+        let msg = qsock.recv_msg(BlockingMode::NonBlocking)?;
+        if msg.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+
+        let mut reader = Cursor::new(msg.as_slice());
+        while (reader.position() as usize) < msg.len() {
+            if let ClientCmd::StringCmd { cmd } = ClientCmd::deserialize(&mut reader)? {
+                received.push_back(cmd);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        client::{
+            connect_qsocket, delay::DelayBuffer, strings::Strings, view::KickVars, Connection,
+            ConnectionKind, ConnectionState,
+        },
+        common::net::{ClientStat, EntityUpdate, GameType, ProtocolFlags, SignOnStage},
+    };
+
+    use cgmath::{Deg, Vector3};
+
+    const KICK_VARS: KickVars = KickVars {
+        v_kickpitch: 0.6,
+        v_kickroll: 0.6,
+        v_kicktime: 0.5,
+    };
+
+    fn connect(addr: SocketAddr) -> Connection {
+        Connection::new(
+            ConnectionKind::Server {
+                qsock: connect_qsocket(addr, &Strings::new()).unwrap(),
+                compose: Vec::new(),
+                delay: DelayBuffer::new(),
+            },
+            None,
+        )
+    }
+
+    /// Handles messages from the server until it disconnects, returning the protocol version it
+    /// announced.
+    fn run(conn: &mut Connection) -> Option<i32> {
+        let deadline = Instant::now() + TIMEOUT;
+        let mut protocol_version = None;
+
+        loop {
+            assert!(Instant::now() < deadline, "Timed out waiting for server");
+
+            let (qsock, compose) = match conn.kind {
+                ConnectionKind::Server {
+                    ref mut qsock,
+                    ref mut compose,
+                    ..
+                } => (qsock, compose),
+                ConnectionKind::Demo(_) => unreachable!(),
+            };
+
+            // respond to the server
+            if qsock.can_send() && !compose.is_empty() {
+                qsock.begin_send_msg(compose).unwrap();
+                compose.clear();
+            }
+
+            let msg = qsock.recv_msg(BlockingMode::NonBlocking).unwrap();
+            if msg.is_empty() {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+
+            let mut reader = Cursor::new(msg.as_slice());
+            while let Some(cmd) =
+                ServerCmd::deserialize_with_format(&mut reader, conn.wire_format).unwrap()
+            {
+                match conn.apply_server_cmd(cmd, false, KICK_VARS, None).unwrap() {
+                    None => (),
+                    Some(ServerCmd::ServerInfo {
+                        protocol_version: v,
+                        ..
+                    }) => protocol_version = Some(v),
+                    Some(ServerCmd::Disconnect) => return protocol_version,
+                    Some(cmd) => panic!("Unexpected command from server: {:?}", cmd),
+                }
+            }
+        }
+    }
+
+    /// Returns the position of an entity as of the last message from the server.
+    fn origin(conn: &Connection, id: usize) -> Vector3<f32> {
+        conn.state.entities[id]
+            .state_at(conn.state.msg_times[0])
+            .origin
+    }
+
+    fn baseline(ent_id: u16, model_id: u8, origin: Vector3<f32>) -> ServerCmd {
+        ServerCmd::SpawnBaseline {
+            ent_id,
+            model_id,
+            frame_id: 0,
+            colormap: 0,
+            skin_id: 0,
+            origin,
+            angles: Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)),
+        }
+    }
+
+    fn move_x(ent_id: u16, x: f32) -> ServerCmd {
+        ServerCmd::FastUpdate(EntityUpdate {
+            ent_id,
+            model_id: None,
+            frame_id: None,
+            colormap: None,
+            skin_id: None,
+            effects: None,
+            origin_x: Some(x),
+            pitch: None,
+            origin_y: None,
+            yaw: None,
+            origin_z: None,
+            roll: None,
+            no_lerp: false,
+            alpha: None,
+        })
+    }
+
+    #[test]
+    fn test_connect_signon_disconnect() {
+        let server = FakeServer::start(vec![
+            Step::Reliable(vec![
+                ServerCmd::ServerInfo {
+                    protocol_version: net::PROTOCOL_VERSION as i32,
+                    protocol_flags: ProtocolFlags::empty(),
+                    max_clients: 1,
+                    game_type: GameType::CoOp,
+                    message: String::from("Fake Server"),
+                    model_precache: vec![
+                        String::from("maps/fake.bsp"),
+                        String::from("progs/player.mdl"),
+                    ],
+                    sound_precache: Vec::new(),
+                },
+                ServerCmd::SignOnStage {
+                    stage: SignOnStage::Prespawn,
+                },
+            ]),
+            Step::Expect("prespawn"),
+            Step::Reliable(vec![
+                baseline(1, 2, Vector3::new(0.0, 0.0, 0.0)),
+                baseline(2, 2, Vector3::new(64.0, 32.0, 0.0)),
+                ServerCmd::SignOnStage {
+                    stage: SignOnStage::ClientInfo,
+                },
+            ]),
+            Step::Expect("name"),
+            Step::Expect("color"),
+            Step::Expect("spawn"),
+            Step::Reliable(vec![
+                ServerCmd::UpdateStat {
+                    stat: ClientStat::Health,
+                    value: 100,
+                },
+                ServerCmd::SignOnStage {
+                    stage: SignOnStage::Begin,
+                },
+            ]),
+            Step::Expect("begin"),
+            Step::Unreliable(vec![ServerCmd::Time { time: 0.1 }, move_x(1, 16.0)]),
+            Step::Unreliable(vec![ServerCmd::Time { time: 0.2 }, move_x(1, 32.0)]),
+            Step::Reliable(vec![ServerCmd::UpdateStat {
+                stat: ClientStat::Health,
+                value: 75,
+            }]),
+            Step::Unreliable(vec![ServerCmd::Time { time: 0.3 }, move_x(1, 48.0)]),
+            Step::Reliable(vec![ServerCmd::Disconnect]),
+        ]);
+
+        let mut conn = connect(server.addr());
+        let protocol_version = run(&mut conn);
+        server.join().unwrap();
+
+        assert_eq!(protocol_version, Some(net::PROTOCOL_VERSION as i32));
+        match conn.conn_state {
+            ConnectionState::SignOn(SignOnStage::Done) => (),
+            _ => panic!("Client didn't finish signing on"),
+        }
+        assert_eq!(conn.state.stats.get(ClientStat::Health), 75);
+
+        // entity 0 is the world, entity 1 moved every frame, entity 2 stayed at its baseline
+        assert_eq!(conn.state.entities.len(), 3);
+        assert_eq!(conn.state.entities[1].model_id(), 2);
+        assert_eq!(origin(&conn, 1), Vector3::new(48.0, 0.0, 0.0));
+        assert_eq!(origin(&conn, 2), Vector3::new(64.0, 32.0, 0.0));
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Global fog.
+//!
+//! This follows FitzQuake: fog is set by the `fog` command, by a map's worldspawn `fog` key or by
+//! `svc_fog`, and may fade from its previous value over a given time.
+
+use crate::common::engine;
+
+use cgmath::Vector3;
+use chrono::Duration;
+
+/// Fog parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    /// The fog density. Zero disables fog.
+    pub density: f32,
+
+    /// The fog color, with each component in [0, 1].
+    pub color: Vector3<f32>,
+}
+
+impl Fog {
+    /// Parses fog parameters in the format used by the `fog` command and the worldspawn `fog`
+    /// key, using `current` for any values that aren't given.
+    ///
+    /// Returns the new parameters and the time to fade to them over.
+    ///
+    /// The accepted forms are `density`, `density time`, `r g b`, `density r g b` and
+    /// `density r g b time`.
+    pub fn parse(args: &[&str], current: Fog) -> Option<(Fog, Duration)> {
+        let values = args
+            .iter()
+            .map(|a| a.parse::<f32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+
+        let (density, color, time) = match *values.as_slice() {
+            [d] => (d, current.color, 0.0),
+            [d, t] => (d, current.color, t),
+            [r, g, b] => (current.density, Vector3::new(r, g, b), 0.0),
+            [d, r, g, b] => (d, Vector3::new(r, g, b), 0.0),
+            [d, r, g, b, t] => (d, Vector3::new(r, g, b), t),
+            _ => return None,
+        };
+
+        Some((
+            Fog {
+                density: density.max(0.0),
+                color: Vector3::new(
+                    color.x.max(0.0).min(1.0),
+                    color.y.max(0.0).min(1.0),
+                    color.z.max(0.0).min(1.0),
+                ),
+            },
+            engine::duration_from_f32(time.max(0.0)),
+        ))
+    }
+}
+
+impl std::default::Default for Fog {
+    fn default() -> Fog {
+        Fog {
+            density: 0.0,
+            color: Vector3::new(0.3, 0.3, 0.3),
+        }
+    }
+}
+
+/// The current fog and any fade in progress.
+#[derive(Clone, Debug)]
+pub struct FogState {
+    from: Fog,
+    to: Fog,
+    fade_start: Duration,
+    fade_time: Duration,
+}
+
+impl FogState {
+    pub fn new(fog: Fog) -> FogState {
+        FogState {
+            from: fog,
+            to: fog,
+            fade_start: Duration::zero(),
+            fade_time: Duration::zero(),
+        }
+    }
+
+    /// Changes the fog, fading from its value at `time` over `fade_time`.
+    pub fn set(&mut self, fog: Fog, time: Duration, fade_time: Duration) {
+        self.from = self.at(time);
+        self.to = fog;
+        self.fade_start = time;
+        self.fade_time = fade_time;
+    }
+
+    /// Returns the fog that will be in effect once any fade finishes.
+    pub fn target(&self) -> Fog {
+        self.to
+    }
+
+    /// Returns the fog in effect at the given time.
+    pub fn at(&self, time: Duration) -> Fog {
+        let elapsed = time - self.fade_start;
+        if elapsed >= self.fade_time {
+            return self.to;
+        }
+
+        let ratio = if elapsed <= Duration::zero() {
+            0.0
+        } else {
+            engine::duration_to_f32(elapsed) / engine::duration_to_f32(self.fade_time)
+        };
+
+        Fog {
+            density: self.from.density + ratio * (self.to.density - self.from.density),
+            color: self.from.color + ratio * (self.to.color - self.from.color),
+        }
+    }
+}
+
+impl std::default::Default for FogState {
+    fn default() -> FogState {
+        FogState::new(Fog::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let current = Fog::default();
+
+        let (fog, time) = Fog::parse(&["0.05"], current).unwrap();
+        assert_eq!(fog.density, 0.05);
+        assert_eq!(fog.color, current.color);
+        assert_eq!(time, Duration::zero());
+
+        let (fog, _) = Fog::parse(&["0.5", "1", "0"], current).unwrap();
+        assert_eq!(fog.density, 0.0);
+        assert_eq!(fog.color, Vector3::new(0.5, 1.0, 0.0));
+
+        let (fog, time) = Fog::parse(&["0.1", "2", "0.5", "0.5", "3"], current).unwrap();
+        assert_eq!(fog.density, 0.1);
+        assert_eq!(fog.color, Vector3::new(1.0, 0.5, 0.5));
+        assert_eq!(time, Duration::seconds(3));
+
+        assert!(Fog::parse(&["thick"], current).is_none());
+        assert!(Fog::parse(&[], current).is_none());
+    }
+
+    #[test]
+    fn test_fade() {
+        let mut state = FogState::default();
+        let dense = Fog {
+            density: 1.0,
+            color: Vector3::new(1.0, 0.0, 0.0),
+        };
+
+        state.set(dense, Duration::seconds(10), Duration::seconds(2));
+        assert_eq!(state.at(Duration::seconds(10)).density, 0.0);
+        assert_eq!(state.at(Duration::seconds(11)).density, 0.5);
+        assert_eq!(state.at(Duration::seconds(12)), dense);
+        assert_eq!(state.target(), dense);
+
+        // an instant change
+        state.set(Fog::default(), Duration::seconds(20), Duration::zero());
+        assert_eq!(state.at(Duration::seconds(20)), Fog::default());
+    }
+}
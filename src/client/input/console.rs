@@ -15,20 +15,32 @@
 // DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use crate::common::console::Console;
 
 use failure::Error;
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode as Key, WindowEvent};
+use winit::event::{
+    ElementState, Event, KeyboardInput, ModifiersState, VirtualKeyCode as Key, WindowEvent,
+};
+
+/// Number of lines scrolled by Page Up and Page Down.
+const SCROLL_LINES: usize = 4;
 
 pub struct ConsoleInput {
     console: Rc<RefCell<Console>>,
+    modifiers: Cell<ModifiersState>,
 }
 
 impl ConsoleInput {
     pub fn new(console: Rc<RefCell<Console>>) -> ConsoleInput {
-        ConsoleInput { console }
+        ConsoleInput {
+            console,
+            modifiers: Cell::new(ModifiersState::empty()),
+        }
     }
 
     pub fn handle_event<T>(&self, event: Event<T>) -> Result<(), Error> {
@@ -36,6 +48,8 @@ impl ConsoleInput {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::ReceivedCharacter(c) => self.console.borrow_mut().send_char(c),
 
+                WindowEvent::ModifiersChanged(modifiers) => self.modifiers.set(modifiers),
+
                 WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
@@ -49,7 +63,14 @@ impl ConsoleInput {
                     Key::Down => self.console.borrow_mut().history_down(),
                     Key::Left => self.console.borrow_mut().cursor_left(),
                     Key::Right => self.console.borrow_mut().cursor_right(),
+                    Key::Home => self.console.borrow_mut().cursor_home(),
+                    Key::End => self.console.borrow_mut().cursor_end(),
+                    Key::PageUp => self.console.borrow_mut().scroll_up(SCROLL_LINES),
+                    Key::PageDown => self.console.borrow_mut().scroll_down(SCROLL_LINES),
                     Key::Grave => self.console.borrow_mut().stuff_text("toggleconsole\n"),
+                    Key::C if self.modifiers.get().ctrl() && self.modifiers.get().shift() => {
+                        self.console.borrow_mut().stuff_text("copy\n")
+                    }
                     _ => (),
                 },
 
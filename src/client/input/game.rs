@@ -17,18 +17,19 @@
 
 use std::{
     cell::{Cell, RefCell},
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     rc::Rc,
     str::FromStr,
     string::ToString,
 };
 
 use crate::common::{
-    console::{CmdRegistry, Console},
+    console::{CmdRegistry, Console, CvarRegistry},
     parse,
 };
 
 use failure::Error;
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 use winit::{
@@ -41,7 +42,7 @@ use winit::{
 
 const ACTION_COUNT: usize = 19;
 
-static INPUT_NAMES: [&'static str; 79] = [
+static INPUT_NAMES: [&'static str; 81] = [
     ",",
     ".",
     "/",
@@ -95,6 +96,8 @@ static INPUT_NAMES: [&'static str; 79] = [
     "MOUSE2",
     "MOUSE3",
     "MWHEELDOWN",
+    "MWHEELLEFT",
+    "MWHEELRIGHT",
     "MWHEELUP",
     "N",
     "O",
@@ -123,7 +126,7 @@ static INPUT_NAMES: [&'static str; 79] = [
     "`",
 ];
 
-static INPUT_VALUES: [BindInput; 79] = [
+static INPUT_VALUES: [BindInput; 81] = [
     BindInput::Key(Key::Comma),
     BindInput::Key(Key::Period),
     BindInput::Key(Key::Slash),
@@ -177,6 +180,8 @@ static INPUT_VALUES: [BindInput; 79] = [
     BindInput::MouseButton(MouseButton::Right),
     BindInput::MouseButton(MouseButton::Middle),
     BindInput::MouseWheel(MouseWheel::Down),
+    BindInput::MouseWheel(MouseWheel::Left),
+    BindInput::MouseWheel(MouseWheel::Right),
     BindInput::MouseWheel(MouseWheel::Up),
     BindInput::Key(Key::N),
     BindInput::Key(Key::O),
@@ -323,35 +328,56 @@ impl ToString for Action {
     }
 }
 
-// for game input, we only care about the direction the mouse wheel moved, not how far it went in
-// one event
-/// A movement of the mouse wheel up or down.
+/// One notch of the mouse wheel in either direction on either axis.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum MouseWheel {
     Up,
     Down,
+    Left,
+    Right,
 }
 
-// TODO: this currently doesn't handle NaN and treats 0.0 as negative which is probably not optimal
-impl ::std::convert::From<MouseScrollDelta> for MouseWheel {
-    fn from(src: MouseScrollDelta) -> MouseWheel {
-        match src {
-            MouseScrollDelta::LineDelta(_, y) => {
-                if y > 0.0 {
-                    MouseWheel::Up
-                } else {
-                    MouseWheel::Down
-                }
-            }
+/// Pixels of smooth scrolling (e.g. on a touchpad) that count as one wheel notch.
+const PIXELS_PER_NOTCH: f64 = 50.0;
+
+/// Converts mouse wheel movement into discrete notches.
+///
+/// Fractional movement, as produced by high-resolution wheels and touchpads, is carried over
+/// until it adds up to a whole notch.
+#[derive(Debug, Default)]
+pub struct WheelAccumulator {
+    x: f64,
+    y: f64,
+}
 
-            MouseScrollDelta::PixelDelta(LogicalPosition { y, .. }) => {
-                if y > 0.0 {
-                    MouseWheel::Up
-                } else {
-                    MouseWheel::Down
-                }
+impl WheelAccumulator {
+    pub fn new() -> WheelAccumulator {
+        WheelAccumulator::default()
+    }
+
+    /// Adds a wheel movement and returns the notches it completed.
+    pub fn notches(&mut self, delta: MouseScrollDelta) -> Vec<MouseWheel> {
+        let (dx, dy) = match delta {
+            MouseScrollDelta::LineDelta(x, y) => (x as f64, y as f64),
+            MouseScrollDelta::PixelDelta(LogicalPosition { x, y }) => {
+                (x / PIXELS_PER_NOTCH, y / PIXELS_PER_NOTCH)
             }
+        };
+
+        let mut notches = Vec::new();
+        for (accum, delta, positive, negative) in [
+            (&mut self.y, dy, MouseWheel::Up, MouseWheel::Down),
+            (&mut self.x, dx, MouseWheel::Right, MouseWheel::Left),
+        ] {
+            *accum += delta;
+            let whole = accum.trunc();
+            *accum -= whole;
+
+            let notch = if whole > 0.0 { positive } else { negative };
+            notches.extend((0..whole.abs() as usize).map(|_| notch));
         }
+
+        notches
     }
 }
 
@@ -386,12 +412,6 @@ impl ::std::convert::From<MouseWheel> for BindInput {
     }
 }
 
-impl ::std::convert::From<MouseScrollDelta> for BindInput {
-    fn from(src: MouseScrollDelta) -> BindInput {
-        BindInput::MouseWheel(MouseWheel::from(src))
-    }
-}
-
 impl FromStr for BindInput {
     type Err = Error;
 
@@ -421,6 +441,349 @@ impl ToString for BindInput {
     }
 }
 
+// inputs are stored by the same names used by `bind`
+impl Serialize for BindInput {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let name = self.to_string();
+        if name.is_empty() {
+            return Err(ser::Error::custom(format!("{:?} has no name", self)));
+        }
+
+        serializer.serialize_str(&name)
+    }
+}
+
+impl<'de> Deserialize<'de> for BindInput {
+    fn deserialize<D>(deserializer: D) -> Result<BindInput, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        BindInput::from_str(&name).map_err(de::Error::custom)
+    }
+}
+
+/// The input context in which a binding applies.
+///
+/// The active context is determined by the top of the input focus stack, see
+/// [`Input`](super::Input).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, EnumIter)]
+pub enum BindContext {
+    /// Playing the game.
+    Game,
+
+    /// Watching a demo. Inputs not bound here fall back to `Game`.
+    Spectator,
+
+    /// Typing in the console.
+    Console,
+
+    /// Navigating the menu.
+    Menu,
+}
+
+impl BindContext {
+    /// Returns the context consulted for inputs that aren't bound in this one.
+    pub fn parent(&self) -> Option<BindContext> {
+        match *self {
+            BindContext::Spectator => Some(BindContext::Game),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for BindContext {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let context = match s.to_lowercase().as_str() {
+            "game" => BindContext::Game,
+            "spectator" => BindContext::Spectator,
+            "console" => BindContext::Console,
+            "menu" => BindContext::Menu,
+            _ => bail!("Invalid bind context: {}", s),
+        };
+
+        Ok(context)
+    }
+}
+
+impl ToString for BindContext {
+    fn to_string(&self) -> String {
+        String::from(match *self {
+            BindContext::Game => "game",
+            BindContext::Spectator => "spectator",
+            BindContext::Console => "console",
+            BindContext::Menu => "menu",
+        })
+    }
+}
+
+impl Serialize for BindContext {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for BindContext {
+    fn deserialize<D>(deserializer: D) -> Result<BindContext, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        BindContext::from_str(&name).map_err(de::Error::custom)
+    }
+}
+
+/// Extracts the bindable input from a window event, if there is one.
+pub fn bind_input_event<T>(event: &Event<T>) -> Option<(BindInput, ElementState)> {
+    match *event {
+        Event::WindowEvent { ref event, .. } => match *event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => Some((key.into(), state)),
+
+            WindowEvent::MouseInput { state, button, .. } => Some((button.into(), state)),
+
+            // wheel movement has to be split into notches, see WheelAccumulator
+            _ => None,
+        },
+
+        _ => None,
+    }
+}
+
+/// A built-in set of default bindings, selected by the `bindings_profile` cvar.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BindingsProfile {
+    /// The original Quake layout: the arrow keys move and turn and Ctrl fires.
+    Classic,
+
+    /// W/A/S/D to move, the mouse to look around and fire.
+    Modern,
+}
+
+impl BindingsProfile {
+    /// Returns whether the mouse looks up and down without holding `+mlook`.
+    pub fn freelook(&self) -> bool {
+        match *self {
+            BindingsProfile::Classic => false,
+            BindingsProfile::Modern => true,
+        }
+    }
+}
+
+impl std::default::Default for BindingsProfile {
+    fn default() -> Self {
+        BindingsProfile::Modern
+    }
+}
+
+impl FromStr for BindingsProfile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let profile = match s.to_lowercase().as_str() {
+            "classic" => BindingsProfile::Classic,
+            "modern" => BindingsProfile::Modern,
+            _ => bail!("Invalid bindings profile: {}", s),
+        };
+
+        Ok(profile)
+    }
+}
+
+impl ToString for BindingsProfile {
+    fn to_string(&self) -> String {
+        String::from(match *self {
+            BindingsProfile::Classic => "classic",
+            BindingsProfile::Modern => "modern",
+        })
+    }
+}
+
+// bindings shared by every profile, as (input, target) pairs in the format of `bind`
+const COMMON_BINDINGS: &[(&str, &str)] = &[
+    ("`", "toggleconsole"),
+    ("ESCAPE", "togglemenu"),
+    ("TAB", "+showscores"),
+    ("SPACE", "+jump"),
+    ("1", "impulse 1"),
+    ("2", "impulse 2"),
+    ("3", "impulse 3"),
+    ("4", "impulse 4"),
+    ("5", "impulse 5"),
+    ("6", "impulse 6"),
+    ("7", "impulse 7"),
+    ("8", "impulse 8"),
+    ("9", "impulse 9"),
+    // next and previous weapon
+    ("MWHEELUP", "impulse 10"),
+    ("MWHEELDOWN", "impulse 12"),
+];
+
+// the layout of Quake's default.cfg
+const CLASSIC_BINDINGS: &[(&str, &str)] = &[
+    ("UPARROW", "+forward"),
+    ("DOWNARROW", "+back"),
+    ("LEFTARROW", "+left"),
+    ("RIGHTARROW", "+right"),
+    (",", "+moveleft"),
+    (".", "+moveright"),
+    ("ALT", "+strafe"),
+    ("SHIFT", "+speed"),
+    ("CTRL", "+attack"),
+    ("ENTER", "+jump"),
+    ("A", "+lookup"),
+    ("Z", "+lookdown"),
+    ("PGDN", "+lookup"),
+    ("DEL", "+lookdown"),
+    ("D", "+moveup"),
+    ("C", "+movedown"),
+    ("MOUSE1", "+attack"),
+    ("MOUSE2", "+forward"),
+    ("MOUSE3", "+mlook"),
+    ("/", "+mlook"),
+];
+
+const MODERN_BINDINGS: &[(&str, &str)] = &[
+    ("W", "+forward"),
+    ("A", "+moveleft"),
+    ("S", "+back"),
+    ("D", "+moveright"),
+    ("C", "+movedown"),
+    ("E", "+use"),
+    ("SHIFT", "+speed"),
+    ("UPARROW", "+lookup"),
+    ("DOWNARROW", "+lookdown"),
+    ("LEFTARROW", "+left"),
+    ("RIGHTARROW", "+right"),
+    ("CTRL", "+attack"),
+    ("MOUSE1", "+attack"),
+    ("MOUSE2", "+jump"),
+];
+
+/// The targets bound to inputs in each context.
+#[derive(Clone, Debug, Default)]
+pub struct Bindings {
+    targets: HashMap<(BindContext, BindInput), BindTarget>,
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings::default()
+    }
+
+    /// Returns the target bound to `input` in `context`, ignoring its parents.
+    pub fn get(&self, key: &(BindContext, BindInput)) -> Option<&BindTarget> {
+        self.targets.get(key)
+    }
+
+    /// Binds `input` in `context`, returning the target it was previously bound to.
+    pub fn insert(
+        &mut self,
+        key: (BindContext, BindInput),
+        target: BindTarget,
+    ) -> Option<BindTarget> {
+        self.targets.insert(key, target)
+    }
+
+    /// Removes all bindings.
+    pub fn clear(&mut self) {
+        self.targets.clear();
+    }
+
+    /// Replaces all bindings with the defaults for `profile`.
+    pub fn assign_defaults(&mut self, profile: BindingsProfile) {
+        self.clear();
+
+        let profile_bindings = match profile {
+            BindingsProfile::Classic => CLASSIC_BINDINGS,
+            BindingsProfile::Modern => MODERN_BINDINGS,
+        };
+
+        for (input, target) in COMMON_BINDINGS.iter().chain(profile_bindings.iter()) {
+            self.insert(
+                (BindContext::Game, BindInput::from_str(input).unwrap()),
+                BindTarget::from_str(target).unwrap(),
+            );
+        }
+
+        self.insert(
+            (BindContext::Console, BindInput::Key(Key::Escape)),
+            BindTarget::from_str("toggleconsole").unwrap(),
+        );
+    }
+}
+
+/// Bindings serialize as a map from each context to a map from input names to targets, e.g.
+/// `{"game": {"UPARROW": "+forward"}}`, sorted by name.
+impl Serialize for Bindings {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut contexts: BTreeMap<String, BTreeMap<String, &BindTarget>> = BTreeMap::new();
+        for ((context, input), target) in self.targets.iter() {
+            let name = input.to_string();
+            if name.is_empty() {
+                return Err(ser::Error::custom(format!("{:?} has no name", input)));
+            }
+
+            contexts
+                .entry(context.to_string())
+                .or_default()
+                .insert(name, target);
+        }
+
+        contexts.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bindings {
+    fn deserialize<D>(deserializer: D) -> Result<Bindings, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let contexts: HashMap<BindContext, HashMap<BindInput, BindTarget>> =
+            HashMap::deserialize(deserializer)?;
+
+        let mut bindings = Bindings::new();
+        for (context, inputs) in contexts {
+            for (input, target) in inputs {
+                bindings.insert((context, input), target);
+            }
+        }
+
+        Ok(bindings)
+    }
+}
+
+/// Looks up the binding for `input` in `context` and then in its parents.
+fn binding_in(bindings: &Bindings, context: BindContext, input: BindInput) -> Option<BindTarget> {
+    let mut context = Some(context);
+    while let Some(c) = context {
+        if let Some(target) = bindings.get(&(c, input)) {
+            return Some(target.clone());
+        }
+        context = c.parent();
+    }
+
+    None
+}
+
 /// An operation to perform when a `BindInput` is received.
 #[derive(Clone, Debug)]
 pub enum BindTarget {
@@ -474,11 +837,38 @@ impl ToString for BindTarget {
     }
 }
 
+// unlike `to_string`, console input is stored without quotes
+impl Serialize for BindTarget {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self {
+            BindTarget::Action { .. } => serializer.serialize_str(&self.to_string()),
+            BindTarget::ConsoleInput { ref text } => serializer.serialize_str(text),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BindTarget {
+    fn deserialize<D>(deserializer: D) -> Result<BindTarget, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        BindTarget::from_str(&text).map_err(de::Error::custom)
+    }
+}
+
 #[derive(Clone)]
 pub struct GameInput {
     console: Rc<RefCell<Console>>,
-    bindings: Rc<RefCell<HashMap<BindInput, BindTarget>>>,
+    bindings: Rc<RefCell<Bindings>>,
+    context: BindContext,
     action_states: Rc<RefCell<[bool; ACTION_COUNT]>>,
+    // set when an action is triggered and cleared by refresh(), so that actions which are
+    // pressed and released within one frame (e.g. by the mouse wheel) are still seen
+    action_latches: Rc<RefCell<[bool; ACTION_COUNT]>>,
     mouse_delta: (f64, f64),
     impulse: Rc<Cell<u8>>,
 }
@@ -487,13 +877,30 @@ impl GameInput {
     pub fn new(console: Rc<RefCell<Console>>) -> GameInput {
         GameInput {
             console,
-            bindings: Rc::new(RefCell::new(HashMap::new())),
+            bindings: Rc::new(RefCell::new(Bindings::new())),
+            context: BindContext::Game,
             action_states: Rc::new(RefCell::new([false; ACTION_COUNT])),
+            action_latches: Rc::new(RefCell::new([false; ACTION_COUNT])),
             mouse_delta: (0.0, 0.0),
             impulse: Rc::new(Cell::new(0)),
         }
     }
 
+    pub fn context(&self) -> BindContext {
+        self.context
+    }
+
+    /// Switches the context used to look up bindings.
+    ///
+    /// All actions are released, since the inputs holding them may not be bound in the new
+    /// context.
+    pub fn set_context(&mut self, context: BindContext) {
+        if context != self.context {
+            self.context = context;
+            *self.action_states.borrow_mut() = [false; ACTION_COUNT];
+        }
+    }
+
     pub fn mouse_delta(&self) -> (f64, f64) {
         self.mouse_delta
     }
@@ -502,117 +909,111 @@ impl GameInput {
         self.impulse.get()
     }
 
-    /// Bind the default controls.
-    pub fn bind_defaults(&mut self) {
-        self.bind(Key::W, BindTarget::from_str("+forward").unwrap());
-        self.bind(Key::A, BindTarget::from_str("+moveleft").unwrap());
-        self.bind(Key::S, BindTarget::from_str("+back").unwrap());
-        self.bind(Key::D, BindTarget::from_str("+moveright").unwrap());
-        self.bind(Key::Space, BindTarget::from_str("+jump").unwrap());
-        self.bind(Key::Up, BindTarget::from_str("+lookup").unwrap());
-        self.bind(Key::Left, BindTarget::from_str("+left").unwrap());
-        self.bind(Key::Down, BindTarget::from_str("+lookdown").unwrap());
-        self.bind(Key::Right, BindTarget::from_str("+right").unwrap());
-        self.bind(Key::LControl, BindTarget::from_str("+attack").unwrap());
-        self.bind(Key::E, BindTarget::from_str("+use").unwrap());
-        self.bind(Key::Grave, BindTarget::from_str("toggleconsole").unwrap());
-        self.bind(Key::Key1, BindTarget::from_str("impulse 1").unwrap());
-        self.bind(Key::Key2, BindTarget::from_str("impulse 2").unwrap());
-        self.bind(Key::Key3, BindTarget::from_str("impulse 3").unwrap());
-        self.bind(Key::Key4, BindTarget::from_str("impulse 4").unwrap());
-        self.bind(Key::Key5, BindTarget::from_str("impulse 5").unwrap());
-        self.bind(Key::Key6, BindTarget::from_str("impulse 6").unwrap());
-        self.bind(Key::Key7, BindTarget::from_str("impulse 7").unwrap());
-        self.bind(Key::Key8, BindTarget::from_str("impulse 8").unwrap());
-        self.bind(Key::Key9, BindTarget::from_str("impulse 9").unwrap());
-    }
-
-    /// Bind a `BindInput` to a `BindTarget`.
+    /// Replaces all bindings with the defaults for `profile`.
+    pub fn bind_defaults(&mut self, profile: BindingsProfile) {
+        self.bindings.borrow_mut().assign_defaults(profile);
+    }
+
+    /// Bind a `BindInput` to a `BindTarget` in the game context.
     pub fn bind<I, T>(&mut self, input: I, target: T) -> Option<BindTarget>
+    where
+        I: Into<BindInput>,
+        T: Into<BindTarget>,
+    {
+        self.bind_in(BindContext::Game, input, target)
+    }
+
+    /// Bind a `BindInput` to a `BindTarget` in the specified context.
+    pub fn bind_in<I, T>(&mut self, context: BindContext, input: I, target: T) -> Option<BindTarget>
     where
         I: Into<BindInput>,
         T: Into<BindTarget>,
     {
         self.bindings
             .borrow_mut()
-            .insert(input.into(), target.into())
+            .insert((context, input.into()), target.into())
     }
 
-    /// Return the `BindTarget` that `input` is bound to, or `None` if `input` is not present.
+    /// Return the `BindTarget` that `input` is bound to in the game context, or `None` if `input`
+    /// is not present.
     pub fn binding<I>(&self, input: I) -> Option<BindTarget>
     where
         I: Into<BindInput>,
     {
-        self.bindings.borrow().get(&input.into()).map(|t| t.clone())
+        binding_in(&self.bindings.borrow(), BindContext::Game, input.into())
     }
 
     pub fn handle_event<T>(&mut self, outer_event: Event<T>) {
-        let (input, state): (BindInput, _) = match outer_event {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            state,
-                            virtual_keycode: Some(key),
-                            ..
-                        },
-                    ..
-                } => (key.into(), state),
-
-                WindowEvent::MouseInput { state, button, .. } => (button.into(), state),
-                WindowEvent::MouseWheel { delta, .. } => (delta.into(), ElementState::Pressed),
-                _ => return,
-            },
-
-            Event::DeviceEvent { event, .. } => match event {
-                DeviceEvent::MouseMotion { delta } => {
-                    self.mouse_delta.0 += delta.0;
-                    self.mouse_delta.1 += delta.1;
-                    return;
-                }
-
-                _ => return,
-            },
-
-            _ => return,
-        };
+        if let Event::DeviceEvent {
+            event: DeviceEvent::MouseMotion { delta },
+            ..
+        } = outer_event
+        {
+            self.mouse_delta.0 += delta.0;
+            self.mouse_delta.1 += delta.1;
+            return;
+        }
 
-        self.handle_input(input, state);
+        if let Some((input, state)) = bind_input_event(&outer_event) {
+            self.handle_input(input, state);
+        }
     }
 
-    pub fn handle_input<I>(&mut self, input: I, state: ElementState)
+    /// Performs the operation bound to `input` in the current context.
+    ///
+    /// Returns `false` if `input` is not bound.
+    pub fn handle_input<I>(&mut self, input: I, state: ElementState) -> bool
     where
         I: Into<BindInput>,
     {
         let bind_input = input.into();
 
         // debug!("handle input {:?}: {:?}", &bind_input, state);
-        if let Some(target) = self.bindings.borrow().get(&bind_input) {
-            match *target {
-                BindTarget::Action { trigger, action } => {
-                    self.action_states.borrow_mut()[action as usize] = state == trigger;
-                    debug!(
-                        "{}{}",
-                        if state == trigger { '+' } else { '-' },
-                        action.to_string()
-                    );
+        let target = match binding_in(&self.bindings.borrow(), self.context, bind_input) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        match target {
+            BindTarget::Action { trigger, action } => {
+                self.action_states.borrow_mut()[action as usize] = state == trigger;
+                if state == trigger {
+                    self.action_latches.borrow_mut()[action as usize] = true;
                 }
+                debug!(
+                    "{}{}",
+                    if state == trigger { '+' } else { '-' },
+                    action.to_string()
+                );
+            }
 
-                BindTarget::ConsoleInput { ref text } => {
-                    if state == ElementState::Pressed {
-                        self.console.borrow_mut().stuff_text(text);
-                    }
+            BindTarget::ConsoleInput { ref text } => {
+                if state == ElementState::Pressed {
+                    self.console.borrow_mut().stuff_text(text);
                 }
             }
         }
+
+        true
     }
 
+    /// Performs the operation bound to one notch of the mouse wheel.
+    ///
+    /// Each notch is a separate press and release of its input. Returns `false` if the notch is
+    /// not bound.
+    pub fn handle_wheel(&mut self, wheel: MouseWheel) -> bool {
+        self.handle_input(wheel, ElementState::Pressed)
+            && self.handle_input(wheel, ElementState::Released)
+    }
+
+    /// Returns whether `action` is active or was triggered since the last `refresh`.
     pub fn action_state(&self, action: Action) -> bool {
         self.action_states.borrow()[action as usize]
+            || self.action_latches.borrow()[action as usize]
     }
 
     // TODO: roll actions into a loop
-    pub fn register_cmds(&self, cmds: &mut CmdRegistry) {
+    pub fn register_cmds(&self, cmds: &mut CmdRegistry, cvars: Rc<RefCell<CvarRegistry>>) {
         let states = [("+", true), ("-", false)];
         for action in Action::iter() {
             for (state_str, state_bool) in states.iter().cloned() {
@@ -638,7 +1039,7 @@ impl GameInput {
                     // bind (key)
                     // queries what (key) is bound to, if anything
                     1 => match BindInput::from_str(args[0]) {
-                        Ok(i) => match bindings.borrow().get(&i) {
+                        Ok(i) => match bindings.borrow().get(&(BindContext::Game, i)) {
                             Some(t) => format!("\"{}\" = \"{}\"", i.to_string(), t.to_string()),
                             None => format!("\"{}\" is not bound", i.to_string()),
                         },
@@ -650,7 +1051,9 @@ impl GameInput {
                     2 => match BindInput::from_str(args[0]) {
                         Ok(input) => match BindTarget::from_str(args[1]) {
                             Ok(target) => {
-                                bindings.borrow_mut().insert(input, target);
+                                bindings
+                                    .borrow_mut()
+                                    .insert((BindContext::Game, input), target);
                                 debug!("Bound {:?} to {:?}", input, args[1]);
                                 String::new()
                             }
@@ -668,13 +1071,60 @@ impl GameInput {
         )
         .unwrap();
 
+        // "bindctx"
+        let bindings = self.bindings.clone();
+        cmds.insert_or_replace(
+            "bindctx",
+            Box::new(move |args| {
+                if !(2..=3).contains(&args.len()) {
+                    return "bindctx [context] [key] (command): attach a command to a key in \
+                            the game, spectator, console or menu context"
+                        .to_owned();
+                }
+
+                let context = match BindContext::from_str(args[0]) {
+                    Ok(c) => c,
+                    Err(_) => return format!("\"{}\" isn't a valid bind context", args[0]),
+                };
+                let input = match BindInput::from_str(args[1]) {
+                    Ok(i) => i,
+                    Err(_) => return format!("\"{}\" isn't a valid key", args[1]),
+                };
+
+                match args.get(2) {
+                    None => match bindings.borrow().get(&(context, input)) {
+                        Some(t) => format!(
+                            "{} \"{}\" = \"{}\"",
+                            context.to_string(),
+                            input.to_string(),
+                            t.to_string()
+                        ),
+                        None => format!(
+                            "\"{}\" is not bound in {}",
+                            input.to_string(),
+                            context.to_string()
+                        ),
+                    },
+
+                    Some(target_str) => match BindTarget::from_str(target_str) {
+                        Ok(target) => {
+                            bindings.borrow_mut().insert((context, input), target);
+                            String::new()
+                        }
+                        Err(_) => format!("\"{}\" isn't a valid bind target", target_str),
+                    },
+                }
+            }),
+        )
+        .unwrap();
+
         // "unbindall"
         let bindings = self.bindings.clone();
         cmds.insert_or_replace(
             "unbindall",
             Box::new(move |args| match args.len() {
                 0 => {
-                    let _ = bindings.replace(HashMap::new());
+                    bindings.borrow_mut().clear();
                     String::new()
                 }
                 _ => "unbindall: delete all keybindings".to_owned(),
@@ -682,6 +1132,30 @@ impl GameInput {
         )
         .unwrap();
 
+        // "resetbindings"
+        let bindings = self.bindings.clone();
+        cmds.insert_or_replace(
+            "resetbindings",
+            Box::new(move |args| {
+                if !args.is_empty() {
+                    return "resetbindings: restore the default bindings for bindings_profile"
+                        .to_owned();
+                }
+
+                let name = cvars.borrow().get("bindings_profile").unwrap_or_default();
+                let profile = match BindingsProfile::from_str(&name) {
+                    Ok(p) => p,
+                    Err(_) => return format!("\"{}\" isn't a valid bindings profile", name),
+                };
+
+                bindings.borrow_mut().assign_defaults(profile);
+                let freelook = if profile.freelook() { "1" } else { "0" };
+                let _ = cvars.borrow().set("freelook", freelook);
+                String::new()
+            }),
+        )
+        .unwrap();
+
         // "impulse"
         let impulse = self.impulse.clone();
         cmds.insert_or_replace(
@@ -708,11 +1182,10 @@ impl GameInput {
     pub fn refresh(&mut self) {
         self.clear_mouse();
         self.clear_impulse();
+        *self.action_latches.borrow_mut() = [false; ACTION_COUNT];
     }
 
     fn clear_mouse(&mut self) {
-        self.handle_input(MouseWheel::Up, ElementState::Released);
-        self.handle_input(MouseWheel::Down, ElementState::Released);
         self.mouse_delta = (0.0, 0.0);
     }
 
@@ -740,4 +1213,120 @@ mod test {
 
         assert_eq!(target.to_string(), "+forward");
     }
+
+    #[test]
+    fn test_binding_context_fallback() {
+        let mut bindings = Bindings::new();
+        bindings.insert(
+            (BindContext::Game, BindInput::Key(Key::Up)),
+            BindTarget::from_str("+forward").unwrap(),
+        );
+        bindings.insert(
+            (BindContext::Menu, BindInput::Key(Key::F1)),
+            BindTarget::from_str("help").unwrap(),
+        );
+
+        let up = BindInput::Key(Key::Up);
+        assert!(binding_in(&bindings, BindContext::Game, up).is_some());
+        assert!(binding_in(&bindings, BindContext::Spectator, up).is_some());
+        assert!(binding_in(&bindings, BindContext::Menu, up).is_none());
+        assert!(binding_in(&bindings, BindContext::Game, BindInput::Key(Key::F1)).is_none());
+    }
+
+    #[test]
+    fn test_assign_defaults() {
+        let mut bindings = Bindings::new();
+        bindings.insert(
+            (BindContext::Game, BindInput::Key(Key::F1)),
+            BindTarget::from_str("help").unwrap(),
+        );
+
+        let forward = |bindings: &Bindings, key| {
+            binding_in(bindings, BindContext::Game, BindInput::Key(key))
+                .map(|t| t.to_string())
+                .as_deref()
+                == Some("+forward")
+        };
+
+        bindings.assign_defaults(BindingsProfile::Modern);
+        assert!(forward(&bindings, Key::W));
+        assert!(!forward(&bindings, Key::Up));
+        assert!(binding_in(&bindings, BindContext::Game, BindInput::Key(Key::F1)).is_none());
+
+        bindings.assign_defaults(BindingsProfile::Classic);
+        assert!(forward(&bindings, Key::Up));
+        assert!(!forward(&bindings, Key::W));
+        assert!(binding_in(&bindings, BindContext::Console, BindInput::Key(Key::Escape)).is_some());
+    }
+
+    #[test]
+    fn test_bindings_profile_from_str() {
+        assert_eq!(
+            BindingsProfile::from_str("Classic").unwrap(),
+            BindingsProfile::Classic
+        );
+        assert_eq!(
+            BindingsProfile::from_str(&BindingsProfile::Modern.to_string()).unwrap(),
+            BindingsProfile::Modern
+        );
+        assert!(BindingsProfile::from_str("quake2").is_err());
+    }
+
+    #[test]
+    fn test_wheel_notches() {
+        let mut wheel = WheelAccumulator::new();
+        assert_eq!(
+            wheel.notches(MouseScrollDelta::LineDelta(0.0, 2.0)),
+            vec![MouseWheel::Up, MouseWheel::Up]
+        );
+        assert_eq!(
+            wheel.notches(MouseScrollDelta::LineDelta(-1.0, 0.0)),
+            vec![MouseWheel::Left]
+        );
+
+        // fractional movement carries over
+        assert!(wheel
+            .notches(MouseScrollDelta::LineDelta(0.0, -0.6))
+            .is_empty());
+        assert_eq!(
+            wheel.notches(MouseScrollDelta::LineDelta(0.0, -0.6)),
+            vec![MouseWheel::Down]
+        );
+    }
+
+    #[test]
+    fn test_wheel_bind_input_names() {
+        assert_eq!(
+            BindInput::from_str("mwheelright").unwrap(),
+            BindInput::MouseWheel(MouseWheel::Right)
+        );
+        assert_eq!(
+            BindInput::MouseWheel(MouseWheel::Left).to_string(),
+            "MWHEELLEFT"
+        );
+    }
+
+    #[test]
+    fn test_bindings_serde() {
+        let mut bindings = Bindings::new();
+        bindings.insert(
+            (BindContext::Game, BindInput::Key(Key::Up)),
+            BindTarget::from_str("+forward").unwrap(),
+        );
+        bindings.insert(
+            (BindContext::Menu, BindInput::Key(Key::F1)),
+            BindTarget::from_str("help").unwrap(),
+        );
+
+        let json = serde_json::to_string(&bindings).unwrap();
+        assert_eq!(
+            json,
+            r#"{"game":{"UPARROW":"+forward"},"menu":{"F1":"help"}}"#
+        );
+
+        let parsed: Bindings = serde_json::from_str(&json).unwrap();
+        let up = binding_in(&parsed, BindContext::Game, BindInput::Key(Key::Up));
+        assert_eq!(up.map(|t| t.to_string()).as_deref(), Some("+forward"));
+        assert!(serde_json::from_str::<Bindings>(r#"{"game":{"NOTAKEY":"help"}}"#).is_err());
+    }
 }
@@ -35,7 +35,12 @@ impl MenuInput {
     pub fn handle_event<T>(&self, event: Event<T>) -> Result<(), Error> {
         match event {
             Event::WindowEvent { event, .. } => match event {
-                WindowEvent::ReceivedCharacter(_) => (),
+                WindowEvent::ReceivedCharacter(c) => {
+                    // editing keys are handled as key presses below
+                    if !c.is_control() {
+                        self.menu.borrow().insert(c)?;
+                    }
+                }
 
                 WindowEvent::KeyboardInput {
                     input:
@@ -56,9 +61,13 @@ impl MenuInput {
 
                     Key::Up => self.menu.borrow().prev()?,
                     Key::Down => self.menu.borrow().next()?,
-                    Key::Return => self.menu.borrow().activate()?,
+                    Key::Return => self.menu.borrow_mut().activate()?,
                     Key::Left => self.menu.borrow().left()?,
                     Key::Right => self.menu.borrow().right()?,
+                    Key::Back => self.menu.borrow().backspace()?,
+                    Key::Delete => self.menu.borrow().delete()?,
+                    Key::Home => self.menu.borrow().home()?,
+                    Key::End => self.menu.borrow().end()?,
 
                     _ => (),
                 },
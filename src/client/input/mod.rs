@@ -23,7 +23,7 @@ use std::{cell::RefCell, rc::Rc};
 
 use crate::{
     client::menu::Menu,
-    common::console::{CmdRegistry, Console},
+    common::console::{CmdRegistry, Console, CvarRegistry},
 };
 
 use failure::Error;
@@ -31,7 +31,10 @@ use winit::event::{Event, WindowEvent};
 
 use self::{
     console::ConsoleInput,
-    game::{BindInput, BindTarget, GameInput},
+    game::{
+        bind_input_event, BindContext, BindInput, BindTarget, BindingsProfile, GameInput,
+        WheelAccumulator,
+    },
     menu::MenuInput,
 };
 
@@ -42,9 +45,17 @@ pub enum InputFocus {
     Menu,
 }
 
+/// Routes window events to the game, console or menu.
+///
+/// Focus is managed as a stack: opening the console or menu pushes it on top of whatever had
+/// focus, and closing it returns focus to what was underneath. Bindings are looked up in the
+/// context corresponding to the top of the stack, so the same key can open the menu in game and
+/// close it in the menu.
 pub struct Input {
     window_focused: bool,
-    focus: InputFocus,
+    focus_stack: Vec<InputFocus>,
+    spectator: bool,
+    wheel: WheelAccumulator,
 
     game_input: GameInput,
     console_input: ConsoleInput,
@@ -57,14 +68,19 @@ impl Input {
         console: Rc<RefCell<Console>>,
         menu: Rc<RefCell<Menu>>,
     ) -> Input {
-        Input {
+        let mut input = Input {
             window_focused: true,
-            focus: init_focus,
+            focus_stack: vec![init_focus],
+            spectator: false,
+            wheel: WheelAccumulator::new(),
 
             game_input: GameInput::new(console.clone()),
             console_input: ConsoleInput::new(console.clone()),
             menu_input: MenuInput::new(menu.clone(), console.clone()),
-        }
+        };
+
+        input.update_context();
+        input
     }
 
     pub fn handle_event<T>(&mut self, event: Event<T>) -> Result<(), Error> {
@@ -75,12 +91,33 @@ impl Input {
                 ..
             } => self.window_focused = focused,
 
+            // each notch is bound separately in every context
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                ..
+            } => {
+                let notches = self.wheel.notches(delta);
+                if self.window_focused {
+                    for wheel in notches {
+                        self.game_input.handle_wheel(wheel);
+                    }
+                }
+            }
+
             _ => {
                 if self.window_focused {
-                    match self.focus {
+                    match self.focus() {
                         InputFocus::Game => self.game_input.handle_event(event),
-                        InputFocus::Console => self.console_input.handle_event(event)?,
-                        InputFocus::Menu => self.menu_input.handle_event(event)?,
+                        InputFocus::Console => {
+                            if !self.handle_bound_event(&event) {
+                                self.console_input.handle_event(event)?;
+                            }
+                        }
+                        InputFocus::Menu => {
+                            if !self.handle_bound_event(&event) {
+                                self.menu_input.handle_event(event)?;
+                            }
+                        }
                     }
                 }
             }
@@ -89,12 +126,90 @@ impl Input {
         Ok(())
     }
 
+    // explicit bindings override the built-in console and menu controls
+    fn handle_bound_event<T>(&mut self, event: &Event<T>) -> bool {
+        match bind_input_event(event) {
+            Some((input, state)) => self.game_input.handle_input(input, state),
+            None => false,
+        }
+    }
+
+    /// Returns whether the window has keyboard focus.
+    pub fn window_focused(&self) -> bool {
+        self.window_focused
+    }
+
+    /// Returns the input focus at the top of the focus stack.
     pub fn focus(&self) -> InputFocus {
-        self.focus
+        *self.focus_stack.last().unwrap()
     }
 
+    /// Replaces the whole focus stack with `new_focus`.
     pub fn set_focus(&mut self, new_focus: InputFocus) {
-        self.focus = new_focus;
+        self.focus_stack.clear();
+        self.focus_stack.push(new_focus);
+        self.update_context();
+    }
+
+    /// Gives focus to `new_focus`, keeping the current focus underneath it.
+    pub fn push_focus(&mut self, new_focus: InputFocus) {
+        if self.focus() != new_focus {
+            self.focus_stack.push(new_focus);
+            self.update_context();
+        }
+    }
+
+    /// Returns focus to whatever had it before the current focus was pushed.
+    ///
+    /// The bottom of the stack is never popped. Returns the removed focus, if any.
+    pub fn pop_focus(&mut self) -> Option<InputFocus> {
+        if self.focus_stack.len() > 1 {
+            let popped = self.focus_stack.pop();
+            self.update_context();
+            popped
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether `focus` is anywhere on the focus stack.
+    pub fn has_focus(&self, focus: InputFocus) -> bool {
+        self.focus_stack.contains(&focus)
+    }
+
+    /// Pops the focus stack until `focus` is at the top.
+    ///
+    /// Does nothing if `focus` is not on the stack.
+    pub fn unwind_to(&mut self, focus: InputFocus) {
+        if self.has_focus(focus) {
+            while self.focus() != focus {
+                self.focus_stack.pop();
+            }
+            self.update_context();
+        }
+    }
+
+    /// Sets whether the player is watching rather than playing, e.g. during demo playback.
+    pub fn set_spectator(&mut self, spectator: bool) {
+        if self.spectator != spectator {
+            self.spectator = spectator;
+            self.update_context();
+        }
+    }
+
+    /// Returns the context in which bindings are currently looked up.
+    pub fn bind_context(&self) -> BindContext {
+        match self.focus() {
+            InputFocus::Game if self.spectator => BindContext::Spectator,
+            InputFocus::Game => BindContext::Game,
+            InputFocus::Console => BindContext::Console,
+            InputFocus::Menu => BindContext::Menu,
+        }
+    }
+
+    fn update_context(&mut self) {
+        let context = self.bind_context();
+        self.game_input.set_context(context);
     }
 
     /// Bind a `BindInput` to a `BindTarget`.
@@ -106,12 +221,13 @@ impl Input {
         self.game_input.bind(input, target)
     }
 
-    pub fn bind_defaults(&mut self) {
-        self.game_input.bind_defaults();
+    /// Replaces all bindings with the defaults for `profile`.
+    pub fn bind_defaults(&mut self, profile: BindingsProfile) {
+        self.game_input.bind_defaults(profile);
     }
 
     pub fn game_input(&self) -> Option<&GameInput> {
-        if let InputFocus::Game = self.focus {
+        if let InputFocus::Game = self.focus() {
             Some(&self.game_input)
         } else {
             None
@@ -119,14 +235,14 @@ impl Input {
     }
 
     pub fn game_input_mut(&mut self) -> Option<&mut GameInput> {
-        if let InputFocus::Game = self.focus {
+        if let InputFocus::Game = self.focus() {
             Some(&mut self.game_input)
         } else {
             None
         }
     }
 
-    pub fn register_cmds(&self, cmds: &mut CmdRegistry) {
-        self.game_input.register_cmds(cmds);
+    pub fn register_cmds(&self, cmds: &mut CmdRegistry, cvars: Rc<RefCell<CvarRegistry>>) {
+        self.game_input.register_cmds(cmds, cvars);
     }
 }
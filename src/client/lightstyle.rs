@@ -0,0 +1,227 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Animated light styles.
+//!
+//! Each light style is a string of characters from `a` (dark) to `z` (double brightness), set by
+//! the server with `svc_lightstyle`, e.g. `mmnmmommommnonmmonqnmmo` for a flickering light. The
+//! animation advances one character every tenth of a second. Styles 32 and up are switchable
+//! lights, which the server toggles by replacing their strings.
+
+use crate::common::{engine, limits::Limits};
+
+use arrayvec::ArrayVec;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+
+/// The brightness of every light style any protocol can address, indexed by style ID.
+pub type LightStyleValues = ArrayVec<f32, { Limits::EXTENDED.light_styles }>;
+
+/// The number of pattern characters played per second.
+const FRAMES_PER_SECOND: f32 = 10.0;
+
+// 'z' - 'a' = 25, so divide by 12.5 to get range [0, 2]
+const CHAR_SCALE: f32 = 12.5;
+
+// brightness of an unset or empty style
+const NORMAL_VALUE: f32 = 1.0;
+
+// brightness changes of at least this much are treated as deliberate flicker
+const ABRUPT_CHANGE: u8 = (b'm' - b'a') / 2;
+
+/// How light styles blend between pattern characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightStyleLerp {
+    /// Each character is held for its whole frame, as in the original engine.
+    None,
+
+    /// Gradual changes are blended, but abrupt ones (e.g. flickering lights) are kept sharp.
+    Smooth,
+
+    /// Every change is blended.
+    All,
+}
+
+impl LightStyleLerp {
+    /// Interprets the value of the `r_lerplightstyles` cvar.
+    pub fn from_cvar(value: f32) -> LightStyleLerp {
+        if value >= 2.0 {
+            LightStyleLerp::All
+        } else if value >= 1.0 {
+            LightStyleLerp::Smooth
+        } else {
+            LightStyleLerp::None
+        }
+    }
+}
+
+/// The patterns of all light styles.
+///
+/// These serialize as a list of pattern strings indexed by style ID, as in the original savegames.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<String>", into = "Vec<String>")]
+pub struct LightStyles {
+    // pattern characters as offsets from 'a', indexed by style ID
+    patterns: Vec<Vec<u8>>,
+}
+
+impl LightStyles {
+    pub fn new() -> LightStyles {
+        LightStyles {
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Sets the pattern of a light style.
+    ///
+    /// Characters outside `a..=z` are clamped into that range. The caller is responsible for
+    /// checking `id` against the connection's `Limits`.
+    pub fn set(&mut self, id: usize, pattern: &str) {
+        let pattern = pattern
+            .bytes()
+            .map(|c| c.clamp(b'a', b'z') - b'a')
+            .collect();
+
+        if self.patterns.len() <= id {
+            self.patterns.resize(id + 1, Vec::new());
+        }
+        self.patterns[id] = pattern;
+    }
+
+    /// Evaluates the brightness of every light style at `time`.
+    pub fn values(&self, time: Duration, lerp: LightStyleLerp) -> LightStyleValues {
+        let frame_time = engine::duration_to_f32(time) * FRAMES_PER_SECOND;
+        let frame = frame_time as usize;
+        let frac = frame_time.fract();
+
+        (0..Limits::EXTENDED.light_styles)
+            .map(|id| self.patterns.get(id).map_or(&[][..], |p| p.as_slice()))
+            .map(|pattern| match pattern.len() {
+                0 => NORMAL_VALUE,
+                len => {
+                    let cur = pattern[frame % len];
+                    let next = pattern[(frame + 1) % len];
+
+                    let blend = match lerp {
+                        LightStyleLerp::None => false,
+                        LightStyleLerp::Smooth => {
+                            (cur as i32 - next as i32).abs() < ABRUPT_CHANGE as i32
+                        }
+                        LightStyleLerp::All => true,
+                    };
+
+                    let value = if blend {
+                        cur as f32 + (next as f32 - cur as f32) * frac
+                    } else {
+                        cur as f32
+                    };
+
+                    value / CHAR_SCALE
+                }
+            })
+            .collect()
+    }
+}
+
+impl From<Vec<String>> for LightStyles {
+    fn from(patterns: Vec<String>) -> LightStyles {
+        let mut styles = LightStyles::new();
+        for (id, pattern) in patterns.iter().enumerate() {
+            styles.set(id, pattern);
+        }
+        styles
+    }
+}
+
+impl From<LightStyles> for Vec<String> {
+    fn from(styles: LightStyles) -> Vec<String> {
+        styles
+            .patterns
+            .into_iter()
+            .map(|p| p.into_iter().map(|c| (b'a' + c) as char).collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value_at(styles: &LightStyles, id: usize, ms: i64, lerp: LightStyleLerp) -> f32 {
+        styles.values(Duration::milliseconds(ms), lerp)[id]
+    }
+
+    #[test]
+    fn test_unset_style_is_normal() {
+        let styles = LightStyles::new();
+        assert_eq!(value_at(&styles, 0, 0, LightStyleLerp::All), 1.0);
+        assert_eq!(value_at(&styles, 63, 0, LightStyleLerp::All), 1.0);
+    }
+
+    #[test]
+    fn test_pattern_frames() {
+        let mut styles = LightStyles::new();
+        styles.set(1, "az");
+
+        assert_eq!(value_at(&styles, 1, 0, LightStyleLerp::None), 0.0);
+        assert_eq!(value_at(&styles, 1, 150, LightStyleLerp::None), 2.0);
+        // wraps around
+        assert_eq!(value_at(&styles, 1, 250, LightStyleLerp::None), 0.0);
+    }
+
+    #[test]
+    fn test_lerp() {
+        let mut styles = LightStyles::new();
+        styles.set(0, "mo");
+        styles.set(1, "az");
+
+        // gradual changes are blended
+        let v = value_at(&styles, 0, 50, LightStyleLerp::Smooth);
+        assert!((v - 13.0 / CHAR_SCALE).abs() < 1e-4);
+
+        // abrupt changes are only blended when forced
+        assert_eq!(value_at(&styles, 1, 50, LightStyleLerp::Smooth), 0.0);
+        let v = value_at(&styles, 1, 50, LightStyleLerp::All);
+        assert!((v - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_switchable_style() {
+        let mut styles = LightStyles::new();
+        styles.set(32, "a");
+        assert_eq!(value_at(&styles, 32, 0, LightStyleLerp::None), 0.0);
+        styles.set(32, "m");
+        assert_eq!(
+            value_at(&styles, 32, 0, LightStyleLerp::None),
+            12.0 / CHAR_SCALE
+        );
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut styles = LightStyles::new();
+        styles.set(0, "m");
+        styles.set(2, "mmnmmommommnonmmonqnmmo");
+
+        let json = serde_json::to_string(&styles).unwrap();
+        assert_eq!(json, r#"["m","","mmnmmommommnonmmonqnmmo"]"#);
+        assert_eq!(serde_json::from_str::<LightStyles>(&json).unwrap(), styles);
+    }
+}
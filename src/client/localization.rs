@@ -0,0 +1,173 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Localized game strings from the 2021 rerelease.
+//!
+//! The rerelease's progs send many of their messages as references like `$qc_need_gold_key`
+//! rather than as text. The text for each reference is found in `localization/loc_english.txt`,
+//! which is shipped in `QuakeEX.kpf`. Each entry in that file has the form `key = "text"`, where
+//! the text may span several lines and use `\n`, `\t`, `\"` and `\\` escapes.
+
+use std::{borrow::Cow, collections::HashMap, io::Read};
+
+use crate::common::vfs::Vfs;
+
+const LOCALIZATION_FILE: &str = "localization/loc_english.txt";
+
+/// A table of localized strings.
+#[derive(Clone, Debug, Default)]
+pub struct Localization {
+    strings: HashMap<String, String>,
+}
+
+impl Localization {
+    /// Returns an empty table, which leaves all messages unchanged.
+    pub fn new() -> Localization {
+        Localization::default()
+    }
+
+    /// Loads the localized strings, if the rerelease's localization file is present.
+    pub fn load(vfs: &Vfs) -> Localization {
+        let mut text = String::new();
+        match vfs.open(LOCALIZATION_FILE) {
+            Ok(mut file) => {
+                if let Err(e) = file.read_to_string(&mut text) {
+                    warn!("Couldn't read {}: {}", LOCALIZATION_FILE, e);
+                }
+            }
+            Err(_) => return Localization::new(),
+        }
+
+        let localization = Localization::parse(&text);
+        debug!(
+            "Loaded {} localized strings from {}",
+            localization.strings.len(),
+            LOCALIZATION_FILE
+        );
+        localization
+    }
+
+    fn parse(text: &str) -> Localization {
+        let mut strings = HashMap::new();
+        let mut rest = text.trim_start_matches('\u{feff}');
+
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+
+            // comments run to the end of the line
+            if rest.starts_with("//") {
+                rest = rest.find('\n').map(|i| &rest[i..]).unwrap_or("");
+                continue;
+            }
+
+            let (key, value, remainder) = match parse_entry(rest) {
+                Some(entry) => entry,
+                None => {
+                    // skip the malformed line
+                    warn!("Invalid line in {}", LOCALIZATION_FILE);
+                    rest = rest.find('\n').map(|i| &rest[i..]).unwrap_or("");
+                    continue;
+                }
+            };
+
+            strings.insert(key.trim_start_matches('$').to_owned(), value);
+            rest = remainder;
+        }
+
+        Localization { strings }
+    }
+
+    /// Returns the localized text for `message` if it is a `$` reference to a known string, or
+    /// the message unchanged otherwise.
+    pub fn localize<'a>(&self, message: &'a str) -> Cow<'a, str> {
+        if !message.starts_with('$') {
+            return Cow::Borrowed(message);
+        }
+
+        // references may be followed by a newline like any other message
+        let reference = message.trim_end();
+        match self.strings.get(&reference[1..]) {
+            Some(text) => Cow::Owned(format!("{}{}", text, &message[reference.len()..])),
+            None => Cow::Borrowed(message),
+        }
+    }
+}
+
+/// Parses `key = "value"` from the start of `text`, returning the key, the unescaped value and the
+/// text after the entry.
+fn parse_entry(text: &str) -> Option<(&str, String, &str)> {
+    let key_end = text.find(|c: char| c == '=' || c.is_whitespace())?;
+    let key = &text[..key_end];
+    let rest = text[key_end..].trim_start().strip_prefix('=')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+
+    let mut value = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((key, value, &rest[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                other => value.push(other),
+            },
+            c => value.push(c),
+        }
+    }
+
+    // unterminated string
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let localization = Localization::parse(
+            "\u{feff}// localization\n\
+             qc_need_gold_key = \"You need the gold key\"\n\
+             $qc_two_lines = \"first\\nsecond \\\"quoted\\\"\"\n\
+             not an entry\n\
+             qc_multiline =\n\"spans\nlines\"\n",
+        );
+
+        assert_eq!(
+            localization.localize("$qc_need_gold_key"),
+            "You need the gold key"
+        );
+        assert_eq!(
+            localization.localize("$qc_two_lines\n"),
+            "first\nsecond \"quoted\"\n"
+        );
+        assert_eq!(localization.localize("$qc_multiline"), "spans\nlines");
+
+        // anything else is left alone
+        assert_eq!(localization.localize("$qc_unknown"), "$qc_unknown");
+        assert_eq!(
+            localization.localize("qc_need_gold_key"),
+            "qc_need_gold_key"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Named map locations for team communication.
+//!
+//! Location files live at `locs/<map>.loc` and contain one location per line in the form
+//! `x y z name`, with coordinates in eighths of a unit. This is the format used by ProQuake and
+//! Qizmo.
+
+use std::io::Read;
+
+use crate::common::vfs::Vfs;
+
+use cgmath::{InnerSpace as _, Vector3};
+
+#[derive(Debug)]
+struct Location {
+    origin: Vector3<f32>,
+    name: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Locations {
+    locs: Vec<Location>,
+}
+
+impl Locations {
+    pub fn new() -> Locations {
+        Locations { locs: Vec::new() }
+    }
+
+    /// Parses the contents of a location file, skipping malformed lines.
+    pub fn parse(text: &str) -> Locations {
+        let mut locs = Vec::new();
+
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let coords = fields
+                .iter()
+                .take(3)
+                .map(|f| {
+                    f.parse::<f32>()
+                        .ok()
+                        .filter(|c| c.is_finite())
+                        .map(|c| c / 8.0)
+                })
+                .collect::<Option<Vec<_>>>();
+            let name = fields
+                .get(3..)
+                .map(|n| n.join(" ").trim_matches('"').to_owned());
+
+            match (coords, name) {
+                (Some(c), Some(name)) if c.len() == 3 && !name.is_empty() => locs.push(Location {
+                    origin: Vector3::new(c[0], c[1], c[2]),
+                    name,
+                }),
+                _ => warn!("Malformed location on line {}: {}", line_num + 1, line),
+            }
+        }
+
+        Locations { locs }
+    }
+
+    /// Loads the location file for the given map, if there is one.
+    pub fn load(vfs: &Vfs, map_name: &str) -> Locations {
+        let mut text = String::new();
+        match vfs.open(format!("locs/{}.loc", map_name)) {
+            Ok(mut f) => match f.read_to_string(&mut text) {
+                Ok(_) => Locations::parse(&text),
+                Err(e) => {
+                    warn!("Couldn't read locations for {}: {}", map_name, e);
+                    Locations::new()
+                }
+            },
+
+            // most maps won't have a location file
+            Err(_) => Locations::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locs.is_empty()
+    }
+
+    /// Returns the name of the location closest to `origin`.
+    pub fn nearest(&self, origin: Vector3<f32>) -> Option<&str> {
+        self.locs
+            .iter()
+            .map(|loc| (loc, (loc.origin - origin).magnitude2()))
+            // a non-finite origin makes every distance incomparable
+            .filter(|(_, dist)| !dist.is_nan())
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|(loc, _)| loc.name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_locations() {
+        let locs = Locations::parse(concat!(
+            "800 0 0 red armor\n",
+            "// comment\n",
+            "\n",
+            "-800 0 0 \"quad\"\n",
+            "garbage line\n",
+            "nan 0 0 nowhere\n",
+            "0 inf 0 nowhere\n",
+        ));
+
+        assert_eq!(locs.locs.len(), 2);
+        assert_eq!(locs.locs[0].origin, Vector3::new(100.0, 0.0, 0.0));
+        assert_eq!(locs.locs[1].name, "quad");
+    }
+
+    #[test]
+    fn test_nearest_location() {
+        let locs = Locations::parse("800 0 0 red armor\n-800 0 0 quad\n");
+        assert_eq!(
+            locs.nearest(Vector3::new(60.0, 10.0, 0.0)),
+            Some("red armor")
+        );
+        assert_eq!(locs.nearest(Vector3::new(-20.0, 0.0, 0.0)), Some("quad"));
+        assert_eq!(Locations::new().nearest(Vector3::new(0.0, 0.0, 0.0)), None);
+        assert_eq!(locs.nearest(Vector3::new(f32::NAN, 0.0, 0.0)), None);
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Per-map configuration overrides.
+//!
+//! When a map is loaded, `configs/default.cfg` and then `configs/<map>.cfg` are executed if they
+//! exist. Since mod directories are searched first, this lets a mod tune client settings for all
+//! of its maps or for individual ones. When the map ends, the cvars these scripts assign are
+//! reverted; anything else the player changes during the map is kept.
+
+use std::{cell::RefCell, collections::BTreeSet, io::Read as _, rc::Rc};
+
+use crate::common::{
+    console::{Console, CvarRegistry, CvarSnapshot},
+    parse,
+    vfs::Vfs,
+};
+
+const CONFIG_DIR: &str = "configs";
+
+/// Returns the paths of the config scripts for the given map, in execution order.
+pub fn config_paths(map_name: &str) -> [String; 2] {
+    [
+        format!("{}/default.cfg", CONFIG_DIR),
+        format!("{}/{}.cfg", CONFIG_DIR, map_name),
+    ]
+}
+
+/// Returns the names of the registered cvars assigned a value by `script`.
+///
+/// Only assignments made directly by the script are found, not those made by aliases or other
+/// scripts it executes.
+fn assigned_cvars<'a>(cvars: &CvarRegistry, script: &'a str) -> Vec<&'a str> {
+    let commands = match parse::commands(script) {
+        Ok((_, commands)) => commands,
+        Err(_) => return Vec::new(),
+    };
+
+    commands
+        .into_iter()
+        .filter(|args| args.len() >= 2 && cvars.contains(args[0]))
+        .map(|args| args[0])
+        .collect()
+}
+
+/// The config overrides for the current map.
+///
+/// Dropping this restores the cvars assigned by the map's config scripts to their values from
+/// before the map was loaded.
+pub struct MapConfig {
+    cvars: Rc<RefCell<CvarRegistry>>,
+    snapshot: CvarSnapshot,
+}
+
+impl MapConfig {
+    /// Records the current cvar values and queues the map's config scripts for execution.
+    pub fn begin(
+        vfs: &Vfs,
+        cvars: Rc<RefCell<CvarRegistry>>,
+        console: &Console,
+        map_name: &str,
+    ) -> MapConfig {
+        let mut names = BTreeSet::new();
+        for path in config_paths(map_name).iter() {
+            let mut script = String::new();
+            match vfs.open(path) {
+                Ok(mut file) => {
+                    if let Err(e) = file.read_to_string(&mut script) {
+                        warn!("Couldn't read {}: {}", path, e);
+                        continue;
+                    }
+                }
+                Err(_) => continue,
+            }
+
+            names.extend(
+                assigned_cvars(&cvars.borrow(), &script)
+                    .into_iter()
+                    .map(str::to_owned),
+            );
+
+            debug!("Executing {}", path);
+            console.stuff_text(format!("exec {}\n", path));
+        }
+
+        // the scripts are only run once the console executes its buffer, so this snapshot is
+        // taken before any of their changes
+        let snapshot = cvars.borrow().snapshot_of(&names);
+
+        MapConfig { cvars, snapshot }
+    }
+}
+
+impl Drop for MapConfig {
+    fn drop(&mut self) {
+        for name in self.cvars.borrow().restore(&self.snapshot) {
+            debug!("Restored {} after map config", name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::console::CmdRegistry;
+
+    #[test]
+    fn test_config_paths() {
+        assert_eq!(
+            config_paths("e1m1"),
+            [
+                "configs/default.cfg".to_owned(),
+                "configs/e1m1.cfg".to_owned()
+            ]
+        );
+    }
+
+    fn test_console() -> (Rc<RefCell<CvarRegistry>>, Console) {
+        let names = Rc::new(RefCell::new(Vec::new()));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new(names.clone())));
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new(names)));
+        let console = Console::new(cmds, cvars.clone());
+        cvars.borrow().register("fov", "90").unwrap();
+        cvars.borrow().register("sensitivity", "3").unwrap();
+        (cvars, console)
+    }
+
+    #[test]
+    fn test_assigned_cvars() {
+        let (cvars, _) = test_console();
+        let script = "fov 110\nsensitivity\necho hello\nr_missing 1\n";
+        assert_eq!(assigned_cvars(&cvars.borrow(), script), vec!["fov"]);
+    }
+
+    #[test]
+    fn test_restore_on_drop() {
+        let (cvars, console) = test_console();
+        let mut vfs = Vfs::new();
+        vfs.add_memory("configs/e1m1.cfg", b"fov 110\n".to_vec());
+
+        // `exec` is registered by the client binary, so apply the script's change directly
+        let config = MapConfig::begin(&vfs, cvars.clone(), &console, "e1m1");
+        cvars.borrow().set("fov", "110").unwrap();
+        drop(config);
+
+        assert_eq!(cvars.borrow().get("fov").unwrap(), "90");
+    }
+
+    #[test]
+    fn test_player_changes_survive_drop() {
+        let (cvars, console) = test_console();
+        let mut vfs = Vfs::new();
+        vfs.add_memory("configs/e1m1.cfg", b"fov 110\n".to_vec());
+
+        let config = MapConfig::begin(&vfs, cvars.clone(), &console, "e1m1");
+        cvars.borrow().set("sensitivity", "5").unwrap();
+        drop(config);
+
+        assert_eq!(cvars.borrow().get("sensitivity").unwrap(), "5");
+    }
+}
@@ -35,6 +35,7 @@ pub enum Item {
 
 pub struct Toggle {
     state: Cell<bool>,
+    bound: Option<Box<dyn Fn() -> bool>>,
     on_toggle: Box<dyn Fn(bool)>,
 }
 
@@ -42,6 +43,7 @@ impl Toggle {
     pub fn new(init: bool, on_toggle: Box<dyn Fn(bool)>) -> Toggle {
         let t = Toggle {
             state: Cell::new(init),
+            bound: None,
             on_toggle,
         };
 
@@ -51,23 +53,39 @@ impl Toggle {
         t
     }
 
+    /// Creates a toggle which shows the value returned by `bound` instead of keeping its own.
+    ///
+    /// `on_toggle` is only called when the toggle is changed.
+    pub fn bound(bound: Box<dyn Fn() -> bool>, on_toggle: Box<dyn Fn(bool)>) -> Toggle {
+        Toggle {
+            state: Cell::new(false),
+            bound: Some(bound),
+            on_toggle,
+        }
+    }
+
+    fn set(&self, state: bool) {
+        self.state.set(state);
+        (self.on_toggle)(state);
+    }
+
     pub fn set_false(&self) {
-        self.state.set(false);
-        (self.on_toggle)(self.state.get());
+        self.set(false);
     }
 
     pub fn set_true(&self) {
-        self.state.set(true);
-        (self.on_toggle)(self.state.get());
+        self.set(true);
     }
 
     pub fn toggle(&self) {
-        self.state.set(!self.state.get());
-        (self.on_toggle)(self.state.get());
+        self.set(!self.get());
     }
 
     pub fn get(&self) -> bool {
-        self.state.get()
+        match self.bound {
+            Some(ref bound) => bound(),
+            None => self.state.get(),
+        }
     }
 }
 
@@ -75,6 +93,7 @@ impl Toggle {
 // e.g. resolution enum wraps, texture filtering does not
 pub struct Enum {
     selected: Cell<usize>,
+    bound: Option<Box<dyn Fn() -> usize>>,
     items: Vec<EnumItem>,
 }
 
@@ -85,6 +104,7 @@ impl Enum {
 
         let e = Enum {
             selected: Cell::new(init),
+            bound: None,
             items,
         };
 
@@ -94,12 +114,33 @@ impl Enum {
         Ok(e)
     }
 
+    /// Creates an enum which shows the item whose index is returned by `bound`.
+    ///
+    /// Items are only selected when the enum is changed. Indices past the last item show the last
+    /// item.
+    pub fn bound(bound: Box<dyn Fn() -> usize>, items: Vec<EnumItem>) -> Result<Enum, Error> {
+        ensure!(items.len() > 0, "Enum element must have at least one item");
+
+        Ok(Enum {
+            selected: Cell::new(0),
+            bound: Some(bound),
+            items,
+        })
+    }
+
+    fn selected(&self) -> usize {
+        match self.bound {
+            Some(ref bound) => bound().min(self.items.len() - 1),
+            None => self.selected.get(),
+        }
+    }
+
     pub fn selected_name(&self) -> &str {
-        self.items[self.selected.get()].name.as_str()
+        self.items[self.selected()].name.as_str()
     }
 
     pub fn select_next(&self) {
-        let selected = match self.selected.get() + 1 {
+        let selected = match self.selected() + 1 {
             s if s >= self.items.len() => 0,
             s => s,
         };
@@ -109,7 +150,7 @@ impl Enum {
     }
 
     pub fn select_prev(&self) {
-        let selected = match self.selected.get() {
+        let selected = match self.selected() {
             0 => self.items.len() - 1,
             s => s - 1,
         };
@@ -143,6 +184,7 @@ pub struct Slider {
     steps: usize,
 
     selected: Cell<usize>,
+    bound: Option<Box<dyn Fn() -> f32>>,
     on_select: Box<dyn Fn(f32)>,
 }
 
@@ -167,32 +209,49 @@ impl Slider {
             increment: (max - min) / (steps - 1) as f32,
             steps,
             selected: Cell::new(init),
+            bound: None,
             on_select,
         })
     }
 
-    pub fn increase(&self) {
-        let old = self.selected.get();
+    /// Creates a slider which shows the step closest to the value returned by `bound`.
+    pub fn bound(
+        min: f32,
+        max: f32,
+        steps: usize,
+        bound: Box<dyn Fn() -> f32>,
+        on_select: Box<dyn Fn(f32)>,
+    ) -> Result<Slider, Error> {
+        let mut slider = Slider::new(min, max, steps, 0, on_select)?;
+        slider.bound = Some(bound);
+        Ok(slider)
+    }
 
-        if old != self.steps - 1 {
-            self.selected.set(old + 1);
+    fn selected(&self) -> usize {
+        match self.bound {
+            Some(ref bound) => {
+                let step = ((bound() - self.min) / self.increment).round();
+                (step.max(0.0) as usize).min(self.steps - 1)
+            }
+            None => self.selected.get(),
         }
+    }
 
-        (self.on_select)(self.min + self.selected.get() as f32 * self.increment);
+    pub fn increase(&self) {
+        self.select((self.selected() + 1).min(self.steps - 1));
     }
 
     pub fn decrease(&self) {
-        let old = self.selected.get();
-
-        if old != 0 {
-            self.selected.set(old - 1);
-        }
+        self.select(self.selected().saturating_sub(1));
+    }
 
-        (self.on_select)(self.min + self.selected.get() as f32 * self.increment);
+    fn select(&self, selected: usize) {
+        self.selected.set(selected);
+        (self.on_select)(self.min + selected as f32 * self.increment);
     }
 
     pub fn position(&self) -> f32 {
-        self.selected.get() as f32 / self.steps as f32
+        self.selected() as f32 / self.steps as f32
     }
 }
 
@@ -239,6 +298,10 @@ impl TextField {
         self.chars.borrow().len()
     }
 
+    pub fn cursor(&self) -> usize {
+        self.cursor.get()
+    }
+
     pub fn set_cursor(&self, cursor: usize) -> Result<(), Error> {
         ensure!(cursor <= self.len(), "Index out of range");
 
@@ -264,7 +327,7 @@ impl TextField {
 
     pub fn cursor_left(&self) {
         let curs = self.cursor.get();
-        if curs > 1 {
+        if curs > 0 {
             self.cursor.set(curs - 1);
         }
     }
@@ -277,12 +340,14 @@ impl TextField {
         }
 
         self.chars.borrow_mut().insert(self.cursor.get(), c);
+        self.cursor.set(self.cursor.get() + 1);
         (self.on_update)(&self.text());
     }
 
     pub fn backspace(&self) {
-        if self.cursor.get() > 1 {
+        if self.cursor.get() > 0 {
             self.chars.borrow_mut().remove(self.cursor.get() - 1);
+            self.cursor.set(self.cursor.get() - 1);
             (self.on_update)(&self.text());
         }
     }
@@ -381,6 +446,59 @@ mod test {
         assert_eq!(f.get(), 10.0);
     }
 
+    #[test]
+    fn test_bound_items() {
+        let value = Rc::new(Cell::new(0.0f32));
+
+        let get = value.clone();
+        let set = value.clone();
+        let slider = Slider::bound(
+            0.0,
+            10.0,
+            11,
+            Box::new(move || get.get()),
+            Box::new(move |f| set.set(f)),
+        )
+        .unwrap();
+
+        // the slider follows changes made elsewhere
+        value.set(7.2);
+        assert_eq!(slider.position(), 7.0 / 11.0);
+        slider.increase();
+        assert_eq!(value.get(), 8.0);
+
+        let get = value.clone();
+        let set = value.clone();
+        let toggle = Toggle::bound(
+            Box::new(move || get.get() != 0.0),
+            Box::new(move |on| set.set(if on { 1.0 } else { 0.0 })),
+        );
+
+        // creating a bound toggle doesn't write its value
+        assert_eq!(value.get(), 8.0);
+        assert!(toggle.get());
+        value.set(0.0);
+        assert!(!toggle.get());
+        toggle.toggle();
+        assert_eq!(value.get(), 1.0);
+
+        let get = value.clone();
+        let items = (0..3)
+            .map(|i| {
+                let set = value.clone();
+                EnumItem::new(format!("option_{}", i), Box::new(move || set.set(i as f32))).unwrap()
+            })
+            .collect();
+        let e = Enum::bound(Box::new(move || get.get() as usize), items).unwrap();
+
+        assert_eq!(e.selected_name(), "option_1");
+        e.select_next();
+        assert_eq!(value.get(), 2.0);
+        value.set(0.0);
+        e.select_prev();
+        assert_eq!(value.get(), 2.0);
+    }
+
     #[test]
     fn test_textfield() {
         let MAX_LEN = 10;
@@ -417,4 +535,29 @@ mod test {
 
         assert_eq!(tf.len(), MAX_LEN);
     }
+
+    #[test]
+    fn test_textfield_cursor() {
+        let tf = TextField::new(Some("ab"), None, Box::new(|_| ())).unwrap();
+        assert_eq!(tf.cursor(), 2);
+
+        tf.insert('c');
+        assert_eq!(tf.text(), "abc");
+        assert_eq!(tf.cursor(), 3);
+
+        tf.home();
+        tf.insert('x');
+        assert_eq!(tf.text(), "xabc");
+        assert_eq!(tf.cursor(), 1);
+
+        tf.backspace();
+        assert_eq!(tf.text(), "abc");
+        assert_eq!(tf.cursor(), 0);
+
+        // can't move or delete past the start
+        tf.cursor_left();
+        tf.backspace();
+        assert_eq!(tf.text(), "abc");
+        assert_eq!(tf.cursor(), 0);
+    }
 }
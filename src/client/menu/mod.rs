@@ -74,18 +74,38 @@ impl MenuView {
 
 pub struct Menu {
     items: Vec<NamedMenuItem>,
+    populate: Option<Box<dyn Fn(MenuBuilder) -> MenuBuilder>>,
     state: Cell<MenuState>,
     view: MenuView,
 }
 
 impl Menu {
+    /// Creates a menu whose items are added by `populate` each time it's opened.
+    ///
+    /// `populate` must add at least one item.
+    pub fn lazy(view: MenuView, populate: Box<dyn Fn(MenuBuilder) -> MenuBuilder>) -> Menu {
+        Menu {
+            items: Vec::new(),
+            populate: Some(populate),
+            state: Cell::new(MenuState::Inactive),
+            view,
+        }
+    }
+
+    /// Rebuilds the items of a lazily built menu.
+    fn populate(&mut self) {
+        if let Some(ref populate) = self.populate {
+            self.items = populate(MenuBuilder::new()).into_items();
+        }
+    }
+
     /// Returns a reference to the active submenu of this menu and its parent.
     fn active_submenu_and_parent(&self) -> Result<(&Menu, Option<&Menu>), Error> {
         let mut m = self;
         let mut m_parent = None;
 
         while let MenuState::InSubMenu { index } = m.state.get() {
-            match m.items[index].item {
+            match m.items()[index].item {
                 Item::Submenu(ref s) => {
                     m_parent = Some(m);
                     m = s;
@@ -97,6 +117,17 @@ impl Menu {
         Ok((m, m_parent))
     }
 
+    /// Returns a mutable reference to the active submenu of this menu.
+    fn active_submenu_mut(&mut self) -> Result<&mut Menu, Error> {
+        match self.state.get() {
+            MenuState::InSubMenu { index } => match self.items[index].item {
+                Item::Submenu(ref mut s) => s.active_submenu_mut(),
+                _ => bail!("Menu state points to invalid submenu"),
+            },
+            _ => Ok(self),
+        }
+    }
+
     /// Return a reference to the active submenu of this menu
     pub fn active_submenu(&self) -> Result<&Menu, Error> {
         let (m, _) = self.active_submenu_and_parent()?;
@@ -118,7 +149,7 @@ impl Menu {
         let s = m.state.get().clone();
         if let MenuState::Active { index } = s {
             m.state.replace(MenuState::Active {
-                index: (index + 1) % m.items.len(),
+                index: (index + 1) % m.items().len(),
             });
         } else {
             bail!("Selected menu is inactive (invariant violation)");
@@ -134,7 +165,7 @@ impl Menu {
         let s = m.state.get().clone();
         if let MenuState::Active { index } = s {
             m.state.replace(MenuState::Active {
-                index: (index - 1) % m.items.len(),
+                index: (index - 1) % m.items().len(),
             });
         } else {
             bail!("Selected menu is inactive (invariant violation)");
@@ -148,7 +179,7 @@ impl Menu {
         let m = self.active_submenu()?;
 
         if let MenuState::Active { index } = m.state.get() {
-            return Ok(&m.items[index].item);
+            return Ok(&m.items()[index].item);
         } else {
             bail!("Active menu in invalid state (invariant violation)")
         }
@@ -164,12 +195,13 @@ impl Menu {
     /// `Action`.
     ///
     /// Otherwise, this has no effect.
-    pub fn activate(&self) -> Result<(), Error> {
-        let m = self.active_submenu()?;
+    pub fn activate(&mut self) -> Result<(), Error> {
+        let m = self.active_submenu_mut()?;
 
         if let MenuState::Active { index } = m.state.get() {
             match m.items[index].item {
-                Item::Submenu(ref submenu) => {
+                Item::Submenu(ref mut submenu) => {
+                    submenu.populate();
                     m.state.replace(MenuState::InSubMenu { index });
                     submenu.state.replace(MenuState::Active { index: 0 });
                 }
@@ -187,7 +219,7 @@ impl Menu {
         let m = self.active_submenu()?;
 
         if let MenuState::Active { index } = m.state.get() {
-            match m.items[index].item {
+            match m.items()[index].item {
                 Item::Enum(ref e) => e.select_prev(),
                 Item::Slider(ref slider) => slider.decrease(),
                 Item::TextField(ref text) => text.cursor_left(),
@@ -203,7 +235,7 @@ impl Menu {
         let m = self.active_submenu()?;
 
         if let MenuState::Active { index } = m.state.get() {
-            match m.items[index].item {
+            match m.items()[index].item {
                 Item::Enum(ref e) => e.select_next(),
                 Item::Slider(ref slider) => slider.increase(),
                 Item::TextField(ref text) => text.cursor_right(),
@@ -215,6 +247,49 @@ impl Menu {
         Ok(())
     }
 
+    /// Inserts a character into the selected text field.
+    ///
+    /// If the selected item isn't a text field, this has no effect.
+    pub fn insert(&self, c: char) -> Result<(), Error> {
+        if let Item::TextField(ref text) = *self.selected()? {
+            text.insert(c);
+        }
+
+        Ok(())
+    }
+
+    pub fn backspace(&self) -> Result<(), Error> {
+        if let Item::TextField(ref text) = *self.selected()? {
+            text.backspace();
+        }
+
+        Ok(())
+    }
+
+    pub fn delete(&self) -> Result<(), Error> {
+        if let Item::TextField(ref text) = *self.selected()? {
+            text.delete();
+        }
+
+        Ok(())
+    }
+
+    pub fn home(&self) -> Result<(), Error> {
+        if let Item::TextField(ref text) = *self.selected()? {
+            text.home();
+        }
+
+        Ok(())
+    }
+
+    pub fn end(&self) -> Result<(), Error> {
+        if let Item::TextField(ref text) = *self.selected()? {
+            text.end();
+        }
+
+        Ok(())
+    }
+
     /// Return `true` if the root menu is active, `false` otherwise.
     pub fn at_root(&self) -> bool {
         match self.state.get() {
@@ -247,6 +322,7 @@ impl Menu {
         Ok(())
     }
 
+    /// Returns the items of this menu, which are empty if it's lazily built and hasn't been opened.
     pub fn items(&self) -> &[NamedMenuItem] {
         &self.items
     }
@@ -274,6 +350,15 @@ impl MenuBuilder {
     }
 
     pub fn build(self, view: MenuView) -> Menu {
+        Menu {
+            items: self.into_items(),
+            populate: None,
+            state: Cell::new(MenuState::Active { index: 0 }),
+            view,
+        }
+    }
+
+    fn into_items(self) -> Vec<NamedMenuItem> {
         // deactivate all child menus
         for item in self.items.iter() {
             if let Item::Submenu(ref m) = item.item {
@@ -281,11 +366,7 @@ impl MenuBuilder {
             }
         }
 
-        Menu {
-            items: self.items,
-            state: Cell::new(MenuState::Active { index: 0 }),
-            view,
-        }
+        self.items
     }
 
     pub fn add_submenu<S>(mut self, name: S, submenu: Menu) -> MenuBuilder
@@ -317,6 +398,23 @@ impl MenuBuilder {
         self
     }
 
+    /// Adds a toggle which shows the value returned by `bound`, see [`Toggle::bound`].
+    pub fn add_bound_toggle<S>(
+        mut self,
+        name: S,
+        bound: Box<dyn Fn() -> bool>,
+        on_toggle: Box<dyn Fn(bool)>,
+    ) -> MenuBuilder
+    where
+        S: AsRef<str>,
+    {
+        self.items.push(NamedMenuItem::new(
+            name,
+            Item::Toggle(Toggle::bound(bound, on_toggle)),
+        ));
+        self
+    }
+
     pub fn add_enum<S, E>(mut self, name: S, items: E, init: usize) -> Result<MenuBuilder, Error>
     where
         S: AsRef<str>,
@@ -329,6 +427,24 @@ impl MenuBuilder {
         Ok(self)
     }
 
+    /// Adds an enum which shows the item whose index is returned by `bound`, see [`Enum::bound`].
+    pub fn add_bound_enum<S, E>(
+        mut self,
+        name: S,
+        items: E,
+        bound: Box<dyn Fn() -> usize>,
+    ) -> Result<MenuBuilder, Error>
+    where
+        S: AsRef<str>,
+        E: Into<Vec<EnumItem>>,
+    {
+        self.items.push(NamedMenuItem::new(
+            name,
+            Item::Enum(Enum::bound(bound, items.into())?),
+        ));
+        Ok(self)
+    }
+
     pub fn add_slider<S>(
         mut self,
         name: S,
@@ -348,6 +464,26 @@ impl MenuBuilder {
         Ok(self)
     }
 
+    /// Adds a slider which shows the value returned by `bound`, see [`Slider::bound`].
+    pub fn add_bound_slider<S>(
+        mut self,
+        name: S,
+        min: f32,
+        max: f32,
+        steps: usize,
+        bound: Box<dyn Fn() -> f32>,
+        on_select: Box<dyn Fn(f32)>,
+    ) -> Result<MenuBuilder, Error>
+    where
+        S: AsRef<str>,
+    {
+        self.items.push(NamedMenuItem::new(
+            name,
+            Item::Slider(Slider::bound(min, max, steps, bound, on_select)?),
+        ));
+        Ok(self)
+    }
+
     pub fn add_text_field<S>(
         mut self,
         name: S,
@@ -394,7 +530,10 @@ impl NamedMenuItem {
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::{cell::Cell, rc::Rc};
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
 
     fn view() -> MenuView {
         MenuView {
@@ -404,6 +543,13 @@ mod test {
         }
     }
 
+    fn submenu(menu: &Menu, index: usize) -> &Menu {
+        match menu.items()[index].item {
+            Item::Submenu(ref sub) => sub,
+            _ => unreachable!(),
+        }
+    }
+
     fn is_inactive(state: &MenuState) -> bool {
         match state {
             MenuState::Inactive => true,
@@ -439,7 +585,7 @@ mod test {
 
     #[test]
     fn test_menu_active_submenu() {
-        let menu = MenuBuilder::new()
+        let mut menu = MenuBuilder::new()
             .add_submenu(
                 "menu_1",
                 MenuBuilder::new()
@@ -454,37 +600,97 @@ mod test {
             )
             .build(view());
 
-        let m = &menu;
-        let m1 = match m.items[0].item {
-            Item::Submenu(ref m1i) => m1i,
-            _ => unreachable!(),
-        };
-        let m2 = match m.items[1].item {
-            Item::Submenu(ref m2i) => m2i,
-            _ => unreachable!(),
-        };
-
+        let m = &mut menu;
         assert!(is_active(&m.state.get()));
-        assert!(is_inactive(&m1.state.get()));
-        assert!(is_inactive(&m2.state.get()));
+        assert!(is_inactive(&submenu(m, 0).state.get()));
+        assert!(is_inactive(&submenu(m, 1).state.get()));
 
         // enter m1
         m.activate().unwrap();
         assert!(is_insubmenu(&m.state.get()));
-        assert!(is_active(&m1.state.get()));
-        assert!(is_inactive(&m2.state.get()));
+        assert!(is_active(&submenu(m, 0).state.get()));
+        assert!(is_inactive(&submenu(m, 1).state.get()));
 
         // exit m1
         m.back().unwrap();
         assert!(is_active(&m.state.get()));
-        assert!(is_inactive(&m1.state.get()));
-        assert!(is_inactive(&m2.state.get()));
+        assert!(is_inactive(&submenu(m, 0).state.get()));
+        assert!(is_inactive(&submenu(m, 1).state.get()));
 
         // enter m2
         m.next().unwrap();
         m.activate().unwrap();
         assert!(is_insubmenu(&m.state.get()));
-        assert!(is_inactive(&m1.state.get()));
-        assert!(is_active(&m2.state.get()));
+        assert!(is_inactive(&submenu(m, 0).state.get()));
+        assert!(is_active(&submenu(m, 1).state.get()));
+    }
+
+    #[test]
+    fn test_menu_text_field() {
+        let target = Rc::new(RefCell::new(String::new()));
+        let target_handle = target.clone();
+
+        let menu = MenuBuilder::new()
+            .add_action("action", Box::new(|| ()))
+            .add_text_field(
+                "text",
+                None,
+                None,
+                Box::new(move |text| {
+                    target_handle.replace(text.to_owned());
+                }),
+            )
+            .unwrap()
+            .build(view());
+
+        // typing has no effect unless a text field is selected
+        menu.insert('a').unwrap();
+        assert_eq!(*target.borrow(), "");
+
+        menu.next().unwrap();
+        for c in "host".chars() {
+            menu.insert(c).unwrap();
+        }
+        assert_eq!(*target.borrow(), "host");
+
+        menu.home().unwrap();
+        menu.delete().unwrap();
+        menu.end().unwrap();
+        menu.backspace().unwrap();
+        assert_eq!(*target.borrow(), "os");
+    }
+
+    #[test]
+    fn test_menu_lazy() {
+        let populated = Rc::new(Cell::new(0));
+        let populated_handle = populated.clone();
+
+        let mut menu = MenuBuilder::new()
+            .add_submenu(
+                "lazy",
+                Menu::lazy(
+                    view(),
+                    Box::new(move |builder| {
+                        populated_handle.set(populated_handle.get() + 1);
+                        builder.add_action("item", Box::new(|| ()))
+                    }),
+                ),
+            )
+            .build(view());
+
+        // nothing is built until the submenu is opened
+        assert_eq!(populated.get(), 0);
+        assert!(submenu(&menu, 0).items().is_empty());
+
+        menu.activate().unwrap();
+        assert_eq!(populated.get(), 1);
+        assert_eq!(submenu(&menu, 0).items()[0].name(), "item");
+        assert!(is_active(&submenu(&menu, 0).state.get()));
+
+        // reopening builds the items again
+        menu.back().unwrap();
+        menu.activate().unwrap();
+        assert_eq!(populated.get(), 2);
+        assert_eq!(submenu(&menu, 0).items().len(), 1);
     }
 }
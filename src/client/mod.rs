@@ -18,14 +18,29 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+pub mod chat;
 mod cvars;
+pub mod delay;
 pub mod demo;
+pub mod download;
 pub mod entity;
+#[cfg(test)]
+mod fake_server;
+pub mod fog;
 pub mod input;
+pub mod lightstyle;
+pub mod localization;
+pub mod location;
+pub mod mapconfig;
 pub mod menu;
 pub mod render;
+pub mod showlmp;
 pub mod sound;
 pub mod state;
+pub mod stats;
+pub mod strings;
+pub mod teaminfo;
+pub mod timegraph;
 pub mod trace;
 pub mod view;
 
@@ -34,41 +49,55 @@ pub use self::cvars::register_cvars;
 use std::{
     cell::RefCell,
     collections::{HashMap, VecDeque},
-    io::BufReader,
+    io::Cursor,
     net::ToSocketAddrs,
     rc::Rc,
+    time::Instant,
 };
 
 use crate::{
     client::{
+        chat::{cmd_ignore, cmd_unignore, ChatVars},
+        delay::{interp_delay, DelayBuffer},
         demo::{DemoServer, DemoServerError},
-        entity::{ClientEntity, MAX_STATIC_ENTITIES},
+        download::{self, HttpDownloads},
+        entity::ClientEntity,
+        fog::Fog,
         input::{game::GameInput, Input},
+        localization::Localization,
+        mapconfig::MapConfig,
+        showlmp::ShowLmp,
         sound::{MusicPlayer, StaticSound},
         state::{ClientState, PlayerInfo},
+        strings::Strings,
+        teaminfo,
         trace::{TraceEntity, TraceFrame},
-        view::{IdleVars, KickVars, MouseVars, RollVars},
+        view::{GunVars, IdleVars, KickVars, MouseVars, RollVars},
     },
     common::{
         console::{CmdRegistry, Console, ConsoleError, CvarRegistry},
-        engine,
+        engine, host,
+        http::FetchOptions,
+        limits::Limits,
         model::ModelError,
         net::{
             self,
             connect::{ConnectSocket, Request, Response, CONNECT_PROTOCOL_VERSION},
-            BlockingMode, ClientCmd, ClientStat, ColorShift, EntityEffects, EntityState, GameType,
-            NetError, PlayerColor, QSocket, ServerCmd, SignOnStage,
+            BlockingMode, ClientCmd, ColorShift, EntityEffects, EntityState, GameType, NetError,
+            PlayerColor, QSocket, ServerCmd, SignOnStage, WireFormat,
         },
         vfs::{Vfs, VfsError},
     },
 };
 
-use cgmath::Deg;
+use cgmath::{Deg, Vector3};
 use chrono::Duration;
+use copypasta::{ClipboardContext, ClipboardProvider as _};
 use input::InputFocus;
 use menu::Menu;
-use render::{ClientRenderer, GraphicsState, WorldRenderer};
+use render::{ClientRenderer, GpuTimings, GraphicsState, WorldRenderer};
 use rodio::{OutputStream, OutputStreamHandle};
+use serde::{Deserialize, Serialize};
 use sound::SoundError;
 use thiserror::Error;
 use view::BobVars;
@@ -76,11 +105,20 @@ use view::BobVars;
 // connections are tried 3 times, see
 // https://github.com/id-Software/Quake/blob/master/WinQuake/net_dgrm.c#L1248
 const MAX_CONNECT_ATTEMPTS: usize = 3;
-const MAX_STATS: usize = 32;
 
 const DEFAULT_SOUND_PACKET_VOLUME: u8 = 255;
 const DEFAULT_SOUND_PACKET_ATTENUATION: f32 = 1.0;
 
+// number of not-yet-used precached sounds to load each frame
+const SOUND_PREFETCH_PER_FRAME: usize = 2;
+
+// how often a nop is sent to the server while downloads hold up the sign-on, as in
+// CL_KeepaliveMessage
+const KEEPALIVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+// number of console lines copied by the "copy" command without an argument
+const DEFAULT_COPY_LINES: usize = 32;
+
 const CONSOLE_DIVIDER: &'static str = "\
 \n\n\
 \x1D\x1E\x1E\x1E\x1E\x1E\x1E\x1E\
@@ -103,8 +141,12 @@ pub enum ClientError {
     InvalidServerAddress,
     #[error("No response from server")]
     NoResponse,
+    #[error("{0} is only in the registered version of Quake")]
+    RegisteredMap(String),
     #[error("Unrecognized protocol: {0}")]
     UnrecognizedProtocol(i32),
+    #[error("Server sent {0}, but protocol extensions are disabled (see cl_protocol_extensions)")]
+    ProtocolExtensionDisabled(&'static str),
     #[error("Client is not connected")]
     NotConnected,
     #[error("Client has already signed on")]
@@ -121,6 +163,8 @@ pub enum ClientError {
     EntityExists(usize),
     #[error("Invalid view entity: {0}")]
     InvalidViewEntity(usize),
+    #[error("Entity ID {0} is over the entity limit")]
+    TooManyEntities(usize),
     #[error("Too many static entities")]
     TooManyStaticEntities,
     #[error("No such lightmap animation: {0}")]
@@ -140,6 +184,29 @@ pub enum ClientError {
     Vfs(#[from] VfsError),
 }
 
+impl ClientError {
+    /// Returns the message for this error from `strings`.
+    ///
+    /// Only errors that a player is expected to see are overridable. Everything else is
+    /// described by its `Display` implementation.
+    pub fn localize(&self, strings: &Strings) -> String {
+        use ClientError::*;
+
+        match self {
+            ConnectionRejected(reason) => strings.format("error.connection_rejected", &[reason]),
+            InvalidConnectPort(port) => strings.format("error.invalid_connect_port", &[port]),
+            InvalidConnectResponse => strings.get("error.invalid_connect_response").to_owned(),
+            InvalidServerAddress => strings.get("error.invalid_server_address").to_owned(),
+            NoResponse => strings.get("error.no_response").to_owned(),
+            RegisteredMap(map) => strings.format("error.registered_map", &[map]),
+            UnrecognizedProtocol(version) => {
+                strings.format("error.unrecognized_protocol", &[version])
+            }
+            e => e.to_string(),
+        }
+    }
+}
+
 pub struct MoveVars {
     cl_anglespeedkey: f32,
     cl_pitchspeed: f32,
@@ -149,6 +216,7 @@ pub struct MoveVars {
     cl_forwardspeed: f32,
     cl_backspeed: f32,
     cl_movespeedkey: f32,
+    cl_run: f32,
 }
 
 #[derive(Debug, FromPrimitive)]
@@ -159,12 +227,7 @@ enum ColorShiftCode {
     Powerup = 3,
 }
 
-struct ServerInfo {
-    _max_clients: u8,
-    _game_type: GameType,
-}
-
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum IntermissionKind {
     Intermission,
     Finale { text: String },
@@ -202,6 +265,9 @@ enum ConnectionKind {
 
         /// The client's packet composition buffer.
         compose: Vec<u8>,
+
+        /// Holds incoming messages for `cl_interp` seconds.
+        delay: DelayBuffer,
     },
 
     /// A demo server.
@@ -215,85 +281,618 @@ pub struct Connection {
     state: ClientState,
     conn_state: ConnectionState,
     kind: ConnectionKind,
+
+    /// Encoding of coordinates and angles, as announced in the server info.
+    wire_format: WireFormat,
+
+    /// Config overrides for the current map, reverted when this is replaced or dropped.
+    map_config: Option<MapConfig>,
+
+    /// Missing content being fetched before the server info is loaded.
+    downloads: Option<PendingDownloads>,
+}
+
+/// A server info message waiting on HTTP downloads.
+struct PendingDownloads {
+    downloads: HttpDownloads,
+    max_clients: u8,
+    game_type: GameType,
+    limits: Limits,
+    model_precache: Vec<String>,
+    sound_precache: Vec<String>,
+
+    /// The rest of the message containing the server info, handled once the downloads finish.
+    remainder: Vec<u8>,
+
+    /// When a keepalive was last sent to the server.
+    last_keepalive: Instant,
 }
 
 impl Connection {
-    fn handle_signon(
+    /// Creates a connection which has yet to sign on, playing sounds on `stream` if there is one.
+    fn new(kind: ConnectionKind, stream: Option<OutputStreamHandle>) -> Connection {
+        Connection {
+            state: ClientState::new(stream),
+            kind,
+            conn_state: ConnectionState::SignOn(SignOnStage::Prespawn),
+            wire_format: WireFormat::STANDARD,
+            map_config: None,
+            downloads: None,
+        }
+    }
+
+    /// Returns a description of the content being downloaded, if any.
+    fn download_status(&self, strings: &Strings) -> Option<String> {
+        self.downloads.as_ref().map(|p| p.downloads.status(strings))
+    }
+
+    /// Starts fetching any precached files missing from the VFS from the server's download mirror.
+    ///
+    /// Returns `None` if nothing is missing or no mirror is available.
+    fn start_downloads(
+        &self,
+        vfs: &Vfs,
+        cvars: &CvarRegistry,
+        model_precache: &[String],
+        sound_precache: &[String],
+    ) -> Option<HttpDownloads> {
+        // demos can't be paused while content is fetched
+        if let ConnectionKind::Demo(_) = self.kind {
+            return None;
+        }
+
+        let base_url = cvars.get("sv_downloadurl").unwrap_or_default();
+        let cache = match vfs.download_cache() {
+            Some(c) if !base_url.is_empty() => c,
+            _ => return None,
+        };
+
+        let missing = download::missing_files(vfs, model_precache, sound_precache);
+        if missing.is_empty() {
+            return None;
+        }
+
+        // cl_cachesize is in megabytes. the cache is opened before any config is executed, so the
+        // player's cap is applied here
+        let max_size = cvars.get_value("cl_cachesize").unwrap_or(0.0).max(0.0) as u64 * 1024 * 1024;
+        if let Err(e) = cache.borrow_mut().set_max_size(max_size) {
+            warn!("Couldn't resize download cache: {}", e);
+        }
+
+        let options = FetchOptions {
+            https_only: cvars.get_value("cl_httpsonly").unwrap_or(0.0) != 0.0,
+            max_size,
+        };
+
+        match HttpDownloads::start(base_url, missing, options) {
+            Ok(d) => Some(d),
+            Err(e) => {
+                warn!("Couldn't start downloads: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Loads the models and sounds named in the server info.
+    fn load_server_info(
         &mut self,
-        new_stage: SignOnStage,
-        gfx_state: &GraphicsState,
+        vfs: &Vfs,
+        cmds: &mut CmdRegistry,
+        console: &Console,
+        cvars: &Rc<RefCell<CvarRegistry>>,
+        max_clients: u8,
+        game_type: GameType,
+        limits: Limits,
+        model_precache: Vec<String>,
+        sound_precache: Vec<String>,
+    ) -> Result<(), ClientError> {
+        // revert the previous map's overrides before recording the current values
+        self.map_config = None;
+        if let Some(world) = model_precache.get(0) {
+            let map_name = world.trim_start_matches("maps/").trim_end_matches(".bsp");
+            self.map_config = Some(MapConfig::begin(vfs, cvars.clone(), console, map_name));
+        }
+
+        self.state = ClientState::from_server_info(
+            vfs,
+            self.state.mixer.stream(),
+            max_clients,
+            game_type,
+            limits,
+            model_precache,
+            sound_precache,
+            host::worker_threads(&cvars.borrow()),
+        )?;
+
+        let bonus_cshift = self.state.color_shifts[ColorShiftCode::Bonus as usize].clone();
+        cmds.insert_or_replace(
+            "bf",
+            Box::new(move |_| {
+                bonus_cshift.replace(ColorShift {
+                    dest_color: [215, 186, 69],
+                    percent: 50,
+                });
+                String::new()
+            }),
+        )
+        .unwrap();
+
+        Ok(())
+    }
+
+    /// Keeps the server connection alive while downloads are pending.
+    ///
+    /// Like `CL_KeepaliveMessage`, a nop is sent every few seconds so the server doesn't time the
+    /// client out. Messages from the server are acknowledged as they arrive and held until the
+    /// server info has been handled.
+    fn keep_alive(&mut self) -> Result<(), ClientError> {
+        let pending = match self.downloads.as_mut() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        if let ConnectionKind::Server {
+            ref mut qsock,
+            ref mut compose,
+            ref mut delay,
+        } = self.kind
+        {
+            loop {
+                let msg = qsock.recv_msg(BlockingMode::NonBlocking)?;
+                if msg.is_empty() {
+                    break;
+                }
+                delay.push(msg, Duration::zero());
+            }
+
+            if pending.last_keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+                ClientCmd::NoOp.serialize(compose)?;
+                pending.last_keepalive = Instant::now();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks on pending downloads, loading the server info once they have finished.
+    ///
+    /// Returns the rest of the server info message once it can be handled.
+    fn finish_downloads(
+        &mut self,
+        vfs: &Vfs,
+        cmds: &mut CmdRegistry,
+        console: &Console,
+        strings: &Strings,
+        cvars: &Rc<RefCell<CvarRegistry>>,
+    ) -> Result<Option<Vec<u8>>, ClientError> {
+        let finished = match (self.downloads.as_mut(), vfs.download_cache()) {
+            (Some(pending), Some(cache)) => pending.downloads.poll(&mut cache.borrow_mut()),
+            _ => true,
+        };
+
+        if !finished {
+            return Ok(None);
+        }
+
+        let pending = match self.downloads.take() {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+
+        // there is no in-protocol download yet, so anything still missing is loaded as it would
+        // have been without a mirror
+        for path in pending.downloads.failed() {
+            console.println(strings.format("download.failed", &[&path]));
+        }
+
+        self.load_server_info(
+            vfs,
+            cmds,
+            console,
+            cvars,
+            pending.max_clients,
+            pending.game_type,
+            pending.limits,
+            pending.model_precache,
+            pending.sound_precache,
+        )?;
+
+        Ok(Some(pending.remainder))
+    }
+
+    /// Fails if a FitzQuake server command arrives over another protocol and the protocol
+    /// extensions are disabled.
+    fn check_fitz_extension(
+        &self,
+        extensions: bool,
+        name: &'static str,
     ) -> Result<(), ClientError> {
+        if self.wire_format.fitz {
+            Ok(())
+        } else {
+            check_extension(extensions, name)
+        }
+    }
+
+    fn handle_signon(&mut self, new_stage: SignOnStage) -> Result<(), ClientError> {
         use SignOnStage::*;
 
-        let new_conn_state = match self.conn_state {
+        match self.conn_state {
             // TODO: validate stage transition
-            ConnectionState::SignOn(ref mut _stage) => {
+            ConnectionState::SignOn(stage) if stage != Done => {
                 if let ConnectionKind::Server {
                     ref mut compose, ..
                 } = self.kind
                 {
-                    match new_stage {
-                        Not => (), // TODO this is an error (invalid value)
-                        Prespawn => {
-                            ClientCmd::StringCmd {
-                                cmd: String::from("prespawn"),
-                            }
-                            .serialize(compose)?;
-                        }
-                        ClientInfo => {
-                            // TODO: fill in client info here
-                            ClientCmd::StringCmd {
-                                cmd: format!("name \"{}\"\n", "UNNAMED"),
-                            }
-                            .serialize(compose)?;
-                            ClientCmd::StringCmd {
-                                cmd: format!("color {} {}", 0, 0),
-                            }
-                            .serialize(compose)?;
-                            // TODO: need default spawn parameters?
-                            ClientCmd::StringCmd {
-                                cmd: format!("spawn {}", ""),
-                            }
-                            .serialize(compose)?;
-                        }
-                        SignOnStage::Begin => {
-                            ClientCmd::StringCmd {
-                                cmd: String::from("begin"),
-                            }
-                            .serialize(compose)?;
-                        }
-                        SignOnStage::Done => {
-                            debug!("SignOn complete");
-                            // TODO: end load screen
-                            self.state.start_time = self.state.time;
-                        }
+                    if new_stage == Done {
+                        debug!("SignOn complete");
+                        // TODO: end load screen
+                        self.state.start_time = self.state.time;
+                    }
+
+                    for cmd in signon_reply(new_stage) {
+                        cmd.serialize(compose)?;
                     }
                 }
 
                 match new_stage {
                     // TODO proper error
                     Not => panic!("SignOnStage::Not in handle_signon"),
-                    // still signing on, advance to the new stage
-                    Prespawn | ClientInfo | Begin => ConnectionState::SignOn(new_stage),
-
-                    // finished signing on, build world renderer
-                    Done => ConnectionState::Connected(WorldRenderer::new(
-                        gfx_state,
-                        self.state.models(),
-                        1,
-                    )),
+                    // advance to the new stage, see finish_signon for the end of sign-on
+                    Prespawn | ClientInfo | Begin | Done => {
+                        self.conn_state = ConnectionState::SignOn(new_stage)
+                    }
                 }
             }
 
             // ignore spurious sign-on messages
-            ConnectionState::Connected { .. } => return Ok(()),
-        };
-
-        self.conn_state = new_conn_state;
+            _ => (),
+        }
 
         Ok(())
     }
 
+    /// Builds the world renderer once the client has finished signing on.
+    fn finish_signon(
+        &mut self,
+        gfx_state: &GraphicsState,
+        console: &mut Console,
+        strings: &Strings,
+    ) {
+        if let ConnectionState::SignOn(SignOnStage::Done) = self.conn_state {
+            // the level's entities have all been sent by the end of sign-on
+            for warning in self.state.limit_warnings() {
+                console.println(strings.format("signon.limit_warning", &[&warning]));
+            }
+
+            self.conn_state =
+                ConnectionState::Connected(WorldRenderer::new(gfx_state, self.state.models(), 1));
+        }
+    }
+
+    /// Applies a server command which only updates the state of the connection.
+    ///
+    /// Commands which need the renderer, the audio output, the console or the VFS are returned to
+    /// the caller unhandled.
+    fn apply_server_cmd(
+        &mut self,
+        cmd: ServerCmd,
+        extensions: bool,
+        kick_vars: KickVars,
+        demo_view_angles: Option<Vector3<Deg<f32>>>,
+    ) -> Result<Option<ServerCmd>, ClientError> {
+        match cmd {
+            ServerCmd::NoOp => (),
+
+            ServerCmd::PlayerData(player_data) => self.state.update_player(player_data),
+
+            ServerCmd::Damage {
+                armor,
+                blood,
+                source,
+            } => self.state.handle_damage(armor, blood, source, kick_vars),
+
+            ServerCmd::FastUpdate(ent_update) => {
+                // first update signals the last sign-on stage
+                self.handle_signon(SignOnStage::Done)?;
+
+                if ent_update.alpha.is_some() {
+                    check_extension(extensions, "entity transparency")?;
+                }
+
+                let ent_id = ent_update.ent_id as usize;
+                self.state.update_entity(ent_id, ent_update)?;
+
+                // patch view angles in demos
+                if let Some(angles) = demo_view_angles {
+                    if ent_id == self.state.view_entity_id() {
+                        self.state.update_view_angles(angles);
+                    }
+                }
+            }
+
+            ServerCmd::FoundSecret => self.state.stats.found_secrets += 1,
+            ServerCmd::Intermission => {
+                self.state.intermission = Some(IntermissionKind::Intermission);
+                self.state.completion_time = Some(self.state.time);
+            }
+            ServerCmd::KilledMonster => self.state.stats.killed_monsters += 1,
+
+            ServerCmd::LightStyle { id, value } => {
+                if id as usize >= self.state.limits.light_styles {
+                    warn!("Ignoring light style {} over the light style limit", id);
+                } else {
+                    trace!("Inserting light style {} with value {}", id, &value);
+                    self.state.light_styles.set(id as usize, &value);
+                }
+            }
+
+            ServerCmd::Particle {
+                origin,
+                direction,
+                count,
+                color,
+            } => {
+                match count {
+                    // if count is 255, this is an explosion
+                    255 => self
+                        .state
+                        .particles
+                        .create_explosion(self.state.time, origin),
+
+                    // otherwise it's an impact
+                    _ => self.state.particles.create_projectile_impact(
+                        self.state.time,
+                        origin,
+                        direction,
+                        color,
+                        count as usize,
+                    ),
+                }
+            }
+
+            ServerCmd::SetAngle { angles } => self.state.set_view_angles(angles),
+
+            ServerCmd::SetView { ent_id } => {
+                if ent_id <= 0 {
+                    Err(ClientError::InvalidViewEntity(ent_id as usize))?;
+                }
+
+                self.state.set_view_entity(ent_id as usize)?;
+            }
+
+            ServerCmd::SignOnStage { stage } => self.handle_signon(stage)?,
+
+            ServerCmd::SpawnBaseline {
+                ent_id,
+                model_id,
+                frame_id,
+                colormap,
+                skin_id,
+                origin,
+                angles,
+            } => {
+                self.state.spawn_entities(
+                    ent_id as usize,
+                    EntityState {
+                        model_id: model_id as usize,
+                        frame_id: frame_id as usize,
+                        colormap,
+                        skin_id: skin_id as usize,
+                        origin,
+                        angles,
+                        effects: EntityEffects::empty(),
+                        alpha: 1.0,
+                    },
+                )?;
+            }
+
+            ServerCmd::SpawnBaseline2 {
+                ent_id,
+                model_id,
+                frame_id,
+                colormap,
+                skin_id,
+                origin,
+                angles,
+                alpha,
+            } => {
+                self.check_fitz_extension(extensions, "svc_spawnbaseline2")?;
+                self.state.spawn_entities(
+                    ent_id as usize,
+                    EntityState {
+                        model_id: model_id as usize,
+                        frame_id: frame_id as usize,
+                        colormap,
+                        skin_id: skin_id as usize,
+                        origin,
+                        angles,
+                        effects: EntityEffects::empty(),
+                        alpha: net::decode_alpha(alpha),
+                    },
+                )?;
+            }
+
+            ServerCmd::SpawnStatic {
+                model_id,
+                frame_id,
+                colormap,
+                skin_id,
+                origin,
+                angles,
+            } => {
+                if self.state.static_entities.len() >= self.state.limits.static_entities {
+                    Err(ClientError::TooManyStaticEntities)?;
+                }
+                self.state
+                    .static_entities
+                    .push(ClientEntity::from_baseline(EntityState {
+                        origin,
+                        angles,
+                        model_id: model_id as usize,
+                        frame_id: frame_id as usize,
+                        colormap,
+                        skin_id: skin_id as usize,
+                        effects: EntityEffects::empty(),
+                        alpha: 1.0,
+                    }));
+            }
+
+            ServerCmd::SpawnStatic2 {
+                model_id,
+                frame_id,
+                colormap,
+                skin_id,
+                origin,
+                angles,
+                alpha,
+            } => {
+                check_extension(extensions, "svc_spawnstatic2")?;
+                if self.state.static_entities.len() >= self.state.limits.static_entities {
+                    Err(ClientError::TooManyStaticEntities)?;
+                }
+                self.state
+                    .static_entities
+                    .push(ClientEntity::from_baseline(EntityState {
+                        origin,
+                        angles,
+                        model_id: model_id as usize,
+                        frame_id: frame_id as usize,
+                        colormap,
+                        skin_id: skin_id as usize,
+                        effects: EntityEffects::empty(),
+                        alpha: net::decode_alpha(alpha),
+                    }));
+            }
+
+            ServerCmd::Time { time } => {
+                self.state.msg_times[1] = self.state.msg_times[0];
+                self.state.msg_times[0] = engine::duration_from_f32(time);
+            }
+
+            ServerCmd::UpdateColors {
+                player_id,
+                new_colors,
+            } => {
+                let player_id = player_id as usize;
+                self.state.check_player_id(player_id)?;
+
+                match self.state.player_info[player_id] {
+                    Some(ref mut info) => {
+                        trace!(
+                            "Player {} (ID {}) colors: {:?} -> {:?}",
+                            info.name,
+                            player_id,
+                            info.colors,
+                            new_colors,
+                        );
+                        info.colors = new_colors;
+                    }
+
+                    None => {
+                        error!(
+                            "Attempted to set colors on nonexistent player with ID {}",
+                            player_id
+                        );
+                    }
+                }
+            }
+
+            ServerCmd::UpdateFrags {
+                player_id,
+                new_frags,
+            } => {
+                let player_id = player_id as usize;
+                self.state.check_player_id(player_id)?;
+
+                match self.state.player_info[player_id] {
+                    Some(ref mut info) => {
+                        trace!(
+                            "Player {} (ID {}) frags: {} -> {}",
+                            &info.name,
+                            player_id,
+                            info.frags,
+                            new_frags
+                        );
+                        info.frags = new_frags as i32;
+                    }
+                    None => {
+                        error!(
+                            "Attempted to set frags on nonexistent player with ID {}",
+                            player_id
+                        );
+                    }
+                }
+            }
+
+            ServerCmd::UpdateName {
+                player_id,
+                new_name,
+            } => {
+                let player_id = player_id as usize;
+                self.state.check_player_id(player_id)?;
+
+                if let Some(ref mut info) = self.state.player_info[player_id] {
+                    // if this player is already connected, it's a name change
+                    debug!("Player {} has changed name to {}", &info.name, &new_name);
+                    info.name = new_name.to_owned();
+                } else {
+                    // if this player is not connected, it's a join
+                    debug!("Player {} with ID {} has joined", &new_name, player_id);
+                    self.state.player_info[player_id] = Some(PlayerInfo {
+                        name: new_name.to_owned(),
+                        colors: PlayerColor::new(0, 0),
+                        frags: 0,
+                    });
+                }
+            }
+
+            ServerCmd::UpdateStat { stat, value } => {
+                debug!("{:?}: {} -> {}", stat, self.state.stats.get(stat), value);
+                self.state.stats.set(stat, value);
+            }
+
+            ServerCmd::ShowLmp { slot, name, x, y } => {
+                check_extension(extensions, "svc_showlmp")?;
+                self.state.show_lmps_mut().show(
+                    slot,
+                    ShowLmp {
+                        name,
+                        x: x as i32,
+                        y: y as i32,
+                    },
+                );
+            }
+            ServerCmd::HideLmp { slot } => {
+                check_extension(extensions, "svc_hidelmp")?;
+                self.state.show_lmps_mut().hide(slot);
+            }
+
+            ServerCmd::SkyBox { name } => {
+                check_extension(extensions, "svc_skybox")?;
+                self.state.set_skybox(name);
+            }
+
+            ServerCmd::Fog {
+                density,
+                color,
+                time,
+            } => {
+                self.check_fitz_extension(extensions, "svc_fog")?;
+                let fog = Fog {
+                    density: density as f32 / 255.0,
+                    color: Vector3::new(color[0] as f32, color[1] as f32, color[2] as f32) / 255.0,
+                };
+                let fade_time = Duration::milliseconds(time.max(0) as i64 * 10);
+                self.state.set_fog(fog, fade_time);
+            }
+
+            cmd => return Ok(Some(cmd)),
+        }
+
+        Ok(None)
+    }
+
     fn parse_server_msg(
         &mut self,
         vfs: &Vfs,
@@ -301,52 +900,97 @@ impl Connection {
         cmds: &mut CmdRegistry,
         console: &mut Console,
         music_player: &mut MusicPlayer,
+        localization: &Localization,
+        strings: &Strings,
+        cvars: &Rc<RefCell<CvarRegistry>>,
         kick_vars: KickVars,
+        chat_vars: &ChatVars,
+        cl_interp: f32,
+        cl_protocol_extensions: f32,
     ) -> Result<ConnectionStatus, ClientError> {
         use ConnectionStatus::*;
 
-        let (msg, demo_view_angles, track_override) = match self.kind {
-            ConnectionKind::Server { ref mut qsock, .. } => {
-                let msg = qsock.recv_msg(match self.conn_state {
-                    // if we're in the game, don't block waiting for messages
-                    ConnectionState::Connected(_) => BlockingMode::NonBlocking,
-
-                    // otherwise, give the server some time to respond
-                    // TODO: might make sense to make this a future or something
-                    ConnectionState::SignOn(_) => BlockingMode::Timeout(Duration::seconds(5)),
-                })?;
+        // tolerate the extensions used by Nehahra and similar mods
+        let extensions = cl_protocol_extensions != 0.0;
+        let supported_protocol = |version: i32| {
+            version == net::PROTOCOL_VERSION as i32
+                || version == net::PROTOCOL_FITZQUAKE
+                || (extensions
+                    && (version == net::PROTOCOL_NEHAHRA_MOVIE || version == net::PROTOCOL_RMQ))
+        };
 
-                (msg, None, None)
+        // nothing else is handled until the content named in the server info has been fetched
+        let resumed = if self.downloads.is_some() {
+            self.keep_alive()?;
+            match self.finish_downloads(vfs, cmds, console, strings, cvars)? {
+                Some(remainder) => Some(remainder),
+                None => return Ok(Maintain),
             }
+        } else {
+            None
+        };
 
-            ConnectionKind::Demo(ref mut demo_srv) => {
-                // only get the next update once we've made it all the way to
-                // the previous one
-                if self.state.time >= self.state.msg_times[0] {
-                    let msg_view = match demo_srv.next() {
-                        Some(v) => v,
-                        None => {
-                            // if there are no commands left in the demo, play
-                            // the next demo if there is one
-                            return Ok(NextDemo);
+        let (msg, demo_view_angles, track_override) = match resumed {
+            Some(remainder) => (remainder, None, None),
+            None => match self.kind {
+                ConnectionKind::Server {
+                    ref mut qsock,
+                    ref mut delay,
+                    ..
+                } => {
+                    let msg = match self.conn_state {
+                        // if we're in the game, don't block waiting for messages, and hold them for
+                        // cl_interp seconds to smooth out jitter
+                        ConnectionState::Connected(_) => {
+                            let msg = qsock.recv_msg(BlockingMode::NonBlocking)?;
+                            if !msg.is_empty() {
+                                delay.push(msg, interp_delay(cl_interp));
+                            }
+
+                            delay.pop().unwrap_or_default()
                         }
+
+                        // otherwise, give the server some time to respond once anything held over
+                        // from the game has been handled
+                        // TODO: might make sense to make this a future or something
+                        ConnectionState::SignOn(_) => match delay.force_pop() {
+                            Some(msg) => msg,
+                            None => qsock.recv_msg(BlockingMode::Timeout(Duration::seconds(5)))?,
+                        },
                     };
 
-                    let mut view_angles = msg_view.view_angles();
-                    // invert entity angles to get the camera direction right.
-                    // yaw is already inverted.
-                    view_angles.z = -view_angles.z;
-
-                    // TODO: we shouldn't have to copy the message here
-                    (
-                        msg_view.message().to_owned(),
-                        Some(view_angles),
-                        demo_srv.track_override(),
-                    )
-                } else {
-                    (Vec::new(), None, demo_srv.track_override())
+                    (msg, None, None)
                 }
-            }
+
+                ConnectionKind::Demo(ref mut demo_srv) => {
+                    // only get the next update once we've made it all the way to
+                    // the previous one
+                    if self.state.time >= self.state.msg_times[0] {
+                        let msg_view = match demo_srv.next() {
+                            Some(v) => v,
+                            None => {
+                                // if there are no commands left in the demo, play
+                                // the next demo if there is one
+                                return Ok(NextDemo);
+                            }
+                        };
+
+                        let mut view_angles = msg_view.view_angles();
+                        // invert entity angles to get the camera direction right.
+                        // yaw is already inverted.
+                        view_angles.z = -view_angles.z;
+
+                        // TODO: we shouldn't have to copy the message here
+                        (
+                            msg_view.message().to_owned(),
+                            Some(view_angles),
+                            demo_srv.track_override(),
+                        )
+                    } else {
+                        (Vec::new(), None, demo_srv.track_override())
+                    }
+                }
+            },
         };
 
         // no data available at this time
@@ -354,17 +998,20 @@ impl Connection {
             return Ok(Maintain);
         }
 
-        let mut reader = BufReader::new(msg.as_slice());
+        let mut reader = Cursor::new(msg.as_slice());
+
+        while let Some(cmd) = ServerCmd::deserialize_with_format(&mut reader, self.wire_format)? {
+            let cmd = match self.apply_server_cmd(cmd, extensions, kick_vars, demo_view_angles)? {
+                Some(cmd) => cmd,
+                None => continue,
+            };
 
-        while let Some(cmd) = ServerCmd::deserialize(&mut reader)? {
             match cmd {
                 // TODO: have an error for this instead of panicking
                 // once all other commands have placeholder handlers, just error
                 // in the wildcard branch
                 ServerCmd::Bad => panic!("Invalid command from server"),
 
-                ServerCmd::NoOp => (),
-
                 ServerCmd::CdTrack { track, .. } => {
                     music_player.play_track(match track_override {
                         Some(t) => t as usize,
@@ -373,93 +1020,52 @@ impl Connection {
                 }
 
                 ServerCmd::CenterPrint { text } => {
-                    // TODO: print to center of screen
-                    warn!("Center print not yet implemented!");
-                    println!("{}", text);
+                    let text = localization.localize(&text).into_owned();
+                    debug!("Center print: {}", text);
+                    self.state.center_print = Some((text, self.state.time));
                 }
 
-                ServerCmd::PlayerData(player_data) => self.state.update_player(player_data),
-
                 ServerCmd::Cutscene { text } => {
+                    let text = localization.localize(&text).into_owned();
                     self.state.intermission = Some(IntermissionKind::Cutscene { text });
                     self.state.completion_time = Some(self.state.time);
                 }
 
-                ServerCmd::Damage {
-                    armor,
-                    blood,
-                    source,
-                } => self.state.handle_damage(armor, blood, source, kick_vars),
-
                 ServerCmd::Disconnect => {
                     return Ok(match self.kind {
                         ConnectionKind::Demo(_) => NextDemo,
-                        ConnectionKind::Server { .. } => Disconnect,
-                    })
-                }
-
-                ServerCmd::FastUpdate(ent_update) => {
-                    // first update signals the last sign-on stage
-                    self.handle_signon(SignOnStage::Done, gfx_state)?;
-
-                    let ent_id = ent_update.ent_id as usize;
-                    self.state.update_entity(ent_id, ent_update)?;
-
-                    // patch view angles in demos
-                    if let Some(angles) = demo_view_angles {
-                        if ent_id == self.state.view_entity_id() {
-                            self.state.update_view_angles(angles);
+                        ConnectionKind::Server { .. } => {
+                            console.println(strings.get("disconnect.server"));
+                            Disconnect
                         }
-                    }
+                    })
                 }
 
                 ServerCmd::Finale { text } => {
+                    let text = localization.localize(&text).into_owned();
                     self.state.intermission = Some(IntermissionKind::Finale { text });
                     self.state.completion_time = Some(self.state.time);
                 }
 
-                ServerCmd::FoundSecret => self.state.stats[ClientStat::FoundSecrets as usize] += 1,
-                ServerCmd::Intermission => {
-                    self.state.intermission = Some(IntermissionKind::Intermission);
-                    self.state.completion_time = Some(self.state.time);
-                }
-                ServerCmd::KilledMonster => {
-                    self.state.stats[ClientStat::KilledMonsters as usize] += 1
-                }
-
-                ServerCmd::LightStyle { id, value } => {
-                    trace!("Inserting light style {} with value {}", id, &value);
-                    let _ = self.state.light_styles.insert(id, value);
-                }
-
-                ServerCmd::Particle {
-                    origin,
-                    direction,
-                    count,
-                    color,
-                } => {
-                    match count {
-                        // if count is 255, this is an explosion
-                        255 => self
-                            .state
-                            .particles
-                            .create_explosion(self.state.time, origin),
-
-                        // otherwise it's an impact
-                        _ => self.state.particles.create_projectile_impact(
-                            self.state.time,
-                            origin,
-                            direction,
-                            color,
-                            count as usize,
-                        ),
+                ServerCmd::Print { text } => {
+                    let text = localization.localize(&text);
+                    let player_names = self
+                        .state
+                        .player_info
+                        .iter()
+                        .filter_map(|info| info.as_ref().map(|i| i.name.as_str()));
+
+                    match chat::parse_chat(&text, player_names) {
+                        Some(ref msg) if chat_vars.is_filtered(msg) => {
+                            debug!("Filtered chat message: {}", text)
+                        }
+                        _ => console.print_alert(&text),
                     }
                 }
 
-                ServerCmd::Print { text } => console.print_alert(&text),
-
                 ServerCmd::ServerInfo {
                     protocol_version,
+                    protocol_flags,
                     max_clients,
                     game_type,
                     message,
@@ -467,54 +1073,66 @@ impl Connection {
                     sound_precache,
                 } => {
                     // check protocol version
-                    if protocol_version != net::PROTOCOL_VERSION as i32 {
+                    if !supported_protocol(protocol_version) {
                         Err(ClientError::UnrecognizedProtocol(protocol_version))?;
                     }
 
+                    // the world model is always first in the precache
+                    if let Some(world) = model_precache.first() {
+                        if !vfs.edition().allows_map(world) {
+                            Err(ClientError::RegisteredMap(world.clone()))?;
+                        }
+                    }
+
+                    // any later coordinates and angles, even in this message, use the new format
+                    self.wire_format = WireFormat::for_protocol(protocol_version, protocol_flags);
+                    let limits = Limits::for_protocol(protocol_version, extensions);
+
+                    // the new server sets watervis for its own map, if at all
+                    let _ = cvars.borrow().set("watervis", "-1");
+
                     console.println(CONSOLE_DIVIDER);
-                    console.println(message);
+                    console.println(localization.localize(&message));
                     console.println(CONSOLE_DIVIDER);
 
-                    let _server_info = ServerInfo {
-                        _max_clients: max_clients,
-                        _game_type: game_type,
-                    };
-
-                    self.state = ClientState::from_server_info(
+                    let downloads = self.start_downloads(
                         vfs,
-                        self.state.mixer.stream(),
-                        max_clients,
-                        model_precache,
-                        sound_precache,
-                    )?;
-
-                    let bonus_cshift =
-                        self.state.color_shifts[ColorShiftCode::Bonus as usize].clone();
-                    cmds.insert_or_replace(
-                        "bf",
-                        Box::new(move |_| {
-                            bonus_cshift.replace(ColorShift {
-                                dest_color: [215, 186, 69],
-                                percent: 50,
-                            });
-                            String::new()
-                        }),
-                    )
-                    .unwrap();
-                }
+                        &cvars.borrow(),
+                        &model_precache,
+                        &sound_precache,
+                    );
 
-                ServerCmd::SetAngle { angles } => self.state.set_view_angles(angles),
+                    match downloads {
+                        // hold the rest of the message until the missing content arrives
+                        Some(downloads) => {
+                            let position = reader.position() as usize;
+                            self.downloads = Some(PendingDownloads {
+                                downloads,
+                                max_clients,
+                                game_type,
+                                limits,
+                                model_precache,
+                                sound_precache,
+                                remainder: msg[position..].to_vec(),
+                                last_keepalive: Instant::now(),
+                            });
+                            return Ok(Maintain);
+                        }
 
-                ServerCmd::SetView { ent_id } => {
-                    if ent_id <= 0 {
-                        Err(ClientError::InvalidViewEntity(ent_id as usize))?;
+                        None => self.load_server_info(
+                            vfs,
+                            cmds,
+                            console,
+                            cvars,
+                            max_clients,
+                            game_type,
+                            limits,
+                            model_precache,
+                            sound_precache,
+                        )?,
                     }
-
-                    self.state.set_view_entity(ent_id as usize)?;
                 }
 
-                ServerCmd::SignOnStage { stage } => self.handle_signon(stage, gfx_state)?,
-
                 ServerCmd::Sound {
                     volume,
                     attenuation,
@@ -538,11 +1156,19 @@ impl Connection {
                         break;
                     }
 
+                    let src = match self.state.sounds.get(vfs, sound_id as usize) {
+                        Some(s) => s,
+                        None => {
+                            warn!("server tried to start nonexistent sound {}", sound_id);
+                            break;
+                        }
+                    };
+
                     let volume = volume.unwrap_or(DEFAULT_SOUND_PACKET_VOLUME);
                     let attenuation = attenuation.unwrap_or(DEFAULT_SOUND_PACKET_ATTENUATION);
                     // TODO: apply volume, attenuation, spatialization
                     self.state.mixer.start_sound(
-                        self.state.sounds[sound_id as usize].clone(),
+                        src,
                         self.state.msg_times[0],
                         Some(entity_id as usize),
                         channel,
@@ -553,165 +1179,79 @@ impl Connection {
                     );
                 }
 
-                ServerCmd::SpawnBaseline {
-                    ent_id,
-                    model_id,
-                    frame_id,
-                    colormap,
-                    skin_id,
-                    origin,
-                    angles,
-                } => {
-                    self.state.spawn_entities(
-                        ent_id as usize,
-                        EntityState {
-                            model_id: model_id as usize,
-                            frame_id: frame_id as usize,
-                            colormap,
-                            skin_id: skin_id as usize,
-                            origin,
-                            angles,
-                            effects: EntityEffects::empty(),
-                        },
-                    )?;
-                }
-
-                ServerCmd::SpawnStatic {
-                    model_id,
-                    frame_id,
-                    colormap,
-                    skin_id,
-                    origin,
-                    angles,
-                } => {
-                    if self.state.static_entities.len() >= MAX_STATIC_ENTITIES {
-                        Err(ClientError::TooManyStaticEntities)?;
-                    }
-                    self.state
-                        .static_entities
-                        .push(ClientEntity::from_baseline(EntityState {
-                            origin,
-                            angles,
-                            model_id: model_id as usize,
-                            frame_id: frame_id as usize,
-                            colormap,
-                            skin_id: skin_id as usize,
-                            effects: EntityEffects::empty(),
-                        }));
-                }
-
                 ServerCmd::SpawnStaticSound {
                     origin,
                     sound_id,
                     volume,
                     attenuation,
                 } => {
-                    self.state.static_sounds.push(StaticSound::new(
-                        &self.state.mixer.stream(),
-                        origin,
-                        self.state.sounds[sound_id as usize].clone(),
-                        volume as f32 / 255.0,
-                        attenuation as f32 / 64.0,
-                        &self.state.listener,
-                    ));
-                }
-
-                ServerCmd::TempEntity { temp_entity } => self.state.spawn_temp_entity(&temp_entity),
-
-                ServerCmd::StuffText { text } => console.stuff_text(text),
-
-                ServerCmd::Time { time } => {
-                    self.state.msg_times[1] = self.state.msg_times[0];
-                    self.state.msg_times[0] = engine::duration_from_f32(time);
-                }
-
-                ServerCmd::UpdateColors {
-                    player_id,
-                    new_colors,
-                } => {
-                    let player_id = player_id as usize;
-                    self.state.check_player_id(player_id)?;
-
-                    match self.state.player_info[player_id] {
-                        Some(ref mut info) => {
-                            trace!(
-                                "Player {} (ID {}) colors: {:?} -> {:?}",
-                                info.name,
-                                player_id,
-                                info.colors,
-                                new_colors,
-                            );
-                            info.colors = new_colors;
-                        }
-
+                    let src = match self.state.sounds.get(vfs, sound_id as usize) {
+                        Some(s) => s,
                         None => {
-                            error!(
-                                "Attempted to set colors on nonexistent player with ID {}",
-                                player_id
+                            warn!(
+                                "server tried to spawn nonexistent static sound {}",
+                                sound_id
                             );
+                            break;
                         }
+                    };
+
+                    if let Some(stream) = self.state.mixer.stream() {
+                        self.state.static_sounds.push(StaticSound::new(
+                            &stream,
+                            origin,
+                            src,
+                            volume as f32 / 255.0,
+                            attenuation as f32 / 64.0,
+                            &self.state.listener,
+                        ));
                     }
                 }
 
-                ServerCmd::UpdateFrags {
-                    player_id,
-                    new_frags,
+                ServerCmd::SpawnStaticSound2 {
+                    origin,
+                    sound_id,
+                    volume,
+                    attenuation,
                 } => {
-                    let player_id = player_id as usize;
-                    self.state.check_player_id(player_id)?;
-
-                    match self.state.player_info[player_id] {
-                        Some(ref mut info) => {
-                            trace!(
-                                "Player {} (ID {}) frags: {} -> {}",
-                                &info.name,
-                                player_id,
-                                info.frags,
-                                new_frags
-                            );
-                            info.frags = new_frags as i32;
-                        }
+                    check_extension(extensions, "svc_spawnstaticsound2")?;
+                    let src = match self.state.sounds.get(vfs, sound_id as usize) {
+                        Some(s) => s,
                         None => {
-                            error!(
-                                "Attempted to set frags on nonexistent player with ID {}",
-                                player_id
+                            warn!(
+                                "server tried to spawn nonexistent static sound {}",
+                                sound_id
                             );
+                            break;
                         }
+                    };
+
+                    if let Some(stream) = self.state.mixer.stream() {
+                        self.state.static_sounds.push(StaticSound::new(
+                            &stream,
+                            origin,
+                            src,
+                            volume as f32 / 255.0,
+                            attenuation as f32 / 64.0,
+                            &self.state.listener,
+                        ));
                     }
                 }
 
-                ServerCmd::UpdateName {
-                    player_id,
-                    new_name,
-                } => {
-                    let player_id = player_id as usize;
-                    self.state.check_player_id(player_id)?;
+                ServerCmd::TempEntity { temp_entity } => self.state.spawn_temp_entity(&temp_entity),
 
-                    if let Some(ref mut info) = self.state.player_info[player_id] {
-                        // if this player is already connected, it's a name change
-                        debug!("Player {} has changed name to {}", &info.name, &new_name);
-                        info.name = new_name.to_owned();
-                    } else {
-                        // if this player is not connected, it's a join
-                        debug!("Player {} with ID {} has joined", &new_name, player_id);
-                        self.state.player_info[player_id] = Some(PlayerInfo {
-                            name: new_name.to_owned(),
-                            colors: PlayerColor::new(0, 0),
-                            frags: 0,
-                        });
+                ServerCmd::StuffText { text } => {
+                    let (text, updates) = teaminfo::split_stuff_text(&text);
+                    for status in updates {
+                        self.state.team_info.update(status, self.state.time);
+                    }
+                    if !text.is_empty() {
+                        console.stuff_text(text);
                     }
-                }
-
-                ServerCmd::UpdateStat { stat, value } => {
-                    debug!(
-                        "{:?}: {} -> {}",
-                        stat, self.state.stats[stat as usize], value
-                    );
-                    self.state.stats[stat as usize] = value;
                 }
 
                 ServerCmd::Version { version } => {
-                    if version != net::PROTOCOL_VERSION as i32 {
+                    if !supported_protocol(version) {
                         // TODO: handle with an error
                         error!(
                             "Incompatible server version: server's is {}, client's is {}",
@@ -722,13 +1262,13 @@ impl Connection {
                     }
                 }
 
-                x => {
-                    debug!("{:?}", x);
-                    unimplemented!();
-                }
+                // skip anything else we don't handle yet rather than aborting the demo
+                x => warn!("Unhandled server command: {:?}", x),
             }
         }
 
+        self.finish_signon(gfx_state, console, strings);
+
         Ok(Maintain)
     }
 
@@ -740,25 +1280,61 @@ impl Connection {
         cmds: &mut CmdRegistry,
         console: &mut Console,
         music_player: &mut MusicPlayer,
+        localization: &Localization,
+        strings: &Strings,
+        cvars: &Rc<RefCell<CvarRegistry>>,
         idle_vars: IdleVars,
         kick_vars: KickVars,
         roll_vars: RollVars,
         bob_vars: BobVars,
+        gun_vars: GunVars,
+        chat_vars: &ChatVars,
+        cl_interp: f32,
         cl_nolerp: f32,
+        cl_protocol_extensions: f32,
         sv_gravity: f32,
+        snd_cachesize: f32,
     ) -> Result<ConnectionStatus, ClientError> {
         debug!("frame time: {}ms", frame_time.num_milliseconds());
 
+        // snd_cachesize is in kilobytes
+        self.state
+            .sounds
+            .set_budget((snd_cachesize.max(0.0) * 1024.0) as usize);
+
         // do this _before_ parsing server messages so that we know when to
         // request the next message from the demo server.
         self.state.advance_time(frame_time);
-        match self.parse_server_msg(vfs, gfx_state, cmds, console, music_player, kick_vars)? {
+        if let ConnectionKind::Server { ref mut delay, .. } = self.kind {
+            delay.advance(frame_time);
+        }
+        match self.parse_server_msg(
+            vfs,
+            gfx_state,
+            cmds,
+            console,
+            music_player,
+            localization,
+            strings,
+            cvars,
+            kick_vars,
+            chat_vars,
+            cl_interp,
+            cl_protocol_extensions,
+        )? {
             ConnectionStatus::Maintain => (),
             // if Disconnect or NextDemo, delegate up the chain
             s => return Ok(s),
         };
 
-        self.state.update_interp_ratio(cl_nolerp);
+        // load a few sounds ahead of time so they don't stall the first time they're played
+        self.state.sounds.prefetch(vfs, SOUND_PREFETCH_PER_FRAME);
+
+        let interp = match self.kind {
+            ConnectionKind::Server { ref delay, .. } => delay.last_hold(),
+            ConnectionKind::Demo(_) => Duration::zero(),
+        };
+        self.state.sync_clock(cl_nolerp, frame_time, interp);
 
         // interpolate entity data and spawn particle effects, lights
         self.state.update_entities()?;
@@ -777,6 +1353,7 @@ impl Connection {
         if let ConnectionKind::Server {
             ref mut qsock,
             ref mut compose,
+            ..
         } = self.kind
         {
             // respond to the server
@@ -786,11 +1363,18 @@ impl Connection {
             }
         }
 
+        if let ConnectionState::Connected(ref mut world) = self.conn_state {
+            // load or remove the skybox if the server or the sky command changed it
+            world.update_skybox(gfx_state, self.state.skybox());
+
+            world.prepare_player_skins(gfx_state, self.state.iter_visible_entities());
+        }
+
         // these all require the player entity to have spawned
         if let ConnectionState::Connected(_) = self.conn_state {
             // update view
             self.state
-                .calc_final_view(idle_vars, kick_vars, roll_vars, bob_vars);
+                .calc_final_view(idle_vars, kick_vars, roll_vars, bob_vars, gun_vars);
 
             // update ear positions
             self.state.update_listener();
@@ -815,9 +1399,15 @@ pub struct Client {
     _output_stream: OutputStream,
     output_stream_handle: OutputStreamHandle,
     music_player: Rc<RefCell<MusicPlayer>>,
+    localization: Localization,
+    strings: Rc<Strings>,
     conn: Rc<RefCell<Option<Connection>>>,
     renderer: ClientRenderer,
+    gpu_timings: Rc<RefCell<GpuTimings>>,
     demo_queue: Rc<RefCell<VecDeque<String>>>,
+
+    // the sum of all frame times, which animates the title screen while disconnected
+    uptime: Duration,
 }
 
 impl Client {
@@ -831,6 +1421,8 @@ impl Client {
         menu: &Menu,
     ) -> Client {
         let conn = Rc::new(RefCell::new(None));
+        let strings = Rc::new(Strings::load(&vfs));
+        let localization = Localization::load(&vfs);
 
         let (stream, handle) = match OutputStream::try_default() {
             Ok(o) => o,
@@ -840,27 +1432,39 @@ impl Client {
 
         // set up overlay/ui toggles
         cmds.borrow_mut()
-            .insert_or_replace(
-                "toggleconsole",
-                cmd_toggleconsole(conn.clone(), input.clone()),
-            )
+            .insert_or_replace("toggleconsole", cmd_toggleconsole(input.clone()))
             .unwrap();
         cmds.borrow_mut()
-            .insert_or_replace("togglemenu", cmd_togglemenu(conn.clone(), input.clone()))
+            .insert_or_replace("togglemenu", cmd_togglemenu(input.clone()))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("copy", cmd_copy(console.clone(), strings.clone()))
             .unwrap();
 
         // set up connection console commands
         cmds.borrow_mut()
             .insert_or_replace(
                 "connect",
-                cmd_connect(conn.clone(), input.clone(), handle.clone()),
+                cmd_connect(
+                    conn.clone(),
+                    cvars.clone(),
+                    input.clone(),
+                    handle.clone(),
+                    strings.clone(),
+                ),
             )
             .unwrap();
         cmds.borrow_mut()
-            .insert_or_replace("reconnect", cmd_reconnect(conn.clone(), input.clone()))
+            .insert_or_replace(
+                "reconnect",
+                cmd_reconnect(conn.clone(), input.clone(), strings.clone()),
+            )
             .unwrap();
         cmds.borrow_mut()
-            .insert_or_replace("disconnect", cmd_disconnect(conn.clone(), input.clone()))
+            .insert_or_replace(
+                "disconnect",
+                cmd_disconnect(conn.clone(), input.clone(), strings.clone()),
+            )
             .unwrap();
 
         // set up demo playback
@@ -885,6 +1489,32 @@ impl Client {
             )
             .unwrap();
 
+        // set up chat
+        cmds.borrow_mut()
+            .insert_or_replace("say", cmd_say(conn.clone(), strings.clone(), false))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("say_team", cmd_say(conn.clone(), strings.clone(), true))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("sky", cmd_sky(conn.clone(), strings.clone()))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("fog", cmd_fog(conn.clone(), strings.clone()))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("freecam", cmd_freecam(conn.clone(), strings.clone()))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("pov", cmd_pov(conn.clone(), strings.clone()))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("ignore", cmd_ignore(cvars.clone()))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("unignore", cmd_unignore(cvars.clone()))
+            .unwrap();
+
         let music_player = Rc::new(RefCell::new(MusicPlayer::new(vfs.clone(), handle.clone())));
         cmds.borrow_mut()
             .insert_or_replace("music", cmd_music(music_player.clone()))
@@ -899,6 +1529,12 @@ impl Client {
             .insert_or_replace("music_resume", cmd_music_resume(music_player.clone()))
             .unwrap();
 
+        // kept across renderer rebuilds so the command stays valid
+        let gpu_timings = Rc::new(RefCell::new(GpuTimings::new()));
+        cmds.borrow_mut()
+            .insert_or_replace("gpuprofile", cmd_gpuprofile(gpu_timings.clone()))
+            .unwrap();
+
         Client {
             vfs,
             cvars,
@@ -908,44 +1544,93 @@ impl Client {
             _output_stream: stream,
             output_stream_handle: handle,
             music_player,
+            localization,
+            strings,
             conn,
-            renderer: ClientRenderer::new(gfx_state, menu),
+            renderer: ClientRenderer::new(gfx_state, menu, gpu_timings.clone()),
+            gpu_timings,
             demo_queue,
+            uptime: Duration::zero(),
         }
     }
 
+    /// Returns whether the client is connected to a server or playing a demo.
+    pub fn connected(&self) -> bool {
+        self.conn.borrow().is_some()
+    }
+
     pub fn disconnect(&mut self) {
         self.conn.replace(None);
         self.input.borrow_mut().set_focus(InputFocus::Console);
     }
 
+    /// Disconnects after an error, printing the reason to the console.
+    pub fn disconnect_with_error(&mut self, error: &ClientError) {
+        let reason = error.localize(&self.strings);
+        self.console
+            .borrow()
+            .println(self.strings.format("disconnect.error", &[&reason]));
+        self.disconnect();
+    }
+
     pub fn frame(
         &mut self,
         frame_time: Duration,
         gfx_state: &GraphicsState,
     ) -> Result<(), ClientError> {
+        self.uptime = self.uptime + frame_time;
+
+        let cl_interp = self.cvar_value("cl_interp")?;
         let cl_nolerp = self.cvar_value("cl_nolerp")?;
+        let cl_protocol_extensions = self.cvar_value("cl_protocol_extensions")?;
         let sv_gravity = self.cvar_value("sv_gravity")?;
+        let snd_cachesize = self.cvar_value("snd_cachesize")?;
+        let volume = self.cvar_value("volume")?;
+        let bgmvolume = self.cvar_value("bgmvolume")?;
         let idle_vars = self.idle_vars()?;
         let kick_vars = self.kick_vars()?;
         let roll_vars = self.roll_vars()?;
         let bob_vars = self.bob_vars()?;
+        let gun_vars = self.gun_vars()?;
+        let chat_vars = self.chat_vars()?;
+
+        // demos are watched rather than played, so they get their own bindings
+        let spectating = matches!(
+            *self.conn.borrow(),
+            Some(Connection {
+                kind: ConnectionKind::Demo(_),
+                ..
+            })
+        );
+        self.input.borrow_mut().set_spectator(spectating);
+        self.music_player.borrow_mut().set_volume(bgmvolume);
 
         let status = match *self.conn.borrow_mut() {
-            Some(ref mut conn) => conn.frame(
-                frame_time,
-                &self.vfs,
-                gfx_state,
-                &mut self.cmds.borrow_mut(),
-                &mut self.console.borrow_mut(),
-                &mut self.music_player.borrow_mut(),
-                idle_vars,
-                kick_vars,
-                roll_vars,
-                bob_vars,
-                cl_nolerp,
-                sv_gravity,
-            )?,
+            Some(ref mut conn) => {
+                conn.state.listener.set_volume(volume);
+                conn.frame(
+                    frame_time,
+                    &self.vfs,
+                    gfx_state,
+                    &mut self.cmds.borrow_mut(),
+                    &mut self.console.borrow_mut(),
+                    &mut self.music_player.borrow_mut(),
+                    &self.localization,
+                    &self.strings,
+                    &self.cvars,
+                    idle_vars,
+                    kick_vars,
+                    roll_vars,
+                    bob_vars,
+                    gun_vars,
+                    &chat_vars,
+                    cl_interp,
+                    cl_nolerp,
+                    cl_protocol_extensions,
+                    sv_gravity,
+                    snd_cachesize,
+                )?
+            }
             None => ConnectionStatus::Disconnect,
         };
 
@@ -964,20 +1649,23 @@ impl Client {
                                 Ok(f) => Some(f),
                                 Err(e) => {
                                     // log the error, dump the demo queue and disconnect
-                                    self.console.borrow_mut().println(format!("{}", e));
+                                    self.console
+                                        .borrow_mut()
+                                        .println(self.strings.format("demo.failed", &[&demo, &e]));
                                     self.demo_queue.borrow_mut().clear();
                                     None
                                 }
                             };
 
                             demo_file.as_mut().and_then(|df| match DemoServer::new(df) {
-                                Ok(d) => Some(Connection {
-                                    kind: ConnectionKind::Demo(d),
-                                    state: ClientState::new(self.output_stream_handle.clone()),
-                                    conn_state: ConnectionState::SignOn(SignOnStage::Prespawn),
-                                }),
+                                Ok(d) => Some(Connection::new(
+                                    ConnectionKind::Demo(d),
+                                    Some(self.output_stream_handle.clone()),
+                                )),
                                 Err(e) => {
-                                    self.console.borrow_mut().println(format!("{}", e));
+                                    self.console
+                                        .borrow_mut()
+                                        .println(self.strings.format("demo.failed", &[&demo, &e]));
                                     self.demo_queue.borrow_mut().clear();
                                     None
                                 }
@@ -1006,6 +1694,21 @@ impl Client {
         Ok(())
     }
 
+    /// Rebuilds every renderer on a new graphics device.
+    ///
+    /// This is used to recover from device loss. Models are kept on the CPU, so the world
+    /// renderer is rebuilt from them without reconnecting.
+    pub fn rebuild_renderers(&mut self, gfx_state: &GraphicsState, menu: &Menu) {
+        self.renderer = ClientRenderer::new(gfx_state, menu, self.gpu_timings.clone());
+
+        if let Some(ref mut conn) = *self.conn.borrow_mut() {
+            if let ConnectionState::Connected(ref mut world) = conn.conn_state {
+                // the skybox is reloaded on the next frame
+                *world = WorldRenderer::new(gfx_state, conn.state.models(), 1);
+            }
+        }
+    }
+
     pub fn render(
         &mut self,
         gfx_state: &GraphicsState,
@@ -1023,10 +1726,12 @@ impl Client {
             gfx_state,
             encoder,
             self.conn.borrow().as_ref(),
+            self.uptime,
             width,
             height,
             fov,
             &cvars,
+            &self.strings,
             &console,
             menu,
             focus,
@@ -1057,18 +1762,39 @@ impl Client {
             Some(Connection {
                 ref mut state,
                 kind: ConnectionKind::Server { ref mut qsock, .. },
+                wire_format,
                 ..
             }) => {
                 let move_cmd = state.handle_input(game_input, frame_time, move_vars, mouse_vars);
                 // TODO: arrayvec here
                 let mut msg = Vec::new();
-                move_cmd.serialize(&mut msg)?;
+                move_cmd.serialize_with_format(&mut msg, wire_format)?;
                 qsock.send_msg_unreliable(&msg)?;
 
                 // clear mouse and impulse
                 game_input.refresh();
             }
 
+            // during demo playback, input only steers the free camera
+            Some(Connection {
+                ref mut state,
+                kind: ConnectionKind::Demo(_),
+                ..
+            }) if state.free_camera() => {
+                if let ClientCmd::Move {
+                    fwd_move,
+                    side_move,
+                    up_move,
+                    ..
+                } = state.handle_input(game_input, frame_time, move_vars, mouse_vars)
+                {
+                    let velocity = Vector3::new(fwd_move as f32, side_move as f32, up_move as f32);
+                    state.move_free_camera(frame_time, velocity);
+                }
+
+                game_input.refresh();
+            }
+
             _ => (),
         }
 
@@ -1085,6 +1811,7 @@ impl Client {
             cl_forwardspeed: self.cvar_value("cl_forwardspeed")?,
             cl_backspeed: self.cvar_value("cl_backspeed")?,
             cl_movespeedkey: self.cvar_value("cl_movespeedkey")?,
+            cl_run: self.cvar_value("cl_run")?,
         })
     }
 
@@ -1110,6 +1837,7 @@ impl Client {
 
     fn mouse_vars(&self) -> Result<MouseVars, ClientError> {
         Ok(MouseVars {
+            freelook: self.cvar_value("freelook")?,
             m_pitch: self.cvar_value("m_pitch")?,
             m_yaw: self.cvar_value("m_yaw")?,
             sensitivity: self.cvar_value("sensitivity")?,
@@ -1131,6 +1859,24 @@ impl Client {
         })
     }
 
+    fn gun_vars(&self) -> Result<GunVars, ClientError> {
+        Ok(GunVars {
+            cl_gunbob: self.cvar_value("cl_gunbob")?,
+            cl_gunsway: self.cvar_value("cl_gunsway")?,
+        })
+    }
+
+    fn chat_vars(&self) -> Result<ChatVars, ClientError> {
+        Ok(ChatVars {
+            cl_mute_all_chat: self.cvar_value("cl_mute_all_chat")?,
+            cl_ignorelist: self
+                .cvars
+                .borrow()
+                .get("cl_ignorelist")
+                .map_err(ClientError::Cvar)?,
+        })
+    }
+
     pub fn view_entity_id(&self) -> Option<usize> {
         match *self.conn.borrow() {
             Some(Connection { ref state, .. }) => Some(state.view_entity_id()),
@@ -1157,18 +1903,11 @@ impl Client {
                 for id in entity_ids.into_iter() {
                     let ent = &state.entities[*id];
 
-                    let msg_origins = [ent.msg_origins[0].into(), ent.msg_origins[1].into()];
+                    let (latest, prev) = (ent.history.latest(), ent.history.previous());
+                    let msg_origins = [latest.origin.into(), prev.origin.into()];
                     let msg_angles_deg = [
-                        [
-                            ent.msg_angles[0][0].0,
-                            ent.msg_angles[0][1].0,
-                            ent.msg_angles[0][2].0,
-                        ],
-                        [
-                            ent.msg_angles[1][0].0,
-                            ent.msg_angles[1][1].0,
-                            ent.msg_angles[1][2].0,
-                        ],
+                        [latest.angles[0].0, latest.angles[1].0, latest.angles[2].0],
+                        [prev.angles[0].0, prev.angles[1].0, prev.angles[2].0],
                     ];
 
                     trace.entities.insert(
@@ -1197,52 +1936,107 @@ impl std::ops::Drop for Client {
 }
 
 // implements the "toggleconsole" command
-fn cmd_toggleconsole(
-    conn: Rc<RefCell<Option<Connection>>>,
-    input: Rc<RefCell<Input>>,
-) -> Box<dyn Fn(&[&str]) -> String> {
+fn cmd_toggleconsole(input: Rc<RefCell<Input>>) -> Box<dyn Fn(&[&str]) -> String> {
     Box::new(move |_| {
-        let focus = input.borrow().focus();
-        match *conn.borrow() {
-            Some(_) => match focus {
-                InputFocus::Game => input.borrow_mut().set_focus(InputFocus::Console),
-                InputFocus::Console => input.borrow_mut().set_focus(InputFocus::Game),
-                InputFocus::Menu => input.borrow_mut().set_focus(InputFocus::Console),
-            },
-            None => match focus {
-                InputFocus::Console => input.borrow_mut().set_focus(InputFocus::Menu),
-                InputFocus::Game => unreachable!(),
-                InputFocus::Menu => input.borrow_mut().set_focus(InputFocus::Console),
-            },
+        let mut input = input.borrow_mut();
+        match input.focus() {
+            InputFocus::Console => {
+                // when disconnected the console is at the bottom of the stack, so show the menu
+                if input.pop_focus().is_none() {
+                    input.push_focus(InputFocus::Menu);
+                }
+            }
+
+            // if the console is under the menu, return to it rather than opening another one
+            _ if input.has_focus(InputFocus::Console) => input.unwind_to(InputFocus::Console),
+            _ => input.push_focus(InputFocus::Console),
         }
+
         String::new()
     })
 }
 
+// implements the "copy" command
+fn cmd_copy(console: Rc<RefCell<Console>>, strings: Rc<Strings>) -> Box<dyn Fn(&[&str]) -> String> {
+    // the context is kept around since on X11 it has to serve the copied text to other programs
+    let clipboard: RefCell<Option<ClipboardContext>> = RefCell::new(None);
+
+    Box::new(move |args| {
+        let count = match args.get(0) {
+            None => DEFAULT_COPY_LINES,
+            Some(arg) => match arg.parse() {
+                Ok(n) => n,
+                Err(_) => return strings.get("cmd.copy.usage").to_owned(),
+            },
+        };
+
+        let mut clipboard = clipboard.borrow_mut();
+        if clipboard.is_none() {
+            match ClipboardContext::new() {
+                Ok(ctx) => *clipboard = Some(ctx),
+                Err(e) => return strings.format("cmd.copy.failed", &[&e]),
+            }
+        }
+
+        let text = console.borrow().output().text(count);
+        let line_count = text.lines().count();
+        match clipboard.as_mut().unwrap().set_contents(text) {
+            Ok(()) => strings.format("cmd.copy.done", &[&line_count]),
+            Err(e) => strings.format("cmd.copy.failed", &[&e]),
+        }
+    })
+}
+
 // implements the "togglemenu" command
-fn cmd_togglemenu(
-    conn: Rc<RefCell<Option<Connection>>>,
-    input: Rc<RefCell<Input>>,
-) -> Box<dyn Fn(&[&str]) -> String> {
+fn cmd_togglemenu(input: Rc<RefCell<Input>>) -> Box<dyn Fn(&[&str]) -> String> {
     Box::new(move |_| {
-        let focus = input.borrow().focus();
-        match *conn.borrow() {
-            Some(_) => match focus {
-                InputFocus::Game => input.borrow_mut().set_focus(InputFocus::Menu),
-                InputFocus::Console => input.borrow_mut().set_focus(InputFocus::Menu),
-                InputFocus::Menu => input.borrow_mut().set_focus(InputFocus::Game),
-            },
-            None => match focus {
-                InputFocus::Console => input.borrow_mut().set_focus(InputFocus::Menu),
-                InputFocus::Game => unreachable!(),
-                InputFocus::Menu => input.borrow_mut().set_focus(InputFocus::Console),
-            },
+        let mut input = input.borrow_mut();
+        match input.focus() {
+            InputFocus::Menu => {
+                // the menu is never at the bottom of the stack, but just in case
+                if input.pop_focus().is_none() {
+                    input.set_focus(InputFocus::Console);
+                }
+            }
+            _ => input.push_focus(InputFocus::Menu),
         }
+
         String::new()
     })
 }
 
-fn connect<A>(server_addrs: A, stream: OutputStreamHandle) -> Result<Connection, ClientError>
+/// Fails if a server command needs the protocol extensions and they are disabled.
+fn check_extension(extensions: bool, name: &'static str) -> Result<(), ClientError> {
+    if extensions {
+        Ok(())
+    } else {
+        Err(ClientError::ProtocolExtensionDisabled(name))
+    }
+}
+
+/// Returns the commands the client sends in reply to reaching a sign-on stage.
+fn signon_reply(stage: SignOnStage) -> Vec<ClientCmd> {
+    let cmds = match stage {
+        SignOnStage::Not => Vec::new(), // TODO this is an error (invalid value)
+        SignOnStage::Prespawn => vec![String::from("prespawn")],
+        SignOnStage::ClientInfo => vec![
+            // TODO: fill in client info here
+            format!("name \"{}\"\n", "UNNAMED"),
+            format!("color {} {}", 0, 0),
+            // TODO: need default spawn parameters?
+            format!("spawn {}", ""),
+        ],
+        SignOnStage::Begin => vec![String::from("begin")],
+        SignOnStage::Done => Vec::new(),
+    };
+
+    cmds.into_iter()
+        .map(|cmd| ClientCmd::StringCmd { cmd })
+        .collect()
+}
+
+/// Performs the connection handshake with a server and returns a socket for the new connection.
+fn connect_qsocket<A>(server_addrs: A, strings: &Strings) -> Result<QSocket, ClientError>
 where
     A: ToSocketAddrs,
 {
@@ -1256,9 +2050,8 @@ where
 
     for attempt in 0..MAX_CONNECT_ATTEMPTS {
         println!(
-            "Connecting...(attempt {} of {})",
-            attempt + 1,
-            MAX_CONNECT_ATTEMPTS
+            "{}",
+            strings.format("connect.attempt", &[&(attempt + 1), &MAX_CONNECT_ATTEMPTS])
         );
         con_sock.send_request(
             Request::connect(net::GAME_NAME, CONNECT_PROTOCOL_VERSION),
@@ -1313,16 +2106,27 @@ where
     new_addr.set_port(port);
 
     // we're done with the connection socket, so turn it into a QSocket with the new address
-    let qsock = con_sock.into_qsocket(new_addr);
+    Ok(con_sock.into_qsocket(new_addr))
+}
+
+fn connect<A>(
+    server_addrs: A,
+    stream: OutputStreamHandle,
+    strings: &Strings,
+) -> Result<Connection, ClientError>
+where
+    A: ToSocketAddrs,
+{
+    let qsock = connect_qsocket(server_addrs, strings)?;
 
-    Ok(Connection {
-        state: ClientState::new(stream),
-        kind: ConnectionKind::Server {
+    Ok(Connection::new(
+        ConnectionKind::Server {
             qsock,
             compose: Vec::new(),
+            delay: DelayBuffer::new(),
         },
-        conn_state: ConnectionState::SignOn(SignOnStage::Prespawn),
-    })
+        Some(stream),
+    ))
 }
 
 // TODO: when an audio device goes down, every command with an
@@ -1333,22 +2137,27 @@ where
 // separate thread so the OS doesn't think the client has gone unresponsive.
 fn cmd_connect(
     conn: Rc<RefCell<Option<Connection>>>,
+    cvars: Rc<RefCell<CvarRegistry>>,
     input: Rc<RefCell<Input>>,
     stream: OutputStreamHandle,
+    strings: Rc<Strings>,
 ) -> Box<dyn Fn(&[&str]) -> String> {
     Box::new(move |args| {
         if args.len() < 1 {
             // TODO: print to console
-            return "usage: connect <server_ip>:<server_port>".to_owned();
+            return strings.get("cmd.connect.usage").to_owned();
         }
 
-        match connect(args[0], stream.clone()) {
+        // the previous server's download mirror doesn't apply to this one
+        let _ = cvars.borrow().set("sv_downloadurl", "");
+
+        match connect(args[0], stream.clone(), &strings) {
             Ok(new_conn) => {
                 conn.replace(Some(new_conn));
                 input.borrow_mut().set_focus(InputFocus::Game);
                 String::new()
             }
-            Err(e) => format!("{}", e),
+            Err(e) => e.localize(&strings),
         }
     })
 }
@@ -1356,6 +2165,7 @@ fn cmd_connect(
 fn cmd_reconnect(
     conn: Rc<RefCell<Option<Connection>>>,
     input: Rc<RefCell<Input>>,
+    strings: Rc<Strings>,
 ) -> Box<dyn Fn(&[&str]) -> String> {
     Box::new(move |_| {
         match *conn.borrow_mut() {
@@ -1366,7 +2176,7 @@ fn cmd_reconnect(
                 String::new()
             }
             // TODO: log message, e.g. "can't reconnect while disconnected"
-            None => "not connected".to_string(),
+            None => strings.get("cmd.not_connected").to_owned(),
         }
     })
 }
@@ -1374,6 +2184,7 @@ fn cmd_reconnect(
 fn cmd_disconnect(
     conn: Rc<RefCell<Option<Connection>>>,
     input: Rc<RefCell<Input>>,
+    strings: Rc<Strings>,
 ) -> Box<dyn Fn(&[&str]) -> String> {
     Box::new(move |_| {
         let connected = conn.borrow().is_some();
@@ -1382,7 +2193,183 @@ fn cmd_disconnect(
             input.borrow_mut().set_focus(InputFocus::Console);
             String::new()
         } else {
-            "not connected".to_string()
+            strings.get("cmd.not_connected").to_owned()
+        }
+    })
+}
+
+/// Implements the `say` and `say_team` commands.
+///
+/// Team messages have macros expanded before they're sent, see [`chat::expand_macros`].
+fn cmd_say(
+    conn: Rc<RefCell<Option<Connection>>>,
+    strings: Rc<Strings>,
+    team: bool,
+) -> Box<dyn Fn(&[&str]) -> String> {
+    let cmd_name = if team { "say_team" } else { "say" };
+
+    Box::new(move |args| {
+        if args.is_empty() {
+            return strings.format("cmd.say.usage", &[&cmd_name]);
+        }
+
+        let mut conn = conn.borrow_mut();
+        let conn = match *conn {
+            Some(ref mut c) => c,
+            None => return strings.get("cmd.not_connected").to_owned(),
+        };
+
+        let text = args.join(" ");
+        let text = if team {
+            chat::expand_macros(&text, &conn.state)
+        } else {
+            text
+        };
+
+        match conn.kind {
+            ConnectionKind::Server {
+                ref mut compose, ..
+            } => {
+                let cmd = ClientCmd::StringCmd {
+                    cmd: chat::say_command(cmd_name, &text),
+                };
+
+                match cmd.serialize(compose) {
+                    Ok(()) => String::new(),
+                    Err(e) => format!("{}", e),
+                }
+            }
+
+            ConnectionKind::Demo(_) => strings.get("cmd.say.demo").to_owned(),
+        }
+    })
+}
+
+fn cmd_sky(
+    conn: Rc<RefCell<Option<Connection>>>,
+    strings: Rc<Strings>,
+) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| {
+        let mut conn = conn.borrow_mut();
+        let conn = match *conn {
+            Some(ref mut c) => c,
+            None => return strings.get("cmd.not_connected").to_owned(),
+        };
+
+        match args.len() {
+            0 => format!(
+                "current skybox is \"{}\"",
+                conn.state.skybox().unwrap_or("")
+            ),
+            1 => {
+                // "none" is accepted for compatibility with FitzQuake configs
+                match args[0] {
+                    "none" => conn.state.set_skybox(""),
+                    name => conn.state.set_skybox(name),
+                }
+                String::new()
+            }
+            _ => strings.get("cmd.sky.usage").to_owned(),
+        }
+    })
+}
+
+fn cmd_fog(
+    conn: Rc<RefCell<Option<Connection>>>,
+    strings: Rc<Strings>,
+) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| {
+        let mut conn = conn.borrow_mut();
+        let conn = match *conn {
+            Some(ref mut c) => c,
+            None => return strings.get("cmd.not_connected").to_owned(),
+        };
+
+        if args.is_empty() {
+            let fog = conn.state.fog_target();
+            return format!(
+                "density {} color {} {} {}\n{}",
+                fog.density,
+                fog.color.x,
+                fog.color.y,
+                fog.color.z,
+                strings.get("cmd.fog.usage")
+            );
+        }
+
+        match Fog::parse(args, conn.state.fog_target()) {
+            Some((fog, fade_time)) => {
+                conn.state.set_fog(fog, fade_time);
+                String::new()
+            }
+            None => strings.get("cmd.fog.usage").to_owned(),
+        }
+    })
+}
+
+fn cmd_freecam(
+    conn: Rc<RefCell<Option<Connection>>>,
+    strings: Rc<Strings>,
+) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |_| {
+        let mut conn = conn.borrow_mut();
+        let state = match *conn {
+            Some(Connection {
+                ref mut state,
+                kind: ConnectionKind::Demo(_),
+                ..
+            }) => state,
+            Some(_) => return strings.format("cmd.not_demo", &[&"freecam"]),
+            None => return strings.get("cmd.not_connected").to_owned(),
+        };
+
+        let free = !state.free_camera();
+        state.set_free_camera(free);
+        match free {
+            true => strings.get("cmd.freecam.on").to_owned(),
+            false => strings.get("cmd.freecam.off").to_owned(),
+        }
+    })
+}
+
+fn cmd_pov(
+    conn: Rc<RefCell<Option<Connection>>>,
+    strings: Rc<Strings>,
+) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| {
+        let client = match args {
+            [] => None,
+            [c] => match c.parse::<u8>() {
+                Ok(c) => Some(c),
+                Err(_) => return strings.get("cmd.pov.usage").to_owned(),
+            },
+            _ => return strings.get("cmd.pov.usage").to_owned(),
+        };
+
+        let mut conn = conn.borrow_mut();
+        let (state, demo_srv) = match *conn {
+            Some(Connection {
+                ref mut state,
+                kind: ConnectionKind::Demo(ref mut demo_srv),
+                ..
+            }) => (state, demo_srv),
+            Some(_) => return strings.format("cmd.not_demo", &[&"pov"]),
+            None => return strings.get("cmd.not_connected").to_owned(),
+        };
+
+        if demo_srv.pov().is_none() {
+            return strings.get("cmd.pov.single").to_owned();
+        }
+
+        match demo_srv.switch_pov(client) {
+            Some(c) => {
+                // client entities follow the world entity
+                if let Err(e) = state.set_view_entity(c as usize + 1) {
+                    return format!("{}", e);
+                }
+                strings.format("cmd.pov.watching", &[&c])
+            }
+            None => strings.format("cmd.pov.missing", &[&client.unwrap_or(0)]),
         }
     })
 }
@@ -1398,21 +2385,31 @@ fn cmd_playdemo(
             return "usage: playdemo [DEMOFILE]".to_owned();
         }
 
-        let mut demo_file = match vfs.open(format!("{}.dem", args[0])) {
+        // multi-view demos must be named in full, regular demos may leave out the extension
+        let is_mvd = args[0].ends_with(".mvd");
+        let demo_name = match is_mvd {
+            true => args[0].to_owned(),
+            false => format!("{}.dem", args[0]),
+        };
+
+        let mut demo_file = match vfs.open(demo_name) {
             Ok(f) => f,
             Err(e) => return format!("{}", e),
         };
 
-        let demo_server = match DemoServer::new(&mut demo_file) {
+        let demo_server = match is_mvd {
+            true => DemoServer::from_mvd(&mut demo_file),
+            false => DemoServer::new(&mut demo_file),
+        };
+        let demo_server = match demo_server {
             Ok(d) => d,
             Err(e) => return format!("{}", e),
         };
 
-        conn.replace(Some(Connection {
-            state: ClientState::new(stream.clone()),
-            kind: ConnectionKind::Demo(demo_server),
-            conn_state: ConnectionState::SignOn(SignOnStage::Prespawn),
-        }));
+        conn.replace(Some(Connection::new(
+            ConnectionKind::Demo(demo_server),
+            Some(stream.clone()),
+        )));
 
         input.borrow_mut().set_focus(InputFocus::Game);
         String::new()
@@ -1448,11 +2445,10 @@ fn cmd_startdemos(
             Err(e) => return format!("{}", e),
         };
 
-        conn.replace(Some(Connection {
-            state: ClientState::new(stream.clone()),
-            kind: ConnectionKind::Demo(demo_server),
-            conn_state: ConnectionState::SignOn(SignOnStage::Prespawn),
-        }));
+        conn.replace(Some(Connection::new(
+            ConnectionKind::Demo(demo_server),
+            Some(stream.clone()),
+        )));
 
         input.borrow_mut().set_focus(InputFocus::Game);
 
@@ -1477,6 +2473,11 @@ fn cmd_music(music_player: Rc<RefCell<MusicPlayer>>) -> Box<dyn Fn(&[&str]) -> S
     })
 }
 
+// implements the "gpuprofile" command, which prints the GPU time taken by each render pass
+fn cmd_gpuprofile(gpu_timings: Rc<RefCell<GpuTimings>>) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |_| gpu_timings.borrow().report())
+}
+
 fn cmd_music_stop(music_player: Rc<RefCell<MusicPlayer>>) -> Box<dyn Fn(&[&str]) -> String> {
     Box::new(move |_| {
         music_player.borrow_mut().stop();
@@ -21,6 +21,39 @@
 use crate::common::console::CvarRegistry;
 
 pub fn register_cvars(cvars: &CvarRegistry) {
+    cvars.register_archive("cl_gun_fovscale", "1").unwrap();
+    cvars.register_archive("crosshair", "1").unwrap();
+    cvars.register_archive("crosshaircolor", "15").unwrap();
+    cvars.register_archive("crosshairsize", "1").unwrap();
+    cvars.register("gl_cshiftpercent", "100").unwrap();
+    cvars.register_archive("gl_flashblend", "0").unwrap();
+    cvars
+        .register_archive("gl_texturemode", "GL_NEAREST_MIPMAP_LINEAR")
+        .unwrap();
+    cvars.register_archive("hud_teaminfo", "1").unwrap();
+    cvars.register_archive("r_anisotropy", "1").unwrap();
+    cvars.register("r_drawflat", "0").unwrap();
+    cvars.register_archive("r_drawviewmodel", "1").unwrap();
+    cvars.register_archive("r_dynamic", "1").unwrap();
+    cvars.register("r_gpuprofile", "0").unwrap();
+    cvars.register_archive("r_lerplightstyles", "1").unwrap();
     cvars.register("r_lightmap", "0").unwrap();
-    cvars.register("r_msaa_samples", "4").unwrap();
+    cvars.register_archive("r_msaa", "4").unwrap();
+    cvars.register("r_nocull", "0").unwrap();
+    cvars.register("r_novis", "0").unwrap();
+    cvars.register_archive("r_particles", "1").unwrap();
+    cvars.register_archive("r_scale", "1").unwrap();
+    cvars.register_archive("r_scaletarget", "0").unwrap();
+    cvars.register("r_shadows", "0").unwrap();
+    cvars.register("r_showbboxes", "0").unwrap();
+    cvars.register("r_showtris", "0").unwrap();
+    cvars.register_archive("r_softwarelook", "0").unwrap();
+    cvars.register("r_speeds", "0").unwrap();
+    cvars.register_archive("r_wateralpha", "1").unwrap();
+    cvars.register_archive("r_waterwarp", "1").unwrap();
+    cvars.register("scr_centertime", "2").unwrap();
+    cvars.register_archive("scr_sbarscale", "2").unwrap();
+    cvars.register_archive("scr_showfps", "0").unwrap();
+    cvars.register_archive("viewsize", "100").unwrap();
+    cvars.register("watervis", "-1").unwrap();
 }
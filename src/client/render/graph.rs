@@ -0,0 +1,288 @@
+// Copyright © 2020 Cormac O'Brien.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A small frame graph.
+//!
+//! Each frame, the renderer declares its passes along with the target each one draws into and
+//! the attachments it samples. The graph orders the passes so that every attachment is written
+//! before it is read, and begins each render pass with the right attachments: the first pass
+//! drawing into a target clears it, and later passes load its contents.
+
+use std::collections::HashSet;
+
+use crate::client::render::{
+    profile::GpuProfiler,
+    target::{RenderPassBuilder, RenderTarget},
+    GraphicsState,
+};
+
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FrameGraphError {
+    #[error("Pass {pass} reads {attachment:?}, which no pass writes")]
+    MissingWriter {
+        pass: &'static str,
+        attachment: Attachment,
+    },
+    #[error("Passes have a dependency cycle involving {0}")]
+    Cycle(&'static str),
+}
+
+/// An attachment written by one pass and possibly sampled by later ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Attachment {
+    Diffuse,
+    Normal,
+    Light,
+    Depth,
+    /// The lit scene.
+    Color,
+    /// The final image, before it is blitted to the swap chain.
+    Final,
+}
+
+/// The render target a pass draws into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PassTarget {
+    /// The G-buffer, i.e. `InitialPassTarget`.
+    Initial,
+    Deferred,
+    Final,
+}
+
+impl PassTarget {
+    /// Returns the attachments written by passes drawing into this target.
+    pub fn attachments(&self) -> &'static [Attachment] {
+        match self {
+            PassTarget::Initial => &[
+                Attachment::Diffuse,
+                Attachment::Normal,
+                Attachment::Light,
+                Attachment::Depth,
+            ],
+            PassTarget::Deferred => &[Attachment::Color],
+            PassTarget::Final => &[Attachment::Final],
+        }
+    }
+
+    fn render_pass_builder<'a>(&self, state: &'a GraphicsState) -> RenderPassBuilder<'a> {
+        match self {
+            PassTarget::Initial => state.initial_pass_target().render_pass_builder(),
+            PassTarget::Deferred => state.deferred_pass_target().render_pass_builder(),
+            PassTarget::Final => state.final_pass_target().render_pass_builder(),
+        }
+    }
+}
+
+/// Records a pass's commands.
+///
+/// The function should begin its render pass with
+/// `encoder.begin_render_pass(&builder.descriptor())`. The pass only lives for the duration of the
+/// call, so the renderers it draws with can be borrowed for the life of the graph.
+type RecordFn<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &RenderPassBuilder) + 'a>;
+
+struct FramePass<'a> {
+    name: &'static str,
+    target: PassTarget,
+    reads: &'static [Attachment],
+    record: RecordFn<'a>,
+}
+
+/// The passes making up a single frame.
+pub struct FrameGraph<'a> {
+    passes: Vec<FramePass<'a>>,
+}
+
+impl<'a> FrameGraph<'a> {
+    pub fn new() -> FrameGraph<'a> {
+        FrameGraph { passes: Vec::new() }
+    }
+
+    /// Declares a pass drawing into `target` and sampling the attachments in `reads`.
+    ///
+    /// Passes drawing into the same target run in the order they were added.
+    pub fn add_pass<F>(
+        &mut self,
+        name: &'static str,
+        target: PassTarget,
+        reads: &'static [Attachment],
+        record: F,
+    ) where
+        F: FnOnce(&mut wgpu::CommandEncoder, &RenderPassBuilder) + 'a,
+    {
+        self.passes.push(FramePass {
+            name,
+            target,
+            reads,
+            record: Box::new(record),
+        });
+    }
+
+    /// Records every pass into `encoder` in dependency order.
+    ///
+    /// If a `profiler` is given, each pass is timed on the GPU.
+    pub fn execute(
+        self,
+        state: &GraphicsState,
+        encoder: &mut wgpu::CommandEncoder,
+        mut profiler: Option<&mut GpuProfiler>,
+    ) -> Result<(), FrameGraphError> {
+        let order = schedule(
+            &self
+                .passes
+                .iter()
+                .map(|p| (p.name, p.target, p.reads))
+                .collect::<Vec<_>>(),
+        )?;
+
+        let mut passes = self.passes.into_iter().map(Some).collect::<Vec<_>>();
+        let mut begun = HashSet::new();
+        for pass_id in order {
+            let pass = passes[pass_id].take().unwrap();
+            let mut builder = pass.target.render_pass_builder(state);
+
+            // don't clear what an earlier pass drew into the same target
+            if !begun.insert(pass.target) {
+                builder = builder.load_existing();
+            }
+
+            debug!("Recording {} pass", pass.name);
+            let timestamp = profiler
+                .as_mut()
+                .and_then(|p| p.begin_pass(encoder, pass.name));
+            (pass.record)(encoder, &builder);
+            if let (Some(p), Some(t)) = (profiler.as_ref(), timestamp) {
+                p.end_pass(encoder, t);
+            }
+        }
+
+        if let Some(p) = profiler {
+            p.resolve(encoder);
+        }
+
+        Ok(())
+    }
+}
+
+// Returns the indices of `passes` in an order where every attachment is written before it is
+// read. Passes writing the same target keep their relative order, and otherwise passes run in the
+// order they were declared.
+fn schedule(
+    passes: &[(&'static str, PassTarget, &'static [Attachment])],
+) -> Result<Vec<usize>, FrameGraphError> {
+    // deps[i] holds the passes that must run before pass i
+    let mut deps = vec![Vec::new(); passes.len()];
+    for (id, &(name, target, reads)) in passes.iter().enumerate() {
+        for attachment in reads {
+            let writers = passes
+                .iter()
+                .enumerate()
+                .filter(|(other, (_, t, _))| *other != id && t.attachments().contains(attachment))
+                .map(|(other, _)| other)
+                .collect::<Vec<_>>();
+
+            if writers.is_empty() {
+                return Err(FrameGraphError::MissingWriter {
+                    pass: name,
+                    attachment: *attachment,
+                });
+            }
+
+            deps[id].extend(writers);
+        }
+
+        // earlier passes into the same target run first
+        deps[id].extend((0..id).filter(|&other| passes[other].1 == target));
+    }
+
+    let mut order = Vec::with_capacity(passes.len());
+    let mut done = vec![false; passes.len()];
+    while order.len() < passes.len() {
+        let next = (0..passes.len())
+            .find(|&id| !done[id] && deps[id].iter().all(|&dep| done[dep]))
+            .ok_or_else(|| {
+                let stuck = (0..passes.len()).find(|&id| !done[id]).unwrap();
+                FrameGraphError::Cycle(passes[stuck].0)
+            })?;
+
+        done[next] = true;
+        order.push(next);
+    }
+
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_declaration_order() {
+        let passes = [
+            ("world", PassTarget::Initial, &[][..]),
+            (
+                "lighting",
+                PassTarget::Deferred,
+                &[Attachment::Diffuse, Attachment::Depth][..],
+            ),
+            ("post", PassTarget::Final, &[Attachment::Color][..]),
+            ("ui", PassTarget::Final, &[][..]),
+        ];
+
+        assert_eq!(schedule(&passes).unwrap(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_schedule_reorders_readers() {
+        // passes reading the world are declared before the passes that draw it
+        let passes = [
+            ("post", PassTarget::Final, &[Attachment::Color][..]),
+            ("ui", PassTarget::Final, &[][..]),
+            ("lighting", PassTarget::Deferred, &[Attachment::Light][..]),
+            ("world", PassTarget::Initial, &[][..]),
+        ];
+
+        assert_eq!(schedule(&passes).unwrap(), vec![3, 2, 0, 1]);
+    }
+
+    #[test]
+    fn test_schedule_missing_writer() {
+        let passes = [("post", PassTarget::Final, &[Attachment::Color][..])];
+
+        assert_eq!(
+            schedule(&passes),
+            Err(FrameGraphError::MissingWriter {
+                pass: "post",
+                attachment: Attachment::Color,
+            })
+        );
+    }
+
+    #[test]
+    fn test_schedule_cycle() {
+        let passes = [
+            ("a", PassTarget::Initial, &[Attachment::Color][..]),
+            ("b", PassTarget::Deferred, &[Attachment::Depth][..]),
+        ];
+
+        assert_eq!(schedule(&passes), Err(FrameGraphError::Cycle("a")));
+    }
+}
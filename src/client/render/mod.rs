@@ -27,7 +27,9 @@
 ///   - Inputs:
 ///     - `AliasPipeline`
 ///     - `BrushPipeline`
+///     - `SkyboxPipeline`
 ///     - `SpritePipeline`
+///     - `WarpPipeline`
 ///   - Output: `InitialPassTarget`
 /// - Deferred lighting pass
 ///   - Inputs:
@@ -43,12 +45,18 @@
 ///   - Inputs:
 ///     - `BlitPipeline`
 ///   - Output: `SwapChainTarget`
+///
+/// Except for the blit, the stages are declared each frame as passes of a `FrameGraph`, which
+/// orders them by the attachments they read and write.
 // mod atlas;
 mod blit;
 mod cvars;
 mod error;
+mod graph;
 mod palette;
 mod pipeline;
+mod profile;
+mod stats;
 mod target;
 mod ui;
 mod uniform;
@@ -60,11 +68,12 @@ pub use error::{RenderError, RenderErrorKind};
 pub use palette::Palette;
 pub use pipeline::Pipeline;
 pub use postprocess::PostProcessRenderer;
+pub use profile::GpuTimings;
 pub use target::{RenderTarget, RenderTargetResolve, SwapChainTarget};
 pub use ui::{hud::HudState, UiOverlay, UiRenderer, UiState};
 pub use world::{
     deferred::{DeferredRenderer, DeferredUniforms, PointLight},
-    Camera, WorldRenderer,
+    Camera, Viewmodel, WorldRenderer,
 };
 
 use std::{
@@ -79,37 +88,55 @@ use crate::{
     client::{
         entity::MAX_LIGHTS,
         input::InputFocus,
+        lightstyle::LightStyleLerp,
         menu::Menu,
         render::{
             blit::BlitPipeline,
+            graph::{Attachment, FrameGraph, PassTarget},
+            profile::GpuProfiler,
+            stats::{FrameRate, RenderStats},
             target::{DeferredPassTarget, FinalPassTarget, InitialPassTarget},
-            ui::{glyph::GlyphPipeline, quad::QuadPipeline},
+            ui::{
+                draw_list::{DrawList2D, ScreenRect},
+                glyph::GlyphPipeline,
+                hud::{self, Crosshair, CrosshairStyle},
+                quad::QuadPipeline,
+            },
             uniform::DynamicUniformBuffer,
+            warp::WarpPipeline,
             world::{
                 alias::AliasPipeline,
+                bbox::BboxPipeline,
                 brush::BrushPipeline,
+                corona::CoronaPipeline,
                 deferred::DeferredPipeline,
                 particle::ParticlePipeline,
                 postprocess::{self, PostProcessPipeline},
+                shadow::ShadowPipeline,
+                skybox::SkyboxPipeline,
                 sprite::SpritePipeline,
                 EntityUniforms,
             },
         },
+        strings::Strings,
         Connection, ConnectionKind,
     },
     common::{
         console::{Console, CvarRegistry},
+        dds::DdsFormat,
+        engine,
+        mission_pack::MissionPack,
         model::Model,
-        net::SignOnStage,
+        net::{GameType, SignOnStage},
         vfs::Vfs,
-        wad::Wad,
+        wad::{QPic, Wad},
     },
 };
 
 use super::ConnectionState;
 use bumpalo::Bump;
 use cgmath::{Deg, InnerSpace, Vector3, Zero};
-use chrono::{DateTime, Duration, Utc};
+use chrono::Duration;
 use failure::Error;
 
 const DEPTH_ATTACHMENT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
@@ -119,7 +146,233 @@ const LIGHT_ATTACHMENT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8U
 
 const DIFFUSE_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
 const FULLBRIGHT_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
-const LIGHTMAP_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+// wgpu has no 3-channel formats, so RGB lightmaps are padded to RGBA
+const LIGHTMAP_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// The MSAA sample counts accepted by `r_msaa`.
+pub const MSAA_SAMPLE_COUNTS: &[u32] = &[1, 2, 4, 8];
+const DEFAULT_MSAA_SAMPLE_COUNT: u32 = 4;
+
+/// The anisotropic filtering levels accepted by `r_anisotropy`.
+pub const ANISOTROPY_LEVELS: &[u8] = &[1, 2, 4, 8, 16];
+
+/// The smallest fraction of the display resolution the 3D scene is rendered at.
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+
+// the largest resolution scale used with `r_softwarelook`
+const SOFTWARE_LOOK_MAX_SCALE: f32 = 0.5;
+
+// the change in resolution scale per frame when `r_scaletarget` is set
+const RENDER_SCALE_STEP: f32 = 0.05;
+
+/// The largest width or height of a 2D texture loaded from a file.
+///
+/// wgpu 0.8 doesn't report the device's texture size limits, so this is the limit every WebGPU
+/// implementation must support.
+pub const MAX_TEXTURE_DIMENSION: u32 = 8192;
+
+/// Width and height of the image substituted for missing pictures.
+const PLACEHOLDER_QPIC_SIZE: u32 = 16;
+
+/// Returns the MSAA sample count set by `r_msaa`.
+///
+/// Values not listed in `MSAA_SAMPLE_COUNTS` fall back to 4 samples.
+pub fn msaa_sample_count(cvars: &CvarRegistry) -> u32 {
+    let samples = cvars.get_value("r_msaa").unwrap_or(0.0) as u32;
+    if MSAA_SAMPLE_COUNTS.contains(&samples) {
+        samples
+    } else {
+        DEFAULT_MSAA_SAMPLE_COUNT
+    }
+}
+
+/// Returns whether `r_softwarelook` is set.
+///
+/// The software look imitates the original software renderer: the 3D scene is rendered at no more
+/// than half the display resolution and scaled up without filtering, world textures are drawn with
+/// `GL_NEAREST_MIPMAP_NEAREST`, and the final image is dithered and reduced to the palette.
+pub fn software_look_enabled(cvars: &CvarRegistry) -> bool {
+    cvars.get_value("r_softwarelook").unwrap_or(0.0) != 0.0
+}
+
+/// Returns the anisotropic filtering level set by `r_anisotropy`.
+///
+/// The value is rounded down to one of `ANISOTROPY_LEVELS`.
+pub fn anisotropy_level(cvars: &CvarRegistry) -> u8 {
+    let level = cvars.get_value("r_anisotropy").unwrap_or(1.0);
+    ANISOTROPY_LEVELS
+        .iter()
+        .copied()
+        .take_while(|&l| l as f32 <= level)
+        .last()
+        .unwrap_or(1)
+}
+
+/// How world textures are filtered, as selected by `gl_texturemode` and `r_anisotropy`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureFilter {
+    mag_filter: wgpu::FilterMode,
+    min_filter: wgpu::FilterMode,
+    // `None` if textures are drawn without mipmaps
+    mipmap_filter: Option<wgpu::FilterMode>,
+    anisotropy: u8,
+}
+
+impl TextureFilter {
+    /// Returns the texture filter set by `gl_texturemode` and `r_anisotropy`.
+    ///
+    /// `gl_texturemode` takes the name of an OpenGL filter mode, such as `GL_NEAREST` or
+    /// `GL_LINEAR_MIPMAP_LINEAR`. Unknown modes fall back to `GL_NEAREST_MIPMAP_LINEAR`. Both
+    /// cvars are ignored if `r_softwarelook` is set.
+    pub fn from_cvars(cvars: &CvarRegistry) -> TextureFilter {
+        if software_look_enabled(cvars) {
+            return parse_texture_mode("GL_NEAREST_MIPMAP_NEAREST").unwrap();
+        }
+
+        let mode = cvars
+            .get("gl_texturemode")
+            .ok()
+            .and_then(|mode| parse_texture_mode(&mode))
+            .unwrap_or_default();
+
+        TextureFilter {
+            anisotropy: anisotropy_level(cvars),
+            ..mode
+        }
+    }
+
+    fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("world texture sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter.unwrap_or(wgpu::FilterMode::Nearest),
+            lod_min_clamp: 0.0,
+            // without mipmapping, always sample the full-size image
+            lod_max_clamp: match self.mipmap_filter {
+                Some(_) => 1000.0,
+                None => 0.0,
+            },
+            compare: None,
+            anisotropy_clamp: NonZeroU8::new(self.anisotropy).filter(|a| a.get() > 1),
+            ..Default::default()
+        })
+    }
+}
+
+impl Default for TextureFilter {
+    fn default() -> Self {
+        TextureFilter {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: Some(wgpu::FilterMode::Linear),
+            anisotropy: 1,
+        }
+    }
+}
+
+// Returns the filter named by an OpenGL texture mode, without anisotropic filtering.
+//
+// As in GLQuake, the magnification filter of the mipmapped modes matches their minification
+// filter.
+fn parse_texture_mode(mode: &str) -> Option<TextureFilter> {
+    use wgpu::FilterMode::{Linear, Nearest};
+
+    let (filter, mipmap_filter) = match mode.to_ascii_uppercase().as_str() {
+        "GL_NEAREST" => (Nearest, None),
+        "GL_LINEAR" => (Linear, None),
+        "GL_NEAREST_MIPMAP_NEAREST" => (Nearest, Some(Nearest)),
+        "GL_LINEAR_MIPMAP_NEAREST" => (Linear, Some(Nearest)),
+        "GL_NEAREST_MIPMAP_LINEAR" => (Nearest, Some(Linear)),
+        "GL_LINEAR_MIPMAP_LINEAR" => (Linear, Some(Linear)),
+        _ => return None,
+    };
+
+    Some(TextureFilter {
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_filter,
+        anisotropy: 1,
+    })
+}
+
+/// Returns `size` scaled by `scale`, which is never smaller than 1x1.
+pub fn scaled_size(size: Extent2d, scale: f32) -> Extent2d {
+    Extent2d {
+        width: ((size.width as f32 * scale).round() as u32).max(1),
+        height: ((size.height as f32 * scale).round() as u32).max(1),
+    }
+}
+
+/// Chooses the resolution the 3D scene is rendered at.
+///
+/// `r_scale` is the largest fraction of the display resolution to render at, and is capped at one
+/// half with `r_softwarelook`. If `r_scaletarget` is a positive frame rate, the scale is lowered
+/// while frames take longer than that rate allows and raised again once there's time to spare. The
+/// HUD and menus are always drawn at the display resolution.
+pub struct RenderScale {
+    scale: f32,
+}
+
+impl RenderScale {
+    pub fn new() -> RenderScale {
+        RenderScale { scale: 1.0 }
+    }
+
+    /// Updates the scale after a frame which took `frame_duration` and returns the new scale.
+    pub fn update(&mut self, cvars: &CvarRegistry, frame_duration: Duration) -> f32 {
+        let mut max_scale = cvars
+            .get_value("r_scale")
+            .unwrap()
+            .max(MIN_RENDER_SCALE)
+            .min(1.0);
+        if software_look_enabled(cvars) {
+            max_scale = max_scale.min(SOFTWARE_LOOK_MAX_SCALE);
+        }
+
+        // the host never runs frames faster than host_maxfps, so there's no point aiming higher
+        let target_fps = cvars
+            .get_value("r_scaletarget")
+            .unwrap()
+            .min(cvars.get_value("host_maxfps").unwrap_or(std::f32::INFINITY));
+
+        self.scale = if target_fps > 0.0 {
+            adjust_render_scale(
+                self.scale,
+                max_scale,
+                engine::duration_to_f32(frame_duration),
+                1.0 / target_fps,
+            )
+        } else {
+            max_scale
+        };
+
+        self.scale
+    }
+}
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        RenderScale::new()
+    }
+}
+
+// Steps `scale` toward the largest value that keeps frames within `budget` seconds. Frames a bit
+// faster than the budget leave the scale alone so it doesn't flip back and forth.
+fn adjust_render_scale(scale: f32, max_scale: f32, frame_time: f32, budget: f32) -> f32 {
+    let scale = if frame_time > budget * 1.05 {
+        scale - RENDER_SCALE_STEP
+    } else if frame_time < budget * 0.85 {
+        scale + RENDER_SCALE_STEP
+    } else {
+        scale
+    };
+
+    scale.max(MIN_RENDER_SCALE).min(max_scale)
+}
 
 /// Create a `wgpu::TextureDescriptor` appropriate for the provided texture data.
 pub fn texture_descriptor<'a>(
@@ -143,6 +396,85 @@ pub fn texture_descriptor<'a>(
     }
 }
 
+/// Returns the number of levels in a full mip chain for a texture of the given dimensions.
+pub fn full_mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+// Halves the dimensions of an image by averaging each 2x2 block of texels. A dimension of 1 is
+// left as is, and the last row or column of an odd dimension is dropped.
+fn downsample(data: &[u8], width: u32, height: u32, stride: u32) -> Vec<u8> {
+    let (width, height, stride) = (width as usize, height as usize, stride as usize);
+    let out_width = (width / 2).max(1);
+    let out_height = (height / 2).max(1);
+
+    let mut out = Vec::with_capacity(out_width * out_height * stride);
+    for y in 0..out_height {
+        let rows = [2 * y, (2 * y + 1).min(height - 1)];
+        for x in 0..out_width {
+            let cols = [2 * x, (2 * x + 1).min(width - 1)];
+            for c in 0..stride {
+                let mut sum = 0;
+                for &row in rows.iter() {
+                    for &col in cols.iter() {
+                        sum += data[(row * width + col) * stride + c] as u32;
+                    }
+                }
+                out.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+
+    out
+}
+
+// Uploads `data` to the first mip level of layer `layer` of `texture`, then fills in the rest of
+// its `mip_level_count` levels by repeatedly downsampling it.
+fn write_texture_levels(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    layer: u32,
+    width: u32,
+    height: u32,
+    mip_level_count: u32,
+    data: &TextureData,
+) {
+    let stride = data.stride();
+    let mut level_data = Cow::Borrowed(data.data());
+    let (mut level_width, mut level_height) = (width, height);
+
+    for mip_level in 0..mip_level_count {
+        if mip_level > 0 {
+            level_data = Cow::Owned(downsample(&level_data, level_width, level_height, stride));
+            level_width = (level_width / 2).max(1);
+            level_height = (level_height / 2).max(1);
+        }
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
+            },
+            &level_data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(level_width * stride),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: level_width,
+                height: level_height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
 pub fn create_texture<'a>(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
@@ -157,26 +489,131 @@ pub fn create_texture<'a>(
         width,
         height
     );
-    let texture = device.create_texture(&texture_descriptor(label, width, height, data.format()));
-    queue.write_texture(
-        wgpu::ImageCopyTexture {
-            texture: &texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-        },
-        data.data(),
-        wgpu::ImageDataLayout {
-            offset: 0,
-            bytes_per_row: NonZeroU32::new(width * data.stride()),
-            rows_per_image: None,
-        },
-        wgpu::Extent3d {
+    let mip_level_count = data.mip_level_count(width, height);
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        mip_level_count,
+        ..texture_descriptor(label, width, height, data.format())
+    });
+    write_texture_levels(queue, &texture, 0, width, height, mip_level_count, data);
+
+    texture
+}
+
+/// Creates a 2D texture array with one layer per element of `layers`.
+///
+/// Every layer must have the given dimensions and the same format.
+pub fn create_texture_array<'a>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: Option<&'a str>,
+    width: u32,
+    height: u32,
+    layers: &[TextureData],
+) -> wgpu::Texture {
+    assert!(!layers.is_empty());
+    let format = layers[0].format();
+    trace!(
+        "Creating texture array ({:?}: {}x{}x{})",
+        format,
+        width,
+        height,
+        layers.len()
+    );
+
+    let mip_level_count = layers[0].mip_level_count(width, height);
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
             width,
             height,
-            depth_or_array_layers: 1,
+            depth_or_array_layers: layers.len() as u32,
         },
+        mip_level_count,
+        ..texture_descriptor(label, width, height, format)
+    });
+
+    for (layer, data) in layers.iter().enumerate() {
+        assert_eq!(data.format(), format);
+        write_texture_levels(
+            queue,
+            &texture,
+            layer as u32,
+            width,
+            height,
+            mip_level_count,
+            data,
+        );
+    }
+
+    texture
+}
+
+/// Returns the texture format storing blocks compressed with `format`.
+pub fn compressed_texture_format(format: DdsFormat) -> wgpu::TextureFormat {
+    match format {
+        DdsFormat::Bc1 => wgpu::TextureFormat::Bc1RgbaUnorm,
+        DdsFormat::Bc2 => wgpu::TextureFormat::Bc2RgbaUnorm,
+        DdsFormat::Bc3 => wgpu::TextureFormat::Bc3RgbaUnorm,
+        DdsFormat::Bc7 => wgpu::TextureFormat::Bc7RgbaUnorm,
+    }
+}
+
+/// Creates a 2D texture array from layers of block-compressed data.
+///
+/// The device must have `wgpu::Features::TEXTURE_COMPRESSION_BC` enabled. Every layer must have
+/// the given dimensions, which must be multiples of the 4x4 block size.
+pub fn create_compressed_texture_array<'a>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: Option<&'a str>,
+    width: u32,
+    height: u32,
+    format: DdsFormat,
+    layers: &[&[u8]],
+) -> wgpu::Texture {
+    assert!(!layers.is_empty());
+    trace!(
+        "Creating compressed texture array ({:?}: {}x{}x{})",
+        format,
+        width,
+        height,
+        layers.len()
     );
 
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers.len() as u32,
+        },
+        ..texture_descriptor(label, width, height, compressed_texture_format(format))
+    });
+
+    for (layer, data) in layers.iter().enumerate() {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer as u32,
+                },
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                // one row of blocks covers four rows of texels
+                bytes_per_row: NonZeroU32::new(width / 4 * format.block_size()),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
     texture
 }
 
@@ -219,13 +656,24 @@ impl<'a> TextureData<'a> {
         (match self {
             TextureData::Diffuse(_) => size_of::<[u8; 4]>(),
             TextureData::Fullbright(_) => size_of::<u8>(),
-            TextureData::Lightmap(_) => size_of::<u8>(),
+            TextureData::Lightmap(_) => size_of::<[u8; 4]>(),
         }) as u32
     }
 
     pub fn size(&self) -> wgpu::BufferAddress {
         self.data().len() as wgpu::BufferAddress
     }
+
+    /// Returns the number of mip levels generated for a texture of this kind.
+    ///
+    /// Lightmaps are packed into atlas pages, so they only get a single level to keep neighbouring
+    /// lightmaps from bleeding into each other.
+    pub fn mip_level_count(&self, width: u32, height: u32) -> u32 {
+        match self {
+            TextureData::Lightmap(_) => 1,
+            _ => full_mip_level_count(width, height),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -251,6 +699,38 @@ impl std::convert::From<winit::dpi::PhysicalSize<u32>> for Extent2d {
     }
 }
 
+// Creates the bind group holding the entity uniforms and the world texture and lightmap samplers.
+fn create_per_entity_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    entity_uniform_buffer: &DynamicUniformBuffer<EntityUniforms>,
+    texture_sampler: &wgpu::Sampler,
+    lightmap_sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("brush per-entity bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: entity_uniform_buffer.buffer(),
+                    offset: 0,
+                    size: Some(NonZeroU64::new(size_of::<EntityUniforms>() as u64).unwrap()),
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(texture_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(lightmap_sampler),
+            },
+        ],
+    })
+}
+
 pub struct GraphicsState {
     device: wgpu::Device,
     queue: wgpu::Queue,
@@ -268,13 +748,21 @@ pub struct GraphicsState {
     diffuse_sampler: wgpu::Sampler,
     lightmap_sampler: wgpu::Sampler,
 
+    texture_filter: TextureFilter,
+    texture_sampler: wgpu::Sampler,
+
     sample_count: Cell<u32>,
 
     alias_pipeline: AliasPipeline,
     brush_pipeline: BrushPipeline,
+    skybox_pipeline: SkyboxPipeline,
     sprite_pipeline: SpritePipeline,
+    warp_pipeline: WarpPipeline,
     deferred_pipeline: DeferredPipeline,
     particle_pipeline: ParticlePipeline,
+    bbox_pipeline: BboxPipeline,
+    shadow_pipeline: ShadowPipeline,
+    corona_pipeline: CoronaPipeline,
     postprocess_pipeline: PostProcessPipeline,
     glyph_pipeline: GlyphPipeline,
     quad_pipeline: QuadPipeline,
@@ -345,6 +833,9 @@ impl GraphicsState {
             ..Default::default()
         });
 
+        let texture_filter = TextureFilter::default();
+        let texture_sampler = texture_filter.create_sampler(&device);
+
         let world_bind_group_layouts: Vec<wgpu::BindGroupLayout> =
             world::BIND_GROUP_LAYOUT_DESCRIPTORS
                 .iter()
@@ -363,30 +854,13 @@ impl GraphicsState {
                     }),
                 }],
             }),
-            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("brush per-entity bind group"),
-                layout: &world_bind_group_layouts[world::BindGroupLayoutId::PerEntity as usize],
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                            buffer: &entity_uniform_buffer.borrow().buffer(),
-                            offset: 0,
-                            size: Some(
-                                NonZeroU64::new(size_of::<EntityUniforms>() as u64).unwrap(),
-                            ),
-                        }),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::Sampler(&lightmap_sampler),
-                    },
-                ],
-            }),
+            create_per_entity_bind_group(
+                &device,
+                &world_bind_group_layouts[world::BindGroupLayoutId::PerEntity as usize],
+                &entity_uniform_buffer.borrow(),
+                &texture_sampler,
+                &lightmap_sampler,
+            ),
         ];
 
         let alias_pipeline = AliasPipeline::new(
@@ -402,16 +876,33 @@ impl GraphicsState {
             &world_bind_group_layouts,
             sample_count,
         );
+        let skybox_pipeline = SkyboxPipeline::new(
+            &device,
+            &mut compiler,
+            &world_bind_group_layouts,
+            sample_count,
+        );
         let sprite_pipeline = SpritePipeline::new(
             &device,
             &mut compiler,
             &world_bind_group_layouts,
             sample_count,
         );
+        let warp_pipeline = WarpPipeline::new(
+            &device,
+            &mut compiler,
+            &world_bind_group_layouts,
+            brush_pipeline.bind_group_layouts(),
+            sample_count,
+        );
         let deferred_pipeline = DeferredPipeline::new(&device, &mut compiler, sample_count);
         let particle_pipeline =
             ParticlePipeline::new(&device, &queue, &mut compiler, sample_count, &palette);
-        let postprocess_pipeline = PostProcessPipeline::new(&device, &mut compiler, sample_count);
+        let bbox_pipeline = BboxPipeline::new(&device, &mut compiler, sample_count);
+        let shadow_pipeline = ShadowPipeline::new(&device, &mut compiler, sample_count);
+        let corona_pipeline = CoronaPipeline::new(&device, &mut compiler, sample_count);
+        let postprocess_pipeline =
+            PostProcessPipeline::new(&device, &queue, &mut compiler, sample_count, &palette);
         let quad_pipeline = QuadPipeline::new(&device, &mut compiler, sample_count);
         let glyph_pipeline = GlyphPipeline::new(&device, &mut compiler, sample_count);
         let blit_pipeline =
@@ -424,7 +915,7 @@ impl GraphicsState {
             1,
             1,
             &TextureData::Lightmap(LightmapData {
-                lightmap: (&[0xFF][..]).into(),
+                lightmap: (&[0xFF; 4][..]).into(),
             }),
         );
         let default_lightmap_view = default_lightmap.create_view(&Default::default());
@@ -445,9 +936,14 @@ impl GraphicsState {
 
             alias_pipeline,
             brush_pipeline,
+            skybox_pipeline,
             sprite_pipeline,
+            warp_pipeline,
             deferred_pipeline,
             particle_pipeline,
+            bbox_pipeline,
+            shadow_pipeline,
+            corona_pipeline,
             postprocess_pipeline,
             glyph_pipeline,
             quad_pipeline,
@@ -455,6 +951,8 @@ impl GraphicsState {
 
             diffuse_sampler,
             lightmap_sampler,
+            texture_filter,
+            texture_sampler,
             default_lightmap,
             default_lightmap_view,
             vfs,
@@ -474,28 +972,92 @@ impl GraphicsState {
         create_texture(&self.device, &self.queue, label, width, height, data)
     }
 
-    /// Update graphics state with the new framebuffer size and sample count.
+    pub fn create_texture_array<'a>(
+        &self,
+        label: Option<&'a str>,
+        width: u32,
+        height: u32,
+        layers: &[TextureData],
+    ) -> wgpu::Texture {
+        create_texture_array(&self.device, &self.queue, label, width, height, layers)
+    }
+
+    /// Returns whether block-compressed textures can be created on this device.
+    pub fn supports_compressed_textures(&self) -> bool {
+        self.device
+            .features()
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+    }
+
+    pub fn create_compressed_texture_array<'a>(
+        &self,
+        label: Option<&'a str>,
+        width: u32,
+        height: u32,
+        format: DdsFormat,
+        layers: &[&[u8]],
+    ) -> wgpu::Texture {
+        create_compressed_texture_array(
+            &self.device,
+            &self.queue,
+            label,
+            width,
+            height,
+            format,
+            layers,
+        )
+    }
+
+    /// Update graphics state with the new framebuffer size, resolution scale, sample count and
+    /// texture filter.
     ///
     /// If the framebuffer size has changed, this recreates all render targets with the new size.
+    /// The targets the 3D scene is drawn into are `scale` times the framebuffer size, and are
+    /// scaled up to the framebuffer size by the postprocessing pass.
     ///
     /// If the framebuffer sample count has changed, this recreates all render targets with the
     /// new sample count and rebuilds the render pipelines to output that number of samples.
-    pub fn update(&mut self, size: Extent2d, sample_count: u32) {
+    ///
+    /// If the texture filter has changed, this rebuilds the sampler used for world textures.
+    pub fn update(
+        &mut self,
+        size: Extent2d,
+        scale: f32,
+        sample_count: u32,
+        texture_filter: TextureFilter,
+    ) {
         if self.sample_count.get() != sample_count {
             self.sample_count.set(sample_count);
             self.recreate_pipelines(sample_count);
         }
 
-        if self.initial_pass_target.size() != size
+        if self.texture_filter != texture_filter {
+            self.texture_filter = texture_filter;
+            self.texture_sampler = texture_filter.create_sampler(&self.device);
+            self.world_bind_groups[world::BindGroupLayoutId::PerEntity as usize] =
+                create_per_entity_bind_group(
+                    &self.device,
+                    &self.world_bind_group_layouts[world::BindGroupLayoutId::PerEntity as usize],
+                    &self.entity_uniform_buffer.borrow(),
+                    &self.texture_sampler,
+                    &self.lightmap_sampler,
+                );
+        }
+
+        let scene_size = scaled_size(size, scale);
+
+        if self.initial_pass_target.size() != scene_size
             || self.initial_pass_target.sample_count() != sample_count
         {
-            self.initial_pass_target = InitialPassTarget::new(self.device(), size, sample_count);
+            self.initial_pass_target =
+                InitialPassTarget::new(self.device(), scene_size, sample_count);
         }
 
-        if self.deferred_pass_target.size() != size
+        if self.deferred_pass_target.size() != scene_size
             || self.deferred_pass_target.sample_count() != sample_count
         {
-            self.deferred_pass_target = DeferredPassTarget::new(self.device(), size, sample_count);
+            self.deferred_pass_target =
+                DeferredPassTarget::new(self.device(), scene_size, sample_count);
         }
 
         if self.final_pass_target.size() != size
@@ -527,14 +1089,33 @@ impl GraphicsState {
             &self.world_bind_group_layouts,
             sample_count,
         );
+        self.skybox_pipeline.rebuild(
+            &self.device,
+            &mut self.compiler.borrow_mut(),
+            &self.world_bind_group_layouts,
+            sample_count,
+        );
         self.sprite_pipeline.rebuild(
             &self.device,
             &mut self.compiler.borrow_mut(),
             &self.world_bind_group_layouts,
             sample_count,
         );
+        self.warp_pipeline.rebuild(
+            &self.device,
+            &mut self.compiler.borrow_mut(),
+            &self.world_bind_group_layouts,
+            self.brush_pipeline.bind_group_layouts(),
+            sample_count,
+        );
         self.deferred_pipeline
             .rebuild(&self.device, &mut self.compiler.borrow_mut(), sample_count);
+        self.bbox_pipeline
+            .rebuild(&self.device, &mut self.compiler.borrow_mut(), sample_count);
+        self.shadow_pipeline
+            .rebuild(&self.device, &mut self.compiler.borrow_mut(), sample_count);
+        self.corona_pipeline
+            .rebuild(&self.device, &mut self.compiler.borrow_mut(), sample_count);
         self.postprocess_pipeline.rebuild(
             &self.device,
             &mut self.compiler.borrow_mut(),
@@ -555,6 +1136,10 @@ impl GraphicsState {
         &self.device
     }
 
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count.get()
+    }
+
     pub fn queue(&self) -> &wgpu::Queue {
         &self.queue
     }
@@ -617,10 +1202,18 @@ impl GraphicsState {
         &self.brush_pipeline
     }
 
+    pub fn skybox_pipeline(&self) -> &SkyboxPipeline {
+        &self.skybox_pipeline
+    }
+
     pub fn sprite_pipeline(&self) -> &SpritePipeline {
         &self.sprite_pipeline
     }
 
+    pub fn warp_pipeline(&self) -> &WarpPipeline {
+        &self.warp_pipeline
+    }
+
     pub fn deferred_pipeline(&self) -> &DeferredPipeline {
         &self.deferred_pipeline
     }
@@ -629,6 +1222,18 @@ impl GraphicsState {
         &self.particle_pipeline
     }
 
+    pub fn bbox_pipeline(&self) -> &BboxPipeline {
+        &self.bbox_pipeline
+    }
+
+    pub fn shadow_pipeline(&self) -> &ShadowPipeline {
+        &self.shadow_pipeline
+    }
+
+    pub fn corona_pipeline(&self) -> &CoronaPipeline {
+        &self.corona_pipeline
+    }
+
     pub fn postprocess_pipeline(&self) -> &PostProcessPipeline {
         &self.postprocess_pipeline
     }
@@ -656,18 +1261,71 @@ impl GraphicsState {
     pub fn gfx_wad(&self) -> &Wad {
         &self.gfx_wad
     }
+
+    /// Loads a QPic from the virtual filesystem.
+    ///
+    /// If the picture is missing (e.g. when running on the fallback assets), a placeholder is
+    /// returned instead.
+    pub fn load_qpic<S>(&self, path: S) -> QPic
+    where
+        S: AsRef<str>,
+    {
+        let path = path.as_ref();
+        let qpic = self
+            .vfs
+            .open(path)
+            .map_err(Error::from)
+            .and_then(|f| QPic::load(f).map_err(Error::from));
+
+        match qpic {
+            Ok(qpic) => qpic,
+            Err(e) => {
+                warn!("Couldn't load {}, using placeholder: {}", path, e);
+                QPic::placeholder(PLACEHOLDER_QPIC_SIZE, PLACEHOLDER_QPIC_SIZE)
+            }
+        }
+    }
+
+    /// Loads a QPic from `gfx.wad`, substituting a placeholder if it is missing.
+    pub fn load_wad_qpic<S>(&self, name: S) -> QPic
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+        match self.gfx_wad.open_qpic(name) {
+            Ok(qpic) => qpic,
+            Err(e) => {
+                warn!(
+                    "Couldn't load {} from gfx.wad, using placeholder: {}",
+                    name, e
+                );
+                QPic::placeholder(PLACEHOLDER_QPIC_SIZE, PLACEHOLDER_QPIC_SIZE)
+            }
+        }
+    }
 }
 
 pub struct ClientRenderer {
     deferred_renderer: DeferredRenderer,
     postprocess_renderer: PostProcessRenderer,
     ui_renderer: UiRenderer,
+    frame_rate: FrameRate,
+    profiler: Option<GpuProfiler>,
+    gpu_timings: Rc<RefCell<GpuTimings>>,
     bump: Bump,
-    start_time: DateTime<Utc>,
 }
 
 impl ClientRenderer {
-    pub fn new(state: &GraphicsState, menu: &Menu) -> ClientRenderer {
+    /// Creates a renderer which records its GPU timings in `gpu_timings`, if the device supports
+    /// timestamp queries.
+    pub fn new(
+        state: &GraphicsState,
+        menu: &Menu,
+        gpu_timings: Rc<RefCell<GpuTimings>>,
+    ) -> ClientRenderer {
+        let profiler = GpuProfiler::new(state.device(), state.queue());
+        gpu_timings.borrow_mut().set_supported(profiler.is_some());
+
         ClientRenderer {
             deferred_renderer: DeferredRenderer::new(
                 state,
@@ -681,8 +1339,10 @@ impl ClientRenderer {
                 state.deferred_pass_target.color_view(),
             ),
             ui_renderer: UiRenderer::new(state, menu),
+            frame_rate: FrameRate::new(),
+            profiler,
+            gpu_timings,
             bump: Bump::new(),
-            start_time: Utc::now(),
         }
     }
 
@@ -691,104 +1351,56 @@ impl ClientRenderer {
         gfx_state: &GraphicsState,
         encoder: &mut wgpu::CommandEncoder,
         conn: Option<&Connection>,
+        uptime: Duration,
         width: u32,
         height: u32,
         fov: Deg<f32>,
         cvars: &CvarRegistry,
+        strings: &Strings,
         console: &Console,
         menu: &Menu,
         focus: InputFocus,
     ) {
         self.bump.reset();
+        self.frame_rate.update(uptime);
+
+        // the previous frame has been submitted, so its timings can be read back
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.read_back(gfx_state.device(), &mut self.gpu_timings.borrow_mut());
+        }
+        let gpu_profile = cvars.get_value("r_gpuprofile").unwrap() != 0.0;
+        let gpu_timings = self.gpu_timings.borrow();
+
+        // filled in by the world, entity and viewmodel passes and displayed by the UI pass
+        let render_stats = Cell::new(RenderStats::default());
+
+        // the view always covers the display during intermissions
+        let viewsize = match conn {
+            Some(Connection { ref state, .. }) if state.intermission().is_none() => {
+                cvars.get_value("viewsize").unwrap()
+            }
+            _ => 100.0,
+        };
+        let sbar_scale = cvars.get_value("scr_sbarscale").unwrap().max(1.0);
+        let view_rect = hud::view_rect(viewsize, Extent2d { width, height }, sbar_scale);
+        let aspect = view_rect.width as f32 / view_rect.height as f32;
+
+        let [r, g, b] = gfx_state
+            .palette()
+            .rgb(cvars.get_value("crosshaircolor").unwrap() as u8);
+        let crosshair = Crosshair {
+            style: CrosshairStyle::from_cvar(cvars.get_value("crosshair").unwrap()),
+            color: [r, g, b, 0xFF],
+            size: cvars.get_value("crosshairsize").unwrap(),
+        };
 
         if let Some(Connection {
             state: ref cl_state,
-            ref conn_state,
-            ref kind,
+            ..
         }) = conn
         {
-            match conn_state {
-                ConnectionState::Connected(ref world) => {
-                    // if client is fully connected, draw world
-                    let camera = match kind {
-                        ConnectionKind::Demo(_) => {
-                            cl_state.demo_camera(width as f32 / height as f32, fov)
-                        }
-                        ConnectionKind::Server { .. } => {
-                            cl_state.camera(width as f32 / height as f32, fov)
-                        }
-                    };
-
-                    // initial render pass
-                    {
-                        let init_pass_builder =
-                            gfx_state.initial_pass_target().render_pass_builder();
-
-                        let mut init_pass =
-                            encoder.begin_render_pass(&init_pass_builder.descriptor());
-
-                        world.render_pass(
-                            gfx_state,
-                            &mut init_pass,
-                            &self.bump,
-                            &camera,
-                            cl_state.time(),
-                            cl_state.iter_visible_entities(),
-                            cl_state.iter_particles(),
-                            cl_state.lightstyle_values().unwrap().as_slice(),
-                            cl_state.viewmodel_id(),
-                            cvars,
-                        );
-                    }
-
-                    // deferred lighting pass
-                    {
-                        let deferred_pass_builder =
-                            gfx_state.deferred_pass_target().render_pass_builder();
-                        let mut deferred_pass =
-                            encoder.begin_render_pass(&deferred_pass_builder.descriptor());
-
-                        let mut lights = [PointLight {
-                            origin: Vector3::zero(),
-                            radius: 0.0,
-                        }; MAX_LIGHTS];
-
-                        let mut light_count = 0;
-                        for (light_id, light) in cl_state.iter_lights().enumerate() {
-                            light_count += 1;
-                            let light_origin = light.origin();
-                            let converted_origin =
-                                Vector3::new(-light_origin.y, light_origin.z, -light_origin.x);
-                            lights[light_id].origin =
-                                (camera.view() * converted_origin.extend(1.0)).truncate();
-                            lights[light_id].radius = light.radius(cl_state.time());
-                        }
-
-                        let uniforms = DeferredUniforms {
-                            inv_projection: camera.inverse_projection().into(),
-                            light_count,
-                            _pad: [0; 3],
-                            lights,
-                        };
-
-                        self.deferred_renderer.rebuild(
-                            gfx_state,
-                            gfx_state.initial_pass_target().diffuse_view(),
-                            gfx_state.initial_pass_target().normal_view(),
-                            gfx_state.initial_pass_target().light_view(),
-                            gfx_state.initial_pass_target().depth_view(),
-                        );
-
-                        self.deferred_renderer
-                            .record_draw(gfx_state, &mut deferred_pass, uniforms);
-                    }
-                }
-
-                // if client is still signing on, draw the loading screen
-                ConnectionState::SignOn(_) => {
-                    // TODO: loading screen
-                }
-            }
+            self.ui_renderer
+                .update_show_lmps(gfx_state, cl_state.show_lmps());
         }
 
         let ui_state = match conn {
@@ -806,14 +1418,67 @@ impl ClientRenderer {
                     },
 
                     None => HudState::InGame {
+                        view_rect,
+                        viewsize,
                         items: cl_state.items(),
                         item_pickup_time: cl_state.item_pickup_times(),
                         stats: cl_state.stats(),
                         face_anim_time: cl_state.face_anim_time(),
+                        crosshair,
                         console,
+                        mission_pack: MissionPack::from_name(
+                            &cvars.get("missionpack").unwrap_or_default(),
+                        ),
                     },
                 },
 
+                scale: sbar_scale,
+
+                strings,
+
+                show_lmps: cl_state.show_lmps(),
+
+                time_graph: match cvars.get_value("cl_timegraph") {
+                    Ok(v) if v != 0.0 => Some(&cl_state.time_graph),
+                    _ => None,
+                },
+
+                teammates: match cvars.get_value("hud_teaminfo") {
+                    Ok(v) if v != 0.0 && cl_state.game_type == GameType::CoOp => {
+                        cl_state.teammates().collect()
+                    }
+                    _ => Vec::new(),
+                },
+
+                frame_rate: match cvars.get_value("scr_showfps") {
+                    Ok(v) if v != 0.0 => Some(&self.frame_rate),
+                    _ => None,
+                },
+
+                render_stats: match cvars.get_value("r_speeds") {
+                    Ok(v) if v != 0.0 => Some(&render_stats),
+                    _ => None,
+                },
+
+                gpu_timings: if gpu_profile && gpu_timings.supported() {
+                    Some(&*gpu_timings)
+                } else {
+                    None
+                },
+
+                download_status: conn.and_then(|c| c.download_status(strings)),
+
+                center_print: cl_state.center_print().and_then(|(text, received)| {
+                    let center_time = cvars.get_value("scr_centertime").unwrap_or(2.0);
+                    let center_time = Duration::milliseconds((center_time * 1000.0) as i64);
+                    ui::center_print_alpha(cl_state.time() - received, center_time)
+                        .map(|alpha| (text, alpha))
+                }),
+
+                notify_time: Duration::milliseconds(
+                    (cvars.get_value("con_notifytime").unwrap_or(3.0) * 1000.0) as i64,
+                ),
+
                 overlay: match focus {
                     InputFocus::Game => None,
                     InputFocus::Console => Some(UiOverlay::Console(console)),
@@ -830,46 +1495,312 @@ impl ClientRenderer {
             },
         };
 
-        // final render pass: postprocess the world and draw the UI
+        // use client time when in game, client uptime otherwise
+        let time = match conn {
+            Some(Connection { ref state, .. }) => state.time,
+            None => uptime,
+        };
+
+        let deferred_renderer = &mut self.deferred_renderer;
+        let postprocess_renderer = &mut self.postprocess_renderer;
+        let ui_renderer = &self.ui_renderer;
+        let bump = &self.bump;
+        let render_stats = &render_stats;
+        let ui_state = &ui_state;
+
+        let mut graph = FrameGraph::new();
+
+        // if client is still signing on, only the UI is drawn
+        // TODO: loading screen
+        if let Some(Connection {
+            state: ref cl_state,
+            conn_state: ConnectionState::Connected(ref world),
+            ref kind,
+            ..
+        }) = conn
         {
-            // quad_commands must outlive final pass
-            let mut quad_commands = Vec::new();
-            let mut glyph_commands = Vec::new();
+            let camera = match kind {
+                ConnectionKind::Demo(_) => cl_state.demo_camera(aspect, fov),
+                ConnectionKind::Server { .. } => cl_state.camera(aspect, fov),
+            };
+
+            let mut lights = [PointLight {
+                origin: Vector3::zero(),
+                radius: 0.0,
+            }; MAX_LIGHTS];
+
+            // with r_dynamic 0, only the static lightmaps are used. with gl_flashblend, dynamic
+            // lights are drawn as coronas in the world pass instead
+            let r_dynamic = cvars.get_value("r_dynamic").unwrap() != 0.0;
+            let gl_flashblend = cvars.get_value("gl_flashblend").unwrap() != 0.0;
+
+            let mut light_count = 0;
+            if r_dynamic && !gl_flashblend {
+                for (light_id, light) in cl_state.iter_lights().enumerate() {
+                    light_count += 1;
+                    let light_origin = light.origin();
+                    let converted_origin =
+                        Vector3::new(-light_origin.y, light_origin.z, -light_origin.x);
+                    lights[light_id].origin =
+                        (camera.view() * converted_origin.extend(1.0)).truncate();
+                    lights[light_id].radius = light.radius(cl_state.time());
+                }
+            }
 
-            let final_pass_builder = gfx_state.final_pass_target().render_pass_builder();
-            let mut final_pass = encoder.begin_render_pass(&final_pass_builder.descriptor());
+            let lightstyle_values = cl_state.lightstyle_values(LightStyleLerp::from_cvar(
+                cvars.get_value("r_lerplightstyles").unwrap(),
+            ));
+
+            let deferred_uniforms = DeferredUniforms {
+                inv_projection: camera.inverse_projection().into(),
+                light_count,
+                _pad: [0; 3],
+                lights,
+            };
+
+            // the world, entities and viewmodel all draw into the G-buffer, in that order
+            let lightstyle_values: &[f32] = bump.alloc_slice_copy(&lightstyle_values);
+            graph.add_pass("world", PassTarget::Initial, &[], move |encoder, target| {
+                let mut pass = encoder.begin_render_pass(&target.descriptor());
+                let mut stats = render_stats.get();
+                world.render_world_pass(
+                    gfx_state,
+                    &mut pass,
+                    bump,
+                    &camera,
+                    cl_state.time(),
+                    cl_state.iter_visible_entities(),
+                    lightstyle_values,
+                    cl_state.fog(),
+                    cvars,
+                    &mut stats,
+                );
+                render_stats.set(stats);
+            });
+
+            graph.add_pass(
+                "entities",
+                PassTarget::Initial,
+                &[],
+                move |encoder, target| {
+                    let mut pass = encoder.begin_render_pass(&target.descriptor());
+                    let mut stats = render_stats.get();
+                    world.render_entity_pass(
+                        gfx_state,
+                        &mut pass,
+                        bump,
+                        &camera,
+                        cl_state.time(),
+                        cl_state.iter_visible_entities(),
+                        cl_state.iter_particles(),
+                        cl_state.iter_lights(),
+                        lightstyle_values,
+                        cvars,
+                        &mut stats,
+                    );
+                    render_stats.set(stats);
+                },
+            );
 
-            if let Some(Connection {
-                state: ref cl_state,
-                ref conn_state,
-                ..
-            }) = conn
-            {
-                // only postprocess if client is in the game
-                if let ConnectionState::Connected(_) = conn_state {
-                    self.postprocess_renderer
-                        .rebuild(gfx_state, gfx_state.deferred_pass_target.color_view());
-                    self.postprocess_renderer.record_draw(
+            graph.add_pass(
+                "viewmodel",
+                PassTarget::Initial,
+                &[],
+                move |encoder, target| {
+                    let mut pass = encoder.begin_render_pass(&target.descriptor());
+                    let mut stats = render_stats.get();
+                    world.render_viewmodel_pass(
                         gfx_state,
-                        &mut final_pass,
-                        cl_state.color_shift(),
+                        &mut pass,
+                        bump,
+                        &camera,
+                        cl_state.time(),
+                        lightstyle_values,
+                        cl_state.viewmodel(),
+                        cvars,
+                        &mut stats,
                     );
-                }
-            }
+                    render_stats.set(stats);
+                },
+            );
 
-            self.ui_renderer.render_pass(
-                &gfx_state,
-                &mut final_pass,
-                Extent2d { width, height },
-                // use client time when in game, renderer time otherwise
-                match conn {
-                    Some(Connection { ref state, .. }) => state.time,
-                    None => Utc::now().signed_duration_since(self.start_time),
+            graph.add_pass(
+                "lighting",
+                PassTarget::Deferred,
+                &[
+                    Attachment::Diffuse,
+                    Attachment::Normal,
+                    Attachment::Light,
+                    Attachment::Depth,
+                ],
+                move |encoder, target| {
+                    deferred_renderer.rebuild(
+                        gfx_state,
+                        gfx_state.initial_pass_target().diffuse_view(),
+                        gfx_state.initial_pass_target().normal_view(),
+                        gfx_state.initial_pass_target().light_view(),
+                        gfx_state.initial_pass_target().depth_view(),
+                    );
+
+                    let mut pass = encoder.begin_render_pass(&target.descriptor());
+                    deferred_renderer.record_draw(gfx_state, &mut pass, deferred_uniforms);
+                },
+            );
+
+            // damage and pickup flashes, powerup tints and the tint of the contents the view
+            // is in, blended over the world
+            let color_shift = cl_state.color_shift(cvars.get_value("gl_cshiftpercent").unwrap());
+            let quantize = software_look_enabled(cvars);
+
+            graph.add_pass(
+                "postprocess",
+                PassTarget::Final,
+                &[Attachment::Color],
+                move |encoder, target| {
+                    postprocess_renderer
+                        .rebuild(gfx_state, gfx_state.deferred_pass_target.color_view());
+
+                    let mut pass = encoder.begin_render_pass(&target.descriptor());
+
+                    // the world is rendered at full size and scaled down into the view
+                    let ScreenRect {
+                        x,
+                        y,
+                        width: view_width,
+                        height: view_height,
+                    } = view_rect;
+                    pass.set_viewport(
+                        x as f32,
+                        (height as i32 - y - view_height as i32) as f32,
+                        view_width as f32,
+                        view_height as f32,
+                        0.0,
+                        1.0,
+                    );
+
+                    postprocess_renderer.record_draw(gfx_state, &mut pass, color_shift, quantize);
                 },
-                &ui_state,
-                &mut quad_commands,
-                &mut glyph_commands,
             );
         }
+
+        graph.add_pass("ui", PassTarget::Final, &[], move |encoder, target| {
+            // draw_list must outlive the pass
+            let mut draw_list = DrawList2D::new();
+            let mut pass = encoder.begin_render_pass(&target.descriptor());
+            ui_renderer.render_pass(
+                gfx_state,
+                &mut pass,
+                Extent2d { width, height },
+                time,
+                ui_state,
+                &mut draw_list,
+            );
+        });
+
+        let profiler = if gpu_profile {
+            self.profiler.as_mut()
+        } else {
+            None
+        };
+        graph.execute(gfx_state, encoder, profiler).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scaled_size() {
+        let size = Extent2d {
+            width: 1366,
+            height: 768,
+        };
+        assert_eq!(scaled_size(size, 1.0), size);
+        assert_eq!(
+            scaled_size(size, 0.5),
+            Extent2d {
+                width: 683,
+                height: 384
+            }
+        );
+
+        // a tiny window still gets a scene target
+        let tiny = Extent2d {
+            width: 1,
+            height: 1,
+        };
+        assert_eq!(scaled_size(tiny, 0.25), tiny);
+    }
+
+    #[test]
+    fn test_adjust_render_scale() {
+        let budget = 1.0 / 60.0;
+
+        // slow frames lower the scale, but not below the minimum
+        assert_eq!(adjust_render_scale(1.0, 1.0, 0.05, budget), 0.95);
+        assert_eq!(
+            adjust_render_scale(MIN_RENDER_SCALE, 1.0, 0.05, budget),
+            MIN_RENDER_SCALE
+        );
+
+        // fast frames raise it up to r_scale, and frames near the budget leave it alone
+        assert_eq!(adjust_render_scale(0.5, 1.0, 0.001, budget), 0.55);
+        assert_eq!(adjust_render_scale(0.75, 0.75, 0.001, budget), 0.75);
+        assert_eq!(adjust_render_scale(0.5, 1.0, budget, budget), 0.5);
+    }
+
+    #[test]
+    fn test_full_mip_level_count() {
+        assert_eq!(full_mip_level_count(1, 1), 1);
+        assert_eq!(full_mip_level_count(64, 64), 7);
+        assert_eq!(full_mip_level_count(16, 128), 8);
+        assert_eq!(full_mip_level_count(24, 8), 5);
+    }
+
+    #[test]
+    fn test_downsample() {
+        #[rustfmt::skip]
+        let data = [
+            0, 4, 8,
+            4, 8, 12,
+        ];
+        assert_eq!(downsample(&data, 3, 2, 1), vec![4]);
+
+        // channels are averaged separately
+        let data = [0, 255, 10, 255];
+        assert_eq!(downsample(&data, 2, 1, 2), vec![5, 255]);
+    }
+
+    #[test]
+    fn test_parse_texture_mode() {
+        use wgpu::FilterMode::{Linear, Nearest};
+
+        let filter = parse_texture_mode("gl_linear_mipmap_nearest").unwrap();
+        assert_eq!(filter.mag_filter, Linear);
+        assert_eq!(filter.min_filter, Linear);
+        assert_eq!(filter.mipmap_filter, Some(Nearest));
+
+        assert_eq!(
+            parse_texture_mode("GL_NEAREST").unwrap().mipmap_filter,
+            None
+        );
+        assert!(parse_texture_mode("GL_BILINEAR").is_none());
+    }
+
+    #[test]
+    fn test_anisotropy_level() {
+        let cvars = CvarRegistry::new(Rc::new(RefCell::new(Vec::new())));
+        cvars.register_archive("r_anisotropy", "1").unwrap();
+        assert_eq!(anisotropy_level(&cvars), 1);
+
+        cvars.set("r_anisotropy", "6").unwrap();
+        assert_eq!(anisotropy_level(&cvars), 4);
+
+        cvars.set("r_anisotropy", "64").unwrap();
+        assert_eq!(anisotropy_level(&cvars), 16);
+
+        cvars.set("r_anisotropy", "0").unwrap();
+        assert_eq!(anisotropy_level(&cvars), 1);
     }
 }
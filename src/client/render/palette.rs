@@ -2,11 +2,35 @@ use std::{borrow::Cow, io::BufReader};
 
 use crate::{
     client::render::{DiffuseData, FullbrightData},
-    common::vfs::Vfs,
+    common::{net::PlayerColor, vfs::Vfs},
 };
 
 use byteorder::ReadBytesExt;
 
+// the palette rows player skins use for shirts and pants
+const TOP_RANGE: usize = 16;
+const BOTTOM_RANGE: usize = 96;
+
+/// Returns the table which recolors a player skin's shirt and pants to `colors`.
+///
+/// Each color selects a row of 16 palette entries to replace the corresponding skin row with.
+/// The rows from 8 onward run from light to dark rather than dark to light, so they are reversed.
+pub fn player_translation(colors: PlayerColor) -> [u8; 256] {
+    let mut table = [0; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = i as u8;
+    }
+
+    for &(range, color) in [(TOP_RANGE, colors.top()), (BOTTOM_RANGE, colors.bottom())].iter() {
+        let row = color * 16;
+        for j in 0..16 {
+            table[range + j as usize] = if color < 8 { row + j } else { row + 15 - j };
+        }
+    }
+
+    table
+}
+
 pub struct Palette {
     rgb: [[u8; 3]; 256],
 }
@@ -44,6 +68,48 @@ impl Palette {
         Palette { rgb }
     }
 
+    /// Returns the RGB value of the color at `index`.
+    pub fn rgb(&self, index: u8) -> [u8; 3] {
+        self.rgb[index as usize]
+    }
+
+    /// Returns the palette entry closest to `rgb`.
+    pub fn nearest(&self, rgb: [u8; 3]) -> u8 {
+        let distance = |color: &[u8; 3]| -> i32 {
+            color
+                .iter()
+                .zip(rgb.iter())
+                .map(|(&a, &b)| (a as i32 - b as i32).pow(2))
+                .sum()
+        };
+
+        (0..=255)
+            .min_by_key(|&i| distance(&self.rgb[i as usize]))
+            .unwrap()
+    }
+
+    /// Returns an RGBA lookup table of `size`³ entries which maps colors to the nearest palette
+    /// entry.
+    ///
+    /// Red, green and blue are each divided evenly into `size` steps. Red varies fastest and blue
+    /// slowest, so the table can be uploaded directly as a 3D texture.
+    pub fn quantization_table(&self, size: usize) -> Vec<u8> {
+        let step = |i: usize| (i * 255 / (size - 1)) as u8;
+
+        let mut table = Vec::with_capacity(size * size * size * 4);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let index = self.nearest([step(r), step(g), step(b)]);
+                    table.extend_from_slice(&self.rgb[index as usize]);
+                    table.push(0xFF);
+                }
+            }
+        }
+
+        table
+    }
+
     // TODO: this will not render console characters correctly, as they use index 0 (black) to
     // indicate transparency.
     /// Translates a set of indices into a list of RGBA values and a list of fullbright values.
@@ -80,3 +146,42 @@ impl Palette {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_player_translation() {
+        let table = player_translation(PlayerColor::new(2, 13));
+
+        // the shirt row is replaced in order, the pants row reversed
+        assert_eq!(table[TOP_RANGE], 32);
+        assert_eq!(table[TOP_RANGE + 15], 47);
+        assert_eq!(table[BOTTOM_RANGE], 13 * 16 + 15);
+        assert_eq!(table[BOTTOM_RANGE + 15], 13 * 16);
+
+        // everything else is left alone
+        assert_eq!(table[0], 0);
+        assert_eq!(table[TOP_RANGE + 16], (TOP_RANGE + 16) as u8);
+        assert_eq!(table[255], 255);
+    }
+
+    #[test]
+    fn test_quantization_table() {
+        let mut data = vec![0; 768];
+        // entry 1 is pure red, everything else is black
+        data[3] = 0xFF;
+        let palette = Palette::new(&data);
+
+        assert_eq!(palette.nearest([200, 40, 40]), 1);
+        assert_eq!(palette.nearest([40, 40, 200]), 0);
+
+        let table = palette.quantization_table(2);
+        assert_eq!(table.len(), 2 * 2 * 2 * 4);
+        assert_eq!(&table[0..4], &[0, 0, 0, 0xFF]);
+        // (255, 0, 0) is the second entry, (0, 255, 0) the third
+        assert_eq!(&table[4..8], &[0xFF, 0, 0, 0xFF]);
+        assert_eq!(&table[8..12], &[0, 0, 0, 0xFF]);
+    }
+}
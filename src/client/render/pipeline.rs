@@ -24,19 +24,43 @@ use crate::common::util::{any_as_bytes, Pod};
 
 /// The `Pipeline` trait, which allows render pipelines to be defined more-or-less declaratively.
 
+// GLSL shared between shaders, available to `#include`.
+const SHADER_INCLUDES: &[(&str, &str)] = &[(
+    "fog.glsl",
+    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/fog.glsl")),
+)];
+
+// Shaders are compiled with MULTISAMPLED defined if the pipeline renders more than one sample per
+// pixel, so that shaders reading the world attachments can use the matching texture type.
 fn create_shader<S>(
     device: &wgpu::Device,
     compiler: &mut shaderc::Compiler,
     name: S,
     kind: shaderc::ShaderKind,
     source: S,
+    sample_count: u32,
 ) -> wgpu::ShaderModule
 where
     S: AsRef<str>,
 {
     log::debug!("creating shader {}", name.as_ref());
+    let mut options = shaderc::CompileOptions::new().unwrap();
+    if sample_count > 1 {
+        options.add_macro_definition("MULTISAMPLED", None);
+    }
+    options.set_include_callback(|name, _, _, _| {
+        SHADER_INCLUDES
+            .iter()
+            .find(|(include, _)| *include == name)
+            .map(|(include, content)| shaderc::ResolvedInclude {
+                resolved_name: include.to_string(),
+                content: content.to_string(),
+            })
+            .ok_or_else(|| format!("No shader include named {}", name))
+    });
+
     let spirv = compiler
-        .compile_into_spirv(source.as_ref(), kind, name.as_ref(), "main", None)
+        .compile_into_spirv(source.as_ref(), kind, name.as_ref(), "main", Some(&options))
         .unwrap();
     device.create_shader_module(&wgpu::ShaderModuleDescriptor {
         label: Some(name.as_ref()),
@@ -45,6 +69,28 @@ where
     })
 }
 
+// Returns a copy of `entries` with multisampled texture bindings made single-sampled if the
+// attachments they read have only one sample.
+fn entries_for_sample_count(
+    entries: &[wgpu::BindGroupLayoutEntry],
+    sample_count: u32,
+) -> Vec<wgpu::BindGroupLayoutEntry> {
+    entries
+        .iter()
+        .map(|entry| {
+            let mut entry = entry.clone();
+            if let wgpu::BindingType::Texture {
+                ref mut multisampled,
+                ..
+            } = entry.ty
+            {
+                *multisampled &= sample_count > 1;
+            }
+            entry
+        })
+        .collect()
+}
+
 pub enum PushConstantUpdate<T> {
     /// Update the push constant to a new value.
     Update(T),
@@ -72,6 +118,9 @@ pub trait Pipeline {
     fn name() -> &'static str;
 
     /// The `BindGroupLayoutDescriptor`s describing the bindings used in the pipeline.
+    ///
+    /// Textures declared as multisampled are bound as single-sampled when the pipeline is created
+    /// with a sample count of 1.
     fn bind_group_layout_descriptors() -> Vec<wgpu::BindGroupLayoutDescriptor<'static>>;
 
     /// The GLSL source of the pipeline's vertex shader.
@@ -179,7 +228,13 @@ pub trait Pipeline {
         info!("Creating {} pipeline", Self::name());
         let bind_group_layouts = Self::bind_group_layout_descriptors()
             .iter()
-            .map(|desc| device.create_bind_group_layout(desc))
+            .map(|desc| {
+                let entries = entries_for_sample_count(desc.entries, sample_count);
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: desc.label,
+                    entries: &entries,
+                })
+            })
             .collect::<Vec<_>>();
         info!(
             "{} layouts in prefix | {} specific to pipeline",
@@ -210,6 +265,7 @@ pub trait Pipeline {
             format!("{}.vert", Self::name()).as_str(),
             shaderc::ShaderKind::Vertex,
             Self::vertex_shader(),
+            sample_count,
         );
         let fragment_shader = create_shader(
             device,
@@ -217,6 +273,7 @@ pub trait Pipeline {
             format!("{}.frag", Self::name()).as_str(),
             shaderc::ShaderKind::Fragment,
             Self::fragment_shader(),
+            sample_count,
         );
 
         info!("create_render_pipeline");
@@ -247,7 +304,9 @@ pub trait Pipeline {
 
     /// Reconstructs the pipeline using its original bind group layouts and a new sample count.
     ///
-    /// Pipelines must be reconstructed when the MSAA sample count is changed.
+    /// Pipelines must be reconstructed when the MSAA sample count is changed. Pipelines that read
+    /// multisampled textures must be recreated with `create()` instead when switching to or from a
+    /// sample count of 1, since their bind group layouts change.
     fn recreate(
         device: &wgpu::Device,
         compiler: &mut shaderc::Compiler,
@@ -270,6 +329,7 @@ pub trait Pipeline {
             format!("{}.vert", Self::name()).as_str(),
             shaderc::ShaderKind::Vertex,
             Self::vertex_shader(),
+            sample_count,
         );
         let fragment_shader = create_shader(
             device,
@@ -277,6 +337,7 @@ pub trait Pipeline {
             format!("{}.frag", Self::name()).as_str(),
             shaderc::ShaderKind::Fragment,
             Self::fragment_shader(),
+            sample_count,
         );
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some(&format!("{} pipeline", Self::name())),
@@ -0,0 +1,278 @@
+// Copyright © 2020 Cormac O'Brien.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! GPU timing of frame graph passes.
+//!
+//! With `r_gpuprofile` set, a timestamp is written before and after each pass of the frame graph.
+//! The timestamps are read back at the start of the next frame, which waits for the GPU to finish
+//! the previous one, so profiling lowers the frame rate somewhat. This requires
+//! `wgpu::Features::TIMESTAMP_QUERY`, which the device is opened with if the adapter supports it.
+
+use std::fmt::Write as _;
+
+use byteorder::{ByteOrder, NativeEndian};
+
+/// The maximum number of timestamps written per frame, two for each pass.
+const MAX_TIMESTAMPS: u32 = 32;
+
+const TIMESTAMP_SIZE: u64 = 8;
+
+const NANOSECONDS_PER_MILLISECOND: f32 = 1_000_000.0;
+
+// weight of the newest sample in the running average
+const AVERAGE_WEIGHT: f32 = 0.1;
+
+/// The GPU time taken by one frame graph pass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PassTiming {
+    pub name: &'static str,
+
+    /// The time taken in the most recently measured frame, in milliseconds.
+    pub last_ms: f32,
+
+    /// A running average of the time taken, in milliseconds.
+    pub average_ms: f32,
+}
+
+/// The measured GPU time of each pass, shared with the `gpuprofile` command.
+#[derive(Debug, Default)]
+pub struct GpuTimings {
+    supported: bool,
+    passes: Vec<PassTiming>,
+}
+
+impl GpuTimings {
+    pub fn new() -> GpuTimings {
+        GpuTimings {
+            supported: false,
+            passes: Vec::new(),
+        }
+    }
+
+    /// Returns whether the device supports timestamp queries.
+    pub fn supported(&self) -> bool {
+        self.supported
+    }
+
+    pub fn set_supported(&mut self, supported: bool) {
+        self.supported = supported;
+    }
+
+    /// Records a pass taking `ms` milliseconds, averaging it with earlier frames.
+    pub fn record(&mut self, name: &'static str, ms: f32) {
+        match self.passes.iter_mut().find(|p| p.name == name) {
+            Some(pass) => {
+                pass.last_ms = ms;
+                pass.average_ms += (ms - pass.average_ms) * AVERAGE_WEIGHT;
+            }
+
+            None => self.passes.push(PassTiming {
+                name,
+                last_ms: ms,
+                average_ms: ms,
+            }),
+        }
+    }
+
+    /// Returns the timing of each pass, in the order the passes were first recorded.
+    pub fn passes(&self) -> &[PassTiming] {
+        &self.passes
+    }
+
+    /// Returns the sum of the running averages of all passes.
+    pub fn total_average_ms(&self) -> f32 {
+        self.passes.iter().map(|p| p.average_ms).sum()
+    }
+
+    /// Formats a table of the timing of each pass.
+    pub fn report(&self) -> String {
+        if !self.supported {
+            return String::from("GPU profiling requires timestamp query support\n");
+        }
+
+        if self.passes.is_empty() {
+            return String::from("No GPU timings recorded, set r_gpuprofile 1 to enable\n");
+        }
+
+        let mut report = format!("{:<12} {:>10} {:>10}\n", "pass", "last ms", "avg ms");
+        for pass in self.passes.iter() {
+            writeln!(
+                report,
+                "{:<12} {:>10.3} {:>10.3}",
+                pass.name, pass.last_ms, pass.average_ms
+            )
+            .unwrap();
+        }
+        writeln!(
+            report,
+            "{:<12} {:>10} {:>10.3}",
+            "total",
+            "",
+            self.total_average_ms()
+        )
+        .unwrap();
+
+        report
+    }
+}
+
+/// Writes and reads back the timestamps around each frame graph pass.
+pub struct GpuProfiler {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+
+    // nanoseconds per timestamp tick
+    period: f32,
+
+    // the passes timed in the last recorded frame, in timestamp order
+    passes: Vec<&'static str>,
+}
+
+impl GpuProfiler {
+    /// Creates a profiler, or returns `None` if the device doesn't support timestamp queries.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<GpuProfiler> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_TIMESTAMPS,
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("timestamp resolve buffer"),
+            size: MAX_TIMESTAMPS as u64 * TIMESTAMP_SIZE,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Some(GpuProfiler {
+            query_set,
+            resolve_buffer,
+            period: queue.get_timestamp_period(),
+            passes: Vec::new(),
+        })
+    }
+
+    /// Writes the timestamp at the start of a pass.
+    ///
+    /// Returns the index of the timestamp to pass to `end_pass`, or `None` if every timestamp
+    /// has been used this frame.
+    pub fn begin_pass(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        name: &'static str,
+    ) -> Option<u32> {
+        let index = self.passes.len() as u32 * 2;
+        if index + 2 > MAX_TIMESTAMPS {
+            return None;
+        }
+
+        self.passes.push(name);
+        encoder.write_timestamp(&self.query_set, index);
+        Some(index)
+    }
+
+    /// Writes the timestamp at the end of the pass started at `index`.
+    pub fn end_pass(&self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+        encoder.write_timestamp(&self.query_set, index + 1);
+    }
+
+    /// Copies this frame's timestamps into the buffer read by `read_back`.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        if self.passes.is_empty() {
+            return;
+        }
+
+        encoder.resolve_query_set(
+            &self.query_set,
+            0..self.passes.len() as u32 * 2,
+            &self.resolve_buffer,
+            0,
+        );
+    }
+
+    /// Waits for the last recorded frame to finish on the GPU and records its timings.
+    ///
+    /// This must be called after the command buffer containing that frame is submitted.
+    pub fn read_back(&mut self, device: &wgpu::Device, timings: &mut GpuTimings) {
+        if self.passes.is_empty() {
+            return;
+        }
+
+        let slice = self
+            .resolve_buffer
+            .slice(..self.passes.len() as u64 * 2 * TIMESTAMP_SIZE);
+        let map_future = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+
+        match futures::executor::block_on(map_future) {
+            Ok(()) => {
+                let data = slice.get_mapped_range();
+                for (name, pair) in self.passes.iter().zip(data.chunks_exact(16)) {
+                    let start = NativeEndian::read_u64(&pair[..8]);
+                    let end = NativeEndian::read_u64(&pair[8..]);
+                    let ns = end.saturating_sub(start) as f32 * self.period;
+                    timings.record(name, ns / NANOSECONDS_PER_MILLISECOND);
+                }
+                drop(data);
+                self.resolve_buffer.unmap();
+            }
+
+            Err(_) => warn!("Couldn't map timestamp buffer"),
+        }
+
+        self.passes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_timings_average() {
+        let mut timings = GpuTimings::new();
+        timings.record("world", 2.0);
+        timings.record("ui", 0.5);
+        timings.record("world", 4.0);
+
+        let world = timings.passes()[0];
+        assert_eq!(world.name, "world");
+        assert_eq!(world.last_ms, 4.0);
+        assert!((world.average_ms - 2.2).abs() < 1e-5);
+        assert!((timings.total_average_ms() - 2.7).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_gpu_timings_report() {
+        let mut timings = GpuTimings::new();
+        assert!(timings.report().contains("timestamp query"));
+
+        timings.set_supported(true);
+        assert!(timings.report().contains("r_gpuprofile"));
+
+        timings.record("lighting", 1.0);
+        let report = timings.report();
+        assert!(report.contains("lighting"));
+        assert!(report.contains("total"));
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright © 2020 Cormac O'Brien.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Frame rate and rendering statistics.
+//!
+//! `scr_showfps` displays the frame rate and frame time, and `r_speeds` displays the counters
+//! collected while drawing the world, like the statistics printed by the original engine.
+
+use std::ops::Range;
+
+use chrono::Duration;
+
+/// How often the displayed frame rate is recalculated, in milliseconds.
+const FRAME_RATE_INTERVAL_MS: i64 = 500;
+
+/// Counters collected while recording the world, entity and viewmodel passes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// The number of draw calls recorded.
+    pub draw_calls: u32,
+
+    /// The number of triangles drawn.
+    pub triangles: u32,
+
+    /// The number of world leaves in the PVS and inside the view frustum.
+    pub visible_leaves: u32,
+
+    /// The number of world surfaces in visible leaves.
+    pub visible_surfaces: u32,
+
+    /// The number of entities drawn.
+    pub entities: u32,
+
+    /// The number of bytes in use in the entity uniform buffer.
+    pub uniform_bytes: u64,
+
+    /// The size of the entity uniform buffer in bytes.
+    pub uniform_capacity: u64,
+}
+
+impl RenderStats {
+    /// Records a draw call of the triangle list in `vertices`.
+    pub fn record_draw(&mut self, vertices: &Range<u32>) {
+        self.draw_calls += 1;
+        self.triangles += (vertices.end - vertices.start) / 3;
+    }
+}
+
+/// Measures the frame rate from the time at which each frame is rendered.
+///
+/// The frame rate is averaged over half a second so that it's readable on screen.
+#[derive(Debug, Default)]
+pub struct FrameRate {
+    last_frame: Option<Duration>,
+    frame_time: Duration,
+
+    interval_start: Option<Duration>,
+    interval_frames: u32,
+    fps: f32,
+}
+
+impl FrameRate {
+    pub fn new() -> FrameRate {
+        FrameRate {
+            last_frame: None,
+            frame_time: Duration::zero(),
+            interval_start: None,
+            interval_frames: 0,
+            fps: 0.0,
+        }
+    }
+
+    /// Records a frame rendered at `uptime`.
+    pub fn update(&mut self, uptime: Duration) {
+        if let Some(last) = self.last_frame {
+            self.frame_time = uptime - last;
+        }
+        self.last_frame = Some(uptime);
+
+        let start = match self.interval_start {
+            Some(s) => s,
+            None => {
+                self.interval_start = Some(uptime);
+                return;
+            }
+        };
+
+        self.interval_frames += 1;
+        let elapsed_ms = (uptime - start).num_milliseconds();
+        if elapsed_ms >= FRAME_RATE_INTERVAL_MS {
+            self.fps = self.interval_frames as f32 * 1000.0 / elapsed_ms as f32;
+            self.interval_start = Some(uptime);
+            self.interval_frames = 0;
+        }
+    }
+
+    /// Returns the average frame rate over the last complete interval.
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    /// Returns the time between the last two frames.
+    pub fn frame_time(&self) -> Duration {
+        self.frame_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_draw() {
+        let mut stats = RenderStats::default();
+        stats.record_draw(&(0..6));
+        stats.record_draw(&(12..21));
+        assert_eq!(stats.draw_calls, 2);
+        assert_eq!(stats.triangles, 5);
+    }
+
+    #[test]
+    fn test_frame_rate() {
+        let mut frame_rate = FrameRate::new();
+
+        // 20ms frames
+        for i in 0..25 {
+            frame_rate.update(Duration::milliseconds(20 * i));
+        }
+        assert_eq!(frame_rate.frame_time(), Duration::milliseconds(20));
+        assert_eq!(frame_rate.fps(), 0.0);
+
+        frame_rate.update(Duration::milliseconds(500));
+        assert_eq!(frame_rate.fps(), 50.0);
+    }
+}
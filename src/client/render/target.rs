@@ -122,6 +122,21 @@ impl<'a> RenderPassBuilder<'a> {
             depth_stencil_attachment: self.depth_attachment.clone(),
         }
     }
+
+    /// Keeps the existing contents of the attachments rather than clearing them.
+    pub fn load_existing(mut self) -> RenderPassBuilder<'a> {
+        for attachment in self.color_attachments.iter_mut() {
+            attachment.ops.load = wgpu::LoadOp::Load;
+        }
+
+        if let Some(ref mut attachment) = self.depth_attachment {
+            if let Some(ref mut ops) = attachment.depth_ops {
+                ops.load = wgpu::LoadOp::Load;
+            }
+        }
+
+        self
+    }
 }
 
 /// A trait describing a render target.
@@ -321,20 +336,29 @@ impl RenderTarget for DeferredPassTarget {
     }
 }
 
+/// Render target for the final pass.
+///
+/// If multisampling is enabled, the pass draws into a multisampled attachment which is resolved
+/// into the resolve attachment. Otherwise it draws into the resolve attachment directly.
 pub struct FinalPassTarget {
     size: Extent2d,
     sample_count: u32,
-    color_attachment: wgpu::Texture,
-    color_view: wgpu::TextureView,
+    color_attachment: Option<(wgpu::Texture, wgpu::TextureView)>,
     resolve_attachment: wgpu::Texture,
     resolve_view: wgpu::TextureView,
 }
 
 impl FinalPassTarget {
     pub fn new(device: &wgpu::Device, size: Extent2d, sample_count: u32) -> FinalPassTarget {
-        let color_attachment =
-            create_color_attachment(device, size, sample_count, wgpu::TextureUsage::empty());
-        let color_view = color_attachment.create_view(&Default::default());
+        let color_attachment = if sample_count > 1 {
+            let attachment =
+                create_color_attachment(device, size, sample_count, wgpu::TextureUsage::empty());
+            let view = attachment.create_view(&Default::default());
+            Some((attachment, view))
+        } else {
+            None
+        };
+
         // add COPY_SRC so we can copy to a buffer for capture and SAMPLED so we
         // can blit to the swap chain
         let resolve_attachment = create_color_attachment(
@@ -349,7 +373,6 @@ impl FinalPassTarget {
             size,
             sample_count,
             color_attachment,
-            color_view,
             resolve_attachment,
             resolve_view,
         }
@@ -368,8 +391,11 @@ impl RenderTarget for FinalPassTarget {
     fn render_pass_builder<'a>(&'a self) -> RenderPassBuilder {
         RenderPassBuilder {
             color_attachments: vec![wgpu::RenderPassColorAttachment {
-                view: &self.color_view,
-                resolve_target: Some(self.resolve_view()),
+                view: match self.color_attachment {
+                    Some((_, ref view)) => view,
+                    None => self.resolve_view(),
+                },
+                resolve_target: self.color_attachment.as_ref().map(|_| self.resolve_view()),
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                     store: true,
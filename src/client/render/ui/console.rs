@@ -1,41 +1,80 @@
+use std::cell::Cell;
+
 use crate::{
     client::render::{
         ui::{
-            glyph::{GlyphRendererCommand, GLYPH_HEIGHT, GLYPH_WIDTH},
+            draw_list::{DrawCmd2D, DrawList2D, TextStyle},
+            glyph::{GLYPH_HEIGHT, GLYPH_WIDTH},
             layout::{Anchor, AnchorCoord, Layout, ScreenPosition, Size},
-            quad::{QuadRendererCommand, QuadTexture},
+            quad::QuadTexture,
         },
         GraphicsState,
     },
-    common::{console::Console, engine, wad::QPic},
+    common::{console::Console, engine},
 };
 
 use chrono::Duration;
 
 const PAD_LEFT: i32 = GLYPH_WIDTH as i32;
 
+// proportion of the display the console moves per second while opening or closing
+const SLIDE_SPEED: f32 = 1.5;
+
+// maximum number of output lines drawn
+const MAX_VISIBLE_LINES: usize = 100;
+
 pub struct ConsoleRenderer {
     conback: QuadTexture,
+
+    // current height of the console as a proportion of the display, and the time it was updated
+    slide: Cell<(f32, Option<Duration>)>,
 }
 
 impl ConsoleRenderer {
     pub fn new(state: &GraphicsState) -> ConsoleRenderer {
-        let conback = QuadTexture::from_qpic(
-            state,
-            &QPic::load(state.vfs().open("gfx/conback.lmp").unwrap()).unwrap(),
-        );
+        let conback = QuadTexture::from_qpic(state, &state.load_qpic("gfx/conback.lmp"));
+
+        ConsoleRenderer {
+            conback,
+            slide: Cell::new((1.0, None)),
+        }
+    }
+
+    /// Moves the console toward `target` and returns its new height.
+    fn slide(&self, target: f32, time: Duration) -> f32 {
+        let (current, last_time) = self.slide.get();
+        let elapsed = match last_time {
+            Some(t) => engine::duration_to_f32(time - t).max(0.0),
+            None => 0.0,
+        };
 
-        ConsoleRenderer { conback }
+        let step = SLIDE_SPEED * elapsed;
+        let new = if current < target {
+            (current + step).min(target)
+        } else {
+            (current - step).max(target)
+        };
+
+        self.slide.set((new, Some(time)));
+        new
     }
 
+    /// Generates commands to draw the console.
+    ///
+    /// The console slides toward `proportion` of the display height over time, so this should
+    /// keep being called with a `proportion` of 0 after it is closed.
     pub fn generate_commands<'a>(
         &'a self,
         console: &Console,
         time: Duration,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
         proportion: f32,
     ) {
+        let proportion = self.slide(proportion, time);
+        if proportion <= 0.0 {
+            return;
+        }
+
         // TODO: take scale as cvar
         let scale = 2.0;
         let console_anchor = Anchor {
@@ -44,8 +83,8 @@ impl ConsoleRenderer {
         };
 
         // draw console background
-        quad_cmds.push(QuadRendererCommand {
-            texture: &self.conback,
+        draw_list.push(DrawCmd2D::Pic {
+            pic: &self.conback,
             layout: Layout {
                 position: ScreenPosition::Absolute(console_anchor),
                 anchor: Anchor::BOTTOM_LEFT,
@@ -53,82 +92,94 @@ impl ConsoleRenderer {
             },
         });
 
+        let style = TextStyle::new(Anchor::BOTTOM_LEFT, scale);
+
         // draw version string
         let version_string = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-        glyph_cmds.push(GlyphRendererCommand::Text {
-            text: version_string,
-            position: ScreenPosition::Absolute(console_anchor),
-            anchor: Anchor::BOTTOM_RIGHT,
-            scale,
-        });
+        draw_list.draw_string(
+            version_string,
+            ScreenPosition::Absolute(console_anchor),
+            TextStyle::new(Anchor::BOTTOM_RIGHT, scale),
+        );
 
         // draw input line
-        glyph_cmds.push(GlyphRendererCommand::Glyph {
-            glyph_id: ']' as u8,
-            position: ScreenPosition::Relative {
+        draw_list.draw_char(
+            b']',
+            ScreenPosition::Relative {
                 anchor: console_anchor,
                 x_ofs: PAD_LEFT,
                 y_ofs: 0,
             },
-            anchor: Anchor::BOTTOM_LEFT,
-            scale,
-        });
-        let input_text = console.get_string();
-        glyph_cmds.push(GlyphRendererCommand::Text {
-            text: input_text,
-            position: ScreenPosition::Relative {
+            style,
+        );
+        draw_list.draw_string(
+            console.get_string(),
+            ScreenPosition::Relative {
                 anchor: console_anchor,
                 x_ofs: PAD_LEFT + GLYPH_WIDTH as i32,
                 y_ofs: 0,
             },
-            anchor: Anchor::BOTTOM_LEFT,
-            scale,
-        });
+            style,
+        );
         // blink cursor in half-second intervals
         if engine::duration_to_f32(time).fract() > 0.5 {
-            glyph_cmds.push(GlyphRendererCommand::Glyph {
-                glyph_id: 11,
-                position: ScreenPosition::Relative {
+            draw_list.draw_char(
+                11,
+                ScreenPosition::Relative {
                     anchor: console_anchor,
                     x_ofs: PAD_LEFT + (GLYPH_WIDTH * (console.cursor() + 1)) as i32,
                     y_ofs: 0,
                 },
-                anchor: Anchor::BOTTOM_LEFT,
-                scale,
-            });
+                style,
+            );
+        }
+
+        // when scrolled back, mark the bottom row to show there is more output below
+        let mut first_row = 1;
+        if console.scroll() > 0 {
+            draw_list.draw_string(
+                "^   ".repeat(10),
+                ScreenPosition::Relative {
+                    anchor: console_anchor,
+                    x_ofs: PAD_LEFT + 1,
+                    y_ofs: GLYPH_HEIGHT as i32,
+                },
+                style,
+            );
+            first_row += 1;
         }
 
         // draw previous output
-        for (line_id, line) in console.output().lines().enumerate() {
-            // TODO: implement scrolling
-            if line_id > 100 {
-                break;
-            }
-
-            for (chr_id, chr) in line.iter().enumerate() {
-                let position = ScreenPosition::Relative {
+        let output = console.output();
+        let lines = output
+            .lines()
+            .skip(console.scroll())
+            .take(MAX_VISIBLE_LINES);
+        for (line_id, line) in lines.enumerate() {
+            let text = line
+                .iter()
+                .map(|chr| {
+                    if *chr as u32 > std::u8::MAX as u32 {
+                        warn!(
+                            "char \"{}\" (U+{:4}) cannot be displayed in the console",
+                            *chr, *chr as u32
+                        );
+                        '?'
+                    } else {
+                        *chr
+                    }
+                })
+                .collect::<String>();
+
+            draw_list.draw_string(
+                text,
+                ScreenPosition::Relative {
                     anchor: console_anchor,
-                    x_ofs: PAD_LEFT + (1 + chr_id * GLYPH_WIDTH) as i32,
-                    y_ofs: ((line_id + 1) * GLYPH_HEIGHT) as i32,
-                };
-
-                let c = if *chr as u32 > std::u8::MAX as u32 {
-                    warn!(
-                        "char \"{}\" (U+{:4}) cannot be displayed in the console",
-                        *chr, *chr as u32
-                    );
-                    '?'
-                } else {
-                    *chr
-                };
-
-                glyph_cmds.push(GlyphRendererCommand::Glyph {
-                    glyph_id: c as u8,
-                    position,
-                    anchor: Anchor::BOTTOM_LEFT,
-                    scale,
-                });
-            }
+                    x_ofs: PAD_LEFT + 1,
+                    y_ofs: ((line_id + first_row) * GLYPH_HEIGHT) as i32,
+                },
+                style,
+            );
         }
     }
 }
@@ -0,0 +1,682 @@
+//! Renderer-agnostic 2D drawing.
+//!
+//! The console, HUD and menus describe what they want drawn by filling a [`DrawList2D`], which is
+//! then consumed by the quad and glyph pipelines. The list holds no graphics API types: pictures
+//! are referred to by a handle which only needs to report its dimensions, so UI code can be
+//! exercised without a device.
+
+use crate::client::render::{
+    ui::{
+        glyph::{GLYPH_HEIGHT, GLYPH_WIDTH},
+        layout::{Anchor, Layout, ScreenPosition, Size},
+    },
+    Extent2d,
+};
+
+/// A handle to a picture that can be drawn by a [`DrawList2D`].
+pub trait PicHandle {
+    /// The width of the picture in pixels.
+    fn width(&self) -> u32;
+
+    /// The height of the picture in pixels.
+    fn height(&self) -> u32;
+}
+
+impl<T> PicHandle for &T
+where
+    T: PicHandle,
+{
+    fn width(&self) -> u32 {
+        (*self).width()
+    }
+
+    fn height(&self) -> u32 {
+        (*self).height()
+    }
+}
+
+/// How a glyph or run of text is drawn.
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+    /// The point on the text that is placed at its position.
+    pub anchor: Anchor,
+
+    pub scale: f32,
+
+    /// An RGBA color multiplied with the glyphs.
+    pub color: [u8; 4],
+
+    /// If set, text is wrapped onto further lines so that no line is wider than this many
+    /// unscaled pixels.
+    pub wrap_width: Option<u32>,
+
+    /// If set, each line is centered within the block rather than starting at its left edge.
+    pub center_lines: bool,
+}
+
+impl TextStyle {
+    pub const WHITE: [u8; 4] = [0xFF; 4];
+
+    /// Returns a style drawing unwrapped white text.
+    pub fn new(anchor: Anchor, scale: f32) -> TextStyle {
+        TextStyle {
+            anchor,
+            scale,
+            color: TextStyle::WHITE,
+            wrap_width: None,
+            center_lines: false,
+        }
+    }
+
+    pub fn with_color(self, color: [u8; 4]) -> TextStyle {
+        TextStyle { color, ..self }
+    }
+
+    pub fn with_wrap_width(self, wrap_width: u32) -> TextStyle {
+        TextStyle {
+            wrap_width: Some(wrap_width),
+            ..self
+        }
+    }
+
+    pub fn with_centered_lines(self) -> TextStyle {
+        TextStyle {
+            center_lines: true,
+            ..self
+        }
+    }
+}
+
+/// A single 2D draw command.
+#[derive(Clone, Debug)]
+pub enum DrawCmd2D<P> {
+    /// Draw a picture.
+    Pic { pic: P, layout: Layout },
+
+    /// Fill an area by repeating a picture.
+    ///
+    /// The area is determined by `layout`, and the picture is repeated at `scale` times its size
+    /// starting from the top-left corner.
+    TileFill { pic: P, layout: Layout, scale: f32 },
+
+    /// Fill an area with a solid RGBA color.
+    Rect { color: [u8; 4], layout: Layout },
+
+    /// Draw a single glyph.
+    Glyph {
+        glyph_id: u8,
+        position: ScreenPosition,
+        style: TextStyle,
+    },
+
+    /// Draw a run of text.
+    ///
+    /// The text is broken into lines at newlines and, if the style has a wrap width, between
+    /// words. The anchor applies to the block of lines as a whole.
+    Text {
+        text: String,
+        position: ScreenPosition,
+        style: TextStyle,
+    },
+}
+
+/// A rectangle in screen space, in pixels from the bottom-left corner of the display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScreenRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// What a quad is filled with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuadFill<P> {
+    Pic(P),
+    Color([u8; 4]),
+}
+
+/// A quad resolved to screen space.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quad2D<P> {
+    pub fill: QuadFill<P>,
+    pub rect: ScreenRect,
+
+    /// How many times the picture is repeated along each axis.
+    pub texcoord_scale: [f32; 2],
+}
+
+/// A glyph resolved to screen space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Glyph2D {
+    pub glyph_id: u8,
+    pub rect: ScreenRect,
+    pub color: [u8; 4],
+}
+
+/// A list of 2D draw commands.
+///
+/// This is the screen-space drawing layer shared by the HUD, console and menus. Quads (pictures,
+/// fills and rectangles) are drawn in the order they were added, followed by all glyphs in the
+/// order they were added. The UI pass that draws the list runs after the 3D scene has been
+/// postprocessed, so everything in it appears over the world.
+#[derive(Clone, Debug)]
+pub struct DrawList2D<P> {
+    commands: Vec<DrawCmd2D<P>>,
+}
+
+impl<P> DrawList2D<P> {
+    pub fn new() -> DrawList2D<P> {
+        DrawList2D {
+            commands: Vec::new(),
+        }
+    }
+
+    /// Removes all commands, keeping the allocated storage.
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    pub fn push(&mut self, command: DrawCmd2D<P>) {
+        self.commands.push(command);
+    }
+
+    pub fn pic(&mut self, pic: P, layout: Layout) {
+        self.push(DrawCmd2D::Pic { pic, layout });
+    }
+
+    pub fn tile_fill(&mut self, pic: P, layout: Layout, scale: f32) {
+        self.push(DrawCmd2D::TileFill { pic, layout, scale });
+    }
+
+    pub fn rect(&mut self, color: [u8; 4], layout: Layout) {
+        self.push(DrawCmd2D::Rect { color, layout });
+    }
+
+    /// Draws a picture at `scale` times its size, with its `anchor` placed at `position`.
+    pub fn draw_pic(&mut self, pic: P, position: ScreenPosition, anchor: Anchor, scale: f32) {
+        self.pic(
+            pic,
+            Layout {
+                position,
+                anchor,
+                size: Size::Scale { factor: scale },
+            },
+        );
+    }
+
+    /// Draws a picture stretched to cover `rect`.
+    pub fn draw_stretch_pic(&mut self, pic: P, rect: ScreenRect) {
+        self.pic(pic, rect_layout(rect));
+    }
+
+    /// Fills `rect` with a solid RGBA color.
+    pub fn draw_fill(&mut self, color: [u8; 4], rect: ScreenRect) {
+        self.rect(color, rect_layout(rect));
+    }
+
+    pub fn draw_char(&mut self, glyph_id: u8, position: ScreenPosition, style: TextStyle) {
+        self.push(DrawCmd2D::Glyph {
+            glyph_id,
+            position,
+            style,
+        });
+    }
+
+    pub fn draw_string<S>(&mut self, text: S, position: ScreenPosition, style: TextStyle)
+    where
+        S: Into<String>,
+    {
+        self.push(DrawCmd2D::Text {
+            text: text.into(),
+            position,
+            style,
+        });
+    }
+
+    pub fn commands(&self) -> &[DrawCmd2D<P>] {
+        &self.commands
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Resolves the glyph and text commands to screen space.
+    ///
+    /// Text is cut off at the right edge of the display.
+    pub fn glyphs(&self, display_size: Extent2d) -> Vec<Glyph2D> {
+        let Extent2d {
+            width: display_width,
+            height: display_height,
+        } = display_size;
+
+        let mut glyphs = Vec::new();
+        for cmd in self.commands.iter() {
+            match cmd {
+                DrawCmd2D::Glyph {
+                    glyph_id,
+                    position,
+                    style,
+                } => {
+                    let (width, height) = glyph_size(style.scale);
+                    let (screen_x, screen_y) =
+                        position.to_xy(display_width, display_height, style.scale);
+                    let (glyph_x, glyph_y) = style.anchor.to_xy(width, height);
+
+                    glyphs.push(Glyph2D {
+                        glyph_id: *glyph_id,
+                        rect: ScreenRect {
+                            x: screen_x - glyph_x,
+                            y: screen_y - glyph_y,
+                            width,
+                            height,
+                        },
+                        color: style.color,
+                    });
+                }
+
+                DrawCmd2D::Text {
+                    text,
+                    position,
+                    style,
+                } => {
+                    let scale = style.scale;
+                    let lines = wrap_lines(
+                        text,
+                        style.wrap_width.map(|w| (w as usize / GLYPH_WIDTH).max(1)),
+                    );
+                    let columns = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+
+                    let (width, height) = glyph_size(scale);
+                    let (screen_x, screen_y) = position.to_xy(display_width, display_height, scale);
+                    let (block_x, block_y) = style.anchor.to_xy(
+                        ((columns * GLYPH_WIDTH) as f32 * scale) as u32,
+                        height * lines.len() as u32,
+                    );
+                    let x = screen_x - block_x;
+
+                    for (line_id, line) in lines.iter().enumerate() {
+                        // the first line is at the top of the block
+                        let y = screen_y - block_y
+                            + (height * (lines.len() - 1 - line_id) as u32) as i32;
+                        let line_x = if style.center_lines {
+                            let margin = columns - line.chars().count();
+                            x + ((GLYPH_WIDTH * margin) as f32 * scale / 2.0) as i32
+                        } else {
+                            x
+                        };
+
+                        for (chr_id, chr) in line.chars().enumerate() {
+                            let abs_x = line_x + ((GLYPH_WIDTH * chr_id) as f32 * scale) as i32;
+
+                            if abs_x >= display_width as i32 {
+                                // don't render past the edge of the screen
+                                break;
+                            }
+
+                            glyphs.push(Glyph2D {
+                                glyph_id: chr as u8,
+                                rect: ScreenRect {
+                                    x: abs_x,
+                                    y,
+                                    width,
+                                    height,
+                                },
+                                color: style.color,
+                            });
+                        }
+                    }
+                }
+
+                _ => (),
+            }
+        }
+
+        glyphs
+    }
+}
+
+impl<P> DrawList2D<P>
+where
+    P: PicHandle + Clone,
+{
+    /// Resolves the picture, fill and rectangle commands to screen space.
+    pub fn quads(&self, display_size: Extent2d) -> Vec<Quad2D<P>> {
+        let Extent2d {
+            width: display_width,
+            height: display_height,
+        } = display_size;
+
+        let mut quads = Vec::new();
+        for cmd in self.commands.iter() {
+            match cmd {
+                DrawCmd2D::Pic { pic, layout } => {
+                    let Layout {
+                        position,
+                        anchor,
+                        size,
+                    } = *layout;
+
+                    let scale = match size {
+                        Size::Scale { factor } => factor,
+                        _ => 1.0,
+                    };
+
+                    let (screen_x, screen_y) = position.to_xy(display_width, display_height, scale);
+                    let (quad_x, quad_y) = anchor.to_xy(pic.width(), pic.height());
+                    let (width, height) =
+                        size.to_wh(pic.width(), pic.height(), display_width, display_height);
+
+                    quads.push(Quad2D {
+                        fill: QuadFill::Pic(pic.clone()),
+                        rect: ScreenRect {
+                            x: screen_x - (quad_x as f32 * scale) as i32,
+                            y: screen_y - (quad_y as f32 * scale) as i32,
+                            width,
+                            height,
+                        },
+                        texcoord_scale: [1.0, 1.0],
+                    });
+                }
+
+                DrawCmd2D::TileFill { pic, layout, scale } => {
+                    let rect =
+                        region_rect(layout, *scale, (pic.width(), pic.height()), display_size);
+
+                    quads.push(Quad2D {
+                        fill: QuadFill::Pic(pic.clone()),
+                        rect,
+                        texcoord_scale: [
+                            rect.width as f32 / (pic.width() as f32 * scale),
+                            rect.height as f32 / (pic.height() as f32 * scale),
+                        ],
+                    });
+                }
+
+                DrawCmd2D::Rect { color, layout } => {
+                    quads.push(Quad2D {
+                        fill: QuadFill::Color(*color),
+                        rect: region_rect(layout, 1.0, (1, 1), display_size),
+                        texcoord_scale: [1.0, 1.0],
+                    });
+                }
+
+                _ => (),
+            }
+        }
+
+        quads
+    }
+}
+
+impl<P> std::default::Default for DrawList2D<P> {
+    fn default() -> DrawList2D<P> {
+        DrawList2D::new()
+    }
+}
+
+fn glyph_size(scale: f32) -> (u32, u32) {
+    (
+        (GLYPH_WIDTH as f32 * scale) as u32,
+        (GLYPH_HEIGHT as f32 * scale) as u32,
+    )
+}
+
+// split text into lines at newlines and, if `max_columns` is given, so that no line is longer than
+// `max_columns` characters, preferring to break at spaces
+fn wrap_lines(text: &str, max_columns: Option<usize>) -> Vec<&str> {
+    let mut lines = Vec::new();
+    for mut line in text.split('\n') {
+        if let Some(max_columns) = max_columns {
+            while let Some((limit, _)) = line.char_indices().nth(max_columns) {
+                let break_at = if line[limit..].starts_with(' ') {
+                    Some(limit)
+                } else {
+                    line[..limit].rfind(' ')
+                };
+
+                match break_at {
+                    // the space itself is dropped
+                    Some(i) if i > 0 => {
+                        lines.push(&line[..i]);
+                        line = &line[i + 1..];
+                    }
+
+                    // no space to break at, so break mid-word
+                    _ => {
+                        lines.push(&line[..limit]);
+                        line = &line[limit..];
+                    }
+                }
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+// a layout covering exactly `rect`
+fn rect_layout(rect: ScreenRect) -> Layout {
+    Layout {
+        position: ScreenPosition::Absolute(Anchor::absolute_xy(rect.x, rect.y)),
+        anchor: Anchor::BOTTOM_LEFT,
+        size: Size::Absolute {
+            width: rect.width,
+            height: rect.height,
+        },
+    }
+}
+
+// the anchor of a filled region is relative to the region itself rather than to a picture
+fn region_rect(
+    layout: &Layout,
+    scale: f32,
+    (base_width, base_height): (u32, u32),
+    display_size: Extent2d,
+) -> ScreenRect {
+    let Layout {
+        position,
+        anchor,
+        size,
+    } = *layout;
+
+    let (width, height) = size.to_wh(
+        base_width,
+        base_height,
+        display_size.width,
+        display_size.height,
+    );
+    let (screen_x, screen_y) = position.to_xy(display_size.width, display_size.height, scale);
+    let (region_x, region_y) = anchor.to_xy(width, height);
+
+    ScreenRect {
+        x: screen_x - region_x,
+        y: screen_y - region_y,
+        width,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct TestPic {
+        width: u32,
+        height: u32,
+    }
+
+    impl PicHandle for TestPic {
+        fn width(&self) -> u32 {
+            self.width
+        }
+
+        fn height(&self) -> u32 {
+            self.height
+        }
+    }
+
+    const DISPLAY: Extent2d = Extent2d {
+        width: 640,
+        height: 480,
+    };
+
+    #[test]
+    fn test_pic_anchored_center() {
+        let pic = TestPic {
+            width: 32,
+            height: 16,
+        };
+        let mut list = DrawList2D::new();
+        list.pic(
+            &pic,
+            Layout {
+                position: ScreenPosition::Absolute(Anchor::CENTER),
+                anchor: Anchor::CENTER,
+                size: Size::Scale { factor: 2.0 },
+            },
+        );
+
+        let quads = list.quads(DISPLAY);
+        assert_eq!(quads.len(), 1);
+        assert_eq!(quads[0].fill, QuadFill::Pic(&pic));
+        assert_eq!(
+            quads[0].rect,
+            ScreenRect {
+                x: 288,
+                y: 224,
+                width: 64,
+                height: 32,
+            }
+        );
+    }
+
+    #[test]
+    fn test_draw_stretch_pic_and_fill() {
+        let pic = TestPic {
+            width: 32,
+            height: 16,
+        };
+        let rect = ScreenRect {
+            x: 10,
+            y: 20,
+            width: 100,
+            height: 50,
+        };
+        let mut list = DrawList2D::new();
+        list.draw_stretch_pic(&pic, rect);
+        list.draw_fill([0, 0, 0, 0xFF], rect);
+
+        let quads = list.quads(DISPLAY);
+        assert_eq!(quads.len(), 2);
+        assert_eq!(quads[0].rect, rect);
+        assert_eq!(quads[1].fill, QuadFill::Color([0, 0, 0, 0xFF]));
+        assert_eq!(quads[1].rect, rect);
+    }
+
+    #[test]
+    fn test_tile_fill_repeats() {
+        let pic = TestPic {
+            width: 64,
+            height: 64,
+        };
+        let mut list = DrawList2D::new();
+        list.tile_fill(
+            pic,
+            Layout {
+                position: ScreenPosition::Absolute(Anchor::BOTTOM_LEFT),
+                anchor: Anchor::BOTTOM_LEFT,
+                size: Size::DisplayScale { ratio: 1.0 },
+            },
+            2.0,
+        );
+
+        let quads = list.quads(DISPLAY);
+        assert_eq!(quads[0].rect.width, 640);
+        assert_eq!(quads[0].texcoord_scale, [5.0, 3.75]);
+    }
+
+    #[test]
+    fn test_text_clipped_at_edge() {
+        let mut list: DrawList2D<TestPic> = DrawList2D::new();
+        list.draw_string(
+            "abcdef",
+            ScreenPosition::Absolute(Anchor::absolute_xy(624, 0)),
+            TextStyle::new(Anchor::BOTTOM_LEFT, 1.0),
+        );
+        list.rect(
+            [0, 0, 0, 128],
+            Layout {
+                position: ScreenPosition::Absolute(Anchor::BOTTOM_LEFT),
+                anchor: Anchor::BOTTOM_LEFT,
+                size: Size::Absolute {
+                    width: 10,
+                    height: 10,
+                },
+            },
+        );
+
+        // only two 8-pixel glyphs fit, and the rect isn't a glyph
+        let glyphs = list.glyphs(DISPLAY);
+        assert_eq!(glyphs.len(), 2);
+        assert_eq!(glyphs[1].glyph_id, b'b');
+        assert_eq!(glyphs[1].rect.x, 632);
+        assert_eq!(list.quads(DISPLAY).len(), 1);
+    }
+
+    #[test]
+    fn test_wrap_lines() {
+        assert_eq!(wrap_lines("one two three", None), vec!["one two three"]);
+        assert_eq!(wrap_lines("one\ntwo", None), vec!["one", "two"]);
+        assert_eq!(
+            wrap_lines("one two three", Some(8)),
+            vec!["one two", "three"]
+        );
+        // a space right at the limit is a break point
+        assert_eq!(wrap_lines("one two", Some(3)), vec!["one", "two"]);
+        // words longer than a line are split
+        assert_eq!(wrap_lines("abcdefgh", Some(3)), vec!["abc", "def", "gh"]);
+    }
+
+    #[test]
+    fn test_text_wrapped_block() {
+        let mut list: DrawList2D<TestPic> = DrawList2D::new();
+        let red = [0xFF, 0, 0, 0xFF];
+        list.draw_string(
+            "ab cd",
+            ScreenPosition::Absolute(Anchor::absolute_xy(100, 100)),
+            TextStyle::new(Anchor::TOP_LEFT, 2.0)
+                .with_color(red)
+                .with_wrap_width(16),
+        );
+
+        // two lines of two 16-pixel glyphs, hanging down from the position
+        let glyphs = list.glyphs(DISPLAY);
+        assert_eq!(glyphs.len(), 4);
+        assert_eq!(glyphs[0].glyph_id, b'a');
+        assert_eq!((glyphs[0].rect.x, glyphs[0].rect.y), (100, 84));
+        assert_eq!(glyphs[2].glyph_id, b'c');
+        assert_eq!((glyphs[2].rect.x, glyphs[2].rect.y), (100, 68));
+        assert!(glyphs.iter().all(|g| g.color == red));
+    }
+
+    #[test]
+    fn test_text_centered_lines() {
+        let mut list: DrawList2D<TestPic> = DrawList2D::new();
+        list.draw_string(
+            "abcd\nef",
+            ScreenPosition::Absolute(Anchor::absolute_xy(100, 100)),
+            TextStyle::new(Anchor::TOP_LEFT, 1.0).with_centered_lines(),
+        );
+
+        // the shorter line is indented by half the difference in width
+        let glyphs = list.glyphs(DISPLAY);
+        assert_eq!(glyphs[0].rect.x, 100);
+        assert_eq!(glyphs[4].glyph_id, b'e');
+        assert_eq!(glyphs[4].rect.x, 108);
+    }
+}
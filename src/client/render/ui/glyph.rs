@@ -3,7 +3,7 @@ use std::{mem::size_of, num::NonZeroU32};
 use crate::{
     client::render::{
         ui::{
-            layout::{Anchor, ScreenPosition},
+            draw_list::{Glyph2D, ScreenRect},
             quad::{QuadPipeline, QuadVertex},
             screen_space_vertex_scale, screen_space_vertex_translate,
         },
@@ -33,7 +33,8 @@ lazy_static! {
         wgpu::vertex_attr_array![
             2 => Float32x2, // a_instance_position
             3 => Float32x2, // a_instance_scale
-            4 => Uint32 // a_instance_layer
+            4 => Uint32, // a_instance_layer
+            5 => Unorm8x4 // a_instance_color
         ].to_vec(),
     ];
 }
@@ -172,21 +173,7 @@ pub struct GlyphInstance {
     pub position: Vector2<f32>,
     pub scale: Vector2<f32>,
     pub layer: u32,
-}
-
-pub enum GlyphRendererCommand {
-    Glyph {
-        glyph_id: u8,
-        position: ScreenPosition,
-        anchor: Anchor,
-        scale: f32,
-    },
-    Text {
-        text: String,
-        position: ScreenPosition,
-        anchor: Anchor,
-        scale: f32,
-    },
+    pub color: [u8; 4],
 }
 
 pub struct GlyphRenderer {
@@ -267,91 +254,41 @@ impl GlyphRenderer {
 
     pub fn generate_instances(
         &self,
-        commands: &[GlyphRendererCommand],
+        glyphs: &[Glyph2D],
         target_size: Extent2d,
     ) -> Vec<GlyphInstance> {
-        let mut instances = Vec::new();
         let Extent2d {
             width: display_width,
             height: display_height,
         } = target_size;
-        for cmd in commands {
-            match cmd {
-                GlyphRendererCommand::Glyph {
-                    glyph_id,
-                    position,
-                    anchor,
-                    scale,
-                } => {
-                    let (screen_x, screen_y) =
-                        position.to_xy(display_width, display_height, *scale);
-                    let (glyph_x, glyph_y) = anchor.to_xy(
-                        (GLYPH_WIDTH as f32 * scale) as u32,
-                        (GLYPH_HEIGHT as f32 * scale) as u32,
-                    );
-                    let x = screen_x - glyph_x;
-                    let y = screen_y - glyph_y;
-
-                    instances.push(GlyphInstance {
-                        position: screen_space_vertex_translate(
-                            display_width,
-                            display_height,
-                            x,
-                            y,
-                        ),
-                        scale: screen_space_vertex_scale(
-                            display_width,
-                            display_height,
-                            (GLYPH_WIDTH as f32 * scale) as u32,
-                            (GLYPH_HEIGHT as f32 * scale) as u32,
-                        ),
-                        layer: *glyph_id as u32,
-                    });
-                }
-                GlyphRendererCommand::Text {
-                    text,
-                    position,
-                    anchor,
-                    scale,
-                } => {
-                    let (screen_x, screen_y) =
-                        position.to_xy(display_width, display_height, *scale);
-                    let (glyph_x, glyph_y) = anchor.to_xy(
-                        ((text.chars().count() * GLYPH_WIDTH) as f32 * scale) as u32,
-                        (GLYPH_HEIGHT as f32 * scale) as u32,
-                    );
-                    let x = screen_x - glyph_x;
-                    let y = screen_y - glyph_y;
-
-                    for (chr_id, chr) in text.as_str().chars().enumerate() {
-                        let abs_x = x + ((GLYPH_WIDTH * chr_id) as f32 * scale) as i32;
-
-                        if abs_x >= display_width as i32 {
-                            // don't render past the edge of the screen
-                            break;
-                        }
-
-                        instances.push(GlyphInstance {
-                            position: screen_space_vertex_translate(
-                                display_width,
-                                display_height,
-                                abs_x,
-                                y,
-                            ),
-                            scale: screen_space_vertex_scale(
-                                display_width,
-                                display_height,
-                                (GLYPH_WIDTH as f32 * scale) as u32,
-                                (GLYPH_HEIGHT as f32 * scale) as u32,
-                            ),
-                            layer: chr as u32,
-                        });
-                    }
-                }
-            }
+
+        if glyphs.len() > MAX_INSTANCES {
+            warn!(
+                "Too many glyphs ({}), only drawing the first {}",
+                glyphs.len(),
+                MAX_INSTANCES
+            );
         }
 
-        instances
+        glyphs
+            .iter()
+            .take(MAX_INSTANCES)
+            .map(|glyph| {
+                let ScreenRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                } = glyph.rect;
+
+                GlyphInstance {
+                    position: screen_space_vertex_translate(display_width, display_height, x, y),
+                    scale: screen_space_vertex_scale(display_width, display_height, width, height),
+                    layer: glyph.glyph_id as u32,
+                    color: glyph.color,
+                }
+            })
+            .collect()
     }
 
     pub fn record_draw<'a>(
@@ -359,9 +296,9 @@ impl GlyphRenderer {
         state: &'a GraphicsState,
         pass: &mut wgpu::RenderPass<'a>,
         target_size: Extent2d,
-        commands: &[GlyphRendererCommand],
+        glyphs: &[Glyph2D],
     ) {
-        let instances = self.generate_instances(commands, target_size);
+        let instances = self.generate_instances(glyphs, target_size);
         state
             .queue()
             .write_buffer(state.glyph_pipeline().instance_buffer(), 0, unsafe {
@@ -371,6 +308,6 @@ impl GlyphRenderer {
         pass.set_vertex_buffer(0, state.quad_pipeline().vertex_buffer().slice(..));
         pass.set_vertex_buffer(1, state.glyph_pipeline().instance_buffer().slice(..));
         pass.set_bind_group(0, &self.const_bind_group, &[]);
-        pass.draw(0..6, 0..commands.len() as u32);
+        pass.draw(0..6, 0..instances.len() as u32);
     }
 }
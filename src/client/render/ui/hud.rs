@@ -4,18 +4,19 @@ use crate::{
     client::{
         render::{
             ui::{
-                glyph::GlyphRendererCommand,
+                draw_list::{DrawList2D, ScreenRect, TextStyle},
                 layout::{Anchor, Layout, ScreenPosition, Size},
-                quad::{QuadRendererCommand, QuadTexture},
+                quad::QuadTexture,
             },
-            GraphicsState,
+            Extent2d, GraphicsState,
         },
+        stats::ClientStats,
         IntermissionKind,
     },
     common::{
         console::Console,
-        net::{ClientStat, ItemFlags},
-        wad::QPic,
+        mission_pack::{hipnotic, rogue, MissionPack},
+        net::ItemFlags,
     },
 };
 
@@ -34,22 +35,177 @@ const OVERLAY_Y_OFS: i32 = -OVERLAY_HEIGHT / 2;
 
 const OVERLAY_ANCHOR: Anchor = Anchor::CENTER;
 
+// height of the status bar
+const SBAR_HEIGHT: u32 = 24;
+
+// combined height of the status and inventory bars
+const SBAR_LINES: u32 = 48;
+
+/// Returns the height of the visible part of the status bar for the given `viewsize`.
+///
+/// As in Quake, the inventory bar is hidden at 110 and above and the whole status bar is hidden at
+/// 120 and above.
+pub fn sbar_lines(viewsize: f32) -> u32 {
+    if viewsize >= 120.0 {
+        0
+    } else if viewsize >= 110.0 {
+        SBAR_HEIGHT
+    } else {
+        SBAR_LINES
+    }
+}
+
+/// Returns the area of the display covered by the 3D view for the given `viewsize`.
+///
+/// At 100 and above the view covers the whole display and the status bar is drawn over it. Below
+/// 100 the view is shrunk proportionally and centered in the area above the status bar, as in
+/// Quake.
+pub fn view_rect(viewsize: f32, display_size: Extent2d, scale: f32) -> ScreenRect {
+    let Extent2d { width, height } = display_size;
+    let size = viewsize.max(30.0);
+    if size >= 100.0 {
+        return ScreenRect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+    }
+
+    let sbar_height = ((sbar_lines(size) as f32 * scale) as u32).min(height);
+    let avail_height = height - sbar_height;
+    let view_width = (width as f32 * size / 100.0) as u32;
+    let view_height = (avail_height as f32 * size / 100.0) as u32;
+
+    ScreenRect {
+        x: ((width - view_width) / 2) as i32,
+        y: (sbar_height + (avail_height - view_height) / 2) as i32,
+        width: view_width,
+        height: view_height,
+    }
+}
+
+/// How the crosshair is drawn, selected by the `crosshair` cvar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrosshairStyle {
+    /// No crosshair (`crosshair 0`).
+    None,
+
+    /// The `+` character from the console font (`crosshair 1`).
+    Glyph,
+
+    /// A square dot (`crosshair 2`).
+    Dot,
+
+    /// Four lines around an open center (`crosshair 3`).
+    Cross,
+}
+
+impl CrosshairStyle {
+    pub fn from_cvar(value: f32) -> CrosshairStyle {
+        match value as i32 {
+            1 => CrosshairStyle::Glyph,
+            2 => CrosshairStyle::Dot,
+            3 => CrosshairStyle::Cross,
+            _ => CrosshairStyle::None,
+        }
+    }
+}
+
+/// The crosshair drawn at the center of the view.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crosshair {
+    pub style: CrosshairStyle,
+
+    /// An RGBA color, taken from the palette entry given by `crosshaircolor`.
+    pub color: [u8; 4],
+
+    /// A multiplier applied to the HUD scale, set by `crosshairsize`.
+    pub size: f32,
+}
+
+/// Returns the rectangles making up a `style` crosshair centered on `(x, y)`.
+///
+/// `unit` is the width in pixels of the lines making up the crosshair. Glyph crosshairs are
+/// drawn as text and have no rectangles.
+fn crosshair_rects(style: CrosshairStyle, x: i32, y: i32, unit: u32) -> Vec<ScreenRect> {
+    let unit = unit.max(1);
+    let half = (unit / 2) as i32;
+
+    match style {
+        CrosshairStyle::None | CrosshairStyle::Glyph => Vec::new(),
+
+        CrosshairStyle::Dot => vec![ScreenRect {
+            x: x - unit as i32,
+            y: y - unit as i32,
+            width: 2 * unit,
+            height: 2 * unit,
+        }],
+
+        CrosshairStyle::Cross => {
+            let gap = unit as i32;
+            let len = 3 * unit;
+            vec![
+                // left, right, bottom, top
+                ScreenRect {
+                    x: x - gap - len as i32,
+                    y: y - half,
+                    width: len,
+                    height: unit,
+                },
+                ScreenRect {
+                    x: x + gap,
+                    y: y - half,
+                    width: len,
+                    height: unit,
+                },
+                ScreenRect {
+                    x: x - half,
+                    y: y - gap - len as i32,
+                    width: unit,
+                    height: len,
+                },
+                ScreenRect {
+                    x: x - half,
+                    y: y + gap,
+                    width: unit,
+                    height: len,
+                },
+            ]
+        }
+    }
+}
+
 pub enum HudState<'a> {
     InGame {
+        view_rect: ScreenRect,
+        viewsize: f32,
         items: ItemFlags,
         item_pickup_time: &'a [Duration],
-        stats: &'a [i32],
+        stats: &'a ClientStats,
         face_anim_time: Duration,
+        crosshair: Crosshair,
         console: &'a Console,
+        mission_pack: Option<MissionPack>,
     },
     Intermission {
         kind: &'a IntermissionKind,
         completion_duration: Duration,
-        stats: &'a [i32],
+        stats: &'a ClientStats,
         console: &'a Console,
     },
 }
 
+impl<'a> HudState<'a> {
+    /// Returns the console whose notify lines are displayed.
+    pub fn console(&self) -> &'a Console {
+        match *self {
+            HudState::InGame { console, .. } => console,
+            HudState::Intermission { console, .. } => console,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 enum HudTextureId {
     Digit { alt: bool, value: usize },
@@ -65,6 +221,15 @@ enum HudTextureId {
     StatusBar,
     InvBar,
     ScoreBar,
+    BackTile,
+
+    // these are only in the mission packs' gfx.wad
+    HipnoticWeapon { id: HipWeaponId, frame: WeaponFrame },
+    HipnoticItem { id: usize },
+    RogueInvBar { powered: bool },
+    RogueWeapon { id: usize },
+    RogueAmmo { id: usize },
+    RogueItem { id: usize },
 
     // these are not in gfx.wad
     Complete,
@@ -88,6 +253,15 @@ impl std::fmt::Display for HudTextureId {
             StatusBar => write!(f, "SBAR"),
             InvBar => write!(f, "IBAR"),
             ScoreBar => write!(f, "SCOREBAR"),
+            BackTile => write!(f, "BACKTILE"),
+
+            // these are only in the mission packs' gfx.wad
+            HipnoticWeapon { id, frame } => write!(f, "INV{}_{}", frame, id),
+            HipnoticItem { id } => write!(f, "{}", HIPNOTIC_ITEM_NAMES[id]),
+            RogueInvBar { powered } => write!(f, "R_INVBAR{}", if powered { 1 } else { 2 }),
+            RogueWeapon { id } => write!(f, "{}", ROGUE_WEAPON_NAMES[id]),
+            RogueAmmo { id } => write!(f, "{}", ROGUE_AMMO_NAMES[id]),
+            RogueItem { id } => write!(f, "{}", ROGUE_ITEM_NAMES[id]),
 
             // these are not in gfx.wad
             Complete => write!(f, "gfx/complete.lmp"),
@@ -133,6 +307,54 @@ impl std::fmt::Display for WeaponFrame {
     }
 }
 
+impl WeaponFrame {
+    // Returns the frame of a weapon icon picked up at `pickup_time`, which flashes for a moment
+    // after the weapon is picked up.
+    fn new(time: Duration, pickup_time: Duration, active: bool) -> WeaponFrame {
+        let delta = time - pickup_time;
+        if delta >= Duration::milliseconds(100) {
+            if active {
+                WeaponFrame::Active
+            } else {
+                WeaponFrame::Inactive
+            }
+        } else {
+            WeaponFrame::Pickup {
+                frame: (delta.num_milliseconds() * 100) as usize % 5,
+            }
+        }
+    }
+}
+
+const HIPNOTIC_WEAPON_ID_NAMES: [&'static str; 5] =
+    ["LASER", "MJOLNIR", "GREN_PROX", "PROX_GL", "PROX"];
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, EnumIter)]
+enum HipWeaponId {
+    LaserCannon = 0,
+    Mjolnir = 1,
+    // the grenade launcher slot while the proximity gun is flashing
+    ProximityGrenade = 2,
+    // the grenade launcher slot when both it and the proximity gun are held
+    ProximityLauncher = 3,
+    ProximityGun = 4,
+}
+
+impl std::fmt::Display for HipWeaponId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", HIPNOTIC_WEAPON_ID_NAMES[*self as usize])
+    }
+}
+
+const HIPNOTIC_ITEM_NAMES: [&'static str; 2] = ["SB_WSUIT", "SB_ESHLD"];
+const HIPNOTIC_ITEMS: [u32; 2] = [hipnotic::WETSUIT, hipnotic::EMPATHY_SHIELDS];
+
+// the powered-up weapons replacing the nailgun through the lightning gun, in order
+const ROGUE_WEAPON_NAMES: [&'static str; 5] =
+    ["R_LAVA", "R_SUPERLAVA", "R_GREN", "R_MULTIROCK", "R_PLASMA"];
+const ROGUE_AMMO_NAMES: [&'static str; 3] = ["R_AMMOLAVA", "R_AMMOMULTI", "R_AMMOPLASMA"];
+const ROGUE_ITEM_NAMES: [&'static str; 2] = ["R_SHIELD1", "R_AGRAV1"];
+const ROGUE_ITEMS: [u32; 2] = [rogue::SHIELD, rogue::ANTIGRAV];
+
 const AMMO_ID_NAMES: [&'static str; 4] = ["SHELLS", "NAILS", "ROCKET", "CELLS"];
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, FromPrimitive, EnumIter)]
 enum AmmoId {
@@ -245,21 +467,42 @@ impl HudRenderer {
         );
 
         // unit variants
-        ids.extend(vec![Colon, Slash, StatusBar, InvBar, ScoreBar].into_iter());
+        ids.extend(vec![Colon, Slash, StatusBar, InvBar, ScoreBar, BackTile].into_iter());
 
         let mut textures = HashMap::new();
         for id in ids.into_iter() {
             debug!("Opening {}", id);
-            let qpic = state.gfx_wad().open_qpic(id.to_string()).unwrap();
+            let qpic = state.load_wad_qpic(id.to_string());
             let texture = QuadTexture::from_qpic(state, &qpic);
             textures.insert(id, texture);
         }
 
+        // the mission pack icons are only loaded if the mission pack's gfx.wad is mounted
+        let mut ids = Vec::new();
+        ids.extend(HipWeaponId::iter().flat_map(|id| {
+            (0..5)
+                .map(|frame| WeaponFrame::Pickup { frame })
+                .chain(std::iter::once(WeaponFrame::Inactive))
+                .chain(std::iter::once(WeaponFrame::Active))
+                .map(move |frame| HipnoticWeapon { id, frame })
+        }));
+        ids.extend((0..HIPNOTIC_ITEM_NAMES.len()).map(|id| HipnoticItem { id }));
+        ids.extend((&[false, true]).iter().map(|b| RogueInvBar { powered: *b }));
+        ids.extend((0..ROGUE_WEAPON_NAMES.len()).map(|id| RogueWeapon { id }));
+        ids.extend((0..ROGUE_AMMO_NAMES.len()).map(|id| RogueAmmo { id }));
+        ids.extend((0..ROGUE_ITEM_NAMES.len()).map(|id| RogueItem { id }));
+        for id in ids.into_iter() {
+            if let Ok(qpic) = state.gfx_wad().open_qpic(id.to_string()) {
+                debug!("Opening {}", id);
+                textures.insert(id, QuadTexture::from_qpic(state, &qpic));
+            }
+        }
+
         // new id list for textures not in gfx.wad
         let ids = vec![Complete, Intermission];
         for id in ids.into_iter() {
             debug!("Opening {}", id);
-            let qpic = QPic::load(state.vfs().open(&format!("{}", id)).unwrap()).unwrap();
+            let qpic = state.load_qpic(format!("{}", id));
             textures.insert(id, QuadTexture::from_qpic(state, &qpic));
         }
 
@@ -276,7 +519,7 @@ impl HudRenderer {
         screen_y_ofs: i32,
         quad_anchor: Anchor,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) {
         use HudTextureId::*;
 
@@ -301,18 +544,16 @@ impl HudRenderer {
                 _ => unreachable!(),
             };
 
-            quad_cmds.push(QuadRendererCommand {
-                texture: self.textures.get(&tex_id).unwrap(),
-                layout: Layout {
-                    position: ScreenPosition::Relative {
-                        anchor: screen_anchor,
-                        x_ofs: screen_x_ofs + place_ofs + 24 * chr_id as i32,
-                        y_ofs: screen_y_ofs,
-                    },
-                    anchor: quad_anchor,
-                    size: Size::Scale { factor: scale },
+            draw_list.draw_pic(
+                self.textures.get(&tex_id).unwrap(),
+                ScreenPosition::Relative {
+                    anchor: screen_anchor,
+                    x_ofs: screen_x_ofs + place_ofs + 24 * chr_id as i32,
+                    y_ofs: screen_y_ofs,
                 },
-            });
+                quad_anchor,
+                scale,
+            );
         }
     }
 
@@ -326,20 +567,45 @@ impl HudRenderer {
         x_ofs: i32,
         y_ofs: i32,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) {
-        quad_cmds.push(QuadRendererCommand {
-            texture: self.textures.get(&texture_id).unwrap(),
-            layout: Layout {
-                position: ScreenPosition::Relative {
+        draw_list.draw_pic(
+            self.textures.get(&texture_id).unwrap(),
+            ScreenPosition::Relative {
+                anchor: Anchor::BOTTOM_CENTER,
+                x_ofs: OVERLAY_X_OFS + x_ofs,
+                y_ofs,
+            },
+            Anchor::BOTTOM_LEFT,
+            scale,
+        );
+    }
+
+    // Draw a quad on the status bar at the same position as Quake's `Sbar_DrawPic`.
+    //
+    // `x_ofs` is relative to the left edge of the status bar and `y_ofs` is measured down from its
+    // top edge, so the inventory bar is at negative offsets. Textures that aren't loaded, like the
+    // mission pack icons when playing the base game, are skipped.
+    fn cmd_sbar_pic<'a>(
+        &'a self,
+        texture_id: HudTextureId,
+        x_ofs: i32,
+        y_ofs: i32,
+        scale: f32,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
+    ) {
+        if let Some(texture) = self.textures.get(&texture_id) {
+            draw_list.draw_pic(
+                texture,
+                ScreenPosition::Relative {
                     anchor: Anchor::BOTTOM_CENTER,
                     x_ofs: OVERLAY_X_OFS + x_ofs,
-                    y_ofs,
+                    y_ofs: SBAR_HEIGHT as i32 - y_ofs,
                 },
-                anchor: Anchor::BOTTOM_LEFT,
-                size: Size::Scale { factor: scale },
-            },
-        });
+                Anchor::TOP_LEFT,
+                scale,
+            );
+        }
     }
 
     // Draw a quad on the status bar.
@@ -354,7 +620,7 @@ impl HudRenderer {
         x_ofs: i32,
         y_ofs: i32,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) {
         self.cmd_number(
             number,
@@ -365,183 +631,382 @@ impl HudRenderer {
             y_ofs,
             Anchor::BOTTOM_LEFT,
             scale,
-            quad_cmds,
+            draw_list,
         );
     }
 
     // Draw the status bar.
+    //
+    // `sbar_lines` is the visible height of the status bar as returned by `sbar_lines`.
     fn cmd_sbar<'a>(
         &'a self,
         time: Duration,
+        sbar_lines: u32,
         items: ItemFlags,
         item_pickup_time: &'a [Duration],
-        stats: &'a [i32],
+        stats: &'a ClientStats,
         face_anim_time: Duration,
+        mission_pack: Option<MissionPack>,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) {
         use HudTextureId::*;
 
-        let sbar = self.textures.get(&StatusBar).unwrap();
-        let sbar_x_ofs = -(sbar.width() as i32) / 2;
+        if sbar_lines == 0 {
+            return;
+        }
 
         // status bar background
-        self.cmd_sbar_quad(StatusBar, 0, 0, scale, quad_cmds);
+        self.cmd_sbar_quad(StatusBar, 0, 0, scale, draw_list);
+
+        if sbar_lines > SBAR_HEIGHT {
+            self.cmd_inventory(
+                time,
+                items,
+                item_pickup_time,
+                stats,
+                mission_pack,
+                scale,
+                draw_list,
+            );
+        }
+
+        // Scourge of Armagon moves the keys next to the health
+        if mission_pack == Some(MissionPack::Hipnotic) {
+            if items.contains(ItemFlags::KEY_1) {
+                let id = Item { id: ItemId::Key1 };
+                self.cmd_sbar_pic(id, 209, 3, scale, draw_list);
+            }
+            if items.contains(ItemFlags::KEY_2) {
+                let id = Item { id: ItemId::Key2 };
+                self.cmd_sbar_pic(id, 209, 12, scale, draw_list);
+            }
+        }
 
-        // inventory bar background
-        self.cmd_sbar_quad(InvBar, 0, sbar.height() as i32, scale, quad_cmds);
+        // armor
+        let armor_width = self.textures.get(&Armor { id: 0 }).unwrap().width() as i32;
+        if items.contains(ItemFlags::INVULNERABILITY) {
+            self.cmd_sbar_number(666, true, 3, armor_width, 0, scale, draw_list);
+        // TODO draw_disc
+        } else {
+            let armor = stats.armor;
+            self.cmd_sbar_number(armor, armor <= 25, 3, armor_width, 0, scale, draw_list);
+
+            // Dissolution of Eternity moves the armor out of the way of its weapons
+            let armor_1 = match mission_pack {
+                Some(MissionPack::Rogue) => rogue::ARMOR_1,
+                _ => ItemFlags::ARMOR_1.bits(),
+            };
+
+            let mut armor_id = None;
+            for i in (0..3).rev() {
+                if items.bits() & armor_1 << i != 0 {
+                    armor_id = Some(Armor { id: i });
+                    break;
+                }
+            }
+
+            if let Some(a) = armor_id {
+                self.cmd_sbar_quad(a, 0, 0, scale, draw_list);
+            }
+        }
+
+        // health
+        let health = stats.health;
+        self.cmd_sbar_number(health, health <= 25, 3, 136, 0, scale, draw_list);
+
+        // ammo
+        let ammo_icon = match mission_pack {
+            Some(MissionPack::Rogue) => {
+                let ammo = [rogue::SHELLS, rogue::NAILS, rogue::ROCKETS, rogue::CELLS]
+                    .iter()
+                    .zip(AmmoId::iter().map(|id| Ammo { id }));
+                let special = [rogue::LAVA_NAILS, rogue::MULTI_ROCKETS, rogue::PLASMA_AMMO]
+                    .iter()
+                    .zip((0..ROGUE_AMMO_NAMES.len()).map(|id| RogueAmmo { id }));
+                ammo.chain(special)
+                    .find(|(bit, _)| items.bits() & *bit != 0)
+                    .map(|(_, id)| id)
+            }
+            _ => AmmoId::iter()
+                .find(|id| {
+                    items.contains(
+                        ItemFlags::from_bits(ItemFlags::SHELLS.bits() << *id as usize).unwrap(),
+                    )
+                })
+                .map(|id| Ammo { id }),
+        };
+        if let Some(id) = ammo_icon {
+            self.cmd_sbar_pic(id, 224, 0, scale, draw_list);
+        }
+
+        let ammo = stats.ammo;
+        self.cmd_sbar_number(ammo, ammo <= 10, 3, 248, 0, scale, draw_list);
+
+        let face = if items.contains(ItemFlags::INVISIBILITY | ItemFlags::INVULNERABILITY) {
+            FaceId::InvisibleInvulnerable
+        } else if items.contains(ItemFlags::QUAD) {
+            FaceId::QuadDamage
+        } else if items.contains(ItemFlags::INVISIBILITY) {
+            FaceId::Invisible
+        } else if items.contains(ItemFlags::INVULNERABILITY) {
+            FaceId::Invulnerable
+        } else {
+            let health = stats.health;
+            let frame = 4 - if health >= 100 {
+                4
+            } else {
+                health.max(0) as usize / 20
+            };
+
+            FaceId::Normal {
+                pain: face_anim_time > time,
+                frame,
+            }
+        };
+
+        self.cmd_sbar_quad(Face { id: face }, 112, 0, scale, draw_list);
+    }
+
+    // Draw the inventory bar above the status bar.
+    fn cmd_inventory<'a>(
+        &'a self,
+        time: Duration,
+        items: ItemFlags,
+        item_pickup_time: &'a [Duration],
+        stats: &'a ClientStats,
+        mission_pack: Option<MissionPack>,
+        scale: f32,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
+    ) {
+        use HudTextureId::*;
+
+        let sbar = self.textures.get(&StatusBar).unwrap();
+        let sbar_x_ofs = -(sbar.width() as i32) / 2;
+
+        // inventory bar background. Dissolution of Eternity highlights the powered-up weapons
+        let inv_bar = match mission_pack {
+            Some(MissionPack::Rogue) => RogueInvBar {
+                powered: stats.active_weapon as u32 >= rogue::LAVA_NAILGUN,
+            },
+            _ => InvBar,
+        };
+        if self.textures.contains_key(&inv_bar) {
+            self.cmd_sbar_quad(inv_bar, 0, sbar.height() as i32, scale, draw_list);
+        } else {
+            self.cmd_sbar_quad(InvBar, 0, sbar.height() as i32, scale, draw_list);
+        }
 
         // weapon slots
         for i in 0..7 {
-            if items.contains(ItemFlags::from_bits(ItemFlags::SHOTGUN.bits() << i).unwrap()) {
+            let weapon = ItemFlags::from_bits(ItemFlags::SHOTGUN.bits() << i).unwrap();
+            if items.contains(weapon) {
                 let id = WeaponId::from_usize(i).unwrap();
-                let pickup_time = item_pickup_time[i];
-                let delta = time - pickup_time;
-                let frame = if delta >= Duration::milliseconds(100) {
-                    if stats[ClientStat::ActiveWeapon as usize] as u32
-                        == ItemFlags::SHOTGUN.bits() << i
-                    {
-                        WeaponFrame::Active
-                    } else {
-                        WeaponFrame::Inactive
-                    }
-                } else {
-                    WeaponFrame::Pickup {
-                        frame: (delta.num_milliseconds() * 100) as usize % 5,
-                    }
-                };
+                let frame =
+                    WeaponFrame::new(time, item_pickup_time[i], stats.is_active_weapon(weapon));
 
                 self.cmd_sbar_quad(
                     Weapon { id, frame },
                     24 * i as i32,
                     sbar.height() as i32,
                     scale,
-                    quad_cmds,
+                    draw_list,
                 );
             }
         }
 
+        match mission_pack {
+            Some(MissionPack::Hipnotic) => {
+                self.cmd_hipnotic_weapons(time, items, item_pickup_time, stats, scale, draw_list)
+            }
+
+            // the powered-up weapon replaces its regular counterpart
+            Some(MissionPack::Rogue) => {
+                for i in 0..ROGUE_WEAPON_NAMES.len() {
+                    if stats.active_weapon as u32 == rogue::LAVA_NAILGUN << i {
+                        let id = RogueWeapon { id: i };
+                        self.cmd_sbar_pic(id, (i as i32 + 2) * 24, -16, scale, draw_list);
+                    }
+                }
+            }
+
+            None => (),
+        }
+
         // ammo counters
-        for i in 0..4 {
-            let ammo_str = format!("{: >3}", stats[ClientStat::Shells as usize + i]);
+        for (i, ammo) in stats.ammo_counts().iter().enumerate() {
+            let ammo_str = format!("{: >3}", ammo);
             for (chr_id, chr) in ammo_str.chars().enumerate() {
                 if chr != ' ' {
-                    glyph_cmds.push(GlyphRendererCommand::Glyph {
-                        glyph_id: 18 + chr as u8 - '0' as u8,
-                        position: ScreenPosition::Relative {
+                    draw_list.draw_char(
+                        18 + chr as u8 - '0' as u8,
+                        ScreenPosition::Relative {
                             anchor: Anchor::BOTTOM_CENTER,
                             x_ofs: sbar_x_ofs + 8 * (6 * i + chr_id) as i32 + 10,
                             y_ofs: sbar.height() as i32 + 16,
                         },
-                        anchor: Anchor::BOTTOM_LEFT,
-                        scale,
-                    });
+                        TextStyle::new(Anchor::BOTTOM_LEFT, scale),
+                    );
                 }
             }
         }
 
-        // items (keys and powerups)
-        for i in 0..6 {
+        // items (keys and powerups). Scourge of Armagon draws the keys on the status bar
+        let first_item = match mission_pack {
+            Some(MissionPack::Hipnotic) => 2,
+            _ => 0,
+        };
+        for i in first_item..6 {
             if items.contains(ItemFlags::from_bits(ItemFlags::KEY_1.bits() << i).unwrap()) {
-                quad_cmds.push(QuadRendererCommand {
-                    texture: self
-                        .textures
+                draw_list.draw_pic(
+                    self.textures
                         .get(&Item {
                             id: ItemId::from_usize(i).unwrap(),
                         })
                         .unwrap(),
-                    layout: Layout {
-                        position: ScreenPosition::Relative {
-                            anchor: Anchor::BOTTOM_CENTER,
-                            x_ofs: sbar_x_ofs + 16 * i as i32 + 192,
-                            y_ofs: sbar.height() as i32,
-                        },
-                        anchor: Anchor::BOTTOM_LEFT,
-                        size: Size::Scale { factor: scale },
+                    ScreenPosition::Relative {
+                        anchor: Anchor::BOTTOM_CENTER,
+                        x_ofs: sbar_x_ofs + 16 * i as i32 + 192,
+                        y_ofs: sbar.height() as i32,
                     },
-                })
+                    Anchor::BOTTOM_LEFT,
+                    scale,
+                )
             }
         }
 
+        if mission_pack == Some(MissionPack::Hipnotic) {
+            for (i, bit) in HIPNOTIC_ITEMS.iter().enumerate() {
+                if items.bits() & bit != 0 {
+                    let id = HipnoticItem { id: i };
+                    self.cmd_sbar_pic(id, 288 + 16 * i as i32, -16, scale, draw_list);
+                }
+            }
+        }
+
+        // Dissolution of Eternity's items replace the sigils
+        if mission_pack == Some(MissionPack::Rogue) {
+            for (i, bit) in ROGUE_ITEMS.iter().enumerate() {
+                if items.bits() & bit != 0 {
+                    let id = RogueItem { id: i };
+                    self.cmd_sbar_pic(id, 288 + 16 * i as i32, -16, scale, draw_list);
+                }
+            }
+            return;
+        }
+
         // sigils
         for i in 0..4 {
             if items.contains(ItemFlags::from_bits(ItemFlags::SIGIL_1.bits() << i).unwrap()) {
-                quad_cmds.push(QuadRendererCommand {
-                    texture: self.textures.get(&Sigil { id: i }).unwrap(),
-                    layout: Layout {
-                        position: ScreenPosition::Relative {
-                            anchor: Anchor::BOTTOM_CENTER,
-                            x_ofs: sbar_x_ofs + 8 * i as i32 + 288,
-                            y_ofs: sbar.height() as i32,
-                        },
-                        anchor: Anchor::BOTTOM_LEFT,
-                        size: Size::Scale { factor: scale },
+                draw_list.draw_pic(
+                    self.textures.get(&Sigil { id: i }).unwrap(),
+                    ScreenPosition::Relative {
+                        anchor: Anchor::BOTTOM_CENTER,
+                        x_ofs: sbar_x_ofs + 8 * i as i32 + 288,
+                        y_ofs: sbar.height() as i32,
                     },
-                });
+                    Anchor::BOTTOM_LEFT,
+                    scale,
+                );
             }
         }
+    }
 
-        // armor
-        let armor_width = self.textures.get(&Armor { id: 0 }).unwrap().width() as i32;
-        if items.contains(ItemFlags::INVULNERABILITY) {
-            self.cmd_sbar_number(666, true, 3, armor_width, 0, scale, quad_cmds);
-        // TODO draw_disc
-        } else {
-            let armor = stats[ClientStat::Armor as usize];
-            self.cmd_sbar_number(armor, armor <= 25, 3, armor_width, 0, scale, quad_cmds);
+    // Draw Scourge of Armagon's weapons on the inventory bar.
+    //
+    // The laser cannon and Mjolnir get slots of their own. The proximity gun shares the grenade
+    // launcher's slot, which shows whichever of the two was picked up last while it flashes.
+    fn cmd_hipnotic_weapons<'a>(
+        &'a self,
+        time: Duration,
+        items: ItemFlags,
+        item_pickup_time: &'a [Duration],
+        stats: &'a ClientStats,
+        scale: f32,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
+    ) {
+        use HipWeaponId::*;
+        use HudTextureId::*;
 
-            let mut armor_id = None;
-            for i in (0..3).rev() {
-                if items.contains(ItemFlags::from_bits(ItemFlags::ARMOR_1.bits() << i).unwrap()) {
-                    armor_id = Some(Armor { id: i });
-                    break;
-                }
-            }
+        let weapon_frame = |bit: u32| {
+            WeaponFrame::new(
+                time,
+                item_pickup_time[bit.trailing_zeros() as usize],
+                stats.active_weapon as u32 == bit,
+            )
+        };
 
-            if let Some(a) = armor_id {
-                self.cmd_sbar_quad(a, 0, 0, scale, quad_cmds);
+        for (i, (bit, id)) in [
+            (hipnotic::LASER_CANNON, LaserCannon),
+            (hipnotic::MJOLNIR, Mjolnir),
+        ]
+        .iter()
+        .enumerate()
+        {
+            if items.bits() & bit != 0 {
+                let frame = weapon_frame(*bit);
+                let id = HipnoticWeapon { id: *id, frame };
+                self.cmd_sbar_pic(id, 176 + 24 * i as i32, -16, scale, draw_list);
             }
         }
 
-        // health
-        let health = stats[ClientStat::Health as usize];
-        self.cmd_sbar_number(health, health <= 25, 3, 136, 0, scale, quad_cmds);
-
-        let ammo = stats[ClientStat::Ammo as usize];
-        self.cmd_sbar_number(ammo, ammo <= 10, 3, 248, 0, scale, quad_cmds);
+        let has_launcher = items.contains(ItemFlags::GRENADE_LAUNCHER);
+        let has_prox_gun = items.bits() & hipnotic::PROXIMITY_GUN != 0;
+
+        let mut grenade_flashing = false;
+        if has_launcher && has_prox_gun {
+            let frame = weapon_frame(ItemFlags::GRENADE_LAUNCHER.bits());
+            if frame != WeaponFrame::Inactive {
+                grenade_flashing = true;
+                let id = HipnoticWeapon {
+                    id: ProximityGrenade,
+                    frame,
+                };
+                self.cmd_sbar_pic(id, 96, -16, scale, draw_list);
+            }
+        }
 
-        let face = if items.contains(ItemFlags::INVISIBILITY | ItemFlags::INVULNERABILITY) {
-            FaceId::InvisibleInvulnerable
-        } else if items.contains(ItemFlags::QUAD) {
-            FaceId::QuadDamage
-        } else if items.contains(ItemFlags::INVISIBILITY) {
-            FaceId::Invisible
-        } else if items.contains(ItemFlags::INVULNERABILITY) {
-            FaceId::Invulnerable
-        } else {
-            let health = stats[ClientStat::Health as usize];
-            let frame = 4 - if health >= 100 {
-                4
+        if has_prox_gun && !grenade_flashing {
+            let frame = weapon_frame(hipnotic::PROXIMITY_GUN);
+            let id = if has_launcher {
+                HipnoticWeapon {
+                    id: ProximityLauncher,
+                    frame,
+                }
             } else {
-                health.max(0) as usize / 20
+                HipnoticWeapon {
+                    id: ProximityGun,
+                    frame,
+                }
             };
+            self.cmd_sbar_pic(id, 96, -16, scale, draw_list);
+        }
+    }
 
-            FaceId::Normal {
-                pain: face_anim_time > time,
-                frame,
-            }
-        };
-
-        self.cmd_sbar_quad(Face { id: face }, 112, 0, scale, quad_cmds);
+    // Draw the crosshair at the center of the view.
+    fn cmd_crosshair<P>(
+        &self,
+        crosshair: Crosshair,
+        view_rect: ScreenRect,
+        scale: f32,
+        draw_list: &mut DrawList2D<P>,
+    ) {
+        let scale = scale * crosshair.size.max(0.0);
+        let x = view_rect.x + (view_rect.width / 2) as i32;
+        let y = view_rect.y + (view_rect.height / 2) as i32;
+
+        if crosshair.style == CrosshairStyle::Glyph {
+            draw_list.draw_char(
+                b'+',
+                ScreenPosition::Absolute(Anchor::absolute_xy(x, y)),
+                TextStyle::new(Anchor::CENTER, scale).with_color(crosshair.color),
+            );
+            return;
+        }
 
-        // crosshair
-        glyph_cmds.push(GlyphRendererCommand::Glyph {
-            glyph_id: '+' as u8,
-            position: ScreenPosition::Absolute(Anchor::CENTER),
-            anchor: Anchor::TOP_LEFT,
-            scale,
-        });
+        for rect in crosshair_rects(crosshair.style, x, y, scale as u32) {
+            draw_list.draw_fill(crosshair.color, rect);
+        }
     }
 
     // Draw a quad on the intermission overlay.
@@ -554,20 +1019,18 @@ impl HudRenderer {
         x_ofs: i32,
         y_ofs: i32,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) {
-        quad_cmds.push(QuadRendererCommand {
-            texture: self.textures.get(&texture_id).unwrap(),
-            layout: Layout {
-                position: ScreenPosition::Relative {
-                    anchor: Anchor::CENTER,
-                    x_ofs: OVERLAY_X_OFS + x_ofs,
-                    y_ofs: OVERLAY_Y_OFS + y_ofs,
-                },
-                anchor: Anchor::TOP_LEFT,
-                size: Size::Scale { factor: scale },
+        draw_list.draw_pic(
+            self.textures.get(&texture_id).unwrap(),
+            ScreenPosition::Relative {
+                anchor: Anchor::CENTER,
+                x_ofs: OVERLAY_X_OFS + x_ofs,
+                y_ofs: OVERLAY_Y_OFS + y_ofs,
             },
-        });
+            Anchor::TOP_LEFT,
+            scale,
+        );
     }
 
     // Draw a number on the intermission overlay.
@@ -581,7 +1044,7 @@ impl HudRenderer {
         x_ofs: i32,
         y_ofs: i32,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) {
         self.cmd_number(
             number,
@@ -592,7 +1055,7 @@ impl HudRenderer {
             OVERLAY_Y_OFS + y_ofs,
             Anchor::TOP_LEFT,
             scale,
-            quad_cmds,
+            draw_list,
         );
     }
 
@@ -601,40 +1064,114 @@ impl HudRenderer {
         &'a self,
         _kind: &'a IntermissionKind,
         completion_duration: Duration,
-        stats: &'a [i32],
+        stats: &'a ClientStats,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) {
         use HudTextureId::*;
 
         // TODO: check gametype
 
-        self.cmd_intermission_quad(Complete, 64, OVERLAY_HEIGHT - 24, scale, quad_cmds);
-        self.cmd_intermission_quad(Intermission, 0, OVERLAY_HEIGHT - 56, scale, quad_cmds);
+        self.cmd_intermission_quad(Complete, 64, OVERLAY_HEIGHT - 24, scale, draw_list);
+        self.cmd_intermission_quad(Intermission, 0, OVERLAY_HEIGHT - 56, scale, draw_list);
 
         // TODO: zero-pad number of seconds
         let time_y_ofs = OVERLAY_HEIGHT - 64;
         let minutes = completion_duration.num_minutes() as i32;
         let seconds = completion_duration.num_seconds() as i32 - 60 * minutes;
-        self.cmd_intermission_number(minutes, 3, 160, time_y_ofs, scale, quad_cmds);
-        self.cmd_intermission_quad(Colon, 234, time_y_ofs, scale, quad_cmds);
-        self.cmd_intermission_number(seconds, 2, 246, time_y_ofs, scale, quad_cmds);
+        self.cmd_intermission_number(minutes, 3, 160, time_y_ofs, scale, draw_list);
+        self.cmd_intermission_quad(Colon, 234, time_y_ofs, scale, draw_list);
+        self.cmd_intermission_number(seconds, 2, 246, time_y_ofs, scale, draw_list);
 
         // secrets
         let secrets_y_ofs = OVERLAY_HEIGHT - 104;
-        let secrets_found = stats[ClientStat::FoundSecrets as usize];
-        let secrets_total = stats[ClientStat::TotalSecrets as usize];
-        self.cmd_intermission_number(secrets_found, 3, 160, secrets_y_ofs, scale, quad_cmds);
-        self.cmd_intermission_quad(Slash, 232, secrets_y_ofs, scale, quad_cmds);
-        self.cmd_intermission_number(secrets_total, 3, 240, secrets_y_ofs, scale, quad_cmds);
+        let secrets_found = stats.found_secrets;
+        let secrets_total = stats.total_secrets;
+        self.cmd_intermission_number(secrets_found, 3, 160, secrets_y_ofs, scale, draw_list);
+        self.cmd_intermission_quad(Slash, 232, secrets_y_ofs, scale, draw_list);
+        self.cmd_intermission_number(secrets_total, 3, 240, secrets_y_ofs, scale, draw_list);
 
         // monsters
         let monsters_y_ofs = OVERLAY_HEIGHT - 144;
-        let monsters_killed = stats[ClientStat::KilledMonsters as usize];
-        let monsters_total = stats[ClientStat::TotalMonsters as usize];
-        self.cmd_intermission_number(monsters_killed, 3, 160, monsters_y_ofs, scale, quad_cmds);
-        self.cmd_intermission_quad(Slash, 232, monsters_y_ofs, scale, quad_cmds);
-        self.cmd_intermission_number(monsters_total, 3, 240, monsters_y_ofs, scale, quad_cmds);
+        let monsters_killed = stats.killed_monsters;
+        let monsters_total = stats.total_monsters;
+        self.cmd_intermission_number(monsters_killed, 3, 160, monsters_y_ofs, scale, draw_list);
+        self.cmd_intermission_quad(Slash, 232, monsters_y_ofs, scale, draw_list);
+        self.cmd_intermission_number(monsters_total, 3, 240, monsters_y_ofs, scale, draw_list);
+    }
+
+    // Draw the console lines printed within `timeout` at the top of the screen.
+    fn cmd_notify_lines<P>(
+        &self,
+        console: &Console,
+        timeout: Duration,
+        scale: f32,
+        draw_list: &mut DrawList2D<P>,
+    ) {
+        let output = console.output();
+        for (id, line) in output.recent_lines(timeout, 100, 10).enumerate() {
+            draw_list.draw_string(
+                line.iter().collect::<String>(),
+                ScreenPosition::Relative {
+                    anchor: Anchor::TOP_LEFT,
+                    x_ofs: 0,
+                    y_ofs: -8 * id as i32,
+                },
+                TextStyle::new(Anchor::TOP_LEFT, scale),
+            );
+        }
+    }
+
+    // Fill the parts of the display outside the 3D view with the background tile.
+    fn cmd_backtile<'a>(
+        &'a self,
+        view_rect: ScreenRect,
+        display_size: Extent2d,
+        scale: f32,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
+    ) {
+        let backtile = self.textures.get(&HudTextureId::BackTile).unwrap();
+        let ScreenRect {
+            x: view_x,
+            y: view_y,
+            width: view_width,
+            height: view_height,
+        } = view_rect;
+        let view_top = view_y + view_height as i32;
+        let display_width = display_size.width;
+        let display_height = display_size.height as i32;
+
+        // above, below, left of and right of the view
+        let regions = [
+            (0, view_top, display_width, display_height - view_top),
+            (0, 0, display_width, view_y),
+            (0, view_y, view_x as u32, view_height as i32),
+            (
+                view_x + view_width as i32,
+                view_y,
+                display_width.saturating_sub(view_x as u32 + view_width),
+                view_height as i32,
+            ),
+        ];
+
+        for &(x, y, width, height) in regions.iter() {
+            if width == 0 || height <= 0 {
+                continue;
+            }
+
+            draw_list.tile_fill(
+                backtile,
+                Layout {
+                    position: ScreenPosition::Absolute(Anchor::absolute_xy(x, y)),
+                    anchor: Anchor::BOTTOM_LEFT,
+                    size: Size::Absolute {
+                        width,
+                        height: height as u32,
+                    },
+                },
+                scale,
+            );
+        }
     }
 
     /// Generate render commands to draw the HUD in the specified state.
@@ -642,47 +1179,40 @@ impl HudRenderer {
         &'a self,
         hud_state: &HudState<'a>,
         time: Duration,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+        display_size: Extent2d,
+        scale: f32,
+        notify_time: Duration,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) {
-        // TODO: get from cvar
-        let scale = 2.0;
-        let console_timeout = Duration::seconds(3);
-
         match hud_state {
             HudState::InGame {
+                view_rect,
+                viewsize,
                 items,
                 item_pickup_time,
                 stats,
                 face_anim_time,
+                crosshair,
                 console,
+                mission_pack,
             } => {
+                // nothing is drawn if the view covers the display
+                self.cmd_backtile(*view_rect, display_size, scale, draw_list);
+
                 self.cmd_sbar(
                     time,
+                    sbar_lines(*viewsize),
                     *items,
                     item_pickup_time,
                     stats,
                     *face_anim_time,
+                    *mission_pack,
                     scale,
-                    quad_cmds,
-                    glyph_cmds,
+                    draw_list,
                 );
 
-                let output = console.output();
-                for (id, line) in output.recent_lines(console_timeout, 100, 10).enumerate() {
-                    for (chr_id, chr) in line.into_iter().enumerate() {
-                        glyph_cmds.push(GlyphRendererCommand::Glyph {
-                            glyph_id: *chr as u8,
-                            position: ScreenPosition::Relative {
-                                anchor: Anchor::TOP_LEFT,
-                                x_ofs: 8 * chr_id as i32,
-                                y_ofs: -8 * id as i32,
-                            },
-                            anchor: Anchor::TOP_LEFT,
-                            scale,
-                        });
-                    }
-                }
+                self.cmd_crosshair(*crosshair, *view_rect, scale, draw_list);
+                self.cmd_notify_lines(console, notify_time, scale, draw_list);
             }
             HudState::Intermission {
                 kind,
@@ -690,25 +1220,97 @@ impl HudRenderer {
                 stats,
                 console,
             } => {
-                self.cmd_intermission_overlay(kind, *completion_duration, stats, scale, quad_cmds);
-
-                // TODO: dedup this code
-                let output = console.output();
-                for (id, line) in output.recent_lines(console_timeout, 100, 10).enumerate() {
-                    for (chr_id, chr) in line.into_iter().enumerate() {
-                        glyph_cmds.push(GlyphRendererCommand::Glyph {
-                            glyph_id: *chr as u8,
-                            position: ScreenPosition::Relative {
-                                anchor: Anchor::TOP_LEFT,
-                                x_ofs: 8 * chr_id as i32,
-                                y_ofs: -8 * id as i32,
-                            },
-                            anchor: Anchor::TOP_LEFT,
-                            scale,
-                        });
-                    }
-                }
+                self.cmd_intermission_overlay(kind, *completion_duration, stats, scale, draw_list);
+
+                self.cmd_notify_lines(console, notify_time, scale, draw_list);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISPLAY: Extent2d = Extent2d {
+        width: 640,
+        height: 480,
+    };
+
+    #[test]
+    fn test_view_rect() {
+        assert_eq!(
+            view_rect(100.0, DISPLAY, 2.0),
+            ScreenRect {
+                x: 0,
+                y: 0,
+                width: 640,
+                height: 480,
+            }
+        );
+
+        // centered above the 96-pixel status bar
+        assert_eq!(
+            view_rect(50.0, DISPLAY, 2.0),
+            ScreenRect {
+                x: 160,
+                y: 192,
+                width: 320,
+                height: 192,
+            }
+        );
+
+        // clamped to 30
+        assert_eq!(view_rect(10.0, DISPLAY, 2.0), view_rect(30.0, DISPLAY, 2.0));
+    }
+
+    #[test]
+    fn test_crosshair_rects() {
+        assert!(crosshair_rects(CrosshairStyle::Glyph, 320, 240, 2).is_empty());
+
+        assert_eq!(
+            crosshair_rects(CrosshairStyle::Dot, 320, 240, 2),
+            vec![ScreenRect {
+                x: 318,
+                y: 238,
+                width: 4,
+                height: 4,
+            }]
+        );
+
+        // each arm starts one line width from the center
+        let rects = crosshair_rects(CrosshairStyle::Cross, 320, 240, 2);
+        assert_eq!(rects.len(), 4);
+        assert_eq!(rects[0].x + rects[0].width as i32, 318);
+        assert_eq!(rects[1].x, 322);
+        assert_eq!(rects[2].y + rects[2].height as i32, 238);
+        assert_eq!(rects[3].y, 242);
+        assert!(rects.iter().all(|r| r.width.min(r.height) == 2));
+    }
+
+    #[test]
+    fn test_sbar_lines() {
+        assert_eq!(sbar_lines(30.0), SBAR_LINES);
+        assert_eq!(sbar_lines(100.0), SBAR_LINES);
+        assert_eq!(sbar_lines(110.0), SBAR_HEIGHT);
+        assert_eq!(sbar_lines(120.0), 0);
+    }
+
+    #[test]
+    fn test_mission_pack_texture_names() {
+        use HudTextureId::*;
+
+        let laser = HipnoticWeapon {
+            id: HipWeaponId::LaserCannon,
+            frame: WeaponFrame::Active,
+        };
+        let prox = HipnoticWeapon {
+            id: HipWeaponId::ProximityLauncher,
+            frame: WeaponFrame::Pickup { frame: 0 },
+        };
+        assert_eq!(laser.to_string(), "INV2_LASER");
+        assert_eq!(prox.to_string(), "INVA1_PROX_GL");
+        assert_eq!(RogueInvBar { powered: true }.to_string(), "R_INVBAR1");
+        assert_eq!(RogueItem { id: 1 }.to_string(), "R_AGRAV1");
+    }
+}
@@ -1,18 +1,16 @@
 use std::collections::HashMap;
 
-use crate::{
-    client::{
-        menu::{Item, Menu, MenuBodyView, MenuState, NamedMenuItem},
-        render::{
-            ui::{
-                glyph::{GlyphRendererCommand, GLYPH_HEIGHT, GLYPH_WIDTH},
-                layout::{Anchor, Layout, ScreenPosition, Size},
-                quad::{QuadRendererCommand, QuadTexture},
-            },
-            GraphicsState,
+use crate::client::{
+    menu::{Item, Menu, MenuBodyView, MenuState, NamedMenuItem},
+    render::{
+        ui::{
+            draw_list::{DrawList2D, TextStyle},
+            glyph::{GLYPH_HEIGHT, GLYPH_WIDTH},
+            layout::{Anchor, ScreenPosition},
+            quad::QuadTexture,
         },
+        GraphicsState,
     },
-    common::wad::QPic,
 };
 
 use chrono::Duration;
@@ -27,6 +25,8 @@ const SLIDER_RIGHT: u8 = 130;
 const SLIDER_HANDLE: u8 = 131;
 const SLIDER_WIDTH: i32 = 10;
 
+const TEXT_CURSOR: u8 = 11;
+
 #[derive(Clone, Copy, Debug)]
 enum Align {
     Left,
@@ -81,10 +81,7 @@ impl MenuRenderer {
                 .map(|name| {
                     (
                         name.clone(),
-                        QuadTexture::from_qpic(
-                            state,
-                            &QPic::load(state.vfs().open(&name).unwrap()).unwrap(),
-                        ),
+                        QuadTexture::from_qpic(state, &state.load_qpic(&name)),
                     )
                 })
                 .collect(),
@@ -106,57 +103,54 @@ impl MenuRenderer {
         x_ofs: i32,
         y_ofs: i32,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'state>>,
+        draw_list: &mut DrawList2D<&'state QuadTexture>,
     ) {
-        quad_cmds.push(QuadRendererCommand {
+        draw_list.draw_pic(
             texture,
-            layout: Layout {
-                position: ScreenPosition::Relative {
-                    anchor: Anchor::CENTER,
-                    x_ofs: align.x_ofs() + x_ofs,
-                    y_ofs: MENU_HEIGHT / 2 + y_ofs,
-                },
-                anchor: align.anchor(),
-                size: Size::Scale { factor: scale },
+            ScreenPosition::Relative {
+                anchor: Anchor::CENTER,
+                x_ofs: align.x_ofs() + x_ofs,
+                y_ofs: MENU_HEIGHT / 2 + y_ofs,
             },
-        });
+            align.anchor(),
+            scale,
+        );
     }
 
-    fn cmd_draw_glyph(
+    fn cmd_draw_glyph<P>(
         &self,
         glyph_id: u8,
         x_ofs: i32,
         y_ofs: i32,
         scale: f32,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+        draw_list: &mut DrawList2D<P>,
     ) {
-        glyph_cmds.push(GlyphRendererCommand::Glyph {
+        draw_list.draw_char(
             glyph_id,
-            position: ScreenPosition::Relative {
+            ScreenPosition::Relative {
                 anchor: Anchor::CENTER,
                 x_ofs: -MENU_WIDTH / 2 + x_ofs,
                 y_ofs: -MENU_HEIGHT / 2 + y_ofs,
             },
-            anchor: Anchor::TOP_LEFT,
-            scale,
-        });
+            TextStyle::new(Anchor::TOP_LEFT, scale),
+        );
     }
 
-    fn cmd_draw_plaque<'a>(&'a self, scale: f32, quad_cmds: &mut Vec<QuadRendererCommand<'a>>) {
+    fn cmd_draw_plaque<'a>(&'a self, scale: f32, draw_list: &mut DrawList2D<&'a QuadTexture>) {
         let plaque = self.texture("gfx/qplaque.lmp");
-        self.cmd_draw_quad(plaque, Align::Left, 16, 4, scale, quad_cmds);
+        self.cmd_draw_quad(plaque, Align::Left, 16, 4, scale, draw_list);
     }
 
     fn cmd_draw_title<'a, S>(
         &'a self,
         name: S,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) where
         S: AsRef<str>,
     {
         let title = self.texture(name.as_ref());
-        self.cmd_draw_quad(title, Align::Center, 0, 4, scale, quad_cmds);
+        self.cmd_draw_quad(title, Align::Center, 0, 4, scale, draw_list);
     }
 
     fn cmd_draw_body_predef<'a, S>(
@@ -165,12 +159,12 @@ impl MenuRenderer {
         cursor_pos: usize,
         time: Duration,
         scale: f32,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) where
         S: AsRef<str>,
     {
         let predef = self.texture(name.as_ref());
-        self.cmd_draw_quad(predef, Align::Left, 72, -32, scale, quad_cmds);
+        self.cmd_draw_quad(predef, Align::Left, 72, -32, scale, draw_list);
         let curs_frame = (time.num_milliseconds() / 100) % 6;
         let curs = self.texture(&format!("gfx/menudot{}.lmp", curs_frame + 1));
         self.cmd_draw_quad(
@@ -179,83 +173,81 @@ impl MenuRenderer {
             72 - curs.width() as i32,
             -32 - cursor_pos as i32 * 20,
             scale,
-            quad_cmds,
+            draw_list,
         );
     }
 
-    fn cmd_draw_item_name<S>(
+    fn cmd_draw_item_name<S, P>(
         &self,
         x: i32,
         y: i32,
         name: S,
         scale: f32,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+        draw_list: &mut DrawList2D<P>,
     ) where
         S: AsRef<str>,
     {
-        glyph_cmds.push(GlyphRendererCommand::Text {
-            text: name.as_ref().to_string(),
-            position: ScreenPosition::Relative {
+        draw_list.draw_string(
+            name.as_ref(),
+            ScreenPosition::Relative {
                 anchor: Anchor::CENTER,
                 x_ofs: -MENU_WIDTH / 2 + x - GLYPH_WIDTH as i32,
                 y_ofs: -MENU_HEIGHT / 2 + y,
             },
-            anchor: Anchor::TOP_RIGHT,
-            scale,
-        });
+            TextStyle::new(Anchor::TOP_RIGHT, scale),
+        );
     }
 
-    fn cmd_draw_item_text<S>(
+    fn cmd_draw_item_text<S, P>(
         &self,
         x: i32,
         y: i32,
         text: S,
         scale: f32,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+        draw_list: &mut DrawList2D<P>,
     ) where
         S: AsRef<str>,
     {
-        glyph_cmds.push(GlyphRendererCommand::Text {
-            text: text.as_ref().to_string(),
-            position: ScreenPosition::Relative {
+        draw_list.draw_string(
+            text.as_ref(),
+            ScreenPosition::Relative {
                 anchor: Anchor::CENTER,
                 x_ofs: -MENU_WIDTH / 2 + x + GLYPH_WIDTH as i32,
                 y_ofs: -MENU_HEIGHT / 2 + y,
             },
-            anchor: Anchor::TOP_LEFT,
-            scale,
-        });
+            TextStyle::new(Anchor::TOP_LEFT, scale),
+        );
     }
 
-    fn cmd_draw_slider(
+    fn cmd_draw_slider<P>(
         &self,
         x: i32,
         y: i32,
         pos: f32,
         scale: f32,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+        draw_list: &mut DrawList2D<P>,
     ) {
-        self.cmd_draw_glyph(SLIDER_LEFT, x, y, scale, glyph_cmds);
+        self.cmd_draw_glyph(SLIDER_LEFT, x, y, scale, draw_list);
         for i in 0..SLIDER_WIDTH {
-            self.cmd_draw_glyph(SLIDER_MIDDLE, x + 8 * (i + 1), y, scale, glyph_cmds);
+            self.cmd_draw_glyph(SLIDER_MIDDLE, x + 8 * (i + 1), y, scale, draw_list);
         }
-        self.cmd_draw_glyph(SLIDER_RIGHT, x + 8 * SLIDER_WIDTH, y, scale, glyph_cmds);
+        self.cmd_draw_glyph(SLIDER_RIGHT, x + 8 * SLIDER_WIDTH, y, scale, draw_list);
         let handle_x = x + ((8 * (SLIDER_WIDTH - 1)) as f32 * pos) as i32;
-        self.cmd_draw_glyph(SLIDER_HANDLE, handle_x, y, scale, glyph_cmds);
+        self.cmd_draw_glyph(SLIDER_HANDLE, handle_x, y, scale, draw_list);
     }
 
-    fn cmd_draw_body_dynamic(
+    fn cmd_draw_body_dynamic<P>(
         &self,
         items: &[NamedMenuItem],
         cursor_pos: usize,
         time: Duration,
         scale: f32,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+        draw_list: &mut DrawList2D<P>,
     ) {
         for (item_id, item) in items.iter().enumerate() {
             let y = MENU_HEIGHT - 32 - (GLYPH_HEIGHT * item_id) as i32;
             let x = 16 + 24 * GLYPH_WIDTH as i32;
-            self.cmd_draw_item_name(x, y, item.name(), scale, glyph_cmds);
+            self.cmd_draw_item_name(x, y, item.name(), scale, draw_list);
 
             match item.item() {
                 Item::Toggle(toggle) => self.cmd_draw_item_text(
@@ -263,15 +255,19 @@ impl MenuRenderer {
                     y,
                     if toggle.get() { "yes" } else { "no" },
                     scale,
-                    glyph_cmds,
+                    draw_list,
                 ),
-                Item::Enum(e) => {
-                    self.cmd_draw_item_text(x, y, e.selected_name(), scale, glyph_cmds)
-                }
+                Item::Enum(e) => self.cmd_draw_item_text(x, y, e.selected_name(), scale, draw_list),
                 Item::Slider(slider) => {
-                    self.cmd_draw_slider(x, y, slider.position(), scale, glyph_cmds)
+                    self.cmd_draw_slider(x, y, slider.position(), scale, draw_list)
+                }
+                Item::TextField(text) => {
+                    self.cmd_draw_item_text(x, y, text.text(), scale, draw_list);
+                    if item_id == cursor_pos && time.num_milliseconds() / 250 % 2 == 0 {
+                        let cursor_x = x + (GLYPH_WIDTH * (text.cursor() + 1)) as i32;
+                        self.cmd_draw_glyph(TEXT_CURSOR, cursor_x, y, scale, draw_list);
+                    }
                 }
-                Item::TextField(_) => (),
                 _ => (),
             }
         }
@@ -282,7 +278,7 @@ impl MenuRenderer {
                 200,
                 MENU_HEIGHT - 32 - 8 * cursor_pos as i32,
                 scale,
-                glyph_cmds,
+                draw_list,
             );
         }
     }
@@ -291,8 +287,7 @@ impl MenuRenderer {
         &'a self,
         menu: &Menu,
         time: Duration,
-        quad_cmds: &mut Vec<QuadRendererCommand<'a>>,
-        glyph_cmds: &mut Vec<GlyphRendererCommand>,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
     ) {
         let active_menu = menu.active_submenu().unwrap();
         let view = active_menu.view();
@@ -301,10 +296,10 @@ impl MenuRenderer {
         let scale = 2.0;
 
         if view.draw_plaque() {
-            self.cmd_draw_plaque(scale, quad_cmds);
+            self.cmd_draw_plaque(scale, draw_list);
         }
 
-        self.cmd_draw_title(view.title_path(), scale, quad_cmds);
+        self.cmd_draw_title(view.title_path(), scale, draw_list);
 
         let cursor_pos = match active_menu.state() {
             MenuState::Active { index } => index,
@@ -313,7 +308,7 @@ impl MenuRenderer {
 
         match *view.body() {
             MenuBodyView::Predefined { ref path } => {
-                self.cmd_draw_body_predef(path, cursor_pos, time, scale, quad_cmds);
+                self.cmd_draw_body_predef(path, cursor_pos, time, scale, draw_list);
             }
             MenuBodyView::Dynamic => {
                 self.cmd_draw_body_dynamic(
@@ -321,7 +316,7 @@ impl MenuRenderer {
                     cursor_pos,
                     time,
                     scale,
-                    glyph_cmds,
+                    draw_list,
                 );
             }
         }
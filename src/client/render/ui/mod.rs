@@ -1,26 +1,40 @@
 pub mod console;
+pub mod draw_list;
 pub mod glyph;
 pub mod hud;
 pub mod layout;
 pub mod menu;
 pub mod quad;
+pub mod showlmp;
+pub mod stats;
+pub mod teaminfo;
+pub mod timegraph;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 use crate::{
     client::{
         menu::Menu,
         render::{
+            profile::GpuTimings,
+            stats::{FrameRate, RenderStats},
             ui::{
                 console::ConsoleRenderer,
-                glyph::{GlyphRenderer, GlyphRendererCommand},
+                draw_list::{DrawList2D, TextStyle},
+                glyph::GlyphRenderer,
                 hud::{HudRenderer, HudState},
+                layout::{Anchor, AnchorCoord, ScreenPosition},
                 menu::MenuRenderer,
-                quad::{QuadRenderer, QuadRendererCommand, QuadUniforms},
+                quad::{QuadRenderer, QuadTexture, QuadUniforms},
+                showlmp::ShowLmpRenderer,
             },
             uniform::{self, DynamicUniformBufferBlock},
             Extent2d, GraphicsState,
         },
+        showlmp::ShowLmps,
+        strings::Strings,
+        teaminfo::TeammateStatus,
+        timegraph::TimeGraph,
     },
     common::{console::Console, util::any_slice_as_bytes},
 };
@@ -28,6 +42,27 @@ use crate::{
 use cgmath::{Matrix4, Vector2};
 use chrono::Duration;
 
+/// Height of the top of a centerprint message as a proportion of the display height.
+const CENTER_PRINT_TOP: f32 = 0.65;
+
+/// Maximum width of a line of centerprint text in unscaled pixels.
+const CENTER_PRINT_WIDTH: u32 = 320;
+
+/// Returns the opacity of a centerprint message `elapsed` after it was received, or `None` once it
+/// has disappeared.
+///
+/// The message is fully opaque for `center_time`, then fades out over half a second.
+pub fn center_print_alpha(elapsed: Duration, center_time: Duration) -> Option<f32> {
+    let fade = Duration::milliseconds(500);
+    let remaining = center_time + fade - elapsed;
+    if elapsed < Duration::zero() || remaining <= Duration::zero() {
+        return None;
+    }
+
+    let alpha = remaining.num_milliseconds() as f32 / fade.num_milliseconds() as f32;
+    Some(alpha.min(1.0))
+}
+
 pub fn screen_space_vertex_translate(
     display_w: u32,
     display_h: u32,
@@ -84,6 +119,27 @@ pub enum UiState<'a> {
     },
     InGame {
         hud: HudState<'a>,
+        /// The scale factor of the status bar and other in-game pictures.
+        scale: f32,
+        /// The strings for the text drawn over the game view.
+        strings: &'a Strings,
+        show_lmps: &'a ShowLmps,
+        time_graph: Option<&'a TimeGraph>,
+        /// The name and status of each teammate, shown in cooperative games.
+        teammates: Vec<(&'a str, &'a TeammateStatus)>,
+        /// The frame rate, shown with `scr_showfps`.
+        frame_rate: Option<&'a FrameRate>,
+        /// Counters from the world, entity and viewmodel passes, shown with `r_speeds`. Those passes
+        /// fill them in before the UI is drawn.
+        render_stats: Option<&'a Cell<RenderStats>>,
+        /// The GPU time of each render pass, shown with `r_gpuprofile`.
+        gpu_timings: Option<&'a GpuTimings>,
+        /// A description of the content being downloaded while signing on.
+        download_status: Option<String>,
+        /// The current centerprint message and its opacity.
+        center_print: Option<(&'a str, f32)>,
+        /// How long console output stays at the top of the screen.
+        notify_time: Duration,
         overlay: Option<UiOverlay<'a>>,
     },
 }
@@ -92,6 +148,7 @@ pub struct UiRenderer {
     console_renderer: ConsoleRenderer,
     menu_renderer: MenuRenderer,
     hud_renderer: HudRenderer,
+    show_lmp_renderer: ShowLmpRenderer,
     glyph_renderer: GlyphRenderer,
     quad_renderer: QuadRenderer,
 }
@@ -102,11 +159,17 @@ impl UiRenderer {
             console_renderer: ConsoleRenderer::new(state),
             menu_renderer: MenuRenderer::new(state, menu),
             hud_renderer: HudRenderer::new(state),
+            show_lmp_renderer: ShowLmpRenderer::new(),
             glyph_renderer: GlyphRenderer::new(state),
             quad_renderer: QuadRenderer::new(state),
         }
     }
 
+    /// Loads the textures for any pictures newly displayed with `svc_showlmp`.
+    pub fn update_show_lmps(&mut self, state: &GraphicsState, show_lmps: &ShowLmps) {
+        self.show_lmp_renderer.update(state, show_lmps);
+    }
+
     pub fn render_pass<'pass>(
         &'pass self,
         state: &'pass GraphicsState,
@@ -114,24 +177,122 @@ impl UiRenderer {
         target_size: Extent2d,
         time: Duration,
         ui_state: &UiState<'pass>,
-        quad_commands: &'pass mut Vec<QuadRendererCommand<'pass>>,
-        glyph_commands: &'pass mut Vec<GlyphRendererCommand>,
+        draw_list: &'pass mut DrawList2D<&'pass QuadTexture>,
     ) {
-        let (hud_state, overlay) = match ui_state {
-            UiState::Title { overlay } => (None, Some(overlay)),
-            UiState::InGame { hud, overlay } => (Some(hud), overlay.as_ref()),
+        let (hud_state, show_lmps, overlay) = match ui_state {
+            UiState::Title { overlay } => (None, None, Some(overlay)),
+            UiState::InGame {
+                hud,
+                show_lmps,
+                overlay,
+                ..
+            } => (Some(hud), Some(show_lmps), overlay.as_ref()),
+        };
+
+        let scale = match ui_state {
+            UiState::InGame { scale, .. } => *scale,
+            UiState::Title { .. } => 1.0,
+        };
+
+        if let Some(lmps) = show_lmps {
+            self.show_lmp_renderer
+                .generate_commands(lmps, scale, draw_list);
+        }
+
+        if let Some(hstate) = hud_state {
+            let notify_time = match ui_state {
+                UiState::InGame { notify_time, .. } => *notify_time,
+                UiState::Title { .. } => Duration::zero(),
+            };
+
+            self.hud_renderer.generate_commands(
+                hstate,
+                time,
+                target_size,
+                scale,
+                notify_time,
+                draw_list,
+            );
+        }
+
+        if let UiState::InGame {
+            time_graph: Some(graph),
+            strings,
+            ..
+        } = ui_state
+        {
+            timegraph::generate_commands(graph, strings, scale, draw_list);
+        }
+
+        if let UiState::InGame { teammates, .. } = ui_state {
+            teaminfo::generate_commands(teammates, scale, draw_list);
+        }
+
+        if let UiState::InGame {
+            frame_rate,
+            render_stats,
+            gpu_timings,
+            strings,
+            ..
+        } = ui_state
+        {
+            stats::generate_commands(
+                *frame_rate,
+                render_stats.map(Cell::get),
+                *gpu_timings,
+                strings,
+                scale,
+                draw_list,
+            );
+        }
+
+        if let UiState::InGame {
+            download_status: Some(status),
+            ..
+        } = ui_state
+        {
+            draw_list.draw_string(
+                status.as_str(),
+                ScreenPosition::Absolute(Anchor::CENTER),
+                TextStyle::new(Anchor::CENTER, scale),
+            );
+        }
+
+        if let UiState::InGame {
+            center_print: Some((text, alpha)),
+            ..
+        } = ui_state
+        {
+            draw_list.draw_string(
+                *text,
+                ScreenPosition::Absolute(Anchor {
+                    x: AnchorCoord::Center,
+                    y: AnchorCoord::Proportion(CENTER_PRINT_TOP),
+                }),
+                TextStyle::new(Anchor::TOP_CENTER, scale)
+                    .with_color([0xFF, 0xFF, 0xFF, (alpha * 255.0) as u8])
+                    .with_wrap_width(CENTER_PRINT_WIDTH)
+                    .with_centered_lines(),
+            );
+        }
+
+        let console_open = match overlay {
+            Some(UiOverlay::Console(_)) => true,
+            _ => false,
         };
 
+        // keep drawing the console while it slides closed
         if let Some(hstate) = hud_state {
-            self.hud_renderer
-                .generate_commands(hstate, time, quad_commands, glyph_commands);
+            if !console_open {
+                self.console_renderer
+                    .generate_commands(hstate.console(), time, draw_list, 0.0);
+            }
         }
 
         if let Some(o) = overlay {
             match o {
                 UiOverlay::Menu(menu) => {
-                    self.menu_renderer
-                        .generate_commands(menu, time, quad_commands, glyph_commands);
+                    self.menu_renderer.generate_commands(menu, time, draw_list);
                 }
                 UiOverlay::Console(console) => {
                     // TODO: take in-game console proportion as cvar
@@ -140,20 +301,33 @@ impl UiRenderer {
                         None => 1.0,
                     };
 
-                    self.console_renderer.generate_commands(
-                        console,
-                        time,
-                        quad_commands,
-                        glyph_commands,
-                        proportion,
-                    );
+                    self.console_renderer
+                        .generate_commands(console, time, draw_list, proportion);
                 }
             }
         }
 
         self.quad_renderer
-            .record_draw(state, pass, target_size, quad_commands);
+            .record_draw(state, pass, target_size, &draw_list.quads(target_size));
         self.glyph_renderer
-            .record_draw(state, pass, target_size, glyph_commands);
+            .record_draw(state, pass, target_size, &draw_list.glyphs(target_size));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_center_print_alpha() {
+        let center_time = Duration::seconds(2);
+        let alpha = |ms| center_print_alpha(Duration::milliseconds(ms), center_time);
+
+        assert_eq!(alpha(0), Some(1.0));
+        assert_eq!(alpha(2000), Some(1.0));
+        assert_eq!(alpha(2250), Some(0.5));
+        assert_eq!(alpha(2500), None);
+        // a message from a previous level
+        assert_eq!(alpha(-1), None);
     }
 }
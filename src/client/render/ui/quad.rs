@@ -7,16 +7,16 @@ use std::{
 use crate::{
     client::render::{
         ui::{
-            layout::{Layout, Size},
+            draw_list::{PicHandle, Quad2D, QuadFill, ScreenRect},
             screen_space_vertex_transform,
         },
         uniform::{self, DynamicUniformBuffer, DynamicUniformBufferBlock},
-        Extent2d, GraphicsState, Pipeline, TextureData, DIFFUSE_ATTACHMENT_FORMAT,
+        DiffuseData, Extent2d, GraphicsState, Pipeline, TextureData, DIFFUSE_ATTACHMENT_FORMAT,
     },
     common::{util::any_slice_as_bytes, wad::QPic},
 };
 
-use cgmath::Matrix4;
+use cgmath::{Matrix4, Vector2, Vector4};
 
 pub const VERTICES: [QuadVertex; 6] = [
     QuadVertex {
@@ -177,7 +177,7 @@ const BIND_GROUP_LAYOUT_ENTRIES: &[&[wgpu::BindGroupLayoutEntry]] = &[
         },
     ],
     &[
-        // transform matrix
+        // transform matrix, texcoord scale and color
         // TODO: move to push constants once they're exposed in wgpu
         wgpu::BindGroupLayoutEntry {
             binding: 0,
@@ -244,7 +244,7 @@ impl Pipeline for QuadPipeline {
     fn color_target_states() -> Vec<wgpu::ColorTargetState> {
         vec![wgpu::ColorTargetState {
             format: DIFFUSE_ATTACHMENT_FORMAT,
-            blend: Some(wgpu::BlendState::REPLACE),
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
             write_mask: wgpu::ColorWrite::ALL,
         }]
     }
@@ -267,6 +267,12 @@ impl Pipeline for QuadPipeline {
 #[derive(Clone, Copy, Debug)]
 pub struct QuadUniforms {
     transform: Matrix4<f32>,
+
+    // multiplied with the texture color
+    color: Vector4<f32>,
+
+    // scales the texture coordinates so tiled fills repeat the texture
+    texcoord_scale: Vector2<f32>,
 }
 
 pub struct QuadTexture {
@@ -282,12 +288,17 @@ pub struct QuadTexture {
 impl QuadTexture {
     pub fn from_qpic(state: &GraphicsState, qpic: &QPic) -> QuadTexture {
         let (diffuse_data, _) = state.palette().translate(qpic.indices());
-        let texture = state.create_texture(
-            None,
-            qpic.width(),
-            qpic.height(),
-            &TextureData::Diffuse(diffuse_data),
-        );
+        QuadTexture::from_diffuse(state, qpic.width(), qpic.height(), diffuse_data)
+    }
+
+    fn from_diffuse(
+        state: &GraphicsState,
+        width: u32,
+        height: u32,
+        diffuse_data: DiffuseData,
+    ) -> QuadTexture {
+        let texture =
+            state.create_texture(None, width, height, &TextureData::Diffuse(diffuse_data));
         let texture_view = texture.create_view(&Default::default());
         let bind_group = state
             .device()
@@ -304,8 +315,8 @@ impl QuadTexture {
             texture,
             texture_view,
             bind_group,
-            width: qpic.width(),
-            height: qpic.height(),
+            width,
+            height,
         }
     }
 
@@ -326,18 +337,22 @@ impl QuadTexture {
     }
 }
 
-/// A command which specifies how a quad should be rendered.
-pub struct QuadRendererCommand<'a> {
-    /// The texture to be mapped to the quad.
-    pub texture: &'a QuadTexture,
+impl PicHandle for QuadTexture {
+    fn width(&self) -> u32 {
+        self.width
+    }
 
-    /// The layout specifying the size and position of the quad on the screen.
-    pub layout: Layout,
+    fn height(&self) -> u32 {
+        self.height
+    }
 }
 
 pub struct QuadRenderer {
     sampler_bind_group: wgpu::BindGroup,
     transform_bind_group: wgpu::BindGroup,
+
+    // tinted to draw solid rectangles
+    white: QuadTexture,
 }
 
 impl QuadRenderer {
@@ -367,71 +382,70 @@ impl QuadRenderer {
                 }],
             });
 
+        let white = QuadTexture::from_diffuse(
+            state,
+            1,
+            1,
+            DiffuseData {
+                rgba: (&[0xFF; 4][..]).into(),
+            },
+        );
+
         QuadRenderer {
             sampler_bind_group,
             transform_bind_group,
+            white,
         }
     }
 
-    fn generate_uniforms<'cmds>(
+    fn generate_uniforms<P>(
         &self,
-        commands: &[QuadRendererCommand<'cmds>],
+        quads: &[Quad2D<P>],
         target_size: Extent2d,
     ) -> Vec<QuadUniforms> {
         let mut uniforms = Vec::new();
 
-        for cmd in commands {
-            let QuadRendererCommand {
-                texture,
-                layout:
-                    Layout {
-                        position,
-                        anchor,
-                        size,
-                    },
-            } = *cmd;
-
-            let scale = match size {
-                Size::Scale { factor } => factor,
-                _ => 1.0,
+        for quad in quads {
+            let ScreenRect {
+                x,
+                y,
+                width,
+                height,
+            } = quad.rect;
+
+            let color = match quad.fill {
+                QuadFill::Pic(_) => Vector4::new(1.0, 1.0, 1.0, 1.0),
+                QuadFill::Color([r, g, b, a]) => {
+                    Vector4::new(r as f32, g as f32, b as f32, a as f32) / 255.0
+                }
             };
 
-            let Extent2d {
-                width: display_width,
-                height: display_height,
-            } = target_size;
-
-            let (screen_x, screen_y) = position.to_xy(display_width, display_height, scale);
-            let (quad_x, quad_y) = anchor.to_xy(texture.width, texture.height);
-            let x = screen_x - (quad_x as f32 * scale) as i32;
-            let y = screen_y - (quad_y as f32 * scale) as i32;
-            let (quad_width, quad_height) =
-                size.to_wh(texture.width, texture.height, display_width, display_height);
-
             uniforms.push(QuadUniforms {
                 transform: screen_space_vertex_transform(
-                    display_width,
-                    display_height,
-                    quad_width,
-                    quad_height,
+                    target_size.width,
+                    target_size.height,
+                    width,
+                    height,
                     x,
                     y,
                 ),
+                color,
+                texcoord_scale: quad.texcoord_scale.into(),
             });
         }
 
         uniforms
     }
 
-    pub fn record_draw<'pass, 'cmds>(
+    pub fn record_draw<'pass>(
         &'pass self,
         state: &'pass GraphicsState,
         pass: &mut wgpu::RenderPass<'pass>,
         target_size: Extent2d,
-        commands: &'pass [QuadRendererCommand<'pass>],
+        quads: &[Quad2D<&'pass QuadTexture>],
     ) {
         // update uniform buffer
-        let uniforms = self.generate_uniforms(commands, target_size);
+        let uniforms = self.generate_uniforms(quads, target_size);
         uniform::clear_and_rewrite(
             state.queue(),
             &mut state.quad_pipeline().uniform_buffer_mut(),
@@ -442,11 +456,16 @@ impl QuadRenderer {
         pass.set_pipeline(state.quad_pipeline().pipeline());
         pass.set_vertex_buffer(0, state.quad_pipeline().vertex_buffer().slice(..));
         pass.set_bind_group(0, &self.sampler_bind_group, &[]);
-        for (cmd, block) in commands
+        for (quad, block) in quads
             .iter()
             .zip(state.quad_pipeline().uniform_buffer_blocks().iter())
         {
-            pass.set_bind_group(1, &cmd.texture.bind_group, &[]);
+            let texture = match quad.fill {
+                QuadFill::Pic(pic) => pic,
+                QuadFill::Color(_) => &self.white,
+            };
+
+            pass.set_bind_group(1, &texture.bind_group, &[]);
             pass.set_bind_group(2, &self.transform_bind_group, &[block.offset()]);
             pass.draw(0..6, 0..1);
         }
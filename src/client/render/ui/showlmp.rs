@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use crate::client::{
+    render::{
+        ui::{
+            draw_list::DrawList2D,
+            layout::{Anchor, ScreenPosition},
+            quad::QuadTexture,
+        },
+        GraphicsState,
+    },
+    showlmp::ShowLmps,
+};
+
+// pictures are positioned on a virtual 320x200 screen centered on the display
+const SCREEN_WIDTH: i32 = 320;
+const SCREEN_HEIGHT: i32 = 200;
+
+#[derive(Default)]
+pub struct ShowLmpRenderer {
+    textures: HashMap<String, QuadTexture>,
+}
+
+impl ShowLmpRenderer {
+    pub fn new() -> ShowLmpRenderer {
+        ShowLmpRenderer {
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Loads the textures for any newly displayed pictures.
+    pub fn update(&mut self, state: &GraphicsState, lmps: &ShowLmps) {
+        for lmp in lmps.iter() {
+            if !self.textures.contains_key(&lmp.name) {
+                debug!("Opening {}", lmp.name);
+                let qpic = state.load_qpic(&lmp.name);
+                self.textures
+                    .insert(lmp.name.clone(), QuadTexture::from_qpic(state, &qpic));
+            }
+        }
+    }
+
+    pub fn generate_commands<'a>(
+        &'a self,
+        lmps: &ShowLmps,
+        scale: f32,
+        draw_list: &mut DrawList2D<&'a QuadTexture>,
+    ) {
+        for lmp in lmps.iter() {
+            // textures are loaded in update(), skip any that showed up since
+            let texture = match self.textures.get(&lmp.name) {
+                Some(t) => t,
+                None => continue,
+            };
+
+            draw_list.draw_pic(
+                texture,
+                ScreenPosition::Relative {
+                    anchor: Anchor::CENTER,
+                    x_ofs: lmp.x - SCREEN_WIDTH / 2,
+                    y_ofs: SCREEN_HEIGHT / 2 - lmp.y,
+                },
+                Anchor::TOP_LEFT,
+                scale,
+            );
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::client::{
+    render::{
+        profile::GpuTimings,
+        stats::{FrameRate, RenderStats},
+        ui::{
+            draw_list::{DrawList2D, TextStyle},
+            layout::{Anchor, ScreenPosition},
+        },
+    },
+    strings::Strings,
+};
+
+const BYTES_PER_KIB: u64 = 1024;
+
+/// Draws the frame rate and rendering statistics in the bottom-right corner of the screen.
+///
+/// The frame rate is on the bottom line, with the statistics from `r_speeds` stacked above it and
+/// the GPU pass timings from `r_gpuprofile` above those.
+pub fn generate_commands<P>(
+    frame_rate: Option<&FrameRate>,
+    render_stats: Option<RenderStats>,
+    gpu_timings: Option<&GpuTimings>,
+    strings: &Strings,
+    scale: f32,
+    draw_list: &mut DrawList2D<P>,
+) {
+    let mut lines = Vec::new();
+
+    if let Some(timings) = gpu_timings {
+        for pass in timings.passes() {
+            lines.push(strings.format(
+                "hud.gpu_pass",
+                &[&pass.name, &format!("{:.2}", pass.average_ms)],
+            ));
+        }
+        lines.push(strings.format(
+            "hud.gpu_total",
+            &[&format!("{:.2}", timings.total_average_ms())],
+        ));
+    }
+
+    if let Some(stats) = render_stats {
+        lines.push(strings.format("hud.draws", &[&stats.draw_calls, &stats.triangles]));
+        lines.push(strings.format(
+            "hud.leaves",
+            &[
+                &stats.visible_leaves,
+                &stats.visible_surfaces,
+                &stats.entities,
+            ],
+        ));
+        lines.push(strings.format(
+            "hud.uniforms",
+            &[
+                &(stats.uniform_bytes / BYTES_PER_KIB),
+                &(stats.uniform_capacity / BYTES_PER_KIB),
+            ],
+        ));
+    }
+
+    if let Some(rate) = frame_rate {
+        let frame_ms = rate.frame_time().num_microseconds().unwrap_or(0) as f32 / 1000.0;
+        lines.push(strings.format(
+            "hud.fps",
+            &[&format!("{:.0}", rate.fps()), &format!("{:.1}", frame_ms)],
+        ));
+    }
+
+    let style = TextStyle::new(Anchor::BOTTOM_RIGHT, scale);
+    for (row, line) in lines.into_iter().rev().enumerate() {
+        draw_list.draw_string(
+            line,
+            ScreenPosition::Relative {
+                anchor: Anchor::BOTTOM_RIGHT,
+                x_ofs: 0,
+                y_ofs: 8 * row as i32,
+            },
+            style,
+        );
+    }
+}
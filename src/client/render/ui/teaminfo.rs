@@ -0,0 +1,37 @@
+use crate::client::{
+    render::ui::{
+        draw_list::{DrawList2D, TextStyle},
+        layout::{Anchor, ScreenPosition},
+    },
+    teaminfo::TeammateStatus,
+};
+
+// health at or below which a teammate's row is drawn in red
+const LOW_HEALTH: i32 = 25;
+
+const LOW_HEALTH_COLOR: [u8; 4] = [0xFF, 0x40, 0x40, 0xFF];
+
+/// Draws a row with the name, health and armor of each teammate in the top-right corner of the
+/// screen.
+pub fn generate_commands<P>(
+    teammates: &[(&str, &TeammateStatus)],
+    scale: f32,
+    draw_list: &mut DrawList2D<P>,
+) {
+    for (row, (name, status)) in teammates.iter().enumerate() {
+        let mut style = TextStyle::new(Anchor::TOP_RIGHT, scale);
+        if status.health <= LOW_HEALTH {
+            style = style.with_color(LOW_HEALTH_COLOR);
+        }
+
+        draw_list.draw_string(
+            format!("{:<15.15} {:>3} {:>3}", name, status.health, status.armor),
+            ScreenPosition::Relative {
+                anchor: Anchor::TOP_RIGHT,
+                x_ofs: 0,
+                y_ofs: -8 * row as i32,
+            },
+            style,
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use crate::client::{
+    render::ui::{
+        draw_list::{DrawList2D, TextStyle},
+        layout::{Anchor, ScreenPosition},
+    },
+    strings::Strings,
+    timegraph::TimeGraph,
+};
+
+// horizontal distance between samples
+const SAMPLE_SPACING: i32 = 2;
+
+// vertical distance per millisecond of drift
+const PIXELS_PER_MS: f32 = 1.0;
+
+// drift beyond this many milliseconds is drawn at the edge of the graph
+const GRAPH_RANGE_MS: f32 = 50.0;
+
+// distance from the top of the screen to the graph's zero line
+const GRAPH_BASELINE: i32 = 16 + GRAPH_RANGE_MS as i32;
+
+/// Draws the client clock drift in the top-left corner of the screen.
+///
+/// The summary line shows the most recent drift, correction, interpolation delay and frame time in
+/// milliseconds. Below it, each frame's drift is plotted as a dot, with points above the line ahead
+/// of the server.
+pub fn generate_commands<P>(
+    time_graph: &TimeGraph,
+    strings: &Strings,
+    scale: f32,
+    draw_list: &mut DrawList2D<P>,
+) {
+    let latest = match time_graph.latest() {
+        Some(l) => l,
+        None => return,
+    };
+
+    let style = TextStyle::new(Anchor::TOP_LEFT, scale);
+    draw_list.draw_string(
+        strings.format(
+            "hud.timegraph",
+            &[
+                &format!("{:+}", latest.drift.num_milliseconds()),
+                &format!("{:+}", latest.nudge.num_milliseconds()),
+                &latest.interp.num_milliseconds(),
+                &latest.frame_time.num_milliseconds(),
+            ],
+        ),
+        ScreenPosition::Relative {
+            anchor: Anchor::TOP_LEFT,
+            x_ofs: 0,
+            y_ofs: 0,
+        },
+        style,
+    );
+
+    for (i, sample) in time_graph.iter().enumerate() {
+        let drift_ms = sample.drift.num_milliseconds() as f32;
+        let y = drift_ms.max(-GRAPH_RANGE_MS).min(GRAPH_RANGE_MS) * PIXELS_PER_MS;
+
+        draw_list.draw_char(
+            b'.',
+            ScreenPosition::Relative {
+                anchor: Anchor::TOP_LEFT,
+                x_ofs: SAMPLE_SPACING * i as i32,
+                y_ofs: y as i32 - GRAPH_BASELINE,
+            },
+            TextStyle {
+                anchor: Anchor::CENTER,
+                ..style
+            },
+        );
+    }
+}
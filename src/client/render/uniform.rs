@@ -161,6 +161,16 @@ where
     pub fn buffer(&self) -> &wgpu::Buffer {
         &self.inner
     }
+
+    /// Returns the number of bytes currently allocated.
+    pub fn allocated(&self) -> u64 {
+        self.allocated.get()
+    }
+
+    /// Returns the size of the buffer in bytes.
+    pub fn capacity(&self) -> u64 {
+        DYNAMIC_UNIFORM_BUFFER_SIZE
+    }
 }
 
 /// An address into a dynamic uniform buffer.
@@ -1,9 +1,128 @@
+//! Turbulent liquid surfaces.
+//!
+//! Liquid faces (those with textures whose names begin with `*`) are subdivided into a grid when
+//! the brush model is loaded, and drawn with their own pipeline that perturbs the texture
+//! coordinates over time. The distortion can be disabled with `r_waterwarp 0`.
+//!
+//! Liquids are drawn after all opaque geometry and blended over it using the render pass's blend
+//! constant, which holds the liquid opacity (`r_wateralpha`).
+
 use std::cmp::Ordering;
 
-use crate::common::math;
+use crate::{
+    client::render::{
+        world::{brush, WorldPipelineBase},
+        Pipeline,
+    },
+    common::math,
+};
 
 use cgmath::{InnerSpace, Vector2, Vector3};
 
+pub struct WarpPipeline {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl WarpPipeline {
+    /// Creates the warp pipeline.
+    ///
+    /// Liquid faces are drawn from the brush renderer's vertex buffer and per-texture bind groups,
+    /// so the pipeline is laid out with the world bind group layouts followed by those of the
+    /// brush pipeline.
+    pub fn new(
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        world_bind_group_layouts: &[wgpu::BindGroupLayout],
+        brush_bind_group_layouts: &[wgpu::BindGroupLayout],
+        sample_count: u32,
+    ) -> WarpPipeline {
+        let layout_refs: Vec<_> = world_bind_group_layouts
+            .iter()
+            .chain(brush_bind_group_layouts.iter())
+            .collect();
+        let pipeline = WarpPipeline::recreate(device, compiler, &layout_refs, sample_count);
+
+        WarpPipeline { pipeline }
+    }
+
+    pub fn rebuild(
+        &mut self,
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        world_bind_group_layouts: &[wgpu::BindGroupLayout],
+        brush_bind_group_layouts: &[wgpu::BindGroupLayout],
+        sample_count: u32,
+    ) {
+        *self = WarpPipeline::new(
+            device,
+            compiler,
+            world_bind_group_layouts,
+            brush_bind_group_layouts,
+            sample_count,
+        );
+    }
+
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+}
+
+impl Pipeline for WarpPipeline {
+    type VertexPushConstants = brush::VertexPushConstants;
+    type SharedPushConstants = brush::SharedPushConstants;
+    type FragmentPushConstants = ();
+
+    fn name() -> &'static str {
+        "warp"
+    }
+
+    fn vertex_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/warp.vert"))
+    }
+
+    fn fragment_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/warp.frag"))
+    }
+
+    // the bind group layouts are shared with the brush pipeline
+    fn bind_group_layout_descriptors() -> Vec<wgpu::BindGroupLayoutDescriptor<'static>> {
+        Vec::new()
+    }
+
+    fn primitive_state() -> wgpu::PrimitiveState {
+        WorldPipelineBase::primitive_state()
+    }
+
+    // every attachment is blended by the opacity in the blend constant, since the alpha channel
+    // of the light attachment isn't an opacity
+    fn color_target_states() -> Vec<wgpu::ColorTargetState> {
+        let blend = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Constant,
+            dst_factor: wgpu::BlendFactor::OneMinusConstant,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        WorldPipelineBase::color_target_states()
+            .into_iter()
+            .map(|target| wgpu::ColorTargetState {
+                blend: Some(wgpu::BlendState {
+                    color: blend,
+                    alpha: blend,
+                }),
+                ..target
+            })
+            .collect()
+    }
+
+    fn depth_stencil_state() -> Option<wgpu::DepthStencilState> {
+        WorldPipelineBase::depth_stencil_state()
+    }
+
+    fn vertex_buffer_layouts() -> Vec<wgpu::VertexBufferLayout<'static>> {
+        brush::BrushPipeline::vertex_buffer_layouts()
+    }
+}
+
 // TODO: make this a cvar
 const SUBDIVIDE_SIZE: f32 = 32.0;
 
@@ -1,22 +1,27 @@
-use std::{mem::size_of, ops::Range};
+use std::{borrow::Cow, cell::Cell, io::Read, mem::size_of, ops::Range};
 
 use crate::{
     client::render::{
-        world::{BindGroupLayoutId, WorldPipelineBase},
-        GraphicsState, Pipeline, TextureData,
+        palette,
+        stats::RenderStats,
+        world::{BindGroupLayoutId, Translucent, WorldPipelineBase},
+        DiffuseData, GraphicsState, Pipeline, TextureData, MAX_TEXTURE_DIMENSION,
     },
     common::{
         mdl::{self, AliasModel},
+        net::PlayerColor,
+        tga::Tga,
         util::any_slice_as_bytes,
     },
 };
 
-use cgmath::{InnerSpace as _, Matrix4, Vector3, Zero as _};
+use cgmath::{Deg, InnerSpace as _, Matrix4, Rad, Vector3, Vector4, Zero as _};
 use chrono::Duration;
 use failure::Error;
 
 pub struct AliasPipeline {
     pipeline: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
     bind_group_layouts: Vec<wgpu::BindGroupLayout>,
 }
 
@@ -29,9 +34,16 @@ impl AliasPipeline {
     ) -> AliasPipeline {
         let (pipeline, bind_group_layouts) =
             AliasPipeline::create(device, compiler, world_bind_group_layouts, sample_count);
+        let layout_refs: Vec<_> = world_bind_group_layouts
+            .iter()
+            .chain(bind_group_layouts.iter())
+            .collect();
+        let translucent_pipeline =
+            Translucent::<AliasPipeline>::recreate(device, compiler, &layout_refs, sample_count);
 
         AliasPipeline {
             pipeline,
+            translucent_pipeline,
             bind_group_layouts,
         }
     }
@@ -48,12 +60,19 @@ impl AliasPipeline {
             .chain(self.bind_group_layouts.iter())
             .collect();
         self.pipeline = AliasPipeline::recreate(device, compiler, &layout_refs, sample_count);
+        self.translucent_pipeline =
+            Translucent::<AliasPipeline>::recreate(device, compiler, &layout_refs, sample_count);
     }
 
     pub fn pipeline(&self) -> &wgpu::RenderPipeline {
         &self.pipeline
     }
 
+    /// Returns the pipeline for entities drawn with an alpha below 1.
+    pub fn translucent_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.translucent_pipeline
+    }
+
     pub fn bind_group_layouts(&self) -> &[wgpu::BindGroupLayout] {
         &self.bind_group_layouts
     }
@@ -66,6 +85,16 @@ pub struct VertexPushConstants {
     pub model_view: Matrix4<f32>,
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct FragmentPushConstants {
+    /// The direction models are shaded from, in model space. The last component is unused.
+    pub shade_vector: [f32; 4],
+    /// The light level at the model, where 1 is a lightmap texel at full brightness. The last
+    /// component is unused.
+    pub shade_light: [f32; 4],
+}
+
 lazy_static! {
     static ref VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 3] =
         wgpu::vertex_attr_array![
@@ -83,7 +112,7 @@ lazy_static! {
 impl Pipeline for AliasPipeline {
     type VertexPushConstants = VertexPushConstants;
     type SharedPushConstants = ();
-    type FragmentPushConstants = ();
+    type FragmentPushConstants = FragmentPushConstants;
 
     fn name() -> &'static str {
         "alias"
@@ -230,14 +259,152 @@ impl Texture {
     }
 }
 
+/// The formats external skins are searched for in, in order of preference.
+const SKIN_EXTENSIONS: [&str; 2] = ["png", "tga"];
+
+/// Returns the path of an external skin image for the given model and skin index.
+///
+/// Skins are named after the model, so skin 1 of `progs/player.mdl` is `progs/player_1.png`.
+fn external_skin_path(model_name: &str, skin_id: usize, extension: &str) -> String {
+    let stem = model_name.strip_suffix(".mdl").unwrap_or(model_name);
+    format!("{}_{}.{}", stem, skin_id, extension)
+}
+
+/// Decodes a PNG image to 8-bit RGBA.
+fn load_png<R>(reader: R) -> Result<(u32, u32, Vec<u8>), png::DecodingError>
+where
+    R: Read,
+{
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data)?;
+
+    let rgba = match info.color_type {
+        png::ColorType::RGBA => data,
+        png::ColorType::RGB => data
+            .chunks_exact(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 0xFF])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+            .collect(),
+        // indexed images are expanded to RGB(A) by the decoder
+        png::ColorType::Grayscale | png::ColorType::Indexed => {
+            data.iter().flat_map(|&p| vec![p, p, p, 0xFF]).collect()
+        }
+    };
+
+    Ok((info.width, info.height, rgba))
+}
+
+/// Loads an external skin image, if there is one.
+///
+/// Images that exist but can't be decoded are logged and skipped.
+fn load_external_skin(
+    state: &GraphicsState,
+    model_name: &str,
+    skin_id: usize,
+) -> Option<wgpu::Texture> {
+    for extension in SKIN_EXTENSIONS.iter() {
+        let path = external_skin_path(model_name, skin_id, extension);
+        let file = match state.vfs().open(&path) {
+            Ok(f) => f,
+            Err(_) => continue,
+        };
+
+        let image = match *extension {
+            "png" => load_png(file).map_err(|e| e.to_string()),
+            _ => Tga::load(file, MAX_TEXTURE_DIMENSION)
+                .map(|tga| (tga.width(), tga.height(), tga.rgba().to_vec()))
+                .map_err(|e| e.to_string()),
+        };
+
+        match image {
+            Ok((width, height, rgba)) => {
+                debug!("Loaded external skin {}", path);
+                return Some(state.create_texture(
+                    Some(&path),
+                    width,
+                    height,
+                    &TextureData::Diffuse(DiffuseData {
+                        rgba: Cow::Owned(rgba),
+                    }),
+                ));
+            }
+
+            Err(e) => warn!("Couldn't load external skin {}: {}", path, e),
+        }
+    }
+
+    None
+}
+
+/// Models which are drawn at full brightness regardless of the light around them.
+const FULLBRIGHT_MODELS: [&str; 2] = ["progs/flame.mdl", "progs/flame2.mdl"];
+
+/// Models which don't cast a shadow with `r_shadows`, since they give off light themselves. This
+/// is FitzQuake's default `r_noshadow_list`.
+const NO_SHADOW_MODELS: [&str; 6] = [
+    "progs/flame.mdl",
+    "progs/flame2.mdl",
+    "progs/bolt1.mdl",
+    "progs/bolt2.mdl",
+    "progs/bolt3.mdl",
+    "progs/laser.mdl",
+];
+
+// the lowest light levels players and the viewmodel are drawn at, out of 255, so they never go
+// completely dark
+const MIN_PLAYER_LIGHT: f32 = 8.0;
+const MIN_VIEWMODEL_LIGHT: f32 = 24.0;
+
+/// The most player-colored skins an alias model keeps at once.
+///
+/// This covers a full 16-player server with some room for players changing colors.
+const MAX_TRANSLATED_SKINS: usize = 32;
+
+/// A skin recolored with a player's shirt and pants colors.
+struct TranslatedSkin {
+    skin_id: usize,
+    colors: PlayerColor,
+    _diffuse_texture: wgpu::Texture,
+    _diffuse_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    last_used: u64,
+}
+
 pub struct AliasRenderer {
+    name: String,
     keyframes: Vec<Keyframe>,
     textures: Vec<Texture>,
     vertex_buffer: wgpu::Buffer,
+
+    // palette indices of each skin that can be recolored, i.e. the static embedded ones
+    skin_indices: Vec<Option<Vec<u8>>>,
+    skin_width: u32,
+    skin_height: u32,
+
+    // recolored skins, evicted least recently used first
+    translated_skins: Vec<TranslatedSkin>,
+    use_counter: u64,
+
+    // set once an out-of-range skin has been logged, so it isn't logged every frame
+    bad_skin_logged: Cell<bool>,
 }
 
 impl AliasRenderer {
-    pub fn new(state: &GraphicsState, alias_model: &AliasModel) -> Result<AliasRenderer, Error> {
+    /// Creates a renderer for the alias model with the given name.
+    ///
+    /// Embedded skins are replaced by external skin images named after the model, and further
+    /// images past the last embedded skin add new skins.
+    pub fn new(
+        state: &GraphicsState,
+        name: &str,
+        alias_model: &AliasModel,
+    ) -> Result<AliasRenderer, Error> {
         let mut vertices = Vec::new();
         let mut keyframes = Vec::new();
 
@@ -337,26 +504,50 @@ impl AliasRenderer {
                 usage: wgpu::BufferUsage::VERTEX,
             });
 
+        let create_bind_group = |diffuse_view: &wgpu::TextureView| {
+            state
+                .device()
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    // TODO: per-pipeline bind group layout ids
+                    layout: &state.alias_pipeline().bind_group_layouts()
+                        [BindGroupLayoutId::PerTexture as usize - 2],
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(diffuse_view),
+                    }],
+                })
+        };
+
+        let external_skin = |skin_id| {
+            load_external_skin(state, name, skin_id).map(|diffuse_texture| {
+                let diffuse_view = diffuse_texture.create_view(&Default::default());
+                let bind_group = create_bind_group(&diffuse_view);
+                Texture::Static {
+                    diffuse_texture,
+                    diffuse_view,
+                    bind_group,
+                }
+            })
+        };
+
         let mut textures = Vec::new();
-        for texture in alias_model.textures() {
+        let mut skin_indices = Vec::new();
+        for (skin_id, texture) in alias_model.textures().iter().enumerate() {
+            if let Some(skin) = external_skin(skin_id) {
+                textures.push(skin);
+                skin_indices.push(None);
+                continue;
+            }
+
             match *texture {
                 mdl::Texture::Static(ref tex) => {
+                    skin_indices.push(Some(tex.indices().to_vec()));
                     let (diffuse_data, _fullbright_data) = state.palette.translate(tex.indices());
                     let diffuse_texture =
                         state.create_texture(None, w, h, &TextureData::Diffuse(diffuse_data));
                     let diffuse_view = diffuse_texture.create_view(&Default::default());
-                    let bind_group = state
-                        .device()
-                        .create_bind_group(&wgpu::BindGroupDescriptor {
-                            label: None,
-                            // TODO: per-pipeline bind group layout ids
-                            layout: &state.alias_pipeline().bind_group_layouts()
-                                [BindGroupLayoutId::PerTexture as usize - 2],
-                            entries: &[wgpu::BindGroupEntry {
-                                binding: 0,
-                                resource: wgpu::BindingResource::TextureView(&diffuse_view),
-                            }],
-                        });
+                    let bind_group = create_bind_group(&diffuse_view);
                     textures.push(Texture::Static {
                         diffuse_texture,
                         diffuse_view,
@@ -364,6 +555,7 @@ impl AliasRenderer {
                     });
                 }
                 mdl::Texture::Animated(ref tex) => {
+                    skin_indices.push(None);
                     let mut total_duration = Duration::zero();
                     let mut durations = Vec::new();
                     let mut diffuse_textures = Vec::new();
@@ -379,18 +571,7 @@ impl AliasRenderer {
                         let diffuse_texture =
                             state.create_texture(None, w, h, &TextureData::Diffuse(diffuse_data));
                         let diffuse_view = diffuse_texture.create_view(&Default::default());
-                        let bind_group =
-                            state
-                                .device()
-                                .create_bind_group(&wgpu::BindGroupDescriptor {
-                                    label: None,
-                                    layout: &state.alias_pipeline().bind_group_layouts()
-                                        [BindGroupLayoutId::PerTexture as usize - 2],
-                                    entries: &[wgpu::BindGroupEntry {
-                                        binding: 0,
-                                        resource: wgpu::BindingResource::TextureView(&diffuse_view),
-                                    }],
-                                });
+                        let bind_group = create_bind_group(&diffuse_view);
 
                         diffuse_textures.push(diffuse_texture);
                         diffuse_views.push(diffuse_view);
@@ -408,13 +589,132 @@ impl AliasRenderer {
             }
         }
 
+        // external skins may add to the embedded ones
+        while let Some(skin) = external_skin(textures.len()) {
+            textures.push(skin);
+            skin_indices.push(None);
+        }
+
         Ok(AliasRenderer {
+            name: name.to_owned(),
             keyframes,
             textures,
             vertex_buffer,
+            skin_indices,
+            skin_width: w,
+            skin_height: h,
+            translated_skins: Vec::new(),
+            use_counter: 0,
+            bad_skin_logged: Cell::new(false),
         })
     }
 
+    /// Makes sure a copy of the given skin recolored with `colors` is ready to draw.
+    ///
+    /// Recolored skins are kept until the cache is full, so a skin is only recolored again if
+    /// it hasn't been drawn in those colors recently. Skins which can't be recolored are skipped.
+    pub fn prepare_translated_skin(
+        &mut self,
+        state: &GraphicsState,
+        skin_id: usize,
+        colors: PlayerColor,
+    ) {
+        self.use_counter += 1;
+
+        if let Some(skin) = self
+            .translated_skins
+            .iter_mut()
+            .find(|s| s.skin_id == skin_id && s.colors == colors)
+        {
+            skin.last_used = self.use_counter;
+            return;
+        }
+
+        let indices = match self.skin_indices.get(skin_id) {
+            Some(Some(indices)) => indices,
+            _ => return,
+        };
+
+        if self.translated_skins.len() >= MAX_TRANSLATED_SKINS {
+            if let Some(lru) = least_recently_used(&self.translated_skins) {
+                self.translated_skins.swap_remove(lru);
+            }
+        }
+
+        let table = palette::player_translation(colors);
+        let translated: Vec<u8> = indices.iter().map(|&i| table[i as usize]).collect();
+        let (diffuse_data, _fullbright_data) = state.palette.translate(&translated);
+        let diffuse_texture = state.create_texture(
+            None,
+            self.skin_width,
+            self.skin_height,
+            &TextureData::Diffuse(diffuse_data),
+        );
+        let diffuse_view = diffuse_texture.create_view(&Default::default());
+        let bind_group = state
+            .device()
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &state.alias_pipeline().bind_group_layouts()
+                    [BindGroupLayoutId::PerTexture as usize - 2],
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_view),
+                }],
+            });
+
+        self.translated_skins.push(TranslatedSkin {
+            skin_id,
+            colors,
+            _diffuse_texture: diffuse_texture,
+            _diffuse_view: diffuse_view,
+            bind_group,
+            last_used: self.use_counter,
+        });
+    }
+
+    /// Returns the skin with the given index, or the first skin if there is no such skin.
+    fn skin(&self, texture_id: usize) -> &Texture {
+        match self.textures.get(texture_id) {
+            Some(t) => t,
+            None => {
+                if !self.bad_skin_logged.replace(true) {
+                    warn!(
+                        "{} has {} skins, drawing skin {} with skin 0",
+                        self.name,
+                        self.textures.len(),
+                        texture_id
+                    );
+                }
+
+                &self.textures[0]
+            }
+        }
+    }
+
+    /// Returns the push constants which shade this model.
+    ///
+    /// `light` is the light level at the model's origin, as returned by `BspData::light_point`,
+    /// and `yaw` is the model's yaw. The shade vector turns against the yaw so that models are lit
+    /// from the same direction in the world however they face.
+    pub fn shading(
+        &self,
+        light: Vector3<f32>,
+        yaw: Deg<f32>,
+        viewmodel: bool,
+    ) -> FragmentPushConstants {
+        let min_light = min_light(&self.name, viewmodel);
+        let light = light.map(|l| l.max(min_light) / 255.0);
+
+        FragmentPushConstants {
+            shade_vector: shade_vector(yaw).extend(0.0).into(),
+            shade_light: light.extend(0.0).into(),
+        }
+    }
+
+    /// Records the draw commands for a keyframe of this model.
+    ///
+    /// If `translucent` is set, the model is blended by the render pass's blend constant.
     pub fn record_draw<'a>(
         &'a self,
         state: &'a GraphicsState,
@@ -422,15 +722,148 @@ impl AliasRenderer {
         time: Duration,
         keyframe_id: usize,
         texture_id: usize,
+        colors: Option<PlayerColor>,
+        translucent: bool,
+        stats: &mut RenderStats,
+    ) {
+        if translucent {
+            pass.set_pipeline(state.alias_pipeline().translucent_pipeline());
+        } else {
+            pass.set_pipeline(state.alias_pipeline().pipeline());
+        }
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        // recolored skins are prepared before drawing, see `prepare_translated_skin`
+        let translated = colors.and_then(|colors| {
+            self.translated_skins
+                .iter()
+                .find(|s| s.skin_id == texture_id && s.colors == colors)
+        });
+        let bind_group = match translated {
+            Some(skin) => &skin.bind_group,
+            None => self.skin(texture_id).animate(time),
+        };
+
+        pass.set_bind_group(BindGroupLayoutId::PerTexture as u32, bind_group, &[]);
+        let vertices = self.keyframes[keyframe_id].animate(time);
+        stats.record_draw(&vertices);
+        pass.draw(vertices, 0..1)
+    }
+
+    /// Returns whether this model casts a shadow with `r_shadows`.
+    pub fn casts_shadow(&self) -> bool {
+        !NO_SHADOW_MODELS.contains(&self.name.as_str())
+    }
+
+    /// Records the draw commands for the shadow of a keyframe of this model.
+    ///
+    /// The shadow pipeline and its push constants must already be set, see `shadow_vector`.
+    pub fn record_draw_shadow<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        time: Duration,
+        keyframe_id: usize,
+        stats: &mut RenderStats,
     ) {
-        pass.set_pipeline(state.alias_pipeline().pipeline());
         pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        let vertices = self.keyframes[keyframe_id].animate(time);
+        stats.record_draw(&vertices);
+        pass.draw(vertices, 0..1)
+    }
+}
+
+/// Returns the shadow parameters of a model with the given yaw, `height` units above the floor.
+///
+/// Shadows are cast along the shade vector, so they fall the same way whichever way the model
+/// faces.
+pub fn shadow_vector(yaw: Deg<f32>, height: f32) -> Vector4<f32> {
+    let v = shade_vector(yaw);
+    Vector4::new(v.x, v.y, height, 0.0)
+}
+
+/// Returns the lowest light level, out of 255, that a model is drawn at.
+fn min_light(model_name: &str, viewmodel: bool) -> f32 {
+    if FULLBRIGHT_MODELS.contains(&model_name) {
+        256.0
+    } else if viewmodel {
+        MIN_VIEWMODEL_LIGHT
+    } else if model_name == "progs/player.mdl" {
+        MIN_PLAYER_LIGHT
+    } else {
+        0.0
+    }
+}
+
+/// Returns the direction a model with the given yaw is shaded from, in model space.
+fn shade_vector(yaw: Deg<f32>) -> Vector3<f32> {
+    let angle = Rad::from(-yaw).0;
+    Vector3::new(angle.cos(), angle.sin(), 1.0).normalize()
+}
+
+// Returns the index of the skin which was used longest ago.
+fn least_recently_used(skins: &[TranslatedSkin]) -> Option<usize> {
+    skins
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, s)| s.last_used)
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        pass.set_bind_group(
-            BindGroupLayoutId::PerTexture as u32,
-            self.textures[texture_id].animate(time),
-            &[],
+    #[test]
+    fn test_external_skin_path() {
+        assert_eq!(
+            external_skin_path("progs/player.mdl", 1, "png"),
+            "progs/player_1.png"
         );
-        pass.draw(self.keyframes[keyframe_id].animate(time), 0..1)
+        assert_eq!(
+            external_skin_path("progs/eyes", 0, "tga"),
+            "progs/eyes_0.tga"
+        );
+    }
+
+    #[test]
+    fn test_min_light() {
+        assert_eq!(min_light("progs/flame2.mdl", false), 256.0);
+        assert_eq!(min_light("progs/player.mdl", false), MIN_PLAYER_LIGHT);
+        assert_eq!(min_light("progs/v_shot.mdl", true), MIN_VIEWMODEL_LIGHT);
+        assert_eq!(min_light("progs/ogre.mdl", false), 0.0);
+    }
+
+    #[test]
+    fn test_shade_vector() {
+        let v = shade_vector(Deg(0.0));
+        assert!((v - Vector3::new(1.0, 0.0, 1.0).normalize()).magnitude() < 1e-6);
+
+        // the light stays put as the model turns left, so it comes from the model's right
+        let v = shade_vector(Deg(90.0));
+        assert!((v - Vector3::new(0.0, -1.0, 1.0).normalize()).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn test_shadow_vector() {
+        let v = shadow_vector(Deg(0.0), 24.0);
+        assert!((v.x - 0.5f32.sqrt()).abs() < 1e-6);
+        assert_eq!(v.y, 0.0);
+        assert_eq!(v.z, 24.0);
+    }
+
+    #[test]
+    fn test_load_png_rgb() {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, 2, 1);
+            encoder.set_color(png::ColorType::RGB);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[1, 2, 3, 4, 5, 6]).unwrap();
+        }
+
+        let (width, height, rgba) = load_png(&data[..]).unwrap();
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(rgba, vec![1, 2, 3, 0xFF, 4, 5, 6, 0xFF]);
     }
 }
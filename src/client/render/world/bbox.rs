@@ -0,0 +1,321 @@
+//! Entity bounding box visualization for `r_showbboxes`.
+//!
+//! Each entity's bounding box is drawn as a wireframe at its interpolated origin. If the latest
+//! origin received from the server differs, the box is drawn there as well in another color, so
+//! the distance between the two shows how far interpolation lags behind. A cross marks each
+//! origin. With `r_showbboxes 2`, the entity's model-space axes are drawn too.
+
+use std::mem::size_of;
+
+use crate::{
+    client::render::{
+        pipeline::{Pipeline, PushConstantUpdate},
+        stats::RenderStats,
+        world::{Camera, WorldPipelineBase},
+    },
+    common::util::any_slice_as_bytes,
+};
+
+use bumpalo::Bump;
+use cgmath::{Deg, Matrix4, Vector3, Vector4};
+
+/// The half-width of the cross drawn at each origin.
+const ORIGIN_SIZE: f32 = 4.0;
+
+/// The length of the model-space axes.
+const AXIS_LENGTH: f32 = 24.0;
+
+const BOX_COLOR: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+const SERVER_BOX_COLOR: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const ORIGIN_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+// x, y and z are red, green and blue
+const AXIS_COLORS: [[f32; 4]; 3] = [
+    [1.0, 0.0, 0.0, 1.0],
+    [0.0, 1.0, 0.0, 1.0],
+    [0.0, 0.0, 1.0, 1.0],
+];
+
+// ranges of the vertex buffer, see `create_vertices`
+const BOX_VERTICES: std::ops::Range<u32> = 0..24;
+const ORIGIN_VERTICES: std::ops::Range<u32> = 24..30;
+const AXIS_VERTICES: u32 = 30;
+
+/// The bounding box of an entity and where to draw it.
+#[derive(Clone, Copy, Debug)]
+pub struct EntityBox {
+    /// The model's extent around its origin.
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+
+    /// The interpolated origin and angles.
+    pub origin: Vector3<f32>,
+    pub angles: Vector3<Deg<f32>>,
+
+    /// The most recent origin received from the server.
+    pub server_origin: Vector3<f32>,
+}
+
+pub struct BboxPipeline {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl BboxPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        sample_count: u32,
+    ) -> BboxPipeline {
+        let (pipeline, bind_group_layouts) =
+            BboxPipeline::create(device, compiler, &[], sample_count);
+
+        use wgpu::util::DeviceExt as _;
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("bbox vertex buffer"),
+            contents: unsafe { any_slice_as_bytes(&create_vertices()) },
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        BboxPipeline {
+            pipeline,
+            bind_group_layouts,
+            vertex_buffer,
+        }
+    }
+
+    pub fn rebuild(
+        &mut self,
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        sample_count: u32,
+    ) {
+        let layout_refs: Vec<_> = self.bind_group_layouts.iter().collect();
+        self.pipeline = BboxPipeline::recreate(device, compiler, &layout_refs, sample_count);
+    }
+
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    /// Draws the boxes and origins of `boxes`, and their axes if `show_axes` is set.
+    pub fn record_draw<'a, I>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        camera: &Camera,
+        boxes: I,
+        show_axes: bool,
+        stats: &mut RenderStats,
+    ) where
+        I: Iterator<Item = EntityBox>,
+    {
+        pass.set_pipeline(self.pipeline());
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        let view_projection = camera.view_projection() * from_quake();
+        let mut draw = |pass: &mut wgpu::RenderPass<'a>,
+                        model: Matrix4<f32>,
+                        color: [f32; 4],
+                        vertices: std::ops::Range<u32>| {
+            Self::set_push_constants(
+                pass,
+                PushConstantUpdate::Update(bump.alloc(VertexPushConstants {
+                    transform: view_projection * model,
+                })),
+                PushConstantUpdate::Retain,
+                PushConstantUpdate::Update(bump.alloc(FragmentPushConstants {
+                    color: color.into(),
+                })),
+            );
+            stats.draw_calls += 1;
+            pass.draw(vertices, 0..1);
+        };
+
+        for b in boxes {
+            if b.server_origin != b.origin {
+                draw(
+                    pass,
+                    box_transform(b.server_origin, b.min, b.max),
+                    SERVER_BOX_COLOR,
+                    BOX_VERTICES,
+                );
+            }
+            draw(
+                pass,
+                box_transform(b.origin, b.min, b.max),
+                BOX_COLOR,
+                BOX_VERTICES,
+            );
+            draw(
+                pass,
+                Matrix4::from_translation(b.origin) * Matrix4::from_scale(ORIGIN_SIZE),
+                ORIGIN_COLOR,
+                ORIGIN_VERTICES,
+            );
+
+            if show_axes {
+                // rotated as in GLQuake's R_RotateForEntity
+                let model = Matrix4::from_translation(b.origin)
+                    * Matrix4::from_angle_z(b.angles.y)
+                    * Matrix4::from_angle_y(-b.angles.x)
+                    * Matrix4::from_angle_x(b.angles.z)
+                    * Matrix4::from_scale(AXIS_LENGTH);
+                for (axis, color) in AXIS_COLORS.iter().enumerate() {
+                    let start = AXIS_VERTICES + 2 * axis as u32;
+                    draw(pass, model, *color, start..start + 2);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the transform from the unit cube to the box from `min` to `max` around `origin`.
+fn box_transform(origin: Vector3<f32>, min: Vector3<f32>, max: Vector3<f32>) -> Matrix4<f32> {
+    let size = max - min;
+    Matrix4::from_translation(origin + min) * Matrix4::from_nonuniform_scale(size.x, size.y, size.z)
+}
+
+/// Returns the transform from Quake coordinates, as done by the world shaders.
+#[rustfmt::skip]
+fn from_quake() -> Matrix4<f32> {
+    Matrix4::new(
+         0.0, 0.0, -1.0, 0.0,
+        -1.0, 0.0,  0.0, 0.0,
+         0.0, 1.0,  0.0, 0.0,
+         0.0, 0.0,  0.0, 1.0,
+    )
+}
+
+/// Returns the line list vertices of the unit cube, the origin cross and the three unit axes.
+fn create_vertices() -> Vec<BboxVertex> {
+    let mut vertices = Vec::new();
+
+    // the edges of the cube connect corners differing in exactly one coordinate
+    for axis in 0..3 {
+        for corner in 0..4 {
+            let mut start = [0.0; 3];
+            let others = [(axis + 1) % 3, (axis + 2) % 3];
+            start[others[0]] = (corner & 1) as f32;
+            start[others[1]] = (corner >> 1) as f32;
+
+            let mut end = start;
+            end[axis] = 1.0;
+            vertices.push(BboxVertex { position: start });
+            vertices.push(BboxVertex { position: end });
+        }
+    }
+
+    for &(start, end) in [(-1.0, 1.0), (0.0, 1.0)].iter() {
+        for axis in 0..3 {
+            let mut a = [0.0; 3];
+            let mut b = [0.0; 3];
+            a[axis] = start;
+            b[axis] = end;
+            vertices.push(BboxVertex { position: a });
+            vertices.push(BboxVertex { position: b });
+        }
+    }
+
+    vertices
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct VertexPushConstants {
+    pub transform: Matrix4<f32>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FragmentPushConstants {
+    pub color: Vector4<f32>,
+}
+
+lazy_static! {
+    static ref VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![
+        // position
+        0 => Float32x3,
+    ];
+}
+
+impl Pipeline for BboxPipeline {
+    type VertexPushConstants = VertexPushConstants;
+    type SharedPushConstants = ();
+    type FragmentPushConstants = FragmentPushConstants;
+
+    fn name() -> &'static str {
+        "bbox"
+    }
+
+    fn vertex_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/bbox.vert"))
+    }
+
+    fn fragment_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/bbox.frag"))
+    }
+
+    fn bind_group_layout_descriptors() -> Vec<wgpu::BindGroupLayoutDescriptor<'static>> {
+        Vec::new()
+    }
+
+    fn primitive_state() -> wgpu::PrimitiveState {
+        wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::LineList,
+            ..WorldPipelineBase::primitive_state()
+        }
+    }
+
+    fn color_target_states() -> Vec<wgpu::ColorTargetState> {
+        WorldPipelineBase::color_target_states()
+    }
+
+    fn depth_stencil_state() -> Option<wgpu::DepthStencilState> {
+        let mut desc = WorldPipelineBase::depth_stencil_state().unwrap();
+        desc.depth_write_enabled = false;
+        Some(desc)
+    }
+
+    // NOTE: if the vertex format is changed, this descriptor must also be changed accordingly.
+    fn vertex_buffer_layouts() -> Vec<wgpu::VertexBufferLayout<'static>> {
+        vec![wgpu::VertexBufferLayout {
+            array_stride: size_of::<BboxVertex>() as u64,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &VERTEX_ATTRIBUTES[..],
+        }]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct BboxVertex {
+    position: [f32; 3],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vertex_ranges() {
+        let vertices = create_vertices();
+        assert_eq!(vertices.len() as u32, AXIS_VERTICES + 6);
+
+        // every cube edge has unit length along exactly one axis
+        for edge in vertices[BOX_VERTICES.start as usize..BOX_VERTICES.end as usize].chunks(2) {
+            let diff: Vec<f32> = (0..3)
+                .map(|i| edge[1].position[i] - edge[0].position[i])
+                .collect();
+            assert_eq!(diff.iter().sum::<f32>(), 1.0);
+            assert!(diff.iter().all(|d| *d == 0.0 || *d == 1.0));
+        }
+
+        assert_eq!(
+            vertices[ORIGIN_VERTICES.start as usize].position,
+            [-1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            vertices[AXIS_VERTICES as usize + 5].position,
+            [0.0, 0.0, 1.0]
+        );
+    }
+}
@@ -19,20 +19,19 @@
 // SOFTWARE.
 
 use std::{
-    borrow::Cow,
-    cell::{Cell, RefCell},
-    collections::HashMap,
-    mem::size_of,
-    num::NonZeroU32,
-    ops::Range,
+    borrow::Cow, cell::Cell, collections::HashMap, mem::size_of, num::NonZeroU32, ops::Range,
     rc::Rc,
 };
 
 use crate::{
     client::render::{
         pipeline::PushConstantUpdate,
-        warp,
-        world::{BindGroupLayoutId, WorldPipelineBase},
+        stats::RenderStats,
+        warp::{self, WarpPipeline},
+        world::{
+            skybox::{Skybox, SkyboxPipeline},
+            BindGroupLayoutId, Translucent, WorldPipelineBase,
+        },
         Camera, GraphicsState, LightmapData, Pipeline, TextureData,
     },
     common::{
@@ -52,6 +51,7 @@ use failure::Error;
 
 pub struct BrushPipeline {
     pipeline: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
     bind_group_layouts: Vec<wgpu::BindGroupLayout>,
 }
 
@@ -65,9 +65,16 @@ impl BrushPipeline {
     ) -> BrushPipeline {
         let (pipeline, bind_group_layouts) =
             BrushPipeline::create(device, compiler, world_bind_group_layouts, sample_count);
+        let layout_refs: Vec<_> = world_bind_group_layouts
+            .iter()
+            .chain(bind_group_layouts.iter())
+            .collect();
+        let translucent_pipeline =
+            Translucent::<BrushPipeline>::recreate(device, compiler, &layout_refs, sample_count);
 
         BrushPipeline {
             pipeline,
+            translucent_pipeline,
             // TODO: pick a starting capacity
             bind_group_layouts,
         }
@@ -85,12 +92,19 @@ impl BrushPipeline {
             .chain(self.bind_group_layouts.iter())
             .collect();
         self.pipeline = BrushPipeline::recreate(device, compiler, &layout_refs, sample_count);
+        self.translucent_pipeline =
+            Translucent::<BrushPipeline>::recreate(device, compiler, &layout_refs, sample_count);
     }
 
     pub fn pipeline(&self) -> &wgpu::RenderPipeline {
         &self.pipeline
     }
 
+    /// Returns the pipeline for entities drawn with an alpha below 1.
+    pub fn translucent_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.translucent_pipeline
+    }
+
     pub fn bind_group_layouts(&self) -> &[wgpu::BindGroupLayout] {
         &self.bind_group_layouts
     }
@@ -112,27 +126,29 @@ pub struct VertexPushConstants {
 #[derive(Copy, Clone, Debug)]
 pub struct SharedPushConstants {
     pub texture_kind: u32,
+    /// The layer of the bound texture array holding the current texture frame.
+    pub texture_layer: u32,
 }
 
 const BIND_GROUP_LAYOUT_ENTRIES: &[&[wgpu::BindGroupLayoutEntry]] = &[
     &[
-        // diffuse texture, updated once per face
+        // diffuse texture array, updated once per group of equally-sized textures
         wgpu::BindGroupLayoutEntry {
             binding: 0,
             visibility: wgpu::ShaderStage::FRAGMENT,
             ty: wgpu::BindingType::Texture {
-                view_dimension: wgpu::TextureViewDimension::D2,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
                 sample_type: wgpu::TextureSampleType::Float { filterable: true },
                 multisampled: false,
             },
             count: None,
         },
-        // fullbright texture
+        // fullbright texture array
         wgpu::BindGroupLayoutEntry {
             binding: 1,
             visibility: wgpu::ShaderStage::FRAGMENT,
             ty: wgpu::BindingType::Texture {
-                view_dimension: wgpu::TextureViewDimension::D2,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
                 sample_type: wgpu::TextureSampleType::Float { filterable: true },
                 multisampled: false,
             },
@@ -140,7 +156,7 @@ const BIND_GROUP_LAYOUT_ENTRIES: &[&[wgpu::BindGroupLayoutEntry]] = &[
         },
     ],
     &[
-        // lightmap texture array
+        // lightmap page, one texture per light style slot
         wgpu::BindGroupLayoutEntry {
             count: NonZeroU32::new(4),
             binding: 0,
@@ -196,7 +212,7 @@ impl Pipeline for BrushPipeline {
                 label: Some("brush per-texture bind group"),
                 entries: BIND_GROUP_LAYOUT_ENTRIES[0],
             },
-            // group 3: updated per-face
+            // group 3: updated per lightmap page
             wgpu::BindGroupLayoutDescriptor {
                 label: Some("brush per-face bind group"),
                 entries: BIND_GROUP_LAYOUT_ENTRIES[1],
@@ -226,23 +242,175 @@ impl Pipeline for BrushPipeline {
     }
 }
 
+/// Side length in texels of a lightmap atlas page.
+const LIGHTMAP_PAGE_DIM: u32 = 512;
+
+/// The location of a face's lightmaps within the lightmap atlas.
+#[derive(Clone, Copy, Debug)]
+struct LightmapPlacement {
+    page_id: usize,
+    origin: [u32; 2],
+}
+
 fn calculate_lightmap_texcoords(
     position: Vector3<f32>,
     face: &BspFace,
     texinfo: &BspTexInfo,
+    placement: Option<LightmapPlacement>,
 ) -> [f32; 2] {
+    let placement = match placement {
+        Some(p) => p,
+        // unlit faces sample the 1x1 default lightmap
+        None => return [0.0, 0.0],
+    };
+
+    // lightmap texels are 16 world units apart; offset by half a texel to sample texel centers
     let mut s = texinfo.s_vector.dot(position) + texinfo.s_offset;
     s -= (face.texture_mins[0] as f32 / 16.0).floor() * 16.0;
-    s += 0.5;
-    s /= face.extents[0] as f32;
+    s += placement.origin[0] as f32 * 16.0 + 8.0;
+    s /= LIGHTMAP_PAGE_DIM as f32 * 16.0;
 
     let mut t = texinfo.t_vector.dot(position) + texinfo.t_offset;
     t -= (face.texture_mins[1] as f32 / 16.0).floor() * 16.0;
-    t += 0.5;
-    t /= face.extents[1] as f32;
+    t += placement.origin[1] as f32 * 16.0 + 8.0;
+    t /= LIGHTMAP_PAGE_DIM as f32 * 16.0;
     [s, t]
 }
 
+/// Packs face lightmaps into lightmap atlas pages.
+///
+/// This is the allocator used by GLQuake: each page tracks the height of the filled area in
+/// every column, and each lightmap is placed wherever it would sit lowest. Only the newest page
+/// is searched, so faces packed in sequence tend to share a page.
+struct LightmapPacker {
+    pages: usize,
+    columns: Vec<u32>,
+}
+
+impl LightmapPacker {
+    fn new() -> LightmapPacker {
+        LightmapPacker {
+            pages: 0,
+            columns: Vec::new(),
+        }
+    }
+
+    /// Returns the number of pages allocated so far.
+    fn page_count(&self) -> usize {
+        self.pages
+    }
+
+    /// Allocates space for a lightmap, starting a new page if the current one is full.
+    ///
+    /// Returns `None` if the lightmap is larger than a page.
+    fn alloc(&mut self, width: u32, height: u32) -> Option<LightmapPlacement> {
+        if width > LIGHTMAP_PAGE_DIM || height > LIGHTMAP_PAGE_DIM {
+            return None;
+        }
+
+        if self.pages > 0 {
+            if let Some(origin) = self.alloc_in_page(width, height) {
+                return Some(LightmapPlacement {
+                    page_id: self.pages - 1,
+                    origin,
+                });
+            }
+        }
+
+        self.pages += 1;
+        self.columns = vec![0; LIGHTMAP_PAGE_DIM as usize];
+        self.alloc_in_page(width, height)
+            .map(|origin| LightmapPlacement {
+                page_id: self.pages - 1,
+                origin,
+            })
+    }
+
+    fn alloc_in_page(&mut self, width: u32, height: u32) -> Option<[u32; 2]> {
+        let w = width as usize;
+        let mut best: Option<[u32; 2]> = None;
+        let mut best_y = LIGHTMAP_PAGE_DIM;
+
+        for x in 0..=self.columns.len() - w {
+            // the lightmap would rest on the tallest column it covers
+            let mut y = 0;
+            let mut fits = true;
+            for &column in self.columns[x..x + w].iter() {
+                if column >= best_y {
+                    fits = false;
+                    break;
+                }
+                y = y.max(column);
+            }
+
+            if fits {
+                best = Some([x as u32, y]);
+                best_y = y;
+            }
+        }
+
+        let [x, y] = best?;
+        if y + height > LIGHTMAP_PAGE_DIM {
+            return None;
+        }
+
+        for column in self.columns[x as usize..x as usize + w].iter_mut() {
+            *column = y + height;
+        }
+
+        Some([x, y])
+    }
+}
+
+fn create_lightmap_bind_group(
+    state: &GraphicsState,
+    layout: &wgpu::BindGroupLayout,
+    mut lightmap_views: Vec<wgpu::TextureView>,
+) -> wgpu::BindGroup {
+    // unused light style slots are never sampled, but must still be bound
+    lightmap_views.resize_with(4, || {
+        state.default_lightmap().create_view(&Default::default())
+    });
+
+    let lightmap_view_refs = lightmap_views.iter().collect::<Vec<_>>();
+
+    let desc = wgpu::BindGroupDescriptor {
+        label: Some("per-face bind group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureViewArray(&lightmap_view_refs[..]),
+        }],
+    };
+    state.device().create_bind_group(&desc)
+}
+
+/// The contents of a lightmap atlas page before upload.
+struct LightmapPage {
+    /// One RGBA image per light style slot, created when first used.
+    slots: Vec<Vec<u8>>,
+}
+
+impl LightmapPage {
+    fn new() -> LightmapPage {
+        LightmapPage { slots: Vec::new() }
+    }
+
+    fn write(&mut self, slot: usize, origin: [u32; 2], width: u32, rgba: &[u8]) {
+        let page_len = (4 * LIGHTMAP_PAGE_DIM * LIGHTMAP_PAGE_DIM) as usize;
+        while self.slots.len() <= slot {
+            self.slots.push(vec![0; page_len]);
+        }
+
+        let row_len = 4 * width as usize;
+        for (r, row) in rgba.chunks_exact(row_len).enumerate() {
+            let start =
+                4 * ((origin[1] as usize + r) * LIGHTMAP_PAGE_DIM as usize + origin[0] as usize);
+            self.slots[slot][start..start + row_len].copy_from_slice(row);
+        }
+    }
+}
+
 type Position = [f32; 3];
 type Normal = [f32; 3];
 type DiffuseTexcoord = [f32; 2];
@@ -267,13 +435,56 @@ pub enum TextureKind {
     Sky = 2,
 }
 
+impl TextureKind {
+    fn from_name(name: &str) -> TextureKind {
+        if name.starts_with("sky") {
+            TextureKind::Sky
+        } else if name.starts_with("*") {
+            TextureKind::Warp
+        } else {
+            TextureKind::Normal
+        }
+    }
+}
+
+/// The maximum number of layers in a brush texture array.
+///
+/// This is the minimum value of `maxTextureArrayLayers` guaranteed by WebGPU.
+const MAX_TEXTURE_ARRAY_LAYERS: usize = 256;
+
+/// Groups texture frames with equal dimensions into texture arrays.
+///
+/// Returns the indices into `dimensions` of the frames stored in each array, in the order the
+/// arrays should be created. Frames whose dimensions no other frame shares end up alone in an
+/// array of one layer, i.e. an individual texture.
+fn group_texture_frames(dimensions: &[(u32, u32)]) -> Vec<Vec<usize>> {
+    let mut group_ids: HashMap<(u32, u32), usize> = HashMap::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+
+    for (frame_id, dim) in dimensions.iter().enumerate() {
+        match group_ids.get(dim) {
+            Some(&group_id) if groups[group_id].len() < MAX_TEXTURE_ARRAY_LAYERS => {
+                groups[group_id].push(frame_id)
+            }
+
+            // start a new array if there isn't one for these dimensions or it's full
+            _ => {
+                group_ids.insert(*dim, groups.len());
+                groups.push(vec![frame_id]);
+            }
+        }
+    }
+
+    groups
+}
+
 /// A single frame of a brush texture.
+#[derive(Clone, Copy, Debug)]
 pub struct BrushTextureFrame {
+    /// The per-texture bind group of the texture array holding this frame.
     bind_group_id: usize,
-    diffuse: wgpu::Texture,
-    fullbright: wgpu::Texture,
-    diffuse_view: wgpu::TextureView,
-    fullbright_view: wgpu::TextureView,
+    /// The layer of the texture array holding this frame.
+    layer: u32,
     kind: TextureKind,
 }
 
@@ -309,18 +520,30 @@ struct BrushFace {
 
     texture_id: usize,
 
-    lightmap_ids: Vec<usize>,
+    lightmap_placement: Option<LightmapPlacement>,
     light_styles: [u8; 4],
 
     /// Indicates whether the face should be drawn this frame.
     ///
-    /// This is set to false by default, and will be set to true if the model is
-    /// a worldmodel and the containing leaf is in the PVS. If the model is not
-    /// a worldmodel, this flag is ignored.
+    /// This is set to true by `mark_visible_faces` if the model is a worldmodel and the
+    /// containing leaf is in the PVS, and cleared again once the face is drawn. If the model is
+    /// not a worldmodel, this flag is ignored.
     draw_flag: Cell<bool>,
 }
 
+/// A set of faces sharing a texture and lightmap page.
+///
+/// The vertices of the faces in a batch are contiguous in the vertex buffer and in the same
+/// order as `face_ids`, so any run of visible faces can be drawn with a single call.
+struct BrushBatch {
+    texture_id: usize,
+    lightmap_bind_group_id: usize,
+    face_ids: Vec<usize>,
+}
+
 struct BrushLeaf {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
     facelist_ids: Range<usize>,
 }
 
@@ -330,7 +553,11 @@ where
 {
     fn from(bsp_leaf: B) -> Self {
         let bsp_leaf = bsp_leaf.borrow();
+        let [min_x, min_y, min_z] = bsp_leaf.min;
+        let [max_x, max_y, max_z] = bsp_leaf.max;
         BrushLeaf {
+            min: Vector3::new(min_x as f32, min_y as f32, min_z as f32),
+            max: Vector3::new(max_x as f32, max_y as f32, max_z as f32),
             facelist_ids: bsp_leaf.facelist_id..bsp_leaf.facelist_id + bsp_leaf.facelist_count,
         }
     }
@@ -342,15 +569,16 @@ pub struct BrushRendererBuilder {
 
     leaves: Option<Vec<BrushLeaf>>,
 
-    per_texture_bind_groups: RefCell<Vec<wgpu::BindGroup>>,
-    per_face_bind_groups: Vec<wgpu::BindGroup>,
+    per_texture_bind_groups: Vec<wgpu::BindGroup>,
+    lightmap_bind_groups: Vec<wgpu::BindGroup>,
 
     vertices: Vec<BrushVertex>,
     faces: Vec<BrushFace>,
-    texture_chains: HashMap<usize, Vec<usize>>,
+    batches: Vec<BrushBatch>,
     textures: Vec<BrushTexture>,
+    texture_arrays: Vec<wgpu::Texture>,
+    lightmap_pages: Vec<LightmapPage>,
     lightmaps: Vec<wgpu::Texture>,
-    //lightmap_views: Vec<wgpu::TextureView>,
 }
 
 impl BrushRendererBuilder {
@@ -368,18 +596,64 @@ impl BrushRendererBuilder {
             } else {
                 None
             },
-            per_texture_bind_groups: RefCell::new(Vec::new()),
-            per_face_bind_groups: Vec::new(),
+            per_texture_bind_groups: Vec::new(),
+            lightmap_bind_groups: Vec::new(),
             vertices: Vec::new(),
             faces: Vec::new(),
-            texture_chains: HashMap::new(),
+            batches: Vec::new(),
             textures: Vec::new(),
+            texture_arrays: Vec::new(),
+            lightmap_pages: Vec::new(),
             lightmaps: Vec::new(),
-            //lightmap_views: Vec::new(),
         }
     }
 
-    fn create_face(&mut self, state: &GraphicsState, face_id: usize) -> BrushFace {
+    fn face_texture_id(&self, face_id: usize) -> usize {
+        let face = &self.bsp_data.faces()[face_id];
+        self.bsp_data.texinfo()[face.texinfo_id].tex_id as usize
+    }
+
+    /// Copies a face's lightmaps into the atlas, returning their location.
+    fn pack_lightmaps(
+        &mut self,
+        packer: &mut LightmapPacker,
+        face_id: usize,
+    ) -> Option<LightmapPlacement> {
+        let face = &self.bsp_data.faces()[face_id];
+        if self.bsp_data.texinfo()[face.texinfo_id].special {
+            return None;
+        }
+
+        let lightmaps = self.bsp_data.face_lightmaps(face_id);
+        let (width, height) = match lightmaps.first() {
+            Some(l) => (l.width(), l.height()),
+            None => return None,
+        };
+
+        let placement = match packer.alloc(width, height) {
+            Some(p) => p,
+            None => {
+                warn!(
+                    "Lightmap for face {} is too large ({}x{}), drawing unlit",
+                    face_id, width, height
+                );
+                return None;
+            }
+        };
+
+        while self.lightmap_pages.len() < packer.page_count() {
+            self.lightmap_pages.push(LightmapPage::new());
+        }
+
+        let page = &mut self.lightmap_pages[placement.page_id];
+        for (slot, lightmap) in lightmaps.iter().enumerate() {
+            page.write(slot, placement.origin, width, &lightmap.to_rgba());
+        }
+
+        Some(placement)
+    }
+
+    fn create_face(&mut self, face_id: usize, placement: Option<LightmapPlacement>) -> BrushFace {
         let face = &self.bsp_data.faces()[face_id];
         let face_vert_id = self.vertices.len();
         let texinfo = &self.bsp_data.texinfo()[face.texinfo_id];
@@ -410,7 +684,12 @@ impl BrushRendererBuilder {
                         ((vert.dot(texinfo.s_vector) + texinfo.s_offset) / tex.width() as f32),
                         ((vert.dot(texinfo.t_vector) + texinfo.t_offset) / tex.height() as f32),
                     ],
-                    lightmap_texcoord: calculate_lightmap_texcoords(vert.into(), face, texinfo),
+                    lightmap_texcoord: calculate_lightmap_texcoords(
+                        vert.into(),
+                        face,
+                        texinfo,
+                        placement,
+                    ),
                     lightmap_anim: face.light_styles,
                 })
             }
@@ -445,6 +724,7 @@ impl BrushRendererBuilder {
                             (*vert).into(),
                             face,
                             texinfo,
+                            placement,
                         ),
                         lightmap_anim: face.light_styles,
                     });
@@ -454,44 +734,22 @@ impl BrushRendererBuilder {
             }
         }
 
-        // build the lightmaps
-        let lightmaps = if !texinfo.special {
-            self.bsp_data.face_lightmaps(face_id)
-        } else {
-            Vec::new()
-        };
-
-        let mut lightmap_ids = Vec::new();
-        for lightmap in lightmaps {
-            let lightmap_data = TextureData::Lightmap(LightmapData {
-                lightmap: Cow::Borrowed(lightmap.data()),
-            });
-
-            let texture =
-                state.create_texture(None, lightmap.width(), lightmap.height(), &lightmap_data);
-
-            let id = self.lightmaps.len();
-            self.lightmaps.push(texture);
-            //self.lightmap_views
-            //.push(self.lightmaps[id].create_view(&Default::default()));
-            lightmap_ids.push(id);
-        }
-
         BrushFace {
             vertices: face_vert_id as u32..self.vertices.len() as u32,
             min,
             max,
             texture_id: texinfo.tex_id as usize,
-            lightmap_ids,
+            lightmap_placement: placement,
             light_styles: face.light_styles,
-            draw_flag: Cell::new(true),
+            draw_flag: Cell::new(false),
         }
     }
 
     fn create_per_texture_bind_group(
         &self,
         state: &GraphicsState,
-        tex: &BrushTextureFrame,
+        diffuse_view: &wgpu::TextureView,
+        fullbright_view: &wgpu::TextureView,
     ) -> wgpu::BindGroup {
         let layout = &state
             .brush_pipeline()
@@ -502,174 +760,225 @@ impl BrushRendererBuilder {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&tex.diffuse_view),
+                    resource: wgpu::BindingResource::TextureView(diffuse_view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&tex.fullbright_view),
+                    resource: wgpu::BindingResource::TextureView(fullbright_view),
                 },
             ],
         };
         state.device().create_bind_group(&desc)
     }
 
-    fn create_per_face_bind_group(&self, state: &GraphicsState, face_id: usize) -> wgpu::BindGroup {
-        let mut lightmap_views: Vec<_> = self.faces[face_id]
-            .lightmap_ids
-            .iter()
-            .map(|id| self.lightmaps[*id].create_view(&Default::default()))
-            .collect();
-        lightmap_views.resize_with(4, || {
-            state.default_lightmap().create_view(&Default::default())
-        });
-
-        let lightmap_view_refs = lightmap_views.iter().collect::<Vec<_>>();
-
+    /// Uploads the lightmap atlas pages and creates a bind group for each.
+    ///
+    /// An extra bind group containing only the default lightmap is added after the pages for
+    /// faces without lightmaps.
+    ///
+    /// The pages are only uploaded once: light styles are animated by weighting each slot in the
+    /// fragment shader, and dynamic lights are added in the deferred lighting pass, so the
+    /// lightmaps themselves never change.
+    fn create_lightmap_bind_groups(&mut self, state: &GraphicsState) {
         let layout = &state
             .brush_pipeline()
             .bind_group_layout(BindGroupLayoutId::PerFace);
-        let desc = wgpu::BindGroupDescriptor {
-            label: Some("per-face bind group"),
-            layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureViewArray(&lightmap_view_refs[..]),
-            }],
-        };
-        state.device().create_bind_group(&desc)
-    }
 
-    fn create_brush_texture_frame<S>(
-        &self,
-        state: &GraphicsState,
-        mipmap: &[u8],
-        width: u32,
-        height: u32,
-        name: S,
-    ) -> BrushTextureFrame
-    where
-        S: AsRef<str>,
-    {
-        let name = name.as_ref();
-
-        let (diffuse_data, fullbright_data) = state.palette().translate(mipmap);
-        let diffuse =
-            state.create_texture(None, width, height, &TextureData::Diffuse(diffuse_data));
-        let fullbright = state.create_texture(
-            None,
-            width,
-            height,
-            &TextureData::Fullbright(fullbright_data),
-        );
+        for page in self.lightmap_pages.drain(..) {
+            let mut lightmap_views = Vec::new();
+            for slot in page.slots {
+                let texture = state.create_texture(
+                    None,
+                    LIGHTMAP_PAGE_DIM,
+                    LIGHTMAP_PAGE_DIM,
+                    &TextureData::Lightmap(LightmapData {
+                        lightmap: Cow::Owned(slot),
+                    }),
+                );
+                lightmap_views.push(texture.create_view(&Default::default()));
+                self.lightmaps.push(texture);
+            }
 
-        let diffuse_view = diffuse.create_view(&Default::default());
-        let fullbright_view = fullbright.create_view(&Default::default());
+            self.lightmap_bind_groups.push(create_lightmap_bind_group(
+                state,
+                layout,
+                lightmap_views,
+            ));
+        }
 
-        let kind = if name.starts_with("sky") {
-            TextureKind::Sky
-        } else if name.starts_with("*") {
-            TextureKind::Warp
-        } else {
-            TextureKind::Normal
-        };
+        self.lightmap_bind_groups
+            .push(create_lightmap_bind_group(state, layout, Vec::new()));
+    }
 
-        let mut frame = BrushTextureFrame {
-            bind_group_id: 0,
-            diffuse,
-            fullbright,
-            diffuse_view,
-            fullbright_view,
-            kind,
-        };
+    /// Groups faces into batches and reorders the vertex buffer to match.
+    ///
+    /// `face_order` lists face ids in the order they should be drawn.
+    fn create_batches(&mut self, face_order: &[usize]) {
+        let unlit_bind_group_id = self.lightmap_bind_groups.len() - 1;
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+
+        for &face_id in face_order {
+            let texture_id = self.faces[face_id].texture_id;
+            let lightmap_bind_group_id = match self.faces[face_id].lightmap_placement {
+                Some(p) => p.page_id,
+                None => unlit_bind_group_id,
+            };
 
-        // generate texture bind group
-        let per_texture_bind_group = self.create_per_texture_bind_group(state, &frame);
-        let bind_group_id = self.per_texture_bind_groups.borrow().len();
-        self.per_texture_bind_groups
-            .borrow_mut()
-            .push(per_texture_bind_group);
-
-        frame.bind_group_id = bind_group_id;
-        frame
-    }
-
-    pub fn create_brush_texture(&self, state: &GraphicsState, tex: &BspTexture) -> BrushTexture {
-        // TODO: upload mipmaps
-        let (width, height) = tex.dimensions();
-
-        match tex.kind() {
-            // sequence animated textures
-            BspTextureKind::Animated { primary, alternate } => {
-                let primary_frames: Vec<_> = primary
-                    .iter()
-                    .map(|f| {
-                        self.create_brush_texture_frame(
-                            state,
-                            f.mipmap(BspTextureMipmap::Full),
-                            width,
-                            height,
-                            tex.name(),
-                        )
-                    })
-                    .collect();
-
-                let alternate_frames: Option<Vec<_>> = alternate.as_ref().map(|a| {
-                    a.iter()
-                        .map(|f| {
-                            self.create_brush_texture_frame(
-                                state,
-                                f.mipmap(BspTextureMipmap::Full),
-                                width,
-                                height,
-                                tex.name(),
-                            )
-                        })
-                        .collect()
-                });
+            match self.batches.last_mut() {
+                Some(batch)
+                    if batch.texture_id == texture_id
+                        && batch.lightmap_bind_group_id == lightmap_bind_group_id =>
+                {
+                    batch.face_ids.push(face_id)
+                }
+                _ => self.batches.push(BrushBatch {
+                    texture_id,
+                    lightmap_bind_group_id,
+                    face_ids: vec![face_id],
+                }),
+            }
+
+            let old = self.faces[face_id].vertices.clone();
+            let start = vertices.len() as u32;
+            vertices.extend_from_slice(&self.vertices[old.start as usize..old.end as usize]);
+            self.faces[face_id].vertices = start..vertices.len() as u32;
+        }
 
-                BrushTexture::Animated {
-                    primary: primary_frames,
-                    alternate: alternate_frames,
+        self.vertices = vertices;
+    }
+
+    /// Uploads the frames of every texture, packing frames of equal dimensions into texture
+    /// arrays so that they share a bind group.
+    fn create_textures(&mut self, state: &GraphicsState) {
+        let bsp_data = self.bsp_data.clone();
+
+        // every frame of every texture, in the order the textures are built below
+        let mut frames = Vec::new();
+        for tex in bsp_data.textures().iter() {
+            match tex.kind() {
+                BspTextureKind::Static(frame) => frames.push((tex, frame)),
+                BspTextureKind::Animated { primary, alternate } => {
+                    frames.extend(primary.iter().map(|f| (tex, f)));
+                    if let Some(a) = alternate {
+                        frames.extend(a.iter().map(|f| (tex, f)));
+                    }
                 }
             }
+        }
 
-            BspTextureKind::Static(bsp_tex) => {
-                BrushTexture::Static(self.create_brush_texture_frame(
-                    state,
-                    bsp_tex.mipmap(BspTextureMipmap::Full),
-                    tex.width(),
-                    tex.height(),
-                    tex.name(),
-                ))
+        let dimensions: Vec<_> = frames.iter().map(|(tex, _)| tex.dimensions()).collect();
+
+        // the bind group and layer holding each frame
+        let mut placements = vec![(0, 0); frames.len()];
+        for group in group_texture_frames(&dimensions) {
+            let (width, height) = dimensions[group[0]];
+
+            let mut diffuse_layers = Vec::with_capacity(group.len());
+            let mut fullbright_layers = Vec::with_capacity(group.len());
+            for &frame_id in group.iter() {
+                let (diffuse_data, fullbright_data) = state
+                    .palette()
+                    .translate(frames[frame_id].1.mipmap(BspTextureMipmap::Full));
+                diffuse_layers.push(TextureData::Diffuse(diffuse_data));
+                fullbright_layers.push(TextureData::Fullbright(fullbright_data));
             }
+
+            let diffuse = state.create_texture_array(None, width, height, &diffuse_layers);
+            let fullbright = state.create_texture_array(None, width, height, &fullbright_layers);
+
+            // arrays of one layer would otherwise get a 2D view
+            let view_desc = wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            };
+            let bind_group = self.create_per_texture_bind_group(
+                state,
+                &diffuse.create_view(&view_desc),
+                &fullbright.create_view(&view_desc),
+            );
+
+            let bind_group_id = self.per_texture_bind_groups.len();
+            self.per_texture_bind_groups.push(bind_group);
+            self.texture_arrays.push(diffuse);
+            self.texture_arrays.push(fullbright);
+
+            for (layer, &frame_id) in group.iter().enumerate() {
+                placements[frame_id] = (bind_group_id, layer as u32);
+            }
+        }
+
+        let mut placements = placements.into_iter();
+        let mut next_frame = |tex: &BspTexture| {
+            let (bind_group_id, layer) = placements.next().unwrap();
+            BrushTextureFrame {
+                bind_group_id,
+                layer,
+                kind: TextureKind::from_name(tex.name()),
+            }
+        };
+
+        for tex in bsp_data.textures().iter() {
+            let texture = match tex.kind() {
+                BspTextureKind::Static(_) => BrushTexture::Static(next_frame(tex)),
+
+                // sequence animated textures
+                BspTextureKind::Animated { primary, alternate } => {
+                    let primary = primary.iter().map(|_| next_frame(tex)).collect();
+                    let alternate = alternate
+                        .as_ref()
+                        .map(|a| a.iter().map(|_| next_frame(tex)).collect());
+                    BrushTexture::Animated { primary, alternate }
+                }
+            };
+
+            self.textures.push(texture);
         }
     }
 
     pub fn build(mut self, state: &GraphicsState) -> Result<BrushRenderer, Error> {
         // create the diffuse and fullbright textures
-        for tex in self.bsp_data.textures().iter() {
-            self.textures.push(self.create_brush_texture(state, tex));
+        self.create_textures(state);
+
+        // face_id is the id of the face in the renderer, which is offset from its id in the
+        // bsp data by the start of the model's face range
+        let face_start = self.face_range.start;
+        let face_count = self.face_range.end - face_start;
+
+        // pack lightmaps in texture order so that faces sharing a texture also tend to share a
+        // lightmap page
+        let mut face_order: Vec<usize> = (0..face_count).collect();
+        face_order.sort_by_key(|id| self.face_texture_id(face_start + id));
+
+        let mut packer = LightmapPacker::new();
+        let mut placements = vec![None; face_count];
+        for &face_id in face_order.iter() {
+            placements[face_id] = self.pack_lightmaps(&mut packer, face_start + face_id);
         }
 
-        // generate faces, vertices and lightmaps
-        // bsp_face_id is the id of the face in the bsp data
-        // face_id is the new id of the face in the renderer
-        for bsp_face_id in self.face_range.start..self.face_range.end {
-            let face_id = self.faces.len();
-            let face = self.create_face(state, bsp_face_id);
+        // generate faces and vertices
+        for face_id in 0..face_count {
+            let face = self.create_face(face_start + face_id, placements[face_id]);
             self.faces.push(face);
+        }
 
-            let face_tex_id = self.faces[face_id].texture_id;
-            // update the corresponding texture chain
-            self.texture_chains
-                .entry(face_tex_id)
-                .or_insert(Vec::new())
-                .push(face_id);
+        self.create_lightmap_bind_groups(state);
 
-            // generate face bind group
-            let per_face_bind_group = self.create_per_face_bind_group(state, face_id);
-            self.per_face_bind_groups.push(per_face_bind_group);
-        }
+        // draw each texture's faces grouped by lightmap page, keeping textures that share an
+        // array together so that its bind group is only bound once
+        face_order.sort_by_key(|id| {
+            let face = &self.faces[*id];
+            let array_id = match self.textures[face.texture_id] {
+                BrushTexture::Static(ref frame) => frame.bind_group_id,
+                BrushTexture::Animated { ref primary, .. } => primary[0].bind_group_id,
+            };
+
+            (
+                array_id,
+                face.texture_id,
+                face.lightmap_placement.map_or(usize::MAX, |p| p.page_id),
+            )
+        });
+        self.create_batches(&face_order);
 
         use wgpu::util::DeviceExt as _;
         let vertex_buffer = state
@@ -684,13 +993,13 @@ impl BrushRendererBuilder {
             bsp_data: self.bsp_data,
             vertex_buffer,
             leaves: self.leaves,
-            per_texture_bind_groups: self.per_texture_bind_groups.into_inner(),
-            per_face_bind_groups: self.per_face_bind_groups,
-            texture_chains: self.texture_chains,
+            per_texture_bind_groups: self.per_texture_bind_groups,
+            lightmap_bind_groups: self.lightmap_bind_groups,
+            batches: self.batches,
             faces: self.faces,
             textures: self.textures,
-            lightmaps: self.lightmaps,
-            //lightmap_views: self.lightmap_views,
+            _texture_arrays: self.texture_arrays,
+            _lightmaps: self.lightmaps,
         })
     }
 }
@@ -702,102 +1011,306 @@ pub struct BrushRenderer {
 
     vertex_buffer: wgpu::Buffer,
     per_texture_bind_groups: Vec<wgpu::BindGroup>,
-    per_face_bind_groups: Vec<wgpu::BindGroup>,
+    lightmap_bind_groups: Vec<wgpu::BindGroup>,
 
-    // faces are grouped by texture to reduce the number of texture rebinds
-    // texture_chains maps texture ids to face ids
-    texture_chains: HashMap<usize, Vec<usize>>,
+    // faces are batched by texture and lightmap page to reduce the number of draw calls, sorted
+    // by texture so that each texture is bound once
+    batches: Vec<BrushBatch>,
     faces: Vec<BrushFace>,
     textures: Vec<BrushTexture>,
-    lightmaps: Vec<wgpu::Texture>,
-    //lightmap_views: Vec<wgpu::TextureView>,
+
+    // the texture arrays and lightmap pages must outlive their bind groups
+    _texture_arrays: Vec<wgpu::Texture>,
+    _lightmaps: Vec<wgpu::Texture>,
 }
 
 impl BrushRenderer {
+    /// Marks the faces in leaves visible from the camera to be drawn this frame.
+    ///
+    /// This only affects worldmodels, and must be called before `record_draw`. If `novis` is set,
+    /// every leaf is treated as potentially visible, and if `nocull` is set, leaves outside the
+    /// viewing frustum are not skipped. The visible leaves and faces are counted in `stats`.
+    pub fn mark_visible_faces(
+        &self,
+        camera: &Camera,
+        novis: bool,
+        nocull: bool,
+        stats: &mut RenderStats,
+    ) {
+        let leaves = match self.leaves {
+            Some(ref l) => l,
+            None => return,
+        };
+
+        let visible_leaves = if novis {
+            (1..leaves.len()).collect()
+        } else {
+            self.bsp_data
+                .get_pvs(self.bsp_data.find_leaf(camera.origin), leaves.len())
+        };
+
+        for leaf_id in visible_leaves {
+            let leaf = &leaves[leaf_id];
+            if !nocull && camera.cull_box(leaf.min, leaf.max) {
+                continue;
+            }
+
+            stats.visible_leaves += 1;
+            for facelist_id in leaf.facelist_ids.clone() {
+                let face = &self.faces[self.bsp_data.facelist()[facelist_id]];
+                // faces may be shared between leaves
+                if !face.draw_flag.replace(true) {
+                    stats.visible_surfaces += 1;
+                }
+            }
+        }
+    }
+
     /// Record the draw commands for this brush model to the given `wgpu::RenderPass`.
+    ///
+    /// Worldmodels only draw the faces marked by `mark_visible_faces`. Liquid surfaces are
+    /// skipped here and drawn later by `record_draw_liquids`. If `skybox` is provided, sky
+    /// surfaces are drawn with it instead of the sky texture. If `translucent` is set, the model is
+    /// blended by the render pass's blend constant.
     pub fn record_draw<'a>(
         &'a self,
         state: &'a GraphicsState,
         pass: &mut wgpu::RenderPass<'a>,
         bump: &'a Bump,
         time: Duration,
-        camera: &Camera,
         frame_id: usize,
+        transforms: VertexPushConstants,
+        skybox: Option<&'a Skybox>,
+        translucent: bool,
+        stats: &mut RenderStats,
     ) {
-        pass.set_pipeline(state.brush_pipeline().pipeline());
+        use PushConstantUpdate::*;
+
+        let transforms: &'a VertexPushConstants = bump.alloc(transforms);
+
+        if translucent {
+            pass.set_pipeline(state.brush_pipeline().translucent_pipeline());
+        } else {
+            pass.set_pipeline(state.brush_pipeline().pipeline());
+        }
+        BrushPipeline::set_push_constants(pass, Update(transforms), Clear, Clear);
         pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
 
-        // if this is a worldmodel, mark faces to be drawn
-        if let Some(ref leaves) = self.leaves {
-            let pvs = self
-                .bsp_data
-                .get_pvs(self.bsp_data.find_leaf(camera.origin), leaves.len());
+        let mut bound_texture_id = None;
+        let mut bound_array_id = None;
+        let mut bound_lightmap_id = None;
+        for batch in self.batches.iter() {
+            let tex_id = batch.texture_id;
+
+            // liquids and skyboxes are drawn afterward with their own pipelines
+            match self.textures[tex_id].kind() {
+                TextureKind::Warp => continue,
+                TextureKind::Sky if skybox.is_some() => continue,
+                _ => (),
+            }
 
-            // only draw faces in pvs
-            for leaf_id in pvs {
-                for facelist_id in leaves[leaf_id].facelist_ids.clone() {
-                    let face = &self.faces[self.bsp_data.facelist()[facelist_id]];
+            if bound_texture_id != Some(tex_id) {
+                let frame = self.texture_frame(tex_id, frame_id, time);
+                BrushPipeline::set_push_constants(
+                    pass,
+                    Retain,
+                    Update(bump.alloc(SharedPushConstants {
+                        texture_kind: frame.kind as u32,
+                        texture_layer: frame.layer,
+                    })),
+                    Retain,
+                );
 
-                    // TODO: frustum culling
-                    face.draw_flag.set(true);
+                if bound_array_id != Some(frame.bind_group_id) {
+                    pass.set_bind_group(
+                        BindGroupLayoutId::PerTexture as u32,
+                        &self.per_texture_bind_groups[frame.bind_group_id],
+                        &[],
+                    );
+                    bound_array_id = Some(frame.bind_group_id);
                 }
+                bound_texture_id = Some(tex_id);
             }
-        }
 
-        for (tex_id, face_ids) in self.texture_chains.iter() {
-            use PushConstantUpdate::*;
-            BrushPipeline::set_push_constants(
-                pass,
-                Retain,
-                Update(bump.alloc(SharedPushConstants {
-                    texture_kind: self.textures[*tex_id].kind() as u32,
-                })),
-                Retain,
-            );
+            if bound_lightmap_id != Some(batch.lightmap_bind_group_id) {
+                pass.set_bind_group(
+                    BindGroupLayoutId::PerFace as u32,
+                    &self.lightmap_bind_groups[batch.lightmap_bind_group_id],
+                    &[],
+                );
+                bound_lightmap_id = Some(batch.lightmap_bind_group_id);
+            }
 
-            let bind_group_id = match &self.textures[*tex_id] {
-                BrushTexture::Static(ref frame) => frame.bind_group_id,
-                BrushTexture::Animated { primary, alternate } => {
-                    // if frame is not zero and this texture has an alternate
-                    // animation, use it
-                    let anim = if frame_id == 0 {
-                        primary
-                    } else if let Some(a) = alternate {
-                        a
-                    } else {
-                        primary
-                    };
-
-                    let time_ms = time.num_milliseconds();
-                    let total_ms = (bsp::frame_duration() * anim.len() as i32).num_milliseconds();
-                    let anim_ms = if total_ms == 0 { 0 } else { time_ms % total_ms };
-                    anim[(anim_ms / bsp::frame_duration().num_milliseconds()) as usize]
-                        .bind_group_id
-                }
-            };
+            self.draw_marked_faces(pass, &batch.face_ids, stats);
+        }
 
+        if let Some(skybox) = skybox {
+            pass.set_pipeline(state.skybox_pipeline().pipeline());
+            SkyboxPipeline::set_push_constants(pass, Update(transforms), Clear, Clear);
             pass.set_bind_group(
                 BindGroupLayoutId::PerTexture as u32,
-                &self.per_texture_bind_groups[bind_group_id],
+                skybox.bind_group(),
                 &[],
             );
 
-            for face_id in face_ids.iter() {
-                let face = &self.faces[*face_id];
-
-                // only skip the face if we have visibility data but it's not marked
-                if self.leaves.is_some() && !face.draw_flag.replace(false) {
-                    continue;
+            // the box shows through the sky surfaces, hiding anything behind them as the
+            // regular sky would
+            for batch in self.batches.iter() {
+                if matches!(self.textures[batch.texture_id].kind(), TextureKind::Sky) {
+                    self.draw_marked_faces(pass, &batch.face_ids, stats);
                 }
+            }
+        }
+    }
 
+    /// Record the draw commands for this brush model's liquid surfaces.
+    ///
+    /// This must be called after `record_draw`, which marks the visible faces, and after all
+    /// opaque geometry has been drawn so that translucent liquids blend over it.
+    pub fn record_draw_liquids<'a>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        time: Duration,
+        frame_id: usize,
+        transforms: VertexPushConstants,
+        stats: &mut RenderStats,
+    ) {
+        use PushConstantUpdate::*;
+
+        pass.set_pipeline(state.warp_pipeline().pipeline());
+        WarpPipeline::set_push_constants(pass, Update(bump.alloc(transforms)), Clear, Clear);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        let mut bound_array_id = None;
+        for batch in self.batches.iter() {
+            let tex_id = batch.texture_id;
+            if !matches!(self.textures[tex_id].kind(), TextureKind::Warp) {
+                continue;
+            }
+
+            let frame = self.texture_frame(tex_id, frame_id, time);
+            WarpPipeline::set_push_constants(
+                pass,
+                Retain,
+                Update(bump.alloc(SharedPushConstants {
+                    texture_kind: frame.kind as u32,
+                    texture_layer: frame.layer,
+                })),
+                Retain,
+            );
+
+            if bound_array_id != Some(frame.bind_group_id) {
                 pass.set_bind_group(
-                    BindGroupLayoutId::PerFace as u32,
-                    &self.per_face_bind_groups[*face_id],
+                    BindGroupLayoutId::PerTexture as u32,
+                    &self.per_texture_bind_groups[frame.bind_group_id],
                     &[],
                 );
+                bound_array_id = Some(frame.bind_group_id);
+            }
+            self.draw_marked_faces(pass, &batch.face_ids, stats);
+        }
+    }
+
+    /// Returns the current frame of the given texture.
+    fn texture_frame(&self, tex_id: usize, frame_id: usize, time: Duration) -> &BrushTextureFrame {
+        match &self.textures[tex_id] {
+            BrushTexture::Static(ref frame) => frame,
+            BrushTexture::Animated { primary, alternate } => {
+                // if frame is not zero and this texture has an alternate
+                // animation, use it
+                let anim = if frame_id == 0 {
+                    primary
+                } else if let Some(a) = alternate {
+                    a
+                } else {
+                    primary
+                };
+
+                let time_ms = time.num_milliseconds();
+                let total_ms = (bsp::frame_duration() * anim.len() as i32).num_milliseconds();
+                let anim_ms = if total_ms == 0 { 0 } else { time_ms % total_ms };
+                &anim[(anim_ms / bsp::frame_duration().num_milliseconds()) as usize]
+            }
+        }
+    }
+
+    /// Draws the marked faces among `face_ids` without binding their lightmaps.
+    ///
+    /// Runs of marked faces whose vertices are adjacent are drawn with a single call.
+    fn draw_marked_faces<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        face_ids: &[usize],
+        stats: &mut RenderStats,
+    ) {
+        let mut run: Option<Range<u32>> = None;
 
-                pass.draw(face.vertices.clone(), 0..1);
+        for face_id in face_ids.iter() {
+            let face = &self.faces[*face_id];
+            if self.leaves.is_some() && !face.draw_flag.replace(false) {
+                continue;
             }
+
+            run = match run {
+                Some(r) if r.end == face.vertices.start => Some(r.start..face.vertices.end),
+                Some(r) => {
+                    stats.record_draw(&r);
+                    pass.draw(r, 0..1);
+                    Some(face.vertices.clone())
+                }
+                None => Some(face.vertices.clone()),
+            };
+        }
+
+        if let Some(r) = run {
+            stats.record_draw(&r);
+            pass.draw(r, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lightmap_packer() {
+        let mut packer = LightmapPacker::new();
+
+        // fill the first row of the first page
+        for i in 0..LIGHTMAP_PAGE_DIM / 16 {
+            let p = packer.alloc(16, 8).unwrap();
+            assert_eq!(p.page_id, 0);
+            assert_eq!(p.origin, [16 * i, 0]);
         }
+
+        // the next lightmap goes on top of the shortest columns
+        let p = packer.alloc(4, 4).unwrap();
+        assert_eq!((p.page_id, p.origin), (0, [0, 8]));
+
+        // a lightmap that doesn't fit starts a new page
+        let p = packer.alloc(LIGHTMAP_PAGE_DIM, LIGHTMAP_PAGE_DIM).unwrap();
+        assert_eq!((p.page_id, p.origin), (1, [0, 0]));
+        assert_eq!(packer.page_count(), 2);
+
+        assert!(packer.alloc(LIGHTMAP_PAGE_DIM + 1, 1).is_none());
+    }
+
+    #[test]
+    fn test_group_texture_frames() {
+        let dimensions = [(64, 64), (128, 32), (64, 64), (16, 16), (128, 32), (64, 64)];
+        assert_eq!(
+            group_texture_frames(&dimensions),
+            vec![vec![0, 2, 5], vec![1, 4], vec![3]]
+        );
+
+        // full arrays spill over into a new one
+        let dimensions = vec![(64, 64); MAX_TEXTURE_ARRAY_LAYERS + 1];
+        let groups = group_texture_frames(&dimensions);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), MAX_TEXTURE_ARRAY_LAYERS);
+        assert_eq!(groups[1], vec![MAX_TEXTURE_ARRAY_LAYERS]);
     }
 }
@@ -0,0 +1,205 @@
+//! Dynamic light coronas (`gl_flashblend`).
+//!
+//! Like GLQuake's flash blend mode, each dynamic light is drawn as a glowing billboard at its
+//! origin instead of lighting the surfaces around it. The glow is added to the diffuse attachment
+//! and raises the light attachment to at least full brightness, so it shows up even in the dark.
+
+use crate::{
+    client::{
+        entity::Light,
+        render::{
+            pipeline::{Pipeline, PushConstantUpdate},
+            stats::RenderStats,
+            world::{
+                particle::{ParticlePipeline, VERTICES},
+                Camera, WorldPipelineBase,
+            },
+        },
+    },
+    common::{math::Angles, util::any_slice_as_bytes},
+};
+
+use bumpalo::Bump;
+use cgmath::{InnerSpace as _, Matrix4, Vector3, Vector4};
+use chrono::Duration;
+
+/// The size of a corona relative to its light's radius, as in `R_RenderDlight`.
+const CORONA_SCALE: f32 = 0.35;
+
+/// The color at the center of a corona. It fades to black at the edge.
+const CORONA_COLOR: [f32; 4] = [0.2, 0.1, 0.0, 1.0];
+
+pub struct CoronaPipeline {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl CoronaPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        sample_count: u32,
+    ) -> CoronaPipeline {
+        let pipeline = CoronaPipeline::recreate(device, compiler, &[], sample_count);
+
+        use wgpu::util::DeviceExt as _;
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("corona vertex buffer"),
+            contents: unsafe { any_slice_as_bytes(&VERTICES) },
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+
+        CoronaPipeline {
+            pipeline,
+            vertex_buffer,
+        }
+    }
+
+    pub fn rebuild(
+        &mut self,
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        sample_count: u32,
+    ) {
+        self.pipeline = CoronaPipeline::recreate(device, compiler, &[], sample_count);
+    }
+
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    pub fn record_draw<'a, 'b, L>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        camera: &Camera,
+        time: Duration,
+        lights: L,
+        stats: &mut RenderStats,
+    ) where
+        L: Iterator<Item = &'b Light>,
+    {
+        use PushConstantUpdate::*;
+
+        pass.set_pipeline(self.pipeline());
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        // face toward camera
+        let Angles { pitch, yaw, roll } = camera.angles();
+        let rotation = Angles {
+            pitch: -pitch,
+            yaw: -yaw,
+            roll: -roll,
+        }
+        .mat4_wgpu();
+
+        for light in lights {
+            let size = light.radius(time) * CORONA_SCALE;
+            let origin = light.origin();
+
+            // a corona around the camera would cover the whole screen
+            if size <= 0.0 || (origin - camera.origin()).magnitude() < size {
+                continue;
+            }
+
+            let translation =
+                Matrix4::from_translation(Vector3::new(-origin.y, origin.z, -origin.x));
+            CoronaPipeline::set_push_constants(
+                pass,
+                Update(bump.alloc(VertexPushConstants {
+                    transform: camera.view_projection()
+                        * translation
+                        * rotation
+                        * Matrix4::from_scale(size),
+                })),
+                Clear,
+                Update(bump.alloc(FragmentPushConstants {
+                    color: CORONA_COLOR.into(),
+                })),
+            );
+
+            stats.record_draw(&(0..6));
+            pass.draw(0..6, 0..1);
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct VertexPushConstants {
+    pub transform: Matrix4<f32>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct FragmentPushConstants {
+    pub color: Vector4<f32>,
+}
+
+impl Pipeline for CoronaPipeline {
+    type VertexPushConstants = VertexPushConstants;
+    type SharedPushConstants = ();
+    type FragmentPushConstants = FragmentPushConstants;
+
+    fn name() -> &'static str {
+        "corona"
+    }
+
+    fn vertex_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/corona.vert"))
+    }
+
+    fn fragment_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/corona.frag"))
+    }
+
+    fn bind_group_layout_descriptors() -> Vec<wgpu::BindGroupLayoutDescriptor<'static>> {
+        Vec::new()
+    }
+
+    fn primitive_state() -> wgpu::PrimitiveState {
+        WorldPipelineBase::primitive_state()
+    }
+
+    // the glow is added to the diffuse attachment, and the light attachment keeps the brighter of
+    // the two values. the normal attachment is left untouched.
+    fn color_target_states() -> Vec<wgpu::ColorTargetState> {
+        let add = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let max = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Max,
+        };
+
+        let mut targets = WorldPipelineBase::color_target_states();
+        targets[0].blend = Some(wgpu::BlendState {
+            color: add,
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        });
+        targets[1].write_mask = wgpu::ColorWrite::empty();
+        targets[2].blend = Some(wgpu::BlendState {
+            color: max,
+            alpha: max,
+        });
+        targets
+    }
+
+    fn depth_stencil_state() -> Option<wgpu::DepthStencilState> {
+        let mut desc = WorldPipelineBase::depth_stencil_state().unwrap();
+        desc.depth_write_enabled = false;
+        Some(desc)
+    }
+
+    // coronas use the same quad as particles
+    fn vertex_buffer_layouts() -> Vec<wgpu::VertexBufferLayout<'static>> {
+        ParticlePipeline::vertex_buffer_layouts()
+    }
+}
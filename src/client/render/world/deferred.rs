@@ -65,15 +65,21 @@ impl DeferredPipeline {
         }
     }
 
+    /// Rebuilds the pipeline with a new sample count.
+    ///
+    /// The bind group layouts are recreated as well, since whether the attachments are bound as
+    /// multisampled textures depends on the sample count. Bind groups must be recreated after
+    /// calling this.
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
         compiler: &mut shaderc::Compiler,
         sample_count: u32,
     ) {
-        let layout_refs: Vec<_> = self.bind_group_layouts.iter().collect();
-        let pipeline = DeferredPipeline::recreate(device, compiler, &layout_refs, sample_count);
+        let (pipeline, bind_group_layouts) =
+            DeferredPipeline::create(device, compiler, &[], sample_count);
         self.pipeline = pipeline;
+        self.bind_group_layouts = bind_group_layouts;
     }
 
     pub fn pipeline(&self) -> &wgpu::RenderPipeline {
@@ -1,29 +1,39 @@
 pub mod alias;
+pub mod bbox;
 pub mod brush;
+pub mod corona;
 pub mod deferred;
 pub mod particle;
 pub mod postprocess;
+pub mod shadow;
+pub mod skybox;
 pub mod sprite;
 
-use std::{cell::RefCell, mem::size_of};
+use std::{cell::RefCell, cmp::Ordering, marker::PhantomData, mem::size_of, rc::Rc};
 
 use crate::{
     client::{
-        entity::particle::Particle,
+        entity::{particle::Particle, Light},
+        fog::Fog,
         render::{
             pipeline::{Pipeline, PushConstantUpdate},
+            stats::RenderStats,
             uniform::{DynamicUniformBufferBlock, UniformArrayFloat, UniformBool},
             world::{
                 alias::{AliasPipeline, AliasRenderer},
-                brush::{BrushPipeline, BrushRenderer, BrushRendererBuilder},
-                sprite::{SpritePipeline, SpriteRenderer},
+                bbox::EntityBox,
+                brush::{BrushRenderer, BrushRendererBuilder},
+                shadow::ShadowPipeline,
+                skybox::Skybox,
+                sprite::{SpriteBlend, SpritePipeline, SpriteRenderer},
             },
-            GraphicsState, DEPTH_ATTACHMENT_FORMAT, DIFFUSE_ATTACHMENT_FORMAT,
+            Extent2d, GraphicsState, DEPTH_ATTACHMENT_FORMAT, DIFFUSE_ATTACHMENT_FORMAT,
             LIGHT_ATTACHMENT_FORMAT, NORMAL_ATTACHMENT_FORMAT,
         },
         ClientEntity,
     },
     common::{
+        bsp::BspData,
         console::CvarRegistry,
         engine,
         math::Angles,
@@ -34,9 +44,26 @@ use crate::{
 };
 
 use bumpalo::Bump;
-use cgmath::{Euler, InnerSpace, Matrix4, SquareMatrix as _, Vector3, Vector4};
+use cgmath::{
+    Angle as _, Deg, Euler, InnerSpace, Matrix as _, Matrix4, SquareMatrix as _, Vector3, Vector4,
+};
 use chrono::Duration;
 
+/// Fraction of the depth range used by the weapon view model.
+///
+/// Like the original's depth hack, this keeps the view model in front of nearby world geometry.
+const VIEWMODEL_DEPTH_RANGE: f32 = 0.3;
+
+/// Returns the scale applied across and up the view model to compensate for a field of view wider
+/// than 90 degrees, which would otherwise shrink it into the bottom of the screen.
+fn gun_fov_scale(fov: f32, enabled: bool) -> f32 {
+    if !enabled || fov <= 90.0 {
+        return 1.0;
+    }
+
+    Deg(fov / 2.0).tan()
+}
+
 lazy_static! {
     static ref BIND_GROUP_LAYOUT_DESCRIPTOR_BINDINGS: [Vec<wgpu::BindGroupLayoutEntry>; 2] = [
         vec![
@@ -97,7 +124,7 @@ lazy_static! {
     ];
 }
 
-struct WorldPipelineBase;
+pub struct WorldPipelineBase;
 
 impl Pipeline for WorldPipelineBase {
     type VertexPushConstants = ();
@@ -180,6 +207,147 @@ impl Pipeline for WorldPipelineBase {
     }
 }
 
+/// A variant of the pipeline `P` for drawing translucent entities.
+///
+/// Like liquids, every attachment is blended by the opacity in the render pass's blend constant.
+/// Translucent geometry doesn't write depth, so it must be drawn back to front after everything
+/// opaque.
+pub struct Translucent<P>(PhantomData<P>);
+
+impl<P> Pipeline for Translucent<P>
+where
+    P: Pipeline,
+{
+    type VertexPushConstants = P::VertexPushConstants;
+    type SharedPushConstants = P::SharedPushConstants;
+    type FragmentPushConstants = P::FragmentPushConstants;
+
+    fn name() -> &'static str {
+        P::name()
+    }
+
+    fn vertex_shader() -> &'static str {
+        P::vertex_shader()
+    }
+
+    fn fragment_shader() -> &'static str {
+        P::fragment_shader()
+    }
+
+    fn bind_group_layout_descriptors() -> Vec<wgpu::BindGroupLayoutDescriptor<'static>> {
+        P::bind_group_layout_descriptors()
+    }
+
+    fn primitive_state() -> wgpu::PrimitiveState {
+        P::primitive_state()
+    }
+
+    fn color_target_states() -> Vec<wgpu::ColorTargetState> {
+        let blend = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Constant,
+            dst_factor: wgpu::BlendFactor::OneMinusConstant,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        P::color_target_states()
+            .into_iter()
+            .map(|target| wgpu::ColorTargetState {
+                blend: Some(wgpu::BlendState {
+                    color: blend,
+                    alpha: blend,
+                }),
+                ..target
+            })
+            .collect()
+    }
+
+    fn depth_stencil_state() -> Option<wgpu::DepthStencilState> {
+        P::depth_stencil_state().map(|desc| wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            ..desc
+        })
+    }
+
+    fn vertex_buffer_layouts() -> Vec<wgpu::VertexBufferLayout<'static>> {
+        P::vertex_buffer_layouts()
+    }
+}
+
+/// A variant of the pipeline `P` for drawing glowing entities, like explosion sprites.
+///
+/// The diffuse color is scaled by the render pass's blend constant and added to what's behind it,
+/// and the light attachment keeps the brighter of the two values so the glow isn't darkened by
+/// the lighting. Like translucent geometry, additive geometry doesn't write depth.
+pub struct Additive<P>(PhantomData<P>);
+
+impl<P> Pipeline for Additive<P>
+where
+    P: Pipeline,
+{
+    type VertexPushConstants = P::VertexPushConstants;
+    type SharedPushConstants = P::SharedPushConstants;
+    type FragmentPushConstants = P::FragmentPushConstants;
+
+    fn name() -> &'static str {
+        P::name()
+    }
+
+    fn vertex_shader() -> &'static str {
+        P::vertex_shader()
+    }
+
+    fn fragment_shader() -> &'static str {
+        P::fragment_shader()
+    }
+
+    fn bind_group_layout_descriptors() -> Vec<wgpu::BindGroupLayoutDescriptor<'static>> {
+        P::bind_group_layout_descriptors()
+    }
+
+    fn primitive_state() -> wgpu::PrimitiveState {
+        P::primitive_state()
+    }
+
+    fn color_target_states() -> Vec<wgpu::ColorTargetState> {
+        let max = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Max,
+        };
+
+        let mut targets = P::color_target_states();
+        targets[0].blend = Some(wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Constant,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        });
+        targets[1].write_mask = wgpu::ColorWrite::empty();
+        targets[2].blend = Some(wgpu::BlendState {
+            color: max,
+            alpha: max,
+        });
+        targets
+    }
+
+    fn depth_stencil_state() -> Option<wgpu::DepthStencilState> {
+        P::depth_stencil_state().map(|desc| wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            ..desc
+        })
+    }
+
+    fn vertex_buffer_layouts() -> Vec<wgpu::VertexBufferLayout<'static>> {
+        P::vertex_buffer_layouts()
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum BindGroupLayoutId {
     PerFrame = 0,
@@ -188,6 +356,17 @@ pub enum BindGroupLayoutId {
     PerFace = 3,
 }
 
+/// The weapon view model and its placement in the world.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewmodel {
+    /// The renderer index of the model.
+    pub model_id: usize,
+    pub frame: usize,
+    pub origin: Vector3<f32>,
+    pub angles: Angles,
+}
+
+#[derive(Clone, Copy)]
 pub struct Camera {
     origin: Vector3<f32>,
     angles: Angles,
@@ -209,20 +388,33 @@ impl Camera {
         let view = rotation * translation;
         let view_projection = projection * view;
 
+        // extract the clipping planes in Quake coordinates, so that the conversion doesn't have
+        // to be applied to everything tested against them. the planes are the sums and
+        // differences of the rows of the transform, and a point p is inside a plane if
+        // dot(plane, p) >= 0.
+        //
         // see https://www.gamedevs.org/uploads/fast-extraction-viewing-frustum-planes-from-world-view-projection-matrix.pdf
+        #[rustfmt::skip]
+        let from_quake = Matrix4::new(
+             0.0, 0.0, -1.0, 0.0,
+            -1.0, 0.0,  0.0, 0.0,
+             0.0, 1.0,  0.0, 0.0,
+             0.0, 0.0,  0.0, 1.0,
+        );
+        let clip = view_projection * from_quake;
         let clipping_planes = [
             // left
-            view_projection.w + view_projection.x,
+            clip.row(3) + clip.row(0),
             // right
-            view_projection.w - view_projection.x,
+            clip.row(3) - clip.row(0),
             // bottom
-            view_projection.w + view_projection.y,
+            clip.row(3) + clip.row(1),
             // top
-            view_projection.w - view_projection.y,
-            // near
-            view_projection.w + view_projection.z,
+            clip.row(3) - clip.row(1),
+            // near (wgpu clip space depth starts at 0)
+            clip.row(2),
             // far
-            view_projection.w - view_projection.z,
+            clip.row(3) - clip.row(2),
         ];
 
         Camera {
@@ -260,16 +452,26 @@ impl Camera {
         self.inverse_projection
     }
 
-    // TODO: this seems to be too lenient
     /// Determines whether a point falls outside the viewing frustum.
     pub fn cull_point(&self, p: Vector3<f32>) -> bool {
-        for plane in self.clipping_planes.iter() {
-            if (self.view_projection() * p.extend(1.0)).dot(*plane) < 0.0 {
-                return true;
-            }
-        }
+        self.clipping_planes
+            .iter()
+            .any(|plane| plane.dot(p.extend(1.0)) < 0.0)
+    }
+
+    /// Determines whether an axis-aligned box falls entirely outside the viewing frustum.
+    pub fn cull_box(&self, min: Vector3<f32>, max: Vector3<f32>) -> bool {
+        self.clipping_planes.iter().any(|plane| {
+            // test the corner furthest along the plane normal
+            let corner = Vector4::new(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+                1.0,
+            );
 
-        false
+            plane.dot(corner) < 0.0
+        })
     }
 }
 
@@ -280,10 +482,17 @@ pub struct FrameUniforms {
     // TODO: pack frame values into a [Vector4<f32>; 16],
     lightmap_anim_frames: [UniformArrayFloat; 64],
     camera_pos: Vector4<f32>,
+    // fog color in xyz, density in w
+    fog: Vector4<f32>,
     time: f32,
 
     // TODO: pack flags into a bit string
     r_lightmap: UniformBool,
+    r_waterwarp: UniformBool,
+
+    // debug modes for brush surfaces
+    r_drawflat: UniformBool,
+    r_showtris: UniformBool,
 }
 
 #[repr(C, align(256))]
@@ -296,6 +505,50 @@ pub struct EntityUniforms {
     model: Matrix4<f32>,
 }
 
+/// The extent of a model around its origin, used for frustum culling.
+#[derive(Clone, Copy, Debug)]
+struct ModelBounds {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    radius: f32,
+}
+
+impl ModelBounds {
+    fn from_box(min: Vector3<f32>, max: Vector3<f32>) -> ModelBounds {
+        ModelBounds {
+            min,
+            max,
+            radius: min.magnitude().max(max.magnitude()),
+        }
+    }
+
+    fn from_radius(radius: f32) -> ModelBounds {
+        let extent = Vector3::new(radius, radius, radius);
+        ModelBounds {
+            min: -extent,
+            max: extent,
+            radius,
+        }
+    }
+
+    /// Returns the world space box containing the model at the given position.
+    ///
+    /// The box is only rotated with the model if it has no rotation, otherwise a box around its
+    /// bounding sphere is returned.
+    fn world_box(
+        &self,
+        origin: Vector3<f32>,
+        angles: Vector3<Deg<f32>>,
+    ) -> (Vector3<f32>, Vector3<f32>) {
+        if angles == Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)) {
+            (origin + self.min, origin + self.max)
+        } else {
+            let extent = Vector3::new(self.radius, self.radius, self.radius);
+            (origin - extent, origin + extent)
+        }
+    }
+}
+
 enum EntityRenderer {
     Alias(AliasRenderer),
     Brush(BrushRenderer),
@@ -306,16 +559,30 @@ enum EntityRenderer {
 /// Top-level renderer.
 pub struct WorldRenderer {
     worldmodel_renderer: BrushRenderer,
+    // the world's BSP data, which alias models are lit from
+    world_bsp: Rc<BspData>,
     entity_renderers: Vec<EntityRenderer>,
+    entity_bounds: Vec<ModelBounds>,
 
     world_uniform_block: DynamicUniformBufferBlock<EntityUniforms>,
     entity_uniform_blocks: RefCell<Vec<DynamicUniformBufferBlock<EntityUniforms>>>,
+
+    skybox: Option<Skybox>,
+
+    // the last skybox requested, so a missing one is only reported once
+    skybox_name: Option<String>,
+
+    // whether the map can be seen through its liquids
+    water_vised: bool,
 }
 
 impl WorldRenderer {
     pub fn new(state: &GraphicsState, models: &[Model], worldmodel_id: usize) -> WorldRenderer {
         let mut worldmodel_renderer = None;
+        let mut world_bsp = None;
+        let mut water_vised = false;
         let mut entity_renderers = Vec::new();
+        let mut entity_bounds = Vec::new();
 
         let world_uniform_block = state.entity_uniform_buffer_mut().allocate(EntityUniforms {
             transform: Matrix4::identity(),
@@ -326,6 +593,8 @@ impl WorldRenderer {
             if i == worldmodel_id {
                 match *model.kind() {
                     ModelKind::Brush(ref bmodel) => {
+                        water_vised = bmodel.is_water_vised();
+                        world_bsp = Some(bmodel.bsp_data());
                         worldmodel_renderer = Some(
                             BrushRendererBuilder::new(bmodel, true)
                                 .build(state)
@@ -336,9 +605,12 @@ impl WorldRenderer {
                 }
             } else {
                 match *model.kind() {
-                    ModelKind::Alias(ref amodel) => entity_renderers.push(EntityRenderer::Alias(
-                        AliasRenderer::new(state, amodel).unwrap(),
-                    )),
+                    ModelKind::Alias(ref amodel) => {
+                        entity_renderers.push(EntityRenderer::Alias(
+                            AliasRenderer::new(state, model.name(), amodel).unwrap(),
+                        ));
+                        entity_bounds.push(ModelBounds::from_radius(amodel.radius()));
+                    }
 
                     ModelKind::Brush(ref bmodel) => {
                         entity_renderers.push(EntityRenderer::Brush(
@@ -346,16 +618,22 @@ impl WorldRenderer {
                                 .build(state)
                                 .unwrap(),
                         ));
+                        entity_bounds.push(ModelBounds::from_box(bmodel.min(), bmodel.max()));
                     }
 
                     ModelKind::Sprite(ref smodel) => {
-                        entity_renderers
-                            .push(EntityRenderer::Sprite(SpriteRenderer::new(&state, smodel)));
+                        entity_renderers.push(EntityRenderer::Sprite(SpriteRenderer::new(
+                            &state,
+                            model.name(),
+                            smodel,
+                        )));
+                        entity_bounds.push(ModelBounds::from_radius(smodel.radius()));
                     }
 
                     _ => {
                         warn!("Non-brush renderers not implemented!");
                         entity_renderers.push(EntityRenderer::None);
+                        entity_bounds.push(ModelBounds::from_radius(0.0));
                     }
                 }
             }
@@ -363,9 +641,75 @@ impl WorldRenderer {
 
         WorldRenderer {
             worldmodel_renderer: worldmodel_renderer.unwrap(),
+            world_bsp: world_bsp.unwrap(),
             entity_renderers,
+            entity_bounds,
             world_uniform_block,
             entity_uniform_blocks: RefCell::new(Vec::new()),
+            skybox: None,
+            skybox_name: None,
+            water_vised,
+        }
+    }
+
+    /// Returns the opacity of liquid surfaces.
+    ///
+    /// Liquids are always opaque on maps that weren't vised for transparent water. The server
+    /// says whether the map was by setting `watervis`, and otherwise it's determined from the
+    /// map's visibility data.
+    fn water_alpha(&self, cvars: &CvarRegistry) -> f32 {
+        let water_vised = match cvars.get_value("watervis").unwrap() {
+            v if v < 0.0 => self.water_vised,
+            v => v != 0.0,
+        };
+        if !water_vised {
+            return 1.0;
+        }
+
+        cvars.get_value("r_wateralpha").unwrap().max(0.0).min(1.0)
+    }
+
+    /// Loads the named skybox if it isn't already loaded, or removes the skybox if `name` is
+    /// `None`.
+    ///
+    /// If the skybox can't be loaded, the regular sky is drawn instead.
+    pub fn update_skybox(&mut self, state: &GraphicsState, name: Option<&str>) {
+        if self.skybox_name.as_deref() == name {
+            return;
+        }
+
+        self.skybox_name = name.map(|n| n.to_owned());
+        self.skybox = name.and_then(|n| match Skybox::load(state, n) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                warn!("Couldn't load skybox {}: {}", n, e);
+                None
+            }
+        });
+    }
+
+    /// Recolors the skins of entities drawn in a player's colors, if they haven't been already.
+    ///
+    /// This must be called before the entities are drawn, or they are drawn in their original
+    /// colors.
+    pub fn prepare_player_skins<'a, I>(&mut self, state: &GraphicsState, entities: I)
+    where
+        I: Iterator<Item = &'a ClientEntity>,
+    {
+        for ent in entities {
+            let colors = match ent.colors() {
+                Some(c) => c,
+                None => continue,
+            };
+
+            // subtract 1 from index because world entity isn't counted
+            let renderer = ent
+                .model_id()
+                .checked_sub(1)
+                .and_then(|i| self.entity_renderers.get_mut(i));
+            if let Some(EntityRenderer::Alias(alias)) = renderer {
+                alias.prepare_translated_skin(state, ent.skin_id(), colors);
+            }
         }
     }
 
@@ -376,6 +720,7 @@ impl WorldRenderer {
         time: Duration,
         entities: I,
         lightstyle_values: &[f32],
+        fog: Fog,
         cvars: &CvarRegistry,
     ) where
         I: Iterator<Item = &'a ClientEntity>,
@@ -393,8 +738,12 @@ impl WorldRenderer {
                         frames
                     },
                     camera_pos: camera.origin.extend(1.0),
+                    fog: fog.color.extend(fog.density),
                     time: engine::duration_to_f32(time),
                     r_lightmap: UniformBool::new(cvars.get_value("r_lightmap").unwrap() != 0.0),
+                    r_waterwarp: UniformBool::new(cvars.get_value("r_waterwarp").unwrap() != 0.0),
+                    r_drawflat: UniformBool::new(cvars.get_value("r_drawflat").unwrap() != 0.0),
+                    r_showtris: UniformBool::new(cvars.get_value("r_showtris").unwrap() != 0.0),
                 })
             });
 
@@ -427,7 +776,11 @@ impl WorldRenderer {
         state.entity_uniform_buffer().flush(state.queue());
     }
 
-    pub fn render_pass<'a, E, P>(
+    /// Records the world pass, which draws the world's opaque geometry.
+    ///
+    /// This also updates the uniform buffers used by the entity and viewmodel passes, so it must
+    /// run before them.
+    pub fn render_world_pass<'a, E>(
         &'a self,
         state: &'a GraphicsState,
         pass: &mut wgpu::RenderPass<'a>,
@@ -435,128 +788,506 @@ impl WorldRenderer {
         camera: &Camera,
         time: Duration,
         entities: E,
-        particles: P,
         lightstyle_values: &[f32],
-        viewmodel_id: usize,
+        fog: Fog,
         cvars: &CvarRegistry,
+        stats: &mut RenderStats,
     ) where
-        E: Iterator<Item = &'a ClientEntity> + Clone,
-        P: Iterator<Item = &'a Particle>,
+        E: Iterator<Item = &'a ClientEntity>,
     {
-        use PushConstantUpdate::*;
         info!("Updating uniform buffers");
-        self.update_uniform_buffers(
-            state,
-            camera,
-            time,
-            entities.clone(),
-            lightstyle_values,
-            cvars,
-        );
+        self.update_uniform_buffers(state, camera, time, entities, lightstyle_values, fog, cvars);
 
-        pass.set_bind_group(
-            BindGroupLayoutId::PerFrame as u32,
-            &state.world_bind_groups()[BindGroupLayoutId::PerFrame as usize],
-            &[],
-        );
+        stats.uniform_bytes = state.entity_uniform_buffer().allocated();
+        stats.uniform_capacity = state.entity_uniform_buffer().capacity();
+
+        self.set_world_bind_groups(state, pass);
 
         // draw world
         info!("Drawing world");
-        pass.set_pipeline(state.brush_pipeline().pipeline());
-        BrushPipeline::set_push_constants(
+        self.worldmodel_renderer.mark_visible_faces(
+            camera,
+            cvars.get_value("r_novis").unwrap() != 0.0,
+            cvars.get_value("r_nocull").unwrap() != 0.0,
+            stats,
+        );
+        self.worldmodel_renderer.record_draw(
+            state,
             pass,
-            Update(bump.alloc(brush::VertexPushConstants {
+            &bump,
+            time,
+            0,
+            brush::VertexPushConstants {
                 transform: camera.view_projection(),
                 model_view: camera.view(),
-            })),
-            Clear,
-            Clear,
+            },
+            self.skybox.as_ref(),
+            false,
+            stats,
         );
+    }
+
+    /// Records the entity pass, which draws entities and everything blended over the world.
+    ///
+    /// Opaque entities and their shadows are drawn first, then particles, coronas and liquids,
+    /// and finally translucent entities from back to front.
+    pub fn render_entity_pass<'a, E, P, L>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        camera: &Camera,
+        time: Duration,
+        entities: E,
+        particles: P,
+        lights: L,
+        lightstyle_values: &[f32],
+        cvars: &CvarRegistry,
+        stats: &mut RenderStats,
+    ) where
+        E: Iterator<Item = &'a ClientEntity> + Clone,
+        P: Iterator<Item = &'a Particle>,
+        L: Iterator<Item = &'a Light>,
+    {
+        self.set_world_bind_groups(state, pass);
+        let nocull = cvars.get_value("r_nocull").unwrap() != 0.0;
+
+        // draw opaque entities, leaving translucent and additive ones until everything else is
+        // drawn
+        info!("Drawing entities");
+        let mut translucent = Vec::new();
+        for (ent_pos, ent) in entities.clone().enumerate() {
+            if ent.alpha <= 0.0 || (!nocull && self.cull_entity(camera, ent)) {
+                continue;
+            }
+
+            let additive = match self.renderer_for_entity(ent) {
+                EntityRenderer::Sprite(ref sprite) => sprite.blend() == SpriteBlend::Additive,
+                _ => false,
+            };
+            if ent.alpha < 1.0 || additive {
+                translucent.push((ent_pos, ent));
+                continue;
+            }
+
+            self.record_draw_entity(
+                state,
+                pass,
+                bump,
+                camera,
+                time,
+                ent_pos,
+                ent,
+                lightstyle_values,
+                false,
+                stats,
+            );
+        }
+
+        if cvars.get_value("r_shadows").unwrap() != 0.0 {
+            info!("Drawing shadows");
+            self.record_draw_shadows(
+                state,
+                pass,
+                bump,
+                camera,
+                time,
+                entities.clone(),
+                nocull,
+                stats,
+            );
+        }
+
+        // particles are still simulated with r_particles 0, just not drawn
+        if cvars.get_value("r_particles").unwrap() != 0.0 {
+            log::debug!("Drawing particles");
+            state
+                .particle_pipeline()
+                .record_draw(pass, &bump, camera, particles, stats);
+        }
+
+        if cvars.get_value("gl_flashblend").unwrap() != 0.0 {
+            log::debug!("Drawing coronas");
+            state
+                .corona_pipeline()
+                .record_draw(pass, &bump, camera, time, lights, stats);
+        }
+
+        // draw liquids last so they can be blended over everything else
+        info!("Drawing liquids");
+        let alpha = self.water_alpha(cvars) as f64;
+        pass.set_blend_constant(wgpu::Color {
+            r: alpha,
+            g: alpha,
+            b: alpha,
+            a: alpha,
+        });
+
         pass.set_bind_group(
             BindGroupLayoutId::PerEntity as u32,
             &state.world_bind_groups()[BindGroupLayoutId::PerEntity as usize],
             &[self.world_uniform_block.offset()],
         );
-        self.worldmodel_renderer
-            .record_draw(state, pass, &bump, time, camera, 0);
+        self.worldmodel_renderer.record_draw_liquids(
+            state,
+            pass,
+            &bump,
+            time,
+            0,
+            brush::VertexPushConstants {
+                transform: camera.view_projection(),
+                model_view: camera.view(),
+            },
+            stats,
+        );
 
-        // draw entities
-        info!("Drawing entities");
+        let entities_for_bboxes = entities.clone();
         for (ent_pos, ent) in entities.enumerate() {
-            pass.set_bind_group(
-                BindGroupLayoutId::PerEntity as u32,
-                &state.world_bind_groups()[BindGroupLayoutId::PerEntity as usize],
-                &[self.entity_uniform_blocks.borrow()[ent_pos].offset()],
-            );
+            if ent.alpha <= 0.0 || (!nocull && self.cull_entity(camera, ent)) {
+                continue;
+            }
 
-            match self.renderer_for_entity(&ent) {
-                EntityRenderer::Brush(ref bmodel) => {
-                    pass.set_pipeline(state.brush_pipeline().pipeline());
-                    BrushPipeline::set_push_constants(
-                        pass,
-                        Update(bump.alloc(brush::VertexPushConstants {
-                            transform: self.calculate_mvp_transform(camera, ent),
-                            model_view: self.calculate_mv_transform(camera, ent),
-                        })),
-                        Clear,
-                        Clear,
-                    );
-                    bmodel.record_draw(state, pass, &bump, time, camera, ent.frame_id);
-                }
-                EntityRenderer::Alias(ref alias) => {
-                    pass.set_pipeline(state.alias_pipeline().pipeline());
-                    AliasPipeline::set_push_constants(
-                        pass,
-                        Update(bump.alloc(alias::VertexPushConstants {
-                            transform: self.calculate_mvp_transform(camera, ent),
-                            model_view: self.calculate_mv_transform(camera, ent),
-                        })),
-                        Clear,
-                        Clear,
-                    );
-                    alias.record_draw(state, pass, time, ent.frame_id(), ent.skin_id());
-                }
-                EntityRenderer::Sprite(ref sprite) => {
-                    pass.set_pipeline(state.sprite_pipeline().pipeline());
-                    SpritePipeline::set_push_constants(pass, Clear, Clear, Clear);
-                    sprite.record_draw(state, pass, ent.frame_id(), time);
-                }
-                _ => warn!("non-brush renderers not implemented!"),
-                // _ => unimplemented!(),
+            if let EntityRenderer::Brush(ref bmodel) = self.renderer_for_entity(&ent) {
+                pass.set_bind_group(
+                    BindGroupLayoutId::PerEntity as u32,
+                    &state.world_bind_groups()[BindGroupLayoutId::PerEntity as usize],
+                    &[self.entity_uniform_blocks.borrow()[ent_pos].offset()],
+                );
+                bmodel.record_draw_liquids(
+                    state,
+                    pass,
+                    &bump,
+                    time,
+                    ent.frame_id,
+                    brush::VertexPushConstants {
+                        transform: self.calculate_mvp_transform(camera, ent),
+                        model_view: self.calculate_mv_transform(camera, ent),
+                    },
+                    stats,
+                );
             }
         }
 
-        let viewmodel_orig = camera.origin();
-        let cam_angles = camera.angles();
-        let viewmodel_mat = Matrix4::from_translation(Vector3::new(
-            -viewmodel_orig.y,
-            viewmodel_orig.z,
-            -viewmodel_orig.x,
-        )) * Matrix4::from_angle_y(cam_angles.yaw)
-            * Matrix4::from_angle_x(-cam_angles.pitch)
-            * Matrix4::from_angle_z(cam_angles.roll);
-        match self.entity_renderers[viewmodel_id] {
+        // draw translucent and additive entities back to front, since they don't write depth
+        info!("Drawing translucent entities");
+        let distance =
+            |ent: &ClientEntity| (self.entity_center(ent) - camera.origin()).magnitude2();
+        translucent.sort_by(|(_, a), (_, b)| {
+            distance(b)
+                .partial_cmp(&distance(a))
+                .unwrap_or(Ordering::Equal)
+        });
+        for (ent_pos, ent) in translucent {
+            let alpha = ent.alpha as f64;
+            pass.set_blend_constant(wgpu::Color {
+                r: alpha,
+                g: alpha,
+                b: alpha,
+                a: alpha,
+            });
+            self.record_draw_entity(
+                state,
+                pass,
+                bump,
+                camera,
+                time,
+                ent_pos,
+                ent,
+                lightstyle_values,
+                true,
+                stats,
+            );
+        }
+
+        let show_bboxes = cvars.get_value("r_showbboxes").unwrap();
+        if show_bboxes != 0.0 {
+            info!("Drawing bounding boxes");
+            let boxes = entities_for_bboxes
+                .filter(|ent| nocull || !self.cull_entity(camera, ent))
+                .map(|ent| {
+                    // subtract 1 from index because world entity isn't counted
+                    let bounds = &self.entity_bounds[ent.model_id() - 1];
+                    EntityBox {
+                        min: bounds.min,
+                        max: bounds.max,
+                        origin: ent.origin,
+                        angles: ent.angles,
+                        server_origin: ent.history.latest().origin,
+                    }
+                });
+            state
+                .bbox_pipeline()
+                .record_draw(pass, bump, camera, boxes, show_bboxes >= 2.0, stats);
+        }
+    }
+
+    /// Records the viewmodel pass, which draws the weapon view model over the rest of the scene.
+    pub fn render_viewmodel_pass<'a>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        camera: &Camera,
+        time: Duration,
+        lightstyle_values: &[f32],
+        viewmodel: Option<Viewmodel>,
+        cvars: &CvarRegistry,
+        stats: &mut RenderStats,
+    ) {
+        if cvars.get_value("r_drawviewmodel").unwrap() == 0.0 {
+            return;
+        }
+
+        if let Some(viewmodel) = viewmodel {
+            self.set_world_bind_groups(state, pass);
+
+            info!("Drawing viewmodel");
+            self.record_draw_viewmodel(
+                state,
+                pass,
+                bump,
+                camera,
+                time,
+                viewmodel,
+                gun_fov_scale(
+                    cvars.get_value("fov").unwrap(),
+                    cvars.get_value("cl_gun_fovscale").unwrap() != 0.0,
+                ),
+                lightstyle_values,
+                stats,
+            );
+        }
+    }
+
+    /// Binds the per-frame uniforms and the world's per-entity uniforms at the start of a pass.
+    fn set_world_bind_groups<'a>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        pass.set_bind_group(
+            BindGroupLayoutId::PerFrame as u32,
+            &state.world_bind_groups()[BindGroupLayoutId::PerFrame as usize],
+            &[],
+        );
+        pass.set_bind_group(
+            BindGroupLayoutId::PerEntity as u32,
+            &state.world_bind_groups()[BindGroupLayoutId::PerEntity as usize],
+            &[self.world_uniform_block.offset()],
+        );
+    }
+
+    /// Draws a single entity with its uniform block at `ent_pos`.
+    ///
+    /// If `translucent` is set, the entity is blended by the render pass's blend constant.
+    fn record_draw_entity<'a>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        camera: &Camera,
+        time: Duration,
+        ent_pos: usize,
+        ent: &ClientEntity,
+        lightstyle_values: &[f32],
+        translucent: bool,
+        stats: &mut RenderStats,
+    ) {
+        use PushConstantUpdate::*;
+
+        stats.entities += 1;
+
+        pass.set_bind_group(
+            BindGroupLayoutId::PerEntity as u32,
+            &state.world_bind_groups()[BindGroupLayoutId::PerEntity as usize],
+            &[self.entity_uniform_blocks.borrow()[ent_pos].offset()],
+        );
+
+        match self.renderer_for_entity(&ent) {
+            EntityRenderer::Brush(ref bmodel) => {
+                bmodel.record_draw(
+                    state,
+                    pass,
+                    &bump,
+                    time,
+                    ent.frame_id,
+                    brush::VertexPushConstants {
+                        transform: self.calculate_mvp_transform(camera, ent),
+                        model_view: self.calculate_mv_transform(camera, ent),
+                    },
+                    None,
+                    translucent,
+                    stats,
+                );
+            }
             EntityRenderer::Alias(ref alias) => {
+                let light = self.light_point(ent.origin, lightstyle_values);
                 pass.set_pipeline(state.alias_pipeline().pipeline());
                 AliasPipeline::set_push_constants(
                     pass,
                     Update(bump.alloc(alias::VertexPushConstants {
-                        transform: camera.view_projection() * viewmodel_mat,
-                        model_view: camera.view() * viewmodel_mat,
+                        transform: self.calculate_mvp_transform(camera, ent),
+                        model_view: self.calculate_mv_transform(camera, ent),
                     })),
                     Clear,
-                    Clear,
+                    Update(bump.alloc(alias.shading(light, ent.angles.y, false))),
                 );
-                alias.record_draw(state, pass, time, 0, 0);
+                alias.record_draw(
+                    state,
+                    pass,
+                    time,
+                    ent.frame_id(),
+                    ent.skin_id(),
+                    ent.colors(),
+                    translucent,
+                    stats,
+                );
+            }
+            EntityRenderer::Sprite(ref sprite) => {
+                pass.set_pipeline(state.sprite_pipeline().pipeline());
+                SpritePipeline::set_push_constants(pass, Clear, Clear, Clear);
+                sprite.record_draw(state, pass, ent.frame_id(), time, translucent, stats);
             }
+            _ => warn!("non-brush renderers not implemented!"),
+            // _ => unimplemented!(),
+        }
+    }
+
+    /// Draws the shadows of alias models onto the floor beneath them.
+    fn record_draw_shadows<'a, E>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        camera: &Camera,
+        time: Duration,
+        entities: E,
+        nocull: bool,
+        stats: &mut RenderStats,
+    ) where
+        E: Iterator<Item = &'a ClientEntity>,
+    {
+        use PushConstantUpdate::*;
+
+        pass.set_pipeline(state.shadow_pipeline().pipeline());
+        for ent in entities {
+            if ent.alpha <= 0.0 || (!nocull && self.cull_entity(camera, ent)) {
+                continue;
+            }
+
+            let alias = match self.renderer_for_entity(ent) {
+                EntityRenderer::Alias(ref alias) if alias.casts_shadow() => alias,
+                _ => continue,
+            };
 
-            _ => unreachable!("non-alias viewmodel"),
+            let floor = match self.world_bsp.light_spot(ent.origin) {
+                Some(spot) => spot,
+                None => continue,
+            };
+
+            // the shadow turns with the model's yaw, but not its pitch or roll
+            let origin = ent.origin;
+            let transform = camera.view_projection()
+                * Matrix4::from_translation(Vector3::new(-origin.y, origin.z, -origin.x))
+                * Matrix4::from_angle_y(ent.angles.y);
+            ShadowPipeline::set_push_constants(
+                pass,
+                Update(bump.alloc(shadow::VertexPushConstants {
+                    transform,
+                    shadow: alias::shadow_vector(ent.angles.y, origin.z - floor.z),
+                })),
+                Clear,
+                Update(bump.alloc(shadow::FragmentPushConstants::new())),
+            );
+            alias.record_draw_shadow(pass, time, ent.frame_id(), stats);
         }
+    }
 
-        log::debug!("Drawing particles");
-        state
-            .particle_pipeline()
-            .record_draw(pass, &bump, camera, particles);
+    /// Draws the weapon view model in front of the camera.
+    ///
+    /// The view model is drawn into a reduced depth range so that it never clips into walls.
+    fn record_draw_viewmodel<'a>(
+        &'a self,
+        state: &'a GraphicsState,
+        pass: &mut wgpu::RenderPass<'a>,
+        bump: &'a Bump,
+        camera: &Camera,
+        time: Duration,
+        viewmodel: Viewmodel,
+        fov_scale: f32,
+        lightstyle_values: &[f32],
+        stats: &mut RenderStats,
+    ) {
+        use PushConstantUpdate::*;
+
+        let alias = match self.entity_renderers[viewmodel.model_id] {
+            EntityRenderer::Alias(ref alias) => alias,
+            _ => {
+                warn!("non-alias viewmodel");
+                return;
+            }
+        };
+
+        let viewmodel_orig = viewmodel.origin;
+        let gun_angles = viewmodel.angles;
+        // the scale stretches the model across and up, which are x and y after conversion
+        let viewmodel_mat = Matrix4::from_translation(Vector3::new(
+            -viewmodel_orig.y,
+            viewmodel_orig.z,
+            -viewmodel_orig.x,
+        )) * Matrix4::from_angle_y(gun_angles.yaw)
+            * Matrix4::from_angle_x(-gun_angles.pitch)
+            * Matrix4::from_angle_z(gun_angles.roll)
+            * Matrix4::from_nonuniform_scale(fov_scale, fov_scale, 1.0);
+
+        let Extent2d { width, height } = state.initial_pass_target().size();
+        pass.set_viewport(
+            0.0,
+            0.0,
+            width as f32,
+            height as f32,
+            0.0,
+            VIEWMODEL_DEPTH_RANGE,
+        );
+
+        pass.set_pipeline(state.alias_pipeline().pipeline());
+        AliasPipeline::set_push_constants(
+            pass,
+            Update(bump.alloc(alias::VertexPushConstants {
+                transform: camera.view_projection() * viewmodel_mat,
+                model_view: camera.view() * viewmodel_mat,
+            })),
+            Clear,
+            Update(bump.alloc(alias.shading(
+                self.light_point(viewmodel_orig, lightstyle_values),
+                gun_angles.yaw,
+                true,
+            ))),
+        );
+        alias.record_draw(state, pass, time, viewmodel.frame, 0, None, false, stats);
+
+        pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+    }
+
+    /// Returns the light level at a point from the world's lightmaps.
+    fn light_point(&self, point: Vector3<f32>, lightstyle_values: &[f32]) -> Vector3<f32> {
+        self.world_bsp.light_point(point, lightstyle_values)
+    }
+
+    /// Determines whether an entity's model falls entirely outside the viewing frustum.
+    fn cull_entity(&self, camera: &Camera, ent: &ClientEntity) -> bool {
+        // subtract 1 from index because world entity isn't counted
+        let (min, max) = self.entity_bounds[ent.model_id() - 1].world_box(ent.origin, ent.angles);
+        camera.cull_box(min, max)
+    }
+
+    /// Returns the center of an entity's model in world space.
+    ///
+    /// Brush models are placed relative to the world's origin rather than their own center, so the
+    /// entity's origin alone doesn't say where a brush model is.
+    fn entity_center(&self, ent: &ClientEntity) -> Vector3<f32> {
+        let (min, max) = self.entity_bounds[ent.model_id() - 1].world_box(ent.origin, ent.angles);
+        (min + max) / 2.0
     }
 
     fn renderer_for_entity(&self, ent: &ClientEntity) -> &EntityRenderer {
@@ -603,3 +1334,16 @@ impl WorldRenderer {
         Matrix4::from_translation(Vector3::new(-origin.y, origin.z, -origin.x)) * rotation
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gun_fov_scale() {
+        assert_eq!(gun_fov_scale(90.0, true), 1.0);
+        assert_eq!(gun_fov_scale(75.0, true), 1.0);
+        assert_eq!(gun_fov_scale(120.0, false), 1.0);
+        assert!((gun_fov_scale(120.0, true) - 3.0f32.sqrt()).abs() < 1e-5);
+    }
+}
@@ -9,6 +9,7 @@ use crate::{
         render::{
             create_texture,
             pipeline::{Pipeline, PushConstantUpdate},
+            stats::RenderStats,
             world::{Camera, WorldPipelineBase},
             Palette, TextureData,
         },
@@ -170,6 +171,7 @@ impl ParticlePipeline {
         bump: &'a Bump,
         camera: &Camera,
         particles: P,
+        stats: &mut RenderStats,
     ) where
         P: Iterator<Item = &'b Particle>,
     {
@@ -203,6 +205,7 @@ impl ParticlePipeline {
                 })),
             );
 
+            stats.record_draw(&(0..6));
             pass.draw(0..6, 0..1);
         }
     }
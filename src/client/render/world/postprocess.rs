@@ -1,27 +1,39 @@
-use std::{mem::size_of, num::NonZeroU64};
+use std::{
+    mem::size_of,
+    num::{NonZeroU32, NonZeroU64},
+};
 
 use crate::{
-    client::render::{pipeline::Pipeline, ui::quad::QuadPipeline, GraphicsState},
+    client::render::{pipeline::Pipeline, ui::quad::QuadPipeline, GraphicsState, Palette},
     common::util::any_as_bytes,
 };
 
+// the number of steps per channel in the table used to quantize to the palette
+const PALETTE_TABLE_SIZE: u32 = 32;
+
 #[repr(C, align(256))]
 #[derive(Clone, Copy, Debug)]
 pub struct PostProcessUniforms {
     pub color_shift: [f32; 4],
+    // nonzero if the image is quantized to the palette
+    pub quantize: u32,
 }
 
 pub struct PostProcessPipeline {
     pipeline: wgpu::RenderPipeline,
     bind_group_layouts: Vec<wgpu::BindGroupLayout>,
     uniform_buffer: wgpu::Buffer,
+    palette_table: wgpu::Texture,
+    palette_table_view: wgpu::TextureView,
 }
 
 impl PostProcessPipeline {
     pub fn new(
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
         compiler: &mut shaderc::Compiler,
         sample_count: u32,
+        palette: &Palette,
     ) -> PostProcessPipeline {
         let (pipeline, bind_group_layouts) =
             PostProcessPipeline::create(device, compiler, &[], sample_count);
@@ -31,27 +43,66 @@ impl PostProcessPipeline {
             contents: unsafe {
                 any_as_bytes(&PostProcessUniforms {
                     color_shift: [0.0; 4],
+                    quantize: 0,
                 })
             },
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         });
 
+        let size = wgpu::Extent3d {
+            width: PALETTE_TABLE_SIZE,
+            height: PALETTE_TABLE_SIZE,
+            depth_or_array_layers: PALETTE_TABLE_SIZE,
+        };
+        let palette_table = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("palette quantization table"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::SAMPLED,
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &palette_table,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &palette.quantization_table(PALETTE_TABLE_SIZE as usize),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(PALETTE_TABLE_SIZE * 4),
+                rows_per_image: NonZeroU32::new(PALETTE_TABLE_SIZE),
+            },
+            size,
+        );
+        let palette_table_view = palette_table.create_view(&Default::default());
+
         PostProcessPipeline {
             pipeline,
             bind_group_layouts,
             uniform_buffer,
+            palette_table,
+            palette_table_view,
         }
     }
 
+    /// Rebuilds the pipeline with a new sample count.
+    ///
+    /// The bind group layouts are recreated as well, since whether the attachments are bound as
+    /// multisampled textures depends on the sample count. Bind groups must be recreated after
+    /// calling this.
     pub fn rebuild(
         &mut self,
         device: &wgpu::Device,
         compiler: &mut shaderc::Compiler,
         sample_count: u32,
     ) {
-        let layout_refs: Vec<_> = self.bind_group_layouts.iter().collect();
-        let pipeline = PostProcessPipeline::recreate(device, compiler, &layout_refs, sample_count);
+        let (pipeline, bind_group_layouts) =
+            PostProcessPipeline::create(device, compiler, &[], sample_count);
         self.pipeline = pipeline;
+        self.bind_group_layouts = bind_group_layouts;
     }
 
     pub fn pipeline(&self) -> &wgpu::RenderPipeline {
@@ -65,6 +116,14 @@ impl PostProcessPipeline {
     pub fn uniform_buffer(&self) -> &wgpu::Buffer {
         &self.uniform_buffer
     }
+
+    pub fn palette_table(&self) -> &wgpu::Texture {
+        &self.palette_table
+    }
+
+    pub fn palette_table_view(&self) -> &wgpu::TextureView {
+        &self.palette_table_view
+    }
 }
 
 const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
@@ -100,6 +159,17 @@ const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
         },
         count: None,
     },
+    // palette quantization table
+    wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            view_dimension: wgpu::TextureViewDimension::D3,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            multisampled: false,
+        },
+        count: None,
+    },
 ];
 
 impl Pipeline for PostProcessPipeline {
@@ -184,6 +254,13 @@ impl PostProcessRenderer {
                             size: None,
                         }),
                     },
+                    // palette quantization table
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(
+                            state.postprocess_pipeline().palette_table_view(),
+                        ),
+                    },
                 ],
             })
     }
@@ -198,22 +275,34 @@ impl PostProcessRenderer {
         self.bind_group = Self::create_bind_group(state, color_buffer);
     }
 
-    pub fn update_uniform_buffers(&self, state: &GraphicsState, color_shift: [f32; 4]) {
+    pub fn update_uniform_buffers(
+        &self,
+        state: &GraphicsState,
+        color_shift: [f32; 4],
+        quantize: bool,
+    ) {
         // update color shift
         state
             .queue()
             .write_buffer(state.postprocess_pipeline().uniform_buffer(), 0, unsafe {
-                any_as_bytes(&PostProcessUniforms { color_shift })
+                any_as_bytes(&PostProcessUniforms {
+                    color_shift,
+                    quantize: quantize as u32,
+                })
             });
     }
 
+    /// Draws the 3D scene with `color_shift` blended over it.
+    ///
+    /// If `quantize` is set, the result is dithered and reduced to the colors of the palette.
     pub fn record_draw<'pass>(
         &'pass self,
         state: &'pass GraphicsState,
         pass: &mut wgpu::RenderPass<'pass>,
         color_shift: [f32; 4],
+        quantize: bool,
     ) {
-        self.update_uniform_buffers(state, color_shift);
+        self.update_uniform_buffers(state, color_shift, quantize);
         pass.set_pipeline(state.postprocess_pipeline().pipeline());
         pass.set_vertex_buffer(0, state.quad_pipeline().vertex_buffer().slice(..));
         pass.set_bind_group(0, &self.bind_group, &[]);
@@ -0,0 +1,126 @@
+//! Blob shadows for alias models (`r_shadows`).
+//!
+//! Like GLQuake, each model's current keyframe is flattened onto the floor beneath it, slanted
+//! along the model's shade vector, and drawn as a translucent dark pass over the world. Shadows
+//! only darken the diffuse attachment, so they don't disturb the lighting.
+
+use crate::client::render::{
+    pipeline::Pipeline,
+    world::{alias::AliasPipeline, WorldPipelineBase},
+};
+
+use cgmath::{Matrix4, Vector4};
+
+/// The opacity of a shadow.
+const SHADOW_ALPHA: f32 = 0.5;
+
+pub struct ShadowPipeline {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl ShadowPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        sample_count: u32,
+    ) -> ShadowPipeline {
+        let pipeline = ShadowPipeline::recreate(device, compiler, &[], sample_count);
+        ShadowPipeline { pipeline }
+    }
+
+    pub fn rebuild(
+        &mut self,
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        sample_count: u32,
+    ) {
+        self.pipeline = ShadowPipeline::recreate(device, compiler, &[], sample_count);
+    }
+
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct VertexPushConstants {
+    /// The transform from the model's space, rotated only by its yaw, to clip space.
+    pub transform: Matrix4<f32>,
+    /// The model's shade vector in x and y, and the model's height above the floor in z. The last
+    /// component is unused.
+    pub shadow: Vector4<f32>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct FragmentPushConstants {
+    pub alpha: f32,
+}
+
+impl FragmentPushConstants {
+    pub fn new() -> FragmentPushConstants {
+        FragmentPushConstants {
+            alpha: SHADOW_ALPHA,
+        }
+    }
+}
+
+impl Pipeline for ShadowPipeline {
+    type VertexPushConstants = VertexPushConstants;
+    type SharedPushConstants = ();
+    type FragmentPushConstants = FragmentPushConstants;
+
+    fn name() -> &'static str {
+        "shadow"
+    }
+
+    fn vertex_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/shadow.vert"))
+    }
+
+    fn fragment_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/shadow.frag"))
+    }
+
+    fn bind_group_layout_descriptors() -> Vec<wgpu::BindGroupLayoutDescriptor<'static>> {
+        Vec::new()
+    }
+
+    fn primitive_state() -> wgpu::PrimitiveState {
+        WorldPipelineBase::primitive_state()
+    }
+
+    // only the diffuse attachment is darkened, keeping its alpha. the normal and light
+    // attachments are left untouched.
+    fn color_target_states() -> Vec<wgpu::ColorTargetState> {
+        let mut targets = WorldPipelineBase::color_target_states();
+        targets[0].blend = Some(wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        });
+        for target in targets[1..].iter_mut() {
+            target.write_mask = wgpu::ColorWrite::empty();
+        }
+        targets
+    }
+
+    fn depth_stencil_state() -> Option<wgpu::DepthStencilState> {
+        let mut desc = WorldPipelineBase::depth_stencil_state().unwrap();
+        desc.depth_write_enabled = false;
+        Some(desc)
+    }
+
+    // shadows are drawn from the alias models' vertex buffers
+    fn vertex_buffer_layouts() -> Vec<wgpu::VertexBufferLayout<'static>> {
+        AliasPipeline::vertex_buffer_layouts()
+    }
+}
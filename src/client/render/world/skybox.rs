@@ -0,0 +1,354 @@
+//! Six-sided skyboxes.
+//!
+//! A skybox replaces the scrolling sky texture with six images loaded from
+//! `env/<name>{rt,bk,lf,ft,up,dn}.tga`. The box is drawn through the world's sky surfaces, so
+//! anything behind them is hidden just as it would be with the regular sky.
+//!
+//! If the device supports block-compressed textures, a set of pre-compressed `.dds` images is
+//! preferred over the `.tga` images.
+
+use std::borrow::Cow;
+
+use crate::{
+    client::render::{
+        world::{
+            brush::{self, BrushPipeline},
+            WorldPipelineBase,
+        },
+        DiffuseData, GraphicsState, Pipeline, TextureData, MAX_TEXTURE_DIMENSION,
+    },
+    common::{
+        dds::{Dds, DdsError},
+        tga::{Tga, TgaError},
+    },
+};
+
+use thiserror::Error;
+
+/// Suffixes of the six skybox images, in texture layer order.
+pub const FACE_SUFFIXES: [&str; 6] = ["rt", "bk", "lf", "ft", "up", "dn"];
+
+// FitzQuake and QuakeSpirit look in gfx/env, other engines in env
+const SEARCH_DIRS: [&str; 2] = ["gfx/env", "env"];
+
+#[derive(Error, Debug)]
+pub enum SkyboxError {
+    #[error("Couldn't find skybox image {0}")]
+    NotFound(String),
+    #[error("Couldn't load skybox image {path}: {source}")]
+    Image { path: String, source: TgaError },
+    #[error("Couldn't load compressed skybox image {path}: {source}")]
+    CompressedImage { path: String, source: DdsError },
+    #[error("Skybox image {0} is compressed differently from the others")]
+    FormatMismatch(String),
+    #[error(
+        "Skybox image {path} is {width}x{height}, expected {expected_width}x{expected_height}"
+    )]
+    SizeMismatch {
+        path: String,
+        width: u32,
+        height: u32,
+        expected_width: u32,
+        expected_height: u32,
+    },
+}
+
+pub struct SkyboxPipeline {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+}
+
+impl SkyboxPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        world_bind_group_layouts: &[wgpu::BindGroupLayout],
+        sample_count: u32,
+    ) -> SkyboxPipeline {
+        let (pipeline, bind_group_layouts) =
+            SkyboxPipeline::create(device, compiler, world_bind_group_layouts, sample_count);
+
+        SkyboxPipeline {
+            pipeline,
+            bind_group_layouts,
+        }
+    }
+
+    pub fn rebuild(
+        &mut self,
+        device: &wgpu::Device,
+        compiler: &mut shaderc::Compiler,
+        world_bind_group_layouts: &[wgpu::BindGroupLayout],
+        sample_count: u32,
+    ) {
+        let layout_refs: Vec<_> = world_bind_group_layouts
+            .iter()
+            .chain(self.bind_group_layouts.iter())
+            .collect();
+        self.pipeline = SkyboxPipeline::recreate(device, compiler, &layout_refs, sample_count);
+    }
+
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    pub fn bind_group_layouts(&self) -> &[wgpu::BindGroupLayout] {
+        &self.bind_group_layouts
+    }
+}
+
+impl Pipeline for SkyboxPipeline {
+    type VertexPushConstants = brush::VertexPushConstants;
+    type SharedPushConstants = ();
+    type FragmentPushConstants = ();
+
+    fn name() -> &'static str {
+        "skybox"
+    }
+
+    fn vertex_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/skybox.vert"))
+    }
+
+    fn fragment_shader() -> &'static str {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/skybox.frag"))
+    }
+
+    // NOTE: if any of the binding indices are changed, they must also be changed in
+    // the corresponding shaders and the BindGroupLayout generation functions.
+    fn bind_group_layout_descriptors() -> Vec<wgpu::BindGroupLayoutDescriptor<'static>> {
+        vec![
+            // group 2: updated when the skybox changes
+            wgpu::BindGroupLayoutDescriptor {
+                label: Some("skybox bind group"),
+                entries: &[
+                    // one layer per face
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            },
+        ]
+    }
+
+    fn primitive_state() -> wgpu::PrimitiveState {
+        WorldPipelineBase::primitive_state()
+    }
+
+    fn color_target_states() -> Vec<wgpu::ColorTargetState> {
+        WorldPipelineBase::color_target_states()
+    }
+
+    fn depth_stencil_state() -> Option<wgpu::DepthStencilState> {
+        WorldPipelineBase::depth_stencil_state()
+    }
+
+    // sky surfaces are drawn from the brush vertex buffer
+    fn vertex_buffer_layouts() -> Vec<wgpu::VertexBufferLayout<'static>> {
+        BrushPipeline::vertex_buffer_layouts()
+    }
+}
+
+/// A loaded skybox.
+pub struct Skybox {
+    name: String,
+    _texture: wgpu::Texture,
+    _view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Skybox {
+    /// Loads the skybox with the given name.
+    ///
+    /// All six images must be present and have the same dimensions.
+    pub fn load(state: &GraphicsState, name: &str) -> Result<Skybox, SkyboxError> {
+        let label = format!("skybox {}", name);
+
+        // compressed images take a quarter of the memory or less
+        let compressed = if state.supports_compressed_textures() {
+            match load_faces(state, name, ImageKind::Dds) {
+                Err(SkyboxError::NotFound(_)) => None,
+                faces => Some(faces?),
+            }
+        } else {
+            None
+        };
+
+        let texture = match compressed {
+            Some(faces) => {
+                let first = match faces[0].1 {
+                    SkyboxImage::Dds(ref dds) => dds,
+                    SkyboxImage::Tga(_) => unreachable!(),
+                };
+
+                let mut layers = Vec::with_capacity(faces.len());
+                for (path, image) in faces.iter() {
+                    match image {
+                        SkyboxImage::Dds(dds) if dds.format() == first.format() => {
+                            layers.push(dds.data())
+                        }
+                        _ => return Err(SkyboxError::FormatMismatch(path.clone())),
+                    }
+                }
+
+                state.create_compressed_texture_array(
+                    Some(&label),
+                    first.width(),
+                    first.height(),
+                    first.format(),
+                    &layers,
+                )
+            }
+
+            None => {
+                let faces = load_faces(state, name, ImageKind::Tga)?;
+                let (width, height) = faces[0].1.dimensions();
+                let layers: Vec<_> = faces
+                    .iter()
+                    .map(|(_, image)| match image {
+                        SkyboxImage::Tga(tga) => TextureData::Diffuse(DiffuseData {
+                            rgba: Cow::Borrowed(tga.rgba()),
+                        }),
+                        SkyboxImage::Dds(_) => unreachable!(),
+                    })
+                    .collect();
+
+                state.create_texture_array(Some(&label), width, height, &layers)
+            }
+        };
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let bind_group = state
+            .device()
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("skybox bind group"),
+                layout: &state.skybox_pipeline().bind_group_layouts()[0],
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                }],
+            });
+
+        Ok(Skybox {
+            name: name.to_owned(),
+            _texture: texture,
+            _view: view,
+            bind_group,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ImageKind {
+    Tga,
+    Dds,
+}
+
+impl ImageKind {
+    fn extension(&self) -> &'static str {
+        match self {
+            ImageKind::Tga => "tga",
+            ImageKind::Dds => "dds",
+        }
+    }
+}
+
+enum SkyboxImage {
+    Tga(Tga),
+    Dds(Dds),
+}
+
+impl SkyboxImage {
+    fn dimensions(&self) -> (u32, u32) {
+        match self {
+            SkyboxImage::Tga(tga) => (tga.width(), tga.height()),
+            SkyboxImage::Dds(dds) => (dds.width(), dds.height()),
+        }
+    }
+}
+
+/// Loads all six faces of the named skybox from images of the given kind.
+fn load_faces(
+    state: &GraphicsState,
+    name: &str,
+    kind: ImageKind,
+) -> Result<Vec<(String, SkyboxImage)>, SkyboxError> {
+    let mut faces: Vec<(String, SkyboxImage)> = Vec::with_capacity(FACE_SUFFIXES.len());
+    for suffix in FACE_SUFFIXES.iter() {
+        let (path, image) = load_face(state, name, suffix, kind)?;
+
+        if let Some((_, first)) = faces.first() {
+            let (width, height) = image.dimensions();
+            let (expected_width, expected_height) = first.dimensions();
+            if (width, height) != (expected_width, expected_height) {
+                return Err(SkyboxError::SizeMismatch {
+                    path,
+                    width,
+                    height,
+                    expected_width,
+                    expected_height,
+                });
+            }
+        }
+
+        faces.push((path, image));
+    }
+
+    Ok(faces)
+}
+
+fn load_face(
+    state: &GraphicsState,
+    name: &str,
+    suffix: &str,
+    kind: ImageKind,
+) -> Result<(String, SkyboxImage), SkyboxError> {
+    for dir in SEARCH_DIRS.iter() {
+        let path = format!("{}/{}{}.{}", dir, name, suffix, kind.extension());
+        if let Ok(file) = state.vfs().open(&path) {
+            let image = match kind {
+                ImageKind::Tga => Tga::load(file, MAX_TEXTURE_DIMENSION)
+                    .map(SkyboxImage::Tga)
+                    .map_err(|source| SkyboxError::Image {
+                        path: path.clone(),
+                        source,
+                    }),
+                ImageKind::Dds => Dds::load(file, MAX_TEXTURE_DIMENSION)
+                    .map(SkyboxImage::Dds)
+                    .map_err(|source| SkyboxError::CompressedImage {
+                        path: path.clone(),
+                        source,
+                    }),
+            }?;
+
+            return Ok((path, image));
+        }
+    }
+
+    Err(SkyboxError::NotFound(format!(
+        "{}/{}{}.{}",
+        SEARCH_DIRS[SEARCH_DIRS.len() - 1],
+        name,
+        suffix,
+        kind.extension()
+    )))
+}
@@ -2,7 +2,8 @@ use std::mem::size_of;
 
 use crate::{
     client::render::{
-        world::{BindGroupLayoutId, WorldPipelineBase},
+        stats::RenderStats,
+        world::{Additive, BindGroupLayoutId, Translucent, WorldPipelineBase},
         GraphicsState, Pipeline, TextureData,
     },
     common::{
@@ -13,8 +14,34 @@ use crate::{
 
 use chrono::Duration;
 
+/// Sprites which give off light, and so are drawn additively. Sprite files have no flags to mark
+/// these, so they're picked out by name.
+const ADDITIVE_SPRITES: [&str; 2] = ["progs/s_explod.spr", "progs/s_light.spr"];
+
+/// How a sprite is combined with what's behind it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpriteBlend {
+    /// Drawn opaque, with its transparent texels discarded.
+    AlphaTest,
+    /// Added to what's behind it.
+    Additive,
+}
+
+impl SpriteBlend {
+    /// Returns the blend mode for the sprite model with the given name.
+    pub fn for_model(name: &str) -> SpriteBlend {
+        if ADDITIVE_SPRITES.contains(&name) {
+            SpriteBlend::Additive
+        } else {
+            SpriteBlend::AlphaTest
+        }
+    }
+}
+
 pub struct SpritePipeline {
     pipeline: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
+    additive_pipeline: wgpu::RenderPipeline,
     bind_group_layouts: Vec<wgpu::BindGroupLayout>,
     vertex_buffer: wgpu::Buffer,
 }
@@ -28,6 +55,14 @@ impl SpritePipeline {
     ) -> SpritePipeline {
         let (pipeline, bind_group_layouts) =
             SpritePipeline::create(device, compiler, world_bind_group_layouts, sample_count);
+        let layout_refs: Vec<_> = world_bind_group_layouts
+            .iter()
+            .chain(bind_group_layouts.iter())
+            .collect();
+        let translucent_pipeline =
+            Translucent::<SpritePipeline>::recreate(device, compiler, &layout_refs, sample_count);
+        let additive_pipeline =
+            Additive::<SpritePipeline>::recreate(device, compiler, &layout_refs, sample_count);
 
         use wgpu::util::DeviceExt as _;
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -38,6 +73,8 @@ impl SpritePipeline {
 
         SpritePipeline {
             pipeline,
+            translucent_pipeline,
+            additive_pipeline,
             bind_group_layouts,
             vertex_buffer,
         }
@@ -55,12 +92,26 @@ impl SpritePipeline {
             .chain(self.bind_group_layouts.iter())
             .collect();
         self.pipeline = SpritePipeline::recreate(device, compiler, &layout_refs, sample_count);
+        self.translucent_pipeline =
+            Translucent::<SpritePipeline>::recreate(device, compiler, &layout_refs, sample_count);
+        self.additive_pipeline =
+            Additive::<SpritePipeline>::recreate(device, compiler, &layout_refs, sample_count);
     }
 
     pub fn pipeline(&self) -> &wgpu::RenderPipeline {
         &self.pipeline
     }
 
+    /// Returns the pipeline for entities drawn with an alpha below 1.
+    pub fn translucent_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.translucent_pipeline
+    }
+
+    /// Returns the pipeline for sprites drawn with [`SpriteBlend::Additive`].
+    pub fn additive_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.additive_pipeline
+    }
+
     pub fn bind_group_layouts(&self) -> &[wgpu::BindGroupLayout] {
         &self.bind_group_layouts
     }
@@ -301,11 +352,12 @@ impl Frame {
 
 pub struct SpriteRenderer {
     kind: SpriteKind,
+    blend: SpriteBlend,
     frames: Vec<Frame>,
 }
 
 impl SpriteRenderer {
-    pub fn new(state: &GraphicsState, sprite: &SpriteModel) -> SpriteRenderer {
+    pub fn new(state: &GraphicsState, name: &str, sprite: &SpriteModel) -> SpriteRenderer {
         let frames = sprite
             .frames()
             .iter()
@@ -314,28 +366,46 @@ impl SpriteRenderer {
 
         SpriteRenderer {
             kind: sprite.kind(),
+            blend: SpriteBlend::for_model(name),
             frames,
         }
     }
 
+    /// Records the draw commands for a frame of this sprite.
+    ///
+    /// If `translucent` is set, the sprite is blended by the render pass's blend constant. Additive
+    /// sprites are always scaled by the blend constant, so it must be set before drawing them.
     pub fn record_draw<'a>(
         &'a self,
         state: &'a GraphicsState,
         pass: &mut wgpu::RenderPass<'a>,
         frame_id: usize,
         time: Duration,
+        translucent: bool,
+        stats: &mut RenderStats,
     ) {
-        pass.set_pipeline(state.sprite_pipeline().pipeline());
+        let pipeline = state.sprite_pipeline();
+        pass.set_pipeline(match (self.blend, translucent) {
+            (SpriteBlend::Additive, _) => pipeline.additive_pipeline(),
+            (SpriteBlend::AlphaTest, true) => pipeline.translucent_pipeline(),
+            (SpriteBlend::AlphaTest, false) => pipeline.pipeline(),
+        });
         pass.set_vertex_buffer(0, state.sprite_pipeline().vertex_buffer().slice(..));
         pass.set_bind_group(
             BindGroupLayoutId::PerTexture as u32,
             self.frames[frame_id].animate(time),
             &[],
         );
-        pass.draw(0..VERTICES.len() as u32, 0..1);
+        let vertices = 0..VERTICES.len() as u32;
+        stats.record_draw(&vertices);
+        pass.draw(vertices, 0..1);
     }
 
     pub fn kind(&self) -> SpriteKind {
         self.kind
     }
+
+    pub fn blend(&self) -> SpriteBlend {
+        self.blend
+    }
 }
@@ -0,0 +1,136 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Pictures displayed by the server with `svc_showlmp`.
+//!
+//! This is a Nehahra extension which mods use to draw custom HUD elements. Each picture occupies
+//! a named slot until it is hidden with `svc_hidelmp` or replaced by another picture in the same
+//! slot.
+
+/// The maximum number of pictures that can be displayed at once.
+///
+/// This is the limit used by Nehahra.
+pub const MAX_SHOW_LMPS: usize = 16;
+
+/// A picture displayed at a fixed position on the screen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShowLmp {
+    /// The path of the picture, e.g. `gfx/logo.lmp`.
+    pub name: String,
+
+    /// The position of the top-left corner of the picture in 320x200 screen coordinates.
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Default)]
+pub struct ShowLmps {
+    // in the order the slots were first used, which is also the draw order
+    lmps: Vec<(String, ShowLmp)>,
+}
+
+impl ShowLmps {
+    pub fn new() -> ShowLmps {
+        ShowLmps { lmps: Vec::new() }
+    }
+
+    /// Displays a picture in the given slot, replacing any picture already there.
+    pub fn show<S>(&mut self, slot: S, lmp: ShowLmp)
+    where
+        S: AsRef<str>,
+    {
+        let slot = slot.as_ref();
+        if let Some((_, existing)) = self.lmps.iter_mut().find(|(s, _)| s == slot) {
+            *existing = lmp;
+            return;
+        }
+
+        if self.lmps.len() >= MAX_SHOW_LMPS {
+            warn!(
+                "Too many pictures, ignoring showlmp {} ({})",
+                slot, lmp.name
+            );
+            return;
+        }
+
+        self.lmps.push((slot.to_owned(), lmp));
+    }
+
+    /// Removes the picture in the given slot, if there is one.
+    pub fn hide<S>(&mut self, slot: S)
+    where
+        S: AsRef<str>,
+    {
+        let slot = slot.as_ref();
+        self.lmps.retain(|(s, _)| s != slot);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lmps.is_empty()
+    }
+
+    /// Returns an iterator over the displayed pictures in draw order.
+    pub fn iter(&self) -> impl Iterator<Item = &ShowLmp> {
+        self.lmps.iter().map(|(_, lmp)| lmp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lmp(name: &str) -> ShowLmp {
+        ShowLmp {
+            name: name.to_owned(),
+            x: 0,
+            y: 0,
+        }
+    }
+
+    #[test]
+    fn test_show_replaces_slot() {
+        let mut lmps = ShowLmps::new();
+        lmps.show("a", lmp("gfx/a.lmp"));
+        lmps.show("b", lmp("gfx/b.lmp"));
+        lmps.show("a", lmp("gfx/c.lmp"));
+
+        let names = lmps.iter().map(|l| l.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["gfx/c.lmp", "gfx/b.lmp"]);
+    }
+
+    #[test]
+    fn test_hide() {
+        let mut lmps = ShowLmps::new();
+        lmps.show("a", lmp("gfx/a.lmp"));
+        lmps.hide("b");
+        assert!(!lmps.is_empty());
+        lmps.hide("a");
+        assert!(lmps.is_empty());
+    }
+
+    #[test]
+    fn test_slot_limit() {
+        let mut lmps = ShowLmps::new();
+        for i in 0..MAX_SHOW_LMPS + 1 {
+            lmps.show(i.to_string(), lmp("gfx/a.lmp"));
+        }
+        assert_eq!(lmps.iter().count(), MAX_SHOW_LMPS);
+    }
+}
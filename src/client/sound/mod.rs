@@ -23,10 +23,15 @@ pub use music::MusicPlayer;
 
 use std::{
     cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
     io::{self, BufReader, Cursor, Read},
+    sync::mpsc::{self, Receiver, Sender},
 };
 
-use crate::common::vfs::{Vfs, VfsError};
+use crate::common::{
+    host,
+    vfs::{Vfs, VfsError},
+};
 
 use cgmath::{InnerSpace, Vector3};
 use rodio::{
@@ -59,6 +64,9 @@ pub struct Listener {
     origin: Cell<Vector3<f32>>,
     left_ear: Cell<Vector3<f32>>,
     right_ear: Cell<Vector3<f32>>,
+
+    // scales every sound heard by this listener
+    volume: Cell<f32>,
 }
 
 impl Listener {
@@ -67,6 +75,7 @@ impl Listener {
             origin: Cell::new(Vector3::new(0.0, 0.0, 0.0)),
             left_ear: Cell::new(Vector3::new(0.0, 0.0, 0.0)),
             right_ear: Cell::new(Vector3::new(0.0, 0.0, 0.0)),
+            volume: Cell::new(1.0),
         }
     }
 
@@ -94,6 +103,11 @@ impl Listener {
         self.right_ear.set(new_origin);
     }
 
+    /// Sets the master volume applied to every sound, e.g. from the `volume` cvar.
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.set(volume.max(0.0).min(1.0));
+    }
+
     pub fn attenuate(
         &self,
         emitter_origin: Vector3<f32>,
@@ -104,7 +118,7 @@ impl Listener {
             * attenuation
             * DISTANCE_ATTENUATION_FACTOR;
         let volume = ((1.0 - decay) * base_volume).max(0.0);
-        volume
+        volume * self.volume.get()
     }
 }
 
@@ -116,20 +130,294 @@ impl AudioSource {
     where
         S: AsRef<str>,
     {
-        let name = name.as_ref();
-        let full_path = "sound/".to_owned() + name;
-        let mut file = vfs.open(&full_path)?;
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
+        AudioSource::from_data(read_sound_file(vfs, name.as_ref())?)
+    }
+
+    /// Decodes an audio source from the contents of a sound file.
+    pub fn from_data(data: Vec<u8>) -> Result<AudioSource, SoundError> {
+        let src = Decoder::new(Cursor::new(data))?
+            .convert_samples()
+            .buffered();
+
+        Ok(AudioSource(src))
+    }
 
+    /// Decodes all of a sound file's samples up front rather than during playback.
+    fn decode(data: Vec<u8>) -> Result<AudioSource, rodio::decoder::DecoderError> {
         let src = Decoder::new(Cursor::new(data))?
             .convert_samples()
             .buffered();
 
+        // clones share decoded samples, so running through one decodes them for all of them
+        src.clone().for_each(drop);
+
         Ok(AudioSource(src))
     }
 }
 
+/// Reads a sound file, relative to the `sound` directory.
+fn read_sound_file(vfs: &Vfs, name: &str) -> Result<Vec<u8>, SoundError> {
+    let mut file = vfs.open(format!("sound/{}", name))?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// A thread which decodes prefetched sounds so they don't stall the frame.
+struct SoundDecoder {
+    requests: Sender<(usize, Vec<u8>)>,
+    results: Receiver<(usize, Result<AudioSource, rodio::decoder::DecoderError>)>,
+}
+
+impl SoundDecoder {
+    fn spawn() -> io::Result<SoundDecoder> {
+        let (requests, request_rx) = mpsc::channel::<(usize, Vec<u8>)>();
+        let (result_tx, results) = mpsc::channel();
+
+        host::spawn_named("sound", move || {
+            for (id, data) in request_rx {
+                // the cache has been dropped
+                if result_tx.send((id, AudioSource::decode(data))).is_err() {
+                    return;
+                }
+            }
+        })?;
+
+        Ok(SoundDecoder { requests, results })
+    }
+}
+
+struct SoundCacheEntry {
+    src: AudioSource,
+    // size of the sound file in bytes, used as an estimate of memory usage
+    size: usize,
+    last_used: u64,
+}
+
+/// Lazily loaded sounds from the server's precache list.
+///
+/// Rather than loading every sound during sign-on, sounds are loaded when they are first played.
+/// Sounds which haven't been used yet are read a few at a time each frame by
+/// [`SoundCache::prefetch`] and decoded on a separate thread, and the least recently used sounds
+/// are evicted when the total size of loaded sounds exceeds the budget.
+pub struct SoundCache {
+    names: Vec<String>,
+    entries: Vec<Option<SoundCacheEntry>>,
+    prefetch_queue: VecDeque<usize>,
+
+    // None if the decoder thread couldn't be started, in which case nothing is prefetched
+    decoder: Option<SoundDecoder>,
+    // sizes of the sounds being decoded, by id
+    pending: HashMap<usize, usize>,
+
+    // played in place of sounds which fail to load
+    silence: Option<AudioSource>,
+
+    budget: usize,
+    size: usize,
+    use_counter: u64,
+}
+
+impl SoundCache {
+    /// Creates an empty cache.
+    pub fn new() -> SoundCache {
+        SoundCache {
+            names: Vec::new(),
+            entries: Vec::new(),
+            prefetch_queue: VecDeque::new(),
+            decoder: None,
+            pending: HashMap::new(),
+            silence: None,
+            budget: usize::MAX,
+            size: 0,
+            use_counter: 0,
+        }
+    }
+
+    /// Creates a cache for the given precache list without loading any sounds.
+    ///
+    /// `silence` is substituted for any sounds which fail to load.
+    pub fn with_names(names: Vec<String>, silence: AudioSource) -> SoundCache {
+        let mut entries = Vec::with_capacity(names.len());
+        entries.resize_with(names.len(), || None);
+
+        let decoder = match SoundDecoder::spawn() {
+            Ok(d) => Some(d),
+            Err(e) => {
+                warn!(
+                    "Couldn't start sound decoder, sounds will load when played: {}",
+                    e
+                );
+                None
+            }
+        };
+
+        SoundCache {
+            prefetch_queue: (0..names.len()).collect(),
+            names,
+            entries,
+            decoder,
+            silence: Some(silence),
+            ..SoundCache::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Sets the memory budget in bytes, evicting sounds if necessary.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict(0);
+    }
+
+    /// Returns the sound with the given id, loading it if necessary.
+    pub fn get(&mut self, vfs: &Vfs, id: usize) -> Option<AudioSource> {
+        if id >= self.names.len() {
+            return None;
+        }
+
+        self.receive();
+
+        // rather than waiting on the decoder, sounds which are still being decoded are loaded
+        // again here
+        if self.entries[id].is_none() {
+            self.load(vfs, id);
+        }
+
+        self.use_counter += 1;
+        let entry = self.entries[id].as_mut()?;
+        entry.last_used = self.use_counter;
+        Some(entry.src.clone())
+    }
+
+    /// Sends up to `count` sounds which haven't been used yet to the decoder thread, as long as
+    /// they fit in the budget, and adds any sounds it has finished decoding.
+    pub fn prefetch(&mut self, vfs: &Vfs, count: usize) {
+        self.receive();
+
+        let requests = match self.decoder {
+            Some(ref d) => d.requests.clone(),
+            None => return,
+        };
+
+        let mut queued = 0;
+        while queued < count && self.size + self.pending.values().sum::<usize>() < self.budget {
+            let id = match self.prefetch_queue.pop_front() {
+                Some(id) => id,
+                None => return,
+            };
+
+            if self.entries[id].is_some() || self.pending.contains_key(&id) {
+                continue;
+            }
+
+            debug!("Prefetching sound {}: {}", id, self.names[id]);
+            match read_sound_file(vfs, &self.names[id]) {
+                Ok(data) => {
+                    self.pending.insert(id, data.len());
+                    if requests.send((id, data)).is_err() {
+                        warn!("Sound decoder stopped, sounds will load when played");
+                        self.decoder = None;
+                        self.pending.clear();
+                        return;
+                    }
+                }
+
+                Err(e) => self.load_failed(id, e),
+            }
+
+            queued += 1;
+        }
+    }
+
+    /// Adds the sounds the decoder thread has finished with.
+    fn receive(&mut self) {
+        let results = match self.decoder {
+            Some(ref d) => d.results.try_iter().collect::<Vec<_>>(),
+            None => return,
+        };
+
+        for (id, result) in results {
+            let size = match self.pending.remove(&id) {
+                Some(size) => size,
+                None => continue,
+            };
+
+            // the sound may have been played, and so loaded, while it was being decoded
+            if self.entries[id].is_some() {
+                continue;
+            }
+
+            match result {
+                Ok(src) => self.insert(id, src, size),
+                Err(e) => self.load_failed(id, e.into()),
+            }
+        }
+    }
+
+    fn load(&mut self, vfs: &Vfs, id: usize) {
+        debug!("Loading sound {}: {}", id, self.names[id]);
+
+        let loaded = read_sound_file(vfs, &self.names[id]).and_then(|data| {
+            let size = data.len();
+            Ok((AudioSource::from_data(data)?, size))
+        });
+
+        match loaded {
+            Ok((src, size)) => self.insert(id, src, size),
+            Err(e) => self.load_failed(id, e),
+        }
+    }
+
+    /// Substitutes silence for a sound which couldn't be loaded.
+    fn load_failed(&mut self, id: usize, e: SoundError) {
+        // missing sounds are expected when running on the fallback assets
+        warn!("Couldn't load sound {}: {}", self.names[id], e);
+        if let Some(silence) = self.silence.clone() {
+            self.insert(id, silence, 0);
+        }
+    }
+
+    fn insert(&mut self, id: usize, src: AudioSource, size: usize) {
+        self.evict(size);
+        self.size += size;
+        self.entries[id] = Some(SoundCacheEntry {
+            src,
+            size,
+            last_used: self.use_counter,
+        });
+    }
+
+    /// Evicts least recently used sounds until `incoming` more bytes fit in the budget.
+    fn evict(&mut self, incoming: usize) {
+        while self.size + incoming > self.budget {
+            let lru = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter_map(|(id, e)| e.as_ref().filter(|e| e.size > 0).map(|e| (id, e.last_used)))
+                .min_by_key(|(_, last_used)| *last_used);
+
+            match lru {
+                Some((id, _)) => {
+                    let entry = self.entries[id].take().unwrap();
+                    debug!("Evicting sound {}", self.names[id]);
+                    self.size -= entry.size;
+                }
+
+                // nothing left to evict
+                None => return,
+            }
+        }
+    }
+}
+
 pub struct StaticSound {
     origin: Vector3<f32>,
     sink: RefCell<Sink>,
@@ -266,13 +554,14 @@ impl EntityChannel {
 }
 
 pub struct EntityMixer {
-    stream: OutputStreamHandle,
+    stream: Option<OutputStreamHandle>,
     // TODO: replace with an array once const type parameters are implemented
     channels: Box<[Option<EntityChannel>]>,
 }
 
 impl EntityMixer {
-    pub fn new(stream: OutputStreamHandle) -> EntityMixer {
+    /// Creates a mixer which plays sounds on `stream`, or plays nothing if there is no stream.
+    pub fn new(stream: Option<OutputStreamHandle>) -> EntityMixer {
         let mut channel_vec = Vec::new();
 
         for _ in 0..MAX_ENTITY_CHANNELS {
@@ -336,8 +625,13 @@ impl EntityMixer {
         origin: Vector3<f32>,
         listener: &Listener,
     ) {
+        let stream = match self.stream {
+            Some(ref s) => s.clone(),
+            None => return,
+        };
+
         let chan_id = self.find_free_channel(ent_id, ent_channel);
-        let new_channel = Channel::new(self.stream.clone());
+        let new_channel = Channel::new(stream);
 
         new_channel.play(
             src.clone(),
@@ -358,7 +652,114 @@ impl EntityMixer {
         self.channels.iter().filter_map(|e| e.as_ref())
     }
 
-    pub fn stream(&self) -> OutputStreamHandle {
+    pub fn stream(&self) -> Option<OutputStreamHandle> {
         self.stream.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration as StdDuration, Instant};
+
+    /// Returns a mono 16-bit WAV file holding `samples` samples of silence.
+    fn wav(samples: usize) -> Vec<u8> {
+        let data_len = samples as u32 * 2;
+        let mut data = Vec::new();
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&(36 + data_len).to_le_bytes());
+        data.extend_from_slice(b"WAVEfmt ");
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        data.extend_from_slice(&1u16.to_le_bytes()); // mono
+        data.extend_from_slice(&11025u32.to_le_bytes());
+        data.extend_from_slice(&22050u32.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&16u16.to_le_bytes());
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&data_len.to_le_bytes());
+        data.resize(data.len() + data_len as usize, 0);
+        data
+    }
+
+    const SOUND_SIZE: usize = 44 + 2 * 100;
+
+    fn cache(names: &[&str]) -> (Vfs, SoundCache) {
+        let mut vfs = Vfs::new();
+        for name in names {
+            vfs.add_memory(format!("sound/{}", name), wav(100));
+        }
+
+        let names = names.iter().map(|n| n.to_string()).collect();
+        let silence = AudioSource::from_data(wav(1)).unwrap();
+        (vfs, SoundCache::with_names(names, silence))
+    }
+
+    fn loaded(cache: &SoundCache) -> Vec<bool> {
+        cache.entries.iter().map(Option::is_some).collect()
+    }
+
+    #[test]
+    fn test_sound_cache_evicts_least_recently_used() {
+        let (vfs, mut cache) = cache(&["a.wav", "b.wav", "c.wav"]);
+        cache.set_budget(2 * SOUND_SIZE);
+
+        cache.get(&vfs, 0).unwrap();
+        cache.get(&vfs, 1).unwrap();
+        cache.get(&vfs, 0).unwrap();
+        cache.get(&vfs, 2).unwrap();
+
+        assert_eq!(loaded(&cache), vec![true, false, true]);
+        assert_eq!(cache.size, 2 * SOUND_SIZE);
+    }
+
+    #[test]
+    fn test_sound_cache_set_budget_evicts() {
+        let (vfs, mut cache) = cache(&["a.wav", "b.wav", "c.wav"]);
+        for id in 0..3 {
+            cache.get(&vfs, id).unwrap();
+        }
+        assert_eq!(cache.size, 3 * SOUND_SIZE);
+
+        // shrinking snd_cachesize evicts the least recently used sounds
+        cache.set_budget(SOUND_SIZE);
+        assert_eq!(loaded(&cache), vec![false, false, true]);
+        cache.set_budget(0);
+        assert_eq!(loaded(&cache), vec![false, false, false]);
+        assert_eq!(cache.size, 0);
+
+        // evicted sounds are loaded again when played
+        assert!(cache.get(&vfs, 0).is_some());
+    }
+
+    #[test]
+    fn test_sound_cache_missing_sound_is_silent() {
+        let (vfs, mut cache) = cache(&["a.wav"]);
+        cache.names.push("missing.wav".to_owned());
+        cache.entries.push(None);
+
+        assert!(cache.get(&vfs, 1).is_some());
+
+        // silence doesn't count against the budget
+        assert_eq!(cache.size, 0);
+    }
+
+    #[test]
+    fn test_sound_cache_prefetch_within_budget() {
+        let (vfs, mut cache) = cache(&["a.wav", "b.wav", "c.wav"]);
+        cache.set_budget(2 * SOUND_SIZE);
+
+        let deadline = Instant::now() + StdDuration::from_secs(5);
+        while loaded(&cache) != vec![true, true, false] {
+            assert!(Instant::now() < deadline, "Timed out waiting for decoder");
+            cache.prefetch(&vfs, 1);
+            std::thread::sleep(StdDuration::from_millis(1));
+        }
+
+        // the budget is full, so the last sound is left until it's played
+        cache.prefetch(&vfs, 1);
+        assert!(cache.pending.is_empty());
+        assert_eq!(cache.size, 2 * SOUND_SIZE);
+    }
+}
@@ -13,6 +13,7 @@ pub struct MusicPlayer {
     stream: OutputStreamHandle,
     playing: Option<String>,
     sink: Option<Sink>,
+    volume: f32,
 }
 
 impl MusicPlayer {
@@ -22,6 +23,7 @@ impl MusicPlayer {
             stream,
             playing: None,
             sink: None,
+            volume: 1.0,
         }
     }
 
@@ -71,6 +73,7 @@ impl MusicPlayer {
         // TODO handle PlayError
         let new_sink = Sink::try_new(&self.stream).unwrap();
         new_sink.append(source);
+        new_sink.set_volume(self.volume);
         self.sink = Some(new_sink);
 
         Ok(())
@@ -80,8 +83,16 @@ impl MusicPlayer {
     ///
     /// Note that the first actual music track is track 2; track 1 on the
     /// original Quake CD-ROM held the game data.
+    ///
+    /// Tracks are usually named with two digits, like `"track02"`, but names
+    /// without the padding, like `"track2"`, are also accepted.
     pub fn play_track(&mut self, track_id: usize) -> Result<(), SoundError> {
-        self.play_named(format!("track{:02}", track_id))
+        match self.play_named(format!("track{:02}", track_id)) {
+            Err(SoundError::NoSuchTrack(_)) if track_id < 10 => {
+                self.play_named(format!("track{}", track_id))
+            }
+            res => res,
+        }
     }
 
     /// Stop the current music track.
@@ -95,6 +106,16 @@ impl MusicPlayer {
         self.playing = None;
     }
 
+    /// Set the music volume, e.g. from the `bgmvolume` cvar.
+    ///
+    /// This applies to the current track and any tracks played later.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.max(0.0).min(1.0);
+        if let Some(ref sink) = self.sink {
+            sink.set_volume(self.volume);
+        }
+    }
+
     /// Pause the current music track.
     ///
     /// If no music track is currently playing, or if the current track is
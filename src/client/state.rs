@@ -5,29 +5,37 @@ use crate::{
     client::{
         entity::{
             particle::{Particle, Particles, TrailKind, MAX_PARTICLES},
-            Beam, ClientEntity, Light, LightDesc, Lights, MAX_BEAMS, MAX_LIGHTS, MAX_TEMP_ENTITIES,
+            Beam, ClientEntity, Light, LightDesc, Lights, MAX_BEAMS, MAX_LIGHTS,
         },
+        fog::{Fog, FogState},
         input::game::{Action, GameInput},
-        render::Camera,
-        sound::{AudioSource, EntityMixer, Listener, StaticSound},
-        view::{IdleVars, KickVars, MouseVars, RollVars, View},
-        ClientError, ColorShiftCode, IntermissionKind, MoveVars, MAX_STATS,
+        lightstyle::{LightStyleLerp, LightStyleValues, LightStyles},
+        location::Locations,
+        render::{Camera, Viewmodel},
+        showlmp::ShowLmps,
+        sound::{AudioSource, EntityMixer, Listener, SoundCache, StaticSound},
+        stats::ClientStats,
+        teaminfo::{TeamInfo, TeammateStatus},
+        timegraph::{self, ClockSample, TimeGraph},
+        view::{GunVars, IdleVars, KickVars, MouseVars, RollVars, View},
+        ClientError, ColorShiftCode, IntermissionKind, MoveVars,
     },
     common::{
         bsp, engine,
+        limits::{Limit, Limits},
         math::{self, Angles},
         model::{Model, ModelFlags, ModelKind, SyncType},
         net::{
-            self, BeamEntityKind, ButtonFlags, ColorShift, EntityEffects, ItemFlags, PlayerData,
-            PointEntityKind, TempEntity,
+            self, BeamEntityKind, ButtonFlags, ColorShift, EntityEffects, GameType, ItemFlags,
+            PlayerData, PointEntityKind, TempEntity,
         },
+        parse,
         vfs::Vfs,
     },
 };
-use arrayvec::ArrayVec;
 use cgmath::{Angle as _, Deg, InnerSpace as _, Matrix4, Vector3, Zero as _};
 use chrono::Duration;
-use net::{ClientCmd, ClientStat, EntityState, EntityUpdate, PlayerColor};
+use net::{ClientCmd, EntityState, EntityUpdate, PlayerColor};
 use rand::{
     distributions::{Distribution as _, Uniform},
     rngs::SmallRng,
@@ -62,8 +70,8 @@ pub struct ClientState {
     // name-to-id map
     pub model_names: HashMap<String, usize>,
 
-    // audio source precache
-    pub sounds: Vec<AudioSource>,
+    // audio source precache, loaded on demand
+    pub sounds: SoundCache,
 
     // sounds that are always needed even if not in precache
     cached_sounds: HashMap<String, AudioSource>,
@@ -85,19 +93,40 @@ pub struct ClientState {
     // visible entities, rebuilt per-frame
     pub visible_entity_ids: Vec<usize>,
 
-    pub light_styles: HashMap<u8, String>,
+    pub light_styles: LightStyles,
 
-    // various values relevant to the player and level (see common::net::ClientStat)
-    pub stats: [i32; MAX_STATS],
+    // various values relevant to the player and level
+    pub stats: ClientStats,
+
+    // limits on the content of the level, depending on the protocol
+    pub limits: Limits,
 
     pub max_players: usize,
+    pub game_type: GameType,
     pub player_info: [Option<PlayerInfo>; net::MAX_CLIENTS],
 
+    // teammate status stuffed by the server
+    pub team_info: TeamInfo,
+
+    // named locations for team chat
+    locations: Locations,
+
+    // pictures drawn with svc_showlmp
+    show_lmps: ShowLmps,
+
+    // name of the six-sided skybox in env/, if any
+    skybox: Option<String>,
+
+    fog: FogState,
+
     // the last two timestamps sent by the server (for lerping)
     pub msg_times: [Duration; 2],
     pub time: Duration,
     pub lerp_factor: f32,
 
+    // recent client clock drift, for cl_timegraph
+    pub time_graph: TimeGraph,
+
     pub items: ItemFlags,
     pub item_get_time: [Duration; net::MAX_ITEMS],
     pub face_anim_time: Duration,
@@ -114,18 +143,22 @@ pub struct ClientState {
     pub start_time: Duration,
     pub completion_time: Option<Duration>,
 
+    // the last centerprint message and the time it was received
+    pub center_print: Option<(String, Duration)>,
+
     pub mixer: EntityMixer,
     pub listener: Listener,
 }
 
 impl ClientState {
+    /// Creates an empty client state which plays sounds on `stream`, if any.
     // TODO: add parameter for number of player slots and reserve them in entity list
-    pub fn new(stream: OutputStreamHandle) -> ClientState {
+    pub fn new(stream: Option<OutputStreamHandle>) -> ClientState {
         ClientState {
             rng: SmallRng::from_entropy(),
             models: vec![Model::none()],
             model_names: HashMap::new(),
-            sounds: Vec::new(),
+            sounds: SoundCache::new(),
             cached_sounds: HashMap::new(),
             static_sounds: Vec::new(),
             entities: Vec::new(),
@@ -135,13 +168,21 @@ impl ClientState {
             beams: [None; MAX_BEAMS],
             particles: Particles::with_capacity(MAX_PARTICLES),
             visible_entity_ids: Vec::new(),
-            light_styles: HashMap::new(),
-            stats: [0; MAX_STATS],
+            light_styles: LightStyles::new(),
+            stats: ClientStats::new(),
+            limits: Limits::default(),
             max_players: 0,
+            game_type: GameType::CoOp,
             player_info: Default::default(),
+            team_info: TeamInfo::new(),
+            locations: Locations::new(),
+            show_lmps: ShowLmps::new(),
+            skybox: None,
+            fog: FogState::default(),
             msg_times: [Duration::zero(), Duration::zero()],
             time: Duration::zero(),
             lerp_factor: 0.0,
+            time_graph: TimeGraph::new(),
             items: ItemFlags::empty(),
             item_get_time: [Duration::zero(); net::MAX_ITEMS],
             color_shifts: [
@@ -171,6 +212,7 @@ impl ClientState {
             intermission: None,
             start_time: Duration::zero(),
             completion_time: None,
+            center_print: None,
             mixer: EntityMixer::new(stream),
             listener: Listener::new(),
         }
@@ -178,20 +220,48 @@ impl ClientState {
 
     pub fn from_server_info(
         vfs: &Vfs,
-        stream: OutputStreamHandle,
+        stream: Option<OutputStreamHandle>,
         max_clients: u8,
+        game_type: GameType,
+        limits: Limits,
         model_precache: Vec<String>,
         sound_precache: Vec<String>,
+        worker_threads: usize,
     ) -> Result<ClientState, ClientError> {
+        // the world model is always first in the precache, e.g. maps/e1m1.bsp
+        let locations = match model_precache.get(0) {
+            Some(world) => {
+                let map_name = world.trim_start_matches("maps/").trim_end_matches(".bsp");
+                Locations::load(vfs, map_name)
+            }
+            None => Locations::new(),
+        };
+
         // TODO: validate submodel names
         let mut models = Vec::with_capacity(model_precache.len());
         models.push(Model::none());
         let mut model_names = HashMap::new();
+        let mut skybox = None;
+        let mut fog = Fog::default();
+        // alias and sprite models are decoded together after the loop, see Model::load_many
+        let mut deferred = Vec::new();
         for mod_name in model_precache {
             // BSPs can have more than one model
             if mod_name.ends_with(".bsp") {
                 let bsp_data = vfs.open(&mod_name)?;
-                let (mut brush_models, _) = bsp::load(bsp_data).unwrap();
+                // colored lighting comes from an optional .lit file alongside the BSP
+                let lit_data = vfs
+                    .open(format!("{}.lit", mod_name.trim_end_matches(".bsp")))
+                    .ok();
+                let (mut brush_models, ent_string) =
+                    bsp::load_with_lit(bsp_data, lit_data).unwrap();
+
+                // the worldspawn entity of the world model may name a skybox and set fog
+                if models.len() == 1 {
+                    skybox = worldspawn_skybox(&ent_string);
+                    fog = worldspawn_fog(&ent_string).unwrap_or_default();
+                }
+
                 for bmodel in brush_models.drain(..) {
                     let id = models.len();
                     let name = bmodel.name().to_owned();
@@ -200,25 +270,51 @@ impl ClientState {
                 }
             } else if !mod_name.starts_with("*") {
                 // model names starting with * are loaded from the world BSP
-                debug!("Loading model {}", mod_name);
                 let id = models.len();
-                models.push(Model::load(vfs, &mod_name)?);
-                model_names.insert(mod_name, id);
+                models.push(Model::none());
+                model_names.insert(mod_name.clone(), id);
+                deferred.push((id, mod_name));
             }
 
             // TODO: send keepalive message?
         }
 
-        let mut sounds = vec![AudioSource::load(&vfs, "misc/null.wav")?];
-        for ref snd_name in sound_precache {
-            debug!("Loading sound {}: {}", sounds.len(), snd_name);
-            sounds.push(AudioSource::load(vfs, snd_name)?);
-            // TODO: send keepalive message?
+        let deferred_names = deferred
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<_>>();
+        debug!(
+            "Loading {} models on {} threads",
+            deferred_names.len(),
+            worker_threads
+        );
+        let loaded = Model::load_many(vfs, &deferred_names, worker_threads);
+        for ((id, mod_name), result) in deferred.iter().zip(loaded) {
+            models[*id] = result.unwrap_or_else(|e| {
+                // missing models are expected when running on the fallback assets
+                warn!("Couldn't load model {}: {}", mod_name, e);
+                Model::none()
+            });
         }
 
+        let null_sound = AudioSource::load(&vfs, "misc/null.wav")?;
+
+        // missing sounds are replaced with silence
+        let load_sound = |name: &str| {
+            AudioSource::load(vfs, name).unwrap_or_else(|e| {
+                warn!("Couldn't load sound {}: {}", name, e);
+                null_sound.clone()
+            })
+        };
+
+        // precached sounds are loaded lazily, see SoundCache
+        let mut sound_names = vec!["misc/null.wav".to_owned()];
+        sound_names.extend(sound_precache);
+        let sounds = SoundCache::with_names(sound_names, null_sound.clone());
+
         let mut cached_sounds = HashMap::new();
         for name in CACHED_SOUND_NAMES {
-            cached_sounds.insert(name.to_string(), AudioSource::load(vfs, name)?);
+            cached_sounds.insert(name.to_string(), load_sound(name));
         }
 
         Ok(ClientState {
@@ -226,7 +322,12 @@ impl ClientState {
             model_names,
             sounds,
             cached_sounds,
+            limits,
             max_players: max_clients as usize,
+            game_type,
+            locations,
+            skybox,
+            fog: FogState::new(fog),
             ..ClientState::new(stream)
         })
     }
@@ -238,11 +339,26 @@ impl ClientState {
         self.time = self.time + frame_time;
     }
 
+    /// Synchronize the client clock with the server and update the interpolation ratio.
+    ///
+    /// `interp` is how long the latest server message was held by the interpolation delay buffer,
+    /// and is only recorded for display.
+    pub fn sync_clock(&mut self, cl_nolerp: f32, frame_time: Duration, interp: Duration) {
+        let prev_time = self.time;
+        self.update_interp_ratio(cl_nolerp, frame_time);
+        self.time_graph.push(ClockSample {
+            drift: self.time - self.msg_times[0],
+            frame_time,
+            nudge: self.time - prev_time,
+            interp,
+        });
+    }
+
     /// Update the client state interpolation ratio.
     ///
     /// This calculates the ratio used to interpolate entities between the last
     /// two updates from the server.
-    pub fn update_interp_ratio(&mut self, cl_nolerp: f32) {
+    fn update_interp_ratio(&mut self, cl_nolerp: f32, frame_time: Duration) {
         if cl_nolerp != 0.0 {
             self.time = self.msg_times[0];
             self.lerp_factor = 1.0;
@@ -273,27 +389,11 @@ impl ClientState {
             d => d,
         });
 
-        let frame_delta = engine::duration_to_f32(self.time - self.msg_times[1]);
-
-        self.lerp_factor = match frame_delta / server_delta {
-            f if f < 0.0 => {
-                if f < -0.01 {
-                    self.time = self.msg_times[1];
-                }
-
-                0.0
-            }
-
-            f if f > 1.0 => {
-                if f > 1.01 {
-                    self.time = self.msg_times[0];
-                }
-
-                1.0
-            }
+        // correct drift gradually so entities don't hitch
+        self.time = timegraph::nudge_time(self.time, self.msg_times, frame_time);
 
-            f => f,
-        }
+        let frame_delta = engine::duration_to_f32(self.time - self.msg_times[1]);
+        self.lerp_factor = (frame_delta / server_delta).max(0.0).min(1.0);
     }
 
     /// Update all entities in the game world.
@@ -336,7 +436,7 @@ impl ClientState {
             }
 
             // if we didn't get an update this frame, remove the entity
-            if ent.msg_time != self.msg_times[0] {
+            if ent.msg_time() != self.msg_times[0] {
                 ent.model_id = 0;
                 continue;
             }
@@ -345,36 +445,18 @@ impl ClientState {
 
             if ent.force_link {
                 trace!("force link on entity {}", ent_id);
-                ent.origin = ent.msg_origins[0];
-                ent.angles = ent.msg_angles[0];
-            } else {
-                let origin_delta = ent.msg_origins[0] - ent.msg_origins[1];
-                let ent_lerp_factor = if origin_delta.magnitude2() > 10_000.0 {
-                    // if the entity moved more than 100 units in one frame,
-                    // assume it was teleported and don't lerp anything
-                    1.0
-                } else {
-                    lerp_factor
-                };
+            }
 
-                ent.origin = ent.msg_origins[1] + ent_lerp_factor * origin_delta;
-
-                // assume that entities will not whip around 180+ degrees in one
-                // frame and adjust the delta accordingly. this avoids a bug
-                // where small turns between 0 <-> 359 cause the demo camera to
-                // face backwards for one frame.
-                for i in 0..3 {
-                    let mut angle_delta = ent.msg_angles[0][i] - ent.msg_angles[1][i];
-                    if angle_delta > Deg(180.0) {
-                        angle_delta = Deg(360.0) - angle_delta;
-                    } else if angle_delta < Deg(-180.0) {
-                        angle_delta = Deg(360.0) + angle_delta;
-                    }
+            ent.interpolate(lerp_factor);
 
-                    ent.angles[i] =
-                        (ent.msg_angles[1][i] + angle_delta * ent_lerp_factor).normalize();
-                }
-            }
+            // players can change colors at any time, so they're looked up every frame
+            let player_info = &self.player_info;
+            ent.set_colors(
+                ent.colormap()
+                    .and_then(|c| player_info.get(c as usize - 1))
+                    .and_then(|info| info.as_ref())
+                    .map(|info| info.colors),
+            );
 
             let model = &self.models[ent.model_id];
             if model.has_flag(ModelFlags::ROTATE) {
@@ -465,8 +547,8 @@ impl ClientState {
                     .create_trail(self.time, prev_origin, ent.origin, kind, false);
             }
 
-            // don't render the player model
-            if self.view.entity_id() != ent_id {
+            // don't render the player model unless the camera has left it
+            if self.view.entity_id() != ent_id || self.view.is_free() {
                 // mark entity for rendering
                 self.visible_entity_ids.push(ent_id);
             }
@@ -544,7 +626,7 @@ impl ClientState {
                     ent.angles =
                         Vector3::new(pitch, yaw, Deg(ANGLE_DISTRIBUTION.sample(&mut self.rng)));
 
-                    if self.temp_entities.len() < MAX_TEMP_ENTITIES {
+                    if self.temp_entities.len() < self.limits.temp_entities {
                         self.temp_entities.push(ent);
                     } else {
                         warn!("too many temp entities!");
@@ -589,19 +671,7 @@ impl ClientState {
         self.on_ground = update.on_ground;
         self.in_water = update.in_water;
 
-        self.stats[ClientStat::WeaponFrame as usize] = update.weapon_frame.unwrap_or(0) as i32;
-        self.stats[ClientStat::Armor as usize] = update.armor.unwrap_or(0) as i32;
-        self.stats[ClientStat::Weapon as usize] = update.weapon.unwrap_or(0) as i32;
-        self.stats[ClientStat::Health as usize] = update.health as i32;
-        self.stats[ClientStat::Ammo as usize] = update.ammo as i32;
-        self.stats[ClientStat::Shells as usize] = update.ammo_shells as i32;
-        self.stats[ClientStat::Nails as usize] = update.ammo_nails as i32;
-        self.stats[ClientStat::Rockets as usize] = update.ammo_rockets as i32;
-        self.stats[ClientStat::Cells as usize] = update.ammo_cells as i32;
-
-        // TODO: this behavior assumes the `standard_quake` behavior and will likely
-        // break with the mission packs
-        self.stats[ClientStat::ActiveWeapon as usize] = update.active_weapon as i32;
+        self.stats.update_from_player_data(&update);
     }
 
     pub fn handle_input(
@@ -613,7 +683,7 @@ impl ClientState {
     ) -> ClientCmd {
         use Action::*;
 
-        let mlook = game_input.action_state(MLook);
+        let mlook = game_input.action_state(MLook) || mouse_vars.freelook != 0.0;
         self.view.handle_input(
             frame_time,
             game_input,
@@ -645,7 +715,9 @@ impl ClientState {
             forwardmove -= move_vars.cl_backspeed * game_input.action_state(Back) as i32 as f32;
         }
 
-        if game_input.action_state(Speed) {
+        // with cl_run set, movement is at run speed by default and +speed walks instead
+        let running = game_input.action_state(Speed) != (move_vars.cl_run != 0.0);
+        if running {
             sidemove *= move_vars.cl_movespeedkey;
             upmove *= move_vars.cl_movespeedkey;
             forwardmove *= move_vars.cl_movespeedkey;
@@ -727,6 +799,7 @@ impl ClientState {
         kick_vars: KickVars,
         roll_vars: RollVars,
         bob_vars: BobVars,
+        gun_vars: GunVars,
     ) {
         self.view.calc_final_angles(
             self.time,
@@ -742,6 +815,26 @@ impl ClientState {
             self.velocity,
             bob_vars,
         );
+        self.view
+            .calc_viewmodel(self.time, self.velocity, bob_vars, gun_vars);
+    }
+
+    /// Returns warnings for any content of the level which is close to or over its limit.
+    ///
+    /// This should be checked once sign-on is complete and the level's entities have all been
+    /// spawned.
+    pub fn limit_warnings(&self) -> Vec<String> {
+        let counts = [
+            (Limit::Entities, self.entities.len()),
+            (Limit::Models, self.models.len()),
+            (Limit::Sounds, self.sounds.len()),
+            (Limit::StaticEntities, self.static_entities.len()),
+        ];
+
+        counts
+            .iter()
+            .filter_map(|&(limit, count)| self.limits.check(limit, count))
+            .collect()
     }
 
     /// Spawn an entity with the given ID, also spawning any uninitialized
@@ -755,6 +848,10 @@ impl ClientState {
             Err(ClientError::EntityExists(id))?;
         }
 
+        if id >= self.limits.entities {
+            Err(ClientError::TooManyEntities(id))?;
+        }
+
         // spawn intermediate entities (uninitialized)
         for i in self.entities.len()..id {
             debug!("Spawning uninitialized entity with ID {}", i);
@@ -788,6 +885,7 @@ impl ClientState {
                 colormap: update.colormap.unwrap_or(0),
                 skin_id: update.skin_id.unwrap_or(0) as usize,
                 effects: EntityEffects::empty(),
+                alpha: 1.0,
             };
 
             self.spawn_entities(id, baseline)?;
@@ -807,15 +905,13 @@ impl ClientState {
             }
         }
 
-        if let Some(_c) = entity.colormap() {
-            // only players may have custom colormaps
-            if id > self.max_players {
+        if let Some(c) = entity.colormap() {
+            if c as usize > self.max_players {
                 warn!(
-                    "Server attempted to set colormap on entity {}, which is not a player",
-                    id
+                    "Server set colormap {} on entity {}, but there are only {} players",
+                    c, id, self.max_players
                 );
             }
-            // TODO: set player custom colormaps
         }
 
         Ok(())
@@ -1157,6 +1253,11 @@ impl ClientState {
 
     /// Update the view angles to the specified value, disabling interpolation.
     pub fn set_view_angles(&mut self, angles: Vector3<Deg<f32>>) {
+        if self.view.is_free() {
+            self.entities[self.view.entity_id()].set_angles(angles);
+            return;
+        }
+
         self.view.update_input_angles(Angles {
             pitch: angles.x,
             roll: angles.z,
@@ -1171,7 +1272,15 @@ impl ClientState {
     }
 
     /// Update the view angles to the specified value, enabling interpolation.
+    ///
+    /// While the camera is free, only the view entity is turned, since the camera is steered by
+    /// the player instead.
     pub fn update_view_angles(&mut self, angles: Vector3<Deg<f32>>) {
+        if self.view.is_free() {
+            self.entities[self.view.entity_id()].update_angles(angles);
+            return;
+        }
+
         self.view.update_input_angles(Angles {
             pitch: angles.x,
             roll: angles.z,
@@ -1196,15 +1305,56 @@ impl ClientState {
         Ok(())
     }
 
+    /// Returns whether the camera is detached from the view entity.
+    pub fn free_camera(&self) -> bool {
+        self.view.is_free()
+    }
+
+    /// Detaches the camera from the view entity or reattaches it.
+    pub fn set_free_camera(&mut self, free: bool) {
+        self.view.set_free(free);
+    }
+
+    /// Moves a detached camera by the forward, side and up speeds of a movement command.
+    pub fn move_free_camera(&mut self, frame_time: Duration, velocity: Vector3<f32>) {
+        self.view.move_free(frame_time, velocity);
+    }
+
     pub fn models(&self) -> &[Model] {
         &self.models
     }
 
-    pub fn viewmodel_id(&self) -> usize {
-        match self.stats[ClientStat::Weapon as usize] as usize {
-            0 => 0,
-            x => x - 1,
+    /// Returns the renderer index of the weapon view model, if it should be drawn.
+    ///
+    /// Like the original, the view model is hidden while the player is invisible or dead.
+    pub fn viewmodel_id(&self) -> Option<usize> {
+        if self.view.is_free() {
+            return None;
+        }
+
+        if self.items.contains(ItemFlags::INVISIBILITY) || self.stats.health <= 0 {
+            return None;
         }
+
+        match self.stats.weapon as usize {
+            0 => None,
+            x => Some(x - 1),
+        }
+    }
+
+    /// Returns the current animation frame of the weapon view model.
+    pub fn viewmodel_frame(&self) -> usize {
+        self.stats.weapon_frame.max(0) as usize
+    }
+
+    /// Returns the weapon view model placed for the current view, if it should be drawn.
+    pub fn viewmodel(&self) -> Option<Viewmodel> {
+        Some(Viewmodel {
+            model_id: self.viewmodel_id()?,
+            frame: self.viewmodel_frame(),
+            origin: self.view.viewmodel_origin(),
+            angles: self.view.viewmodel_angles(),
+        })
     }
 
     pub fn iter_visible_entities(&self) -> impl Iterator<Item = &ClientEntity> + Clone {
@@ -1231,6 +1381,45 @@ impl ClientState {
         self.view.entity_id()
     }
 
+    /// Returns the name of the map location closest to the player, if any are defined.
+    pub fn location(&self) -> Option<&str> {
+        let origin = self.entities.get(self.view.entity_id())?.origin;
+        self.locations.nearest(origin)
+    }
+
+    /// Returns the name of the current skybox, if any.
+    pub fn skybox(&self) -> Option<&str> {
+        self.skybox.as_deref()
+    }
+
+    /// Sets the current skybox. An empty name removes it.
+    pub fn set_skybox<S>(&mut self, name: S)
+    where
+        S: AsRef<str>,
+    {
+        let name = name.as_ref();
+        self.skybox = if name.is_empty() {
+            None
+        } else {
+            Some(name.to_owned())
+        };
+    }
+
+    /// Returns the fog currently in effect.
+    pub fn fog(&self) -> Fog {
+        self.fog.at(self.time)
+    }
+
+    /// Returns the fog that will be in effect once any fade finishes.
+    pub fn fog_target(&self) -> Fog {
+        self.fog.target()
+    }
+
+    /// Changes the fog, fading from the current fog over `fade_time`.
+    pub fn set_fog(&mut self, fog: Fog, fade_time: Duration) {
+        self.fog.set(fog, self.time, fade_time);
+    }
+
     pub fn camera(&self, aspect: f32, fov: Deg<f32>) -> Camera {
         let fov_y = math::fov_x_to_fov_y(fov, aspect).unwrap();
         Camera::new(
@@ -1254,31 +1443,9 @@ impl ClientState {
         )
     }
 
-    pub fn lightstyle_values(&self) -> Result<ArrayVec<f32, 64>, ClientError> {
-        let mut values = ArrayVec::new();
-
-        for lightstyle_id in 0..64 {
-            match self.light_styles.get(&lightstyle_id) {
-                Some(ls) => {
-                    let float_time = engine::duration_to_f32(self.time);
-                    let frame = if ls.len() == 0 {
-                        None
-                    } else {
-                        Some((float_time * 10.0) as usize % ls.len())
-                    };
-
-                    values.push(match frame {
-                        // 'z' - 'a' = 25, so divide by 12.5 to get range [0, 2]
-                        Some(f) => (ls.as_bytes()[f] - 'a' as u8) as f32 / 12.5,
-                        None => 1.0,
-                    })
-                }
-
-                None => Err(ClientError::NoSuchLightmapAnimation(lightstyle_id as usize))?,
-            }
-        }
-
-        Ok(values)
+    /// Returns the current brightness of every light style.
+    pub fn lightstyle_values(&self, lerp: LightStyleLerp) -> LightStyleValues {
+        self.light_styles.values(self.time, lerp)
     }
 
     pub fn intermission(&self) -> Option<&IntermissionKind> {
@@ -1293,10 +1460,40 @@ impl ClientState {
         self.completion_time
     }
 
-    pub fn stats(&self) -> &[i32] {
+    /// Returns the last centerprint message and the time it was received.
+    pub fn center_print(&self) -> Option<(&str, Duration)> {
+        self.center_print
+            .as_ref()
+            .map(|(text, time)| (text.as_str(), *time))
+    }
+
+    pub fn stats(&self) -> &ClientStats {
         &self.stats
     }
 
+    pub fn show_lmps(&self) -> &ShowLmps {
+        &self.show_lmps
+    }
+
+    pub fn show_lmps_mut(&mut self) -> &mut ShowLmps {
+        &mut self.show_lmps
+    }
+
+    /// Returns the name and status of each teammate with a recent status update.
+    ///
+    /// The local player is left out, since their status is already on the status bar.
+    pub fn teammates(&self) -> impl Iterator<Item = (&str, &TeammateStatus)> {
+        // the player's entity ID is one more than their player ID
+        let local_id = self.view_entity_id().checked_sub(1);
+        self.team_info
+            .current(self.time)
+            .filter(move |status| Some(status.player_id) != local_id)
+            .filter_map(move |status| {
+                let info = self.player_info[status.player_id].as_ref()?;
+                Some((info.name.as_str(), status))
+            })
+    }
+
     pub fn items(&self) -> ItemFlags {
         self.items
     }
@@ -1309,24 +1506,14 @@ impl ClientState {
         self.face_anim_time
     }
 
-    pub fn color_shift(&self) -> [f32; 4] {
-        self.color_shifts.iter().fold([0.0; 4], |accum, elem| {
-            let elem_a = elem.borrow().percent as f32 / 255.0 / 2.0;
-            if elem_a == 0.0 {
-                return accum;
-            }
-            let in_a = accum[3];
-            let out_a = in_a + elem_a * (1.0 - in_a);
-            let color_factor = elem_a / out_a;
-
-            let mut out = [0.0; 4];
-            for i in 0..3 {
-                out[i] = accum[i] * (1.0 - color_factor)
-                    + elem.borrow().dest_color[i] as f32 / 255.0 * color_factor;
-            }
-            out[3] = out_a.min(1.0).max(0.0);
-            out
-        })
+    /// Returns the RGBA color blended over the view by the active color shifts.
+    ///
+    /// `cshift_percent` scales the strength of every shift, as in `gl_cshiftpercent`.
+    pub fn color_shift(&self, cshift_percent: f32) -> [f32; 4] {
+        blend_color_shifts(
+            self.color_shifts.iter().map(|s| *s.borrow()),
+            cshift_percent,
+        )
     }
 
     pub fn check_entity_id(&self, id: usize) -> Result<(), ClientError> {
@@ -1347,3 +1534,125 @@ impl ClientState {
         }
     }
 }
+
+/// Returns the value of the first of `keys` present on the worldspawn entity, if any.
+fn worldspawn_value(ent_string: &str, keys: &[&str]) -> Option<String> {
+    let entities = match parse::entities(ent_string) {
+        Ok(e) => e,
+        Err(e) => {
+            warn!("Couldn't parse entity string: {}", e);
+            return None;
+        }
+    };
+
+    let worldspawn = entities
+        .iter()
+        .find(|ent| ent.get("classname") == Some(&"worldspawn"))?;
+
+    keys.iter()
+        .find_map(|key| worldspawn.get(key))
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+/// Returns the skybox named by the worldspawn entity, if any.
+///
+/// FitzQuake-derived engines use the `sky` key, while some older maps use `skyname`.
+fn worldspawn_skybox(ent_string: &str) -> Option<String> {
+    worldspawn_value(ent_string, &["sky", "skyname"])
+}
+
+/// Returns the fog set by the worldspawn entity's `fog` key, if any.
+///
+/// The key holds the density and color, e.g. `"0.05 0.3 0.3 0.3"`.
+fn worldspawn_fog(ent_string: &str) -> Option<Fog> {
+    let value = worldspawn_value(ent_string, &["fog"])?;
+    let args: Vec<&str> = value.split_whitespace().collect();
+    match Fog::parse(&args, Fog::default()) {
+        Some((fog, _)) => Some(fog),
+        None => {
+            warn!("Invalid worldspawn fog \"{}\"", value);
+            None
+        }
+    }
+}
+
+/// Composites color shifts in order, each over the ones before it.
+///
+/// The strength of each shift is scaled by `percent`, which is given out of 100.
+fn blend_color_shifts<I>(shifts: I, percent: f32) -> [f32; 4]
+where
+    I: IntoIterator<Item = ColorShift>,
+{
+    shifts.into_iter().fold([0.0; 4], |accum, elem| {
+        let elem_a = elem.percent as f32 * percent / 100.0 / 255.0 / 2.0;
+        if elem_a <= 0.0 {
+            return accum;
+        }
+        let in_a = accum[3];
+        let out_a = in_a + elem_a * (1.0 - in_a);
+        let color_factor = elem_a / out_a;
+
+        let mut out = [0.0; 4];
+        for i in 0..3 {
+            out[i] =
+                accum[i] * (1.0 - color_factor) + elem.dest_color[i] as f32 / 255.0 * color_factor;
+        }
+        out[3] = out_a.min(1.0).max(0.0);
+        out
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blend_color_shifts() {
+        let damage = ColorShift {
+            dest_color: [255, 0, 0],
+            percent: 102,
+        };
+        let none = ColorShift {
+            dest_color: [0, 0, 255],
+            percent: 0,
+        };
+
+        // a single shift is blended at half its percentage
+        assert_eq!(
+            blend_color_shifts(vec![none, damage], 100.0),
+            [1.0, 0.0, 0.0, 0.2]
+        );
+
+        // gl_cshiftpercent scales every shift, and 0 disables them
+        assert_eq!(blend_color_shifts(vec![damage], 50.0), [1.0, 0.0, 0.0, 0.1]);
+        assert_eq!(blend_color_shifts(vec![damage], 0.0), [0.0; 4]);
+    }
+
+    #[test]
+    fn test_worldspawn_skybox() {
+        let ents = "{\n\"classname\" \"worldspawn\"\n\"sky\" \"stormydays_\"\n}\n";
+        assert_eq!(worldspawn_skybox(ents), Some("stormydays_".to_owned()));
+
+        let ents = "{\n\"classname\" \"worldspawn\"\n\"skyname\" \"night\"\n}\n";
+        assert_eq!(worldspawn_skybox(ents), Some("night".to_owned()));
+
+        let ents = "{\n\"classname\" \"worldspawn\"\n\"wad\" \"gfx/base.wad\"\n}\n";
+        assert_eq!(worldspawn_skybox(ents), None);
+    }
+
+    #[test]
+    fn test_worldspawn_fog() {
+        let ents = "{\n\"classname\" \"worldspawn\"\n\"fog\" \"0.05 0.5 0.25 0\"\n}\n";
+        assert_eq!(
+            worldspawn_fog(ents),
+            Some(Fog {
+                density: 0.05,
+                color: Vector3::new(0.5, 0.25, 0.0),
+            })
+        );
+
+        let ents = "{\n\"classname\" \"worldspawn\"\n\"fog\" \"thick\"\n}\n";
+        assert_eq!(worldspawn_fog(ents), None);
+    }
+}
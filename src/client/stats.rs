@@ -0,0 +1,172 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Player statistics.
+
+use crate::common::net::{ClientStat, ItemFlags, PlayerData};
+
+use serde::{Deserialize, Serialize};
+
+/// The local player's statistics.
+///
+/// These are updated individually by `svc_updatestat`, in bulk by `svc_clientdata`, and by the
+/// `svc_foundsecret` and `svc_killedmonster` counters.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientStats {
+    pub health: i32,
+    pub frags: i32,
+    /// The model index of the weapon view model.
+    pub weapon: i32,
+    /// The ammo count of the active weapon.
+    pub ammo: i32,
+    pub armor: i32,
+    pub weapon_frame: i32,
+    pub shells: i32,
+    pub nails: i32,
+    pub rockets: i32,
+    pub cells: i32,
+    /// The item bit of the active weapon.
+    pub active_weapon: i32,
+    pub total_secrets: i32,
+    pub total_monsters: i32,
+    pub found_secrets: i32,
+    pub killed_monsters: i32,
+}
+
+impl ClientStats {
+    pub fn new() -> ClientStats {
+        ClientStats::default()
+    }
+
+    /// Returns the value of a single stat.
+    pub fn get(&self, stat: ClientStat) -> i32 {
+        match stat {
+            ClientStat::Health => self.health,
+            ClientStat::Frags => self.frags,
+            ClientStat::Weapon => self.weapon,
+            ClientStat::Ammo => self.ammo,
+            ClientStat::Armor => self.armor,
+            ClientStat::WeaponFrame => self.weapon_frame,
+            ClientStat::Shells => self.shells,
+            ClientStat::Nails => self.nails,
+            ClientStat::Rockets => self.rockets,
+            ClientStat::Cells => self.cells,
+            ClientStat::ActiveWeapon => self.active_weapon,
+            ClientStat::TotalSecrets => self.total_secrets,
+            ClientStat::TotalMonsters => self.total_monsters,
+            ClientStat::FoundSecrets => self.found_secrets,
+            ClientStat::KilledMonsters => self.killed_monsters,
+        }
+    }
+
+    /// Sets the value of a single stat, as for `svc_updatestat`.
+    pub fn set(&mut self, stat: ClientStat, value: i32) {
+        let field = match stat {
+            ClientStat::Health => &mut self.health,
+            ClientStat::Frags => &mut self.frags,
+            ClientStat::Weapon => &mut self.weapon,
+            ClientStat::Ammo => &mut self.ammo,
+            ClientStat::Armor => &mut self.armor,
+            ClientStat::WeaponFrame => &mut self.weapon_frame,
+            ClientStat::Shells => &mut self.shells,
+            ClientStat::Nails => &mut self.nails,
+            ClientStat::Rockets => &mut self.rockets,
+            ClientStat::Cells => &mut self.cells,
+            ClientStat::ActiveWeapon => &mut self.active_weapon,
+            ClientStat::TotalSecrets => &mut self.total_secrets,
+            ClientStat::TotalMonsters => &mut self.total_monsters,
+            ClientStat::FoundSecrets => &mut self.found_secrets,
+            ClientStat::KilledMonsters => &mut self.killed_monsters,
+        };
+
+        *field = value;
+    }
+
+    /// Updates the stats carried by `svc_clientdata`.
+    pub fn update_from_player_data(&mut self, update: &PlayerData) {
+        self.weapon_frame = update.weapon_frame.unwrap_or(0) as i32;
+        self.armor = update.armor.unwrap_or(0) as i32;
+        self.weapon = update.weapon.unwrap_or(0) as i32;
+        self.health = update.health as i32;
+        self.ammo = update.ammo as i32;
+        self.shells = update.ammo_shells as i32;
+        self.nails = update.ammo_nails as i32;
+        self.rockets = update.ammo_rockets as i32;
+        self.cells = update.ammo_cells as i32;
+
+        // TODO: this behavior assumes the `standard_quake` behavior and will likely
+        // break with the mission packs
+        self.active_weapon = update.active_weapon as i32;
+    }
+
+    /// Returns the ammo counts in status bar order: shells, nails, rockets and cells.
+    pub fn ammo_counts(&self) -> [i32; 4] {
+        [self.shells, self.nails, self.rockets, self.cells]
+    }
+
+    /// Returns whether the given weapon item is the active weapon.
+    pub fn is_active_weapon(&self, weapon: ItemFlags) -> bool {
+        self.active_weapon as u32 == weapon.bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_set_round_trip() {
+        let mut stats = ClientStats::new();
+        stats.set(ClientStat::Cells, 50);
+        stats.set(ClientStat::TotalMonsters, 12);
+
+        assert_eq!(stats.cells, 50);
+        assert_eq!(stats.get(ClientStat::TotalMonsters), 12);
+        assert_eq!(stats.ammo_counts(), [0, 0, 0, 50]);
+    }
+
+    #[test]
+    fn test_is_active_weapon() {
+        let mut stats = ClientStats::new();
+        stats.set(
+            ClientStat::ActiveWeapon,
+            ItemFlags::ROCKET_LAUNCHER.bits() as i32,
+        );
+
+        assert!(stats.is_active_weapon(ItemFlags::ROCKET_LAUNCHER));
+        assert!(!stats.is_active_weapon(ItemFlags::SHOTGUN));
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut stats = ClientStats::new();
+        stats.set(ClientStat::Health, 100);
+        stats.set(ClientStat::Shells, 25);
+
+        let json = serde_json::to_string(&stats).unwrap();
+        let parsed: ClientStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, stats);
+
+        let items = ItemFlags::SHOTGUN | ItemFlags::KEY_1;
+        let json = serde_json::to_string(&items).unwrap();
+        assert_eq!(json, items.bits().to_string());
+        assert_eq!(serde_json::from_str::<ItemFlags>(&json).unwrap(), items);
+    }
+}
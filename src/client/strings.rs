@@ -0,0 +1,267 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Overridable engine strings.
+//!
+//! Messages generated by the client itself are looked up by key in a [`Strings`] table. Mods may
+//! replace any of them by shipping a `strings.json` file containing a JSON object that maps keys
+//! to new strings, e.g. `{ "cmd.not_connected": "no link to the mothership" }`.
+//!
+//! The table covers console command output, connection errors (see
+//! [`ClientError::localize`](crate::client::ClientError::localize)), disconnect messages and the
+//! text drawn over the game view, such as download progress and the `scr_showfps` and `r_speeds`
+//! overlays. Menu labels are not covered yet.
+//!
+//! Strings may contain `{}` placeholders, which are filled in order by [`Strings::format`]. An
+//! override must contain the same number of placeholders as the string it replaces.
+
+use std::{collections::HashMap, fmt, io::Read};
+
+use crate::common::vfs::Vfs;
+
+const STRINGS_FILE: &str = "strings.json";
+const PLACEHOLDER: &str = "{}";
+
+const DEFAULT_STRINGS: &[(&str, &str)] = &[
+    (
+        "cmd.connect.usage",
+        "usage: connect <server_ip>:<server_port>",
+    ),
+    ("cmd.copy.done", "copied {} lines to the clipboard"),
+    ("cmd.copy.failed", "couldn't copy to the clipboard: {}"),
+    ("cmd.copy.usage", "usage: copy [lines]"),
+    ("cmd.fog.usage", "usage: fog <density> [r g b] [time]"),
+    ("cmd.freecam.off", "camera follows the demo"),
+    ("cmd.freecam.on", "free camera"),
+    ("cmd.not_connected", "not connected"),
+    ("cmd.not_demo", "{} only works during demo playback"),
+    ("cmd.pov.missing", "no point of view recorded for client {}"),
+    ("cmd.pov.single", "demo has only one point of view"),
+    ("cmd.pov.usage", "usage: pov [client]"),
+    ("cmd.pov.watching", "watching client {}"),
+    ("cmd.say.demo", "can't chat during demo playback"),
+    ("cmd.say.usage", "usage: {} <message>"),
+    ("cmd.sky.usage", "usage: sky [name]"),
+    ("connect.attempt", "Connecting...(attempt {} of {})"),
+    ("demo.failed", "Couldn't play demo {}: {}"),
+    ("disconnect.error", "Disconnected: {}"),
+    ("disconnect.server", "Server disconnected"),
+    ("download.failed", "Couldn't download {}"),
+    ("download.status.done", "Downloaded {} files"),
+    ("download.status.kib", "Downloading {} ({}/{}) {}KB"),
+    ("download.status.percent", "Downloading {} ({}/{}) {}%"),
+    ("error.connection_rejected", "Connection rejected: {}"),
+    (
+        "error.invalid_connect_port",
+        "Server sent an invalid port number ({})",
+    ),
+    (
+        "error.invalid_connect_response",
+        "Server sent an invalid connect response",
+    ),
+    ("error.invalid_server_address", "Invalid server address"),
+    ("error.no_response", "No response from server"),
+    (
+        "error.registered_map",
+        "{} is only in the registered version of Quake",
+    ),
+    ("error.unrecognized_protocol", "Unrecognized protocol: {}"),
+    ("hud.draws", "{} draws {} tris"),
+    ("hud.fps", "{} fps {}ms"),
+    ("hud.gpu_pass", "{} {}ms"),
+    ("hud.gpu_total", "gpu {}ms"),
+    ("hud.leaves", "{} leaves {} surfs {} ents"),
+    (
+        "hud.timegraph",
+        "drift {}ms nudge {}ms interp {}ms frame {}ms",
+    ),
+    ("hud.uniforms", "uniforms {}/{} KiB"),
+    ("signon.limit_warning", "Warning: {}"),
+];
+
+/// A table of engine strings.
+#[derive(Clone, Debug)]
+pub struct Strings {
+    strings: HashMap<String, String>,
+}
+
+impl Strings {
+    /// Returns a table containing only the built-in strings.
+    pub fn new() -> Strings {
+        Strings {
+            strings: DEFAULT_STRINGS
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Returns the built-in strings with any overrides from `strings.json` applied.
+    ///
+    /// A missing `strings.json` is not an error. Invalid files and entries are ignored with a
+    /// warning.
+    pub fn load(vfs: &Vfs) -> Strings {
+        let mut strings = Strings::new();
+
+        if let Ok(file) = vfs.open(STRINGS_FILE) {
+            if let Err(e) = strings.merge_json(file) {
+                warn!("Couldn't load {}: {}", STRINGS_FILE, e);
+            }
+        }
+
+        strings
+    }
+
+    fn merge_json<R>(&mut self, reader: R) -> Result<(), serde_json::Error>
+    where
+        R: Read,
+    {
+        let overrides: HashMap<String, String> = serde_json::from_reader(reader)?;
+
+        for (key, value) in overrides {
+            let placeholders = match self.strings.get(&key) {
+                Some(default) => default.matches(PLACEHOLDER).count(),
+                None => {
+                    warn!("Unknown string \"{}\" in {}", key, STRINGS_FILE);
+                    continue;
+                }
+            };
+
+            if value.matches(PLACEHOLDER).count() != placeholders {
+                warn!(
+                    "String \"{}\" in {} must have {} placeholder(s)",
+                    key, STRINGS_FILE, placeholders
+                );
+                continue;
+            }
+
+            self.strings.insert(key, value);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the string for `key`, or the key itself if there is no such string.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        match self.strings.get(key) {
+            Some(s) => s,
+            None => key,
+        }
+    }
+
+    /// Returns the string for `key` with its placeholders replaced by `args` in order.
+    pub fn format(&self, key: &str, args: &[&dyn fmt::Display]) -> String {
+        let mut args = args.iter();
+        let mut pieces = self.get(key).split(PLACEHOLDER);
+        let mut result = pieces.next().unwrap_or("").to_owned();
+
+        for piece in pieces {
+            if let Some(arg) = args.next() {
+                result.push_str(&arg.to_string());
+            }
+            result.push_str(piece);
+        }
+
+        result
+    }
+}
+
+impl std::default::Default for Strings {
+    fn default() -> Strings {
+        Strings::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let strings = Strings::new();
+        assert_eq!(strings.get("cmd.not_connected"), "not connected");
+        assert_eq!(
+            strings.format("connect.attempt", &[&1, &3]),
+            "Connecting...(attempt 1 of 3)"
+        );
+
+        // missing arguments leave the placeholder empty
+        assert_eq!(strings.format("cmd.say.usage", &[]), "usage:  <message>");
+
+        // unknown keys are returned unchanged
+        assert_eq!(strings.get("no.such.key"), "no.such.key");
+    }
+
+    #[test]
+    fn test_merge_json() {
+        let mut strings = Strings::new();
+        strings
+            .merge_json(
+                r#"{
+                    "cmd.not_connected": "offline",
+                    "connect.attempt": "try {}",
+                    "cmd.say.usage": "{} needs a message",
+                    "no.such.key": "ignored"
+                }"#
+                .as_bytes(),
+            )
+            .unwrap();
+
+        assert_eq!(strings.get("cmd.not_connected"), "offline");
+        assert_eq!(
+            strings.format("cmd.say.usage", &[&"say"]),
+            "say needs a message"
+        );
+
+        // wrong placeholder count, so the default is kept
+        assert_eq!(
+            strings.get("connect.attempt"),
+            "Connecting...(attempt {} of {})"
+        );
+        assert_eq!(strings.get("no.such.key"), "no.such.key");
+
+        assert!(strings.merge_json("[1, 2]".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_localize_error() {
+        use crate::client::ClientError;
+
+        let mut strings = Strings::new();
+        strings
+            .merge_json(r#"{ "error.registered_map": "buy the game to play {}" }"#.as_bytes())
+            .unwrap();
+
+        assert_eq!(
+            ClientError::RegisteredMap("e2m1".to_owned()).localize(&strings),
+            "buy the game to play e2m1"
+        );
+        assert_eq!(
+            ClientError::NoResponse.localize(&strings),
+            ClientError::NoResponse.to_string()
+        );
+
+        // errors without a string fall back to their description
+        assert_eq!(
+            ClientError::NullEntity.localize(&strings),
+            "Null entity access"
+        );
+    }
+}
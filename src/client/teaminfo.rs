@@ -0,0 +1,177 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Teammate status broadcast by the server.
+//!
+//! The protocol has no message for other players' health, so servers which share it follow the
+//! convention of QuakeWorld mods and stuff a line of the form
+//!
+//! ```text
+//! //tinfo <player id> <x> <y> <z> <health> <armor> <items> [name]
+//! ```
+//!
+//! for each teammate. The leading `//` makes the line a comment to clients which don't recognize
+//! it.
+
+use crate::common::net::{ItemFlags, MAX_CLIENTS};
+
+use cgmath::Vector3;
+use chrono::Duration;
+
+const TEAM_INFO_PREFIX: &str = "//tinfo";
+
+/// How long a teammate's status is displayed after its last update, in seconds.
+const TEAM_INFO_TIMEOUT_SECS: i64 = 5;
+
+/// The status of a teammate as of its last update.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TeammateStatus {
+    pub player_id: usize,
+    pub origin: Vector3<f32>,
+    pub health: i32,
+    pub armor: i32,
+    pub items: ItemFlags,
+}
+
+/// Parses a `//tinfo` line, returning `None` if it isn't one or is malformed.
+///
+/// The trailing name is ignored, since the client already knows every player's name.
+pub fn parse_team_info(line: &str) -> Option<TeammateStatus> {
+    let mut args = line.split_whitespace();
+    if args.next()? != TEAM_INFO_PREFIX {
+        return None;
+    }
+
+    let mut next_int = || args.next()?.parse::<i32>().ok();
+
+    let player_id = next_int()?;
+    if player_id < 0 || player_id as usize >= MAX_CLIENTS {
+        return None;
+    }
+
+    let origin = Vector3::new(next_int()? as f32, next_int()? as f32, next_int()? as f32);
+    let health = next_int()?;
+    let armor = next_int()?;
+    let items = ItemFlags::from_bits_truncate(next_int()? as u32);
+
+    Some(TeammateStatus {
+        player_id: player_id as usize,
+        origin,
+        health,
+        armor,
+        items,
+    })
+}
+
+/// Separates the `//tinfo` lines in stuffed text from the console commands.
+///
+/// Returns the text to be executed and the teammate updates, in order.
+pub fn split_stuff_text(text: &str) -> (String, Vec<TeammateStatus>) {
+    let mut commands = String::new();
+    let mut updates = Vec::new();
+
+    for line in text.split_inclusive('\n') {
+        match parse_team_info(line) {
+            Some(status) => updates.push(status),
+            None => commands.push_str(line),
+        }
+    }
+
+    (commands, updates)
+}
+
+/// The most recent status of each teammate.
+#[derive(Debug, Default)]
+pub struct TeamInfo {
+    // (status, time of the update), ordered by player ID
+    teammates: Vec<(TeammateStatus, Duration)>,
+}
+
+impl TeamInfo {
+    pub fn new() -> TeamInfo {
+        TeamInfo {
+            teammates: Vec::new(),
+        }
+    }
+
+    /// Records a teammate's status as of `time`.
+    pub fn update(&mut self, status: TeammateStatus, time: Duration) {
+        match self
+            .teammates
+            .binary_search_by_key(&status.player_id, |(s, _)| s.player_id)
+        {
+            Ok(i) => self.teammates[i] = (status, time),
+            Err(i) => self.teammates.insert(i, (status, time)),
+        }
+    }
+
+    /// Returns the teammates updated recently as of `time`, ordered by player ID.
+    pub fn current(&self, time: Duration) -> impl Iterator<Item = &TeammateStatus> {
+        let timeout = Duration::seconds(TEAM_INFO_TIMEOUT_SECS);
+        self.teammates
+            .iter()
+            .filter(move |(_, updated)| time - *updated <= timeout)
+            .map(|(status, _)| status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_team_info() {
+        assert_eq!(
+            parse_team_info("//tinfo 2 100 -50 24 87 150 4097 \"player\"\n"),
+            Some(TeammateStatus {
+                player_id: 2,
+                origin: Vector3::new(100.0, -50.0, 24.0),
+                health: 87,
+                armor: 150,
+                items: ItemFlags::from_bits_truncate(4097),
+            })
+        );
+
+        assert_eq!(parse_team_info("//tinfo 2 100 -50\n"), None);
+        assert_eq!(parse_team_info("//tinfo 255 0 0 0 100 0 0\n"), None);
+        assert_eq!(parse_team_info("bf\n"), None);
+    }
+
+    #[test]
+    fn test_split_stuff_text() {
+        let (commands, updates) = split_stuff_text("bf\n//tinfo 1 0 0 0 100 0 0\nbf\n");
+        assert_eq!(commands, "bf\nbf\n");
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].player_id, 1);
+    }
+
+    #[test]
+    fn test_team_info_timeout() {
+        let status = |player_id| parse_team_info(&format!("//tinfo {} 0 0 0 100 0 0", player_id));
+
+        let mut info = TeamInfo::new();
+        info.update(status(3).unwrap(), Duration::seconds(0));
+        info.update(status(1).unwrap(), Duration::seconds(4));
+
+        let ids = |time| info.current(time).map(|s| s.player_id).collect::<Vec<_>>();
+        assert_eq!(ids(Duration::seconds(5)), vec![1, 3]);
+        assert_eq!(ids(Duration::seconds(6)), vec![1]);
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Client clock synchronization diagnostics.
+//!
+//! The client clock advances by the local frame time and is kept between the times of the last
+//! two server messages so that entities can be interpolated. Small drift outside that window is
+//! corrected gradually by running the clock slightly fast or slow, rather than by jumping, which
+//! would make entities hitch. `cl_timegraph` displays recent samples of the drift along with the
+//! delay added by `cl_interp`.
+
+use std::collections::VecDeque;
+
+use chrono::Duration;
+
+/// The number of frames recorded by the time graph.
+pub const TIME_GRAPH_SAMPLES: usize = 128;
+
+// the clock may be sped up or slowed down by at most the frame time divided by this
+const NUDGE_RATE: i32 = 10;
+
+// drift larger than this is corrected immediately
+const SNAP_THRESHOLD_MS: i64 = 100;
+
+/// Corrects the drift of the client clock relative to the last two server message times.
+///
+/// Times within the window are returned unchanged. Times outside it are moved toward it by at
+/// most a tenth of the frame time, or moved onto its edge if they are too far away.
+pub fn nudge_time(time: Duration, msg_times: [Duration; 2], frame_time: Duration) -> Duration {
+    let (start, end) = if msg_times[1] <= msg_times[0] {
+        (msg_times[1], msg_times[0])
+    } else {
+        (msg_times[0], msg_times[1])
+    };
+
+    let target = if time < start {
+        start
+    } else if time > end {
+        end
+    } else {
+        return time;
+    };
+
+    let drift = target - time;
+    if drift.num_milliseconds().abs() > SNAP_THRESHOLD_MS {
+        return target;
+    }
+
+    let max_nudge = frame_time / NUDGE_RATE;
+    if drift > Duration::zero() {
+        time + drift.min(max_nudge)
+    } else {
+        time + drift.max(-max_nudge)
+    }
+}
+
+/// The state of the client clock for a single frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockSample {
+    /// The client time minus the time of the latest server message.
+    pub drift: Duration,
+
+    /// The local frame time.
+    pub frame_time: Duration,
+
+    /// The correction applied to the client clock.
+    pub nudge: Duration,
+
+    /// How long the latest server message was held by the interpolation delay buffer.
+    pub interp: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct TimeGraph {
+    samples: VecDeque<ClockSample>,
+}
+
+impl TimeGraph {
+    pub fn new() -> TimeGraph {
+        TimeGraph {
+            samples: VecDeque::with_capacity(TIME_GRAPH_SAMPLES),
+        }
+    }
+
+    /// Records a sample, discarding the oldest one if the graph is full.
+    pub fn push(&mut self, sample: ClockSample) {
+        if self.samples.len() >= TIME_GRAPH_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(sample);
+    }
+
+    /// Returns the most recent sample, if any.
+    pub fn latest(&self) -> Option<&ClockSample> {
+        self.samples.back()
+    }
+
+    /// Returns an iterator over the recorded samples from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &ClockSample> {
+        self.samples.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: i64) -> Duration {
+        Duration::milliseconds(n)
+    }
+
+    #[test]
+    fn test_nudge_time_in_window() {
+        assert_eq!(nudge_time(ms(1050), [ms(1100), ms(1000)], ms(16)), ms(1050));
+    }
+
+    #[test]
+    fn test_nudge_time_small_drift() {
+        // ahead of the latest message: slow down by a tenth of the frame
+        assert_eq!(nudge_time(ms(1120), [ms(1100), ms(1000)], ms(20)), ms(1118));
+
+        // behind the previous message: speed up, but don't overshoot
+        assert_eq!(nudge_time(ms(999), [ms(1100), ms(1000)], ms(20)), ms(1000));
+    }
+
+    #[test]
+    fn test_nudge_time_large_drift() {
+        assert_eq!(nudge_time(ms(5000), [ms(1100), ms(1000)], ms(20)), ms(1100));
+        assert_eq!(nudge_time(ms(0), [ms(1100), ms(1000)], ms(20)), ms(1000));
+    }
+
+    #[test]
+    fn test_time_graph_capacity() {
+        let mut graph = TimeGraph::new();
+        for i in 0..TIME_GRAPH_SAMPLES as i64 + 1 {
+            graph.push(ClockSample {
+                drift: ms(i),
+                frame_time: ms(16),
+                nudge: Duration::zero(),
+                interp: Duration::zero(),
+            });
+        }
+
+        assert_eq!(graph.iter().count(), TIME_GRAPH_SAMPLES);
+        assert_eq!(graph.iter().next().unwrap().drift, ms(1));
+        assert_eq!(graph.latest().unwrap().drift, ms(TIME_GRAPH_SAMPLES as i64));
+    }
+}
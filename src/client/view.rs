@@ -5,6 +5,7 @@ use crate::{
     common::{
         engine::{duration_from_f32, duration_to_f32},
         math::{self, Angles},
+        net,
     },
 };
 
@@ -12,6 +13,18 @@ use super::IntermissionKind;
 use cgmath::{Angle as _, Deg, InnerSpace as _, Vector3, Zero as _};
 use chrono::Duration;
 
+// rate in units per second at which the camera follows changes in view height
+const VIEW_HEIGHT_SPEED: f32 = 80.0;
+
+// view height changes larger than this are applied immediately rather than smoothed
+const VIEW_HEIGHT_SNAP_THRESHOLD: f32 = 32.0;
+
+// rate per second at which the view model's trailing angles catch up with the view
+const GUN_SWAY_SPEED: f32 = 10.0;
+
+// the view model never trails the view by more than this many degrees
+const GUN_SWAY_MAX: f32 = 10.0;
+
 pub struct View {
     // entity "holding" the camera
     entity_id: usize,
@@ -19,6 +32,12 @@ pub struct View {
     // how high the entity is "holding" the camera
     view_height: f32,
 
+    // view height actually applied to the camera, which follows view_height over time
+    smooth_view_height: f32,
+
+    // time of the last call to calc_final_origin
+    origin_time: Duration,
+
     // TODO
     ideal_pitch: Deg<f32>,
 
@@ -42,13 +61,27 @@ pub struct View {
 
     // final origin accounting for view bob
     final_origin: Vector3<f32>,
+
+    // time of the last call to calc_viewmodel
+    viewmodel_time: Duration,
+
+    // view angles as followed by the view model, which lag behind when turning
+    sway_angles: Angles,
+
+    viewmodel_origin: Vector3<f32>,
+    viewmodel_angles: Angles,
+
+    // position of the camera when it is detached from the view entity
+    free_origin: Option<Vector3<f32>>,
 }
 
 impl View {
     pub fn new() -> View {
         View {
             entity_id: 0,
-            view_height: 0.0,
+            view_height: net::DEFAULT_VIEWHEIGHT,
+            smooth_view_height: net::DEFAULT_VIEWHEIGHT,
+            origin_time: Duration::zero(),
             ideal_pitch: Deg(0.0),
             msg_angles: [Angles::zero(); 2],
             input_angles: Angles::zero(),
@@ -57,6 +90,11 @@ impl View {
             punch_angles: Angles::zero(),
             final_angles: Angles::zero(),
             final_origin: Vector3::zero(),
+            viewmodel_time: Duration::zero(),
+            sway_angles: Angles::zero(),
+            viewmodel_origin: Vector3::zero(),
+            viewmodel_angles: Angles::zero(),
+            free_origin: None,
         }
     }
 
@@ -64,7 +102,15 @@ impl View {
         self.entity_id
     }
 
+    /// Attaches the view to a different entity.
+    ///
+    /// The view height is not smoothed across the switch, since the old and new entities are
+    /// unrelated.
     pub fn set_entity_id(&mut self, id: usize) {
+        if id != self.entity_id {
+            self.smooth_view_height = self.view_height;
+        }
+
         self.entity_id = id;
     }
 
@@ -72,7 +118,14 @@ impl View {
         self.view_height
     }
 
+    /// Sets the view height.
+    ///
+    /// Small changes are smoothed over the following frames; large ones take effect immediately.
     pub fn set_view_height(&mut self, view_height: f32) {
+        if (view_height - self.smooth_view_height).abs() > VIEW_HEIGHT_SNAP_THRESHOLD {
+            self.smooth_view_height = view_height;
+        }
+
         self.view_height = view_height;
     }
 
@@ -101,6 +154,37 @@ impl View {
         self.input_angles = input_angles;
     }
 
+    /// Returns whether the camera is detached from the view entity.
+    pub fn is_free(&self) -> bool {
+        self.free_origin.is_some()
+    }
+
+    /// Detaches the camera from the view entity or reattaches it.
+    ///
+    /// A detached camera starts where the view currently is and is only moved by `move_free`.
+    pub fn set_free(&mut self, free: bool) {
+        self.free_origin = match free {
+            true => Some(self.free_origin.unwrap_or(self.final_origin)),
+            false => None,
+        };
+    }
+
+    /// Moves a detached camera along its view angles.
+    ///
+    /// `velocity` holds the forward, rightward and upward speeds in units per second.
+    pub fn move_free(&mut self, frame_time: Duration, velocity: Vector3<f32>) {
+        let origin = match self.free_origin.as_mut() {
+            Some(o) => o,
+            None => return,
+        };
+
+        let (sin_yaw, cos_yaw) = self.input_angles.yaw.sin_cos();
+        let right = Vector3::new(sin_yaw, -cos_yaw, 0.0);
+        let up = Vector3::unit_z();
+        *origin += (forward(self.input_angles) * velocity.x + right * velocity.y + up * velocity.z)
+            * duration_to_f32(frame_time);
+    }
+
     pub fn handle_input(
         &mut self,
         frame_time: Duration,
@@ -199,6 +283,11 @@ impl View {
         }
         let idle_angles = idle(time, idle_vars);
 
+        if self.is_free() {
+            self.final_angles = self.input_angles;
+            return;
+        }
+
         self.final_angles =
             self.input_angles + move_angles + damage_angles + self.punch_angles + idle_angles;
     }
@@ -214,25 +303,75 @@ impl View {
         velocity: Vector3<f32>,
         bob_vars: BobVars,
     ) {
+        // time may go backwards when a demo is restarted, in which case don't advance
+        let elapsed = duration_to_f32(time - self.origin_time).max(0.0);
+        self.origin_time = time;
+        self.smooth_view_height = approach(
+            self.smooth_view_height,
+            self.view_height,
+            elapsed * VIEW_HEIGHT_SPEED,
+        );
+
         // offset the view by 1/32 unit to keep it from intersecting liquid planes
         let plane_offset = Vector3::new(1.0 / 32.0, 1.0 / 32.0, 1.0 / 32.0);
-        let height_offset = Vector3::new(0.0, 0.0, self.view_height);
+        let height_offset = Vector3::new(0.0, 0.0, self.smooth_view_height);
         let bob_offset = Vector3::new(0.0, 0.0, bob(time, velocity, bob_vars));
-        self.final_origin = origin + plane_offset + height_offset + bob_offset;
+        self.final_origin = match self.free_origin {
+            Some(free_origin) => free_origin,
+            None => origin + plane_offset + height_offset + bob_offset,
+        };
     }
 
     pub fn final_origin(&self) -> Vector3<f32> {
         self.final_origin
     }
 
-    pub fn viewmodel_angle(&self) -> Angles {
-        // TODO
-        self.final_angles()
+    /// Positions the weapon view model relative to the final view.
+    ///
+    /// This must be called after `calc_final_angles` and `calc_final_origin`.
+    pub fn calc_viewmodel(
+        &mut self,
+        time: Duration,
+        velocity: Vector3<f32>,
+        bob_vars: BobVars,
+        gun_vars: GunVars,
+    ) {
+        let elapsed = duration_to_f32(time - self.viewmodel_time).max(0.0);
+        self.viewmodel_time = time;
+
+        // like the original, the gun bobs forward as well as up, on top of the view's own bob
+        let bob = bob(time, velocity, bob_vars) * gun_vars.cl_gunbob;
+        let forward = forward(self.final_angles);
+        self.viewmodel_origin =
+            self.final_origin + forward * bob * 0.4 + Vector3::new(0.0, 0.0, bob);
+
+        let step = (elapsed * GUN_SWAY_SPEED).min(1.0);
+        self.sway_angles.pitch = trail(self.sway_angles.pitch, self.final_angles.pitch, step);
+        self.sway_angles.yaw = trail(self.sway_angles.yaw, self.final_angles.yaw, step);
+
+        let sway = |trailing: Deg<f32>, target: Deg<f32>| {
+            let lag = wrap_degrees(trailing.0 - target.0).clamp(-GUN_SWAY_MAX, GUN_SWAY_MAX);
+            target + Deg(lag * gun_vars.cl_gunsway)
+        };
+        self.viewmodel_angles = Angles {
+            pitch: sway(self.sway_angles.pitch, self.final_angles.pitch),
+            yaw: sway(self.sway_angles.yaw, self.final_angles.yaw),
+            ..self.final_angles
+        };
+    }
+
+    pub fn viewmodel_origin(&self) -> Vector3<f32> {
+        self.viewmodel_origin
+    }
+
+    pub fn viewmodel_angles(&self) -> Angles {
+        self.viewmodel_angles
     }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct MouseVars {
+    pub freelook: f32,
     pub m_pitch: f32,
     pub m_yaw: f32,
     pub sensitivity: f32,
@@ -252,6 +391,15 @@ pub struct BobVars {
     pub cl_bobup: f32,
 }
 
+/// Moves `current` toward `target` by at most `max_step`.
+fn approach(current: f32, target: f32, max_step: f32) -> f32 {
+    if current < target {
+        (current + max_step).min(target)
+    } else {
+        (current - max_step).max(target)
+    }
+}
+
 pub fn bob(time: Duration, velocity: Vector3<f32>, vars: BobVars) -> f32 {
     let time = duration_to_f32(time);
     let ratio = (time % vars.cl_bobcycle) / vars.cl_bobcycle;
@@ -268,6 +416,31 @@ pub fn bob(time: Duration, velocity: Vector3<f32>, vars: BobVars) -> f32 {
     bob.max(-7.0).min(4.0)
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct GunVars {
+    /// Scale of the view model's bob.
+    pub cl_gunbob: f32,
+    /// Scale of the view model's lag behind the view when turning.
+    pub cl_gunsway: f32,
+}
+
+/// Returns the forward direction of `angles`.
+fn forward(angles: Angles) -> Vector3<f32> {
+    let (sin_pitch, cos_pitch) = angles.pitch.sin_cos();
+    let (sin_yaw, cos_yaw) = angles.yaw.sin_cos();
+    Vector3::new(cos_pitch * cos_yaw, cos_pitch * sin_yaw, -sin_pitch)
+}
+
+/// Maps an angle in degrees into [-180, 180).
+fn wrap_degrees(degrees: f32) -> f32 {
+    (degrees + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Moves `current` a fraction `step` of the way toward `target` along the shorter direction.
+fn trail(current: Deg<f32>, target: Deg<f32>, step: f32) -> Deg<f32> {
+    current + Deg(wrap_degrees(target.0 - current.0) * step)
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct RollVars {
     pub cl_rollangle: f32,
@@ -308,3 +481,140 @@ pub fn idle(time: Duration, vars: IdleVars) -> Angles {
 
     Angles { pitch, roll, yaw }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_approach() {
+        assert_eq!(approach(0.0, 22.0, 5.0), 5.0);
+        assert_eq!(approach(22.0, 0.0, 5.0), 17.0);
+        assert_eq!(approach(20.0, 22.0, 5.0), 22.0);
+    }
+
+    #[test]
+    fn test_view_height_smoothing() {
+        let mut view = View::new();
+        view.calc_final_origin(Duration::zero(), Vector3::zero(), Vector3::zero(), NO_BOB);
+        assert_eq!(view.smooth_view_height, net::DEFAULT_VIEWHEIGHT);
+
+        // a small change is spread across frames
+        view.set_view_height(12.0);
+        view.calc_final_origin(
+            Duration::milliseconds(100),
+            Vector3::zero(),
+            Vector3::zero(),
+            NO_BOB,
+        );
+        assert_eq!(view.smooth_view_height, 14.0);
+
+        // switching entities snaps to the new height
+        view.set_entity_id(2);
+        assert_eq!(view.smooth_view_height, 12.0);
+    }
+
+    #[test]
+    fn test_free_camera() {
+        let mut view = View::new();
+        view.calc_final_origin(Duration::zero(), Vector3::zero(), Vector3::zero(), NO_BOB);
+        let start = view.final_origin();
+
+        view.set_free(true);
+        view.update_input_angles(Angles {
+            pitch: Deg(0.0),
+            roll: Deg(0.0),
+            yaw: Deg(90.0),
+        });
+        view.move_free(Duration::seconds(1), Vector3::new(100.0, 0.0, 0.0));
+
+        // the entity's origin no longer moves the camera
+        view.calc_final_origin(
+            Duration::seconds(1),
+            Vector3::new(500.0, 0.0, 0.0),
+            Vector3::zero(),
+            NO_BOB,
+        );
+        let moved = view.final_origin() - start;
+        assert!(moved.x.abs() < 1e-3);
+        assert!((moved.y - 100.0).abs() < 1e-3);
+
+        view.set_free(false);
+        assert!(!view.is_free());
+    }
+
+    #[test]
+    fn test_viewmodel_bob() {
+        let bob_vars = BobVars {
+            cl_bob: 0.02,
+            ..NO_BOB
+        };
+        let gun_vars = GunVars {
+            cl_gunbob: 1.0,
+            cl_gunsway: 0.0,
+        };
+        let time = Duration::milliseconds(150);
+        let velocity = Vector3::new(320.0, 0.0, 0.0);
+        let expected = bob(time, velocity, bob_vars);
+
+        let mut view = View::new();
+        view.calc_viewmodel(time, velocity, bob_vars, gun_vars);
+        let offset = view.viewmodel_origin() - view.final_origin();
+        assert!((offset - Vector3::new(expected * 0.4, 0.0, expected)).magnitude() < 1e-4);
+
+        // no extra bob with cl_gunbob 0
+        view.calc_viewmodel(
+            time,
+            velocity,
+            bob_vars,
+            GunVars {
+                cl_gunbob: 0.0,
+                ..gun_vars
+            },
+        );
+        assert_eq!(view.viewmodel_origin(), view.final_origin());
+    }
+
+    #[test]
+    fn test_viewmodel_sway() {
+        let gun_vars = GunVars {
+            cl_gunbob: 0.0,
+            cl_gunsway: 1.0,
+        };
+
+        let mut view = View::new();
+        view.calc_viewmodel(Duration::zero(), Vector3::zero(), NO_BOB, gun_vars);
+
+        // turn across the 180 degree boundary in one frame
+        view.final_angles.yaw = Deg(-175.0);
+        view.sway_angles.yaw = Deg(175.0);
+        view.calc_viewmodel(
+            Duration::milliseconds(50),
+            Vector3::zero(),
+            NO_BOB,
+            gun_vars,
+        );
+
+        // the gun trails behind by half the 10 degree turn, the short way around
+        let yaw = view.viewmodel_angles().yaw.0;
+        assert!((wrap_degrees(yaw + 175.0) - -5.0).abs() < 1e-3);
+
+        // without sway the gun follows the view exactly
+        view.calc_viewmodel(
+            Duration::milliseconds(50),
+            Vector3::zero(),
+            NO_BOB,
+            GunVars {
+                cl_gunsway: 0.0,
+                ..gun_vars
+            },
+        );
+        assert_eq!(view.viewmodel_angles().yaw, view.final_angles().yaw);
+    }
+
+    const NO_BOB: BobVars = BobVars {
+        cl_bob: 0.0,
+        cl_bobcycle: 0.6,
+        cl_bobup: 0.5,
+    };
+}
@@ -44,6 +44,9 @@ use thiserror::Error;
 
 const VERSION: i32 = 29;
 
+const LIT_MAGIC: [u8; 4] = *b"QLIT";
+const LIT_VERSION: i32 = 1;
+
 pub const MAX_MODELS: usize = 256;
 const MAX_LEAVES: usize = 32767;
 
@@ -96,6 +99,15 @@ pub enum BspFileError {
     InvalidTextureFrameSpecifier(String),
     #[error("texture has primary animation with 0 frames: {0}")]
     EmptyPrimaryAnimation(String),
+    #[error("invalid .lit file magic: {0:?}")]
+    InvalidLitMagic([u8; 4]),
+    #[error(
+        "unsupported .lit format version (expected {}, found {0})",
+        LIT_VERSION
+    )]
+    UnsupportedLitVersion(i32),
+    #[error("invalid .lit lightmap size (expected {expected}, found {found})")]
+    InvalidLitSize { expected: usize, found: usize },
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -379,11 +391,54 @@ where
     })
 }
 
+/// Read the RGB lightmap data from a `.lit` file.
+///
+/// The data mirrors the BSP lightmap lump with three bytes per sample, so it must be exactly
+/// `3 * lightmap_len` bytes long.
+fn read_lit<R>(mut reader: R, lightmap_len: usize) -> Result<Box<[u8]>, BspFileError>
+where
+    R: Read,
+{
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != LIT_MAGIC {
+        return Err(BspFileError::InvalidLitMagic(magic));
+    }
+
+    match reader.read_i32::<LittleEndian>()? {
+        LIT_VERSION => (),
+        other => return Err(BspFileError::UnsupportedLitVersion(other)),
+    }
+
+    let expected = 3 * lightmap_len;
+    let mut rgb = Vec::with_capacity(expected);
+    reader.read_to_end(&mut rgb)?;
+    if rgb.len() != expected {
+        return Err(BspFileError::InvalidLitSize {
+            expected,
+            found: rgb.len(),
+        });
+    }
+
+    Ok(rgb.into_boxed_slice())
+}
+
 /// Load a BSP file, returning the models it contains and a `String` describing the entities
 /// it contains.
 pub fn load<R>(data: R) -> Result<(Vec<Model>, String), failure::Error>
 where
     R: Read + Seek,
+{
+    load_with_lit(data, None::<R>)
+}
+
+/// Load a BSP file along with the colored lighting from its `.lit` file, if one is given.
+///
+/// An invalid `.lit` file is ignored with a warning, leaving the map with white light.
+pub fn load_with_lit<R, L>(data: R, lit: Option<L>) -> Result<(Vec<Model>, String), failure::Error>
+where
+    R: Read + Seek,
+    L: Read,
 {
     let mut reader = BufReader::new(data);
 
@@ -598,8 +653,8 @@ where
     for (
         name,
         BspFileTextureAnimations {
-            primary: mut pri,
-            alternate: mut alt,
+            primary: pri,
+            alternate: alt,
         },
     ) in anim_file_textures.into_iter()
     {
@@ -607,9 +662,7 @@ where
             Err(BspFileError::EmptyPrimaryAnimation(name.to_owned()))?;
         }
 
-        // TODO: ensure one-to-one frame specifiers
-        // sort names in ascending order to get the frames ordered correctly
-        pri.sort_unstable_by(|(_, tex), (_, other)| tex.name.cmp(&other.name));
+        let pri = sequence_texture_frames(&name, pri, '0');
 
         // TODO: verify width and height?
         let width = pri[0].1.width;
@@ -633,9 +686,8 @@ where
         let alternate = match alt.len() {
             0 => None,
             _ => {
-                alt.sort_unstable_by(|(_, tex), (_, other)| tex.name.cmp(&other.name));
                 let mut alternate = Vec::new();
-                for (file_id, file_texture) in alt {
+                for (file_id, file_texture) in sequence_texture_frames(&name, alt, 'a') {
                     alt_corresp_file_ids.push(file_id);
                     alternate.push(BspTextureFrame {
                         mipmaps: file_texture.mipmaps,
@@ -780,6 +832,14 @@ where
         .read_to_end(&mut lightmaps)?;
     table.check_end_position(&mut reader, BspFileSectionId::Lightmaps)?;
 
+    let lit_lightmaps = lit.and_then(|l| match read_lit(l, lightmaps.len()) {
+        Ok(rgb) => Some(rgb),
+        Err(e) => {
+            warn!("Ignoring .lit file: {}", e);
+            None
+        }
+    });
+
     reader.seek(SeekFrom::Start(collision_node_section.offset))?;
 
     let mut collision_nodes = Vec::with_capacity(collision_node_count);
@@ -1022,6 +1082,7 @@ where
         texinfo: texinfo.into_boxed_slice(),
         faces: faces.into_boxed_slice(),
         lightmaps: lightmaps.into_boxed_slice(),
+        lit_lightmaps,
         hulls: [hull_0, hull_1, hull_2],
         leaves: leaves.into_boxed_slice(),
         facelist: facelist.into_boxed_slice(),
@@ -1117,3 +1178,89 @@ where
     reader.read_i16_into::<LittleEndian>(&mut ar)?;
     Ok(ar)
 }
+
+/// Orders the frames of one animation of the texture `name` by their frame specifiers.
+///
+/// The specifiers should run without gaps starting from `first`, e.g. `+0`, `+1`, `+2`. The
+/// original engine refuses to load maps where they don't, but plenty of maps in the wild have
+/// gaps, so the frames that are present are kept in order with a warning.
+fn sequence_texture_frames(
+    name: &str,
+    mut frames: Vec<(usize, BspFileTexture)>,
+    first: char,
+) -> Vec<(usize, BspFileTexture)> {
+    // frame specifiers are a single character, so sorting by name orders the frames
+    frames.sort_unstable_by(|(_, tex), (_, other)| tex.name.cmp(&other.name));
+
+    for (i, (_, tex)) in frames.iter().enumerate() {
+        let expected = (first as u8 + i as u8) as char;
+        if tex.name.chars().nth(1) != Some(expected) {
+            warn!("Animated texture {} is missing frame {}", name, expected);
+            break;
+        }
+    }
+
+    frames
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn file_texture(name: &str) -> BspFileTexture {
+        BspFileTexture {
+            name: name.to_owned(),
+            width: 16,
+            height: 16,
+            mipmaps: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+        }
+    }
+
+    #[test]
+    fn test_sequence_texture_frames() {
+        let frames = vec![
+            (4, file_texture("+2slip")),
+            (7, file_texture("+0slip")),
+            (1, file_texture("+1slip")),
+        ];
+        let ids: Vec<usize> = sequence_texture_frames("slip", frames, '0')
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, vec![7, 1, 4]);
+
+        // a gap in the chain keeps the frames that are there
+        let frames = vec![(3, file_texture("+cbutton")), (2, file_texture("+abutton"))];
+        let ids: Vec<usize> = sequence_texture_frames("button", frames, 'a')
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_read_lit() {
+        let mut lit = b"QLIT".to_vec();
+        lit.extend_from_slice(&1i32.to_le_bytes());
+        lit.extend_from_slice(&[255, 128, 0, 10, 20, 30]);
+
+        let rgb = read_lit(lit.as_slice(), 2).unwrap();
+        assert_eq!(&*rgb, &[255, 128, 0, 10, 20, 30]);
+
+        // one sample short
+        assert!(matches!(
+            read_lit(lit.as_slice(), 3),
+            Err(BspFileError::InvalidLitSize {
+                expected: 9,
+                found: 6
+            })
+        ));
+
+        let mut bad_magic = lit.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            read_lit(bad_magic.as_slice(), 2),
+            Err(BspFileError::InvalidLitMagic(_))
+        ));
+    }
+}
@@ -125,10 +125,10 @@ use crate::common::math::{Hyperplane, HyperplaneSide, LinePlaneIntersect};
 // TODO: Either Trace should be moved into common or the functions requiring it should be moved into server
 use crate::server::world::{Trace, TraceEnd, TraceStart};
 
-use cgmath::Vector3;
+use cgmath::{InnerSpace as _, Vector3, Zero as _};
 use chrono::Duration;
 
-pub use self::load::{load, BspFileError};
+pub use self::load::{load, load_with_lit, BspFileError};
 
 // this is 4 in the original source, but the 4th hull is never used.
 const MAX_HULLS: usize = 3;
@@ -139,6 +139,9 @@ pub const MAX_SOUNDS: usize = 4;
 pub const MIPLEVELS: usize = 4;
 const DIST_EPSILON: f32 = 0.03125;
 
+// how far below a point `BspData::light_point` looks for a surface
+const LIGHT_POINT_DISTANCE: f32 = 2048.0;
+
 pub fn frame_duration() -> Duration {
     Duration::milliseconds(200)
 }
@@ -682,6 +685,7 @@ pub struct BspLightmap<'a> {
     width: u32,
     height: u32,
     data: &'a [u8],
+    rgb: Option<&'a [u8]>,
 }
 
 impl<'a> BspLightmap<'a> {
@@ -696,6 +700,30 @@ impl<'a> BspLightmap<'a> {
     pub fn data(&self) -> &[u8] {
         self.data
     }
+
+    /// Returns the colored lightmap data from the map's `.lit` file, if it has one.
+    pub fn rgb(&self) -> Option<&[u8]> {
+        self.rgb
+    }
+
+    /// Returns the lightmap as RGBA texels, using white light if the map has no colored
+    /// lighting.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(4 * self.data.len());
+        match self.rgb {
+            Some(rgb) => {
+                for texel in rgb.chunks_exact(3) {
+                    rgba.extend_from_slice(&[texel[0], texel[1], texel[2], 255]);
+                }
+            }
+            None => {
+                for &l in self.data {
+                    rgba.extend_from_slice(&[l, l, l, 255]);
+                }
+            }
+        }
+        rgba
+    }
 }
 
 #[derive(Debug)]
@@ -708,6 +736,7 @@ pub struct BspData {
     pub(crate) texinfo: Box<[BspTexInfo]>,
     pub(crate) faces: Box<[BspFace]>,
     pub(crate) lightmaps: Box<[u8]>,
+    pub(crate) lit_lightmaps: Option<Box<[u8]>>,
     pub(crate) leaves: Box<[BspLeaf]>,
     pub(crate) facelist: Box<[usize]>,
     pub(crate) edges: Box<[BspEdge]>,
@@ -772,6 +801,10 @@ impl BspData {
                             width: lightmap_w,
                             height: lightmap_h,
                             data: &self.lightmaps[start..end],
+                            rgb: self
+                                .lit_lightmaps
+                                .as_ref()
+                                .map(|rgb| &rgb[3 * start..3 * end]),
                         }
                     })
                     .collect()
@@ -828,40 +861,142 @@ impl BspData {
         }
     }
 
+    /// Returns the light level at a point, sampled from the lightmap of the surface beneath it.
+    ///
+    /// The lightmap is sampled where a line straight down from the point first hits a lit surface,
+    /// with each of the surface's light styles scaled by its value in `style_values`. Light is given
+    /// per color channel, where 255 is the brightest a lightmap texel can be at normal intensity.
+    /// Points with no surface beneath them are unlit.
+    pub fn light_point(&self, point: Vector3<f32>, style_values: &[f32]) -> Vector3<f32> {
+        let end = point - Vector3::new(0.0, 0.0, LIGHT_POINT_DISTANCE);
+        self.recursive_light_point(0, point, end, style_values)
+            .map_or(Vector3::zero(), |(light, _)| light)
+    }
+
+    /// Returns the point on the surface from which `light_point` would sample the light level.
+    ///
+    /// Like GLQuake's `lightspot`, this is the floor beneath the point, used to place shadows.
+    pub fn light_spot(&self, point: Vector3<f32>) -> Option<Vector3<f32>> {
+        let end = point - Vector3::new(0.0, 0.0, LIGHT_POINT_DISTANCE);
+        self.recursive_light_point(0, point, end, &[])
+            .map(|(_, spot)| spot)
+    }
+
+    fn recursive_light_point(
+        &self,
+        node_id: usize,
+        start: Vector3<f32>,
+        end: Vector3<f32>,
+        style_values: &[f32],
+    ) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let node = &self.render_nodes[node_id];
+        let plane = &self.planes[node.plane_id];
+        let front = plane.point_dist(start);
+        let back = plane.point_dist(end);
+        let side = (front < 0.0) as usize;
+
+        // if the line doesn't cross the plane, it can't hit any of this node's faces
+        if (back < 0.0) as usize == side {
+            return self.child_light_point(&node.children[side], start, end, style_values);
+        }
+
+        let mid = start + (front / (front - back)) * (end - start);
+
+        // anything on the near side of the plane is hit first
+        if let Some(light) = self.child_light_point(&node.children[side], start, mid, style_values)
+        {
+            return Some(light);
+        }
+
+        for face_id in node.face_id..node.face_id + node.face_count {
+            if let Some(light) = self.face_light_at(face_id, mid, style_values) {
+                return Some((light, mid));
+            }
+        }
+
+        self.child_light_point(&node.children[1 - side], mid, end, style_values)
+    }
+
+    fn child_light_point(
+        &self,
+        child: &BspRenderNodeChild,
+        start: Vector3<f32>,
+        end: Vector3<f32>,
+        style_values: &[f32],
+    ) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        match *child {
+            BspRenderNodeChild::Node(node_id) => {
+                self.recursive_light_point(node_id, start, end, style_values)
+            }
+            BspRenderNodeChild::Leaf(_) => None,
+        }
+    }
+
+    /// Samples a face's lightmap at a point on its plane, or returns `None` if the point is
+    /// outside the face's lightmap.
+    fn face_light_at(
+        &self,
+        face_id: usize,
+        point: Vector3<f32>,
+        style_values: &[f32],
+    ) -> Option<Vector3<f32>> {
+        let face = &self.faces[face_id];
+        let texinfo = &self.texinfo[face.texinfo_id];
+
+        // sky and liquids have no lightmaps
+        if texinfo.special {
+            return None;
+        }
+
+        let s =
+            (point.dot(texinfo.s_vector) + texinfo.s_offset) as i32 - face.texture_mins[0] as i32;
+        let t =
+            (point.dot(texinfo.t_vector) + texinfo.t_offset) as i32 - face.texture_mins[1] as i32;
+        if s < 0 || t < 0 || s > face.extents[0] as i32 || t > face.extents[1] as i32 {
+            return None;
+        }
+
+        let lightmap_id = match face.lightmap_id {
+            Some(id) => id,
+            None => return Some(Vector3::zero()),
+        };
+
+        let lightmap_w = face.extents[0] as usize / 16 + 1;
+        let lightmap_h = face.extents[1] as usize / 16 + 1;
+        let texel = (t as usize / 16) * lightmap_w + s as usize / 16;
+
+        let mut light = Vector3::zero();
+        for (i, &style) in face
+            .light_styles
+            .iter()
+            .take_while(|style| **style != 255)
+            .enumerate()
+        {
+            let id = lightmap_id + lightmap_w * lightmap_h * i + texel;
+            let color = match self.lit_lightmaps {
+                Some(ref rgb) => Vector3::new(rgb[3 * id], rgb[3 * id + 1], rgb[3 * id + 2])
+                    .cast::<f32>()
+                    .unwrap(),
+                None => Vector3::new(1.0, 1.0, 1.0) * self.lightmaps[id] as f32,
+            };
+            light += color * style_values.get(style as usize).copied().unwrap_or(1.0);
+        }
+
+        Some(light)
+    }
+
+    /// Returns the ids of the leaves potentially visible from the given leaf.
+    ///
+    /// If the leaf has no visibility data, all leaves are considered visible.
     pub fn get_pvs(&self, leaf_id: usize, leaf_count: usize) -> Vec<usize> {
         // leaf 0 is outside the map, everything is visible
         if leaf_id == 0 {
-            return Vec::new();
+            return (1..leaf_count).collect();
         }
 
         match self.leaves[leaf_id].vis_offset {
-            Some(o) => {
-                let mut visleaf = 1;
-                let mut visleaf_list = Vec::new();
-                let mut it = (&self.visibility[o..]).iter();
-
-                while visleaf < leaf_count {
-                    let byte = it.next().unwrap();
-                    match *byte {
-                        // a zero byte signals the start of an RLE sequence
-                        0 => visleaf += 8 * *it.next().unwrap() as usize,
-
-                        bits => {
-                            for shift in 0..8 {
-                                if bits & 1 << shift != 0 {
-                                    visleaf_list.push(visleaf);
-                                }
-
-                                visleaf += 1;
-                            }
-                        }
-                    }
-                }
-
-                visleaf_list
-            }
-
-            None => Vec::new(),
+            Some(o) => decompress_vis(&self.visibility[o..], leaf_count),
+            None => (1..leaf_count).collect(),
         }
     }
 
@@ -979,6 +1114,31 @@ impl BspModel {
         self.bsp_data.leaves[self.leaf_id..self.leaf_id + self.leaf_count + 1].iter()
     }
 
+    /// Returns whether this model's visibility data allows seeing through liquids.
+    ///
+    /// Unless a map is vised with transparent water, liquid leaves can only see other liquid
+    /// leaves, and drawing the surface translucent would reveal missing geometry beneath it. Only
+    /// the world model has visibility data, so this is only meaningful for the world.
+    pub fn is_water_vised(&self) -> bool {
+        // the visibility data is indexed by world leaf ids
+        let leaf_count = self.leaf_count + 1;
+        let leaves = &self.bsp_data.leaves;
+
+        (self.leaf_id..self.leaf_id + leaf_count).any(|leaf_id| {
+            let liquid = !matches!(
+                leaves[leaf_id].contents,
+                BspLeafContents::Empty | BspLeafContents::Solid
+            );
+
+            liquid
+                && self
+                    .bsp_data
+                    .get_pvs(leaf_id, leaf_count)
+                    .into_iter()
+                    .any(|vis_id| leaves[vis_id].contents == BspLeafContents::Empty)
+        })
+    }
+
     pub fn iter_faces(&self) -> impl Iterator<Item = &BspFace> {
         self.bsp_data.facelist[self.face_id..self.face_id + self.face_count]
             .iter()
@@ -1011,6 +1171,43 @@ impl BspModel {
 
 impl BspData {}
 
+/// Decodes run-length encoded visibility data into a list of visible leaf ids.
+///
+/// Each bit of `data` marks one leaf as visible, starting from leaf 1, while a zero byte is followed
+/// by the number of bytes of zeros it replaces. Truncated data leaves the remaining leaves hidden.
+fn decompress_vis(data: &[u8], leaf_count: usize) -> Vec<usize> {
+    let mut visleaf = 1;
+    let mut visleaf_list = Vec::new();
+    let mut it = data.iter();
+
+    while visleaf < leaf_count {
+        let byte = match it.next() {
+            Some(b) => *b,
+            None => break,
+        };
+
+        match byte {
+            // a zero byte signals the start of an RLE sequence
+            0 => match it.next() {
+                Some(count) => visleaf += 8 * *count as usize,
+                None => break,
+            },
+
+            bits => {
+                for shift in 0..8 {
+                    if bits & 1 << shift != 0 && visleaf < leaf_count {
+                        visleaf_list.push(visleaf);
+                    }
+
+                    visleaf += 1;
+                }
+            }
+        }
+    }
+
+    visleaf_list
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1068,4 +1265,89 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_light_point() {
+        let hull =
+            || BspCollisionHull::for_bounds(Vector3::zero(), Vector3::new(1.0, 1.0, 1.0)).unwrap();
+
+        // a single 32x32 floor at z = 0 with a 3x3 lightmap in two styles
+        let lightmap: Vec<u8> = (0..9).chain(100..109).collect();
+        let bsp = BspData {
+            planes: Rc::new(vec![Hyperplane::axis_z(0.0)].into_boxed_slice()),
+            textures: Box::new([]),
+            vertices: Box::new([]),
+            visibility: Box::new([]),
+            render_nodes: vec![BspRenderNode {
+                plane_id: 0,
+                children: [BspRenderNodeChild::Leaf(1), BspRenderNodeChild::Leaf(0)],
+                min: [0; 3],
+                max: [0; 3],
+                face_id: 0,
+                face_count: 1,
+            }]
+            .into_boxed_slice(),
+            texinfo: vec![BspTexInfo {
+                s_vector: Vector3::unit_x(),
+                s_offset: 0.0,
+                t_vector: Vector3::unit_y(),
+                t_offset: 0.0,
+                tex_id: 0,
+                special: false,
+            }]
+            .into_boxed_slice(),
+            faces: vec![BspFace {
+                plane_id: 0,
+                side: BspFaceSide::Front,
+                edge_id: 0,
+                edge_count: 0,
+                texinfo_id: 0,
+                light_styles: [0, 1, 255, 255],
+                lightmap_id: Some(0),
+                texture_mins: [0, 0],
+                extents: [32, 32],
+            }]
+            .into_boxed_slice(),
+            lightmaps: lightmap.into_boxed_slice(),
+            lit_lightmaps: None,
+            leaves: Box::new([]),
+            facelist: Box::new([]),
+            edges: Box::new([]),
+            edgelist: Box::new([]),
+            hulls: [hull(), hull(), hull()],
+        };
+
+        let styles = [1.0, 0.5];
+        assert_eq!(
+            bsp.light_point(Vector3::new(8.0, 8.0, 64.0), &styles),
+            Vector3::new(50.0, 50.0, 50.0)
+        );
+        assert_eq!(
+            bsp.light_point(Vector3::new(20.0, 20.0, 64.0), &styles),
+            Vector3::new(56.0, 56.0, 56.0)
+        );
+
+        // outside the face, and below it
+        assert_eq!(
+            bsp.light_point(Vector3::new(64.0, 8.0, 64.0), &styles),
+            Vector3::zero()
+        );
+        assert_eq!(
+            bsp.light_point(Vector3::new(8.0, 8.0, -64.0), &styles),
+            Vector3::zero()
+        );
+    }
+
+    #[test]
+    fn test_decompress_vis() {
+        // leaves 1 and 3, then 16 hidden leaves, then leaf 26
+        let data = [0b0000_0101, 0, 2, 0b0000_0010];
+        assert_eq!(decompress_vis(&data, 30), vec![1, 3, 26]);
+
+        // bits past the last leaf are ignored
+        assert_eq!(decompress_vis(&[0xff], 4), vec![1, 2, 3]);
+
+        // truncated data hides the rest of the leaves
+        assert_eq!(decompress_vis(&[0b0000_0001, 0], 30), vec![1]);
+    }
 }
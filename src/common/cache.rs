@@ -0,0 +1,380 @@
+// Copyright © 2018 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Storage for content downloaded from servers.
+//!
+//! Downloaded files are stored by their SHA-1 checksum in a directory of their own rather than at
+//! their virtual paths, so a server can never overwrite the user's own files. The cache is mounted
+//! below everything else in the [`Vfs`](crate::common::vfs::Vfs), and the least recently stored
+//! files are evicted once it grows past its size cap.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::common::console::CmdRegistry;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Name of the file mapping virtual paths to checksums.
+const INDEX_NAME: &str = "index.json";
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid cache index: {0}")]
+    Index(#[from] serde_json::Error),
+    #[error("Checksum mismatch for {path}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("{path} is {size} bytes, larger than the cache size cap")]
+    TooLarge { path: String, size: u64 },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    sha1: String,
+    size: u64,
+
+    // incremented on every store, used to evict the oldest files first
+    sequence: u64,
+}
+
+#[derive(Debug)]
+pub struct DownloadCache {
+    dir: PathBuf,
+    max_size: u64,
+    entries: BTreeMap<String, CacheEntry>,
+    next_sequence: u64,
+}
+
+impl DownloadCache {
+    /// Opens the cache in the given directory, creating it if necessary.
+    ///
+    /// `max_size` is the size cap in bytes.
+    pub fn open<P>(dir: P, max_size: u64) -> Result<DownloadCache, CacheError>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let entries: BTreeMap<String, CacheEntry> = match fs::read(dir.join(INDEX_NAME)) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => Err(e)?,
+        };
+
+        let next_sequence = entries.values().map(|e| e.sequence + 1).max().unwrap_or(0);
+
+        let mut cache = DownloadCache {
+            dir,
+            max_size,
+            entries,
+            next_sequence,
+        };
+
+        // the cap may have been lowered since the cache was last used
+        cache.evict(0)?;
+
+        Ok(cache)
+    }
+
+    /// Returns the directory holding the cached files.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the size cap in bytes.
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// Changes the size cap, evicting the oldest files if the cache no longer fits under it.
+    pub fn set_max_size(&mut self, max_size: u64) -> Result<(), CacheError> {
+        self.max_size = max_size;
+        self.evict(0)
+    }
+
+    /// Returns the total size of the cached files in bytes.
+    pub fn size(&self) -> u64 {
+        self.entries.values().map(|e| e.size).sum()
+    }
+
+    /// Returns the number of cached files.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the virtual paths of all cached files.
+    pub fn file_names(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    /// Stores a downloaded file under the given virtual path.
+    ///
+    /// If `expected_sha1` is provided, the data is rejected unless its checksum matches. Returns
+    /// the SHA-1 checksum of the data.
+    pub fn store<S>(
+        &mut self,
+        virtual_path: S,
+        data: &[u8],
+        expected_sha1: Option<&str>,
+    ) -> Result<String, CacheError>
+    where
+        S: AsRef<str>,
+    {
+        let vp = virtual_path.as_ref();
+        let sha1 = sha1::Sha1::from(data).digest().to_string();
+
+        if let Some(expected) = expected_sha1 {
+            if !expected.eq_ignore_ascii_case(&sha1) {
+                return Err(CacheError::ChecksumMismatch {
+                    path: vp.to_owned(),
+                    expected: expected.to_owned(),
+                    actual: sha1,
+                });
+            }
+        }
+
+        let size = data.len() as u64;
+        if size > self.max_size {
+            return Err(CacheError::TooLarge {
+                path: vp.to_owned(),
+                size,
+            });
+        }
+
+        // replacing a file frees its space before anything else is evicted
+        if let Some(old) = self.entries.remove(vp) {
+            self.remove_data(&old.sha1)?;
+        }
+
+        self.evict(size)?;
+
+        fs::write(self.data_path(&sha1), data)?;
+        self.entries.insert(
+            vp.to_owned(),
+            CacheEntry {
+                sha1: sha1.clone(),
+                size,
+                sequence: self.next_sequence,
+            },
+        );
+        self.next_sequence += 1;
+        self.write_index()?;
+
+        Ok(sha1)
+    }
+
+    /// Reads a cached file.
+    ///
+    /// Returns `None` if the file is not cached, or if its contents no longer match the checksum
+    /// it was stored with.
+    pub fn read<S>(&self, virtual_path: S) -> Option<Vec<u8>>
+    where
+        S: AsRef<str>,
+    {
+        let vp = virtual_path.as_ref();
+        let entry = self.entries.get(vp)?;
+        let data = fs::read(self.data_path(&entry.sha1)).ok()?;
+
+        if sha1::Sha1::from(&data).digest().to_string() != entry.sha1 {
+            log::warn!("Cached copy of {} is corrupt, ignoring", vp);
+            return None;
+        }
+
+        Some(data)
+    }
+
+    /// Removes every cached file. Returns the number of bytes freed.
+    pub fn clear(&mut self) -> Result<u64, CacheError> {
+        let size = self.size();
+        let entries = std::mem::take(&mut self.entries);
+        for entry in entries.values() {
+            self.remove_data(&entry.sha1)?;
+        }
+        self.write_index()?;
+
+        Ok(size)
+    }
+
+    /// Evicts the oldest files until `incoming` more bytes fit under the size cap.
+    fn evict(&mut self, incoming: u64) -> Result<(), CacheError> {
+        let mut size = self.size();
+        if size + incoming <= self.max_size {
+            return Ok(());
+        }
+
+        let mut oldest = self
+            .entries
+            .iter()
+            .map(|(path, entry)| (entry.sequence, path.clone()))
+            .collect::<Vec<_>>();
+        oldest.sort();
+
+        for (_, path) in oldest {
+            if size + incoming <= self.max_size {
+                break;
+            }
+
+            let entry = self.entries.remove(&path).unwrap();
+            log::debug!("Evicting {} from download cache", path);
+            self.remove_data(&entry.sha1)?;
+            size -= entry.size;
+        }
+
+        self.write_index()
+    }
+
+    /// Removes the data for a checksum unless another entry still refers to it.
+    fn remove_data(&self, sha1: &str) -> Result<(), CacheError> {
+        if self.entries.values().any(|e| e.sha1 == sha1) {
+            return Ok(());
+        }
+
+        match fs::remove_file(self.data_path(sha1)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e)?,
+            _ => Ok(()),
+        }
+    }
+
+    fn data_path(&self, sha1: &str) -> PathBuf {
+        self.dir.join(sha1)
+    }
+
+    fn write_index(&self) -> Result<(), CacheError> {
+        fs::write(
+            self.dir.join(INDEX_NAME),
+            serde_json::to_vec_pretty(&self.entries)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn register_cmds(cache: Rc<RefCell<DownloadCache>>, cmds: &RefCell<CmdRegistry>) {
+        cmds.borrow_mut()
+            .insert_or_replace("cache_clear", cmd_cache_clear(cache))
+            .unwrap();
+    }
+}
+
+/// Implements the `cache_clear` command, which deletes all downloaded content.
+fn cmd_cache_clear(cache: Rc<RefCell<DownloadCache>>) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |_| {
+        let mut cache = cache.borrow_mut();
+        let count = cache.len();
+        match cache.clear() {
+            Ok(size) => format!(
+                "Removed {} files ({} bytes) from the download cache",
+                count, size
+            ),
+            Err(e) => format!("Couldn't clear the download cache: {}", e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("richter-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_store_read() {
+        let dir = test_dir("store-read");
+        let mut cache = DownloadCache::open(&dir, 1024).unwrap();
+        let sha1 = cache.store("maps/test.bsp", b"map data", None).unwrap();
+
+        assert_eq!(cache.read("maps/test.bsp").unwrap(), b"map data");
+        assert!(dir.join(&sha1).is_file());
+        assert!(!dir.join("maps").exists());
+
+        // the index persists across sessions
+        let cache = DownloadCache::open(&dir, 1024).unwrap();
+        assert_eq!(cache.read("maps/test.bsp").unwrap(), b"map data");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_store_checksum_mismatch() {
+        let dir = test_dir("mismatch");
+        let mut cache = DownloadCache::open(&dir, 1024).unwrap();
+        let sha1 = sha1::Sha1::from(b"expected").digest().to_string();
+
+        assert!(cache.store("progs.dat", b"expected", Some(&sha1)).is_ok());
+        assert!(matches!(
+            cache.store("progs.dat", b"tampered", Some(&sha1)),
+            Err(CacheError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(cache.read("progs.dat").unwrap(), b"expected");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_evict_oldest() {
+        let dir = test_dir("evict");
+        let mut cache = DownloadCache::open(&dir, 8).unwrap();
+        cache.store("a", b"1234", None).unwrap();
+        cache.store("b", b"5678", None).unwrap();
+        cache.store("c", b"9012", None).unwrap();
+
+        assert!(cache.read("a").is_none());
+        assert!(cache.read("b").is_some());
+        assert!(cache.read("c").is_some());
+        assert_eq!(cache.size(), 8);
+
+        assert_eq!(cache.clear().unwrap(), 8);
+        assert!(cache.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_max_size() {
+        let dir = test_dir("resize");
+        let mut cache = DownloadCache::open(&dir, 8).unwrap();
+        cache.store("a", b"1234", None).unwrap();
+        cache.store("b", b"5678", None).unwrap();
+
+        cache.set_max_size(4).unwrap();
+        assert_eq!(cache.max_size(), 4);
+        assert!(cache.read("a").is_none());
+        assert!(cache.read("b").is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Sources of time for the host.
+//!
+//! The host reads the time through the `Clock` trait rather than from the system clock, so tests
+//! and fixed-rate modes such as timedemo and capture can decide how fast time passes.
+
+use std::{cell::Cell, rc::Rc, thread};
+
+use chrono::{DateTime, Duration, TimeZone as _, Utc};
+
+/// A source of the current time.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Blocks until `duration` has passed.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system's wall clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration.to_std().unwrap_or_default());
+    }
+}
+
+/// A clock that only moves when it is told to.
+///
+/// Clones share the same time, so a test can keep a handle to advance the clock given to a
+/// `Host`. Sleeping advances the clock by the requested duration instead of blocking.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Rc<Cell<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at the Unix epoch.
+    pub fn new() -> ManualClock {
+        ManualClock::starting_at(Utc.timestamp(0, 0))
+    }
+
+    /// Creates a clock stopped at `time`.
+    pub fn starting_at(time: DateTime<Utc>) -> ManualClock {
+        ManualClock {
+            now: Rc::new(Cell::new(time)),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_shared() {
+        let clock = ManualClock::new();
+        let handle = clock.clone();
+        let start = clock.now();
+
+        handle.advance(Duration::milliseconds(250));
+        assert_eq!(clock.now() - start, Duration::milliseconds(250));
+
+        clock.sleep(Duration::seconds(2));
+        assert_eq!(handle.now() - start, Duration::milliseconds(2250));
+    }
+}
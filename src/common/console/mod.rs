@@ -20,7 +20,7 @@
 
 use std::{
     cell::{Ref, RefCell},
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     fmt::Write,
     iter::FromIterator,
     rc::Rc,
@@ -29,8 +29,12 @@ use std::{
 use crate::common::parse;
 
 use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+/// Maximum number of lines kept in the console scrollback.
+const MAX_OUTPUT_LINES: usize = 1024;
+
 #[derive(Error, Debug)]
 pub enum ConsoleError {
     #[error("{0}")]
@@ -186,6 +190,26 @@ struct Cvar {
     default: String,
 }
 
+/// The values of all cvars at a point in time.
+///
+/// See [`CvarRegistry::snapshot`] and [`CvarRegistry::restore`]. A snapshot serializes as a map
+/// from cvar names to values, sorted by name.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CvarSnapshot {
+    values: BTreeMap<String, String>,
+}
+
+impl CvarSnapshot {
+    /// Returns the recorded value of the cvar `name`.
+    pub fn get<S>(&self, name: S) -> Option<&str>
+    where
+        S: AsRef<str>,
+    {
+        self.values.get(name.as_ref()).map(String::as_str)
+    }
+}
+
 #[derive(Debug)]
 pub struct CvarRegistry {
     cvars: RefCell<HashMap<String, Cvar>>,
@@ -348,6 +372,61 @@ impl CvarRegistry {
     {
         self.cvars.borrow().contains_key(name.as_ref())
     }
+
+    /// Records the current value of every cvar.
+    pub fn snapshot(&self) -> CvarSnapshot {
+        CvarSnapshot {
+            values: self
+                .cvars
+                .borrow()
+                .iter()
+                .map(|(name, cvar)| (name.clone(), cvar.val.clone()))
+                .collect(),
+        }
+    }
+
+    /// Records the current values of the cvars in `names`.
+    ///
+    /// Names that don't belong to a registered cvar are skipped. Restoring the snapshot only
+    /// affects the recorded cvars.
+    pub fn snapshot_of<I, S>(&self, names: I) -> CvarSnapshot
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let cvars = self.cvars.borrow();
+        CvarSnapshot {
+            values: names
+                .into_iter()
+                .filter_map(|name| {
+                    let name = name.as_ref();
+                    cvars
+                        .get(name)
+                        .map(|cvar| (name.to_owned(), cvar.val.clone()))
+                })
+                .collect(),
+        }
+    }
+
+    /// Resets every cvar changed since `snapshot` was taken to its recorded value.
+    ///
+    /// Cvars registered after the snapshot was taken are left alone. Returns the names of the
+    /// restored cvars in sorted order.
+    pub fn restore(&self, snapshot: &CvarSnapshot) -> Vec<String> {
+        let mut restored = Vec::new();
+
+        for (name, cvar) in self.cvars.borrow_mut().iter_mut() {
+            if let Some(val) = snapshot.values.get(name) {
+                if cvar.val != *val {
+                    cvar.val = val.clone();
+                    restored.push(name.clone());
+                }
+            }
+        }
+
+        restored.sort();
+        restored
+    }
 }
 
 /// The line of text currently being edited in the console.
@@ -406,6 +485,16 @@ impl ConsoleInput {
         }
     }
 
+    /// Moves the cursor to the beginning of the text.
+    pub fn cursor_home(&mut self) {
+        self.curs = 0;
+    }
+
+    /// Moves the cursor to the end of the text.
+    pub fn cursor_end(&mut self) {
+        self.curs = self.text.len();
+    }
+
     /// Deletes the character to the right of the cursor.
     ///
     /// If the cursor is at the end of the current text, no character is deleted.
@@ -497,14 +586,36 @@ impl ConsoleOutput {
         C: IntoIterator<Item = char>,
     {
         self.lines
-            .push_front((chars.into_iter().collect(), timestamp))
-        // TODO: set maximum capacity and pop_back when we reach it
+            .push_front((chars.into_iter().collect(), timestamp));
+        self.lines.truncate(MAX_OUTPUT_LINES);
+    }
+
+    /// Returns the number of lines in the scrollback.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
     }
 
     pub fn lines(&self) -> impl Iterator<Item = &[char]> {
         self.lines.iter().map(|(v, _)| v.as_slice())
     }
 
+    /// Returns the most recent `count` lines as text, oldest first, each ending in a newline.
+    ///
+    /// If there are fewer than `count` lines, all of them are returned.
+    pub fn text(&self, count: usize) -> String {
+        let mut text = String::new();
+        for (line, _) in self.lines.iter().take(count).rev() {
+            text.extend(line);
+            text.push('\n');
+        }
+
+        text
+    }
+
     /// Return an iterator over lines that have been printed in the last
     /// `interval` of time.
     ///
@@ -543,6 +654,9 @@ pub struct Console {
     hist: History,
     buffer: RefCell<String>,
 
+    // number of lines the output is scrolled back from the most recent line
+    scroll: usize,
+
     out_buffer: RefCell<Vec<char>>,
     output: RefCell<ConsoleOutput>,
 }
@@ -632,6 +746,7 @@ impl Console {
             input: ConsoleInput::new(),
             hist: History::new(),
             buffer: RefCell::new(String::new()),
+            scroll: 0,
             out_buffer: RefCell::new(Vec::new()),
             output,
         }
@@ -708,8 +823,9 @@ impl Console {
                 input_echo.append(&mut self.input.get_text());
                 self.output.borrow_mut().push(input_echo, None);
 
-                // clear the input line
+                // clear the input line and jump back to the most recent output
                 self.input.clear();
+                self.scroll = 0;
             }
 
             '\x08' => self.input.backspace(),
@@ -717,6 +833,9 @@ impl Console {
 
             '\t' => warn!("Tab completion not implemented"), // TODO: tab completion
 
+            // ignore other control characters, e.g. from Ctrl+C
+            c if c.is_control() => (),
+
             // TODO: we should probably restrict what characters are allowed
             c => self.input.insert(c),
         }
@@ -734,6 +853,30 @@ impl Console {
         self.input.cursor_left()
     }
 
+    pub fn cursor_home(&mut self) {
+        self.input.cursor_home()
+    }
+
+    pub fn cursor_end(&mut self) {
+        self.input.cursor_end()
+    }
+
+    /// Returns the number of lines the output is scrolled back.
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    /// Scrolls the output back by `lines`, stopping at the oldest line.
+    pub fn scroll_up(&mut self, lines: usize) {
+        let max = self.output.borrow().len().saturating_sub(1);
+        self.scroll = (self.scroll + lines).min(max);
+    }
+
+    /// Scrolls the output forward by `lines`, stopping at the most recent line.
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
     pub fn history_up(&mut self) {
         if let Some(line) = self.hist.line_up() {
             self.input.set_text(&line);
@@ -746,6 +889,11 @@ impl Console {
         }
     }
 
+    /// Returns true if there is text in the execution buffer waiting to be interpreted.
+    pub fn has_pending_text(&self) -> bool {
+        !self.buffer.borrow().is_empty()
+    }
+
     /// Interprets the contents of the execution buffer.
     pub fn execute(&self) {
         let text = self.buffer.replace(String::new());
@@ -820,3 +968,110 @@ impl Console {
         self.output.borrow()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cvar_snapshot_restore() {
+        let cvars = CvarRegistry::new(Rc::new(RefCell::new(Vec::new())));
+        cvars.register("fov", "90").unwrap();
+        cvars.register("gamma", "1").unwrap();
+        cvars.set("gamma", "0.8").unwrap();
+
+        let snapshot = cvars.snapshot();
+        cvars.set("fov", "110").unwrap();
+        cvars.register("r_new", "1").unwrap();
+        cvars.set("r_new", "2").unwrap();
+
+        assert_eq!(cvars.restore(&snapshot), vec!["fov".to_owned()]);
+        assert_eq!(cvars.get("fov").unwrap(), "90");
+        assert_eq!(cvars.get("gamma").unwrap(), "0.8");
+        assert_eq!(cvars.get("r_new").unwrap(), "2");
+        assert!(cvars.restore(&snapshot).is_empty());
+    }
+
+    #[test]
+    fn test_cvar_snapshot_of() {
+        let cvars = CvarRegistry::new(Rc::new(RefCell::new(Vec::new())));
+        cvars.register("fov", "90").unwrap();
+        cvars.register("gamma", "1").unwrap();
+
+        let snapshot = cvars.snapshot_of(&["fov", "no_such_cvar"]);
+        assert_eq!(snapshot.get("fov"), Some("90"));
+        assert_eq!(snapshot.get("gamma"), None);
+        assert_eq!(snapshot.get("no_such_cvar"), None);
+
+        cvars.set("fov", "110").unwrap();
+        cvars.set("gamma", "0.8").unwrap();
+        assert_eq!(cvars.restore(&snapshot), vec!["fov".to_owned()]);
+        assert_eq!(cvars.get("gamma").unwrap(), "0.8");
+    }
+
+    #[test]
+    fn test_cvar_snapshot_serde() {
+        let cvars = CvarRegistry::new(Rc::new(RefCell::new(Vec::new())));
+        cvars.register("gamma", "1").unwrap();
+        cvars.register("fov", "90").unwrap();
+
+        let snapshot = cvars.snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(json, r#"{"fov":"90","gamma":"1"}"#);
+
+        let parsed: CvarSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, snapshot);
+        assert_eq!(parsed.get("fov"), Some("90"));
+
+        cvars.set("fov", "110").unwrap();
+        assert_eq!(cvars.restore(&parsed), vec!["fov".to_owned()]);
+    }
+
+    #[test]
+    fn test_console_output_capacity() {
+        let mut output = ConsoleOutput::new();
+        for i in 0..MAX_OUTPUT_LINES + 10 {
+            output.push(format!("{}", i).chars(), None);
+        }
+
+        assert_eq!(output.len(), MAX_OUTPUT_LINES);
+        let newest = format!("{}", MAX_OUTPUT_LINES + 9)
+            .chars()
+            .collect::<Vec<_>>();
+        assert_eq!(output.lines().next().unwrap(), newest.as_slice());
+    }
+
+    #[test]
+    fn test_console_output_text() {
+        let mut output = ConsoleOutput::new();
+        for i in 0..5 {
+            output.push(format!("line {}", i).chars(), None);
+        }
+
+        assert_eq!(output.text(2), "line 3\nline 4\n");
+        assert_eq!(output.text(10).lines().count(), 5);
+        assert_eq!(output.text(0), "");
+    }
+
+    #[test]
+    fn test_console_scroll() {
+        let names = Rc::new(RefCell::new(Vec::new()));
+        let cmds = Rc::new(RefCell::new(CmdRegistry::new(names.clone())));
+        let cvars = Rc::new(RefCell::new(CvarRegistry::new(names)));
+        let mut console = Console::new(cmds, cvars);
+        for i in 0..5 {
+            console.println(format!("line {}", i));
+        }
+
+        console.scroll_up(3);
+        assert_eq!(console.scroll(), 3);
+        console.scroll_up(10);
+        assert_eq!(console.scroll(), 4);
+        console.scroll_down(1);
+        assert_eq!(console.scroll(), 3);
+
+        // entering a command returns to the bottom
+        console.send_char('\r');
+        assert_eq!(console.scroll(), 0);
+    }
+}
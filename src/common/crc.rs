@@ -0,0 +1,86 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! The 16-bit CRC used by the original engine.
+//!
+//! This is CRC-CCITT (polynomial 0x1021) with an initial value of 0xFFFF and no final XOR. See
+//! [crc.c](https://github.com/id-Software/Quake/blob/master/WinQuake/crc.c).
+
+const CRC_INIT_VALUE: u16 = 0xFFFF;
+const CRC_XOR_VALUE: u16 = 0x0000;
+const CRC_POLYNOMIAL: u16 = 0x1021;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Crc {
+    value: u16,
+}
+
+impl Crc {
+    pub fn new() -> Crc {
+        Crc {
+            value: CRC_INIT_VALUE,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.value ^= (*byte as u16) << 8;
+            for _ in 0..8 {
+                self.value = if self.value & 0x8000 != 0 {
+                    (self.value << 1) ^ CRC_POLYNOMIAL
+                } else {
+                    self.value << 1
+                };
+            }
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        self.value ^ CRC_XOR_VALUE
+    }
+}
+
+/// Computes the CRC of a block of data.
+pub fn crc_block(data: &[u8]) -> u16 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc_check_value() {
+        // standard check value for CRC-16/CCITT-FALSE
+        assert_eq!(crc_block(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn test_crc_incremental() {
+        let mut crc = Crc::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.value(), crc_block(b"123456789"));
+    }
+
+    #[test]
+    fn test_crc_empty() {
+        assert_eq!(crc_block(&[]), CRC_INIT_VALUE);
+    }
+}
@@ -0,0 +1,274 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! DirectDraw Surface (DDS) loading for pre-compressed replacement textures.
+//!
+//! Only block-compressed images are supported: BC1 (DXT1), BC2 (DXT3), BC3 (DXT5) and BC7, the
+//! last of which requires the DX10 header extension. Only the top mipmap level is loaded, and the
+//! block data is kept compressed so that it can be uploaded as-is.
+
+use std::io::{self, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use thiserror::Error;
+
+const MAGIC: u32 = 0x2053_4444; // "DDS "
+const HEADER_SIZE: u32 = 124;
+const PIXEL_FORMAT_SIZE: u32 = 32;
+
+// set in the pixel format flags if the format is given by its four-character code
+const PIXEL_FORMAT_FOURCC: u32 = 0x4;
+
+const FOURCC_DXT1: u32 = 0x3154_5844;
+const FOURCC_DXT3: u32 = 0x3354_5844;
+const FOURCC_DXT5: u32 = 0x3554_5844;
+const FOURCC_DX10: u32 = 0x3031_5844;
+
+const DXGI_FORMAT_BC1_UNORM: u32 = 71;
+const DXGI_FORMAT_BC1_UNORM_SRGB: u32 = 72;
+const DXGI_FORMAT_BC2_UNORM: u32 = 74;
+const DXGI_FORMAT_BC2_UNORM_SRGB: u32 = 75;
+const DXGI_FORMAT_BC3_UNORM: u32 = 77;
+const DXGI_FORMAT_BC3_UNORM_SRGB: u32 = 78;
+const DXGI_FORMAT_BC7_UNORM: u32 = 98;
+const DXGI_FORMAT_BC7_UNORM_SRGB: u32 = 99;
+
+#[derive(Error, Debug)]
+pub enum DdsError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Not a DDS file")]
+    BadMagic,
+    #[error("Bad DDS {0} size: {1}")]
+    BadHeaderSize(&'static str, u32),
+    #[error("Unsupported DDS pixel format (fourCC {fourcc:#x}, DXGI format {dxgi_format})")]
+    UnsupportedFormat { fourcc: u32, dxgi_format: u32 },
+    #[error("DDS dimensions must be nonzero multiples of 4, found {0}x{1}")]
+    InvalidDimensions(u32, u32),
+    #[error("DDS image is {0}x{1}, larger than the maximum of {2}x{2}")]
+    TooLarge(u32, u32, u32),
+}
+
+/// A block compression format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DdsFormat {
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc7,
+}
+
+impl DdsFormat {
+    /// Returns the size in bytes of one compressed 4x4 block.
+    pub fn block_size(&self) -> u32 {
+        match self {
+            DdsFormat::Bc1 => 8,
+            DdsFormat::Bc2 | DdsFormat::Bc3 | DdsFormat::Bc7 => 16,
+        }
+    }
+
+    fn from_fourcc(fourcc: u32) -> Option<DdsFormat> {
+        match fourcc {
+            FOURCC_DXT1 => Some(DdsFormat::Bc1),
+            FOURCC_DXT3 => Some(DdsFormat::Bc2),
+            FOURCC_DXT5 => Some(DdsFormat::Bc3),
+            _ => None,
+        }
+    }
+
+    fn from_dxgi(dxgi_format: u32) -> Option<DdsFormat> {
+        match dxgi_format {
+            DXGI_FORMAT_BC1_UNORM | DXGI_FORMAT_BC1_UNORM_SRGB => Some(DdsFormat::Bc1),
+            DXGI_FORMAT_BC2_UNORM | DXGI_FORMAT_BC2_UNORM_SRGB => Some(DdsFormat::Bc2),
+            DXGI_FORMAT_BC3_UNORM | DXGI_FORMAT_BC3_UNORM_SRGB => Some(DdsFormat::Bc3),
+            DXGI_FORMAT_BC7_UNORM | DXGI_FORMAT_BC7_UNORM_SRGB => Some(DdsFormat::Bc7),
+            _ => None,
+        }
+    }
+}
+
+/// The top mipmap level of a block-compressed DDS image.
+#[derive(Debug)]
+pub struct Dds {
+    width: u32,
+    height: u32,
+    format: DdsFormat,
+    data: Vec<u8>,
+}
+
+impl Dds {
+    /// Reads a DDS image no wider or taller than `max_dimension`.
+    pub fn load<R>(mut reader: R, max_dimension: u32) -> Result<Dds, DdsError>
+    where
+        R: Read,
+    {
+        if reader.read_u32::<LittleEndian>()? != MAGIC {
+            Err(DdsError::BadMagic)?;
+        }
+
+        let header_size = reader.read_u32::<LittleEndian>()?;
+        if header_size != HEADER_SIZE {
+            Err(DdsError::BadHeaderSize("header", header_size))?;
+        }
+
+        let _flags = reader.read_u32::<LittleEndian>()?;
+        let height = reader.read_u32::<LittleEndian>()?;
+        let width = reader.read_u32::<LittleEndian>()?;
+
+        // pitch, depth, mipmap count and 11 reserved words
+        io::copy(&mut (&mut reader).take(4 * 14), &mut io::sink())?;
+
+        let pixel_format_size = reader.read_u32::<LittleEndian>()?;
+        if pixel_format_size != PIXEL_FORMAT_SIZE {
+            Err(DdsError::BadHeaderSize("pixel format", pixel_format_size))?;
+        }
+        let pixel_format_flags = reader.read_u32::<LittleEndian>()?;
+        let fourcc = reader.read_u32::<LittleEndian>()?;
+
+        // bit count, the four channel masks, the four caps words and a reserved word
+        io::copy(&mut (&mut reader).take(4 * 10), &mut io::sink())?;
+
+        let mut dxgi_format = 0;
+        let format = if pixel_format_flags & PIXEL_FORMAT_FOURCC == 0 {
+            None
+        } else if fourcc == FOURCC_DX10 {
+            dxgi_format = reader.read_u32::<LittleEndian>()?;
+
+            // resource dimension, flags, array size and more flags
+            io::copy(&mut (&mut reader).take(4 * 4), &mut io::sink())?;
+            DdsFormat::from_dxgi(dxgi_format)
+        } else {
+            DdsFormat::from_fourcc(fourcc)
+        };
+
+        let format = format.ok_or(DdsError::UnsupportedFormat {
+            fourcc,
+            dxgi_format,
+        })?;
+
+        if width == 0 || height == 0 || width % 4 != 0 || height % 4 != 0 {
+            Err(DdsError::InvalidDimensions(width, height))?;
+        }
+
+        if width > max_dimension || height > max_dimension {
+            Err(DdsError::TooLarge(width, height, max_dimension))?;
+        }
+
+        let len = (width as usize / 4)
+            .checked_mul(height as usize / 4)
+            .and_then(|blocks| blocks.checked_mul(format.block_size() as usize))
+            .ok_or(DdsError::TooLarge(width, height, max_dimension))?;
+        let mut data = vec![0; len];
+        reader.read_exact(&mut data)?;
+
+        Ok(Dds {
+            width,
+            height,
+            format,
+            data,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn format(&self) -> DdsFormat {
+        self.format
+    }
+
+    /// Returns the compressed blocks of the image in rows, from top to bottom.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_DIMENSION: u32 = 8192;
+
+    fn header(width: u32, height: u32, flags: u32, fourcc: u32) -> Vec<u8> {
+        let mut words = vec![MAGIC, HEADER_SIZE, 0, height, width];
+        words.extend_from_slice(&[0; 14]);
+        words.extend_from_slice(&[PIXEL_FORMAT_SIZE, flags, fourcc]);
+        words.extend_from_slice(&[0; 10]);
+        words
+            .iter()
+            .flat_map(|w| w.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_load_dxt1() {
+        let mut data = header(8, 4, PIXEL_FORMAT_FOURCC, FOURCC_DXT1);
+        // two 8-byte blocks followed by a smaller mipmap, which is ignored
+        data.extend(0..16);
+        data.extend_from_slice(&[0xFF; 8]);
+
+        let dds = Dds::load(&data[..], MAX_DIMENSION).unwrap();
+        assert_eq!((dds.width(), dds.height()), (8, 4));
+        assert_eq!(dds.format(), DdsFormat::Bc1);
+        assert_eq!(dds.data(), &(0..16).collect::<Vec<u8>>()[..]);
+    }
+
+    #[test]
+    fn test_load_dx10_bc7() {
+        let mut data = header(4, 4, PIXEL_FORMAT_FOURCC, FOURCC_DX10);
+        for word in [DXGI_FORMAT_BC7_UNORM, 3, 0, 1, 0].iter() {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data.extend_from_slice(&[0xAB; 16]);
+
+        let dds = Dds::load(&data[..], MAX_DIMENSION).unwrap();
+        assert_eq!(dds.format(), DdsFormat::Bc7);
+        assert_eq!(dds.data(), &[0xAB; 16][..]);
+    }
+
+    #[test]
+    fn test_uncompressed_unsupported() {
+        let data = header(4, 4, 0x40, 0);
+        assert!(matches!(
+            Dds::load(&data[..], MAX_DIMENSION),
+            Err(DdsError::UnsupportedFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unaligned_dimensions() {
+        let mut data = header(6, 4, PIXEL_FORMAT_FOURCC, FOURCC_DXT5);
+        data.extend_from_slice(&[0; 64]);
+        assert!(matches!(
+            Dds::load(&data[..], MAX_DIMENSION),
+            Err(DdsError::InvalidDimensions(6, 4))
+        ));
+    }
+
+    #[test]
+    fn test_too_large() {
+        let data = header(MAX_DIMENSION + 4, 4, PIXEL_FORMAT_FOURCC, FOURCC_DXT1);
+        assert!(matches!(
+            Dds::load(&data[..], MAX_DIMENSION),
+            Err(DdsError::TooLarge(_, 4, MAX_DIMENSION))
+        ));
+    }
+}
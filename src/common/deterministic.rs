@@ -0,0 +1,261 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Platform-independent math for simulation code.
+//!
+//! The transcendental functions in the platform math library are not guaranteed to be correctly
+//! rounded, so `sin`, `cos` and `atan2` can differ in the last bit between operating systems and
+//! CPUs. Over the course of a demo or co-op game those differences accumulate into visibly
+//! different player positions. The functions in this module are built only from the basic IEEE
+//! 754 operations (addition, subtraction, multiplication, division, square root and rounding),
+//! which are exactly specified, evaluated in a fixed order without fused multiply-adds.
+
+/// Which implementation of the transcendental functions simulation code should use.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MathMode {
+    /// Use the platform math library.
+    Native,
+    /// Use the polynomial approximations in this module.
+    Deterministic,
+}
+
+impl MathMode {
+    /// Selects a math mode from the value of the `sv_deterministic` cvar.
+    pub fn from_cvar(value: f32) -> MathMode {
+        if value != 0.0 {
+            MathMode::Deterministic
+        } else {
+            MathMode::Native
+        }
+    }
+
+    /// Returns the sine and cosine of an angle in degrees.
+    pub fn sin_cos_deg(self, deg: f32) -> (f32, f32) {
+        match self {
+            MathMode::Native => deg.to_radians().sin_cos(),
+            MathMode::Deterministic => sin_cos_deg(deg),
+        }
+    }
+
+    /// Returns the angle in degrees of the vector `(x, y)`, in the range `[-180, 180]`.
+    pub fn atan2_deg(self, y: f32, x: f32) -> f32 {
+        match self {
+            MathMode::Native => y.atan2(x).to_degrees(),
+            MathMode::Deterministic => atan2_deg(y, x),
+        }
+    }
+}
+
+const DEG_TO_RAD: f32 = std::f32::consts::PI / 180.0;
+const RAD_TO_DEG: f32 = 180.0 / std::f32::consts::PI;
+
+// tan(pi / 8), above which atan arguments are shifted by 45 degrees
+const TAN_PI_8: f32 = 0.414_213_57;
+
+// Taylor coefficients for sin(x) / x and cos(x) in x^2. The argument is reduced to [-pi/4, pi/4]
+// before evaluation, where the first omitted term is below f32 precision.
+const SIN_COEFFS: [f32; 5] = [
+    1.0,
+    -1.0 / 6.0,
+    1.0 / 120.0,
+    -1.0 / 5_040.0,
+    1.0 / 362_880.0,
+];
+const COS_COEFFS: [f32; 6] = [
+    1.0,
+    -1.0 / 2.0,
+    1.0 / 24.0,
+    -1.0 / 720.0,
+    1.0 / 40_320.0,
+    -1.0 / 3_628_800.0,
+];
+
+// Taylor coefficients for atan(x) / x in x^2, valid for |x| <= tan(pi / 8).
+const ATAN_COEFFS: [f32; 12] = [
+    1.0,
+    -1.0 / 3.0,
+    1.0 / 5.0,
+    -1.0 / 7.0,
+    1.0 / 9.0,
+    -1.0 / 11.0,
+    1.0 / 13.0,
+    -1.0 / 15.0,
+    1.0 / 17.0,
+    -1.0 / 19.0,
+    1.0 / 21.0,
+    -1.0 / 23.0,
+];
+
+/// Evaluates a polynomial in `x` using Horner's method, highest-order coefficient first.
+fn horner(coeffs: &[f32], x: f32) -> f32 {
+    coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+/// Returns the sine and cosine of an angle in degrees.
+///
+/// Multiples of 90 degrees produce exact results.
+pub fn sin_cos_deg(deg: f32) -> (f32, f32) {
+    // reduce to [-45, 45] degrees and remember the quadrant
+    let r = deg % 360.0;
+    let quadrant = (r / 90.0).round();
+    let x = (r - quadrant * 90.0) * DEG_TO_RAD;
+    let x2 = x * x;
+
+    let s = x * horner(&SIN_COEFFS, x2);
+    let c = horner(&COS_COEFFS, x2);
+
+    match (quadrant as i32).rem_euclid(4) {
+        0 => (s, c),
+        1 => (c, -s),
+        2 => (-s, -c),
+        _ => (-c, s),
+    }
+}
+
+/// Returns the angle in degrees of the vector `(x, y)`, in the range `[-180, 180]`.
+pub fn atan2_deg(y: f32, x: f32) -> f32 {
+    let (ay, ax) = (y.abs(), x.abs());
+    if ay == 0.0 && ax == 0.0 {
+        return 0.0;
+    }
+
+    // reduce to the first octant
+    let swap = ay > ax;
+    let t = if swap { ax / ay } else { ay / ax };
+
+    let (base, t) = if t > TAN_PI_8 {
+        (45.0, (t - 1.0) / (t + 1.0))
+    } else {
+        (0.0, t)
+    };
+
+    let mut angle = base + t * horner(&ATAN_COEFFS, t * t) * RAD_TO_DEG;
+
+    if swap {
+        angle = 90.0 - angle;
+    }
+    if x < 0.0 {
+        angle = 180.0 - angle;
+    }
+    if y < 0.0 {
+        angle = -angle;
+    }
+
+    angle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::crc::Crc;
+
+    const FRAME_TIME: f32 = 1.0 / 72.0;
+
+    /// Runs a crude walking simulation over a trace of `(yaw, forward speed)` inputs and returns
+    /// a checksum of every intermediate state.
+    fn simulate(inputs: &[(f32, f32)]) -> u16 {
+        let mut origin = [0.0f32; 3];
+        let mut velocity = [0.0f32, 0.0, 270.0];
+        let mut crc = Crc::new();
+
+        for &(yaw, forward_move) in inputs {
+            let (sin_yaw, cos_yaw) = sin_cos_deg(yaw);
+            velocity[0] += cos_yaw * forward_move * FRAME_TIME;
+            velocity[1] += sin_yaw * forward_move * FRAME_TIME;
+            velocity[2] -= 800.0 * FRAME_TIME;
+
+            // ground friction
+            let speed = (velocity[0] * velocity[0] + velocity[1] * velocity[1]).sqrt();
+            if speed > 0.0 {
+                let scale = (speed - speed * 4.0 * FRAME_TIME).max(0.0) / speed;
+                velocity[0] *= scale;
+                velocity[1] *= scale;
+            }
+
+            for i in 0..3 {
+                origin[i] += velocity[i] * FRAME_TIME;
+            }
+
+            // bounce off the floor
+            if origin[2] < 0.0 {
+                origin[2] = 0.0;
+                velocity[2] = -velocity[2] * 0.5;
+            }
+
+            let heading = atan2_deg(velocity[1], velocity[0]);
+            for v in origin.iter().chain(velocity.iter()).chain(Some(&heading)) {
+                crc.update(&v.to_bits().to_le_bytes());
+            }
+        }
+
+        crc.value()
+    }
+
+    fn input_trace() -> Vec<(f32, f32)> {
+        (0..720)
+            .map(|i| {
+                let yaw = i as f32 * 7.3 - 1000.0;
+                let forward_move = if i % 90 < 45 { 320.0 } else { -200.0 };
+                (yaw, forward_move)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sin_cos_exact_quadrants() {
+        assert_eq!(sin_cos_deg(0.0), (0.0, 1.0));
+        assert_eq!(sin_cos_deg(90.0).0, 1.0);
+        assert_eq!(sin_cos_deg(180.0).1, -1.0);
+        assert_eq!(sin_cos_deg(-90.0).0, -1.0);
+        assert_eq!(sin_cos_deg(720.0), (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_sin_cos_accuracy() {
+        for i in -7200..7200 {
+            let deg = i as f32 * 0.1;
+            let (s, c) = sin_cos_deg(deg);
+            let (native_s, native_c) = (deg as f64).to_radians().sin_cos();
+            assert!((s as f64 - native_s).abs() < 1e-6, "sin({})", deg);
+            assert!((c as f64 - native_c).abs() < 1e-6, "cos({})", deg);
+        }
+    }
+
+    #[test]
+    fn test_atan2_accuracy() {
+        for i in 0..3600 {
+            let deg = i as f32 * 0.1 - 180.0;
+            let (s, c) = (deg as f64).to_radians().sin_cos();
+            let expected = s.atan2(c).to_degrees();
+            let result = atan2_deg(s as f32 * 50.0, c as f32 * 50.0);
+            assert!((result as f64 - expected).abs() < 1e-4, "atan2 at {}", deg);
+        }
+
+        assert_eq!(atan2_deg(0.0, 0.0), 0.0);
+        assert_eq!(atan2_deg(1.0, 0.0), 90.0);
+        assert_eq!(atan2_deg(0.0, -1.0), 180.0);
+    }
+
+    const SIMULATION_CHECKSUM: u16 = 0xC939;
+
+    #[test]
+    fn test_simulation_checksum() {
+        // this value must be identical on every platform. if it changes, demos recorded with
+        // sv_deterministic enabled will no longer play back correctly.
+        assert_eq!(simulate(&input_trace()), SIMULATION_CHECKSUM);
+    }
+}
@@ -0,0 +1,360 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Built-in fallback assets.
+//!
+//! None of the original game data can be redistributed, so when no PAK archives are found the
+//! virtual filesystem mounts a small generated archive instead. It contains just enough to boot to
+//! the console and menu: a palette, a `gfx.wad` with a console font, a console background and a
+//! silent sound. Everything here is generated at runtime from the public domain `font8x8` glyphs
+//! below, so the engine can be run against freely distributable test maps.
+
+use std::collections::HashMap;
+
+use crate::common::pak::Pak;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+const CONCHARS_SIZE: usize = 128;
+const GLYPH_SIZE: usize = 8;
+const GLYPH_COLS: usize = CONCHARS_SIZE / GLYPH_SIZE;
+
+/// Palette index used for the foreground of normal console characters.
+const TEXT_COLOR: u8 = 15;
+
+/// Palette index used for the foreground of alternate (high bit set) console characters.
+const ALT_TEXT_COLOR: u8 = 79;
+
+/// Base colors of the 16 palette ramps, roughly following the layout of the original palette.
+const RAMPS: [[u8; 3]; 16] = [
+    [255, 255, 255],
+    [143, 111, 79],
+    [143, 143, 191],
+    [107, 107, 15],
+    [255, 0, 0],
+    [191, 123, 47],
+    [255, 243, 27],
+    [227, 179, 151],
+    [171, 139, 163],
+    [187, 115, 159],
+    [219, 195, 187],
+    [111, 131, 123],
+    [255, 255, 0],
+    [0, 0, 255],
+    [255, 243, 147],
+    [255, 127, 127],
+];
+
+// font8x8_basic by Daniel Hepper (public domain), covering ASCII 0x20 through 0x7E.
+// Each byte is one row of a glyph, least significant bit leftmost.
+const FONT_8X8: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00],
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00],
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00],
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00],
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00],
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00],
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00],
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00],
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06],
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00],
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00],
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00],
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00],
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00],
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00],
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00],
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00],
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00],
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00],
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00],
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00],
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00],
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06],
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00],
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00],
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00],
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00],
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00],
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00],
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00],
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00],
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00],
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00],
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00],
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00],
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00],
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00],
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00],
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00],
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00],
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00],
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00],
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00],
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00],
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00],
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00],
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00],
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00],
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00],
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00],
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00],
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00],
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00],
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00],
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF],
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00],
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00],
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00],
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00],
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00],
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00],
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F],
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00],
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E],
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00],
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00],
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00],
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00],
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00],
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F],
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78],
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00],
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00],
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00],
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00],
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00],
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00],
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00],
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F],
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00],
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00],
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00],
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00],
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+];
+
+/// Generates a 256-color palette in the format of `gfx/palette.lmp`.
+///
+/// Colors are laid out in 16 ramps of 16 shades each, dark to bright.
+pub fn palette() -> [u8; 768] {
+    let mut palette = [0; 768];
+
+    for (ramp_id, base) in RAMPS.iter().enumerate() {
+        for shade in 0..16 {
+            let index = 16 * ramp_id + shade;
+            for component in 0..3 {
+                palette[3 * index + component] =
+                    ((base[component] as usize * (shade + 1)) / 16) as u8;
+            }
+        }
+    }
+
+    palette
+}
+
+/// Generates the 128x128 console character atlas stored in the `CONCHARS` lump.
+///
+/// Index 0 marks transparent pixels. Characters 128-255 repeat the printable ASCII range in an
+/// alternate color.
+pub fn conchars() -> Vec<u8> {
+    let mut indices = vec![0; CONCHARS_SIZE * CONCHARS_SIZE];
+
+    for glyph_id in 0..256 {
+        let ascii = glyph_id & 0x7F;
+        if ascii < 0x20 || ascii > 0x7E {
+            continue;
+        }
+
+        let color = if glyph_id < 128 {
+            TEXT_COLOR
+        } else {
+            ALT_TEXT_COLOR
+        };
+
+        let rows = &FONT_8X8[ascii - 0x20];
+        for (glyph_r, row) in rows.iter().enumerate() {
+            for glyph_c in 0..GLYPH_SIZE {
+                if row & (1 << glyph_c) != 0 {
+                    let atlas_r = GLYPH_SIZE * (glyph_id / GLYPH_COLS) + glyph_r;
+                    let atlas_c = GLYPH_SIZE * (glyph_id % GLYPH_COLS) + glyph_c;
+                    indices[atlas_r * CONCHARS_SIZE + atlas_c] = color;
+                }
+            }
+        }
+    }
+
+    indices
+}
+
+fn qpic(width: u32, height: u32, indices: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 + indices.len());
+    data.write_u32::<LittleEndian>(width).unwrap();
+    data.write_u32::<LittleEndian>(height).unwrap();
+    data.extend_from_slice(indices);
+    data
+}
+
+/// Generates a 320x200 console background as a QPic.
+pub fn conback() -> Vec<u8> {
+    let width = 320;
+    let height = 200;
+
+    // dark vertical gradient using the first few shades of the gray ramp
+    let mut indices = Vec::with_capacity(width * height);
+    for r in 0..height {
+        indices.extend(std::iter::repeat((r * 4 / height) as u8).take(width));
+    }
+
+    qpic(width as u32, height as u32, &indices)
+}
+
+/// Generates a WAD2 archive containing the given lumps.
+pub fn wad(lumps: &[(&str, &[u8])]) -> Vec<u8> {
+    // header is magic, lump count and lump info offset
+    let header_size = 12;
+    let data_size: usize = lumps.iter().map(|(_, data)| data.len()).sum();
+    let mut wad = Vec::with_capacity(header_size + data_size + 32 * lumps.len());
+
+    wad.extend_from_slice(b"WAD2");
+    wad.write_u32::<LittleEndian>(lumps.len() as u32).unwrap();
+    wad.write_u32::<LittleEndian>((header_size + data_size) as u32)
+        .unwrap();
+
+    for (_, data) in lumps {
+        wad.extend_from_slice(data);
+    }
+
+    let mut offset = header_size;
+    for (name, data) in lumps {
+        let mut name_bytes = [0u8; 16];
+        name_bytes[..name.len()].copy_from_slice(name.as_bytes());
+
+        wad.write_u32::<LittleEndian>(offset as u32).unwrap();
+        wad.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+        wad.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+        wad.write_u8(0x44).unwrap(); // type
+        wad.write_u8(0).unwrap(); // compression
+        wad.write_u16::<LittleEndian>(0).unwrap(); // padding
+        wad.extend_from_slice(&name_bytes);
+
+        offset += data.len();
+    }
+
+    wad
+}
+
+/// Generates a short silent WAV file (mono, 16-bit, 11025 Hz).
+pub fn silent_wav() -> Vec<u8> {
+    let sample_rate = 11025;
+    let sample_count = 64;
+    let data_size = 2 * sample_count;
+
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.write_u32::<LittleEndian>(36 + data_size).unwrap();
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.write_u32::<LittleEndian>(16).unwrap();
+    wav.write_u16::<LittleEndian>(1).unwrap(); // PCM
+    wav.write_u16::<LittleEndian>(1).unwrap(); // channels
+    wav.write_u32::<LittleEndian>(sample_rate).unwrap();
+    wav.write_u32::<LittleEndian>(2 * sample_rate).unwrap(); // byte rate
+    wav.write_u16::<LittleEndian>(2).unwrap(); // block align
+    wav.write_u16::<LittleEndian>(16).unwrap(); // bits per sample
+
+    wav.extend_from_slice(b"data");
+    wav.write_u32::<LittleEndian>(data_size).unwrap();
+    wav.resize(wav.len() + data_size as usize, 0);
+
+    wav
+}
+
+/// Builds the fallback archive mounted when no game data is available.
+pub fn pak() -> Pak {
+    let mut files: HashMap<String, Box<[u8]>> = HashMap::new();
+
+    files.insert("gfx/palette.lmp".to_owned(), Box::new(palette()));
+    files.insert(
+        "gfx.wad".to_owned(),
+        wad(&[("CONCHARS", &conchars())]).into_boxed_slice(),
+    );
+    files.insert("gfx/conback.lmp".to_owned(), conback().into_boxed_slice());
+    files.insert(
+        "sound/misc/null.wav".to_owned(),
+        silent_wav().into_boxed_slice(),
+    );
+
+    Pak::from_files(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::common::wad::{QPic, Wad};
+
+    #[test]
+    fn test_fallback_gfx_wad_conchars() {
+        let pak = pak();
+        let wad = Wad::load(Cursor::new(pak.open("gfx.wad").unwrap())).unwrap();
+        let conchars = wad.open_conchars().unwrap();
+        assert_eq!(conchars.width(), 128);
+        assert_eq!(conchars.height(), 128);
+
+        // 'A' is glyph 65: column 1, row 4, top row of the glyph is 0x0C (pixels 2 and 3 set)
+        let row_start = 8 * 4 * 128 + 8 * 1;
+        assert_eq!(
+            &conchars.indices()[row_start..row_start + 4],
+            &[0, 0, TEXT_COLOR, TEXT_COLOR]
+        );
+    }
+
+    #[test]
+    fn test_fallback_conback() {
+        let pak = pak();
+        let conback = QPic::load(Cursor::new(pak.open("gfx/conback.lmp").unwrap())).unwrap();
+        assert_eq!(conback.width(), 320);
+        assert_eq!(conback.height(), 200);
+        assert_eq!(conback.indices().len(), 320 * 200);
+    }
+
+    #[test]
+    fn test_fallback_palette_ramps() {
+        let palette = palette();
+        // brightest shade of the first ramp is white
+        assert_eq!(&palette[3 * 15..3 * 16], &[255, 255, 255]);
+        // darkest shade of each ramp is darker than its brightest
+        for ramp in 0..16 {
+            assert!(palette[3 * 16 * ramp] <= palette[3 * (16 * ramp + 15)]);
+        }
+    }
+}
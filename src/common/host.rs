@@ -18,9 +18,17 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use std::cell::{Ref, RefMut};
+use std::{
+    cell::{Ref, RefMut},
+    sync::Mutex,
+    thread::{self, JoinHandle},
+};
 
-use crate::common::{console::CvarRegistry, engine};
+use crate::common::{
+    clock::{Clock, SystemClock},
+    console::CvarRegistry,
+    engine,
+};
 
 use chrono::{DateTime, Duration, Utc};
 use winit::{
@@ -28,6 +36,109 @@ use winit::{
     event_loop::{ControlFlow, EventLoopWindowTarget},
 };
 
+/// What a `Program` is currently doing, which determines how often it runs frames.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Activity {
+    /// Playing. Frames are limited only by `host_maxfps`.
+    Active,
+
+    /// Waiting for input with nothing to simulate, e.g. at the console while disconnected.
+    /// Frames are also limited by `host_idlefps`.
+    Idle,
+
+    /// The window doesn't have focus. Frames are also limited by `host_bgfps`.
+    Background,
+}
+
+/// Returns the frame rate limit for `activity`.
+///
+/// The idle and background limits only apply if they are positive and lower than `maxfps`.
+fn max_frame_rate(activity: Activity, maxfps: f32, idlefps: f32, bgfps: f32) -> f32 {
+    let limit = match activity {
+        Activity::Active => return maxfps,
+        Activity::Idle => idlefps,
+        Activity::Background => bgfps,
+    };
+
+    if limit > 0.0 {
+        maxfps.min(limit)
+    } else {
+        maxfps
+    }
+}
+
+/// Spawns a thread named after its role, e.g. `richter-loader`.
+///
+/// All engine threads should be created this way so that profilers and task managers show
+/// which part of the engine is using the CPU.
+pub fn spawn_named<F, T>(role: &str, f: F) -> std::io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    thread::Builder::new()
+        .name(format!("richter-{}", role))
+        .spawn(f)
+}
+
+/// Returns `f` applied to each of `items`, in order, running up to `threads` threads named after
+/// `role` that take items as they become free.
+///
+/// This blocks until every item is done. A panic on a worker is resumed on the calling thread.
+pub fn parallel_map<T, R, F>(role: &str, threads: usize, items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let threads = threads.max(1).min(items.len());
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            thread::Builder::new()
+                .name(format!("richter-{}", role))
+                .spawn_scoped(scope, || loop {
+                    // release the queue before running f so the other threads can take items
+                    let next = queue.lock().unwrap().next();
+                    match next {
+                        Some((i, item)) => {
+                            let result = f(item);
+                            results.lock().unwrap().push((i, result));
+                        }
+                        None => break,
+                    }
+                })
+                .unwrap();
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Returns the number of threads a worker pool should run, as configured by `host_threads`.
+pub fn worker_threads(cvars: &CvarRegistry) -> usize {
+    let host_threads = cvars.get_value("host_threads").unwrap_or(0.0);
+    let cores = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    worker_thread_count(host_threads, cores)
+}
+
+/// Returns the number of worker threads to run for a `host_threads` value.
+///
+/// Values below 1 select one thread per core, less one for the main thread.
+fn worker_thread_count(host_threads: f32, cores: usize) -> usize {
+    if host_threads >= 1.0 {
+        host_threads as usize
+    } else {
+        cores.saturating_sub(1).max(1)
+    }
+}
+
 pub trait Program: Sized {
     fn handle_event<T>(
         &mut self,
@@ -37,16 +148,28 @@ pub trait Program: Sized {
     );
 
     fn frame(&mut self, frame_duration: Duration);
+
+    /// Returns what the program is doing, so the host can run fewer frames when it's idle.
+    fn activity(&self) -> Activity {
+        Activity::Active
+    }
+
     fn shutdown(&mut self);
     fn cvars(&self) -> Ref<CvarRegistry>;
     fn cvars_mut(&self) -> RefMut<CvarRegistry>;
 }
 
-pub struct Host<P>
+/// Runs a `Program`, limiting how often it runs frames.
+///
+/// Frame times are read from `clock`, which is the system clock unless a different one is given
+/// to `Host::with_clock`.
+pub struct Host<P, C = SystemClock>
 where
     P: Program,
+    C: Clock,
 {
     program: P,
+    clock: C,
 
     init_time: DateTime<Utc>,
     prev_frame_time: DateTime<Utc>,
@@ -58,14 +181,38 @@ where
     P: Program,
 {
     pub fn new(program: P) -> Host<P> {
-        let init_time = Utc::now();
+        Host::with_clock(program, SystemClock)
+    }
+}
+
+impl<P, C> Host<P, C>
+where
+    P: Program,
+    C: Clock,
+{
+    /// Creates a host which reads frame times from `clock`.
+    pub fn with_clock(program: P, clock: C) -> Host<P, C> {
+        let init_time = clock.now();
         program
             .cvars_mut()
             .register_archive("host_maxfps", "72")
             .unwrap();
+        program
+            .cvars_mut()
+            .register_archive("host_idlefps", "30")
+            .unwrap();
+        program
+            .cvars_mut()
+            .register_archive("host_bgfps", "10")
+            .unwrap();
+        program
+            .cvars_mut()
+            .register_archive("host_threads", "0")
+            .unwrap();
 
         Host {
             program,
+            clock,
             init_time,
             prev_frame_time: init_time,
             prev_frame_duration: Duration::zero(),
@@ -101,14 +248,23 @@ where
 
     pub fn frame(&mut self) {
         // TODO: make sure this doesn't cause weirdness with e.g. leap seconds
-        let new_frame_time = Utc::now();
+        let new_frame_time = self.clock.now();
         self.prev_frame_duration = new_frame_time.signed_duration_since(self.prev_frame_time);
 
         // if the time elapsed since the last frame is too low, don't run this one yet
-        let prev_frame_duration = self.prev_frame_duration;
-        if !self.check_frame_duration(prev_frame_duration) {
-            // avoid busy waiting if we're running at a really high framerate
-            std::thread::sleep(std::time::Duration::from_millis(1));
+        let activity = self.program.activity();
+        let min_frame_duration = self.min_frame_duration(activity);
+        if self.prev_frame_duration < min_frame_duration {
+            let sleep_duration = match activity {
+                // avoid busy waiting if we're running at a really high framerate
+                Activity::Active => Duration::milliseconds(1),
+
+                // nothing needs a quick response, so sleep until the next frame is due
+                Activity::Idle | Activity::Background => {
+                    min_frame_duration - self.prev_frame_duration
+                }
+            };
+            self.clock.sleep(sleep_duration);
             return;
         }
 
@@ -118,18 +274,140 @@ where
         self.program.frame(self.prev_frame_duration);
     }
 
-    // Returns whether enough time has elapsed to run the next frame.
-    fn check_frame_duration(&mut self, frame_duration: Duration) -> bool {
-        let host_maxfps = self
-            .program
-            .cvars()
-            .get_value("host_maxfps")
-            .unwrap_or(72.0);
-        let min_frame_duration = engine::duration_from_f32(1.0 / host_maxfps);
-        frame_duration >= min_frame_duration
+    // Returns the shortest time allowed between frames.
+    fn min_frame_duration(&self, activity: Activity) -> Duration {
+        let cvars = self.program.cvars();
+        let host_maxfps = cvars.get_value("host_maxfps").unwrap_or(72.0);
+        let host_idlefps = cvars.get_value("host_idlefps").unwrap_or(0.0);
+        let host_bgfps = cvars.get_value("host_bgfps").unwrap_or(0.0);
+
+        let fps = max_frame_rate(activity, host_maxfps, host_idlefps, host_bgfps);
+        engine::duration_from_f32(1.0 / fps)
     }
 
     pub fn uptime(&self) -> Duration {
         self.prev_frame_time.signed_duration_since(self.init_time)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::common::clock::ManualClock;
+
+    struct TestProgram {
+        cvars: RefCell<CvarRegistry>,
+        activity: Activity,
+        frames: Vec<Duration>,
+    }
+
+    impl TestProgram {
+        fn new(activity: Activity) -> TestProgram {
+            TestProgram {
+                cvars: RefCell::new(CvarRegistry::new(Rc::new(RefCell::new(Vec::new())))),
+                activity,
+                frames: Vec::new(),
+            }
+        }
+    }
+
+    impl Program for TestProgram {
+        fn handle_event<T>(
+            &mut self,
+            _event: Event<T>,
+            _target: &EventLoopWindowTarget<T>,
+            _control_flow: &mut ControlFlow,
+        ) {
+        }
+
+        fn frame(&mut self, frame_duration: Duration) {
+            self.frames.push(frame_duration);
+        }
+
+        fn activity(&self) -> Activity {
+            self.activity
+        }
+
+        fn shutdown(&mut self) {}
+
+        fn cvars(&self) -> Ref<CvarRegistry> {
+            self.cvars.borrow()
+        }
+
+        fn cvars_mut(&self) -> RefMut<CvarRegistry> {
+            self.cvars.borrow_mut()
+        }
+    }
+
+    #[test]
+    fn test_frame_uses_clock() {
+        let clock = ManualClock::new();
+        let mut host = Host::with_clock(TestProgram::new(Activity::Active), clock.clone());
+
+        // too soon after startup, so the host only sleeps briefly
+        host.frame();
+        assert!(host.program.frames.is_empty());
+        assert_eq!(host.uptime(), Duration::zero());
+
+        clock.advance(Duration::milliseconds(20));
+        host.frame();
+        assert_eq!(host.program.frames, vec![Duration::milliseconds(21)]);
+        assert_eq!(host.uptime(), Duration::milliseconds(21));
+    }
+
+    #[test]
+    fn test_idle_frame_sleeps_until_due() {
+        let clock = ManualClock::new();
+        let mut host = Host::with_clock(TestProgram::new(Activity::Idle), clock.clone());
+        host.program.cvars_mut().set("host_idlefps", "10").unwrap();
+
+        // the first call sleeps for the whole frame, so the second one runs it
+        host.frame();
+        host.frame();
+        assert_eq!(host.program.frames, vec![Duration::milliseconds(100)]);
+    }
+
+    #[test]
+    fn test_max_frame_rate() {
+        assert_eq!(max_frame_rate(Activity::Active, 72.0, 30.0, 10.0), 72.0);
+        assert_eq!(max_frame_rate(Activity::Idle, 72.0, 30.0, 10.0), 30.0);
+        assert_eq!(max_frame_rate(Activity::Background, 72.0, 30.0, 10.0), 10.0);
+
+        // the throttle never raises the frame rate and is disabled by 0
+        assert_eq!(max_frame_rate(Activity::Idle, 20.0, 30.0, 10.0), 20.0);
+        assert_eq!(max_frame_rate(Activity::Background, 72.0, 30.0, 0.0), 72.0);
+    }
+
+    #[test]
+    fn test_worker_thread_count() {
+        assert_eq!(worker_thread_count(3.0, 8), 3);
+        assert_eq!(worker_thread_count(0.0, 8), 7);
+        assert_eq!(worker_thread_count(0.0, 1), 1);
+    }
+
+    #[test]
+    fn test_parallel_map() {
+        let items = (0..100).collect::<Vec<u32>>();
+        let doubled = parallel_map("test", 4, items, |i| i * 2);
+        assert_eq!(doubled, (0..100).map(|i| i * 2).collect::<Vec<u32>>());
+
+        let names = parallel_map("test", 2, vec![(); 2], |_| {
+            thread::current().name().map(str::to_owned)
+        });
+        assert!(names.iter().all(|n| n.as_deref() == Some("richter-test")));
+
+        assert!(parallel_map("test", 4, Vec::<u32>::new(), |i| i).is_empty());
+    }
+
+    #[test]
+    fn test_spawn_named() {
+        let name = spawn_named("test", || thread::current().name().map(str::to_owned))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("richter-test"));
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright © 2018 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Fetching game content over HTTP(S).
+//!
+//! Servers following the `sv_downloadurl` convention advertise a base URL mirroring their game
+//! directory, which is much faster than the in-protocol transfer for large custom maps. Fetched
+//! files are validated against their expected checksum before being stored in the
+//! [`DownloadCache`].
+
+use std::io::Read;
+
+use crate::common::cache::{CacheError, DownloadCache};
+
+use thiserror::Error;
+
+/// Size of the chunks in which response bodies are read, used for progress reporting.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(Error, Debug)]
+pub enum HttpError {
+    #[error("Request failed: {0}")]
+    Request(#[from] Box<ureq::Error>),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unsupported URL: {0}")]
+    UnsupportedUrl(String),
+    #[error("Refusing to download over an insecure connection: {0}")]
+    Insecure(String),
+    #[error("Invalid file name: {0}")]
+    InvalidPath(String),
+    #[error("{url} is larger than the limit of {max_size} bytes")]
+    TooLarge { url: String, max_size: u64 },
+    #[error(transparent)]
+    Cache(#[from] CacheError),
+}
+
+impl From<ureq::Error> for HttpError {
+    fn from(e: ureq::Error) -> Self {
+        HttpError::Request(Box::new(e))
+    }
+}
+
+/// Options controlling how files are fetched.
+#[derive(Clone, Copy, Debug)]
+pub struct FetchOptions {
+    /// If set, only `https://` URLs are fetched.
+    pub https_only: bool,
+    /// The largest response body accepted, in bytes.
+    pub max_size: u64,
+}
+
+/// Builds the URL of a file on a download mirror.
+///
+/// Virtual paths which could escape the mirror's directory are rejected.
+pub fn file_url(base_url: &str, virtual_path: &str) -> Result<String, HttpError> {
+    let invalid = virtual_path.is_empty()
+        || virtual_path.starts_with('/')
+        || virtual_path.contains('\\')
+        || virtual_path.contains(':')
+        || virtual_path.split('/').any(|c| c.is_empty() || c == "..");
+    if invalid {
+        return Err(HttpError::InvalidPath(virtual_path.to_owned()));
+    }
+
+    Ok(format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        virtual_path
+    ))
+}
+
+/// Checks that a URL can be fetched with the given options.
+pub fn check_url(url: &str, options: &FetchOptions) -> Result<(), HttpError> {
+    let scheme = match url.find("://") {
+        Some(i) => url[..i].to_ascii_lowercase(),
+        None => return Err(HttpError::UnsupportedUrl(url.to_owned())),
+    };
+
+    match scheme.as_str() {
+        "https" => Ok(()),
+        "http" if options.https_only => Err(HttpError::Insecure(url.to_owned())),
+        "http" => Ok(()),
+        _ => Err(HttpError::UnsupportedUrl(url.to_owned())),
+    }
+}
+
+/// Fetches the body of a URL.
+///
+/// `progress` is called after every chunk with the number of bytes received so far and the total
+/// size, if the server reported it.
+pub fn fetch<F>(url: &str, options: &FetchOptions, mut progress: F) -> Result<Vec<u8>, HttpError>
+where
+    F: FnMut(u64, Option<u64>),
+{
+    check_url(url, options)?;
+
+    let response = ureq::get(url).call()?;
+    let total = response
+        .header("Content-Length")
+        .and_then(|len| len.parse::<u64>().ok());
+
+    if let Some(t) = total {
+        if t > options.max_size {
+            return Err(HttpError::TooLarge {
+                url: url.to_owned(),
+                max_size: options.max_size,
+            });
+        }
+    }
+
+    // don't trust the reported length, stop reading once the limit is exceeded
+    let mut reader = response.into_reader().take(options.max_size + 1);
+    let mut data = Vec::with_capacity(total.unwrap_or(0) as usize);
+    let mut chunk = [0; CHUNK_SIZE];
+    loop {
+        let count = reader.read(&mut chunk)?;
+        if count == 0 {
+            break;
+        }
+
+        data.extend_from_slice(&chunk[..count]);
+        if data.len() as u64 > options.max_size {
+            return Err(HttpError::TooLarge {
+                url: url.to_owned(),
+                max_size: options.max_size,
+            });
+        }
+
+        progress(data.len() as u64, total);
+    }
+
+    Ok(data)
+}
+
+/// Fetches a file from a download mirror and stores it in the download cache.
+///
+/// If `expected_sha1` is provided, the file is rejected unless its checksum matches. Returns the
+/// SHA-1 checksum of the file.
+pub fn fetch_to_cache<F>(
+    cache: &mut DownloadCache,
+    base_url: &str,
+    virtual_path: &str,
+    expected_sha1: Option<&str>,
+    options: &FetchOptions,
+    progress: F,
+) -> Result<String, HttpError>
+where
+    F: FnMut(u64, Option<u64>),
+{
+    let url = file_url(base_url, virtual_path)?;
+    log::info!("Downloading {}", url);
+    let data = fetch(&url, options, progress)?;
+    Ok(cache.store(virtual_path, &data, expected_sha1)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_url() {
+        assert_eq!(
+            file_url("https://example.com/quake/", "maps/e1m1.bsp").unwrap(),
+            "https://example.com/quake/maps/e1m1.bsp"
+        );
+        assert_eq!(
+            file_url("https://example.com/quake", "maps/e1m1.bsp").unwrap(),
+            "https://example.com/quake/maps/e1m1.bsp"
+        );
+
+        for path in &[
+            "",
+            "/etc/passwd",
+            "maps/../../x",
+            "maps//e1m1.bsp",
+            "c:\\x",
+            "maps\\x",
+        ] {
+            assert!(file_url("https://example.com/", path).is_err());
+        }
+    }
+
+    #[test]
+    fn test_check_url() {
+        let options = FetchOptions {
+            https_only: false,
+            max_size: 0,
+        };
+        let https_only = FetchOptions {
+            https_only: true,
+            ..options
+        };
+
+        assert!(check_url("https://example.com/a", &https_only).is_ok());
+        assert!(check_url("HTTPS://example.com/a", &https_only).is_ok());
+        assert!(check_url("http://example.com/a", &options).is_ok());
+        assert!(matches!(
+            check_url("http://example.com/a", &https_only),
+            Err(HttpError::Insecure(_))
+        ));
+        assert!(matches!(
+            check_url("ftp://example.com/a", &options),
+            Err(HttpError::UnsupportedUrl(_))
+        ));
+        assert!(check_url("example.com/a", &options).is_err());
+    }
+}
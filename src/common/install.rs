@@ -0,0 +1,375 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Detection of existing Quake installations.
+//!
+//! If no base directory is given and the working directory doesn't hold any game data, the usual
+//! Steam and GOG install locations are searched instead. Both stores ship the original game data
+//! in `Quake/id1/`, and the 2021 rerelease adds its own copy in `Quake/rerelease/id1/`; each is
+//! offered as a separate installation.
+//!
+//! When more than one installation is found and none has been chosen, the client asks which to
+//! use before mounting any game data. The choice is made with the `basedir` command and
+//! remembered in the user data directory. Tools without a player to ask use the first one.
+
+use std::{
+    cell::RefCell,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::common::console::CmdRegistry;
+
+/// The file in the user data directory which holds the chosen base directory.
+const CHOICE_FILE: &str = "basedir.txt";
+
+/// The store a Quake installation was bought from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Store {
+    Steam,
+    Gog,
+}
+
+impl fmt::Display for Store {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Store::Steam => write!(f, "Steam"),
+            Store::Gog => write!(f, "GOG"),
+        }
+    }
+}
+
+/// A directory containing `id1/` found in one of the stores' install locations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Install {
+    pub store: Store,
+    /// Whether this is the 2021 rerelease's copy of the game data.
+    pub rerelease: bool,
+    pub dir: PathBuf,
+}
+
+impl fmt::Display for Install {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let edition = if self.rerelease {
+            "2021 rerelease"
+        } else {
+            "original"
+        };
+        write!(f, "{} ({}): {}", self.store, edition, self.dir.display())
+    }
+}
+
+/// Returns whether `dir` holds game data, i.e. an `id1/pak0.pak` in either case.
+pub fn is_base_dir<P>(dir: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    ["id1/pak0.pak", "id1/PAK0.PAK", "ID1/PAK0.PAK"]
+        .iter()
+        .any(|pak| dir.join(pak).is_file())
+}
+
+/// Picks the base directory to use when none is given.
+///
+/// Game data in the working directory always wins, as it did before installations were detected.
+/// Otherwise the saved choice is used if there is one, then the first detected installation.
+pub fn detect_base_dir(installs: &[Install], choice: Option<PathBuf>) -> PathBuf {
+    let cwd = crate::common::default_base_dir();
+    if is_base_dir(&cwd) {
+        return cwd;
+    }
+
+    choice
+        .or_else(|| installs.first().map(|i| i.dir.clone()))
+        .unwrap_or(cwd)
+}
+
+/// Searches the usual Steam and GOG install locations for game data.
+pub fn find_installs() -> Vec<Install> {
+    let mut installs: Vec<Install> = Vec::new();
+
+    for (store, dir) in store_dirs() {
+        for install in installs_in(store, &dir) {
+            // the same library can be reached through several paths, e.g. ~/.steam/steam
+            let canonical = fs::canonicalize(&install.dir).ok();
+            let duplicate = installs
+                .iter()
+                .any(|i| fs::canonicalize(&i.dir).ok() == canonical);
+            if !duplicate {
+                installs.push(install);
+            }
+        }
+    }
+
+    installs
+}
+
+/// Returns the installations in a store's game directory: the original game data in the
+/// directory itself, and the rerelease's in `rerelease/`.
+fn installs_in(store: Store, dir: &Path) -> Vec<Install> {
+    let original = (false, dir.to_path_buf());
+    let rerelease = (true, dir.join("rerelease"));
+
+    vec![original, rerelease]
+        .into_iter()
+        .filter(|(_, d)| is_base_dir(d))
+        .map(|(rerelease, dir)| Install {
+            store,
+            rerelease,
+            dir,
+        })
+        .collect()
+}
+
+/// Returns the directories the stores install Quake to on this platform.
+fn store_dirs() -> Vec<(Store, PathBuf)> {
+    let env_dir = |var: &str| {
+        env::var_os(var)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    let home = env_dir("HOME");
+
+    let mut steam_roots = Vec::new();
+    let mut gog_dirs = Vec::new();
+
+    if cfg!(target_os = "windows") {
+        for var in &["ProgramFiles(x86)", "ProgramFiles"] {
+            if let Some(program_files) = env_dir(var) {
+                steam_roots.push(program_files.join("Steam"));
+                gog_dirs.push(program_files.join("GOG Galaxy/Games/Quake"));
+            }
+        }
+        gog_dirs.push(PathBuf::from("C:/GOG Games/Quake"));
+    } else if cfg!(target_os = "macos") {
+        if let Some(ref home) = home {
+            steam_roots.push(home.join("Library/Application Support/Steam"));
+        }
+    } else if let Some(ref home) = home {
+        steam_roots.push(home.join(".steam/steam"));
+        steam_roots.push(home.join(".local/share/Steam"));
+        steam_roots.push(home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"));
+        // the GOG installer keeps the game data in game/
+        gog_dirs.push(home.join("GOG Games/Quake/game"));
+        gog_dirs.push(home.join("GOG Games/Quake"));
+    }
+
+    let mut dirs = Vec::new();
+    for root in steam_roots {
+        for library in steam_libraries(&root) {
+            dirs.push((Store::Steam, library.join("steamapps/common/Quake")));
+        }
+    }
+    dirs.extend(gog_dirs.into_iter().map(|d| (Store::Gog, d)));
+
+    dirs
+}
+
+/// Returns the Steam libraries known to the Steam installation at `root`, including its own.
+fn steam_libraries(root: &Path) -> Vec<PathBuf> {
+    let mut libraries = vec![root.to_path_buf()];
+
+    if let Ok(vdf) = fs::read_to_string(root.join("steamapps/libraryfolders.vdf")) {
+        for library in parse_library_folders(&vdf) {
+            if !libraries.contains(&library) {
+                libraries.push(library);
+            }
+        }
+    }
+
+    libraries
+}
+
+/// Extracts the library paths from Steam's `libraryfolders.vdf`.
+///
+/// Newer versions of the file give each library's path as a `"path"` key, while older ones map
+/// numbered keys directly to paths. Only the values are needed, so the nesting is ignored.
+fn parse_library_folders(vdf: &str) -> Vec<PathBuf> {
+    vdf.lines()
+        .filter_map(|line| {
+            let mut fields = line.split('"').skip(1).step_by(2);
+            let key = fields.next()?;
+            let value = fields.next()?;
+
+            if key == "path" || key.parse::<u32>().is_ok() {
+                // backslashes are escaped
+                Some(PathBuf::from(value.replace("\\\\", "\\")))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Returns the base directory chosen with the `basedir` command, if it still holds game data.
+pub fn load_choice<P>(user_dir: P) -> Option<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let choice = fs::read_to_string(user_dir.as_ref().join(CHOICE_FILE)).ok()?;
+    Some(PathBuf::from(choice.trim())).filter(|dir| is_base_dir(dir))
+}
+
+/// Remembers `dir` as the base directory to use when none is given.
+pub fn save_choice<P, Q>(user_dir: P, dir: Q) -> std::io::Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let user_dir = user_dir.as_ref();
+    fs::create_dir_all(user_dir)?;
+    fs::write(
+        user_dir.join(CHOICE_FILE),
+        format!("{}\n", dir.as_ref().display()),
+    )
+}
+
+/// Registers the `basedir` command, which lists the detected installations and picks one.
+pub fn register_cmds(
+    installs: Vec<Install>,
+    base_dir: PathBuf,
+    user_dir: PathBuf,
+    cmds: &RefCell<CmdRegistry>,
+) {
+    cmds.borrow_mut()
+        .insert_or_replace("basedir", cmd_basedir(installs, base_dir, user_dir))
+        .unwrap();
+}
+
+/// Implements the `basedir` command.
+///
+/// The game data is mounted at startup, so a new choice only takes effect after a restart.
+fn cmd_basedir(
+    installs: Vec<Install>,
+    base_dir: PathBuf,
+    user_dir: PathBuf,
+) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| match args.len() {
+        0 => {
+            let mut output = format!("Current base directory: {}\n", base_dir.display());
+            if installs.is_empty() {
+                output.push_str("No Steam or GOG installations found\n");
+            }
+            for (i, install) in installs.iter().enumerate() {
+                let marker = if install.dir == base_dir { "*" } else { " " };
+                output.push_str(&format!("{}{}. {}\n", marker, i + 1, install));
+            }
+            output
+        }
+
+        1 => {
+            let install = match args[0].parse::<usize>() {
+                Ok(i) if i >= 1 && i <= installs.len() => &installs[i - 1],
+                _ => return format!("No installation numbered {}", args[0]),
+            };
+
+            match save_choice(&user_dir, &install.dir) {
+                Ok(()) => format!("Restart to play from {}", install.dir.display()),
+                Err(e) => format!("Couldn't save base directory: {}", e),
+            }
+        }
+
+        _ => "usage: basedir [NUMBER]".to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("richter-install-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_parse_library_folders() {
+        let new_format = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"C:\\Program Files (x86)\\Steam"
+		"label"		""
+	}
+	"1"
+	{
+		"path"		"D:\\SteamLibrary"
+	}
+}
+"#;
+        assert_eq!(
+            parse_library_folders(new_format),
+            vec![
+                PathBuf::from("C:\\Program Files (x86)\\Steam"),
+                PathBuf::from("D:\\SteamLibrary"),
+            ]
+        );
+
+        let old_format = r#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1600000000"
+	"ContentStatsID"		"123"
+	"1"		"/mnt/games/SteamLibrary"
+}
+"#;
+        assert_eq!(
+            parse_library_folders(old_format),
+            vec![PathBuf::from("/mnt/games/SteamLibrary")]
+        );
+    }
+
+    #[test]
+    fn test_installs_in() {
+        let dir = test_dir("layout");
+        fs::create_dir_all(dir.join("id1")).unwrap();
+        fs::write(dir.join("id1/PAK0.PAK"), "").unwrap();
+        fs::create_dir_all(dir.join("rerelease/id1")).unwrap();
+        fs::write(dir.join("rerelease/id1/pak0.pak"), "").unwrap();
+
+        assert_eq!(
+            installs_in(Store::Steam, &dir),
+            vec![
+                Install {
+                    store: Store::Steam,
+                    rerelease: false,
+                    dir: dir.clone(),
+                },
+                Install {
+                    store: Store::Steam,
+                    rerelease: true,
+                    dir: dir.join("rerelease"),
+                },
+            ]
+        );
+
+        let user_dir = dir.join("user");
+        assert_eq!(load_choice(&user_dir), None);
+        save_choice(&user_dir, dir.join("rerelease")).unwrap();
+        assert_eq!(load_choice(&user_dir), Some(dir.join("rerelease")));
+
+        // choices that no longer hold game data are ignored
+        fs::remove_dir_all(dir.join("rerelease")).unwrap();
+        assert_eq!(load_choice(&user_dir), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright © 2018 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Loading of the 2021 rerelease's `QuakeEX.kpf` archive.
+//!
+//! The rerelease keeps the data its own engine needs, such as the localization strings, in a zip
+//! archive with a `.kpf` extension next to `id1/`. Its contents are loaded into a [`Pak`] so the
+//! virtual filesystem can treat it like any other archive.
+
+use std::{collections::HashMap, fs::File, io::Read, path::Path};
+
+use crate::common::pak::Pak;
+
+use thiserror::Error;
+use zip::{result::ZipError, ZipArchive};
+
+/// The name of the archive in the rerelease's base directory.
+pub const KPF_NAME: &str = "QuakeEX.kpf";
+
+#[derive(Error, Debug)]
+pub enum KpfError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid zip archive: {0}")]
+    Zip(#[from] ZipError),
+}
+
+/// Reads every file in the archive at `path`.
+pub fn load<P>(path: P) -> Result<Pak, KpfError>
+where
+    P: AsRef<Path>,
+{
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut files = HashMap::new();
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+
+        // virtual paths use forward slashes
+        let name = file.name().replace('\\', "/");
+        // the declared size comes from the archive, so don't allocate it up front
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        files.insert(name, data.into_boxed_slice());
+    }
+
+    Ok(Pak::from_files(files))
+}
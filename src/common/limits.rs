@@ -0,0 +1,184 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Limits on how much content the engine handles.
+//!
+//! The original engine has fixed limits on the number of entities, models, sounds and so on, which
+//! maps made for later engines routinely exceed. Richter raises them when protocol extensions are
+//! in use, and warns when content comes close to or goes over the original limits so that maps
+//! which won't run on other engines are easy to spot.
+
+use std::fmt;
+
+use crate::common::net::{PROTOCOL_FITZQUAKE, PROTOCOL_RMQ};
+
+/// A kind of content whose amount is limited.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Limit {
+    Entities,
+    Models,
+    Sounds,
+    LightStyles,
+    StaticEntities,
+    TempEntities,
+    PakFiles,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Limit::Entities => "entities",
+            Limit::Models => "models",
+            Limit::Sounds => "sounds",
+            Limit::LightStyles => "light styles",
+            Limit::StaticEntities => "static entities",
+            Limit::TempEntities => "temporary entities",
+            Limit::PakFiles => "pak files",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// The largest amount of each kind of content the engine handles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub entities: usize,
+    pub models: usize,
+    pub sounds: usize,
+    pub light_styles: usize,
+    pub static_entities: usize,
+    pub temp_entities: usize,
+    pub pak_files: usize,
+}
+
+impl Limits {
+    /// The limits of the original engine.
+    ///
+    /// The original engine loads any number of pak files, but only 32 are loaded here.
+    pub const CLASSIC: Limits = Limits {
+        entities: 600,
+        models: 256,
+        sounds: 256,
+        light_styles: 64,
+        static_entities: 128,
+        temp_entities: 64,
+        pak_files: 32,
+    };
+
+    /// The limits used with protocol extensions, in line with modern source ports.
+    ///
+    /// Light styles are still addressed by a single byte and animated in groups of 64, so their
+    /// limit is unchanged.
+    pub const EXTENDED: Limits = Limits {
+        entities: 32000,
+        models: 2048,
+        sounds: 2048,
+        light_styles: 64,
+        static_entities: 4096,
+        temp_entities: 256,
+        pak_files: 32,
+    };
+
+    /// Returns the limits for a connection using `protocol_version`.
+    ///
+    /// The FitzQuake and RMQ protocols always use the extended limits. Other protocols use them
+    /// only if `extensions` is set, and are held to the original engine's limits otherwise.
+    pub fn for_protocol(protocol_version: i32, extensions: bool) -> Limits {
+        match protocol_version {
+            PROTOCOL_FITZQUAKE | PROTOCOL_RMQ => Limits::EXTENDED,
+            _ if extensions => Limits::EXTENDED,
+            _ => Limits::CLASSIC,
+        }
+    }
+
+    /// Returns the limit on the given kind of content.
+    pub fn get(&self, limit: Limit) -> usize {
+        match limit {
+            Limit::Entities => self.entities,
+            Limit::Models => self.models,
+            Limit::Sounds => self.sounds,
+            Limit::LightStyles => self.light_styles,
+            Limit::StaticEntities => self.static_entities,
+            Limit::TempEntities => self.temp_entities,
+            Limit::PakFiles => self.pak_files,
+        }
+    }
+
+    /// Checks an amount of content against these limits and the original engine's.
+    ///
+    /// Returns a warning if `count` exceeds these limits, or comes within 10% of or exceeds the
+    /// original engine's.
+    pub fn check(&self, limit: Limit, count: usize) -> Option<String> {
+        let max = self.get(limit);
+        let classic = Limits::CLASSIC.get(limit);
+
+        if count > max {
+            Some(format!("too many {}: {} (limit is {})", limit, count, max))
+        } else if count > classic {
+            Some(format!(
+                "{} {}, more than the original engine's limit of {}",
+                count, limit, classic
+            ))
+        } else if count * 10 >= classic * 9 {
+            Some(format!(
+                "{} {}, close to the original engine's limit of {}",
+                count, limit, classic
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::CLASSIC
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_protocol() {
+        assert_eq!(Limits::for_protocol(15, false), Limits::CLASSIC);
+        assert_eq!(Limits::for_protocol(15, true), Limits::EXTENDED);
+        assert_eq!(
+            Limits::for_protocol(PROTOCOL_FITZQUAKE, false),
+            Limits::EXTENDED
+        );
+        assert_eq!(Limits::for_protocol(PROTOCOL_RMQ, false), Limits::EXTENDED);
+    }
+
+    #[test]
+    fn test_check() {
+        let models = |limits: Limits, count| limits.check(Limit::Models, count);
+
+        assert!(models(Limits::CLASSIC, 100).is_none());
+        assert!(models(Limits::CLASSIC, 256).unwrap().contains("close to"));
+        assert!(models(Limits::CLASSIC, 257)
+            .unwrap()
+            .contains("limit is 256"));
+
+        assert!(models(Limits::EXTENDED, 257).unwrap().contains("more than"));
+        assert!(models(Limits::EXTENDED, 3000)
+            .unwrap()
+            .contains("limit is 2048"));
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Mission pack support.
+//!
+//! Scourge of Armagon (`hipnotic/`) and Dissolution of Eternity (`rogue/`) reuse the item flags to
+//! store their own weapons and items. Some bits the original game leaves empty, and some (such as
+//! Scourge of Armagon's Mjolnir) overlap with the original weapons, so the client has to know which
+//! mission pack is running to draw the status bar correctly.
+
+use std::fmt;
+
+/// The item flags used by Scourge of Armagon.
+pub mod hipnotic {
+    pub const MJOLNIR: u32 = 1 << 7;
+    pub const PROXIMITY_GUN: u32 = 1 << 16;
+    pub const LASER_CANNON: u32 = 1 << 23;
+    pub const WETSUIT: u32 = 1 << 25;
+    pub const EMPATHY_SHIELDS: u32 = 1 << 26;
+}
+
+/// The item flags used by Dissolution of Eternity.
+///
+/// The ammo, weapon and armor values are the contents of `STAT_ITEMS` shifted by the mission pack's
+/// own progs, not the original bits.
+pub mod rogue {
+    pub const SHELLS: u32 = 128;
+    pub const NAILS: u32 = 256;
+    pub const ROCKETS: u32 = 512;
+    pub const CELLS: u32 = 1024;
+    pub const AXE: u32 = 2048;
+    pub const LAVA_NAILGUN: u32 = 4096;
+    pub const LAVA_SUPER_NAILGUN: u32 = 8192;
+    pub const MULTI_GRENADE: u32 = 16384;
+    pub const MULTI_ROCKET: u32 = 32768;
+    pub const PLASMA_GUN: u32 = 65536;
+    pub const ARMOR_1: u32 = 1 << 23;
+    pub const ARMOR_2: u32 = 1 << 24;
+    pub const ARMOR_3: u32 = 1 << 25;
+    pub const LAVA_NAILS: u32 = 1 << 26;
+    pub const PLASMA_AMMO: u32 = 1 << 27;
+    pub const MULTI_ROCKETS: u32 = 1 << 28;
+    pub const SHIELD: u32 = 1 << 29;
+    pub const ANTIGRAV: u32 = 1 << 30;
+    pub const SUPERHEALTH: u32 = 1 << 31;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MissionPack {
+    /// Scourge of Armagon.
+    Hipnotic,
+    /// Dissolution of Eternity.
+    Rogue,
+}
+
+impl MissionPack {
+    /// Returns the name of the game directory the mission pack is installed in.
+    pub fn game_dir(self) -> &'static str {
+        match self {
+            MissionPack::Hipnotic => "hipnotic",
+            MissionPack::Rogue => "rogue",
+        }
+    }
+
+    /// Returns the mission pack installed in the game directory `name`, if any.
+    ///
+    /// This is the inverse of [`MissionPack::game_dir`], and is used to read back the
+    /// `missionpack` cvar.
+    pub fn from_name(name: &str) -> Option<MissionPack> {
+        match name.to_lowercase().as_str() {
+            "hipnotic" => Some(MissionPack::Hipnotic),
+            "rogue" => Some(MissionPack::Rogue),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MissionPack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.game_dir())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        for pack in &[MissionPack::Hipnotic, MissionPack::Rogue] {
+            assert_eq!(MissionPack::from_name(pack.game_dir()), Some(*pack));
+        }
+        assert_eq!(MissionPack::from_name("ROGUE"), Some(MissionPack::Rogue));
+        assert_eq!(MissionPack::from_name(""), None);
+        assert_eq!(MissionPack::from_name("id1"), None);
+    }
+}
@@ -21,16 +21,29 @@
 pub mod alloc;
 pub mod bitset;
 pub mod bsp;
+pub mod cache;
+pub mod clock;
 pub mod console;
+pub mod crc;
+pub mod dds;
+pub mod deterministic;
 pub mod engine;
+pub mod fallback;
 pub mod host;
+pub mod http;
+pub mod install;
+pub mod kpf;
+pub mod limits;
 pub mod math;
 pub mod mdl;
+pub mod mission_pack;
 pub mod model;
 pub mod net;
 pub mod pak;
 pub mod parse;
+pub mod profile;
 pub mod sprite;
+pub mod tga;
 pub mod util;
 pub mod vfs;
 pub mod wad;
@@ -47,9 +60,3 @@ pub fn default_base_dir() -> std::path::PathBuf {
     }
 }
 
-pub const MAX_LIGHTSTYLES: usize = 64;
-
-/// The maximum number of `.pak` files that should be loaded at runtime.
-///
-/// The original engine does not make this restriction, and this limit can be increased if need be.
-pub const MAX_PAKFILES: usize = 32;
@@ -17,9 +17,10 @@
 
 use crate::common::{
     bsp::{BspFileError, BspModel},
+    host,
     mdl::{self, AliasModel, MdlFileError},
     sprite::{self, SpriteModel},
-    vfs::{Vfs, VfsError},
+    vfs::{Vfs, VfsError, VirtualFile},
 };
 
 use cgmath::Vector3;
@@ -90,18 +91,38 @@ impl Model {
         S: AsRef<str>,
     {
         let name = name.as_ref();
+        Model::from_file(name, vfs.open(name)?)
+    }
+
+    /// Loads alias and sprite models, decoding them on up to `threads` worker threads.
+    ///
+    /// The files are opened on the calling thread, since the VFS can't be shared between threads.
+    /// The results are in the same order as `names`.
+    pub fn load_many<S>(vfs: &Vfs, names: &[S], threads: usize) -> Vec<Result<Model, ModelError>>
+    where
+        S: AsRef<str>,
+    {
+        let files = names
+            .iter()
+            .map(|name| Ok((name.as_ref(), vfs.open(name)?)))
+            .collect::<Vec<Result<_, ModelError>>>();
+
+        host::parallel_map("loader", threads, files, |file| {
+            let (name, data) = file?;
+            Model::from_file(name, data)
+        })
+    }
+
+    fn from_file(name: &str, data: VirtualFile) -> Result<Model, ModelError> {
         // TODO: original engine uses the magic numbers of each format instead of the extension.
         if name.ends_with(".bsp") {
             panic!("BSP files may contain multiple models, use bsp::load for this");
         } else if name.ends_with(".mdl") {
-            Ok(Model::from_alias_model(
-                name.to_owned(),
-                mdl::load(vfs.open(name)?)?,
-            ))
+            Ok(Model::from_alias_model(name.to_owned(), mdl::load(data)?))
         } else if name.ends_with(".spr") {
             Ok(Model::from_sprite_model(
                 name.to_owned(),
-                sprite::load(vfs.open(name)?),
+                sprite::load(data),
             ))
         } else {
             panic!("Unrecognized model type: {}", name);
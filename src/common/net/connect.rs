@@ -25,7 +25,7 @@ use std::{
 };
 
 use crate::common::{
-    net::{NetError, QSocket, MAX_MESSAGE},
+    net::{self, NetError, QSocket, MAX_MESSAGE},
     util,
 };
 
@@ -37,6 +37,15 @@ pub const CONNECT_PROTOCOL_VERSION: u8 = 3;
 const CONNECT_CONTROL: i32 = 1 << 31;
 const CONNECT_LENGTH_MASK: i32 = 0x0000FFFF;
 
+// Reads a null-terminated string, treating invalid UTF-8 as a malformed packet.
+fn read_cstring<R>(reader: &mut R) -> Result<String, NetError>
+where
+    R: std::io::BufRead,
+{
+    util::read_cstring(reader)
+        .map_err(|e| NetError::InvalidData(format!("string is not valid UTF-8: {}", e)))
+}
+
 pub trait ConnectPacket {
     /// Returns the numeric value of this packet's code.
     fn code(&self) -> u8;
@@ -541,6 +550,11 @@ impl ConnectListener {
         Ok(ConnectListener { socket })
     }
 
+    /// Returns the local address this listener is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr, NetError> {
+        Ok(self.socket.local_addr()?)
+    }
+
     /// Receives a request and returns it along with its remote address.
     pub fn recv_request(&self) -> Result<(Request, SocketAddr), NetError> {
         // Original engine receives connection requests in `net_message`,
@@ -551,9 +565,10 @@ impl ConnectListener {
 
         let control = reader.read_i32::<NetworkEndian>()?;
 
-        // TODO: figure out what a control value of -1 means
+        // a control value of -1 marks connectionless packets from QuakeWorld and later games,
+        // which are common on a LAN
         if control == -1 {
-            return Err(NetError::with_msg("Control value is -1"));
+            return Err(NetError::InvalidData(String::from("connectionless packet")));
         }
 
         // high 4 bits must be 0x8000 (CONNECT_CONTROL)
@@ -587,7 +602,7 @@ impl ConnectListener {
 
         let request = match request_code {
             RequestCode::Connect => {
-                let game_name = util::read_cstring(&mut reader).unwrap();
+                let game_name = read_cstring(&mut reader)?;
                 let proto_ver = reader.read_u8()?;
                 Request::Connect(RequestConnect {
                     game_name,
@@ -596,7 +611,7 @@ impl ConnectListener {
             }
 
             RequestCode::ServerInfo => {
-                let game_name = util::read_cstring(&mut reader).unwrap();
+                let game_name = read_cstring(&mut reader)?;
                 Request::ServerInfo(RequestServerInfo { game_name })
             }
 
@@ -606,7 +621,7 @@ impl ConnectListener {
             }
 
             RequestCode::RuleInfo => {
-                let prev_cvar = util::read_cstring(&mut reader).unwrap();
+                let prev_cvar = read_cstring(&mut reader)?;
                 Request::RuleInfo(RequestRuleInfo { prev_cvar })
             }
         };
@@ -614,10 +629,139 @@ impl ConnectListener {
         Ok((request, remote))
     }
 
+    /// Receives a request if one is waiting, without blocking.
+    pub fn try_recv_request(&self) -> Result<Option<(Request, SocketAddr)>, NetError> {
+        self.socket.set_nonblocking(true)?;
+        let result = self.recv_request();
+        self.socket.set_nonblocking(false)?;
+
+        match result {
+            Err(NetError::Io(ref e)) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            r => r.map(Some),
+        }
+    }
+
     pub fn send_response(&self, response: Response, remote: SocketAddr) -> Result<(), NetError> {
         self.socket.send_to(&response.to_bytes()?, remote)?;
         Ok(())
     }
+
+    /// Handles all waiting out-of-band requests without blocking.
+    ///
+    /// Status queries, including LAN discovery broadcasts, are answered from `status`. The first
+    /// connection request is returned for the caller to accept or reject, and any requests
+    /// behind it are left for the next call. Malformed packets are logged and dropped.
+    pub fn poll(
+        &self,
+        status: &ServerStatus,
+    ) -> Result<Option<(RequestConnect, SocketAddr)>, NetError> {
+        loop {
+            let (request, remote) = match self.try_recv_request() {
+                Ok(Some(r)) => r,
+                Ok(None) => return Ok(None),
+                Err(NetError::InvalidData(msg)) => {
+                    debug!("Invalid out-of-band packet: {}", msg);
+                    continue;
+                }
+                Err(NetError::Io(ref e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                    debug!("Truncated out-of-band packet");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            match request {
+                Request::Connect(connect) => return Ok(Some((connect, remote))),
+                request => {
+                    if let Some(response) = status.respond(&request) {
+                        self.send_response(response, remote)?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A player as listed in answers to status queries.
+#[derive(Clone, Debug)]
+pub struct PlayerStatus {
+    pub name: String,
+    pub colors: i32,
+    pub frags: i32,
+    /// How long the player has been connected, in seconds.
+    pub connect_duration: i32,
+    pub address: String,
+}
+
+/// The public state of a server, used to answer status queries.
+#[derive(Clone, Debug)]
+pub struct ServerStatus {
+    /// The server's address as advertised to clients.
+    pub address: String,
+    pub hostname: String,
+    pub levelname: String,
+    pub max_clients: u8,
+    pub players: Vec<PlayerStatus>,
+    /// Server cvars and their values, in the order they are listed to clients.
+    pub rules: Vec<(String, String)>,
+}
+
+impl ServerStatus {
+    /// Returns the response to a status query.
+    ///
+    /// Connection requests aren't status queries and get no response, and neither do server
+    /// info queries for another game or player info queries for players that don't exist. Rule
+    /// queries walk the rule list: each is answered with the rule after the named one, or the
+    /// first rule if the name is empty, until the list runs out.
+    pub fn respond(&self, request: &Request) -> Option<Response> {
+        match *request {
+            Request::Connect(_) => None,
+
+            Request::ServerInfo(ref info) => {
+                if info.game_name != net::GAME_NAME {
+                    return None;
+                }
+
+                Some(Response::ServerInfo(ResponseServerInfo {
+                    address: self.address.clone(),
+                    hostname: self.hostname.clone(),
+                    levelname: self.levelname.clone(),
+                    client_count: self.players.len() as u8,
+                    client_max: self.max_clients,
+                    protocol_version: net::PROTOCOL_VERSION,
+                }))
+            }
+
+            Request::PlayerInfo(ref info) => {
+                let player = self.players.get(info.player_id as usize)?;
+                Some(Response::PlayerInfo(ResponsePlayerInfo {
+                    player_id: info.player_id,
+                    player_name: player.name.clone(),
+                    colors: player.colors,
+                    frags: player.frags,
+                    connect_duration: player.connect_duration,
+                    address: player.address.clone(),
+                }))
+            }
+
+            Request::RuleInfo(ref info) => {
+                let next = if info.prev_cvar.is_empty() {
+                    0
+                } else {
+                    self.rules
+                        .iter()
+                        .position(|(name, _)| *name == info.prev_cvar)?
+                        + 1
+                };
+
+                let (name, value) = self.rules.get(next)?;
+                Some(Response::RuleInfo(ResponseRuleInfo {
+                    cvar_name: name.clone(),
+                    cvar_val: value.clone(),
+                }))
+            }
+        }
+    }
 }
 
 pub struct ConnectSocket {
@@ -670,9 +814,10 @@ impl ConnectSocket {
 
         let control = reader.read_i32::<NetworkEndian>()?;
 
-        // TODO: figure out what a control value of -1 means
+        // a control value of -1 marks connectionless packets from QuakeWorld and later games,
+        // which are common on a LAN
         if control == -1 {
-            return Err(NetError::with_msg("Control value is -1"));
+            return Err(NetError::InvalidData(String::from("connectionless packet")));
         }
 
         // high 4 bits must be 0x8000 (CONNECT_CONTROL)
@@ -710,14 +855,14 @@ impl ConnectSocket {
             }
 
             ResponseCode::Reject => {
-                let message = util::read_cstring(&mut reader).unwrap();
+                let message = read_cstring(&mut reader)?;
                 Response::Reject(ResponseReject { message })
             }
 
             ResponseCode::ServerInfo => {
-                let address = util::read_cstring(&mut reader).unwrap();
-                let hostname = util::read_cstring(&mut reader).unwrap();
-                let levelname = util::read_cstring(&mut reader).unwrap();
+                let address = read_cstring(&mut reader)?;
+                let hostname = read_cstring(&mut reader)?;
+                let levelname = read_cstring(&mut reader)?;
                 let client_count = reader.read_u8()?;
                 let client_max = reader.read_u8()?;
                 let protocol_version = reader.read_u8()?;
@@ -732,8 +877,32 @@ impl ConnectSocket {
                 })
             }
 
-            ResponseCode::PlayerInfo => unimplemented!(),
-            ResponseCode::RuleInfo => unimplemented!(),
+            ResponseCode::PlayerInfo => {
+                let player_id = reader.read_u8()?;
+                let player_name = read_cstring(&mut reader)?;
+                let colors = reader.read_i32::<LittleEndian>()?;
+                let frags = reader.read_i32::<LittleEndian>()?;
+                let connect_duration = reader.read_i32::<LittleEndian>()?;
+                let address = read_cstring(&mut reader)?;
+
+                Response::PlayerInfo(ResponsePlayerInfo {
+                    player_id,
+                    player_name,
+                    colors,
+                    frags,
+                    connect_duration,
+                    address,
+                })
+            }
+
+            ResponseCode::RuleInfo => {
+                let cvar_name = read_cstring(&mut reader)?;
+                let cvar_val = read_cstring(&mut reader)?;
+                Response::RuleInfo(ResponseRuleInfo {
+                    cvar_name,
+                    cvar_val,
+                })
+            }
         };
 
         Ok(Some((response, remote)))
@@ -840,4 +1009,156 @@ mod test {
     fn test_connect_listener_bind() {
         let _listener = ConnectListener::bind("127.0.0.1:26000").unwrap();
     }
+
+    fn test_status() -> ServerStatus {
+        ServerStatus {
+            address: String::from("192.168.1.2:26000"),
+            hostname: String::from("lan party"),
+            levelname: String::from("e1m1"),
+            max_clients: 8,
+            players: vec![PlayerStatus {
+                name: String::from("player"),
+                colors: 0x4d,
+                frags: 3,
+                connect_duration: 120,
+                address: String::from("192.168.1.3:27001"),
+            }],
+            rules: vec![
+                (String::from("deathmatch"), String::from("1")),
+                (String::from("fraglimit"), String::from("20")),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_server_status_respond() {
+        let status = test_status();
+
+        match status.respond(&Request::ServerInfo(RequestServerInfo {
+            game_name: String::from("QUAKE"),
+        })) {
+            Some(Response::ServerInfo(info)) => {
+                assert_eq!(info.hostname, "lan party");
+                assert_eq!(info.levelname, "e1m1");
+                assert_eq!((info.client_count, info.client_max), (1, 8));
+            }
+            r => panic!("unexpected response {:?}", r),
+        }
+
+        // other games and connection requests are ignored
+        assert!(status
+            .respond(&Request::ServerInfo(RequestServerInfo {
+                game_name: String::from("HEXEN2"),
+            }))
+            .is_none());
+        assert!(status
+            .respond(&Request::connect("QUAKE", CONNECT_PROTOCOL_VERSION))
+            .is_none());
+
+        match status.respond(&Request::PlayerInfo(RequestPlayerInfo { player_id: 0 })) {
+            Some(Response::PlayerInfo(info)) => {
+                assert_eq!(info.player_name, "player");
+                assert_eq!(info.frags, 3);
+            }
+            r => panic!("unexpected response {:?}", r),
+        }
+        assert!(status
+            .respond(&Request::PlayerInfo(RequestPlayerInfo { player_id: 1 }))
+            .is_none());
+    }
+
+    #[test]
+    fn test_server_status_rules() {
+        let status = test_status();
+        let next_rule = |prev: &str| match status.respond(&Request::rule_info(prev)) {
+            Some(Response::RuleInfo(rule)) => Some((rule.cvar_name, rule.cvar_val)),
+            None => None,
+            r => panic!("unexpected response {:?}", r),
+        };
+
+        assert_eq!(
+            next_rule(""),
+            Some((String::from("deathmatch"), String::from("1")))
+        );
+        assert_eq!(
+            next_rule("deathmatch"),
+            Some((String::from("fraglimit"), String::from("20")))
+        );
+        assert_eq!(next_rule("fraglimit"), None);
+        assert_eq!(next_rule("nonexistent"), None);
+    }
+
+    #[test]
+    fn test_connect_listener_poll() {
+        let listener = ConnectListener::bind("127.0.0.1:0").unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+        let mut client = ConnectSocket::bind("127.0.0.1:0").unwrap();
+        let status = test_status();
+
+        // nothing waiting
+        assert!(listener.poll(&status).unwrap().is_none());
+
+        client
+            .send_request(Request::server_info("QUAKE"), listener_addr)
+            .unwrap();
+        client
+            .send_request(
+                Request::connect("QUAKE", CONNECT_PROTOCOL_VERSION),
+                listener_addr,
+            )
+            .unwrap();
+
+        // the status query is answered and the connection request handed back
+        let timeout = Duration::seconds(1);
+        let deadline = ::std::time::Instant::now() + timeout.to_std().unwrap();
+        let connect = loop {
+            if let Some((connect, _)) = listener.poll(&status).unwrap() {
+                break connect;
+            }
+            assert!(::std::time::Instant::now() < deadline);
+        };
+        assert_eq!(connect.proto_ver, CONNECT_PROTOCOL_VERSION);
+
+        match client.recv_response(Some(timeout)).unwrap() {
+            Some((Response::ServerInfo(info), remote)) => {
+                assert_eq!(remote, listener_addr);
+                assert_eq!(info.hostname, "lan party");
+            }
+            r => panic!("unexpected response {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_connect_listener_poll_drops_malformed() {
+        let listener = ConnectListener::bind("127.0.0.1:0").unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+        let mut client = ConnectSocket::bind("127.0.0.1:0").unwrap();
+        let raw = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let status = test_status();
+
+        // a server info query whose game name isn't UTF-8, a connection request missing its
+        // protocol version and a QuakeWorld status query
+        let bad_utf8 = [0x80, 0x00, 0x00, 0x08, 0x02, 0xFF, 0xFE, 0x00];
+        let truncated = [0x80, 0x00, 0x00, 0x05, 0x01];
+        let connectionless = b"\xFF\xFF\xFF\xFFstatus\n";
+        raw.send_to(&bad_utf8, listener_addr).unwrap();
+        raw.send_to(&truncated, listener_addr).unwrap();
+        raw.send_to(connectionless, listener_addr).unwrap();
+        client
+            .send_request(
+                Request::connect("QUAKE", CONNECT_PROTOCOL_VERSION),
+                listener_addr,
+            )
+            .unwrap();
+
+        // all are dropped and the valid request behind them is still returned
+        let deadline = ::std::time::Instant::now() + ::std::time::Duration::from_secs(1);
+        let connect = loop {
+            if let Some((connect, _)) = listener.poll(&status).unwrap() {
+                break connect;
+            }
+            assert!(::std::time::Instant::now() < deadline);
+        };
+        assert_eq!(connect.game_name, "QUAKE");
+    }
 }
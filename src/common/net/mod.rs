@@ -36,6 +36,7 @@ use byteorder::{LittleEndian, NetworkEndian, ReadBytesExt, WriteBytesExt};
 use cgmath::{Deg, Vector3, Zero};
 use chrono::Duration;
 use num::FromPrimitive;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub const MAX_MESSAGE: usize = 8192;
 const MAX_DATAGRAM: usize = 1024;
@@ -44,6 +45,22 @@ const MAX_PACKET: usize = HEADER_SIZE + MAX_DATAGRAM;
 
 pub const PROTOCOL_VERSION: u8 = 15;
 
+/// The FitzQuake protocol version.
+///
+/// This is the standard protocol with the FitzQuake extensions (see [`WireFormat::fitz`]).
+pub const PROTOCOL_FITZQUAKE: i32 = 666;
+
+/// The RMQ protocol version.
+///
+/// This is [`PROTOCOL_FITZQUAKE`] with [`ProtocolFlags`] in
+/// `svc_serverinfo`.
+pub const PROTOCOL_RMQ: i32 = 999;
+
+/// The protocol version used by the Nehahra movie demos.
+///
+/// This is the standard protocol with entity transparency (see [`UpdateFlags::TRANSPARENCY`]).
+pub const PROTOCOL_NEHAHRA_MOVIE: i32 = 250;
+
 const NAME_LEN: usize = 64;
 
 const FAST_UPDATE_FLAG: u8 = 0x80;
@@ -120,7 +137,7 @@ pub enum MsgKind {
 }
 
 bitflags! {
-    pub struct UpdateFlags: u16 {
+    pub struct UpdateFlags: u32 {
         const MORE_BITS = 1 << 0;
         const ORIGIN_X = 1 << 1;
         const ORIGIN_Y = 1 << 2;
@@ -136,11 +153,22 @@ bitflags! {
         const SKIN = 1 << 12;
         const EFFECTS = 1 << 13;
         const LONG_ENTITY = 1 << 14;
+        /// Nehahra entity transparency, only in the original protocol.
+        const TRANSPARENCY = 1 << 15;
+
+        // FitzQuake extensions, which reuse the transparency bit
+        const EXTEND1 = 1 << 15;
+        const ALPHA = 1 << 16;
+        const FRAME2 = 1 << 17;
+        const MODEL2 = 1 << 18;
+        const LERP_FINISH = 1 << 19;
+        const SCALE = 1 << 20;
+        const EXTEND2 = 1 << 23;
     }
 }
 
 bitflags! {
-    pub struct ClientUpdateFlags: u16 {
+    pub struct ClientUpdateFlags: u32 {
         const VIEW_HEIGHT = 1 << 0;
         const IDEAL_PITCH = 1 << 1;
         const PUNCH_PITCH = 1 << 2;
@@ -156,6 +184,158 @@ bitflags! {
         const WEAPON_FRAME = 1 << 12;
         const ARMOR = 1 << 13;
         const WEAPON = 1 << 14;
+
+        // FitzQuake extensions
+        const EXTEND1 = 1 << 15;
+        const WEAPON2 = 1 << 16;
+        const ARMOR2 = 1 << 17;
+        const AMMO2 = 1 << 18;
+        const SHELLS2 = 1 << 19;
+        const NAILS2 = 1 << 20;
+        const ROCKETS2 = 1 << 21;
+        const CELLS2 = 1 << 22;
+        const EXTEND2 = 1 << 23;
+        const WEAPON_FRAME2 = 1 << 24;
+        const WEAPON_ALPHA = 1 << 25;
+    }
+}
+
+bitflags! {
+    /// Wire encoding options announced by the server in RMQ-style `svc_serverinfo`.
+    pub struct ProtocolFlags: u32 {
+        const SHORT_ANGLE = 1 << 1;
+        const FLOAT_ANGLE = 1 << 2;
+        const COORD_24BIT = 1 << 3;
+        const FLOAT_COORD = 1 << 4;
+        const EDICT_SCALE = 1 << 5;
+        const ALPHA_SANITY = 1 << 6;
+        const INT32_COORD = 1 << 7;
+    }
+}
+
+/// The encoding of coordinates on the wire.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CoordFormat {
+    /// 13.3 fixed point in an `i16`, the original encoding.
+    Fixed,
+    /// An `i16` integer part followed by a `u8` fraction.
+    Fixed24,
+    /// 28.4 fixed point in an `i32`.
+    Int32,
+    /// A full-precision `f32`.
+    Float,
+}
+
+/// The encoding of angles on the wire.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AngleFormat {
+    /// 1/256 of a turn in a `u8`, the original encoding.
+    Byte,
+    /// 1/65536 of a turn in an `i16`.
+    Short,
+    /// Degrees in a full-precision `f32`.
+    Float,
+}
+
+/// How coordinates, angles and indices are encoded in a connection's messages.
+///
+/// This only affects the wire format. Decoded values are always kept at full precision.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WireFormat {
+    pub coord: CoordFormat,
+    pub angle: AngleFormat,
+    /// Whether messages use the FitzQuake extensions: 16-bit model, frame and sound indices,
+    /// entity alpha in fast updates and 16-bit player stats.
+    pub fitz: bool,
+}
+
+impl WireFormat {
+    /// The encoding used by the original protocol.
+    pub const STANDARD: WireFormat = WireFormat {
+        coord: CoordFormat::Fixed,
+        angle: AngleFormat::Byte,
+        fitz: false,
+    };
+
+    /// Determines the encoding from the protocol version and flags in `svc_serverinfo`.
+    pub fn for_protocol(protocol_version: i32, flags: ProtocolFlags) -> WireFormat {
+        let coord = if flags.contains(ProtocolFlags::FLOAT_COORD) {
+            CoordFormat::Float
+        } else if flags.contains(ProtocolFlags::INT32_COORD) {
+            CoordFormat::Int32
+        } else if flags.contains(ProtocolFlags::COORD_24BIT) {
+            CoordFormat::Fixed24
+        } else {
+            CoordFormat::Fixed
+        };
+
+        let angle = if flags.contains(ProtocolFlags::FLOAT_ANGLE) {
+            AngleFormat::Float
+        } else if flags.contains(ProtocolFlags::SHORT_ANGLE) {
+            AngleFormat::Short
+        } else {
+            AngleFormat::Byte
+        };
+
+        WireFormat {
+            coord,
+            angle,
+            fitz: protocol_version == PROTOCOL_FITZQUAKE || protocol_version == PROTOCOL_RMQ,
+        }
+    }
+
+    /// The encoding of the view angles in `clc_move`, which FitzQuake sends with at least 16 bits.
+    fn move_format(self) -> WireFormat {
+        match self.angle {
+            AngleFormat::Byte if self.fitz => WireFormat {
+                angle: AngleFormat::Short,
+                ..self
+            },
+            _ => self,
+        }
+    }
+}
+
+impl Default for WireFormat {
+    fn default() -> Self {
+        WireFormat::STANDARD
+    }
+}
+
+bitflags! {
+    /// Optional fields of FitzQuake's `svc_spawnbaseline2`.
+    pub struct BaselineFlags: u8 {
+        const LARGE_MODEL = 1 << 0;
+        const LARGE_FRAME = 1 << 1;
+        const ALPHA = 1 << 2;
+    }
+}
+
+/// Converts a FitzQuake entity alpha byte to an opacity.
+///
+/// 0 means the entity is fully opaque, and 1 through 255 map linearly onto opacities from 0 to 1.
+pub fn decode_alpha(alpha: u8) -> f32 {
+    match alpha {
+        0 => 1.0,
+        a => (a - 1) as f32 / 254.0,
+    }
+}
+
+/// Converts an opacity to a FitzQuake entity alpha byte, the inverse of [`decode_alpha`].
+pub fn encode_alpha(alpha: f32) -> u8 {
+    (alpha.max(0.0).min(1.0) * 254.0).round() as u8 + 1
+}
+
+/// Splits a 16-bit index into the low byte and, if it doesn't fit in one, the high byte that
+/// FitzQuake sends separately.
+fn split_index(index: u16, format: WireFormat, what: &str) -> Result<(u8, Option<u8>), NetError> {
+    match index >> 8 {
+        0 => Ok((index as u8, None)),
+        high if format.fitz => Ok((index as u8, Some(high as u8))),
+        _ => Err(NetError::InvalidData(format!(
+            "{} {} doesn't fit in the original protocol",
+            what, index
+        ))),
     }
 }
 
@@ -164,6 +344,10 @@ bitflags! {
         const VOLUME = 1 << 0;
         const ATTENUATION = 1 << 1;
         const LOOPING = 1 << 2;
+        /// FitzQuake: the entity and channel are sent separately.
+        const LARGE_ENTITY = 1 << 3;
+        /// FitzQuake: the sound index is 16 bits.
+        const LARGE_SOUND = 1 << 4;
     }
 }
 
@@ -192,6 +376,13 @@ bitflags! {
         const INVULNERABILITY  = 0x00100000;
         const SUIT             = 0x00200000;
         const QUAD             = 0x00400000;
+        // unused by the original game, but the mission packs store their own weapons and items
+        // here
+        const EXTRA_23         = 0x00800000;
+        const EXTRA_24         = 0x01000000;
+        const EXTRA_25         = 0x02000000;
+        const EXTRA_26         = 0x04000000;
+        const EXTRA_27         = 0x08000000;
         const SIGIL_1          = 0x10000000;
         const SIGIL_2          = 0x20000000;
         const SIGIL_3          = 0x40000000;
@@ -199,6 +390,26 @@ bitflags! {
     }
 }
 
+// item flags are stored as their bits, as in savegames
+impl Serialize for ItemFlags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.bits().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ItemFlags {
+    fn deserialize<D>(deserializer: D) -> Result<ItemFlags, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bits = u32::deserialize(deserializer)?;
+        Ok(ItemFlags::from_bits_truncate(bits))
+    }
+}
+
 bitflags! {
     pub struct ButtonFlags: u8 {
         const ATTACK = 0x01;
@@ -206,7 +417,7 @@ bitflags! {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct PlayerColor {
     top: u8,
     bottom: u8,
@@ -235,6 +446,16 @@ impl PlayerColor {
     pub fn bits(&self) -> u8 {
         self.top << 4 | (self.bottom & 0x0F)
     }
+
+    /// Returns the shirt color, an index into the 16 rows of the palette.
+    pub fn top(&self) -> u8 {
+        self.top & 0x0F
+    }
+
+    /// Returns the pants color, an index into the 16 rows of the palette.
+    pub fn bottom(&self) -> u8 {
+        self.bottom & 0x0F
+    }
 }
 
 impl ::std::convert::From<u8> for PlayerColor {
@@ -252,7 +473,7 @@ pub struct ColorShift {
     pub percent: i32,
 }
 
-#[derive(Copy, Clone, Debug, Eq, FromPrimitive, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, FromPrimitive, PartialEq, Serialize, Deserialize)]
 pub enum ClientStat {
     Health = 0,
     Frags = 1,
@@ -330,7 +551,7 @@ pub enum TempEntity {
 }
 
 impl TempEntity {
-    pub fn read_temp_entity<R>(reader: &mut R) -> Result<TempEntity, NetError>
+    pub fn read_temp_entity<R>(reader: &mut R, format: WireFormat) -> Result<TempEntity, NetError>
     where
         R: BufRead + ReadBytesExt,
     {
@@ -370,10 +591,10 @@ impl TempEntity {
                     Code::Teleport => PointEntityKind::Teleport,
                     _ => unreachable!(),
                 },
-                origin: read_coord_vector3(reader)?,
+                origin: read_coord_vector3(reader, format)?,
             },
             Code::ColorExplosion => {
-                let origin = read_coord_vector3(reader)?;
+                let origin = read_coord_vector3(reader, format)?;
                 let color_start = reader.read_u8()?;
                 let color_len = reader.read_u8()?;
 
@@ -395,19 +616,19 @@ impl TempEntity {
                     },
                 },
                 entity_id: reader.read_i16::<LittleEndian>()?,
-                start: read_coord_vector3(reader)?,
-                end: read_coord_vector3(reader)?,
+                start: read_coord_vector3(reader, format)?,
+                end: read_coord_vector3(reader, format)?,
             },
             Code::Grapple => Beam {
                 kind: BeamEntityKind::Grapple,
                 entity_id: reader.read_i16::<LittleEndian>()?,
-                start: read_coord_vector3(reader)?,
-                end: read_coord_vector3(reader)?,
+                start: read_coord_vector3(reader, format)?,
+                end: read_coord_vector3(reader, format)?,
             },
         })
     }
 
-    pub fn write_temp_entity<W>(&self, writer: &mut W) -> Result<(), NetError>
+    pub fn write_temp_entity<W>(&self, writer: &mut W, format: WireFormat) -> Result<(), NetError>
     where
         W: WriteBytesExt,
     {
@@ -439,21 +660,20 @@ impl TempEntity {
                             _ => unreachable!(),
                         };
 
-                        // write code
                         writer.write_u8(code as u8)?;
+                        write_coord_vector3(writer, format, origin)?;
                     }
                     PointEntityKind::ColorExplosion {
                         color_start,
                         color_len,
                     } => {
-                        // write code and colors
+                        // the colors follow the origin
                         writer.write_u8(Code::ColorExplosion as u8)?;
+                        write_coord_vector3(writer, format, origin)?;
                         writer.write_u8(color_start)?;
                         writer.write_u8(color_len)?;
                     }
                 };
-
-                write_coord_vector3(writer, origin)?;
             }
 
             TempEntity::Beam {
@@ -467,15 +687,19 @@ impl TempEntity {
                         1 => Code::Lightning1,
                         2 => Code::Lightning2,
                         3 => Code::Lightning3,
-                        // TODO: error
-                        _ => panic!("invalid lightning model id: {}", model_id),
+                        _ => {
+                            return Err(NetError::InvalidData(format!(
+                                "Lightning model id {}",
+                                model_id
+                            )))
+                        }
                     },
                     BeamEntityKind::Grapple => Code::Grapple,
                 };
-                writer.write_i16::<LittleEndian>(entity_id)?;
                 writer.write_u8(code as u8)?;
-                write_coord_vector3(writer, start)?;
-                write_coord_vector3(writer, end)?;
+                writer.write_i16::<LittleEndian>(entity_id)?;
+                write_coord_vector3(writer, format, start)?;
+                write_coord_vector3(writer, format, end)?;
             }
         }
 
@@ -512,6 +736,8 @@ pub struct EntityState {
     pub colormap: u8,
     pub skin_id: usize,
     pub effects: EntityEffects,
+    /// The entity's opacity, where 1 is fully opaque.
+    pub alpha: f32,
 }
 
 impl EntityState {
@@ -524,6 +750,7 @@ impl EntityState {
             colormap: 0,
             skin_id: 0,
             effects: EntityEffects::empty(),
+            alpha: 1.0,
         }
     }
 }
@@ -531,8 +758,8 @@ impl EntityState {
 #[derive(Clone, Debug, PartialEq)]
 pub struct EntityUpdate {
     pub ent_id: u16,
-    pub model_id: Option<u8>,
-    pub frame_id: Option<u8>,
+    pub model_id: Option<u16>,
+    pub frame_id: Option<u16>,
     pub colormap: Option<u8>,
     pub skin_id: Option<u8>,
     pub effects: Option<EntityEffects>,
@@ -543,6 +770,8 @@ pub struct EntityUpdate {
     pub origin_z: Option<f32>,
     pub roll: Option<Deg<f32>>,
     pub no_lerp: bool,
+    /// Entity opacity, only sent by Nehahra and FitzQuake servers.
+    pub alpha: Option<f32>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -558,19 +787,316 @@ pub struct PlayerData {
     pub items: ItemFlags,
     pub on_ground: bool,
     pub in_water: bool,
-    pub weapon_frame: Option<u8>,
-    pub armor: Option<u8>,
-    pub weapon: Option<u8>,
+    pub weapon_frame: Option<u16>,
+    pub armor: Option<u16>,
+    pub weapon: Option<u16>,
     pub health: i16,
-    pub ammo: u8,
-    pub ammo_shells: u8,
-    pub ammo_nails: u8,
-    pub ammo_rockets: u8,
-    pub ammo_cells: u8,
+    pub ammo: u16,
+    pub ammo_shells: u16,
+    pub ammo_nails: u16,
+    pub ammo_rockets: u16,
+    pub ammo_cells: u16,
     pub active_weapon: u8,
 }
 
 impl EntityUpdate {
+    /// Reads a fast update, the first byte of which (`code_num`) has already been read.
+    ///
+    /// Fast updates have no command code. Instead, the high bit of the first byte is set and the
+    /// remaining bits hold the low byte of the update flags.
+    pub fn read_fast_update<R>(
+        reader: &mut R,
+        code_num: u8,
+        format: WireFormat,
+    ) -> Result<EntityUpdate, NetError>
+    where
+        R: BufRead + ReadBytesExt,
+    {
+        let mut all_bits = (code_num & !FAST_UPDATE_FLAG) as u32;
+        if all_bits & UpdateFlags::MORE_BITS.bits() != 0 {
+            all_bits |= (reader.read_u8()? as u32) << 8;
+        }
+
+        if format.fitz {
+            if all_bits & UpdateFlags::EXTEND1.bits() != 0 {
+                all_bits |= (reader.read_u8()? as u32) << 16;
+            }
+
+            if all_bits & UpdateFlags::EXTEND2.bits() != 0 {
+                all_bits |= (reader.read_u8()? as u32) << 24;
+            }
+        }
+
+        let update_flags = match UpdateFlags::from_bits(all_bits) {
+            Some(u) => u,
+            None => {
+                return Err(NetError::InvalidData(format!(
+                    "UpdateFlags: {:b}",
+                    all_bits
+                )))
+            }
+        };
+
+        let ent_id;
+        if update_flags.contains(UpdateFlags::LONG_ENTITY) {
+            ent_id = reader.read_u16::<LittleEndian>()?;
+        } else {
+            ent_id = reader.read_u8()? as u16;
+        }
+
+        let mut model_id;
+        if update_flags.contains(UpdateFlags::MODEL) {
+            model_id = Some(reader.read_u8()? as u16);
+        } else {
+            model_id = None;
+        }
+
+        let mut frame_id;
+        if update_flags.contains(UpdateFlags::FRAME) {
+            frame_id = Some(reader.read_u8()? as u16);
+        } else {
+            frame_id = None;
+        }
+
+        let colormap;
+        if update_flags.contains(UpdateFlags::COLORMAP) {
+            colormap = Some(reader.read_u8()?);
+        } else {
+            colormap = None;
+        }
+
+        let skin_id;
+        if update_flags.contains(UpdateFlags::SKIN) {
+            skin_id = Some(reader.read_u8()?);
+        } else {
+            skin_id = None;
+        }
+
+        let effects;
+        if update_flags.contains(UpdateFlags::EFFECTS) {
+            let effects_bits = reader.read_u8()?;
+            effects = match EntityEffects::from_bits(effects_bits) {
+                Some(e) => Some(e),
+                None => {
+                    return Err(NetError::InvalidData(format!(
+                        "EntityEffects: {:b}",
+                        effects_bits
+                    )))
+                }
+            };
+        } else {
+            effects = None;
+        }
+
+        let origin_x;
+        if update_flags.contains(UpdateFlags::ORIGIN_X) {
+            origin_x = Some(read_coord(reader, format)?);
+        } else {
+            origin_x = None;
+        }
+
+        let pitch;
+        if update_flags.contains(UpdateFlags::PITCH) {
+            pitch = Some(read_angle(reader, format)?);
+        } else {
+            pitch = None;
+        }
+
+        let origin_y;
+        if update_flags.contains(UpdateFlags::ORIGIN_Y) {
+            origin_y = Some(read_coord(reader, format)?);
+        } else {
+            origin_y = None;
+        }
+
+        let yaw;
+        if update_flags.contains(UpdateFlags::YAW) {
+            yaw = Some(read_angle(reader, format)?);
+        } else {
+            yaw = None;
+        }
+
+        let origin_z;
+        if update_flags.contains(UpdateFlags::ORIGIN_Z) {
+            origin_z = Some(read_coord(reader, format)?);
+        } else {
+            origin_z = None;
+        }
+
+        let roll;
+        if update_flags.contains(UpdateFlags::ROLL) {
+            roll = Some(read_angle(reader, format)?);
+        } else {
+            roll = None;
+        }
+
+        let no_lerp = update_flags.contains(UpdateFlags::NO_LERP);
+
+        let alpha;
+        if format.fitz {
+            if update_flags.contains(UpdateFlags::ALPHA) {
+                alpha = Some(decode_alpha(reader.read_u8()?));
+            } else {
+                alpha = None;
+            }
+
+            // entity scale, which we don't support
+            if update_flags.contains(UpdateFlags::SCALE) {
+                reader.read_u8()?;
+            }
+
+            // the high bytes are only sent without the low bytes when the baseline already has
+            // the full index
+            if update_flags.contains(UpdateFlags::FRAME2) {
+                let high = reader.read_u8()? as u16;
+                frame_id = frame_id.map(|f| high << 8 | f);
+            }
+
+            if update_flags.contains(UpdateFlags::MODEL2) {
+                let high = reader.read_u8()? as u16;
+                model_id = model_id.map(|m| high << 8 | m);
+            }
+
+            // the time to finish interpolating, which we don't support
+            if update_flags.contains(UpdateFlags::LERP_FINISH) {
+                reader.read_u8()?;
+            }
+        } else if update_flags.contains(UpdateFlags::TRANSPARENCY) {
+            // the first float gives the number of values that follow. a third value toggles
+            // fullbright, which we don't support.
+            let count = reader.read_f32::<LittleEndian>()?;
+            let value = reader.read_f32::<LittleEndian>()?;
+            if count == 2.0 {
+                reader.read_f32::<LittleEndian>()?;
+            }
+
+            // Nehahra treats an alpha of 0 as the default
+            alpha = Some(if value <= 0.0 { 1.0 } else { value.min(1.0) });
+        } else {
+            alpha = None;
+        }
+
+        Ok(EntityUpdate {
+            ent_id,
+            model_id,
+            frame_id,
+            colormap,
+            skin_id,
+            effects,
+            origin_x,
+            pitch,
+            origin_y,
+            yaw,
+            origin_z,
+            roll,
+            no_lerp,
+            alpha,
+        })
+    }
+
+    /// Writes a fast update, setting the update flags from the fields that are present.
+    pub fn write_fast_update<W>(&self, writer: &mut W, format: WireFormat) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        let model_id = self
+            .model_id
+            .map(|m| split_index(m, format, "model index"))
+            .transpose()?;
+        let frame_id = self
+            .frame_id
+            .map(|f| split_index(f, format, "frame index"))
+            .transpose()?;
+
+        let mut flags = UpdateFlags::empty();
+        flags.set(UpdateFlags::LONG_ENTITY, self.ent_id > u8::MAX as u16);
+        flags.set(UpdateFlags::MODEL, self.model_id.is_some());
+        flags.set(UpdateFlags::FRAME, self.frame_id.is_some());
+        flags.set(UpdateFlags::COLORMAP, self.colormap.is_some());
+        flags.set(UpdateFlags::SKIN, self.skin_id.is_some());
+        flags.set(UpdateFlags::EFFECTS, self.effects.is_some());
+        flags.set(UpdateFlags::ORIGIN_X, self.origin_x.is_some());
+        flags.set(UpdateFlags::PITCH, self.pitch.is_some());
+        flags.set(UpdateFlags::ORIGIN_Y, self.origin_y.is_some());
+        flags.set(UpdateFlags::YAW, self.yaw.is_some());
+        flags.set(UpdateFlags::ORIGIN_Z, self.origin_z.is_some());
+        flags.set(UpdateFlags::ROLL, self.roll.is_some());
+        flags.set(UpdateFlags::NO_LERP, self.no_lerp);
+        if format.fitz {
+            flags.set(UpdateFlags::ALPHA, self.alpha.is_some());
+            flags.set(UpdateFlags::FRAME2, matches!(frame_id, Some((_, Some(_)))));
+            flags.set(UpdateFlags::MODEL2, matches!(model_id, Some((_, Some(_)))));
+            flags.set(UpdateFlags::EXTEND1, flags.bits() & 0xFF_0000 != 0);
+        } else {
+            flags.set(UpdateFlags::TRANSPARENCY, self.alpha.is_some());
+        }
+        flags.set(UpdateFlags::MORE_BITS, flags.bits() & 0xFF00 != 0);
+
+        writer.write_u8(FAST_UPDATE_FLAG | flags.bits() as u8)?;
+        if flags.contains(UpdateFlags::MORE_BITS) {
+            writer.write_u8((flags.bits() >> 8) as u8)?;
+        }
+
+        if format.fitz && flags.contains(UpdateFlags::EXTEND1) {
+            writer.write_u8((flags.bits() >> 16) as u8)?;
+        }
+
+        if flags.contains(UpdateFlags::LONG_ENTITY) {
+            writer.write_u16::<LittleEndian>(self.ent_id)?;
+        } else {
+            writer.write_u8(self.ent_id as u8)?;
+        }
+
+        let bytes = [
+            model_id.map(|(low, _)| low),
+            frame_id.map(|(low, _)| low),
+            self.colormap,
+            self.skin_id,
+        ];
+        for byte in bytes.iter().flatten() {
+            writer.write_u8(*byte)?;
+        }
+
+        if let Some(effects) = self.effects {
+            writer.write_u8(effects.bits())?;
+        }
+
+        let axes = [
+            (self.origin_x, self.pitch),
+            (self.origin_y, self.yaw),
+            (self.origin_z, self.roll),
+        ];
+        for &(coord, angle) in axes.iter() {
+            if let Some(c) = coord {
+                write_coord(writer, format, c)?;
+            }
+
+            if let Some(a) = angle {
+                write_angle(writer, format, a)?;
+            }
+        }
+
+        if format.fitz {
+            if let Some(alpha) = self.alpha {
+                writer.write_u8(encode_alpha(alpha))?;
+            }
+
+            let high_bytes = [
+                frame_id.and_then(|(_, high)| high),
+                model_id.and_then(|(_, high)| high),
+            ];
+            for byte in high_bytes.iter().flatten() {
+                writer.write_u8(*byte)?;
+            }
+        } else if let Some(alpha) = self.alpha {
+            // one value follows: the alpha, without the fullbright toggle
+            writer.write_f32::<LittleEndian>(1.0)?;
+            writer.write_f32::<LittleEndian>(alpha)?;
+        }
+
+        Ok(())
+    }
+
     /// Create an `EntityState` from this update, filling in any `None` values
     /// from the specified baseline state.
     pub fn to_entity_state(&self, baseline: &EntityState) -> EntityState {
@@ -590,6 +1116,7 @@ impl EntityUpdate {
             skin_id: self.skin_id.map_or(baseline.skin_id, |s| s as usize),
             effects: self.effects.unwrap_or(baseline.effects),
             colormap: self.colormap.unwrap_or(baseline.colormap),
+            alpha: self.alpha.unwrap_or(baseline.alpha),
         }
     }
 }
@@ -611,7 +1138,7 @@ pub trait Cmd: Sized {
 }
 
 // TODO: use feature(arbitrary_enum_discriminant)
-#[derive(Debug, FromPrimitive)]
+#[derive(Clone, Copy, Debug, FromPrimitive)]
 pub enum ServerCmdCode {
     Bad = 0,
     NoOp = 1,
@@ -648,9 +1175,20 @@ pub enum ServerCmdCode {
     CdTrack = 32,
     SellScreen = 33,
     Cutscene = 34,
+
+    // Nehahra extensions
+    ShowLmp = 35,
+    HideLmp = 36,
+    SkyBox = 37,
+
+    // FitzQuake extensions
+    Fog = 41,
+    SpawnBaseline2 = 42,
+    SpawnStatic2 = 43,
+    SpawnStaticSound2 = 44,
 }
 
-#[derive(Copy, Clone, Debug, Eq, FromPrimitive, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, FromPrimitive, PartialEq, Serialize, Deserialize)]
 pub enum GameType {
     CoOp = 0,
     Deathmatch = 1,
@@ -676,7 +1214,7 @@ pub enum ServerCmd {
         attenuation: Option<f32>,
         entity_id: u16,
         channel: i8,
-        sound_id: u8,
+        sound_id: u16,
         position: Vector3<f32>,
     },
     Time {
@@ -693,6 +1231,8 @@ pub enum ServerCmd {
     },
     ServerInfo {
         protocol_version: i32,
+        /// Only sent with `PROTOCOL_RMQ`, empty otherwise.
+        protocol_flags: ProtocolFlags,
         max_clients: u8,
         game_type: GameType,
         message: String,
@@ -781,6 +1321,60 @@ pub enum ServerCmd {
     Cutscene {
         text: String,
     },
+    /// Displays a picture at a fixed screen position (Nehahra).
+    ShowLmp {
+        slot: String,
+        name: String,
+        x: u8,
+        y: u8,
+    },
+    /// Removes a picture displayed with `ShowLmp` (Nehahra).
+    HideLmp {
+        slot: String,
+    },
+    /// Sets the skybox texture set (Nehahra).
+    SkyBox {
+        name: String,
+    },
+    /// Sets the global fog (FitzQuake).
+    Fog {
+        /// The fog density, in 255ths.
+        density: u8,
+        /// The fog color, in 255ths per component.
+        color: [u8; 3],
+        /// The time to fade to the new fog over, in hundredths of a second.
+        time: i16,
+    },
+    /// Like `SpawnBaseline`, but with 16-bit model and frame indices and an alpha (FitzQuake).
+    SpawnBaseline2 {
+        ent_id: u16,
+        model_id: u16,
+        frame_id: u16,
+        colormap: u8,
+        skin_id: u8,
+        origin: Vector3<f32>,
+        angles: Vector3<Deg<f32>>,
+        /// The entity's alpha, see [`decode_alpha`].
+        alpha: u8,
+    },
+    /// Like `SpawnStatic`, but with 16-bit model and frame indices and an alpha (FitzQuake).
+    SpawnStatic2 {
+        model_id: u16,
+        frame_id: u16,
+        colormap: u8,
+        skin_id: u8,
+        origin: Vector3<f32>,
+        angles: Vector3<Deg<f32>>,
+        /// The entity's alpha, see [`decode_alpha`].
+        alpha: u8,
+    },
+    /// Like `SpawnStaticSound`, but with a 16-bit sound index (FitzQuake).
+    SpawnStaticSound2 {
+        origin: Vector3<f32>,
+        sound_id: u16,
+        volume: u8,
+        attenuation: u8,
+    },
     FastUpdate(EntityUpdate),
 }
 
@@ -821,6 +1415,13 @@ impl ServerCmd {
             ServerCmd::CdTrack { .. } => ServerCmdCode::CdTrack,
             ServerCmd::SellScreen => ServerCmdCode::SellScreen,
             ServerCmd::Cutscene { .. } => ServerCmdCode::Cutscene,
+            ServerCmd::ShowLmp { .. } => ServerCmdCode::ShowLmp,
+            ServerCmd::HideLmp { .. } => ServerCmdCode::HideLmp,
+            ServerCmd::SkyBox { .. } => ServerCmdCode::SkyBox,
+            ServerCmd::Fog { .. } => ServerCmdCode::Fog,
+            ServerCmd::SpawnBaseline2 { .. } => ServerCmdCode::SpawnBaseline2,
+            ServerCmd::SpawnStatic2 { .. } => ServerCmdCode::SpawnStatic2,
+            ServerCmd::SpawnStaticSound2 { .. } => ServerCmdCode::SpawnStaticSound2,
             // TODO: figure out a more elegant way of doing this
             ServerCmd::FastUpdate(_) => panic!("FastUpdate has no code"),
         };
@@ -828,7 +1429,18 @@ impl ServerCmd {
         code as u8
     }
 
+    /// Reads a command encoded with the original protocol's coordinate and angle formats.
     pub fn deserialize<R>(reader: &mut R) -> Result<Option<ServerCmd>, NetError>
+    where
+        R: BufRead + ReadBytesExt,
+    {
+        ServerCmd::deserialize_with_format(reader, WireFormat::STANDARD)
+    }
+
+    pub fn deserialize_with_format<R>(
+        reader: &mut R,
+        format: WireFormat,
+    ) -> Result<Option<ServerCmd>, NetError>
     where
         R: BufRead + ReadBytesExt,
     {
@@ -839,135 +1451,8 @@ impl ServerCmd {
         };
 
         if code_num & FAST_UPDATE_FLAG != 0 {
-            let all_bits;
-            let low_bits = code_num & !FAST_UPDATE_FLAG;
-            if low_bits & UpdateFlags::MORE_BITS.bits() as u8 != 0 {
-                let high_bits = reader.read_u8()?;
-                all_bits = (high_bits as u16) << 8 | low_bits as u16;
-            } else {
-                all_bits = low_bits as u16;
-            }
-
-            let update_flags = match UpdateFlags::from_bits(all_bits) {
-                Some(u) => u,
-                None => {
-                    return Err(NetError::InvalidData(format!(
-                        "UpdateFlags: {:b}",
-                        all_bits
-                    )))
-                }
-            };
-
-            let ent_id;
-            if update_flags.contains(UpdateFlags::LONG_ENTITY) {
-                ent_id = reader.read_u16::<LittleEndian>()?;
-            } else {
-                ent_id = reader.read_u8()? as u16;
-            }
-
-            let model_id;
-            if update_flags.contains(UpdateFlags::MODEL) {
-                model_id = Some(reader.read_u8()?);
-            } else {
-                model_id = None;
-            }
-
-            let frame_id;
-            if update_flags.contains(UpdateFlags::FRAME) {
-                frame_id = Some(reader.read_u8()?);
-            } else {
-                frame_id = None;
-            }
-
-            let colormap;
-            if update_flags.contains(UpdateFlags::COLORMAP) {
-                colormap = Some(reader.read_u8()?);
-            } else {
-                colormap = None;
-            }
-
-            let skin_id;
-            if update_flags.contains(UpdateFlags::SKIN) {
-                skin_id = Some(reader.read_u8()?);
-            } else {
-                skin_id = None;
-            }
-
-            let effects;
-            if update_flags.contains(UpdateFlags::EFFECTS) {
-                let effects_bits = reader.read_u8()?;
-                effects = match EntityEffects::from_bits(effects_bits) {
-                    Some(e) => Some(e),
-                    None => {
-                        return Err(NetError::InvalidData(format!(
-                            "EntityEffects: {:b}",
-                            effects_bits
-                        )))
-                    }
-                };
-            } else {
-                effects = None;
-            }
-
-            let origin_x;
-            if update_flags.contains(UpdateFlags::ORIGIN_X) {
-                origin_x = Some(read_coord(reader)?);
-            } else {
-                origin_x = None;
-            }
-
-            let pitch;
-            if update_flags.contains(UpdateFlags::PITCH) {
-                pitch = Some(read_angle(reader)?);
-            } else {
-                pitch = None;
-            }
-
-            let origin_y;
-            if update_flags.contains(UpdateFlags::ORIGIN_Y) {
-                origin_y = Some(read_coord(reader)?);
-            } else {
-                origin_y = None;
-            }
-
-            let yaw;
-            if update_flags.contains(UpdateFlags::YAW) {
-                yaw = Some(read_angle(reader)?);
-            } else {
-                yaw = None;
-            }
-
-            let origin_z;
-            if update_flags.contains(UpdateFlags::ORIGIN_Z) {
-                origin_z = Some(read_coord(reader)?);
-            } else {
-                origin_z = None;
-            }
-
-            let roll;
-            if update_flags.contains(UpdateFlags::ROLL) {
-                roll = Some(read_angle(reader)?);
-            } else {
-                roll = None;
-            }
-
-            let no_lerp = update_flags.contains(UpdateFlags::NO_LERP);
-
-            return Ok(Some(ServerCmd::FastUpdate(EntityUpdate {
-                ent_id,
-                model_id,
-                frame_id,
-                colormap,
-                skin_id,
-                effects,
-                origin_x,
-                pitch,
-                origin_y,
-                yaw,
-                origin_z,
-                roll,
-                no_lerp,
-            })));
+            let update = EntityUpdate::read_fast_update(reader, code_num, format)?;
+            return Ok(Some(ServerCmd::FastUpdate(update)));
         }
 
         let code = match ServerCmdCode::from_u8(code_num) {
@@ -1033,14 +1518,26 @@ impl ServerCmd {
                     false => None,
                 };
 
-                let entity_channel = reader.read_i16::<LittleEndian>()?;
-                let entity_id = (entity_channel >> 3) as u16;
-                let channel = (entity_channel & 0b111) as i8;
-                let sound_id = reader.read_u8()?;
+                let entity_id;
+                let channel;
+                if flags.contains(SoundFlags::LARGE_ENTITY) {
+                    entity_id = reader.read_u16::<LittleEndian>()?;
+                    channel = reader.read_u8()? as i8;
+                } else {
+                    let entity_channel = reader.read_u16::<LittleEndian>()?;
+                    entity_id = entity_channel >> 3;
+                    channel = (entity_channel & 0b111) as i8;
+                }
+
+                let sound_id = match flags.contains(SoundFlags::LARGE_SOUND) {
+                    true => reader.read_u16::<LittleEndian>()?,
+                    false => reader.read_u8()? as u16,
+                };
+
                 let position = Vector3::new(
-                    read_coord(reader)?,
-                    read_coord(reader)?,
-                    read_coord(reader)?,
+                    read_coord(reader, format)?,
+                    read_coord(reader, format)?,
+                    read_coord(reader, format)?,
                 );
 
                 ServerCmd::Sound {
@@ -1059,28 +1556,22 @@ impl ServerCmd {
             }
 
             ServerCmdCode::Print => {
-                let text = match util::read_cstring(reader) {
-                    Ok(t) => t,
-                    Err(e) => return Err(NetError::with_msg(format!("{}", e))),
-                };
+                let text = read_cstring(reader)?;
 
                 ServerCmd::Print { text }
             }
 
             ServerCmdCode::StuffText => {
-                let text = match util::read_cstring(reader) {
-                    Ok(t) => t,
-                    Err(e) => return Err(NetError::with_msg(format!("{}", e))),
-                };
+                let text = read_cstring(reader)?;
 
                 ServerCmd::StuffText { text }
             }
 
             ServerCmdCode::SetAngle => {
                 let angles = Vector3::new(
-                    read_angle(reader)?,
-                    read_angle(reader)?,
-                    read_angle(reader)?,
+                    read_angle(reader, format)?,
+                    read_angle(reader, format)?,
+                    read_angle(reader, format)?,
                 );
 
                 ServerCmd::SetAngle { angles }
@@ -1088,6 +1579,12 @@ impl ServerCmd {
 
             ServerCmdCode::ServerInfo => {
                 let protocol_version = reader.read_i32::<LittleEndian>()?;
+                let protocol_flags = match protocol_version {
+                    PROTOCOL_RMQ => {
+                        ProtocolFlags::from_bits_truncate(reader.read_u32::<LittleEndian>()?)
+                    }
+                    _ => ProtocolFlags::empty(),
+                };
                 let max_clients = reader.read_u8()?;
                 let game_type_code = reader.read_u8()?;
                 let game_type = match GameType::from_u8(game_type_code) {
@@ -1100,11 +1597,11 @@ impl ServerCmd {
                     }
                 };
 
-                let message = util::read_cstring(reader).unwrap();
+                let message = read_cstring(reader)?;
 
                 let mut model_precache = Vec::new();
                 loop {
-                    let model_name = util::read_cstring(reader).unwrap();
+                    let model_name = read_cstring(reader)?;
                     if model_name.is_empty() {
                         break;
                     }
@@ -1113,7 +1610,7 @@ impl ServerCmd {
 
                 let mut sound_precache = Vec::new();
                 loop {
-                    let sound_name = util::read_cstring(reader).unwrap();
+                    let sound_name = read_cstring(reader)?;
                     if sound_name.is_empty() {
                         break;
                     }
@@ -1122,6 +1619,7 @@ impl ServerCmd {
 
                 ServerCmd::ServerInfo {
                     protocol_version,
+                    protocol_flags,
                     max_clients,
                     game_type,
                     message,
@@ -1132,13 +1630,13 @@ impl ServerCmd {
 
             ServerCmdCode::LightStyle => {
                 let id = reader.read_u8()?;
-                let value = util::read_cstring(reader).unwrap();
+                let value = read_cstring(reader)?;
                 ServerCmd::LightStyle { id, value }
             }
 
             ServerCmdCode::UpdateName => {
                 let player_id = reader.read_u8()?;
-                let new_name = util::read_cstring(reader).unwrap();
+                let new_name = read_cstring(reader)?;
                 ServerCmd::UpdateName {
                     player_id,
                     new_name,
@@ -1156,7 +1654,17 @@ impl ServerCmd {
             }
 
             ServerCmdCode::PlayerData => {
-                let flags_bits = reader.read_u16::<LittleEndian>()?;
+                let mut flags_bits = reader.read_u16::<LittleEndian>()? as u32;
+                if format.fitz {
+                    if flags_bits & ClientUpdateFlags::EXTEND1.bits() != 0 {
+                        flags_bits |= (reader.read_u8()? as u32) << 16;
+                    }
+
+                    if flags_bits & ClientUpdateFlags::EXTEND2.bits() != 0 {
+                        flags_bits |= (reader.read_u8()? as u32) << 24;
+                    }
+                }
+
                 let flags = match ClientUpdateFlags::from_bits(flags_bits) {
                     Some(f) => f,
                     None => {
@@ -1221,29 +1729,60 @@ impl ServerCmd {
                 let on_ground = flags.contains(ClientUpdateFlags::ON_GROUND);
                 let in_water = flags.contains(ClientUpdateFlags::IN_WATER);
 
-                let weapon_frame = match flags.contains(ClientUpdateFlags::WEAPON_FRAME) {
-                    true => Some(reader.read_u8()?),
+                let mut weapon_frame = match flags.contains(ClientUpdateFlags::WEAPON_FRAME) {
+                    true => Some(reader.read_u8()? as u16),
                     false => None,
                 };
 
-                let armor = match flags.contains(ClientUpdateFlags::ARMOR) {
-                    true => Some(reader.read_u8()?),
+                let mut armor = match flags.contains(ClientUpdateFlags::ARMOR) {
+                    true => Some(reader.read_u8()? as u16),
                     false => None,
                 };
 
-                let weapon = match flags.contains(ClientUpdateFlags::WEAPON) {
-                    true => Some(reader.read_u8()?),
+                let mut weapon = match flags.contains(ClientUpdateFlags::WEAPON) {
+                    true => Some(reader.read_u8()? as u16),
                     false => None,
                 };
 
                 let health = reader.read_i16::<LittleEndian>()?;
-                let ammo = reader.read_u8()?;
-                let ammo_shells = reader.read_u8()?;
-                let ammo_nails = reader.read_u8()?;
-                let ammo_rockets = reader.read_u8()?;
-                let ammo_cells = reader.read_u8()?;
+                let mut ammo = reader.read_u8()? as u16;
+                let mut ammo_shells = reader.read_u8()? as u16;
+                let mut ammo_nails = reader.read_u8()? as u16;
+                let mut ammo_rockets = reader.read_u8()? as u16;
+                let mut ammo_cells = reader.read_u8()? as u16;
                 let active_weapon = reader.read_u8()?;
 
+                // FitzQuake sends the high bytes of the larger values after everything else
+                if flags.contains(ClientUpdateFlags::WEAPON2) {
+                    let high = reader.read_u8()? as u16;
+                    weapon = weapon.map(|w| high << 8 | w);
+                }
+                if flags.contains(ClientUpdateFlags::ARMOR2) {
+                    let high = reader.read_u8()? as u16;
+                    armor = armor.map(|a| high << 8 | a);
+                }
+                let mut counts = [
+                    (ClientUpdateFlags::AMMO2, &mut ammo),
+                    (ClientUpdateFlags::SHELLS2, &mut ammo_shells),
+                    (ClientUpdateFlags::NAILS2, &mut ammo_nails),
+                    (ClientUpdateFlags::ROCKETS2, &mut ammo_rockets),
+                    (ClientUpdateFlags::CELLS2, &mut ammo_cells),
+                ];
+                for (flag, count) in counts.iter_mut() {
+                    if flags.contains(*flag) {
+                        **count |= (reader.read_u8()? as u16) << 8;
+                    }
+                }
+                if flags.contains(ClientUpdateFlags::WEAPON_FRAME2) {
+                    let high = reader.read_u8()? as u16;
+                    weapon_frame = weapon_frame.map(|f| high << 8 | f);
+                }
+
+                // the view model's alpha, which we don't support
+                if flags.contains(ClientUpdateFlags::WEAPON_ALPHA) {
+                    reader.read_u8()?;
+                }
+
                 ServerCmd::PlayerData(PlayerData {
                     view_height,
                     ideal_pitch,
@@ -1289,7 +1828,7 @@ impl ServerCmd {
             }
 
             ServerCmdCode::Particle => {
-                let origin = read_coord_vector3(reader)?;
+                let origin = read_coord_vector3(reader, format)?;
 
                 let mut direction = Vector3::zero();
                 for i in 0..3 {
@@ -1310,7 +1849,7 @@ impl ServerCmd {
             ServerCmdCode::Damage => {
                 let armor = reader.read_u8()?;
                 let blood = reader.read_u8()?;
-                let source = read_coord_vector3(reader)?;
+                let source = read_coord_vector3(reader, format)?;
 
                 ServerCmd::Damage {
                     armor,
@@ -1328,8 +1867,8 @@ impl ServerCmd {
                 let mut origin = Vector3::zero();
                 let mut angles = Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0));
                 for i in 0..3 {
-                    origin[i] = read_coord(reader)?;
-                    angles[i] = read_angle(reader)?;
+                    origin[i] = read_coord(reader, format)?;
+                    angles[i] = read_angle(reader, format)?;
                 }
 
                 ServerCmd::SpawnStatic {
@@ -1352,8 +1891,8 @@ impl ServerCmd {
                 let mut origin = Vector3::zero();
                 let mut angles = Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0));
                 for i in 0..3 {
-                    origin[i] = read_coord(reader)?;
-                    angles[i] = read_angle(reader)?;
+                    origin[i] = read_coord(reader, format)?;
+                    angles[i] = read_angle(reader, format)?;
                 }
 
                 ServerCmd::SpawnBaseline {
@@ -1368,7 +1907,7 @@ impl ServerCmd {
             }
 
             ServerCmdCode::TempEntity => {
-                let temp_entity = TempEntity::read_temp_entity(reader)?;
+                let temp_entity = TempEntity::read_temp_entity(reader, format)?;
 
                 ServerCmd::TempEntity { temp_entity }
             }
@@ -1399,10 +1938,7 @@ impl ServerCmd {
             }
 
             ServerCmdCode::CenterPrint => {
-                let text = match util::read_cstring(reader) {
-                    Ok(t) => t,
-                    Err(e) => return Err(NetError::with_msg(format!("{}", e))),
-                };
+                let text = read_cstring(reader)?;
 
                 ServerCmd::CenterPrint { text }
             }
@@ -1411,7 +1947,7 @@ impl ServerCmd {
             ServerCmdCode::FoundSecret => ServerCmd::FoundSecret,
 
             ServerCmdCode::SpawnStaticSound => {
-                let origin = read_coord_vector3(reader)?;
+                let origin = read_coord_vector3(reader, format)?;
                 let sound_id = reader.read_u8()?;
                 let volume = reader.read_u8()?;
                 let attenuation = reader.read_u8()?;
@@ -1427,10 +1963,7 @@ impl ServerCmd {
             ServerCmdCode::Intermission => ServerCmd::Intermission,
 
             ServerCmdCode::Finale => {
-                let text = match util::read_cstring(reader) {
-                    Ok(t) => t,
-                    Err(e) => return Err(NetError::with_msg(format!("{}", e))),
-                };
+                let text = read_cstring(reader)?;
 
                 ServerCmd::Finale { text }
             }
@@ -1444,22 +1977,182 @@ impl ServerCmd {
             ServerCmdCode::SellScreen => ServerCmd::SellScreen,
 
             ServerCmdCode::Cutscene => {
-                let text = match util::read_cstring(reader) {
-                    Ok(t) => t,
-                    Err(e) => return Err(NetError::with_msg(format!("{}", e))),
-                };
+                let text = read_cstring(reader)?;
 
                 ServerCmd::Cutscene { text }
             }
+
+            ServerCmdCode::ShowLmp => {
+                let slot = read_cstring(reader)?;
+                let name = read_cstring(reader)?;
+                let x = reader.read_u8()?;
+                let y = reader.read_u8()?;
+
+                ServerCmd::ShowLmp { slot, name, x, y }
+            }
+
+            ServerCmdCode::HideLmp => {
+                let slot = read_cstring(reader)?;
+                ServerCmd::HideLmp { slot }
+            }
+
+            ServerCmdCode::SkyBox => {
+                let name = read_cstring(reader)?;
+                ServerCmd::SkyBox { name }
+            }
+
+            ServerCmdCode::Fog => {
+                let density = reader.read_u8()?;
+                let mut color = [0; 3];
+                reader.read_exact(&mut color)?;
+                let time = reader.read_i16::<LittleEndian>()?;
+
+                ServerCmd::Fog {
+                    density,
+                    color,
+                    time,
+                }
+            }
+
+            ServerCmdCode::SpawnBaseline2 => {
+                let ent_id = reader.read_u16::<LittleEndian>()?;
+                let flags_bits = reader.read_u8()?;
+                let flags = match BaselineFlags::from_bits(flags_bits) {
+                    Some(f) => f,
+                    None => {
+                        return Err(NetError::InvalidData(format!(
+                            "BaselineFlags: {:b}",
+                            flags_bits
+                        )))
+                    }
+                };
+
+                let model_id = if flags.contains(BaselineFlags::LARGE_MODEL) {
+                    reader.read_u16::<LittleEndian>()?
+                } else {
+                    reader.read_u8()? as u16
+                };
+                let frame_id = if flags.contains(BaselineFlags::LARGE_FRAME) {
+                    reader.read_u16::<LittleEndian>()?
+                } else {
+                    reader.read_u8()? as u16
+                };
+                let colormap = reader.read_u8()?;
+                let skin_id = reader.read_u8()?;
+
+                let mut origin = Vector3::zero();
+                let mut angles = Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0));
+                for i in 0..3 {
+                    origin[i] = read_coord(reader, format)?;
+                    angles[i] = read_angle(reader, format)?;
+                }
+
+                let alpha = if flags.contains(BaselineFlags::ALPHA) {
+                    reader.read_u8()?
+                } else {
+                    0
+                };
+
+                ServerCmd::SpawnBaseline2 {
+                    ent_id,
+                    model_id,
+                    frame_id,
+                    colormap,
+                    skin_id,
+                    origin,
+                    angles,
+                    alpha,
+                }
+            }
+
+            ServerCmdCode::SpawnStatic2 => {
+                let flags_bits = reader.read_u8()?;
+                let flags = match BaselineFlags::from_bits(flags_bits) {
+                    Some(f) => f,
+                    None => {
+                        return Err(NetError::InvalidData(format!(
+                            "BaselineFlags: {:b}",
+                            flags_bits
+                        )))
+                    }
+                };
+
+                let model_id = if flags.contains(BaselineFlags::LARGE_MODEL) {
+                    reader.read_u16::<LittleEndian>()?
+                } else {
+                    reader.read_u8()? as u16
+                };
+                let frame_id = if flags.contains(BaselineFlags::LARGE_FRAME) {
+                    reader.read_u16::<LittleEndian>()?
+                } else {
+                    reader.read_u8()? as u16
+                };
+                let colormap = reader.read_u8()?;
+                let skin_id = reader.read_u8()?;
+
+                let mut origin = Vector3::zero();
+                let mut angles = Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0));
+                for i in 0..3 {
+                    origin[i] = read_coord(reader, format)?;
+                    angles[i] = read_angle(reader, format)?;
+                }
+
+                let alpha = if flags.contains(BaselineFlags::ALPHA) {
+                    reader.read_u8()?
+                } else {
+                    0
+                };
+
+                ServerCmd::SpawnStatic2 {
+                    model_id,
+                    frame_id,
+                    colormap,
+                    skin_id,
+                    origin,
+                    angles,
+                    alpha,
+                }
+            }
+
+            ServerCmdCode::SpawnStaticSound2 => {
+                let origin = read_coord_vector3(reader, format)?;
+                let sound_id = reader.read_u16::<LittleEndian>()?;
+                let volume = reader.read_u8()?;
+                let attenuation = reader.read_u8()?;
+
+                ServerCmd::SpawnStaticSound2 {
+                    origin,
+                    sound_id,
+                    volume,
+                    attenuation,
+                }
+            }
         };
 
         Ok(Some(cmd))
     }
 
+    /// Writes this command with the original protocol's coordinate and angle formats.
     pub fn serialize<W>(&self, writer: &mut W) -> Result<(), NetError>
     where
         W: WriteBytesExt,
     {
+        self.serialize_with_format(writer, WireFormat::STANDARD)
+    }
+
+    pub fn serialize_with_format<W>(
+        &self,
+        writer: &mut W,
+        format: WireFormat,
+    ) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        // fast updates have no code
+        if let ServerCmd::FastUpdate(ref update) = *self {
+            return update.write_fast_update(writer, format);
+        }
+
         writer.write_u8(self.code())?;
 
         match *self {
@@ -1496,6 +2189,23 @@ impl ServerCmd {
                     sound_flags |= SoundFlags::ATTENUATION;
                 }
 
+                // the entity and channel share a u16 unless the entity needs more than 13 bits
+                let large_entity = entity_id >= 1 << 13;
+                if large_entity {
+                    if !format.fitz {
+                        return Err(NetError::InvalidData(format!(
+                            "sound entity {} doesn't fit in the original protocol",
+                            entity_id
+                        )));
+                    }
+
+                    sound_flags |= SoundFlags::LARGE_ENTITY;
+                }
+
+                if split_index(sound_id, format, "sound index")?.1.is_some() {
+                    sound_flags |= SoundFlags::LARGE_SOUND;
+                }
+
                 writer.write_u8(sound_flags.bits())?;
 
                 if let Some(v) = volume {
@@ -1503,36 +2213,43 @@ impl ServerCmd {
                 }
 
                 if let Some(a) = attenuation {
-                    writer.write_u8(a as u8 * SOUND_ATTENUATION_WRITE_FACTOR)?;
+                    writer.write_u8((a * SOUND_ATTENUATION_WRITE_FACTOR as f32) as u8)?;
                 }
 
-                // TODO: document this better. The entity and channel fields are combined in Sound commands.
-                let ent_channel = (entity_id as i16) << 3 | channel as i16 & 0b111;
-                writer.write_i16::<LittleEndian>(ent_channel)?;
+                if large_entity {
+                    writer.write_u16::<LittleEndian>(entity_id)?;
+                    writer.write_u8(channel as u8)?;
+                } else {
+                    let ent_channel = entity_id << 3 | channel as u16 & 0b111;
+                    writer.write_u16::<LittleEndian>(ent_channel)?;
+                }
 
-                writer.write_u8(sound_id)?;
+                if sound_flags.contains(SoundFlags::LARGE_SOUND) {
+                    writer.write_u16::<LittleEndian>(sound_id)?;
+                } else {
+                    writer.write_u8(sound_id as u8)?;
+                }
 
                 for component in 0..3 {
-                    write_coord(writer, position[component])?;
+                    write_coord(writer, format, position[component])?;
                 }
             }
 
             ServerCmd::Time { time } => writer.write_f32::<LittleEndian>(time)?,
 
             ServerCmd::Print { ref text } => {
-                writer.write(text.as_bytes())?;
-                writer.write_u8(0)?;
+                write_cstring(writer, text)?;
             }
 
             ServerCmd::StuffText { ref text } => {
-                writer.write(text.as_bytes())?;
-                writer.write_u8(0)?;
+                write_cstring(writer, text)?;
             }
 
-            ServerCmd::SetAngle { angles } => write_angle_vector3(writer, angles)?,
+            ServerCmd::SetAngle { angles } => write_angle_vector3(writer, format, angles)?,
 
             ServerCmd::ServerInfo {
                 protocol_version,
+                protocol_flags,
                 max_clients,
                 game_type,
                 ref message,
@@ -1540,29 +2257,28 @@ impl ServerCmd {
                 ref sound_precache,
             } => {
                 writer.write_i32::<LittleEndian>(protocol_version)?;
+                if protocol_version == PROTOCOL_RMQ {
+                    writer.write_u32::<LittleEndian>(protocol_flags.bits())?;
+                }
                 writer.write_u8(max_clients)?;
                 writer.write_u8(game_type as u8)?;
 
-                writer.write(message.as_bytes())?;
-                writer.write_u8(0)?;
+                write_cstring(writer, message)?;
 
                 for model_name in model_precache.iter() {
-                    writer.write(model_name.as_bytes())?;
-                    writer.write_u8(0)?;
+                    write_cstring(writer, model_name)?;
                 }
                 writer.write_u8(0)?;
 
                 for sound_name in sound_precache.iter() {
-                    writer.write(sound_name.as_bytes())?;
-                    writer.write_u8(0)?;
+                    write_cstring(writer, sound_name)?;
                 }
                 writer.write_u8(0)?;
             }
 
             ServerCmd::LightStyle { id, ref value } => {
                 writer.write_u8(id)?;
-                writer.write(value.as_bytes())?;
-                writer.write_u8(0)?;
+                write_cstring(writer, value)?;
             }
 
             ServerCmd::UpdateName {
@@ -1570,8 +2286,7 @@ impl ServerCmd {
                 ref new_name,
             } => {
                 writer.write_u8(player_id)?;
-                writer.write(new_name.as_bytes())?;
-                writer.write_u8(0)?;
+                write_cstring(writer, new_name)?;
             }
 
             ServerCmd::UpdateFrags {
@@ -1605,6 +2320,19 @@ impl ServerCmd {
                 ammo_cells,
                 active_weapon,
             }) => {
+                let weapon_frame = weapon_frame
+                    .map(|f| split_index(f, format, "weapon frame"))
+                    .transpose()?;
+                let armor = armor.map(|a| split_index(a, format, "armor")).transpose()?;
+                let weapon = weapon
+                    .map(|w| split_index(w, format, "weapon model"))
+                    .transpose()?;
+                let ammo = split_index(ammo, format, "ammo")?;
+                let ammo_shells = split_index(ammo_shells, format, "shells")?;
+                let ammo_nails = split_index(ammo_nails, format, "nails")?;
+                let ammo_rockets = split_index(ammo_rockets, format, "rockets")?;
+                let ammo_cells = split_index(ammo_cells, format, "cells")?;
+
                 let mut flags = ClientUpdateFlags::empty();
                 if view_height.is_some() {
                     flags |= ClientUpdateFlags::VIEW_HEIGHT;
@@ -1650,8 +2378,37 @@ impl ServerCmd {
                     flags |= ClientUpdateFlags::WEAPON;
                 }
 
+                // the high bytes, in the order they're written
+                let high_bytes = [
+                    (
+                        ClientUpdateFlags::WEAPON2,
+                        weapon.and_then(|(_, high)| high),
+                    ),
+                    (ClientUpdateFlags::ARMOR2, armor.and_then(|(_, high)| high)),
+                    (ClientUpdateFlags::AMMO2, ammo.1),
+                    (ClientUpdateFlags::SHELLS2, ammo_shells.1),
+                    (ClientUpdateFlags::NAILS2, ammo_nails.1),
+                    (ClientUpdateFlags::ROCKETS2, ammo_rockets.1),
+                    (ClientUpdateFlags::CELLS2, ammo_cells.1),
+                    (
+                        ClientUpdateFlags::WEAPON_FRAME2,
+                        weapon_frame.and_then(|(_, high)| high),
+                    ),
+                ];
+                for &(flag, high) in high_bytes.iter() {
+                    flags.set(flag, high.is_some());
+                }
+                flags.set(ClientUpdateFlags::EXTEND1, flags.bits() & 0xFF_0000 != 0);
+                flags.set(ClientUpdateFlags::EXTEND2, flags.bits() & 0xFF00_0000 != 0);
+
                 // write flags
-                writer.write_u16::<LittleEndian>(flags.bits())?;
+                writer.write_u16::<LittleEndian>(flags.bits() as u16)?;
+                if flags.contains(ClientUpdateFlags::EXTEND1) {
+                    writer.write_u8((flags.bits() >> 16) as u8)?;
+                }
+                if flags.contains(ClientUpdateFlags::EXTEND2) {
+                    writer.write_u8((flags.bits() >> 24) as u8)?;
+                }
 
                 if let Some(vh) = view_height {
                     writer.write_u8(vh as i32 as u8)?;
@@ -1678,22 +2435,28 @@ impl ServerCmd {
                     writer.write_u8((vz * VELOCITY_WRITE_FACTOR) as i32 as u8)?;
                 }
                 writer.write_u32::<LittleEndian>(items.bits())?;
-                if let Some(wf) = weapon_frame {
+                if let Some((wf, _)) = weapon_frame {
                     writer.write_u8(wf)?;
                 }
-                if let Some(a) = armor {
+                if let Some((a, _)) = armor {
                     writer.write_u8(a)?;
                 }
-                if let Some(w) = weapon {
+                if let Some((w, _)) = weapon {
                     writer.write_u8(w)?;
                 }
                 writer.write_i16::<LittleEndian>(health)?;
-                writer.write_u8(ammo)?;
-                writer.write_u8(ammo_shells)?;
-                writer.write_u8(ammo_nails)?;
-                writer.write_u8(ammo_rockets)?;
-                writer.write_u8(ammo_cells)?;
+                writer.write_u8(ammo.0)?;
+                writer.write_u8(ammo_shells.0)?;
+                writer.write_u8(ammo_nails.0)?;
+                writer.write_u8(ammo_rockets.0)?;
+                writer.write_u8(ammo_cells.0)?;
                 writer.write_u8(active_weapon)?;
+
+                for &(_, high) in high_bytes.iter() {
+                    if let Some(h) = high {
+                        writer.write_u8(h)?;
+                    }
+                }
             }
 
             ServerCmd::StopSound { entity_id, channel } => {
@@ -1715,7 +2478,7 @@ impl ServerCmd {
                 count,
                 color,
             } => {
-                write_coord_vector3(writer, origin)?;
+                write_coord_vector3(writer, format, origin)?;
 
                 for i in 0..3 {
                     writer.write_i8(match direction[i] * PARTICLE_DIRECTION_WRITE_FACTOR {
@@ -1736,7 +2499,7 @@ impl ServerCmd {
             } => {
                 writer.write_u8(armor)?;
                 writer.write_u8(blood)?;
-                write_coord_vector3(writer, source)?;
+                write_coord_vector3(writer, format, source)?;
             }
 
             ServerCmd::SpawnStatic {
@@ -1753,8 +2516,8 @@ impl ServerCmd {
                 writer.write_u8(skin_id)?;
 
                 for i in 0..3 {
-                    write_coord(writer, origin[i])?;
-                    write_angle(writer, angles[i])?;
+                    write_coord(writer, format, origin[i])?;
+                    write_angle(writer, format, angles[i])?;
                 }
             }
 
@@ -1774,13 +2537,13 @@ impl ServerCmd {
                 writer.write_u8(skin_id)?;
 
                 for i in 0..3 {
-                    write_coord(writer, origin[i])?;
-                    write_angle(writer, angles[i])?;
+                    write_coord(writer, format, origin[i])?;
+                    write_angle(writer, format, angles[i])?;
                 }
             }
 
             ServerCmd::TempEntity { ref temp_entity } => {
-                temp_entity.write_temp_entity(writer)?;
+                temp_entity.write_temp_entity(writer, format)?;
             }
 
             ServerCmd::SetPause { paused } => {
@@ -1795,8 +2558,7 @@ impl ServerCmd {
             }
 
             ServerCmd::CenterPrint { ref text } => {
-                writer.write(text.as_bytes())?;
-                writer.write_u8(0)?;
+                write_cstring(writer, text)?;
             }
 
             ServerCmd::KilledMonster | ServerCmd::FoundSecret => (),
@@ -1807,7 +2569,7 @@ impl ServerCmd {
                 volume,
                 attenuation,
             } => {
-                write_coord_vector3(writer, origin)?;
+                write_coord_vector3(writer, format, origin)?;
                 writer.write_u8(sound_id)?;
                 writer.write_u8(volume)?;
                 writer.write_u8(attenuation)?;
@@ -1816,8 +2578,7 @@ impl ServerCmd {
             ServerCmd::Intermission => (),
 
             ServerCmd::Finale { ref text } => {
-                writer.write(text.as_bytes())?;
-                writer.write_u8(0)?;
+                write_cstring(writer, text)?;
             }
 
             ServerCmd::CdTrack { track, loop_ } => {
@@ -1828,19 +2589,139 @@ impl ServerCmd {
             ServerCmd::SellScreen => (),
 
             ServerCmd::Cutscene { ref text } => {
-                writer.write(text.as_bytes())?;
-                writer.write_u8(0)?;
+                write_cstring(writer, text)?;
+            }
+
+            ServerCmd::ShowLmp {
+                ref slot,
+                ref name,
+                x,
+                y,
+            } => {
+                write_cstring(writer, slot)?;
+                write_cstring(writer, name)?;
+                writer.write_u8(x)?;
+                writer.write_u8(y)?;
+            }
+
+            ServerCmd::HideLmp { ref slot } => {
+                write_cstring(writer, slot)?;
+            }
+
+            ServerCmd::SkyBox { ref name } => {
+                write_cstring(writer, name)?;
+            }
+
+            ServerCmd::Fog {
+                density,
+                color,
+                time,
+            } => {
+                writer.write_u8(density)?;
+                writer.write_all(&color)?;
+                writer.write_i16::<LittleEndian>(time)?;
+            }
+
+            ServerCmd::SpawnBaseline2 {
+                ent_id,
+                model_id,
+                frame_id,
+                colormap,
+                skin_id,
+                origin,
+                angles,
+                alpha,
+            } => {
+                let mut flags = BaselineFlags::empty();
+                flags.set(BaselineFlags::LARGE_MODEL, model_id > u8::MAX as u16);
+                flags.set(BaselineFlags::LARGE_FRAME, frame_id > u8::MAX as u16);
+                flags.set(BaselineFlags::ALPHA, alpha != 0);
+
+                writer.write_u16::<LittleEndian>(ent_id)?;
+                writer.write_u8(flags.bits())?;
+
+                if flags.contains(BaselineFlags::LARGE_MODEL) {
+                    writer.write_u16::<LittleEndian>(model_id)?;
+                } else {
+                    writer.write_u8(model_id as u8)?;
+                }
+                if flags.contains(BaselineFlags::LARGE_FRAME) {
+                    writer.write_u16::<LittleEndian>(frame_id)?;
+                } else {
+                    writer.write_u8(frame_id as u8)?;
+                }
+                writer.write_u8(colormap)?;
+                writer.write_u8(skin_id)?;
+
+                for i in 0..3 {
+                    write_coord(writer, format, origin[i])?;
+                    write_angle(writer, format, angles[i])?;
+                }
+
+                if flags.contains(BaselineFlags::ALPHA) {
+                    writer.write_u8(alpha)?;
+                }
+            }
+
+            ServerCmd::SpawnStatic2 {
+                model_id,
+                frame_id,
+                colormap,
+                skin_id,
+                origin,
+                angles,
+                alpha,
+            } => {
+                let mut flags = BaselineFlags::empty();
+                flags.set(BaselineFlags::LARGE_MODEL, model_id > u8::MAX as u16);
+                flags.set(BaselineFlags::LARGE_FRAME, frame_id > u8::MAX as u16);
+                flags.set(BaselineFlags::ALPHA, alpha != 0);
+
+                writer.write_u8(flags.bits())?;
+
+                if flags.contains(BaselineFlags::LARGE_MODEL) {
+                    writer.write_u16::<LittleEndian>(model_id)?;
+                } else {
+                    writer.write_u8(model_id as u8)?;
+                }
+                if flags.contains(BaselineFlags::LARGE_FRAME) {
+                    writer.write_u16::<LittleEndian>(frame_id)?;
+                } else {
+                    writer.write_u8(frame_id as u8)?;
+                }
+                writer.write_u8(colormap)?;
+                writer.write_u8(skin_id)?;
+
+                for i in 0..3 {
+                    write_coord(writer, format, origin[i])?;
+                    write_angle(writer, format, angles[i])?;
+                }
+
+                if flags.contains(BaselineFlags::ALPHA) {
+                    writer.write_u8(alpha)?;
+                }
             }
 
-            // TODO
-            ServerCmd::FastUpdate(_) => unimplemented!(),
+            ServerCmd::SpawnStaticSound2 {
+                origin,
+                sound_id,
+                volume,
+                attenuation,
+            } => {
+                write_coord_vector3(writer, format, origin)?;
+                writer.write_u16::<LittleEndian>(sound_id)?;
+                writer.write_u8(volume)?;
+                writer.write_u8(attenuation)?;
+            }
+
+            ServerCmd::FastUpdate(_) => unreachable!(),
         }
 
         Ok(())
     }
 }
 
-#[derive(FromPrimitive)]
+#[derive(Clone, Copy, Debug, FromPrimitive)]
 pub enum ClientCmdCode {
     Bad = 0,
     NoOp = 1,
@@ -1879,7 +2760,18 @@ impl ClientCmd {
         }
     }
 
+    /// Reads a command encoded with the original protocol's angle format.
     pub fn deserialize<R>(reader: &mut R) -> Result<ClientCmd, NetError>
+    where
+        R: ReadBytesExt + BufRead,
+    {
+        ClientCmd::deserialize_with_format(reader, WireFormat::STANDARD)
+    }
+
+    pub fn deserialize_with_format<R>(
+        reader: &mut R,
+        format: WireFormat,
+    ) -> Result<ClientCmd, NetError>
     where
         R: ReadBytesExt + BufRead,
     {
@@ -1901,9 +2793,9 @@ impl ClientCmd {
             ClientCmdCode::Move => {
                 let send_time = engine::duration_from_f32(reader.read_f32::<LittleEndian>()?);
                 let angles = Vector3::new(
-                    read_angle(reader)?,
-                    read_angle(reader)?,
-                    read_angle(reader)?,
+                    read_angle(reader, format.move_format())?,
+                    read_angle(reader, format.move_format())?,
+                    read_angle(reader, format.move_format())?,
                 );
                 let fwd_move = reader.read_i16::<LittleEndian>()?;
                 let side_move = reader.read_i16::<LittleEndian>()?;
@@ -1930,7 +2822,7 @@ impl ClientCmd {
                 }
             }
             ClientCmdCode::StringCmd => {
-                let cmd = util::read_cstring(reader).unwrap();
+                let cmd = read_cstring(reader)?;
                 ClientCmd::StringCmd { cmd }
             }
         };
@@ -1938,7 +2830,19 @@ impl ClientCmd {
         Ok(cmd)
     }
 
+    /// Writes this command with the original protocol's angle format.
     pub fn serialize<W>(&self, writer: &mut W) -> Result<(), NetError>
+    where
+        W: WriteBytesExt,
+    {
+        self.serialize_with_format(writer, WireFormat::STANDARD)
+    }
+
+    pub fn serialize_with_format<W>(
+        &self,
+        writer: &mut W,
+        format: WireFormat,
+    ) -> Result<(), NetError>
     where
         W: WriteBytesExt,
     {
@@ -1958,7 +2862,7 @@ impl ClientCmd {
                 impulse,
             } => {
                 writer.write_f32::<LittleEndian>(engine::duration_to_f32(send_time))?;
-                write_angle_vector3(writer, angles)?;
+                write_angle_vector3(writer, format.move_format(), angles)?;
                 writer.write_i16::<LittleEndian>(fwd_move)?;
                 writer.write_i16::<LittleEndian>(side_move)?;
                 writer.write_i16::<LittleEndian>(up_move)?;
@@ -1966,8 +2870,7 @@ impl ClientCmd {
                 writer.write_u8(impulse)?;
             }
             ClientCmd::StringCmd { ref cmd } => {
-                writer.write(cmd.as_bytes())?;
-                writer.write_u8(0)?;
+                write_cstring(writer, cmd)?;
             }
         }
 
@@ -2311,75 +3214,131 @@ impl QSocket {
     }
 }
 
-fn read_coord<R>(reader: &mut R) -> Result<f32, NetError>
+fn read_cstring<R>(reader: &mut R) -> Result<String, NetError>
+where
+    R: BufRead,
+{
+    util::read_cstring(reader).map_err(|e| NetError::with_msg(format!("{}", e)))
+}
+
+fn write_cstring<W>(writer: &mut W, s: &str) -> Result<(), NetError>
+where
+    W: Write,
+{
+    writer.write_all(s.as_bytes())?;
+    writer.write_all(&[0])?;
+    Ok(())
+}
+
+fn read_coord<R>(reader: &mut R, format: WireFormat) -> Result<f32, NetError>
 where
     R: BufRead + ReadBytesExt,
 {
-    Ok(reader.read_i16::<LittleEndian>()? as f32 / 8.0)
+    Ok(match format.coord {
+        CoordFormat::Fixed => reader.read_i16::<LittleEndian>()? as f32 / 8.0,
+        CoordFormat::Fixed24 => {
+            let int = reader.read_i16::<LittleEndian>()? as f32;
+            let frac = reader.read_u8()? as f32 / 255.0;
+            int + frac
+        }
+        CoordFormat::Int32 => reader.read_i32::<LittleEndian>()? as f32 / 16.0,
+        CoordFormat::Float => reader.read_f32::<LittleEndian>()?,
+    })
 }
 
-fn read_coord_vector3<R>(reader: &mut R) -> Result<Vector3<f32>, NetError>
+fn read_coord_vector3<R>(reader: &mut R, format: WireFormat) -> Result<Vector3<f32>, NetError>
 where
     R: BufRead + ReadBytesExt,
 {
     Ok(Vector3::new(
-        read_coord(reader)?,
-        read_coord(reader)?,
-        read_coord(reader)?,
+        read_coord(reader, format)?,
+        read_coord(reader, format)?,
+        read_coord(reader, format)?,
     ))
 }
 
-fn write_coord<W>(writer: &mut W, coord: f32) -> Result<(), NetError>
+fn write_coord<W>(writer: &mut W, format: WireFormat, coord: f32) -> Result<(), NetError>
 where
     W: WriteBytesExt,
 {
-    writer.write_i16::<LittleEndian>((coord * 8.0) as i16)?;
+    match format.coord {
+        CoordFormat::Fixed => writer.write_i16::<LittleEndian>((coord * 8.0) as i16)?,
+        CoordFormat::Fixed24 => {
+            let int = coord.floor();
+            writer.write_i16::<LittleEndian>(int as i16)?;
+            writer.write_u8(((coord - int) * 255.0).round() as u8)?;
+        }
+        CoordFormat::Int32 => writer.write_i32::<LittleEndian>((coord * 16.0).round() as i32)?,
+        CoordFormat::Float => writer.write_f32::<LittleEndian>(coord)?,
+    }
+
     Ok(())
 }
 
-fn write_coord_vector3<W>(writer: &mut W, coords: Vector3<f32>) -> Result<(), NetError>
+fn write_coord_vector3<W>(
+    writer: &mut W,
+    format: WireFormat,
+    coords: Vector3<f32>,
+) -> Result<(), NetError>
 where
     W: WriteBytesExt,
 {
     for coord in &coords[..] {
-        write_coord(writer, *coord)?;
+        write_coord(writer, format, *coord)?;
     }
 
     Ok(())
 }
 
-fn read_angle<R>(reader: &mut R) -> Result<Deg<f32>, NetError>
+fn read_angle<R>(reader: &mut R, format: WireFormat) -> Result<Deg<f32>, NetError>
 where
     R: BufRead + ReadBytesExt,
 {
-    Ok(Deg(reader.read_i8()? as f32 * (360.0 / 256.0)))
+    Ok(Deg(match format.angle {
+        AngleFormat::Byte => reader.read_i8()? as f32 * (360.0 / 256.0),
+        AngleFormat::Short => reader.read_i16::<LittleEndian>()? as f32 * (360.0 / 65536.0),
+        AngleFormat::Float => reader.read_f32::<LittleEndian>()?,
+    }))
 }
 
-fn read_angle_vector3<R>(reader: &mut R) -> Result<Vector3<Deg<f32>>, NetError>
+fn read_angle_vector3<R>(reader: &mut R, format: WireFormat) -> Result<Vector3<Deg<f32>>, NetError>
 where
     R: BufRead + ReadBytesExt,
 {
     Ok(Vector3::new(
-        read_angle(reader)?,
-        read_angle(reader)?,
-        read_angle(reader)?,
+        read_angle(reader, format)?,
+        read_angle(reader, format)?,
+        read_angle(reader, format)?,
     ))
 }
 
-fn write_angle<W>(writer: &mut W, angle: Deg<f32>) -> Result<(), NetError>
+fn write_angle<W>(writer: &mut W, format: WireFormat, angle: Deg<f32>) -> Result<(), NetError>
 where
     W: WriteBytesExt,
 {
-    writer.write_u8(((angle.0 as i32 * 256 / 360) & 0xFF) as u8)?;
+    match format.angle {
+        AngleFormat::Byte => {
+            writer.write_u8(((angle.0 * 256.0 / 360.0).round() as i32 & 0xFF) as u8)?
+        }
+        AngleFormat::Short => writer.write_u16::<LittleEndian>(
+            ((angle.0 * 65536.0 / 360.0).round() as i32 & 0xFFFF) as u16,
+        )?,
+        AngleFormat::Float => writer.write_f32::<LittleEndian>(angle.0)?,
+    }
+
     Ok(())
 }
 
-fn write_angle_vector3<W>(writer: &mut W, angles: Vector3<Deg<f32>>) -> Result<(), NetError>
+fn write_angle_vector3<W>(
+    writer: &mut W,
+    format: WireFormat,
+    angles: Vector3<Deg<f32>>,
+) -> Result<(), NetError>
 where
     W: WriteBytesExt,
 {
     for angle in &angles[..] {
-        write_angle(writer, *angle)?;
+        write_angle(writer, format, *angle)?;
     }
 
     Ok(())
@@ -2391,6 +3350,479 @@ mod test {
 
     use std::io::BufReader;
 
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    // the number of random commands of each kind to round-trip per wire format
+    const ROUND_TRIP_COUNT: usize = 64;
+
+    const WIRE_FORMATS: &[WireFormat] = &[
+        WireFormat::STANDARD,
+        WireFormat {
+            coord: CoordFormat::Fixed,
+            angle: AngleFormat::Byte,
+            fitz: true,
+        },
+        WireFormat {
+            coord: CoordFormat::Fixed24,
+            angle: AngleFormat::Short,
+            fitz: true,
+        },
+        WireFormat {
+            coord: CoordFormat::Int32,
+            angle: AngleFormat::Short,
+            fitz: true,
+        },
+        WireFormat {
+            coord: CoordFormat::Float,
+            angle: AngleFormat::Float,
+            fitz: false,
+        },
+        WireFormat {
+            coord: CoordFormat::Float,
+            angle: AngleFormat::Float,
+            fitz: true,
+        },
+    ];
+
+    // The generators below only produce values the wire format can represent exactly, computed
+    // the same way the readers compute them, so a command must survive a round trip unchanged.
+
+    fn arbitrary_coord(rng: &mut SmallRng, format: WireFormat) -> f32 {
+        match format.coord {
+            CoordFormat::Fixed => rng.gen::<i16>() as f32 / 8.0,
+            CoordFormat::Fixed24 => {
+                rng.gen_range(-4096i16, 4096) as f32 + rng.gen_range(0u8, 255) as f32 / 255.0
+            }
+            CoordFormat::Int32 => rng.gen_range(-65536 * 16, 65536 * 16) as f32 / 16.0,
+            CoordFormat::Float => rng.gen_range(-65536.0, 65536.0),
+        }
+    }
+
+    fn arbitrary_coords(rng: &mut SmallRng, format: WireFormat) -> Vector3<f32> {
+        Vector3::new(
+            arbitrary_coord(rng, format),
+            arbitrary_coord(rng, format),
+            arbitrary_coord(rng, format),
+        )
+    }
+
+    fn arbitrary_angle(rng: &mut SmallRng, format: WireFormat) -> Deg<f32> {
+        Deg(match format.angle {
+            AngleFormat::Byte => rng.gen::<i8>() as f32 * (360.0 / 256.0),
+            AngleFormat::Short => rng.gen::<i16>() as f32 * (360.0 / 65536.0),
+            AngleFormat::Float => rng.gen_range(-360.0, 360.0),
+        })
+    }
+
+    fn arbitrary_angles(rng: &mut SmallRng, format: WireFormat) -> Vector3<Deg<f32>> {
+        Vector3::new(
+            arbitrary_angle(rng, format),
+            arbitrary_angle(rng, format),
+            arbitrary_angle(rng, format),
+        )
+    }
+
+    fn arbitrary_index(rng: &mut SmallRng, format: WireFormat) -> u16 {
+        match format.fitz {
+            true => rng.gen(),
+            false => rng.gen::<u8>() as u16,
+        }
+    }
+
+    fn arbitrary_string(rng: &mut SmallRng, min_len: usize) -> String {
+        let len = rng.gen_range(min_len, 32);
+        (0..len)
+            .map(|_| rng.gen_range(b' ', b'~') as char)
+            .collect()
+    }
+
+    fn arbitrary_option<T, F>(rng: &mut SmallRng, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut SmallRng) -> T,
+    {
+        if rng.gen() {
+            Some(f(rng))
+        } else {
+            None
+        }
+    }
+
+    fn arbitrary_temp_entity(rng: &mut SmallRng, format: WireFormat) -> TempEntity {
+        let code = loop {
+            if let Some(c) = TempEntityCode::from_u8(rng.gen()) {
+                break c;
+            }
+        };
+
+        use TempEntityCode as Code;
+        let point_kind = match code {
+            Code::Spike => PointEntityKind::Spike,
+            Code::SuperSpike => PointEntityKind::SuperSpike,
+            Code::Gunshot => PointEntityKind::Gunshot,
+            Code::Explosion => PointEntityKind::Explosion,
+            Code::TarExplosion => PointEntityKind::TarExplosion,
+            Code::WizSpike => PointEntityKind::WizSpike,
+            Code::KnightSpike => PointEntityKind::KnightSpike,
+            Code::LavaSplash => PointEntityKind::LavaSplash,
+            Code::Teleport => PointEntityKind::Teleport,
+            Code::ColorExplosion => PointEntityKind::ColorExplosion {
+                color_start: rng.gen(),
+                color_len: rng.gen(),
+            },
+            Code::Lightning1 | Code::Lightning2 | Code::Lightning3 | Code::Grapple => {
+                let kind = match code {
+                    Code::Lightning1 => BeamEntityKind::Lightning { model_id: 1 },
+                    Code::Lightning2 => BeamEntityKind::Lightning { model_id: 2 },
+                    Code::Lightning3 => BeamEntityKind::Lightning { model_id: 3 },
+                    _ => BeamEntityKind::Grapple,
+                };
+
+                return TempEntity::Beam {
+                    kind,
+                    entity_id: rng.gen(),
+                    start: arbitrary_coords(rng, format),
+                    end: arbitrary_coords(rng, format),
+                };
+            }
+        };
+
+        TempEntity::Point {
+            kind: point_kind,
+            origin: arbitrary_coords(rng, format),
+        }
+    }
+
+    fn arbitrary_entity_update(rng: &mut SmallRng, format: WireFormat) -> EntityUpdate {
+        EntityUpdate {
+            ent_id: rng.gen(),
+            model_id: arbitrary_option(rng, |r| arbitrary_index(r, format)),
+            frame_id: arbitrary_option(rng, |r| arbitrary_index(r, format)),
+            colormap: arbitrary_option(rng, |r| r.gen()),
+            skin_id: arbitrary_option(rng, |r| r.gen()),
+            effects: arbitrary_option(rng, |r| EntityEffects::from_bits_truncate(r.gen())),
+            origin_x: arbitrary_option(rng, |r| arbitrary_coord(r, format)),
+            pitch: arbitrary_option(rng, |r| arbitrary_angle(r, format)),
+            origin_y: arbitrary_option(rng, |r| arbitrary_coord(r, format)),
+            yaw: arbitrary_option(rng, |r| arbitrary_angle(r, format)),
+            origin_z: arbitrary_option(rng, |r| arbitrary_coord(r, format)),
+            roll: arbitrary_option(rng, |r| arbitrary_angle(r, format)),
+            no_lerp: rng.gen(),
+            alpha: arbitrary_option(rng, |r| match format.fitz {
+                true => decode_alpha(r.gen()),
+                // Nehahra alphas are clamped to (0, 1]
+                false => 1.0 - r.gen::<f32>(),
+            }),
+        }
+    }
+
+    fn arbitrary_player_data(rng: &mut SmallRng, format: WireFormat) -> PlayerData {
+        let byte_angle = |r: &mut SmallRng| Deg(r.gen::<i8>() as f32);
+        let velocity = |r: &mut SmallRng| r.gen::<i8>() as f32 * VELOCITY_READ_FACTOR;
+
+        PlayerData {
+            view_height: arbitrary_option(rng, |r| r.gen::<i8>() as f32),
+            ideal_pitch: arbitrary_option(rng, byte_angle),
+            punch_pitch: arbitrary_option(rng, byte_angle),
+            velocity_x: arbitrary_option(rng, velocity),
+            punch_yaw: arbitrary_option(rng, byte_angle),
+            velocity_y: arbitrary_option(rng, velocity),
+            punch_roll: arbitrary_option(rng, byte_angle),
+            velocity_z: arbitrary_option(rng, velocity),
+            items: ItemFlags::from_bits_truncate(rng.gen()),
+            on_ground: rng.gen(),
+            in_water: rng.gen(),
+            weapon_frame: arbitrary_option(rng, |r| arbitrary_index(r, format)),
+            armor: arbitrary_option(rng, |r| arbitrary_index(r, format)),
+            weapon: arbitrary_option(rng, |r| arbitrary_index(r, format)),
+            health: rng.gen(),
+            ammo: arbitrary_index(rng, format),
+            ammo_shells: arbitrary_index(rng, format),
+            ammo_nails: arbitrary_index(rng, format),
+            ammo_rockets: arbitrary_index(rng, format),
+            ammo_cells: arbitrary_index(rng, format),
+            active_weapon: rng.gen(),
+        }
+    }
+
+    // Every command code is matched here, so adding a command without a generator is a compile
+    // error.
+    fn arbitrary_server_cmd(
+        rng: &mut SmallRng,
+        code: ServerCmdCode,
+        format: WireFormat,
+    ) -> ServerCmd {
+        match code {
+            ServerCmdCode::Bad => ServerCmd::Bad,
+            ServerCmdCode::NoOp => ServerCmd::NoOp,
+            ServerCmdCode::Disconnect => ServerCmd::Disconnect,
+            ServerCmdCode::UpdateStat => ServerCmd::UpdateStat {
+                stat: ClientStat::from_u8(rng.gen_range(0, 15)).unwrap(),
+                value: rng.gen(),
+            },
+            ServerCmdCode::Version => ServerCmd::Version { version: rng.gen() },
+            ServerCmdCode::SetView => ServerCmd::SetView { ent_id: rng.gen() },
+            ServerCmdCode::Sound => ServerCmd::Sound {
+                volume: arbitrary_option(rng, |r| r.gen()),
+                attenuation: arbitrary_option(rng, |r| {
+                    r.gen::<u8>() as f32 * SOUND_ATTENUATION_READ_FACTOR
+                }),
+                // the entity id and channel share a u16 unless FitzQuake sends them separately
+                entity_id: match format.fitz {
+                    true => rng.gen(),
+                    false => rng.gen_range(0, 8192),
+                },
+                channel: rng.gen_range(0, 8),
+                sound_id: arbitrary_index(rng, format),
+                position: arbitrary_coords(rng, format),
+            },
+            ServerCmdCode::Time => ServerCmd::Time { time: rng.gen() },
+            ServerCmdCode::Print => ServerCmd::Print {
+                text: arbitrary_string(rng, 0),
+            },
+            ServerCmdCode::StuffText => ServerCmd::StuffText {
+                text: arbitrary_string(rng, 0),
+            },
+            ServerCmdCode::SetAngle => ServerCmd::SetAngle {
+                angles: arbitrary_angles(rng, format),
+            },
+            ServerCmdCode::ServerInfo => {
+                let rmq = rng.gen();
+                ServerCmd::ServerInfo {
+                    protocol_version: if rmq { PROTOCOL_RMQ } else { rng.gen() },
+                    protocol_flags: if rmq {
+                        ProtocolFlags::from_bits_truncate(rng.gen())
+                    } else {
+                        ProtocolFlags::empty()
+                    },
+                    max_clients: rng.gen(),
+                    game_type: GameType::from_u8(rng.gen_range(0, 2)).unwrap(),
+                    message: arbitrary_string(rng, 0),
+                    // the precache lists are terminated by an empty name
+                    model_precache: (0..rng.gen_range(0, 8))
+                        .map(|_| arbitrary_string(rng, 1))
+                        .collect(),
+                    sound_precache: (0..rng.gen_range(0, 8))
+                        .map(|_| arbitrary_string(rng, 1))
+                        .collect(),
+                }
+            }
+            ServerCmdCode::LightStyle => ServerCmd::LightStyle {
+                id: rng.gen(),
+                value: arbitrary_string(rng, 0),
+            },
+            ServerCmdCode::UpdateName => ServerCmd::UpdateName {
+                player_id: rng.gen(),
+                new_name: arbitrary_string(rng, 0),
+            },
+            ServerCmdCode::UpdateFrags => ServerCmd::UpdateFrags {
+                player_id: rng.gen(),
+                new_frags: rng.gen(),
+            },
+            ServerCmdCode::PlayerData => ServerCmd::PlayerData(arbitrary_player_data(rng, format)),
+            ServerCmdCode::StopSound => ServerCmd::StopSound {
+                entity_id: rng.gen_range(0, 8192),
+                channel: rng.gen_range(0, 8),
+            },
+            ServerCmdCode::UpdateColors => ServerCmd::UpdateColors {
+                player_id: rng.gen(),
+                new_colors: PlayerColor::from_bits(rng.gen()),
+            },
+            ServerCmdCode::Particle => ServerCmd::Particle {
+                origin: arbitrary_coords(rng, format),
+                direction: Vector3::new(
+                    rng.gen::<i8>() as f32 * PARTICLE_DIRECTION_READ_FACTOR,
+                    rng.gen::<i8>() as f32 * PARTICLE_DIRECTION_READ_FACTOR,
+                    rng.gen::<i8>() as f32 * PARTICLE_DIRECTION_READ_FACTOR,
+                ),
+                count: rng.gen(),
+                color: rng.gen(),
+            },
+            ServerCmdCode::Damage => ServerCmd::Damage {
+                armor: rng.gen(),
+                blood: rng.gen(),
+                source: arbitrary_coords(rng, format),
+            },
+            ServerCmdCode::SpawnStatic => ServerCmd::SpawnStatic {
+                model_id: rng.gen(),
+                frame_id: rng.gen(),
+                colormap: rng.gen(),
+                skin_id: rng.gen(),
+                origin: arbitrary_coords(rng, format),
+                angles: arbitrary_angles(rng, format),
+            },
+            ServerCmdCode::SpawnBaseline => ServerCmd::SpawnBaseline {
+                ent_id: rng.gen(),
+                model_id: rng.gen(),
+                frame_id: rng.gen(),
+                colormap: rng.gen(),
+                skin_id: rng.gen(),
+                origin: arbitrary_coords(rng, format),
+                angles: arbitrary_angles(rng, format),
+            },
+            ServerCmdCode::TempEntity => ServerCmd::TempEntity {
+                temp_entity: arbitrary_temp_entity(rng, format),
+            },
+            ServerCmdCode::SetPause => ServerCmd::SetPause { paused: rng.gen() },
+            ServerCmdCode::SignOnStage => ServerCmd::SignOnStage {
+                stage: SignOnStage::from_u8(rng.gen_range(0, 5)).unwrap(),
+            },
+            ServerCmdCode::CenterPrint => ServerCmd::CenterPrint {
+                text: arbitrary_string(rng, 0),
+            },
+            ServerCmdCode::KilledMonster => ServerCmd::KilledMonster,
+            ServerCmdCode::FoundSecret => ServerCmd::FoundSecret,
+            ServerCmdCode::SpawnStaticSound => ServerCmd::SpawnStaticSound {
+                origin: arbitrary_coords(rng, format),
+                sound_id: rng.gen(),
+                volume: rng.gen(),
+                attenuation: rng.gen(),
+            },
+            ServerCmdCode::Intermission => ServerCmd::Intermission,
+            ServerCmdCode::Finale => ServerCmd::Finale {
+                text: arbitrary_string(rng, 0),
+            },
+            ServerCmdCode::CdTrack => ServerCmd::CdTrack {
+                track: rng.gen(),
+                loop_: rng.gen(),
+            },
+            ServerCmdCode::SellScreen => ServerCmd::SellScreen,
+            ServerCmdCode::Cutscene => ServerCmd::Cutscene {
+                text: arbitrary_string(rng, 0),
+            },
+            ServerCmdCode::ShowLmp => ServerCmd::ShowLmp {
+                slot: arbitrary_string(rng, 0),
+                name: arbitrary_string(rng, 0),
+                x: rng.gen(),
+                y: rng.gen(),
+            },
+            ServerCmdCode::HideLmp => ServerCmd::HideLmp {
+                slot: arbitrary_string(rng, 0),
+            },
+            ServerCmdCode::SkyBox => ServerCmd::SkyBox {
+                name: arbitrary_string(rng, 0),
+            },
+            ServerCmdCode::Fog => ServerCmd::Fog {
+                density: rng.gen(),
+                color: rng.gen(),
+                time: rng.gen(),
+            },
+            ServerCmdCode::SpawnBaseline2 => ServerCmd::SpawnBaseline2 {
+                ent_id: rng.gen(),
+                model_id: rng.gen(),
+                frame_id: rng.gen(),
+                colormap: rng.gen(),
+                skin_id: rng.gen(),
+                origin: arbitrary_coords(rng, format),
+                angles: arbitrary_angles(rng, format),
+                alpha: rng.gen(),
+            },
+            ServerCmdCode::SpawnStatic2 => ServerCmd::SpawnStatic2 {
+                model_id: rng.gen(),
+                frame_id: rng.gen(),
+                colormap: rng.gen(),
+                skin_id: rng.gen(),
+                origin: arbitrary_coords(rng, format),
+                angles: arbitrary_angles(rng, format),
+                alpha: rng.gen(),
+            },
+            ServerCmdCode::SpawnStaticSound2 => ServerCmd::SpawnStaticSound2 {
+                origin: arbitrary_coords(rng, format),
+                sound_id: rng.gen(),
+                volume: rng.gen(),
+                attenuation: rng.gen(),
+            },
+        }
+    }
+
+    fn arbitrary_client_cmd(
+        rng: &mut SmallRng,
+        code: ClientCmdCode,
+        format: WireFormat,
+    ) -> ClientCmd {
+        match code {
+            ClientCmdCode::Bad => ClientCmd::Bad,
+            ClientCmdCode::NoOp => ClientCmd::NoOp,
+            ClientCmdCode::Disconnect => ClientCmd::Disconnect,
+            ClientCmdCode::Move => ClientCmd::Move {
+                // multiples of 1/64 second survive the conversion to f32 seconds
+                send_time: Duration::microseconds(15625 * rng.gen_range(0, 1024)),
+                angles: arbitrary_angles(rng, format.move_format()),
+                fwd_move: rng.gen(),
+                side_move: rng.gen(),
+                up_move: rng.gen(),
+                button_flags: ButtonFlags::from_bits_truncate(rng.gen()),
+                impulse: rng.gen(),
+            },
+            ClientCmdCode::StringCmd => ClientCmd::StringCmd {
+                cmd: arbitrary_string(rng, 0),
+            },
+        }
+    }
+
+    #[test]
+    fn test_server_cmd_round_trip() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for &format in WIRE_FORMATS {
+            let codes = (0..=u8::MAX).filter_map(ServerCmdCode::from_u8);
+            for code in codes {
+                for _ in 0..ROUND_TRIP_COUNT {
+                    let src = arbitrary_server_cmd(&mut rng, code, format);
+
+                    let mut packet = Vec::new();
+                    src.serialize_with_format(&mut packet, format).unwrap();
+                    let mut reader = BufReader::new(packet.as_slice());
+                    let dst = ServerCmd::deserialize_with_format(&mut reader, format)
+                        .unwrap()
+                        .unwrap();
+
+                    assert_eq!(src, dst, "{:?}", format);
+                    assert!(reader.buffer().is_empty(), "{:?} left unread data", src);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fast_update_round_trip() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for &format in WIRE_FORMATS {
+            for _ in 0..ROUND_TRIP_COUNT {
+                let src = ServerCmd::FastUpdate(arbitrary_entity_update(&mut rng, format));
+
+                let mut packet = Vec::new();
+                src.serialize_with_format(&mut packet, format).unwrap();
+                let mut reader = BufReader::new(packet.as_slice());
+                let dst = ServerCmd::deserialize_with_format(&mut reader, format)
+                    .unwrap()
+                    .unwrap();
+
+                assert_eq!(src, dst, "{:?}", format);
+                assert!(reader.buffer().is_empty(), "{:?} left unread data", src);
+            }
+        }
+    }
+
+    #[test]
+    fn test_client_cmd_round_trip() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for &format in WIRE_FORMATS {
+            let codes = (0..=u8::MAX).filter_map(ClientCmdCode::from_u8);
+            for code in codes {
+                for _ in 0..ROUND_TRIP_COUNT {
+                    let src = arbitrary_client_cmd(&mut rng, code, format);
+
+                    let mut packet = Vec::new();
+                    src.serialize_with_format(&mut packet, format).unwrap();
+                    let mut reader = BufReader::new(packet.as_slice());
+                    let dst = ClientCmd::deserialize_with_format(&mut reader, format).unwrap();
+
+                    assert_eq!(src, dst, "{:?}", format);
+                    assert!(reader.buffer().is_empty(), "{:?} left unread data", src);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_server_cmd_update_stat_read_write_eq() {
         let src = ServerCmd::UpdateStat {
@@ -2474,6 +3906,7 @@ mod test {
     fn test_server_cmd_server_info_read_write_eq() {
         let src = ServerCmd::ServerInfo {
             protocol_version: 42,
+            protocol_flags: ProtocolFlags::empty(),
             max_clients: 16,
             game_type: GameType::Deathmatch,
             message: String::from("Test message"),
@@ -2489,6 +3922,84 @@ mod test {
         assert_eq!(src, dst);
     }
 
+    #[test]
+    fn test_server_cmd_server_info_rmq_flags_read_write_eq() {
+        let src = ServerCmd::ServerInfo {
+            protocol_version: PROTOCOL_RMQ,
+            protocol_flags: ProtocolFlags::FLOAT_COORD | ProtocolFlags::SHORT_ANGLE,
+            max_clients: 1,
+            game_type: GameType::CoOp,
+            message: String::from("Test message"),
+            model_precache: vec![String::from("test1.bsp")],
+            sound_precache: vec![],
+        };
+
+        let mut packet = Vec::new();
+        src.serialize(&mut packet).unwrap();
+        let mut reader = BufReader::new(packet.as_slice());
+        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+
+        assert_eq!(src, dst);
+    }
+
+    #[test]
+    fn test_wire_format_for_protocol() {
+        assert_eq!(
+            WireFormat::for_protocol(PROTOCOL_VERSION as i32, ProtocolFlags::empty()),
+            WireFormat::STANDARD
+        );
+        assert_eq!(
+            WireFormat::for_protocol(PROTOCOL_FITZQUAKE, ProtocolFlags::empty()),
+            WireFormat {
+                fitz: true,
+                ..WireFormat::STANDARD
+            }
+        );
+        assert_eq!(
+            WireFormat::for_protocol(
+                PROTOCOL_RMQ,
+                ProtocolFlags::FLOAT_COORD | ProtocolFlags::FLOAT_ANGLE
+            ),
+            WireFormat {
+                coord: CoordFormat::Float,
+                angle: AngleFormat::Float,
+                fitz: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_wire_format_move_angles() {
+        let rmq = WireFormat::for_protocol(PROTOCOL_RMQ, ProtocolFlags::empty());
+        assert_eq!(rmq.angle, AngleFormat::Byte);
+        assert_eq!(rmq.move_format().angle, AngleFormat::Short);
+        assert_eq!(WireFormat::STANDARD.move_format(), WireFormat::STANDARD);
+    }
+
+    #[test]
+    fn test_server_cmd_float_coords_preserve_precision() {
+        let format = WireFormat {
+            coord: CoordFormat::Float,
+            angle: AngleFormat::Float,
+            fitz: false,
+        };
+        let src = ServerCmd::TempEntity {
+            temp_entity: TempEntity::Point {
+                kind: PointEntityKind::Explosion,
+                origin: Vector3::new(100.01, -2.3, 4096.5),
+            },
+        };
+
+        let mut packet = Vec::new();
+        src.serialize_with_format(&mut packet, format).unwrap();
+        let mut reader = BufReader::new(packet.as_slice());
+        let dst = ServerCmd::deserialize_with_format(&mut reader, format)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(src, dst);
+    }
+
     #[test]
     fn test_server_cmd_light_style_read_write_eq() {
         let src = ServerCmd::LightStyle {
@@ -2638,6 +4149,184 @@ mod test {
         assert_eq!(src, dst);
     }
 
+    #[test]
+    fn test_server_cmd_show_lmp_read_write_eq() {
+        let src = ServerCmd::ShowLmp {
+            slot: String::from("logo"),
+            name: String::from("gfx/logo.lmp"),
+            x: 40,
+            y: 120,
+        };
+        let mut packet = Vec::new();
+        src.serialize(&mut packet).unwrap();
+        let mut reader = BufReader::new(packet.as_slice());
+        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+
+        assert_eq!(src, dst);
+    }
+
+    #[test]
+    fn test_server_cmd_hide_lmp_read_write_eq() {
+        let src = ServerCmd::HideLmp {
+            slot: String::from("logo"),
+        };
+        let mut packet = Vec::new();
+        src.serialize(&mut packet).unwrap();
+        let mut reader = BufReader::new(packet.as_slice());
+        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+
+        assert_eq!(src, dst);
+    }
+
+    #[test]
+    fn test_server_cmd_fog_read_write_eq() {
+        let src = ServerCmd::Fog {
+            density: 13,
+            color: [77, 64, 51],
+            time: 150,
+        };
+        let mut packet = Vec::new();
+        src.serialize(&mut packet).unwrap();
+        let mut reader = BufReader::new(packet.as_slice());
+        let dst = ServerCmd::deserialize(&mut reader).unwrap().unwrap();
+
+        assert_eq!(src, dst);
+    }
+
+    #[test]
+    fn test_spawn_baseline2_optional_fields() {
+        let src = ServerCmd::SpawnBaseline2 {
+            ent_id: 12,
+            model_id: 3,
+            frame_id: 300,
+            colormap: 0,
+            skin_id: 0,
+            origin: Vector3::zero(),
+            angles: Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0)),
+            alpha: 0,
+        };
+        let mut packet = Vec::new();
+        src.serialize(&mut packet).unwrap();
+
+        // command, entity, flags, model, frame, colormap, skin, origin and angles
+        assert_eq!(packet.len(), 1 + 2 + 1 + 1 + 2 + 1 + 1 + 3 * 3);
+        assert_eq!(packet[3], BaselineFlags::LARGE_FRAME.bits());
+
+        let mut reader = BufReader::new(packet.as_slice());
+        assert_eq!(ServerCmd::deserialize(&mut reader).unwrap().unwrap(), src);
+    }
+
+    #[test]
+    fn test_decode_alpha() {
+        assert_eq!(decode_alpha(0), 1.0);
+        assert_eq!(decode_alpha(1), 0.0);
+        assert_eq!(decode_alpha(128), 0.5);
+        assert_eq!(decode_alpha(255), 1.0);
+    }
+
+    #[test]
+    fn test_encode_alpha() {
+        for alpha in 1..=u8::MAX {
+            assert_eq!(encode_alpha(decode_alpha(alpha)), alpha);
+        }
+
+        // the default alpha is encoded explicitly as fully opaque
+        assert_eq!(encode_alpha(decode_alpha(0)), 255);
+    }
+
+    #[test]
+    fn test_fast_update_transparency() {
+        let mut packet = vec![
+            FAST_UPDATE_FLAG | UpdateFlags::MORE_BITS.bits() as u8,
+            (UpdateFlags::TRANSPARENCY.bits() >> 8) as u8,
+            7,
+        ];
+        for f in &[2.0f32, 0.5, 1.0] {
+            packet.extend_from_slice(&f.to_le_bytes());
+        }
+        packet.push(ServerCmdCode::NoOp as u8);
+
+        let mut reader = BufReader::new(packet.as_slice());
+        match ServerCmd::deserialize(&mut reader).unwrap().unwrap() {
+            ServerCmd::FastUpdate(update) => {
+                assert_eq!(update.ent_id, 7);
+                assert_eq!(update.alpha, Some(0.5));
+            }
+            other => panic!("expected FastUpdate, got {:?}", other),
+        }
+
+        // the fullbright value must have been consumed
+        assert_eq!(
+            ServerCmd::deserialize(&mut reader).unwrap(),
+            Some(ServerCmd::NoOp)
+        );
+    }
+
+    #[test]
+    fn test_fast_update_transparency_zero_is_opaque() {
+        let mut packet = vec![
+            FAST_UPDATE_FLAG | UpdateFlags::MORE_BITS.bits() as u8,
+            (UpdateFlags::TRANSPARENCY.bits() >> 8) as u8,
+            7,
+        ];
+        for f in &[1.0f32, 0.0] {
+            packet.extend_from_slice(&f.to_le_bytes());
+        }
+
+        let mut reader = BufReader::new(packet.as_slice());
+        match ServerCmd::deserialize(&mut reader).unwrap().unwrap() {
+            ServerCmd::FastUpdate(update) => assert_eq!(update.alpha, Some(1.0)),
+            other => panic!("expected FastUpdate, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fast_update_fitz_extensions() {
+        let format = WireFormat::for_protocol(PROTOCOL_RMQ, ProtocolFlags::empty());
+        let src = ServerCmd::FastUpdate(EntityUpdate {
+            ent_id: 3,
+            model_id: Some(0x123),
+            frame_id: None,
+            colormap: None,
+            skin_id: None,
+            effects: None,
+            origin_x: None,
+            pitch: None,
+            origin_y: None,
+            yaw: None,
+            origin_z: None,
+            roll: None,
+            no_lerp: false,
+            alpha: Some(0.5),
+        });
+
+        let mut packet = Vec::new();
+        src.serialize_with_format(&mut packet, format).unwrap();
+
+        // three bytes of flags, entity, model low byte, alpha, model high byte
+        let flags = UpdateFlags::MORE_BITS
+            | UpdateFlags::MODEL
+            | UpdateFlags::EXTEND1
+            | UpdateFlags::ALPHA
+            | UpdateFlags::MODEL2;
+        let mut expected = vec![FAST_UPDATE_FLAG | flags.bits() as u8];
+        expected.push((flags.bits() >> 8) as u8);
+        expected.push((flags.bits() >> 16) as u8);
+        expected.extend_from_slice(&[3, 0x23, 128, 0x01]);
+        assert_eq!(packet, expected);
+
+        let mut reader = BufReader::new(packet.as_slice());
+        let dst = ServerCmd::deserialize_with_format(&mut reader, format)
+            .unwrap()
+            .unwrap();
+        assert_eq!(src, dst);
+
+        // the original protocol can't carry the large model index
+        assert!(src
+            .serialize_with_format(&mut Vec::<u8>::new(), WireFormat::STANDARD)
+            .is_err());
+    }
+
     #[test]
     fn test_client_cmd_string_cmd_read_write_eq() {
         let src = ClientCmd::StringCmd {
@@ -2672,6 +4361,31 @@ mod test {
         assert_eq!(src, dst);
     }
 
+    #[test]
+    fn test_client_cmd_move_float_angles_read_write_eq() {
+        let format = WireFormat {
+            coord: CoordFormat::Float,
+            angle: AngleFormat::Float,
+            fitz: false,
+        };
+        let src = ClientCmd::Move {
+            send_time: Duration::milliseconds(1234),
+            angles: Vector3::new(Deg(12.345), Deg(-101.7), Deg(0.25)),
+            fwd_move: 27,
+            side_move: 85,
+            up_move: 76,
+            button_flags: ButtonFlags::empty(),
+            impulse: 121,
+        };
+
+        let mut packet = Vec::new();
+        src.serialize_with_format(&mut packet, format).unwrap();
+        let mut reader = BufReader::new(packet.as_slice());
+        let dst = ClientCmd::deserialize_with_format(&mut reader, format).unwrap();
+
+        assert_eq!(src, dst);
+    }
+
     fn gen_qsocket_pair() -> (QSocket, QSocket) {
         let src_udp = UdpSocket::bind("localhost:0").unwrap();
         let src_addr = src_udp.local_addr().unwrap();
@@ -2724,4 +4438,10 @@ mod test {
         let message = [0; MAX_DATAGRAM + 1];
         src.send_msg_unreliable(&message).unwrap();
     }
+
+    #[test]
+    fn test_item_flags_accept_mission_pack_bits() {
+        // the mission packs use every bit of the item flags
+        assert_eq!(ItemFlags::from_bits(0xFFFFFFFF), Some(ItemFlags::all()));
+    }
 }
@@ -24,6 +24,8 @@ use std::{
     path::Path,
 };
 
+use crate::common::crc;
+
 use byteorder::{LittleEndian, ReadBytesExt};
 use thiserror::Error;
 
@@ -54,7 +56,12 @@ pub enum PakError {
 
 /// An open Pak archive.
 #[derive(Debug)]
-pub struct Pak(HashMap<String, Box<[u8]>>);
+pub struct Pak {
+    files: HashMap<String, Box<[u8]>>,
+
+    // CRC of the on-disk file table, if the archive was loaded from disk
+    crc: Option<u16>,
+}
 
 impl Pak {
     // TODO: rename to from_path or similar
@@ -83,6 +90,12 @@ impl Pak {
             s => s as u32,
         };
 
+        // the original engine checksums the raw file table to detect modified archives
+        let mut table = vec![0; table_size as usize];
+        infile.seek(SeekFrom::Start(table_offset as u64))?;
+        infile.read_exact(&mut table)?;
+        let crc = crc::crc_block(&table);
+
         let mut map = HashMap::new();
 
         for i in 0..(table_size as usize / PAK_ENTRY_SIZE) {
@@ -119,7 +132,31 @@ impl Pak {
             map.insert(path, data.into_boxed_slice());
         }
 
-        Ok(Pak(map))
+        Ok(Pak {
+            files: map,
+            crc: Some(crc),
+        })
+    }
+
+    /// Creates an archive from files already held in memory.
+    pub fn from_files(files: HashMap<String, Box<[u8]>>) -> Pak {
+        Pak { files, crc: None }
+    }
+
+    /// Returns the CRC of the archive's file table.
+    ///
+    /// This is `None` for archives which were not loaded from disk.
+    pub fn crc(&self) -> Option<u16> {
+        self.crc
+    }
+
+    /// Returns the number of files in the archive.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
     }
 
     /// Opens a file in the file tree for reading.
@@ -139,13 +176,13 @@ impl Pak {
         S: AsRef<str>,
     {
         let path = path.as_ref();
-        self.0
+        self.files
             .get(path)
             .map(|s| s.as_ref())
             .ok_or(PakError::NoSuchFile(path.to_owned()))
     }
 
     pub fn iter<'a>(&self) -> Iter<String, impl AsRef<[u8]>> {
-        self.0.iter()
+        self.files.iter()
     }
 }
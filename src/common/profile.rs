@@ -0,0 +1,313 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! User data directories and named profiles.
+//!
+//! Game data is read from the base directory, but everything written on the user's behalf goes to
+//! the user data directory: `$XDG_DATA_HOME/richter` on Linux, `%APPDATA%\richter` on Windows and
+//! `~/Library/Application Support/richter` on macOS. If no user data directory can be found, or
+//! `--user-dir` is given the base directory, the original in-place layout is used instead.
+//!
+//! A profile is a directory in the user data directory holding a user's configs, saves and
+//! screenshots. The default profile is `id1/` and other profiles live in `profiles/<name>/`.
+//! Profiles are mounted on top of the game data, so a script like `config.cfg` is read from the
+//! profile if it has its own copy and from the game directory otherwise.
+
+use std::{
+    cell::RefCell,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::common::console::CmdRegistry;
+
+use thiserror::Error;
+
+/// The name of the profile stored in `id1/`.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The directory under the user data directory which holds the named profiles.
+const PROFILES_DIR: &str = "profiles";
+
+/// The name of the directory created in the platform's data directory.
+const USER_DIR_NAME: &str = "richter";
+
+/// Returns the platform's user data directory for the game, or `None` if it can't be determined.
+pub fn default_user_dir() -> Option<PathBuf> {
+    let non_empty = |var: &str| {
+        env::var_os(var)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+
+    let data_dir = if cfg!(target_os = "windows") {
+        non_empty("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        non_empty("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        non_empty("XDG_DATA_HOME").or_else(|| non_empty("HOME").map(|h| h.join(".local/share")))
+    };
+
+    data_dir.map(|d| d.join(USER_DIR_NAME))
+}
+
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("Invalid profile name \"{0}\": use only letters, digits, '-' and '_'")]
+    InvalidName(String),
+    #[error("Couldn't create profile directory: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// The directory to which a user's configs, saves and screenshots are written.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    name: String,
+    dir: PathBuf,
+}
+
+impl Profile {
+    /// Opens the profile `name` in `user_dir`, creating its directory if it doesn't exist.
+    pub fn open<P>(user_dir: P, name: &str) -> Result<Profile, ProfileError>
+    where
+        P: AsRef<Path>,
+    {
+        let user_dir = user_dir.as_ref();
+
+        let dir = if name == DEFAULT_PROFILE {
+            user_dir.join("id1")
+        } else if is_valid_name(name) {
+            user_dir.join(PROFILES_DIR).join(name)
+        } else {
+            return Err(ProfileError::InvalidName(name.to_owned()));
+        };
+        fs::create_dir_all(&dir)?;
+
+        Ok(Profile {
+            name: name.to_owned(),
+            dir,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the directory of this profile.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns whether this is the profile stored in `id1/`.
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_PROFILE
+    }
+
+    /// Resolves `path` relative to the profile directory.
+    ///
+    /// All files written on behalf of the user should go through this, so that profiles don't
+    /// overwrite each other's files.
+    pub fn path<P>(&self, path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        self.dir.join(path)
+    }
+
+    /// Copies the configs and saves that older versions wrote to `game_dir` into this profile.
+    ///
+    /// Only files the profile doesn't already have are copied, and the originals are left in
+    /// place. Returns the names of the copied files.
+    pub fn migrate_legacy<P>(&self, game_dir: P) -> Result<Vec<String>, ProfileError>
+    where
+        P: AsRef<Path>,
+    {
+        let game_dir = game_dir.as_ref();
+        let mut migrated = Vec::new();
+
+        if game_dir == self.dir || !game_dir.is_dir() {
+            return Ok(migrated);
+        }
+
+        for entry in fs::read_dir(game_dir)? {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(n) => n,
+                Err(_) => continue,
+            };
+
+            let lower = name.to_lowercase();
+            if lower != "config.cfg" && !lower.ends_with(".sav") {
+                continue;
+            }
+
+            let dest = self.dir.join(&name);
+            if dest.exists() || !entry.path().is_file() {
+                continue;
+            }
+
+            fs::copy(entry.path(), dest)?;
+            migrated.push(name);
+        }
+
+        migrated.sort();
+        Ok(migrated)
+    }
+
+    /// Returns the names of all profiles in `user_dir` in sorted order, including the default.
+    pub fn list<P>(user_dir: P) -> Vec<String>
+    where
+        P: AsRef<Path>,
+    {
+        let mut names = vec![DEFAULT_PROFILE.to_owned()];
+
+        if let Ok(entries) = fs::read_dir(user_dir.as_ref().join(PROFILES_DIR)) {
+            names.extend(
+                entries
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.path().is_dir())
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .filter(|n| n != DEFAULT_PROFILE && is_valid_name(n)),
+            );
+        }
+
+        names.sort();
+        names
+    }
+
+    pub fn register_cmds(&self, user_dir: PathBuf, cmds: &RefCell<CmdRegistry>) {
+        cmds.borrow_mut()
+            .insert_or_replace("profile", cmd_profile(self.name.clone(), user_dir))
+            .unwrap();
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Implements the `profile` command, which lists the available profiles.
+///
+/// The profile can only be chosen at startup, since the configs of the current profile have
+/// already been executed by the time the console is available.
+fn cmd_profile(current: String, user_dir: PathBuf) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| match args.len() {
+        0 => {
+            let mut output = String::new();
+            for name in Profile::list(&user_dir) {
+                let marker = if name == current { "*" } else { " " };
+                output.push_str(&format!("{} {}\n", marker, name));
+            }
+            output
+        }
+
+        1 if args[0] == current => format!("Already using profile \"{}\"", current),
+        1 => format!(
+            "Restart with --profile {} to switch profiles, the current profile is \"{}\"",
+            args[0], current
+        ),
+
+        _ => "usage: profile [NAME]".to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("richter-profile-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_open_profile() {
+        let user_dir = test_dir("open");
+
+        let default = Profile::open(&user_dir, DEFAULT_PROFILE).unwrap();
+        assert!(default.is_default());
+        assert_eq!(default.path("config.cfg"), user_dir.join("id1/config.cfg"));
+
+        let test = Profile::open(&user_dir, "test_2").unwrap();
+        assert!(!test.is_default());
+        assert!(test.dir().is_dir());
+        assert_eq!(
+            test.path("config.cfg"),
+            user_dir.join("profiles/test_2/config.cfg")
+        );
+
+        assert_eq!(
+            Profile::list(&user_dir),
+            vec![DEFAULT_PROFILE.to_owned(), "test_2".to_owned()]
+        );
+
+        fs::remove_dir_all(&user_dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_name() {
+        let user_dir = test_dir("invalid");
+
+        for name in &["", "..", "a/b", "with space"] {
+            assert!(matches!(
+                Profile::open(&user_dir, name),
+                Err(ProfileError::InvalidName(_))
+            ));
+        }
+        assert!(!user_dir.exists());
+    }
+
+    #[test]
+    fn test_migrate_legacy() {
+        let game_dir = test_dir("legacy").join("id1");
+        fs::create_dir_all(&game_dir).unwrap();
+        fs::write(game_dir.join("config.cfg"), "bind w +forward\n").unwrap();
+        fs::write(game_dir.join("s0.sav"), "save").unwrap();
+        fs::write(game_dir.join("pak0.pak"), "game data").unwrap();
+
+        let user_dir = test_dir("migrate");
+        let profile = Profile::open(&user_dir, DEFAULT_PROFILE).unwrap();
+        fs::write(profile.path("s0.sav"), "newer save").unwrap();
+
+        assert_eq!(
+            profile.migrate_legacy(&game_dir).unwrap(),
+            vec!["config.cfg".to_owned()]
+        );
+        assert_eq!(
+            fs::read_to_string(profile.path("config.cfg")).unwrap(),
+            "bind w +forward\n"
+        );
+        assert_eq!(
+            fs::read_to_string(profile.path("s0.sav")).unwrap(),
+            "newer save"
+        );
+        assert!(!profile.path("pak0.pak").exists());
+        assert!(game_dir.join("config.cfg").exists());
+
+        // the legacy layout doesn't migrate into itself
+        let legacy = Profile::open(game_dir.parent().unwrap(), DEFAULT_PROFILE).unwrap();
+        assert!(legacy.migrate_legacy(&game_dir).unwrap().is_empty());
+
+        fs::remove_dir_all(game_dir.parent().unwrap()).unwrap();
+        fs::remove_dir_all(&user_dir).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Truevision TGA image loading.
+//!
+//! Only the formats used for Quake replacement textures are supported: uncompressed and
+//! run-length encoded truecolor (24- or 32-bit) and grayscale (8-bit) images.
+
+use std::io::{self, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use thiserror::Error;
+
+const IMAGE_TYPE_TRUECOLOR: u8 = 2;
+const IMAGE_TYPE_GRAYSCALE: u8 = 3;
+const IMAGE_TYPE_RLE_TRUECOLOR: u8 = 10;
+const IMAGE_TYPE_RLE_GRAYSCALE: u8 = 11;
+
+// set if the first row in the file is the top of the image
+const DESCRIPTOR_TOP_TO_BOTTOM: u8 = 0x20;
+
+#[derive(Error, Debug)]
+pub enum TgaError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Unsupported TGA image type: {0}")]
+    UnsupportedImageType(u8),
+    #[error("Unsupported TGA pixel depth {depth} for image type {image_type}")]
+    UnsupportedPixelDepth { image_type: u8, depth: u8 },
+    #[error("Invalid TGA dimensions: {0}x{1}")]
+    InvalidDimensions(u16, u16),
+    #[error("TGA image is {0}x{1}, larger than the maximum of {2}x{2}")]
+    TooLarge(u16, u16, u32),
+}
+
+/// A decoded TGA image.
+#[derive(Debug)]
+pub struct Tga {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl Tga {
+    /// Decodes a TGA image no wider or taller than `max_dimension`.
+    pub fn load<R>(mut reader: R, max_dimension: u32) -> Result<Tga, TgaError>
+    where
+        R: Read,
+    {
+        let id_len = reader.read_u8()?;
+        let colormap_type = reader.read_u8()?;
+        let image_type = reader.read_u8()?;
+        let _colormap_start = reader.read_u16::<LittleEndian>()?;
+        let colormap_len = reader.read_u16::<LittleEndian>()?;
+        let colormap_entry_bits = reader.read_u8()?;
+        let _x_origin = reader.read_u16::<LittleEndian>()?;
+        let _y_origin = reader.read_u16::<LittleEndian>()?;
+        let width = reader.read_u16::<LittleEndian>()?;
+        let height = reader.read_u16::<LittleEndian>()?;
+        let depth = reader.read_u8()?;
+        let descriptor = reader.read_u8()?;
+
+        let (grayscale, rle) = match image_type {
+            IMAGE_TYPE_TRUECOLOR => (false, false),
+            IMAGE_TYPE_GRAYSCALE => (true, false),
+            IMAGE_TYPE_RLE_TRUECOLOR => (false, true),
+            IMAGE_TYPE_RLE_GRAYSCALE => (true, true),
+            t => Err(TgaError::UnsupportedImageType(t))?,
+        };
+
+        match (grayscale, depth) {
+            (false, 24) | (false, 32) | (true, 8) => (),
+            _ => Err(TgaError::UnsupportedPixelDepth { image_type, depth })?,
+        }
+
+        if width == 0 || height == 0 {
+            Err(TgaError::InvalidDimensions(width, height))?;
+        }
+
+        if width as u32 > max_dimension || height as u32 > max_dimension {
+            Err(TgaError::TooLarge(width, height, max_dimension))?;
+        }
+
+        // skip the image ID and any color map, which truecolor images don't use
+        let mut skip = id_len as u64;
+        if colormap_type != 0 {
+            skip += colormap_len as u64 * ((colormap_entry_bits as u64 + 7) / 8);
+        }
+        io::copy(&mut (&mut reader).take(skip), &mut io::sink())?;
+
+        let pixel_count = width as usize * height as usize;
+        let bytes_per_pixel = depth as usize / 8;
+        let mut rgba = Vec::with_capacity(pixel_count * 4);
+
+        let mut pixel = [0u8; 4];
+        while rgba.len() < pixel_count * 4 {
+            let (run_len, repeat) = if rle {
+                let header = reader.read_u8()?;
+                ((header & 0x7F) as usize + 1, header & 0x80 != 0)
+            } else {
+                (1, false)
+            };
+
+            for i in 0..run_len {
+                if i == 0 || !repeat {
+                    reader.read_exact(&mut pixel[..bytes_per_pixel])?;
+                }
+
+                // pixels are stored as BGR(A)
+                let color = match bytes_per_pixel {
+                    1 => [pixel[0], pixel[0], pixel[0], 0xFF],
+                    3 => [pixel[2], pixel[1], pixel[0], 0xFF],
+                    _ => [pixel[2], pixel[1], pixel[0], pixel[3]],
+                };
+
+                // a run may not cross the end of the image
+                if rgba.len() < pixel_count * 4 {
+                    rgba.extend_from_slice(&color);
+                }
+            }
+        }
+
+        if descriptor & DESCRIPTOR_TOP_TO_BOTTOM == 0 {
+            let row_len = width as usize * 4;
+            let rows: Vec<&[u8]> = rgba.chunks(row_len).rev().collect();
+            rgba = rows.concat();
+        }
+
+        Ok(Tga {
+            width: width as u32,
+            height: height as u32,
+            rgba,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the image data as rows of RGBA pixels, from top to bottom.
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_DIMENSION: u32 = 8192;
+
+    fn header(image_type: u8, width: u16, height: u16, depth: u8, descriptor: u8) -> Vec<u8> {
+        let mut data = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.push(depth);
+        data.push(descriptor);
+        data
+    }
+
+    #[test]
+    fn test_uncompressed_bottom_to_top() {
+        let mut data = header(IMAGE_TYPE_TRUECOLOR, 1, 2, 24, 0);
+        // bottom row first, stored as BGR
+        data.extend_from_slice(&[3, 2, 1, 6, 5, 4]);
+
+        let tga = Tga::load(&data[..], MAX_DIMENSION).unwrap();
+        assert_eq!((tga.width(), tga.height()), (1, 2));
+        assert_eq!(tga.rgba(), &[4, 5, 6, 255, 1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn test_rle_top_to_bottom() {
+        let mut data = header(IMAGE_TYPE_RLE_TRUECOLOR, 3, 1, 32, DESCRIPTOR_TOP_TO_BOTTOM);
+        // a repeated run of two pixels followed by a raw run of one
+        data.extend_from_slice(&[0x81, 3, 2, 1, 128, 0x00, 6, 5, 4, 255]);
+
+        let tga = Tga::load(&data[..], MAX_DIMENSION).unwrap();
+        assert_eq!(tga.rgba(), &[1, 2, 3, 128, 1, 2, 3, 128, 4, 5, 6, 255][..]);
+    }
+
+    #[test]
+    fn test_unsupported_type() {
+        let data = header(1, 1, 1, 8, 0);
+        assert!(matches!(
+            Tga::load(&data[..], MAX_DIMENSION),
+            Err(TgaError::UnsupportedImageType(1))
+        ));
+    }
+
+    #[test]
+    fn test_too_large() {
+        // no pixel data follows, so this only passes if the header is rejected
+        let data = header(IMAGE_TYPE_TRUECOLOR, 65535, 65535, 32, 0);
+        assert!(matches!(
+            Tga::load(&data[..], MAX_DIMENSION),
+            Err(TgaError::TooLarge(65535, 65535, MAX_DIMENSION))
+        ));
+    }
+}
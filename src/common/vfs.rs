@@ -16,55 +16,204 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
 use std::{
-    fs::File,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
     io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
 };
 
-use crate::common::pak::{Pak, PakError};
+use crate::common::{
+    cache::DownloadCache,
+    console::CmdRegistry,
+    fallback, kpf,
+    limits::Limits,
+    pak::{Pak, PakError},
+};
 
 use thiserror::Error;
 
+/// Number of files in the original `pak0.pak`.
+const PAK0_COUNT: usize = 339;
+
+/// CRC of the file table of the original `pak0.pak`.
+const PAK0_CRC: u16 = 32981;
+
+/// Only present in the registered version's `pak1.pak`.
+const REGISTERED_LUMP: &str = "gfx/pop.lmp";
+
+/// Prefixes of the maps in episodes 2 to 4, which only come with the registered version.
+const REGISTERED_MAP_PREFIXES: &[&str] = &["maps/e2", "maps/e3", "maps/e4", "maps/end."];
+
+/// Name displayed for the built-in fallback assets.
+const FALLBACK_NAME: &str = "<built-in>";
+
+/// Name displayed for files added with [`Vfs::add_memory`].
+const MEMORY_NAME: &str = "<memory>";
+
 #[derive(Error, Debug)]
 pub enum VfsError {
     #[error("Couldn't load pakfile: {0}")]
     Pak(#[from] PakError),
     #[error("File does not exist: {0}")]
     NoSuchFile(String),
+    #[error("No writable directory is mounted")]
+    ReadOnly,
+    #[error("Invalid path \"{0}\": must be relative and stay inside the writable directory")]
+    InvalidPath(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Which release of the game data is mounted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edition {
+    /// The original, unmodified shareware release.
+    Shareware,
+    /// The registered release.
+    Registered,
+    /// Modified or unrecognized game data, including the built-in fallback assets.
+    Unknown,
+}
+
+impl Edition {
+    /// Returns true if this release may load the map at `path`, e.g. `maps/e2m1.bsp`.
+    ///
+    /// Like the original progs, the shareware version doesn't play the registered episodes.
+    /// Modified or unrecognized data may well contain them, so it isn't restricted.
+    pub fn allows_map(self, path: &str) -> bool {
+        let path = path.to_lowercase();
+        self != Edition::Shareware
+            || !REGISTERED_MAP_PREFIXES
+                .iter()
+                .any(|prefix| path.starts_with(prefix))
+    }
 }
 
 #[derive(Debug)]
 enum VfsComponent {
-    Pak(Pak),
+    Pak { name: String, pak: Pak },
     Directory(PathBuf),
+    Memory(HashMap<String, Box<[u8]>>),
+    Cache(Rc<RefCell<DownloadCache>>),
+}
+
+impl VfsComponent {
+    fn name(&self) -> String {
+        match self {
+            VfsComponent::Pak { name, .. } => name.to_owned(),
+            VfsComponent::Directory(path) => format!("{}", path.display()),
+            VfsComponent::Memory(_) => MEMORY_NAME.to_owned(),
+            VfsComponent::Cache(cache) => {
+                format!("{} (download cache)", cache.borrow().dir().display())
+            }
+        }
+    }
+
+    fn read(&self, virtual_path: &str) -> Option<Vec<u8>> {
+        match self {
+            VfsComponent::Pak { pak, .. } => pak.open(virtual_path).ok().map(|f| f.to_vec()),
+            VfsComponent::Directory(path) => fs::read(path.join(virtual_path)).ok(),
+            VfsComponent::Memory(files) => files.get(virtual_path).map(|f| f.to_vec()),
+            VfsComponent::Cache(cache) => cache.borrow().read(virtual_path),
+        }
+    }
+
+    /// Lists the virtual paths of all files provided by this component.
+    fn file_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+
+        match self {
+            VfsComponent::Pak { pak, .. } => names.extend(pak.iter().map(|(name, _)| name.clone())),
+            VfsComponent::Directory(path) => list_directory(path, path, &mut names),
+            VfsComponent::Memory(files) => names.extend(files.keys().cloned()),
+            VfsComponent::Cache(cache) => names.extend(cache.borrow().file_names()),
+        }
+
+        names
+    }
+}
+
+fn list_directory(root: &Path, dir: &Path, names: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) => {
+            log::warn!("Couldn't read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            list_directory(root, &path, names);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            // virtual paths always use forward slashes
+            let components = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            names.push(components.join("/"));
+        }
+    }
+}
+
+/// A copy of a file provided by one of the mounted archives or directories.
+#[derive(Debug)]
+pub struct VfsEntry {
+    /// The name of the archive or directory providing this copy.
+    pub source: String,
+    pub size: usize,
+    pub md5: String,
+    pub sha1: String,
+}
+
+impl VfsEntry {
+    fn new(source: String, data: &[u8]) -> VfsEntry {
+        VfsEntry {
+            source,
+            size: data.len(),
+            md5: format!("{:x}", md5::compute(data)),
+            sha1: sha1::Sha1::from(data).digest().to_string(),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Vfs {
     components: Vec<VfsComponent>,
+    edition: Edition,
+    writable_dir: Option<PathBuf>,
 }
 
 impl Vfs {
     pub fn new() -> Vfs {
         Vfs {
             components: Vec::new(),
+            edition: Edition::Unknown,
+            writable_dir: None,
         }
     }
 
     /// Initializes the virtual filesystem using a base directory.
+    ///
+    /// If no game data can be found, the built-in fallback assets are mounted instead.
     pub fn with_base_dir(base_dir: PathBuf) -> Vfs {
         let mut vfs = Vfs::new();
 
+        let kpf_path = base_dir.join(kpf::KPF_NAME);
         let mut game_dir = base_dir;
         game_dir.push("id1");
 
         if !game_dir.is_dir() {
-            log::error!(concat!(
+            log::warn!(concat!(
                 "`id1/` directory does not exist! Use the `--base-dir` option with the name of the",
-                " directory which contains `id1/`."
+                " directory which contains `id1/`. Falling back to built-in assets."
             ));
 
-            std::process::exit(1);
+            vfs.add_fallback();
+            return vfs;
         }
 
         vfs.add_directory(&game_dir).unwrap();
@@ -72,11 +221,12 @@ impl Vfs {
         // ...then add PAK archives.
         let mut num_paks = 0;
         let mut pak_path = game_dir;
-        for vfs_id in 0..crate::common::MAX_PAKFILES {
+        let max_paks = Limits::default().pak_files;
+        for vfs_id in 0..max_paks {
             // Add the file name.
             pak_path.push(format!("pak{}.pak", vfs_id));
 
-            // Keep adding PAKs until we don't find one or we hit MAX_PAKFILES.
+            // Keep adding PAKs until we don't find one or we hit the limit.
             if !pak_path.exists() {
                 // If the lowercase path doesn't exist, try again with uppercase.
                 pak_path.pop();
@@ -87,28 +237,93 @@ impl Vfs {
             }
 
             vfs.add_pakfile(&pak_path).unwrap();
+
+            if num_paks == 0 {
+                if let Some(VfsComponent::Pak { pak: pak0, .. }) = vfs.components.last() {
+                    if pak0.len() == PAK0_COUNT && pak0.crc() == Some(PAK0_CRC) {
+                        vfs.edition = Edition::Shareware;
+                    } else {
+                        log::info!("pak0.pak has been modified");
+                    }
+                }
+            }
+
             num_paks += 1;
 
             // Remove the file name, leaving the game directory.
             pak_path.pop();
         }
 
+        if num_paks == max_paks {
+            log::warn!("Only the first {} PAK files were loaded", max_paks);
+        }
+
         if num_paks == 0 {
-            log::warn!("No PAK files found.");
+            log::warn!("No PAK files found. Falling back to built-in assets.");
+
+            // loose files in id1/ still take priority over the fallback assets
+            vfs.components.insert(
+                0,
+                VfsComponent::Pak {
+                    name: FALLBACK_NAME.to_owned(),
+                    pak: fallback::pak(),
+                },
+            );
+        }
+
+        // the rerelease's own archive only fills in what the game data doesn't provide
+        if kpf_path.is_file() {
+            match kpf::load(&kpf_path) {
+                Ok(pak) => vfs.components.insert(
+                    0,
+                    VfsComponent::Pak {
+                        name: format!("{}", kpf_path.display()),
+                        pak,
+                    },
+                ),
+                Err(e) => log::warn!("Couldn't load {}: {}", kpf_path.display(), e),
+            }
+        }
+
+        // like COM_CheckRegistered, identify the registered version by the presence of gfx/pop.lmp
+        if vfs.open(REGISTERED_LUMP).is_ok() {
+            vfs.edition = Edition::Registered;
+        }
+
+        match vfs.edition {
+            Edition::Shareware => log::info!("Playing shareware version."),
+            Edition::Registered => log::info!("Playing registered version."),
+            Edition::Unknown => (),
         }
 
         vfs
     }
 
+    /// Returns which release of the game data is mounted.
+    pub fn edition(&self) -> Edition {
+        self.edition
+    }
+
     pub fn add_pakfile<P>(&mut self, path: P) -> Result<(), VfsError>
     where
         P: AsRef<Path>,
     {
         let path = path.as_ref();
-        self.components.push(VfsComponent::Pak(Pak::new(path)?));
+        self.components.push(VfsComponent::Pak {
+            name: format!("{}", path.display()),
+            pak: Pak::new(path)?,
+        });
         Ok(())
     }
 
+    /// Adds the built-in fallback assets. See the [`fallback`] module for details.
+    pub fn add_fallback(&mut self) {
+        self.components.push(VfsComponent::Pak {
+            name: FALLBACK_NAME.to_owned(),
+            pak: fallback::pak(),
+        });
+    }
+
     pub fn add_directory<P>(&mut self, path: P) -> Result<(), VfsError>
     where
         P: AsRef<Path>,
@@ -118,6 +333,133 @@ impl Vfs {
         Ok(())
     }
 
+    /// Mounts a game directory such as a mission pack's on top of the base game.
+    ///
+    /// As with `id1/`, the directory itself is mounted first and then its numbered PAK archives,
+    /// so everything in it overrides the base game's files.
+    pub fn add_game_dir<P>(&mut self, path: P) -> Result<(), VfsError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(VfsError::NoSuchFile(format!("{}", path.display())));
+        }
+
+        self.add_directory(path)?;
+        for vfs_id in 0..Limits::default().pak_files {
+            let pak_path = [format!("pak{}.pak", vfs_id), format!("PAK{}.PAK", vfs_id)]
+                .iter()
+                .map(|name| path.join(name))
+                .find(|p| p.exists());
+
+            match pak_path {
+                Some(p) => self.add_pakfile(p)?,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Mounts a directory to which files can be written with [`Vfs::create`].
+    ///
+    /// The directory takes priority over everything mounted before it, so files written to it
+    /// shadow the game data. This lets the game data itself be installed read-only. If the
+    /// directory is already mounted, e.g. because it is also the game directory, it is only marked
+    /// as writable.
+    pub fn add_writable_directory<P>(&mut self, path: P) -> Result<(), VfsError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        fs::create_dir_all(path)?;
+
+        let mounted = self.components.iter().any(|c| match c {
+            VfsComponent::Directory(p) => p == path,
+            _ => false,
+        });
+        if !mounted {
+            self.add_directory(path)?;
+        }
+
+        self.writable_dir = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Returns the writable directory, if one is mounted.
+    pub fn writable_dir(&self) -> Option<&Path> {
+        self.writable_dir.as_deref()
+    }
+
+    /// Resolves a virtual path to its location in the writable directory.
+    ///
+    /// Parent directories are created as needed. Absolute paths and paths containing `..` are
+    /// rejected, so nothing outside the writable directory can be written.
+    pub fn writable_path<S>(&self, virtual_path: S) -> Result<PathBuf, VfsError>
+    where
+        S: AsRef<str>,
+    {
+        let virtual_path = virtual_path.as_ref();
+        let mut components = Path::new(virtual_path).components();
+        if !components.all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+            || Path::new(virtual_path).file_name().is_none()
+        {
+            return Err(VfsError::InvalidPath(virtual_path.to_owned()));
+        }
+
+        let dir = self.writable_dir.as_ref().ok_or(VfsError::ReadOnly)?;
+        let path = dir.join(virtual_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(path)
+    }
+
+    /// Creates or truncates a file in the writable directory.
+    pub fn create<S>(&self, virtual_path: S) -> Result<File, VfsError>
+    where
+        S: AsRef<str>,
+    {
+        Ok(File::create(self.writable_path(virtual_path)?)?)
+    }
+
+    /// Adds a file held in memory under the given virtual path.
+    ///
+    /// Like other components, the file takes priority over everything mounted before it. Files
+    /// added consecutively share a single component, and adding a path that already exists in it
+    /// replaces the previous contents.
+    pub fn add_memory<S, B>(&mut self, name: S, bytes: B)
+    where
+        S: Into<String>,
+        B: Into<Box<[u8]>>,
+    {
+        if let Some(VfsComponent::Memory(files)) = self.components.last_mut() {
+            files.insert(name.into(), bytes.into());
+            return;
+        }
+
+        let mut files = HashMap::new();
+        files.insert(name.into(), bytes.into());
+        self.components.push(VfsComponent::Memory(files));
+    }
+
+    /// Mounts a download cache below every other component.
+    ///
+    /// Downloaded content can only provide files which are missing from the game data, never
+    /// replace them.
+    pub fn add_download_cache(&mut self, cache: Rc<RefCell<DownloadCache>>) {
+        self.components.insert(0, VfsComponent::Cache(cache));
+    }
+
+    /// Returns the mounted download cache, if any.
+    pub fn download_cache(&self) -> Option<Rc<RefCell<DownloadCache>>> {
+        self.components.iter().find_map(|c| match c {
+            VfsComponent::Cache(cache) => Some(cache.clone()),
+            _ => None,
+        })
+    }
+
     pub fn open<S>(&self, virtual_path: S) -> Result<VirtualFile, VfsError>
     where
         S: AsRef<str>,
@@ -127,13 +469,19 @@ impl Vfs {
         // iterate in reverse so later PAKs overwrite earlier ones
         for c in self.components.iter().rev() {
             match c {
-                VfsComponent::Pak(pak) => {
+                VfsComponent::Pak { pak, .. } => {
                     if let Ok(f) = pak.open(vp) {
                         return Ok(VirtualFile::PakBacked(Cursor::new(f)));
                     }
                 }
 
                 VfsComponent::Directory(path) => {
+                    // like the original, the shareware version never loads loose files outside of
+                    // the game directory itself
+                    if self.edition == Edition::Shareware && vp.contains(&['/', '\\'][..]) {
+                        continue;
+                    }
+
                     let mut full_path = path.to_owned();
                     full_path.push(vp);
 
@@ -141,16 +489,158 @@ impl Vfs {
                         return Ok(VirtualFile::FileBacked(BufReader::new(f)));
                     }
                 }
+
+                VfsComponent::Memory(files) => {
+                    if let Some(f) = files.get(vp) {
+                        return Ok(VirtualFile::PakBacked(Cursor::new(f.as_ref())));
+                    }
+                }
+
+                VfsComponent::Cache(cache) => {
+                    if let Some(data) = cache.borrow().read(vp) {
+                        return Ok(VirtualFile::CacheBacked(Cursor::new(data)));
+                    }
+                }
             }
         }
 
         Err(VfsError::NoSuchFile(vp.to_owned()))
     }
+
+    /// Returns the names of all mounted archives and directories, highest priority first.
+    pub fn search_path(&self) -> Vec<String> {
+        self.components.iter().rev().map(|c| c.name()).collect()
+    }
+
+    /// Returns the names of all files visible through the VFS, sorted and without duplicates.
+    pub fn file_names(&self) -> Vec<String> {
+        let mut names = self
+            .components
+            .iter()
+            .flat_map(|c| c.file_names())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Finds every copy of a file across all mounted archives and directories.
+    ///
+    /// Entries are in priority order, so the first entry (if any) is the one returned by
+    /// [`Vfs::open`].
+    pub fn find_all<S>(&self, virtual_path: S) -> Vec<VfsEntry>
+    where
+        S: AsRef<str>,
+    {
+        let vp = virtual_path.as_ref();
+
+        self.components
+            .iter()
+            .rev()
+            .filter_map(|c| c.read(vp).map(|data| VfsEntry::new(c.name(), &data)))
+            .collect()
+    }
+
+    /// Lists all files provided by more than one archive or directory.
+    ///
+    /// Each file maps to the names of the components providing it, highest priority first.
+    pub fn shadowed_files(&self) -> BTreeMap<String, Vec<String>> {
+        let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for c in self.components.iter().rev() {
+            let name = c.name();
+            for file_name in c.file_names() {
+                sources.entry(file_name).or_default().push(name.clone());
+            }
+        }
+
+        sources
+            .into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .collect()
+    }
+
+    pub fn register_cmds(vfs: Rc<Vfs>, cmds: &RefCell<CmdRegistry>) {
+        cmds.borrow_mut()
+            .insert_or_replace("path", cmd_path(vfs.clone()))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("vfs_which", cmd_vfs_which(vfs.clone()))
+            .unwrap();
+        cmds.borrow_mut()
+            .insert_or_replace("vfs_shadowed", cmd_vfs_shadowed(vfs))
+            .unwrap();
+    }
+}
+
+/// Implements the `path` command, which lists the search path.
+fn cmd_path(vfs: Rc<Vfs>) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |_| {
+        let mut out = "Current search path:\n".to_owned();
+        for name in vfs.search_path() {
+            out.push_str(&name);
+            out.push('\n');
+        }
+        out
+    })
+}
+
+/// Implements the `vfs_which` command, which shows every copy of a file and which one is used.
+fn cmd_vfs_which(vfs: Rc<Vfs>) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| {
+        if args.len() != 1 {
+            return "usage: vfs_which <path>".to_owned();
+        }
+
+        let entries = vfs.find_all(args[0]);
+        if entries.is_empty() {
+            return format!("{} not found", args[0]);
+        }
+
+        let mut out = String::new();
+        for (i, entry) in entries.iter().enumerate() {
+            out.push_str(&format!(
+                "{} {} ({} bytes)\n    md5:  {}\n    sha1: {}\n",
+                if i == 0 { "*" } else { " " },
+                entry.source,
+                entry.size,
+                entry.md5,
+                entry.sha1,
+            ));
+        }
+        out
+    })
+}
+
+/// Implements the `vfs_shadowed` command, which lists files provided by more than one archive or
+/// directory. An optional argument restricts the listing to paths starting with a prefix.
+fn cmd_vfs_shadowed(vfs: Rc<Vfs>) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| {
+        let prefix = match args.len() {
+            0 => "",
+            1 => args[0],
+            _ => return "usage: vfs_shadowed [prefix]".to_owned(),
+        };
+
+        let mut out = String::new();
+        let mut count = 0;
+        for (path, sources) in vfs.shadowed_files() {
+            if !path.starts_with(prefix) {
+                continue;
+            }
+
+            out.push_str(&format!("{}: {}\n", path, sources.join(" > ")));
+            count += 1;
+        }
+        out.push_str(&format!("{} shadowed files", count));
+        out
+    })
 }
 
 pub enum VirtualFile<'a> {
     PakBacked(Cursor<&'a [u8]>),
     FileBacked(BufReader<File>),
+    CacheBacked(Cursor<Vec<u8>>),
 }
 
 impl<'a> Read for VirtualFile<'a> {
@@ -158,6 +648,7 @@ impl<'a> Read for VirtualFile<'a> {
         match self {
             VirtualFile::PakBacked(curs) => curs.read(buf),
             VirtualFile::FileBacked(file) => file.read(buf),
+            VirtualFile::CacheBacked(curs) => curs.read(buf),
         }
     }
 }
@@ -167,6 +658,219 @@ impl<'a> Seek for VirtualFile<'a> {
         match self {
             VirtualFile::PakBacked(curs) => curs.seek(pos),
             VirtualFile::FileBacked(file) => file.seek(pos),
+            VirtualFile::CacheBacked(curs) => curs.seek(pos),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use crate::common::crc::Crc;
+
+    fn read_to_end(vfs: &Vfs, virtual_path: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        vfs.open(virtual_path)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_add_memory_open() {
+        let mut vfs = Vfs::new();
+        vfs.add_memory("maps/test.bsp", vec![1, 2, 3]);
+        vfs.add_memory("progs.dat", vec![4, 5]);
+
+        assert_eq!(read_to_end(&vfs, "maps/test.bsp"), vec![1, 2, 3]);
+        assert_eq!(read_to_end(&vfs, "progs.dat"), vec![4, 5]);
+        assert!(vfs.open("maps/missing.bsp").is_err());
+        assert_eq!(vfs.search_path(), vec![MEMORY_NAME.to_owned()]);
+    }
+
+    #[test]
+    fn test_add_memory_priority() {
+        let mut vfs = Vfs::new();
+        vfs.add_memory("gfx/palette.lmp", vec![0]);
+        vfs.add_fallback();
+        assert_ne!(read_to_end(&vfs, "gfx/palette.lmp"), vec![0]);
+
+        vfs.add_memory("gfx/palette.lmp", vec![1]);
+        assert_eq!(read_to_end(&vfs, "gfx/palette.lmp"), vec![1]);
+
+        let sources = vfs.shadowed_files().remove("gfx/palette.lmp").unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                MEMORY_NAME.to_owned(),
+                FALLBACK_NAME.to_owned(),
+                MEMORY_NAME.to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_writable_directory() {
+        let dir = std::env::temp_dir().join(format!("richter-vfs-write-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut vfs = Vfs::new();
+        vfs.add_memory("config.cfg", b"old".to_vec());
+        assert!(matches!(vfs.create("config.cfg"), Err(VfsError::ReadOnly)));
+
+        vfs.add_writable_directory(&dir).unwrap();
+        vfs.create("config.cfg").unwrap().write_all(b"new").unwrap();
+        vfs.create("demos/test.dem").unwrap();
+        assert_eq!(read_to_end(&vfs, "config.cfg"), b"new".to_vec());
+        assert!(dir.join("demos/test.dem").is_file());
+
+        for path in &[
+            "../escape.cfg",
+            "demos/../../escape.cfg",
+            "/tmp/escape.cfg",
+            "",
+        ] {
+            assert!(matches!(
+                vfs.writable_path(path),
+                Err(VfsError::InvalidPath(_))
+            ));
+        }
+
+        // mounting the same directory again doesn't add another component
+        vfs.add_writable_directory(&dir).unwrap();
+        assert_eq!(vfs.search_path().len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_add_game_dir() {
+        let dir = std::env::temp_dir().join(format!("richter-vfs-game-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut vfs = Vfs::new();
+        vfs.add_memory("progs.dat", b"id1".to_vec());
+        assert!(matches!(
+            vfs.add_game_dir(&dir),
+            Err(VfsError::NoSuchFile(_))
+        ));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("progs.dat"), b"hipnotic").unwrap();
+        vfs.add_game_dir(&dir).unwrap();
+        assert_eq!(read_to_end(&vfs, "progs.dat"), b"hipnotic".to_vec());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Writes a pak holding `count` one-byte files, adjusting unused bytes of the last file name
+    /// so the file table's CRC is `crc`, if given.
+    fn write_pak(path: &Path, count: usize, crc: Option<u16>, extra: &[&str]) {
+        let names = (0..count)
+            .map(|i| format!("file{}", i))
+            .chain(extra.iter().map(|n| n.to_string()))
+            .collect::<Vec<_>>();
+
+        let mut table = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            let mut entry = [0u8; 64];
+            entry[..name.len()].copy_from_slice(name.as_bytes());
+            entry[56..60].copy_from_slice(&(12 + i as i32).to_le_bytes());
+            entry[60..64].copy_from_slice(&1i32.to_le_bytes());
+            table.extend_from_slice(&entry);
+        }
+
+        // the bytes after a name's terminator are ignored, so any two of them can be chosen to
+        // give the table the wanted CRC
+        if let Some(crc) = crc {
+            let pad = table.len() - 10;
+            let mut prefix = Crc::new();
+            prefix.update(&table[..pad]);
+            let value = (0..=u16::MAX)
+                .find(|v| {
+                    let mut c = prefix;
+                    c.update(&v.to_le_bytes());
+                    c.update(&table[pad + 2..]);
+                    c.value() == crc
+                })
+                .unwrap();
+            table[pad..pad + 2].copy_from_slice(&value.to_le_bytes());
+        }
+
+        let mut data = b"PACK".to_vec();
+        data.extend_from_slice(&(12 + names.len() as i32).to_le_bytes());
+        data.extend_from_slice(&(table.len() as i32).to_le_bytes());
+        data.extend(std::iter::repeat(0).take(names.len()));
+        data.extend(table);
+        fs::write(path, data).unwrap();
+    }
+
+    /// Returns the edition detected for a game directory holding a `pak0.pak` with `count` files
+    /// and the given file table CRC, and a `pak1.pak` holding the registered version's lump if
+    /// `registered` is true.
+    fn detect_edition(name: &str, count: usize, crc: u16, registered: bool) -> Edition {
+        let dir = std::env::temp_dir().join(format!(
+            "richter-vfs-edition-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("id1")).unwrap();
+        write_pak(&dir.join("id1/pak0.pak"), count, Some(crc), &[]);
+        if registered {
+            write_pak(&dir.join("id1/pak1.pak"), 0, None, &[REGISTERED_LUMP]);
+        }
+
+        let edition = Vfs::with_base_dir(dir.clone()).edition();
+        fs::remove_dir_all(&dir).unwrap();
+        edition
+    }
+
+    #[test]
+    fn test_edition_shareware() {
+        assert_eq!(
+            detect_edition("shareware", PAK0_COUNT, PAK0_CRC, false),
+            Edition::Shareware
+        );
+    }
+
+    #[test]
+    fn test_edition_modified_pak0() {
+        // the right number of files, but a different table
+        assert_eq!(
+            detect_edition("crc", PAK0_COUNT, PAK0_CRC ^ 1, false),
+            Edition::Unknown
+        );
+
+        // the right table CRC, but a file missing
+        assert_eq!(
+            detect_edition("count", PAK0_COUNT - 1, PAK0_CRC, false),
+            Edition::Unknown
+        );
+    }
+
+    #[test]
+    fn test_edition_registered() {
+        assert_eq!(
+            detect_edition("registered", PAK0_COUNT, PAK0_CRC, true),
+            Edition::Registered
+        );
+    }
+
+    #[test]
+    fn test_edition_allows_map() {
+        assert!(Edition::Shareware.allows_map("maps/e1m1.bsp"));
+        assert!(Edition::Shareware.allows_map("maps/start.bsp"));
+        assert!(Edition::Shareware.allows_map("maps/dm3.bsp"));
+        assert!(!Edition::Shareware.allows_map("maps/e2m1.bsp"));
+        assert!(!Edition::Shareware.allows_map("maps/E4M8.BSP"));
+        assert!(!Edition::Shareware.allows_map("maps/end.bsp"));
+        assert!(Edition::Unknown.allows_map("maps/e3m1.bsp"));
+        assert!(Edition::Registered.allows_map("maps/e2m1.bsp"));
+        assert!(Edition::Registered.allows_map("maps/end.bsp"));
+    }
+}
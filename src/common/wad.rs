@@ -130,6 +130,19 @@ impl QPic {
         })
     }
 
+    /// Creates a checkerboard image to stand in for a missing picture.
+    pub fn placeholder(width: u32, height: u32) -> QPic {
+        let indices = (0..height)
+            .flat_map(|r| (0..width).map(move |c| if (r / 4 + c / 4) % 2 == 0 { 0 } else { 15 }))
+            .collect::<Vec<_>>();
+
+        QPic {
+            width,
+            height,
+            indices: indices.into_boxed_slice(),
+        }
+    }
+
     pub fn width(&self) -> u32 {
         self.width
     }
@@ -0,0 +1,248 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Per-client datagram assembly.
+//!
+//! Each frame, a client's unreliable datagram holds its own state, the updates for every entity
+//! it can see and the temporary entities spawned that frame. On a busy map this can exceed the
+//! datagram size, which the original server handled by cutting off entity updates and printing
+//! "packet overflow". `DatagramBuilder` instead drops the least important data first: temporary
+//! entities go before any entity update, and entity updates go farthest from the client first.
+
+use crate::common::net::{EntityUpdate, NetError, ServerCmd, TempEntity, WireFormat};
+
+use cgmath::{InnerSpace, Vector3};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DatagramError {
+    #[error("Client data ({size} bytes) exceeds datagram budget ({budget} bytes)")]
+    EssentialOverflow { size: usize, budget: usize },
+    #[error("Network error: {0}")]
+    Net(#[from] NetError),
+}
+
+/// Counts the data dropped from a client's datagrams to keep them within budget.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OverflowStats {
+    /// The number of datagrams which had data dropped.
+    pub overflowed_datagrams: u32,
+
+    /// The number of entity updates dropped.
+    pub dropped_entities: u32,
+
+    /// The number of temporary entities dropped.
+    pub dropped_temp_entities: u32,
+}
+
+/// Collects the contents of one client's datagram for a single frame.
+pub struct DatagramBuilder {
+    format: WireFormat,
+    view_origin: Vector3<f32>,
+    essential: Vec<u8>,
+    // (squared distance from the view origin, serialized update)
+    entities: Vec<(f32, Vec<u8>)>,
+    temp_entities: Vec<Vec<u8>>,
+}
+
+impl DatagramBuilder {
+    /// Creates a builder for a client viewing the world from `view_origin`.
+    pub fn new(format: WireFormat, view_origin: Vector3<f32>) -> DatagramBuilder {
+        DatagramBuilder {
+            format,
+            view_origin,
+            essential: Vec::new(),
+            entities: Vec::new(),
+            temp_entities: Vec::new(),
+        }
+    }
+
+    /// Adds a command which is always sent, such as the client's own state.
+    pub fn essential(&mut self, cmd: &ServerCmd) -> Result<(), DatagramError> {
+        cmd.serialize_with_format(&mut self.essential, self.format)?;
+        Ok(())
+    }
+
+    /// Adds an update for an entity located at `origin`.
+    pub fn entity(
+        &mut self,
+        origin: Vector3<f32>,
+        update: &EntityUpdate,
+    ) -> Result<(), DatagramError> {
+        let mut data = Vec::new();
+        update.write_fast_update(&mut data, self.format)?;
+        let distance2 = (origin - self.view_origin).magnitude2();
+        self.entities.push((distance2, data));
+        Ok(())
+    }
+
+    /// Adds a temporary entity.
+    pub fn temp_entity(&mut self, temp_entity: &TempEntity) -> Result<(), DatagramError> {
+        let mut data = Vec::new();
+        ServerCmd::TempEntity {
+            temp_entity: temp_entity.clone(),
+        }
+        .serialize_with_format(&mut data, self.format)?;
+        self.temp_entities.push(data);
+        Ok(())
+    }
+
+    /// Assembles a datagram of at most `budget` bytes, recording anything dropped in `stats`.
+    ///
+    /// Essential commands are never dropped; if they alone exceed the budget, this returns an
+    /// error.
+    pub fn build(
+        mut self,
+        budget: usize,
+        stats: &mut OverflowStats,
+    ) -> Result<Vec<u8>, DatagramError> {
+        if self.essential.len() > budget {
+            return Err(DatagramError::EssentialOverflow {
+                size: self.essential.len(),
+                budget,
+            });
+        }
+
+        let mut msg = self.essential;
+
+        // nearest entities first, so distant ones are the first to go
+        self.entities
+            .sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let mut dropped_entities = 0;
+        for (_, data) in self.entities.iter() {
+            if msg.len() + data.len() <= budget {
+                msg.extend_from_slice(data);
+            } else {
+                dropped_entities += 1;
+            }
+        }
+
+        let mut dropped_temp_entities = 0;
+        for data in self.temp_entities.iter() {
+            if msg.len() + data.len() <= budget {
+                msg.extend_from_slice(data);
+            } else {
+                dropped_temp_entities += 1;
+            }
+        }
+
+        if dropped_entities + dropped_temp_entities > 0 {
+            stats.overflowed_datagrams += 1;
+            stats.dropped_entities += dropped_entities;
+            stats.dropped_temp_entities += dropped_temp_entities;
+        }
+
+        Ok(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::net::PointEntityKind;
+
+    fn update(ent_id: u16) -> EntityUpdate {
+        EntityUpdate {
+            ent_id,
+            model_id: None,
+            frame_id: None,
+            colormap: None,
+            skin_id: None,
+            effects: None,
+            origin_x: Some(0.0),
+            pitch: None,
+            origin_y: Some(0.0),
+            yaw: None,
+            origin_z: Some(0.0),
+            roll: None,
+            no_lerp: false,
+            alpha: None,
+        }
+    }
+
+    fn entity_size(ent_id: u16) -> usize {
+        let mut data = Vec::new();
+        update(ent_id)
+            .write_fast_update(&mut data, WireFormat::STANDARD)
+            .unwrap();
+        data.len()
+    }
+
+    #[test]
+    fn test_build_within_budget() {
+        let mut builder = DatagramBuilder::new(WireFormat::STANDARD, Vector3::new(0.0, 0.0, 0.0));
+        builder.essential(&ServerCmd::NoOp).unwrap();
+        builder
+            .entity(Vector3::new(10.0, 0.0, 0.0), &update(1))
+            .unwrap();
+
+        let mut stats = OverflowStats::default();
+        let msg = builder.build(1024, &mut stats).unwrap();
+        assert_eq!(msg.len(), 1 + entity_size(1));
+        assert_eq!(stats, OverflowStats::default());
+    }
+
+    #[test]
+    fn test_build_drops_temp_entities_then_distant_entities() {
+        let mut builder = DatagramBuilder::new(WireFormat::STANDARD, Vector3::new(0.0, 0.0, 0.0));
+        builder
+            .entity(Vector3::new(1000.0, 0.0, 0.0), &update(1))
+            .unwrap();
+        builder
+            .entity(Vector3::new(10.0, 0.0, 0.0), &update(2))
+            .unwrap();
+        builder
+            .temp_entity(&TempEntity::Point {
+                kind: PointEntityKind::Explosion,
+                origin: Vector3::new(0.0, 0.0, 0.0),
+            })
+            .unwrap();
+
+        // room for one entity update only
+        let budget = entity_size(2);
+        let mut stats = OverflowStats::default();
+        let msg = builder.build(budget, &mut stats).unwrap();
+
+        let mut expected = Vec::new();
+        update(2)
+            .write_fast_update(&mut expected, WireFormat::STANDARD)
+            .unwrap();
+        assert_eq!(msg, expected);
+        assert_eq!(
+            stats,
+            OverflowStats {
+                overflowed_datagrams: 1,
+                dropped_entities: 1,
+                dropped_temp_entities: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_build_essential_overflow() {
+        let mut builder = DatagramBuilder::new(WireFormat::STANDARD, Vector3::new(0.0, 0.0, 0.0));
+        builder.essential(&ServerCmd::NoOp).unwrap();
+        builder.essential(&ServerCmd::NoOp).unwrap();
+
+        let mut stats = OverflowStats::default();
+        assert!(matches!(
+            builder.build(1, &mut stats),
+            Err(DatagramError::EssentialOverflow { size: 2, budget: 1 })
+        ));
+    }
+}
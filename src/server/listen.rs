@@ -0,0 +1,137 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Out-of-band request handling for a listen server.
+//!
+//! As in NetQuake, `listen 1` opens the game to the LAN: server browsers can find it and query
+//! its status. The client can't host a game yet, so connection requests are turned away with a
+//! message rather than accepted.
+
+use std::{
+    cell::Cell,
+    net::{Ipv4Addr, SocketAddr},
+    rc::Rc,
+};
+
+use crate::common::{
+    console::CvarRegistry,
+    net::{
+        connect::{ConnectListener, Response, ResponseReject, ServerStatus},
+        NetError,
+    },
+};
+
+/// The port NetQuake servers listen on.
+pub const DEFAULT_PORT: u16 = 26000;
+
+const REJECT_MESSAGE: &str = "This game isn't accepting players\n";
+
+pub fn register_cvars(cvars: &CvarRegistry) {
+    cvars.register("hostname", "UNNAMED").unwrap();
+}
+
+/// Answers status queries and connection requests sent to a listen server.
+pub struct Listener {
+    listener: ConnectListener,
+}
+
+impl Listener {
+    /// Listens on `port` on every interface, so that LAN broadcasts are received.
+    pub fn bind(port: u16) -> Result<Listener, NetError> {
+        Ok(Listener {
+            listener: ConnectListener::bind((Ipv4Addr::UNSPECIFIED, port))?,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, NetError> {
+        self.listener.local_addr()
+    }
+
+    /// Handles every waiting request without blocking.
+    pub fn frame(&self, status: &ServerStatus) -> Result<(), NetError> {
+        while let Some((_, remote)) = self.listener.poll(status)? {
+            debug!("Turning away connection request from {}", remote);
+            self.listener.send_response(
+                Response::Reject(ResponseReject {
+                    message: REJECT_MESSAGE.to_owned(),
+                }),
+                remote,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Implements the `listen` command, which sets whether the game should answer LAN requests.
+pub fn cmd_listen(enabled: Rc<Cell<bool>>) -> Box<dyn Fn(&[&str]) -> String> {
+    Box::new(move |args| match args.get(0) {
+        None => format!("\"listen\" is \"{}\"", enabled.get() as u8),
+        Some(arg) => match arg.parse::<i32>() {
+            Ok(n) => {
+                enabled.set(n != 0);
+                String::new()
+            }
+            Err(_) => "usage: listen [0|1]".to_owned(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::net::connect::{ConnectSocket, Request, CONNECT_PROTOCOL_VERSION};
+
+    use chrono::Duration;
+
+    #[test]
+    fn test_listener_rejects_connections() {
+        let listener = Listener::bind(0).unwrap();
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, listener.local_addr().unwrap().port()));
+        let mut client = ConnectSocket::bind("127.0.0.1:0").unwrap();
+        let status = ServerStatus {
+            address: addr.to_string(),
+            hostname: String::from("UNNAMED"),
+            levelname: String::new(),
+            max_clients: 1,
+            players: Vec::new(),
+            rules: Vec::new(),
+        };
+
+        client
+            .send_request(Request::connect("QUAKE", CONNECT_PROTOCOL_VERSION), addr)
+            .unwrap();
+
+        let deadline = ::std::time::Instant::now() + ::std::time::Duration::from_secs(1);
+        let response = loop {
+            listener.frame(&status).unwrap();
+            if let Some((response, _)) = client
+                .recv_response(Some(Duration::milliseconds(10)))
+                .unwrap()
+            {
+                break response;
+            }
+            assert!(::std::time::Instant::now() < deadline);
+        };
+
+        match response {
+            Response::Reject(reject) => assert_eq!(reject.message, REJECT_MESSAGE),
+            r => panic!("unexpected response {:?}", r),
+        }
+    }
+}
@@ -15,6 +15,9 @@
 // DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+pub mod datagram;
+pub mod listen;
+pub mod mvd;
 pub mod precache;
 pub mod progs;
 pub mod world;
@@ -28,7 +31,9 @@ use std::{
 use crate::{
     common::{
         console::CvarRegistry,
+        deterministic::MathMode,
         engine::{duration_from_f32, duration_to_f32},
+        limits::Limits,
         math::Hyperplane,
         model::Model,
         parse,
@@ -41,6 +46,7 @@ use crate::{
 };
 
 use self::{
+    datagram::OverflowStats,
     precache::Precache,
     progs::{
         globals::{
@@ -61,7 +67,8 @@ use cgmath::{InnerSpace, Vector3, Zero};
 use chrono::Duration;
 
 const MAX_DATAGRAM: usize = 1024;
-const MAX_LIGHTSTYLES: usize = 64;
+// the server only speaks the standard protocol, so it's held to the original limits
+const MAX_LIGHTSTYLES: usize = Limits::CLASSIC.light_styles;
 
 /// The state of a client's connection to the server.
 pub enum ClientState {
@@ -78,6 +85,9 @@ pub struct ClientActive {
 
     /// ID of the entity controlled by this client.
     entity_id: EntityId,
+
+    /// Data dropped from this client's datagrams to stay within `MAX_DATAGRAM`.
+    datagram_stats: OverflowStats,
 }
 
 bitflags! {
@@ -414,6 +424,16 @@ impl LevelState {
         self.lightstyles[index] = val;
     }
 
+    /// Returns which math implementation QuakeC builtins should use, per `sv_deterministic`.
+    fn math_mode(&self) -> MathMode {
+        MathMode::from_cvar(
+            self.cvars
+                .borrow()
+                .get_value("sv_deterministic")
+                .unwrap_or(0.0),
+        )
+    }
+
     /// Execute a QuakeC function in the VM.
     pub fn execute_program(&mut self, f: FunctionId) -> Result<(), ProgsError> {
         let mut runaway = 100000;
@@ -489,7 +509,10 @@ impl LevelState {
                         debug!("Calling built-in function {}", name);
                         use progs::functions::BuiltinFunctionId::*;
                         match b {
-                            MakeVectors => self.globals.make_vectors()?,
+                            MakeVectors => {
+                                let mode = self.math_mode();
+                                self.globals.make_vectors(mode)?
+                            }
                             SetOrigin => self.builtin_set_origin()?,
                             SetModel => self.builtin_set_model()?,
                             SetSize => self.builtin_set_size()?,
@@ -500,7 +523,10 @@ impl LevelState {
                             Error => unimplemented!(),
                             ObjError => unimplemented!(),
                             VLen => self.globals.builtin_v_len()?,
-                            VecToYaw => self.globals.builtin_vec_to_yaw()?,
+                            VecToYaw => {
+                                let mode = self.math_mode();
+                                self.globals.builtin_vec_to_yaw(mode)?
+                            }
                             Spawn => self.builtin_spawn()?,
                             Remove => self.builtin_remove()?,
                             TraceLine => unimplemented!(),
@@ -0,0 +1,532 @@
+// Copyright © 2020 Cormac O'Brien
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+// and associated documentation files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+// BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Multi-view demo recording.
+//!
+//! A regular demo holds the messages received by a single client. A multi-view demo (MVD) instead
+//! holds every message the server sends, tagged with the clients it was sent to, along with each
+//! client's view angles. Any player's point of view can be extracted from it afterward and played
+//! back as a regular demo.
+//!
+//! The file begins with the magic `RMVD`, a version byte and the maximum number of clients. This
+//! is followed by a sequence of records, each starting with the server time as a little-endian
+//! `f32` and a record kind byte.
+
+use std::io::{self, Read, Write};
+
+use crate::common::{net, util::read_f32_3};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use cgmath::{Deg, Vector3};
+use thiserror::Error;
+
+const MVD_MAGIC: [u8; 4] = *b"RMVD";
+
+/// The version of the container format written by `MvdWriter`.
+pub const MVD_VERSION: u8 = 1;
+
+const RECORD_BROADCAST: u8 = 0;
+const RECORD_UNICAST: u8 = 1;
+const RECORD_VIEW_ANGLES: u8 = 2;
+
+#[derive(Error, Debug)]
+pub enum MvdError {
+    #[error("Invalid MVD header")]
+    InvalidHeader,
+    #[error("Unsupported MVD version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Invalid MVD record kind: {0}")]
+    InvalidRecordKind(u8),
+    #[error("Invalid client ID: {0}")]
+    InvalidClient(u8),
+    #[error("Message size ({0}) exceeds maximum allowed size {}", net::MAX_MESSAGE)]
+    MessageTooLong(u32),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// The clients a message was sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageTarget {
+    All,
+    Client(u8),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MvdRecord {
+    /// A message sent to one or all clients.
+    Message {
+        time: f32,
+        target: MessageTarget,
+        data: Vec<u8>,
+    },
+
+    /// The view angles of a client.
+    ViewAngles {
+        time: f32,
+        client: u8,
+        angles: Vector3<Deg<f32>>,
+    },
+}
+
+/// Writes records to a multi-view demo.
+pub struct MvdWriter<W> {
+    writer: W,
+    max_clients: u8,
+}
+
+impl<W> MvdWriter<W>
+where
+    W: Write,
+{
+    /// Writes the demo header and returns a writer for its records.
+    pub fn new(mut writer: W, max_clients: u8) -> Result<MvdWriter<W>, MvdError> {
+        writer.write_all(&MVD_MAGIC)?;
+        writer.write_u8(MVD_VERSION)?;
+        writer.write_u8(max_clients)?;
+
+        Ok(MvdWriter {
+            writer,
+            max_clients,
+        })
+    }
+
+    pub fn write_record(&mut self, record: &MvdRecord) -> Result<(), MvdError> {
+        match *record {
+            MvdRecord::Message {
+                time,
+                target,
+                ref data,
+            } => {
+                if data.len() > net::MAX_MESSAGE {
+                    Err(MvdError::MessageTooLong(data.len() as u32))?;
+                }
+
+                self.writer.write_f32::<LittleEndian>(time)?;
+                match target {
+                    MessageTarget::All => self.writer.write_u8(RECORD_BROADCAST)?,
+                    MessageTarget::Client(client) => {
+                        self.check_client(client)?;
+                        self.writer.write_u8(RECORD_UNICAST)?;
+                        self.writer.write_u8(client)?;
+                    }
+                }
+                self.writer.write_u32::<LittleEndian>(data.len() as u32)?;
+                self.writer.write_all(data)?;
+            }
+
+            MvdRecord::ViewAngles {
+                time,
+                client,
+                angles,
+            } => {
+                self.check_client(client)?;
+                self.writer.write_f32::<LittleEndian>(time)?;
+                self.writer.write_u8(RECORD_VIEW_ANGLES)?;
+                self.writer.write_u8(client)?;
+                for angle in [angles.x, angles.y, angles.z].iter() {
+                    self.writer.write_f32::<LittleEndian>(angle.0)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn check_client(&self, client: u8) -> Result<(), MvdError> {
+        if client >= self.max_clients {
+            Err(MvdError::InvalidClient(client))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads records from a multi-view demo.
+pub struct MvdReader<R> {
+    reader: R,
+    max_clients: u8,
+}
+
+impl<R> MvdReader<R>
+where
+    R: Read,
+{
+    /// Reads the demo header and returns a reader for its records.
+    pub fn new(mut reader: R) -> Result<MvdReader<R>, MvdError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MVD_MAGIC {
+            Err(MvdError::InvalidHeader)?;
+        }
+
+        let version = reader.read_u8()?;
+        if version != MVD_VERSION {
+            Err(MvdError::UnsupportedVersion(version))?;
+        }
+
+        let max_clients = reader.read_u8()?;
+
+        Ok(MvdReader {
+            reader,
+            max_clients,
+        })
+    }
+
+    pub fn max_clients(&self) -> u8 {
+        self.max_clients
+    }
+
+    /// Reads the next record, or returns `None` at the end of the demo.
+    pub fn read_record(&mut self) -> Result<Option<MvdRecord>, MvdError> {
+        let time = match self.reader.read_f32::<LittleEndian>() {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => Err(e)?,
+        };
+
+        let kind = self.reader.read_u8()?;
+        let record = match kind {
+            RECORD_BROADCAST | RECORD_UNICAST => {
+                let target = match kind {
+                    RECORD_BROADCAST => MessageTarget::All,
+                    _ => MessageTarget::Client(self.read_client()?),
+                };
+
+                let len = self.reader.read_u32::<LittleEndian>()?;
+                if len as usize > net::MAX_MESSAGE {
+                    Err(MvdError::MessageTooLong(len))?;
+                }
+
+                let mut data = vec![0; len as usize];
+                self.reader.read_exact(&mut data)?;
+
+                MvdRecord::Message { time, target, data }
+            }
+
+            RECORD_VIEW_ANGLES => {
+                let client = self.read_client()?;
+                let angles = read_f32_3(&mut self.reader)?;
+
+                MvdRecord::ViewAngles {
+                    time,
+                    client,
+                    angles: Vector3::new(Deg(angles[0]), Deg(angles[1]), Deg(angles[2])),
+                }
+            }
+
+            k => Err(MvdError::InvalidRecordKind(k))?,
+        };
+
+        Ok(Some(record))
+    }
+
+    fn read_client(&mut self) -> Result<u8, MvdError> {
+        let client = self.reader.read_u8()?;
+        if client >= self.max_clients {
+            Err(MvdError::InvalidClient(client))?;
+        }
+
+        Ok(client)
+    }
+}
+
+/// Collects the messages sent to clients during a server frame.
+///
+/// Data written to `broadcast` goes to every client, and data written to `unicast` to a single
+/// client. `flush` assembles the message for each client at the end of the frame and, if a
+/// multi-view demo is being recorded, records what was sent.
+pub struct MessageMux<W> {
+    broadcast: Vec<u8>,
+    unicast: Vec<Vec<u8>>,
+    recorder: Option<MvdWriter<W>>,
+}
+
+impl<W> MessageMux<W>
+where
+    W: Write,
+{
+    pub fn new(max_clients: u8) -> MessageMux<W> {
+        MessageMux {
+            broadcast: Vec::new(),
+            unicast: vec![Vec::new(); max_clients as usize],
+            recorder: None,
+        }
+    }
+
+    /// Starts recording a multi-view demo to `writer`, replacing any recording in progress.
+    pub fn start_recording(&mut self, writer: W) -> Result<(), MvdError> {
+        self.recorder = Some(MvdWriter::new(writer, self.unicast.len() as u8)?);
+        Ok(())
+    }
+
+    /// Stops recording and returns the demo's writer, if a demo was being recorded.
+    pub fn stop_recording(&mut self) -> Option<W> {
+        self.recorder.take().map(MvdWriter::into_inner)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Returns the buffer for data sent to all clients this frame.
+    pub fn broadcast(&mut self) -> &mut Vec<u8> {
+        &mut self.broadcast
+    }
+
+    /// Returns the buffer for data sent to a single client this frame.
+    pub fn unicast(&mut self, client: u8) -> Result<&mut Vec<u8>, MvdError> {
+        self.unicast
+            .get_mut(client as usize)
+            .ok_or(MvdError::InvalidClient(client))
+    }
+
+    /// Records a client's view angles, if a demo is being recorded.
+    pub fn record_view_angles(
+        &mut self,
+        time: f32,
+        client: u8,
+        angles: Vector3<Deg<f32>>,
+    ) -> Result<(), MvdError> {
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.write_record(&MvdRecord::ViewAngles {
+                time,
+                client,
+                angles,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Ends the frame, returning the message for each client.
+    ///
+    /// Each client's message consists of the broadcast data followed by its own data.
+    pub fn flush(&mut self, time: f32) -> Result<Vec<Vec<u8>>, MvdError> {
+        if let Some(ref mut recorder) = self.recorder {
+            if !self.broadcast.is_empty() {
+                recorder.write_record(&MvdRecord::Message {
+                    time,
+                    target: MessageTarget::All,
+                    data: self.broadcast.clone(),
+                })?;
+            }
+
+            for (client, data) in self.unicast.iter().enumerate() {
+                if !data.is_empty() {
+                    recorder.write_record(&MvdRecord::Message {
+                        time,
+                        target: MessageTarget::Client(client as u8),
+                        data: data.clone(),
+                    })?;
+                }
+            }
+        }
+
+        let broadcast = std::mem::take(&mut self.broadcast);
+        Ok(self
+            .unicast
+            .iter_mut()
+            .map(|data| {
+                let mut msg = broadcast.clone();
+                msg.append(data);
+                msg
+            })
+            .collect())
+    }
+}
+
+/// A message from a multi-view demo as received by a single client.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PovMessage {
+    pub time: f32,
+    pub view_angles: Vector3<Deg<f32>>,
+    pub data: Vec<u8>,
+}
+
+/// Extracts the messages received by one client.
+///
+/// Messages recorded at the same time are combined, as they were sent together, as long as the
+/// combined message still fits in `net::MAX_MESSAGE`.
+pub fn client_pov<I>(records: I, client: u8) -> Vec<PovMessage>
+where
+    I: IntoIterator<Item = MvdRecord>,
+{
+    let mut view_angles = Vector3::new(Deg(0.0), Deg(0.0), Deg(0.0));
+    let mut messages: Vec<PovMessage> = Vec::new();
+
+    for record in records {
+        match record {
+            MvdRecord::ViewAngles {
+                client: c, angles, ..
+            } if c == client => view_angles = angles,
+
+            MvdRecord::Message { time, target, data }
+                if target == MessageTarget::All || target == MessageTarget::Client(client) =>
+            {
+                match messages.last_mut() {
+                    Some(last)
+                        if last.time == time
+                            && last.data.len() + data.len() <= net::MAX_MESSAGE =>
+                    {
+                        last.data.extend_from_slice(&data)
+                    }
+                    _ => messages.push(PovMessage {
+                        time,
+                        view_angles,
+                        data,
+                    }),
+                }
+            }
+
+            _ => (),
+        }
+    }
+
+    messages
+}
+
+/// Writes messages extracted with `client_pov` as a regular demo.
+pub fn write_demo<W>(mut writer: W, cd_track: i32, messages: &[PovMessage]) -> io::Result<()>
+where
+    W: Write,
+{
+    writeln!(writer, "{}", cd_track)?;
+
+    for msg in messages {
+        writer.write_u32::<LittleEndian>(msg.data.len() as u32)?;
+        for angle in [msg.view_angles.x, msg.view_angles.y, msg.view_angles.z].iter() {
+            writer.write_f32::<LittleEndian>(angle.0)?;
+        }
+        writer.write_all(&msg.data)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(data: &[u8]) -> Vec<MvdRecord> {
+        let mut reader = MvdReader::new(data).unwrap();
+        let mut records = Vec::new();
+        while let Some(r) = reader.read_record().unwrap() {
+            records.push(r);
+        }
+        records
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let records = vec![
+            MvdRecord::Message {
+                time: 1.0,
+                target: MessageTarget::All,
+                data: vec![1, 2, 3],
+            },
+            MvdRecord::Message {
+                time: 1.0,
+                target: MessageTarget::Client(1),
+                data: vec![4],
+            },
+            MvdRecord::ViewAngles {
+                time: 1.0,
+                client: 0,
+                angles: Vector3::new(Deg(10.0), Deg(90.0), Deg(0.0)),
+            },
+        ];
+
+        let mut writer = MvdWriter::new(Vec::new(), 2).unwrap();
+        for r in records.iter() {
+            writer.write_record(r).unwrap();
+        }
+
+        assert_eq!(read_all(&writer.into_inner()), records);
+    }
+
+    #[test]
+    fn test_invalid_client() {
+        let mut writer = MvdWriter::new(Vec::new(), 2).unwrap();
+        let result = writer.write_record(&MvdRecord::Message {
+            time: 0.0,
+            target: MessageTarget::Client(2),
+            data: Vec::new(),
+        });
+        assert!(matches!(result, Err(MvdError::InvalidClient(2))));
+    }
+
+    #[test]
+    fn test_mux_pov() {
+        let mut mux = MessageMux::new(2);
+        mux.start_recording(Vec::new()).unwrap();
+
+        mux.broadcast().push(1);
+        mux.unicast(0).unwrap().push(2);
+        mux.unicast(1).unwrap().push(3);
+        mux.record_view_angles(0.5, 1, Vector3::new(Deg(0.0), Deg(45.0), Deg(0.0)))
+            .unwrap();
+        let sent = mux.flush(0.5).unwrap();
+        assert_eq!(sent, vec![vec![1, 2], vec![1, 3]]);
+
+        mux.broadcast().push(4);
+        mux.flush(0.6).unwrap();
+
+        let records = read_all(&mux.stop_recording().unwrap());
+        let pov = client_pov(records, 1);
+        assert_eq!(
+            pov,
+            vec![
+                PovMessage {
+                    time: 0.5,
+                    view_angles: Vector3::new(Deg(0.0), Deg(45.0), Deg(0.0)),
+                    data: vec![1, 3],
+                },
+                PovMessage {
+                    time: 0.6,
+                    view_angles: Vector3::new(Deg(0.0), Deg(45.0), Deg(0.0)),
+                    data: vec![4],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_client_pov_split() {
+        let records = vec![
+            MvdRecord::Message {
+                time: 1.0,
+                target: MessageTarget::All,
+                data: vec![1; net::MAX_MESSAGE - 1],
+            },
+            MvdRecord::Message {
+                time: 1.0,
+                target: MessageTarget::Client(0),
+                data: vec![2; 2],
+            },
+        ];
+
+        let pov = client_pov(records, 0);
+        assert_eq!(pov.len(), 2);
+        assert!(pov.iter().all(|m| m.time == 1.0));
+        assert_eq!(pov[1].data, vec![2; 2]);
+    }
+}
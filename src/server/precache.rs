@@ -1,11 +1,14 @@
 use std::ops::Range;
 
+use crate::common::limits::Limits;
+
 use arrayvec::{ArrayString, ArrayVec};
 
 /// Maximum permitted length of a precache path.
 const MAX_PRECACHE_PATH: usize = 64;
 
-const MAX_PRECACHE_ENTRIES: usize = 256;
+// models and sounds are precached separately, but have the same limit
+const MAX_PRECACHE_ENTRIES: usize = Limits::CLASSIC.models;
 
 /// A list of resources to be loaded before entering the game.
 ///
@@ -17,8 +17,9 @@
 
 use std::{cell::RefCell, convert::TryInto, error::Error, fmt, rc::Rc};
 
-use crate::server::progs::{
-    EntityId, FieldAddr, FunctionId, GlobalDef, StringId, StringTable, Type,
+use crate::{
+    common::deterministic::MathMode,
+    server::progs::{EntityId, FieldAddr, FunctionId, GlobalDef, StringId, StringTable, Type},
 };
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -1103,10 +1104,10 @@ impl Globals {
     /// longitudinal (forward) axis, `y` to the *negative* lateral (leftward) axis, and `z` to the
     /// vertical (up) axis. As a result, the rotation matrix has to be calculated from `[roll,
     /// -pitch, yaw]` instead.
-    pub fn make_vectors(&mut self) -> Result<(), GlobalsError> {
+    pub fn make_vectors(&mut self, mode: MathMode) -> Result<(), GlobalsError> {
         let angles = self.get_vector(GLOBAL_ADDR_ARG_0 as i16)?;
 
-        let rotation_matrix = make_vectors(angles);
+        let rotation_matrix = make_vectors(angles, mode);
 
         self.put_vector(rotation_matrix.x.into(), GlobalAddrVector::VForward as i16)?;
         self.put_vector(rotation_matrix.y.into(), GlobalAddrVector::VRight as i16)?;
@@ -1129,14 +1130,14 @@ impl Globals {
     ///
     /// Loads the direction vector from `GLOBAL_ADDR_ARG_0` and stores the yaw value at
     /// `GLOBAL_ADDR_RETURN`.
-    pub fn builtin_vec_to_yaw(&mut self) -> Result<(), GlobalsError> {
+    pub fn builtin_vec_to_yaw(&mut self, mode: MathMode) -> Result<(), GlobalsError> {
         let v = self.get_vector(GLOBAL_ADDR_ARG_0 as i16)?;
 
         let mut yaw;
         if v[0] == 0.0 || v[1] == 0.0 {
             yaw = 0.0;
         } else {
-            yaw = v[1].atan2(v[0]).to_degrees();
+            yaw = mode.atan2_deg(v[1], v[0]);
             if yaw < 0.0 {
                 yaw += 360.0;
             }
@@ -1187,12 +1188,33 @@ impl Globals {
     }
 }
 
-pub fn make_vectors(angles: [f32; 3]) -> Matrix3<f32> {
+pub fn make_vectors(angles: [f32; 3], mode: MathMode) -> Matrix3<f32> {
     let pitch = Deg(-angles[0]);
     let yaw = Deg(angles[1]);
     let roll = Deg(angles[2]);
 
-    Matrix3::from(Euler::new(roll, pitch, yaw))
+    match mode {
+        MathMode::Native => Matrix3::from(Euler::new(roll, pitch, yaw)),
+
+        // same as the Euler conversion above, but without the platform sin/cos
+        MathMode::Deterministic => {
+            let (sx, cx) = mode.sin_cos_deg(roll.0);
+            let (sy, cy) = mode.sin_cos_deg(pitch.0);
+            let (sz, cz) = mode.sin_cos_deg(yaw.0);
+
+            Matrix3::new(
+                cy * cz,
+                cx * sz + sx * sy * cz,
+                sx * sz - cx * sy * cz,
+                -cy * sz,
+                cx * cz - sx * sy * sz,
+                sx * cz + cx * sy * sz,
+                sy,
+                -sx * cy,
+                cx * cy,
+            )
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1204,28 +1226,39 @@ mod test {
     #[test]
     fn test_make_vectors_no_rotation() {
         let angles_zero = [0.0; 3];
-        let result = make_vectors(angles_zero);
+        let result = make_vectors(angles_zero, MathMode::Native);
         assert_eq!(Matrix3::identity(), result);
     }
 
     #[test]
     fn test_make_vectors_pitch() {
         let pitch_90 = [90.0, 0.0, 0.0];
-        let result = make_vectors(pitch_90);
+        let result = make_vectors(pitch_90, MathMode::Native);
         assert_eq!(Matrix3::from_angle_y(Deg(-90.0)), result);
     }
 
     #[test]
     fn test_make_vectors_yaw() {
         let yaw_90 = [0.0, 90.0, 0.0];
-        let result = make_vectors(yaw_90);
+        let result = make_vectors(yaw_90, MathMode::Native);
         assert_eq!(Matrix3::from_angle_z(Deg(90.0)), result);
     }
 
     #[test]
     fn test_make_vectors_roll() {
         let roll_90 = [0.0, 0.0, 90.0];
-        let result = make_vectors(roll_90);
+        let result = make_vectors(roll_90, MathMode::Native);
         assert_eq!(Matrix3::from_angle_x(Deg(90.0)), result);
     }
+
+    #[test]
+    fn test_make_vectors_deterministic() {
+        for angles in &[[0.0, 0.0, 0.0], [30.0, 45.0, 0.0], [-15.0, 200.0, 5.0]] {
+            let native = make_vectors(*angles, MathMode::Native);
+            let deterministic = make_vectors(*angles, MathMode::Deterministic);
+            for i in 0..3 {
+                assert!((native[i] - deterministic[i]).magnitude() < 1e-5);
+            }
+        }
+    }
 }
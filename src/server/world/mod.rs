@@ -40,6 +40,7 @@ use crate::{
     common::{
         bsp,
         bsp::{BspCollisionHull, BspLeafContents},
+        limits::Limits,
         mdl,
         model::{Model, ModelKind},
         parse, sprite,
@@ -56,7 +57,7 @@ use cgmath::{InnerSpace, Vector3, Zero};
 
 const AREA_DEPTH: usize = 4;
 const NUM_AREA_NODES: usize = 2usize.pow(AREA_DEPTH as u32 + 1) - 1;
-const MAX_ENTITIES: usize = 600;
+const MAX_ENTITIES: usize = Limits::CLASSIC.entities;
 
 #[derive(Debug)]
 enum AreaNodeKind {